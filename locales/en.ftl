@@ -0,0 +1,278 @@
+# English messages for dcm-toolbox (reference catalog).
+#
+# Every message id used by the binary must exist here; other catalogs fall
+# back to this one for missing ids.
+
+## Shared
+
+no-dcm-files = No .dcm files found in { $path }
+input-symlinks-skipped = Skipped { $count } symlinked file(s) in { $path } (use --follow-symlinks to include them)
+input-symlinks-broken = Skipped { $count } broken symlink(s) in { $path }
+input-duplicates-skipped = Skipped { $count } file(s) in { $path } that point to an already listed file
+
+## Output folder cleanup prompt
+
+cleanup-folder-exists = Folder already exists: { $path }
+cleanup-prompt = Cleanup? [Y]es / Yes to [A]ll / [N]o / No to A[l]l:{ " " }
+cleanup-invalid-choice = Invalid choice, defaulting to 'No'
+cleanup-removed-file = Removed existing file: { $path }
+cleanup-cleaned-folder = Cleaned output folder: { $path }
+
+## Convert
+
+convert-default-output = No --out given; writing to: { $path }
+convert-found-files = Found { $count } DICOM file(s) to process
+convert-splitting-by = Splitting by: { $tag }
+convert-found-groups = Found { $count } series/groups:
+convert-group-entry = - { $key }: { $count } files
+convert-pick-entry = [{ $number }] { $key }: { $description } ({ $modality }, { $count } files)
+convert-pick-prompt = Series to convert (e.g. 1,3-5; empty for all):{ " " }
+convert-pick-invalid = ✗ { $error }; try again
+convert-picked = Converting { $count } of { $total } series/groups
+convert-unknown-skipped = ⚠ Skipped { $count } file(s) without { $tag }
+convert-folder-collision = ⚠ Group { $key } would share folder { $folder } with an earlier group; writing it to { $renamed }
+convert-annotations-loaded = Loaded { $shapes } annotations for { $instances } instances
+convert-patches-saved = ✓ Saved { $count } annotation patch(es) to: { $path }
+convert-encrypted-saved = 🔒 Encrypted { $series } series ({ $count } files) into: { $path }
+convert-archive-saved = ✓ Saved archive: { $path }
+convert-dicomweb-saved = ✓ Saved { $studies } study(ies) with { $series } series for DICOMweb to: { $path }
+convert-dicomweb-no-uid = ✗ Skipped { $file }: no StudyInstanceUID or SeriesInstanceUID
+convert-processing-series = === Processing series: { $key } ({ $count } files) ===
+convert-study-failed = ✗ Combined video failed: { $error }
+convert-series-failed = ✗ Series { $key } failed: { $error }
+convert-complete = Conversion complete! Created { $count } series.
+convert-interrupted = Conversion stopped: created { $count } of { $total } series.
+metrics-write-failed = ⚠ Could not write metrics: { $error }
+notify-failed = ⚠ Could not send the completion notice: { $error }
+throttle-nice-failed = ⚠ Could not lower the CPU priority (--nice): { $error }
+throttle-ionice-failed = ⚠ Could not lower the disk priority (--ionice): { $error }
+perms-failed = ⚠ Could not set the permissions or owner of { $count } output(s): { $error }
+cancel-interrupted = Stopping after cleaning up; press Ctrl-C again to quit right away.
+cache-failed = ⚠ Could not cache frames, going on without the cache for this file: { $error }
+convert-file-failed = ✗ Failed to convert { $file }: { $error }
+convert-converted = ✓ Converted: { $source } -> { $output }
+convert-converted-single = ✓ Converted: { $output }
+convert-clipboard-copied = ✓ Copied slice { $slice } ({ $file }) to the clipboard
+convert-clipboard-holding = ✓ Copied slice { $slice } ({ $file }) to the clipboard; it stays there for { $seconds }s or until something else is copied (press Ctrl-C twice to end sooner)
+convert-frames-failed = ✗ { $failed } of { $total } frame(s) failed to convert
+convert-quarantined = ⚠ { $count } file(s) could not be read even leniently; listed in { $path }
+convert-high-bit-depth = ⚠ { $bits }-bit data without a window: each slice is stretched to 256 gray levels, merging nearby values. Use --image-format png16 to keep them all
+convert-scouts-found = Found { $count } localizer image(s) for scout lines
+convert-no-scouts = ✗ No localizer images with position data found; scout lines are skipped
+convert-scout-lines-saved = ✓ Saved { $count } scout reference image(s)
+select-kept = Selected { $count } of { $total } file(s) from the ID lists
+select-missing = ⚠ { $count } ID(s) from { $path } matched no file: { $ids }
+
+## Video
+
+video-staging-space = Staging frames needs about { $size } in { $path }
+video-preparing = Preparing frames for video encoding...
+reencode-found-images = Found { $count } image(s) in { $path }
+video-keeping-images = Writing frames as images, reused for video encoding...
+video-prepared-frame = ✓ Prepared frame { $index }/{ $total }: { $file }
+video-cine-fps = Using the cine frame rate of the series: { $fps } fps
+video-trimmed = Keeping frames { $start }:{ $end } of { $total }
+video-trim-playback-time = No frame time recorded; --time-range counts at { $fps } frames per second
+video-creating = Creating video: { $width }x{ $height } @ { $fps } fps
+video-skipped-frames = ✗ Skipped { $count } frame(s) that failed to load
+video-encoding = Encoding video with ffmpeg...
+video-combining = Combining { $count } series into one video...
+video-chapters = Chapters: { $count }
+video-cardiac-slices = Building cine loops for { $count } slice position(s)
+video-cardiac-slice = { $slice }: { $count } phase(s) at { $position } mm
+video-cardiac-unplaced = ⚠ { $count } image(s) have no slice position and are left out of the cine loops
+video-cardiac-phase-count = ⚠ { $slice } has { $count } phase(s), but CardiacNumberOfImages says { $expected }
+video-square-pixels = Stretching frames to square pixels (row/column spacing { $ratio })
+video-audio = Adding audio track: { $path }
+ffmpeg-no-gpu-encoder = ⚠ ffmpeg has no { $encoder } encoder; encoding on the CPU instead
+ffmpeg-codec-fallback = ⚠ ffmpeg has no { $encoder } encoder; using { $fallback } instead
+ffmpeg-waiting-slot = Waiting for one of { $count } encoder slots to free up...
+video-first-pass = First pass: analyzing frames for { $bitrate } kbit/s...
+video-second-pass = Second pass: encoding video...
+video-encode-retry = ⚠ { $encoder } failed, encoding again with libx264 at a fast preset: { $error }
+video-saved = ✓ Video saved to: { $path }
+video-total-frames = Total frames: { $count }
+video-duration = Duration: { $seconds }s
+
+## STL
+
+stl-building-volume = Building 3D volume from { $count } slices...
+stl-volume = Volume: { $cols }x{ $rows }x{ $slices } (spacing: { $spacing } mm)
+stl-stripped-background = Removed background ({ $voxels } voxels)
+stl-smoothing = Applying Gaussian smoothing (sigma={ $sigma })...
+stl-otsu-threshold = Auto-detected Otsu threshold: { $threshold }
+stl-user-iso-level = Using user-specified iso-level: { $threshold }
+stl-target-suggestions = CT levels: bone { $bone } HU, skin { $skin } HU, airways { $airways } HU (pick one with --target)
+stl-target-level = Using the { $target } level: { $threshold } HU
+stl-shell = Hollowed to a { $thickness } mm shell ({ $voxels } voxels removed)
+stl-marching-cubes = Running Marching Cubes...
+stl-mesh = Mesh: { $vertices } vertices, { $triangles } triangles
+stl-cropped = Cropped to { $cols }x{ $rows }x{ $slices } voxels
+stl-decimated = Decimated ({ $cell } mm grid): { $vertices } vertices, { $triangles } triangles
+stl-saved = ✓ { $format } saved to: { $path }
+stl-preview-saved = ✓ Preview saved to: { $path }
+stl-turntable = Rendering a { $seconds } s turntable video...
+stl-turntable-saved = ✓ Turntable video saved to: { $path } ({ $frames } frames)
+stl-labels = Label map: { $count } labels from { $path }
+stl-part = Part { $name } (values { $range })
+stl-part-empty = Skipped part { $name }: no voxels in its range
+stl-measurements = Surface { $area } mm², volume { $volume } mm³ ({ $ml } mL), size { $size } mm
+
+## Point clouds
+
+pointcloud-otsu-threshold = Auto-detected Otsu threshold: { $threshold }
+pointcloud-saved = ✓ Point cloud saved to: { $path } ({ $count } points)
+slide-found = Whole slide of { $width }×{ $height } pixels in { $levels } level(s)
+slide-reading-level = Reading tiles of the { $width }×{ $height } level ({ $file })
+slide-saved = ✓ Slide image saved to: { $path } ({ $width }×{ $height })
+slide-deepzoom-saved = ✓ DeepZoom pyramid saved to: { $path } ({ $levels } levels, { $tiles } tiles)
+print-saved = ✓ { $pages } page(s) saved to: { $path }
+
+## Centerlines
+
+centerline-otsu-threshold = Auto-detected Otsu threshold: { $threshold }
+centerline-region = Segmented region: { $voxels } voxels
+centerline-saved = ✓ { $branches } centerline branches ({ $length } mm in total) saved to: { $path }
+panoramic-threshold = Auto-detected teeth and bone level: { $threshold }
+panoramic-arch = Dental arch fitted: { $length } mm long
+panoramic-saved = ✓ Panoramic image ({ $width }×{ $height }) saved to: { $path }
+panoramic-preview-saved = ✓ Arch preview saved to: { $path }
+measure-roi = { $roi } ({ $shape }, slice { $slice }): { $pixels } px, { $area } mm², mean { $mean } ± { $std }, min { $min }, max { $max }
+measure-saved = ✓ Saved measurements: { $path }
+dose-loaded = RT Dose grid { $cols }×{ $rows }×{ $planes }, maximum { $max }
+dose-saved = ✓ Dose volume saved to: { $path }
+dose-overlays-saved = ✓ { $count } slices with isodose lines saved to: { $path }
+
+## Volumes
+
+volume-loaded-slice = ✓ Loaded slice { $index }/{ $total }: { $file }
+
+## Register
+
+register-loading-fixed = Loading fixed series from: { $path }
+register-loading-moving = Loading moving series from: { $path }
+register-level = Level { $level }/{ $levels }: { $samples } samples, NMI { $metric }
+register-result = Translation (mm): { $translation } | rotation (deg): { $rotation }
+register-saved-transform = ✓ Transform saved to: { $path }
+register-other-frame = The series are in different frames of reference; starting from aligned volume centers
+register-resampling = Resampling moving volume onto the fixed grid...
+register-saved-volume = ✓ Resampled volume saved to: { $path }
+
+## Subtract
+
+subtract-loading-post = Loading post series from: { $path }
+subtract-loading-pre = Loading pre series from: { $path }
+subtract-resampling = Resampling pre series onto the post grid...
+subtract-range = Difference range: { $min } to { $max }
+subtract-saved = ✓ Subtraction saved to: { $path }
+
+## Analyze
+
+analyze-analyzing = Analyzing { $count } DICOM files...
+analyze-identifiers-header = === Potential Cut Identifiers ===
+analyze-unique-values = { $tag } ({ $code }): { $count } unique values
+analyze-entry-count-first = - { $count } files: { $value }
+analyze-entry-series = - Series { $value }: { $count } files
+analyze-entry-acquisition = - Acquisition { $value }: { $count } files
+analyze-entry-description = - "{ $value }": { $count } files
+analyze-entry-stack = - Stack { $value }: { $count } files
+analyze-recommendation-header = === Recommendation ===
+analyze-looking-for = Looking for tag with exactly { $expected } unique values:
+analyze-match = ✓ { $tag } has { $count } unique values - MATCH! Use: { $flag }
+analyze-candidate = - { $tag } has { $count } unique values
+analyze-multiple-values = Tags with multiple unique values (use --expected-groups (-g) to highlight matches):
+analyze-candidate-flag = - { $tag } has { $count } unique values ({ $flag })
+analyze-candidate-too-many = - { $tag } has { $count } unique values (too many to list)
+analyze-bit-depth-header = Bit depth (BitsStored/BitsAllocated/PixelRepresentation) per series:
+analyze-entry-bit-depth-unsigned = - { $series }: { $stored } of { $allocated } bits, unsigned ({ $count } files)
+analyze-entry-bit-depth-signed = - { $series }: { $stored } of { $allocated } bits, signed ({ $count } files)
+analyze-bit-depth-hint = Series with more than 8 bits lose gray levels in JPEG, PNG, and video; convert jpeg --image-format png16 keeps them.
+
+## Analyze previews
+
+analyze-preview-header = Writing series previews to: { $path }
+analyze-preview-written = ✓ Series { $series }{ $description } -> { $path } ({ $frames } frames)
+analyze-preview-failed = ✗ Preview failed for series { $series }: { $error }
+
+## Inventory
+
+inventory-reading = Reading the headers of { $count } DICOM file(s)...
+inventory-total = { $studies } studies, { $series } series, { $instances } instances, { $size }
+inventory-dates = Study dates: { $first } to { $last }
+inventory-series-per-study = Series per study: { $fewest } to { $most }
+inventory-unreadable = ✗ { $count } file(s) could not be read as DICOM
+inventory-by-modality = By modality:
+inventory-by-scanner = By scanner:
+inventory-entry = - { $key }: { $studies } studies, { $series } series, { $instances } instances, { $size }
+inventory-saved = ✓ Saved inventory: { $path }
+
+## Hash
+
+hash-reading = Hashing { $count } DICOM file(s) with { $algorithm }...
+hash-unreadable = ✗ Cannot read { $file }: { $error }
+hash-studies-header = Study checksums (independent of file names and order):
+hash-study = - { $uid }: { $checksum } ({ $count } files)
+hash-saved = ✓ Saved manifest: { $path }
+hash-changed = ✗ Changed: { $file }
+hash-missing = ✗ Missing: { $file }
+hash-extra = ? Not in the manifest: { $file }
+hash-verified = { $matched } of { $total } file(s) match the manifest; { $intact } of { $studies } studies intact
+verify-pixels-comparing = Decoding { $count } DICOM file(s) and their copies...
+verify-pixels-identical = ✓ Identical: { $file } ({ $frames } frame(s))
+verify-pixels-lossy = ~ Lossy copy: { $file } (PSNR { $psnr } dB, largest difference { $max })
+verify-pixels-differs = ✗ Pixels differ: { $file }, frame { $frame } (largest difference { $max }, PSNR { $psnr } dB)
+verify-pixels-failed = ✗ Cannot compare { $file }: { $error }
+verify-pixels-no-copy = no copy with the same path or SOPInstanceUID
+verify-pixels-summary = { $identical } of { $total } file(s) have identical pixels; { $lossy } lossy
+verify-pixels-lowest-psnr = Lowest PSNR of the lossy copies: { $psnr } dB
+verify-pixels-frame-count = { $source } frame(s) in the source, { $copy } in the copy
+verify-pixels-frame-error = frame { $frame }: { $error }
+verify-pixels-size = { $source } in the source, { $copy } in the copy
+verify-pixels-color-mismatch = one is color and the other monochrome
+verify-pixels-mismatched = Verification failed: { $count } file(s) differ or could not be compared
+private-tags-writing = Copying { $count } DICOM file(s) with the private tag policy...
+private-tags-file = ✓ { $file }: { $kept } private element(s) kept, { $dropped } dropped
+private-tags-failed = ✗ Cannot copy { $file }: { $error }
+private-tags-summary = Wrote { $files } file(s) to { $path }: { $kept } private element(s) kept, { $dropped } dropped
+
+## Lenient parsing
+
+lenient-odd-lengths = ⚠ Salvaged { $file }: elements with odd lengths were read as the next even length
+lenient-pixel-data-padded = ⚠ Salvaged { $file }: pixel data is cut short ({ $read } of { $expected } bytes); the rest is black
+lenient-trailing-data = ⚠ Salvaged { $file }: unreadable data after the pixel data was dropped
+
+## Test data
+
+gen-test-data-saved = ✓ Wrote { $count } synthetic DICOM file(s) in { $series } series to: { $path }
+
+## Desktop window
+
+gui-title = DCM Toolbox
+gui-input = Input
+gui-drop-hint = Drop a folder of DICOM files on this window, or type its path:
+gui-open = Open
+gui-loading = Reading the series...
+gui-no-series = No DICOM series found in this folder
+gui-series = Series { $number } { $description } ({ $count } files)
+gui-output = Output folder
+gui-force = Replace series folders already there
+gui-format = Convert to
+gui-format-jpeg = JPEG images
+gui-format-video = MP4 video
+gui-format-stl = STL 3D model
+gui-custom-window = Own window instead of the file's
+gui-window-center = Center
+gui-window-width = Width
+gui-fps = Frames per second
+gui-auto-threshold = Find the threshold automatically (Otsu)
+gui-threshold = Threshold
+gui-open-hint = Open a folder with DICOM files first
+gui-output-missing = Choose an output folder
+gui-output-not-empty = The output folder is not empty: tick "Replace series folders already there" or choose another
+gui-convert = Convert
+gui-pause = Pause
+gui-resume = Resume
+gui-cancel = Cancel
+gui-progress = { $done } of { $total } series
+gui-finished = ✓ Finished ({ $status }): { $path }
+gui-failed = ✗ Conversion failed: { $error }
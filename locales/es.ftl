@@ -0,0 +1,277 @@
+# Mensajes en español para dcm-toolbox.
+#
+# Los identificadores que falten aquí se muestran en inglés (en.ftl).
+
+## Compartidos
+
+no-dcm-files = No se encontraron archivos .dcm en { $path }
+input-symlinks-skipped = Se omitieron { $count } enlace(s) simbólico(s) en { $path } (use --follow-symlinks para incluirlos)
+input-symlinks-broken = Se omitieron { $count } enlace(s) simbólico(s) rotos en { $path }
+input-duplicates-skipped = Se omitieron { $count } archivo(s) en { $path } que apuntan a un archivo ya listado
+
+## Pregunta de limpieza de la carpeta de salida
+
+cleanup-folder-exists = La carpeta ya existe: { $path }
+cleanup-prompt = ¿Limpiar? [S]í / Sí a [T]odo / [N]o / No a t[o]do:{ " " }
+cleanup-invalid-choice = Opción no válida, se usará 'No'
+cleanup-removed-file = Archivo existente eliminado: { $path }
+cleanup-cleaned-folder = Carpeta de salida limpiada: { $path }
+
+## Conversión
+
+convert-default-output = Sin --out; se escribe en: { $path }
+convert-found-files = Se encontraron { $count } archivo(s) DICOM para procesar
+convert-splitting-by = Separando por: { $tag }
+convert-found-groups = Se encontraron { $count } series/grupos:
+convert-group-entry = - { $key }: { $count } archivos
+convert-pick-entry = [{ $number }] { $key }: { $description } ({ $modality }, { $count } archivos)
+convert-pick-prompt = Series a convertir (p. ej. 1,3-5; vacío para todas):{ " " }
+convert-pick-invalid = ✗ { $error }; intente de nuevo
+convert-picked = Se convertirán { $count } de { $total } series/grupos
+convert-unknown-skipped = ⚠ Se omitieron { $count } archivo(s) sin { $tag }
+convert-folder-collision = ⚠ El grupo { $key } compartiría la carpeta { $folder } con un grupo anterior; se escribe en { $renamed }
+convert-annotations-loaded = Cargadas { $shapes } anotaciones para { $instances } instancias
+convert-patches-saved = ✓ Guardado(s) { $count } recorte(s) de anotaciones en: { $path }
+convert-encrypted-saved = 🔒 Se cifraron { $series } serie(s) ({ $count } archivos) en: { $path }
+convert-archive-saved = ✓ Archivo ZIP guardado: { $path }
+convert-dicomweb-saved = ✓ Se guardaron { $studies } estudio(s) con { $series } serie(s) para DICOMweb en: { $path }
+convert-dicomweb-no-uid = ✗ Se omitió { $file }: no tiene StudyInstanceUID ni SeriesInstanceUID
+convert-processing-series = === Procesando serie: { $key } ({ $count } archivos) ===
+convert-study-failed = ✗ Falló el video combinado: { $error }
+convert-series-failed = ✗ Falló la serie { $key }: { $error }
+convert-complete = ¡Conversión completa! Se crearon { $count } series.
+convert-interrupted = Conversión detenida: se crearon { $count } de { $total } series.
+metrics-write-failed = ⚠ No se pudieron escribir las métricas: { $error }
+notify-failed = ⚠ No se pudo enviar el aviso de finalización: { $error }
+throttle-nice-failed = ⚠ No se pudo bajar la prioridad de CPU (--nice): { $error }
+throttle-ionice-failed = ⚠ No se pudo bajar la prioridad de disco (--ionice): { $error }
+perms-failed = ⚠ No se pudieron ajustar los permisos o el propietario de { $count } salida(s): { $error }
+cancel-interrupted = Deteniendo tras limpiar; pulse Ctrl-C otra vez para salir de inmediato.
+cache-failed = ⚠ No se pudieron guardar fotogramas en la caché; se sigue sin ella para este archivo: { $error }
+convert-file-failed = ✗ No se pudo convertir { $file }: { $error }
+convert-converted = ✓ Convertido: { $source } -> { $output }
+convert-converted-single = ✓ Convertido: { $output }
+convert-clipboard-copied = ✓ Se copió el corte { $slice } ({ $file }) al portapapeles
+convert-clipboard-holding = ✓ Se copió el corte { $slice } ({ $file }) al portapapeles; permanece allí { $seconds }s o hasta que se copie otra cosa (pulse Ctrl-C dos veces para terminar antes)
+convert-frames-failed = ✗ { $failed } de { $total } imagen(es) no se pudieron convertir
+convert-quarantined = ⚠ { $count } archivo(s) no se pudieron leer ni en modo tolerante; se listan en { $path }
+convert-high-bit-depth = ⚠ Datos de { $bits } bits sin ventana: cada corte se estira a 256 niveles de gris, uniendo valores cercanos. Use --image-format png16 para conservarlos todos
+convert-scouts-found = Se encontraron { $count } imagen(es) localizadora(s) para las líneas de referencia
+convert-no-scouts = ✗ No se encontraron imágenes localizadoras con datos de posición; se omiten las líneas de referencia
+convert-scout-lines-saved = ✓ Se guardaron { $count } imagen(es) de referencia sobre el localizador
+select-kept = Se seleccionaron { $count } de { $total } archivo(s) según las listas de IDs
+select-missing = ⚠ { $count } ID(s) de { $path } no coincidieron con ningún archivo: { $ids }
+
+## Video
+
+video-staging-space = Las imágenes intermedias necesitan unos { $size } en { $path }
+video-preparing = Preparando imágenes para codificar el video...
+reencode-found-images = Se encontraron { $count } imagen(es) en { $path }
+video-keeping-images = Guardando las imágenes, que se reutilizan para codificar el video...
+video-prepared-frame = ✓ Imagen preparada { $index }/{ $total }: { $file }
+video-cine-fps = Usando la frecuencia de cine de la serie: { $fps } fps
+video-trimmed = Se conservan los fotogramas { $start }:{ $end } de { $total }
+video-trim-playback-time = No hay tiempo entre fotogramas registrado; --time-range cuenta a { $fps } fotogramas por segundo
+video-creating = Creando video: { $width }x{ $height } a { $fps } fps
+video-skipped-frames = ✗ Se omitieron { $count } imagen(es) que no se pudieron cargar
+video-encoding = Codificando video con ffmpeg...
+video-combining = Uniendo { $count } serie(s) en un solo video...
+video-chapters = Capítulos: { $count }
+video-cardiac-slices = Creando bucles de cine para { $count } posición(es) de corte
+video-cardiac-slice = { $slice }: { $count } fase(s) en { $position } mm
+video-cardiac-unplaced = ⚠ { $count } imagen(es) no tienen posición de corte y quedan fuera de los bucles de cine
+video-cardiac-phase-count = ⚠ { $slice } tiene { $count } fase(s), pero CardiacNumberOfImages indica { $expected }
+video-square-pixels = Estirando los fotogramas a píxeles cuadrados (espaciado fila/columna { $ratio })
+video-audio = Añadiendo pista de audio: { $path }
+ffmpeg-no-gpu-encoder = ⚠ ffmpeg no tiene el codificador { $encoder }; se codifica en la CPU
+ffmpeg-codec-fallback = ⚠ ffmpeg no tiene el codificador { $encoder }; se usa { $fallback }
+ffmpeg-waiting-slot = Esperando a que se libere uno de los { $count } turnos de codificación...
+video-first-pass = Primera pasada: analizando las imágenes para { $bitrate } kbit/s...
+video-second-pass = Segunda pasada: codificando el video...
+video-encode-retry = ⚠ { $encoder } falló, se codifica de nuevo con libx264 en un preset rápido: { $error }
+video-saved = ✓ Video guardado en: { $path }
+video-total-frames = Total de imágenes: { $count }
+video-duration = Duración: { $seconds } s
+
+## STL
+
+stl-building-volume = Construyendo volumen 3D a partir de { $count } cortes...
+stl-volume = Volumen: { $cols }x{ $rows }x{ $slices } (espaciado: { $spacing } mm)
+stl-stripped-background = Fondo eliminado ({ $voxels } vóxeles)
+stl-smoothing = Aplicando suavizado gaussiano (sigma={ $sigma })...
+stl-otsu-threshold = Umbral de Otsu detectado automáticamente: { $threshold }
+stl-user-iso-level = Usando el iso-level indicado: { $threshold }
+stl-target-suggestions = Niveles de TC: hueso { $bone } HU, piel { $skin } HU, vías aéreas { $airways } HU (elige uno con --target)
+stl-target-level = Usando el nivel de { $target }: { $threshold } HU
+stl-shell = Vaciado a una pared de { $thickness } mm ({ $voxels } vóxeles eliminados)
+stl-marching-cubes = Ejecutando Marching Cubes...
+stl-mesh = Malla: { $vertices } vértices, { $triangles } triángulos
+stl-cropped = Recortado a { $cols }x{ $rows }x{ $slices } vóxeles
+stl-decimated = Simplificada (rejilla de { $cell } mm): { $vertices } vértices, { $triangles } triángulos
+stl-saved = ✓ { $format } guardado en: { $path }
+stl-preview-saved = ✓ Vista previa guardada en: { $path }
+stl-turntable = Renderizando un video giratorio de { $seconds } s...
+stl-turntable-saved = ✓ Video giratorio guardado en: { $path } ({ $frames } fotogramas)
+stl-labels = Mapa de etiquetas: { $count } etiquetas de { $path }
+stl-part = Parte { $name } (valores { $range })
+stl-part-empty = Parte { $name } omitida: ningún vóxel en su rango
+stl-measurements = Superficie { $area } mm², volumen { $volume } mm³ ({ $ml } mL), tamaño { $size } mm
+
+## Nubes de puntos
+
+pointcloud-otsu-threshold = Umbral de Otsu detectado automáticamente: { $threshold }
+pointcloud-saved = ✓ Nube de puntos guardada en: { $path } ({ $count } puntos)
+slide-found = Lámina completa de { $width }×{ $height } píxeles en { $levels } nivel(es)
+slide-reading-level = Leyendo las teselas del nivel { $width }×{ $height } ({ $file })
+slide-saved = ✓ Imagen de la lámina guardada en: { $path } ({ $width }×{ $height })
+slide-deepzoom-saved = ✓ Pirámide DeepZoom guardada en: { $path } ({ $levels } niveles, { $tiles } teselas)
+print-saved = ✓ { $pages } página(s) guardada(s) en: { $path }
+
+## Líneas centrales
+
+centerline-otsu-threshold = Umbral de Otsu detectado automáticamente: { $threshold }
+centerline-region = Región segmentada: { $voxels } vóxeles
+centerline-saved = ✓ { $branches } ramas de línea central ({ $length } mm en total) guardadas en: { $path }
+panoramic-threshold = Nivel de dientes y hueso detectado automáticamente: { $threshold }
+panoramic-arch = Arcada dental ajustada: { $length } mm de largo
+panoramic-saved = ✓ Imagen panorámica ({ $width }×{ $height }) guardada en: { $path }
+panoramic-preview-saved = ✓ Vista previa de la arcada guardada en: { $path }
+measure-roi = { $roi } ({ $shape }, corte { $slice }): { $pixels } px, { $area } mm², media { $mean } ± { $std }, mín. { $min }, máx. { $max }
+measure-saved = ✓ Mediciones guardadas: { $path }
+dose-loaded = Matriz de RT Dose { $cols }×{ $rows }×{ $planes }, máximo { $max }
+dose-saved = ✓ Volumen de dosis guardado en: { $path }
+dose-overlays-saved = ✓ { $count } cortes con isodosis guardados en: { $path }
+
+## Volúmenes
+
+volume-loaded-slice = ✓ Corte cargado { $index }/{ $total }: { $file }
+
+## Registro
+
+register-loading-fixed = Cargando serie fija desde: { $path }
+register-loading-moving = Cargando serie móvil desde: { $path }
+register-level = Nivel { $level }/{ $levels }: { $samples } muestras, NMI { $metric }
+register-result = Traslación (mm): { $translation } | rotación (grados): { $rotation }
+register-saved-transform = ✓ Transformación guardada en: { $path }
+register-other-frame = Las series están en distintos marcos de referencia; se parte de los centros de los volúmenes alineados
+register-resampling = Remuestreando el volumen móvil en la malla fija...
+register-saved-volume = ✓ Volumen remuestreado guardado en: { $path }
+
+## Sustracción
+
+subtract-loading-post = Cargando serie post desde: { $path }
+subtract-loading-pre = Cargando serie pre desde: { $path }
+subtract-resampling = Remuestreando la serie pre en la malla post...
+subtract-range = Rango de la diferencia: { $min } a { $max }
+subtract-saved = ✓ Sustracción guardada en: { $path }
+
+## Análisis
+
+analyze-analyzing = Analizando { $count } archivos DICOM...
+analyze-identifiers-header = === Posibles identificadores de corte ===
+analyze-unique-values = { $tag } ({ $code }): { $count } valores únicos
+analyze-entry-count-first = - { $count } archivos: { $value }
+analyze-entry-series = - Serie { $value }: { $count } archivos
+analyze-entry-acquisition = - Adquisición { $value }: { $count } archivos
+analyze-entry-description = - "{ $value }": { $count } archivos
+analyze-entry-stack = - Pila { $value }: { $count } archivos
+analyze-recommendation-header = === Recomendación ===
+analyze-looking-for = Buscando una etiqueta con exactamente { $expected } valores únicos:
+analyze-match = ✓ { $tag } tiene { $count } valores únicos - ¡COINCIDE! Use: { $flag }
+analyze-candidate = - { $tag } tiene { $count } valores únicos
+analyze-multiple-values = Etiquetas con varios valores únicos (use --expected-groups (-g) para resaltar coincidencias):
+analyze-candidate-flag = - { $tag } tiene { $count } valores únicos ({ $flag })
+analyze-candidate-too-many = - { $tag } tiene { $count } valores únicos (demasiados para listar)
+analyze-bit-depth-header = Profundidad de bits (BitsStored/BitsAllocated/PixelRepresentation) por serie:
+analyze-entry-bit-depth-unsigned = - { $series }: { $stored } de { $allocated } bits, sin signo ({ $count } archivos)
+analyze-entry-bit-depth-signed = - { $series }: { $stored } de { $allocated } bits, con signo ({ $count } archivos)
+analyze-bit-depth-hint = Las series de más de 8 bits pierden niveles de gris en JPEG, PNG y video; convert jpeg --image-format png16 los conserva.
+
+## Vistas previas de análisis
+
+analyze-preview-header = Guardando vistas previas de series en: { $path }
+analyze-preview-written = ✓ Serie { $series }{ $description } -> { $path } ({ $frames } imágenes)
+analyze-preview-failed = ✗ No se pudo crear la vista previa de la serie { $series }: { $error }
+
+## Inventario
+
+inventory-reading = Leyendo los encabezados de { $count } archivo(s) DICOM...
+inventory-total = { $studies } estudios, { $series } series, { $instances } instancias, { $size }
+inventory-dates = Fechas de estudio: { $first } a { $last }
+inventory-series-per-study = Series por estudio: { $fewest } a { $most }
+inventory-unreadable = ✗ { $count } archivo(s) no se pudieron leer como DICOM
+inventory-by-modality = Por modalidad:
+inventory-by-scanner = Por equipo:
+inventory-entry = - { $key }: { $studies } estudios, { $series } series, { $instances } instancias, { $size }
+inventory-saved = ✓ Inventario guardado: { $path }
+
+## Hash
+
+hash-reading = Calculando el { $algorithm } de { $count } archivo(s) DICOM...
+hash-unreadable = ✗ No se puede leer { $file }: { $error }
+hash-studies-header = Sumas de los estudios (independientes de los nombres y el orden de los archivos):
+hash-study = - { $uid }: { $checksum } ({ $count } archivos)
+hash-saved = ✓ Manifiesto guardado: { $path }
+hash-changed = ✗ Modificado: { $file }
+hash-missing = ✗ Falta: { $file }
+hash-extra = ? No está en el manifiesto: { $file }
+hash-verified = { $matched } de { $total } archivo(s) coinciden con el manifiesto; { $intact } de { $studies } estudios intactos
+verify-pixels-comparing = Decodificando { $count } archivo(s) DICOM y sus copias...
+verify-pixels-identical = ✓ Idéntico: { $file } ({ $frames } imagen(es))
+verify-pixels-lossy = ~ Copia con pérdida: { $file } (PSNR { $psnr } dB, diferencia máxima { $max })
+verify-pixels-differs = ✗ Los píxeles difieren: { $file }, imagen { $frame } (diferencia máxima { $max }, PSNR { $psnr } dB)
+verify-pixels-failed = ✗ No se puede comparar { $file }: { $error }
+verify-pixels-no-copy = no hay copia con la misma ruta ni SOPInstanceUID
+verify-pixels-summary = { $identical } de { $total } archivo(s) tienen píxeles idénticos; { $lossy } con pérdida
+verify-pixels-lowest-psnr = PSNR más bajo de las copias con pérdida: { $psnr } dB
+verify-pixels-frame-count = { $source } imagen(es) en el original, { $copy } en la copia
+verify-pixels-frame-error = imagen { $frame }: { $error }
+verify-pixels-size = { $source } en el original, { $copy } en la copia
+verify-pixels-color-mismatch = una es a color y la otra monocromática
+verify-pixels-mismatched = La verificación falló: { $count } archivo(s) difieren o no se pudieron comparar
+private-tags-writing = Copiando { $count } archivo(s) DICOM con la política de etiquetas privadas...
+private-tags-file = ✓ { $file }: { $kept } elemento(s) privado(s) conservado(s), { $dropped } eliminado(s)
+private-tags-failed = ✗ No se puede copiar { $file }: { $error }
+private-tags-summary = Se escribieron { $files } archivo(s) en { $path }: { $kept } elemento(s) privado(s) conservado(s), { $dropped } eliminado(s)
+
+## Lenient parsing
+
+lenient-odd-lengths = ⚠ Recuperado { $file }: los elementos de longitud impar se leyeron con la longitud par siguiente
+lenient-pixel-data-padded = ⚠ Recuperado { $file }: los datos de píxeles están cortados ({ $read } de { $expected } bytes); el resto queda en negro
+lenient-trailing-data = ⚠ Recuperado { $file }: se descartaron datos ilegibles tras los datos de píxeles
+
+## Test data
+
+gen-test-data-saved = ✓ Se escribieron { $count } archivo(s) DICOM sintéticos en { $series } serie(s) en: { $path }
+
+## Desktop window
+
+gui-title = DCM Toolbox
+gui-input = Entrada
+gui-drop-hint = Arrastre una carpeta de archivos DICOM a esta ventana, o escriba su ruta:
+gui-open = Abrir
+gui-loading = Leyendo las series...
+gui-no-series = No se encontraron series DICOM en esta carpeta
+gui-series = Serie { $number } { $description } ({ $count } archivos)
+gui-output = Carpeta de salida
+gui-force = Reemplazar las carpetas de series existentes
+gui-format = Convertir a
+gui-format-jpeg = Imágenes JPEG
+gui-format-video = Video MP4
+gui-format-stl = Modelo 3D STL
+gui-custom-window = Ventana propia en lugar de la del archivo
+gui-window-center = Centro
+gui-window-width = Ancho
+gui-fps = Cuadros por segundo
+gui-auto-threshold = Buscar el umbral automáticamente (Otsu)
+gui-threshold = Umbral
+gui-open-hint = Abra primero una carpeta con archivos DICOM
+gui-output-missing = Elija una carpeta de salida
+gui-output-not-empty = La carpeta de salida no está vacía: marque "Reemplazar las carpetas de series existentes" o elija otra
+gui-convert = Convertir
+gui-pause = Pausar
+gui-resume = Reanudar
+gui-cancel = Cancelar
+gui-progress = { $done } de { $total } series
+gui-finished = ✓ Terminado ({ $status }): { $path }
+gui-failed = ✗ La conversión falló: { $error }
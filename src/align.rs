@@ -0,0 +1,223 @@
+//! Placing one series on the grid of another.
+//!
+//! Series that share a `FrameOfReferenceUID` were acquired in the same
+//! patient coordinate system (a PET/CT pair, pre- and post-contrast scans of
+//! one session, a treatment plan's CT and dose), so the same patient
+//! position is the same point of the patient in both, and one can be read
+//! at the voxel positions of the other directly. Series in different frames
+//! need a `register` transform first. Shared by `register --resampled`,
+//! `subtract`, and the isodose overlays of `dose`.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+
+use crate::outcome::BadInput;
+use crate::register::Rigid;
+use crate::volume::Volume;
+
+/// How values between voxel centers are read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Interpolation {
+    /// Value of the nearest voxel (keeps labels and sharp edges)
+    Nearest,
+    /// Trilinear blend of the eight surrounding voxels
+    #[default]
+    Linear,
+}
+
+/// How a source volume maps onto a target grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alignment {
+    /// Target patient coordinates to source patient coordinates.
+    pub transform: Rigid,
+    pub interpolation: Interpolation,
+}
+
+impl Alignment {
+    /// Same patient position in both series, for series sharing a frame of
+    /// reference.
+    pub const fn by_position(interpolation: Interpolation) -> Self {
+        Self {
+            transform: Rigid::identity([0.0; 3]),
+            interpolation,
+        }
+    }
+
+    /// Value of `source` at the target patient position `point` (mm);
+    /// `None` where `source` does not reach.
+    pub fn sample(&self, source: &Volume, point: [f64; 3]) -> Option<f32> {
+        let point = self.transform.apply(point);
+        match self.interpolation {
+            Interpolation::Nearest => source.nearest(point),
+            Interpolation::Linear => source.sample(point),
+        }
+    }
+
+    /// `source` read at every voxel of `target`, as a volume with the
+    /// target's geometry. Voxels `source` does not reach get its minimum.
+    pub fn resample(&self, target: &Volume, source: &Volume) -> Volume {
+        let background = source.value_range().0;
+        let mut values = Vec::with_capacity(target.values.len());
+        for z in 0..target.slices {
+            for y in 0..target.rows {
+                for x in 0..target.cols {
+                    let point = target.position(x, y, z);
+                    values.push(self.sample(source, point).unwrap_or(background));
+                }
+            }
+        }
+        Volume {
+            values,
+            ..target.clone()
+        }
+    }
+}
+
+/// `FrameOfReferenceUID` of a series, read from the header of its first
+/// file.
+pub fn frame_of_reference(files: &[PathBuf]) -> Option<String> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(files.first()?)
+        .ok()?;
+    let uid = obj
+        .element(tags::FRAME_OF_REFERENCE_UID)
+        .ok()?
+        .to_str()
+        .ok()?;
+    let uid = uid.trim_end_matches(['\0', ' ']).trim();
+    (!uid.is_empty()).then(|| uid.to_string())
+}
+
+/// Whether patient positions of the two series can be matched without a
+/// transform: false only when both record a frame of reference and the two
+/// differ. A series without one is taken at its word.
+pub fn same_frame(target: &[PathBuf], source: &[PathBuf]) -> bool {
+    differing_frames(target, source).is_none()
+}
+
+/// [`same_frame`], failing with [`BadInput`] when it is not.
+pub fn check_same_frame(target: &[PathBuf], source: &[PathBuf]) -> Result<()> {
+    match differing_frames(target, source) {
+        Some((a, b)) => anyhow::bail!(BadInput(format!(
+            "The series are in different frames of reference ({a} and {b}), so their \
+             patient positions do not match; align them with `register` first"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The two frames of reference, when both are known and differ.
+fn differing_frames(target: &[PathBuf], source: &[PathBuf]) -> Option<(String, String)> {
+    let (a, b) = (frame_of_reference(target)?, frame_of_reference(source)?);
+    (a != b).then_some((a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom::object::InMemDicomObject;
+
+    use super::*;
+
+    /// 4x4x4 volume whose value is `x + 4 y + 16 z`, 1 mm spacing.
+    fn ramp() -> Volume {
+        let mut values = Vec::new();
+        for z in 0..4_u16 {
+            for y in 0..4_u16 {
+                for x in 0..4_u16 {
+                    values.push(f32::from(x + 4 * y + 16 * z));
+                }
+            }
+        }
+        Volume {
+            values,
+            cols: 4,
+            rows: 4,
+            slices: 4,
+            spacing_x: 1.0,
+            spacing_y: 1.0,
+            spacing_z: 1.0,
+            origin: [0.0; 3],
+        }
+    }
+
+    /// A header-only file in the frame of reference `uid`.
+    fn write_header(path: &Path, uid: &str) {
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from("CT")),
+            DataElement::new(
+                tags::FRAME_OF_REFERENCE_UID,
+                VR::UI,
+                PrimitiveValue::from(uid),
+            ),
+        ]);
+        let meta = dicom::object::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        obj.with_exact_meta(meta).write_to_file(path).unwrap();
+    }
+
+    #[test]
+    fn identity_copies_values() {
+        let volume = ramp();
+        for interpolation in [Interpolation::Nearest, Interpolation::Linear] {
+            let out = Alignment::by_position(interpolation).resample(&volume, &volume);
+            assert_eq!(out.values, volume.values);
+        }
+    }
+
+    #[test]
+    fn translation_is_followed_and_the_rest_is_background() {
+        let volume = ramp();
+        let mut transform = Rigid::identity(volume.center());
+        transform.params[0] = 1.0;
+        let alignment = Alignment {
+            transform,
+            interpolation: Interpolation::Linear,
+        };
+        let out = alignment.resample(&volume, &volume);
+        // x=0 reads source x=1; the last column falls outside
+        assert_eq!(&out.values[..4], &[1.0, 2.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn nearest_keeps_values_between_voxels() {
+        let volume = ramp();
+        let point = [1.4, 0.0, 0.0];
+        let linear = Alignment::by_position(Interpolation::Linear).sample(&volume, point);
+        let nearest = Alignment::by_position(Interpolation::Nearest).sample(&volume, point);
+        assert!((linear.unwrap() - 1.4).abs() < 1e-5);
+        assert_eq!(nearest, Some(1.0));
+    }
+
+    #[test]
+    fn differing_frames_of_reference_are_bad_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let series = |name: &str, uid: &str| {
+            let path = dir.path().join(name);
+            write_header(&path, uid);
+            vec![path]
+        };
+        let (ct, pet, other) = (
+            series("ct.dcm", "1.2.3.4"),
+            series("pet.dcm", "1.2.3.4"),
+            series("other.dcm", "9.8.7"),
+        );
+        assert_eq!(frame_of_reference(&ct).as_deref(), Some("1.2.3.4"));
+        assert!(same_frame(&ct, &pet));
+        let err = check_same_frame(&ct, &other).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        // Unknown frames are not checked
+        assert!(same_frame(&ct, &[dir.path().join("missing.dcm")]));
+    }
+}
@@ -0,0 +1,180 @@
+//! Animated GIF "scrub" previews for `analyze --preview`.
+//!
+//! Each series gets a tiny looping GIF built from every Nth slice, so series
+//! can be told apart visually before choosing split/convert options.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame};
+
+use crate::i18n::t;
+use crate::meta::{InstanceMeta, SeriesMeta};
+use crate::pipeline::{self, Frames, RenderOptions};
+use crate::utils::sanitize_filename;
+
+/// Longest edge of preview frames, in pixels.
+const PREVIEW_SIZE: u32 = 64;
+
+/// Only every Nth slice (or frame of a multi-frame object) is included.
+const SLICE_STEP: usize = 10;
+
+/// Display time of each preview frame.
+const FRAME_DELAY_MS: u32 = 100;
+
+/// Default preview folder when `--preview-dir` is not given.
+pub fn default_dir() -> PathBuf {
+    std::env::temp_dir().join("dcm-toolbox-preview")
+}
+
+/// Write one preview GIF per series (keyed by `SeriesInstanceUID`).
+///
+/// A series that cannot be rendered is reported and skipped.
+pub fn write_previews(series: &BTreeMap<String, SeriesMeta>, preview_dir: &Path) -> Result<()> {
+    fs::create_dir_all(preview_dir)
+        .with_context(|| format!("Failed to create preview folder: {}", preview_dir.display()))?;
+
+    println!(
+        "\n{}",
+        t!(
+            "analyze-preview-header",
+            path = preview_dir.display().to_string()
+        )
+    );
+
+    for (uid, series) in series {
+        let Some(first) = series.first() else {
+            continue;
+        };
+        let files = series.files();
+        let (number, description) = series_label(first);
+        let gif_path = preview_dir.join(format!(
+            "{}.gif",
+            sanitize_filename(&format!("{number}_{uid}"))
+        ));
+
+        match sample_frames(&files).and_then(|frames| write_gif(frames, &gif_path)) {
+            Ok(count) => println!(
+                "  {}",
+                t!(
+                    "analyze-preview-written",
+                    series = number,
+                    description = description,
+                    path = gif_path.display().to_string(),
+                    frames = count
+                )
+            ),
+            Err(e) => eprintln!(
+                "  {}",
+                t!(
+                    "analyze-preview-failed",
+                    series = number,
+                    error = format!("{e:#}")
+                )
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// `SeriesNumber` and quoted `SeriesDescription` (with a leading space, or
+/// empty) of a file, for naming and display.
+fn series_label(first: &InstanceMeta) -> (String, String) {
+    (
+        first
+            .series_number
+            .map_or_else(|| "unknown".to_string(), |n| n.to_string()),
+        first
+            .series_description
+            .as_ref()
+            .map_or_else(String::new, |d| format!(" \"{d}\"")),
+    )
+}
+
+/// Decode every [`SLICE_STEP`]th slice of a series as preview frames.
+///
+/// A series made of a single multi-frame object is sampled by frame instead.
+fn sample_frames(files: &[PathBuf]) -> Result<Vec<Frame>> {
+    let images: Vec<DynamicImage> = if let [single] = files {
+        Frames::open(single)?
+            .step_by(SLICE_STEP)
+            .filter_map(Result::ok)
+            .map(|frame| pipeline::render_frame(frame, RenderOptions::default()))
+            .collect()
+    } else {
+        files
+            .iter()
+            .step_by(SLICE_STEP)
+            .filter_map(|path| pipeline::load_frame(path, 0).ok())
+            .map(|frame| pipeline::render_frame(frame, RenderOptions::default()))
+            .collect()
+    };
+
+    if images.is_empty() {
+        anyhow::bail!("No decodable slices");
+    }
+
+    Ok(images.iter().map(to_gif_frame).collect())
+}
+
+/// Shrink an image to the preview size and wrap it as a GIF frame.
+fn to_gif_frame(image: &DynamicImage) -> Frame {
+    let thumbnail = image.thumbnail(PREVIEW_SIZE, PREVIEW_SIZE).to_rgba8();
+    Frame::from_parts(
+        thumbnail,
+        0,
+        0,
+        Delay::from_numer_denom_ms(FRAME_DELAY_MS, 1),
+    )
+}
+
+/// Encode frames as an endlessly looping GIF. Returns the frame count.
+fn write_gif(frames: Vec<Frame>, path: &Path) -> Result<usize> {
+    let count = frames.len();
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create preview: {}", path.display()))?;
+
+    let mut encoder = GifEncoder::new(BufWriter::new(file));
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder
+        .encode_frames(frames)
+        .with_context(|| format!("Failed to encode preview: {}", path.display()))?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_shrunk_to_preview_size() {
+        let frame = to_gif_frame(&DynamicImage::new_luma8(512, 256));
+        assert_eq!(frame.buffer().dimensions(), (64, 32));
+    }
+
+    #[test]
+    fn writes_looping_gif() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("preview.gif");
+        let frames = (0..3)
+            .map(|_| to_gif_frame(&DynamicImage::new_luma8(128, 128)))
+            .collect();
+
+        assert_eq!(write_gif(frames, &path).unwrap(), 3);
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(b"GIF89a"));
+    }
+
+    #[test]
+    fn unreadable_series_has_no_frames() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let files = vec![temp_dir.path().join("a.dcm"), temp_dir.path().join("b.dcm")];
+        assert!(sample_frames(&files).is_err());
+    }
+}
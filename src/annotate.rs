@@ -0,0 +1,522 @@
+//! Annotation overlays for `--annotations`.
+//!
+//! An annotation file maps `SOPInstanceUID`s to shapes, so predictions from
+//! an ML model can be checked on the exported images and video:
+//!
+//! ```json
+//! {
+//!   "1.2.840.113619.2.55.3.1": [
+//!     { "type": "box", "x": 120, "y": 88, "width": 40, "height": 32,
+//!       "label": "nodule 0.92", "color": "#ff0000" },
+//!     { "type": "polygon", "points": [[10, 10], [60, 12], [35, 50]],
+//!       "label": "liver", "frame": 3 }
+//!   ]
+//! }
+//! ```
+//!
+//! Coordinates are pixels of the original frame. `frame` (0-based) limits a
+//! shape to one frame of a multi-frame object; without it the shape is drawn
+//! on every frame. `label` and `color` are optional.
+
+mod colorbar;
+pub mod font;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use image::{DynamicImage, Rgb, RgbImage};
+use serde::Deserialize;
+
+use crate::outcome::BadInput;
+
+pub use colorbar::Legend;
+
+/// Color used when an annotation has none.
+const DEFAULT_COLOR: Rgb<u8> = Rgb([255, 255, 0]);
+
+/// Coordinates are clamped to this magnitude so stray values cannot make a
+/// line walk for billions of steps.
+const MAX_COORDINATE: f32 = 1e6;
+
+/// Annotations keyed by `SOPInstanceUID`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Annotations {
+    instances: HashMap<String, Vec<Annotation>>,
+}
+
+/// A single shape with an optional label.
+#[derive(Debug, PartialEq, Deserialize)]
+struct Annotation {
+    #[serde(flatten)]
+    shape: Shape,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    color: Option<Color>,
+    #[serde(default)]
+    frame: Option<usize>,
+}
+
+/// Geometry of an annotation, in frame pixels.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Shape {
+    Box {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+}
+
+/// `#rrggbb` color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+struct Color(Rgb<u8>);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        parse_hex_color(&value)
+            .map(Self)
+            .ok_or_else(|| format!("invalid color `{value}`, expected #rrggbb"))
+    }
+}
+
+/// Parse a `#rrggbb` color.
+pub fn parse_hex_color(value: &str) -> Option<Rgb<u8>> {
+    let hex = value.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+impl Annotations {
+    /// Read an annotation file. Unreadable or malformed files are [`BadInput`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            BadInput(format!(
+                "Failed to read annotations: {}: {e}",
+                path.display()
+            ))
+        })?;
+        serde_json::from_str(&text).map_err(|e| {
+            BadInput(format!("Invalid annotation file: {}: {e}", path.display())).into()
+        })
+    }
+
+    /// Number of instances and shapes in the file.
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.instances.len(),
+            self.instances.values().map(Vec::len).sum(),
+        )
+    }
+
+    /// Draw the annotations of one frame onto its rendered image.
+    ///
+    /// Images without matching annotations are returned unchanged; annotated
+    /// ones are converted to RGB.
+    pub fn draw(&self, image: DynamicImage, sop_instance_uid: &str, frame: usize) -> DynamicImage {
+        let mut visible = self.visible(sop_instance_uid, frame).peekable();
+        if visible.peek().is_none() {
+            return image;
+        }
+
+        let mut canvas = Canvas::new(image.into_rgb8());
+        for annotation in visible {
+            canvas.draw(annotation);
+        }
+        DynamicImage::ImageRgb8(canvas.image)
+    }
+
+    /// Boxes of one frame, for patch export.
+    pub fn boxes<'a>(
+        &'a self,
+        sop_instance_uid: &str,
+        frame: usize,
+    ) -> impl Iterator<Item = LabeledBox<'a>> {
+        self.visible(sop_instance_uid, frame)
+            .filter_map(|annotation| match annotation.shape {
+                Shape::Box {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => Some(LabeledBox {
+                    x,
+                    y,
+                    width,
+                    height,
+                    label: annotation.label.as_deref(),
+                }),
+                Shape::Polygon { .. } => None,
+            })
+    }
+
+    /// Annotations that apply to one frame of an instance.
+    fn visible<'a>(
+        &'a self,
+        sop_instance_uid: &str,
+        frame: usize,
+    ) -> impl Iterator<Item = &'a Annotation> {
+        self.instances
+            .get(sop_instance_uid)
+            .into_iter()
+            .flatten()
+            .filter(move |a| a.frame.is_none_or(|f| f == frame))
+    }
+}
+
+/// A box annotation in frame pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabeledBox<'a> {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub label: Option<&'a str>,
+}
+
+impl LabeledBox<'_> {
+    /// Pixel rectangle `(x, y, width, height)` clipped to a `width` x `height`
+    /// image, or `None` when no part of the box is inside it.
+    pub fn clip(&self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let span = |start: f32, len: f32, max: u32| {
+            let lo = to_pixel(start).clamp(0, i64::from(max));
+            let hi = to_pixel(start + len).clamp(0, i64::from(max));
+            Some((u32::try_from(lo).ok()?, u32::try_from(hi - lo).ok()?))
+                .filter(|&(_, len)| len > 0)
+        };
+        let (x, w) = span(self.x, self.width, width)?;
+        let (y, h) = span(self.y, self.height, height)?;
+        Some((x, y, w, h))
+    }
+}
+
+/// An RGB image with stroke and label sizes scaled to its dimensions.
+pub struct Canvas {
+    image: RgbImage,
+    /// Line thickness in pixels.
+    thickness: i64,
+    /// Font magnification.
+    scale: i64,
+}
+
+impl Canvas {
+    pub fn new(image: RgbImage) -> Self {
+        let longest = i64::from(image.width().max(image.height()));
+        Self {
+            image,
+            thickness: 1 + longest / 512,
+            scale: 1 + longest / 256,
+        }
+    }
+
+    /// The same canvas with labels magnified `scale` times instead, for
+    /// text sized by print resolution rather than by the image.
+    pub fn scaled(self, scale: i64) -> Self {
+        Self { scale, ..self }
+    }
+
+    pub fn into_image(self) -> RgbImage {
+        self.image
+    }
+
+    /// Width and height in pixels.
+    pub fn size(&self) -> (i64, i64) {
+        (
+            i64::from(self.image.width()),
+            i64::from(self.image.height()),
+        )
+    }
+
+    /// Line thickness in pixels.
+    pub const fn thickness(&self) -> i64 {
+        self.thickness
+    }
+
+    fn draw(&mut self, annotation: &Annotation) {
+        let color = annotation.color.map_or(DEFAULT_COLOR, |c| c.0);
+        let points: Vec<(i64, i64)> = match &annotation.shape {
+            Shape::Box {
+                x,
+                y,
+                width,
+                height,
+            } => vec![
+                (*x, *y),
+                (x + width, *y),
+                (x + width, y + height),
+                (*x, y + height),
+            ],
+            Shape::Polygon { points } => points.iter().map(|&point| point.into()).collect(),
+        }
+        .into_iter()
+        .map(|(x, y)| (to_pixel(x), to_pixel(y)))
+        .collect();
+
+        for (i, &start) in points.iter().enumerate() {
+            let end = points[(i + 1) % points.len()];
+            self.line(start, end, color);
+        }
+
+        if let Some(label) = &annotation.label
+            && let Some(left) = points.iter().map(|p| p.0).min()
+            && let Some(top) = points.iter().map(|p| p.1).min()
+        {
+            self.label(label, (left, top), color);
+        }
+    }
+
+    /// Bresenham line with square brush of [`Self::thickness`].
+    pub fn line(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), color: Rgb<u8>) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+        loop {
+            self.fill((x, y), (self.thickness, self.thickness), color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Label on a filled tag just above `(left, top)`, or just inside the
+    /// shape when there is no room above it.
+    pub fn label(&mut self, text: &str, (left, top): (i64, i64), color: Rgb<u8>) {
+        let s = self.scale;
+        let glyph_width = i64::from(font::WIDTH) + 1;
+        let (tag_width, tag_height) = self.tag_size(text);
+        let tag_top = if top >= tag_height {
+            top - tag_height
+        } else {
+            top + self.thickness
+        };
+
+        self.fill((left, tag_top), (tag_width, tag_height), color);
+        let ink = contrasting(color);
+        for (i, c) in (0..).zip(text.chars()) {
+            let glyph_left = left + (i * glyph_width + 1) * s;
+            for (row, bits) in (0..).zip(font::glyph(c)) {
+                for column in 0..font::WIDTH {
+                    if bits & (1 << (font::WIDTH - 1 - column)) != 0 {
+                        let x = glyph_left + i64::from(column) * s;
+                        let y = tag_top + (row + 1) * s;
+                        self.fill((x, y), (s, s), ink);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Width and height of the tag [`Self::label`] draws for `text`.
+    pub fn tag_size(&self, text: &str) -> (i64, i64) {
+        let chars = i64::try_from(text.chars().count()).unwrap_or(0);
+        let width = (chars * (i64::from(font::WIDTH) + 1) + 1) * self.scale;
+        (width, (i64::from(font::HEIGHT) + 2) * self.scale)
+    }
+
+    /// Fill a rectangle, clipped to the image.
+    pub fn fill(&mut self, (left, top): (i64, i64), (width, height): (i64, i64), color: Rgb<u8>) {
+        let clip = |start: i64, len: i64, max: u32| {
+            let lo = u32::try_from(start.max(0)).unwrap_or(max);
+            let hi = u32::try_from((start + len).max(0)).unwrap_or(max).min(max);
+            lo..hi
+        };
+        for y in clip(top, height, self.image.height()) {
+            for x in clip(left, width, self.image.width()) {
+                self.image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// Round a frame coordinate to a pixel index.
+#[allow(clippy::cast_possible_truncation)]
+fn to_pixel(value: f32) -> i64 {
+    value.round().clamp(-MAX_COORDINATE, MAX_COORDINATE) as i64
+}
+
+/// Black or white, whichever reads better on `background`.
+fn contrasting(background: Rgb<u8>) -> Rgb<u8> {
+    let [r, g, b] = background.0.map(u32::from);
+    if 299 * r + 587 * g + 114 * b > 128_000 {
+        Rgb([0, 0, 0])
+    } else {
+        Rgb([255, 255, 255])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UID: &str = "1.2.3";
+
+    fn parse(json: &str) -> Annotations {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn blank() -> DynamicImage {
+        DynamicImage::new_luma8(64, 64)
+    }
+
+    // =========================================================================
+    // File Format Tests
+    // =========================================================================
+
+    mod file_format {
+        use super::*;
+
+        #[test]
+        fn parses_boxes_and_polygons() {
+            let annotations = parse(
+                r##"{"1.2.3": [
+                    {"type": "box", "x": 1, "y": 2, "width": 3, "height": 4, "label": "a", "color": "#00ff80"},
+                    {"type": "polygon", "points": [[0, 0], [5, 0], [0, 5]], "frame": 2}
+                ]}"##,
+            );
+            assert_eq!(annotations.counts(), (1, 2));
+            let shapes = &annotations.instances[UID];
+            assert_eq!(shapes[0].color, Some(Color(Rgb([0, 255, 128]))));
+            assert_eq!(shapes[0].label.as_deref(), Some("a"));
+            assert_eq!(shapes[1].frame, Some(2));
+            assert!(matches!(&shapes[1].shape, Shape::Polygon { points } if points.len() == 3));
+        }
+
+        #[test]
+        fn rejects_bad_colors() {
+            for color in ["red", "#ff00", "#gg0000"] {
+                let json = format!(
+                    r#"{{"{UID}": [{{"type": "box", "x": 0, "y": 0, "width": 1, "height": 1, "color": "{color}"}}]}}"#
+                );
+                assert!(
+                    serde_json::from_str::<Annotations>(&json).is_err(),
+                    "{color}"
+                );
+            }
+        }
+
+        #[test]
+        fn rejects_unknown_shapes() {
+            let json = r#"{"1.2.3": [{"type": "circle", "x": 0, "y": 0}]}"#;
+            assert!(serde_json::from_str::<Annotations>(json).is_err());
+        }
+    }
+
+    // =========================================================================
+    // Drawing Tests
+    // =========================================================================
+
+    mod drawing {
+        use super::*;
+
+        #[test]
+        fn draws_box_outline_only() {
+            let annotations = parse(
+                r##"{"1.2.3": [{"type": "box", "x": 10, "y": 10, "width": 20, "height": 20, "color": "#ff0000"}]}"##,
+            );
+            let image = annotations.draw(blank(), UID, 0).into_rgb8();
+            assert_eq!(image.get_pixel(10, 20).0, [255, 0, 0]);
+            assert_eq!(image.get_pixel(30, 30).0, [255, 0, 0]);
+            assert_eq!(image.get_pixel(20, 20).0, [0, 0, 0]);
+        }
+
+        #[test]
+        fn unmatched_instance_is_untouched() {
+            let annotations =
+                parse(r#"{"9.9.9": [{"type": "box", "x": 0, "y": 0, "width": 5, "height": 5}]}"#);
+            assert!(matches!(
+                annotations.draw(blank(), UID, 0),
+                DynamicImage::ImageLuma8(_)
+            ));
+        }
+
+        #[test]
+        fn frame_filter_applies_to_multi_frame_objects() {
+            let annotations = parse(
+                r#"{"1.2.3": [{"type": "polygon", "points": [[0, 0], [63, 63]], "frame": 1}]}"#,
+            );
+            assert!(matches!(
+                annotations.draw(blank(), UID, 0),
+                DynamicImage::ImageLuma8(_)
+            ));
+            let image = annotations.draw(blank(), UID, 1).into_rgb8();
+            assert_eq!(image.get_pixel(32, 32).0, DEFAULT_COLOR.0);
+        }
+
+        #[test]
+        fn label_sits_above_the_shape() {
+            let annotations = parse(
+                r##"{"1.2.3": [{"type": "box", "x": 20, "y": 30, "width": 10, "height": 10, "label": "I", "color": "#ffffff"}]}"##,
+            );
+            let image = annotations.draw(blank(), UID, 0).into_rgb8();
+            // White tag with black ink, ending right above the box.
+            assert_eq!(image.get_pixel(20, 29).0, [255, 255, 255]);
+            assert!((21..30).any(|y| image.get_pixel(22, y).0 == [0, 0, 0]));
+        }
+
+        #[test]
+        fn shapes_outside_the_image_are_clipped() {
+            let annotations = parse(
+                r#"{"1.2.3": [{"type": "box", "x": -50, "y": -50, "width": 500, "height": 500, "label": "big"}]}"#,
+            );
+            let image = annotations.draw(blank(), UID, 0).into_rgb8();
+            assert_eq!(image.dimensions(), (64, 64));
+        }
+
+        #[test]
+        fn boxes_skip_polygons_and_other_frames() {
+            let annotations = parse(
+                r#"{"1.2.3": [
+                    {"type": "box", "x": 1, "y": 2, "width": 3, "height": 4, "label": "a"},
+                    {"type": "box", "x": 0, "y": 0, "width": 1, "height": 1, "frame": 5},
+                    {"type": "polygon", "points": [[0, 0], [5, 5]]}
+                ]}"#,
+            );
+            let boxes: Vec<_> = annotations.boxes(UID, 0).collect();
+            assert_eq!(boxes.len(), 1);
+            assert_eq!(boxes[0].label, Some("a"));
+            assert_eq!(annotations.boxes(UID, 5).count(), 2);
+            assert_eq!(annotations.boxes("9.9.9", 0).count(), 0);
+        }
+
+        #[test]
+        fn box_is_clipped_to_the_image() {
+            let inside = LabeledBox {
+                x: 10.0,
+                y: 20.0,
+                width: 5.0,
+                height: 6.0,
+                label: None,
+            };
+            assert_eq!(inside.clip(64, 64), Some((10, 20, 5, 6)));
+            let partly = LabeledBox { x: -2.0, ..inside };
+            assert_eq!(partly.clip(64, 64), Some((0, 20, 3, 6)));
+            let outside = LabeledBox { x: 70.0, ..inside };
+            assert_eq!(outside.clip(64, 64), None);
+        }
+
+        #[test]
+        fn ink_contrasts_with_tag() {
+            assert_eq!(contrasting(Rgb([255, 255, 0])).0, [0, 0, 0]);
+            assert_eq!(contrasting(Rgb([0, 0, 128])).0, [255, 255, 255]);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Calibrated colorbars for `--colorbar`.
+//!
+//! A ramp on the right edge of a frame shows which gray level stands for
+//! which value: the frame's window from its low end at the bottom to its
+//! high end at the top, ticked at round values in modality units (HU for
+//! CT, SUV with `--suv`). Frames windowed differently get their own ticks.
+
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use image::{DynamicImage, Rgb};
+
+use super::Canvas;
+use crate::meta;
+use crate::pixel::{DecodedFrame, Window};
+
+/// Color of the outline, ticks, and labels.
+const INK: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Most ticks on a bar.
+const MAX_TICKS: i64 = 6;
+
+/// The scale of a rendered frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Legend {
+    window: Window,
+    invert: bool,
+    unit: Option<&'static str>,
+}
+
+impl Legend {
+    /// The scale `frame` renders with, its values in `unit`; `None` for
+    /// color frames.
+    pub fn of(frame: &DecodedFrame, unit: Option<&'static str>) -> Option<Self> {
+        match frame {
+            DecodedFrame::Mono(frame) => Some(Self {
+                window: frame.display_window(),
+                invert: frame.invert,
+                unit,
+            }),
+            DecodedFrame::Color(_) => None,
+        }
+    }
+
+    /// Unit of the calibrated values of `obj`: SUV once scaled by `--suv`,
+    /// HU for CT.
+    pub fn unit_of(obj: &InMemDicomObject, suv: bool) -> Option<&'static str> {
+        let text = |tag| meta::text(obj, tag);
+        if suv {
+            Some("SUV")
+        } else if text(tags::RESCALE_TYPE).as_deref() == Some("HU")
+            || text(tags::MODALITY).as_deref() == Some("CT")
+        {
+            Some("HU")
+        } else {
+            None
+        }
+    }
+
+    /// Draw the colorbar on the right edge of `image`. Gray images stay gray.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn draw(self, image: DynamicImage) -> DynamicImage {
+        let gray = matches!(
+            image,
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLuma16(_)
+        );
+        let deep = matches!(image, DynamicImage::ImageLuma16(_));
+        let mut canvas = Canvas::new(image.into_rgb8());
+        let (width, height) = canvas.size();
+        let (bar_width, margin) = ((width / 32).max(4), (width / 64).max(2));
+        let bar_height = (height * 3 / 5).max(2);
+        let (left, top) = (width - margin - bar_width, (height - bar_height) / 2);
+        let (right, bottom) = (left + bar_width, top + bar_height - 1);
+        let (lo, hi) = self.window.bounds();
+        #[allow(clippy::cast_precision_loss)]
+        let at = |value: f64| {
+            let share = (value - lo) / (hi - lo);
+            bottom - (share * (bar_height - 1) as f64).round() as i64
+        };
+
+        for row in 0..bar_height {
+            #[allow(clippy::cast_precision_loss)]
+            let value = hi - (hi - lo) * row as f64 / (bar_height - 1) as f64;
+            let level = self.window.apply(value as f32);
+            let level = if self.invert { u8::MAX - level } else { level };
+            canvas.fill((left, top + row), (bar_width, 1), Rgb([level; 3]));
+        }
+        for (start, end) in [
+            ((left, top), (right, top)),
+            ((right, top), (right, bottom)),
+            ((right, bottom), (left, bottom)),
+            ((left, bottom), (left, top)),
+        ] {
+            canvas.line(start, end, INK);
+        }
+
+        let (_, tag_height) = canvas.tag_size("0");
+        let wanted = (bar_height / (tag_height * 2)).clamp(2, MAX_TICKS);
+        let (values, step) = ticks(lo, hi, wanted);
+        let tick_length = margin;
+        for value in values {
+            let y = at(value);
+            canvas.line((left - tick_length, y), (left, y), INK);
+            let text = tick_label(value, step);
+            let (tag_width, tag_height) = canvas.tag_size(&text);
+            // Tags sit left of the ticks, centered on their level
+            let tag_left = left - tick_length - margin - tag_width;
+            canvas.label(&text, (tag_left, y + tag_height / 2), INK);
+        }
+        if let Some(unit) = self.unit {
+            let (tag_width, _) = canvas.tag_size(unit);
+            canvas.label(unit, ((right - tag_width).max(0), top - margin), INK);
+        }
+
+        let image = DynamicImage::ImageRgb8(canvas.into_image());
+        match (gray, deep) {
+            (true, true) => DynamicImage::ImageLuma16(image.into_luma16()),
+            (true, false) => DynamicImage::ImageLuma8(image.into_luma8()),
+            _ => image,
+        }
+    }
+}
+
+/// Round values from `lo` to `hi`, 1, 2, or 5 times a power of ten apart,
+/// about `wanted` of them, and their step.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn ticks(lo: f64, hi: f64, wanted: i64) -> (Vec<f64>, f64) {
+    let raw = (hi - lo) / wanted as f64;
+    if !raw.is_finite() || raw <= 0.0 {
+        return (Vec::new(), 1.0);
+    }
+    let magnitude = 10_f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|multiple| multiple * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap_or(10.0 * magnitude);
+    // Counting steps rather than adding them keeps values round
+    let first = (lo / step - 1e-9).ceil() as i64;
+    let last = (hi / step + 1e-9).floor() as i64;
+    let values = (first..=last).map(|k| k as f64 * step).collect();
+    (values, step)
+}
+
+/// `value` with as many decimals as `step` needs.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn tick_label(value: f64, step: f64) -> String {
+    let decimals = if step >= 1.0 {
+        0
+    } else {
+        (-step.log10().floor()) as usize
+    };
+    // No `-0`
+    let value = if value.abs() < step * 1e-6 {
+        0.0
+    } else {
+        value
+    };
+    format!("{value:.decimals$}")
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GrayImage;
+
+    use super::*;
+    use crate::pixel::Frame;
+
+    #[test]
+    fn ticks_fall_on_round_values() {
+        assert_eq!(
+            ticks(-1000.0, 1000.0, 5),
+            (vec![-1000.0, -500.0, 0.0, 500.0, 1000.0], 500.0)
+        );
+        assert_eq!(
+            ticks(-160.0, 240.0, 5),
+            (vec![-100.0, 0.0, 100.0, 200.0], 100.0)
+        );
+        let (values, step) = ticks(0.0, 5.0, 5);
+        assert_eq!((values.len(), step), (6, 1.0));
+        assert_eq!(ticks(3.0, 3.0, 5).0, Vec::<f64>::new());
+    }
+
+    #[test]
+    fn labels_keep_the_decimals_of_the_step() {
+        assert_eq!(tick_label(500.0, 500.0), "500");
+        assert_eq!(tick_label(0.5, 0.5), "0.5");
+        assert_eq!(tick_label(0.25, 0.05), "0.25");
+        assert_eq!(tick_label(-1e-12, 0.5), "0.0");
+    }
+
+    #[test]
+    fn the_bar_runs_from_black_to_white() {
+        let frame = DecodedFrame::Mono(Frame {
+            width: 1,
+            height: 1,
+            values: vec![0.0],
+            window: Some(Window::spanning(0.0, 100.0)),
+            invert: false,
+        });
+        let legend = Legend::of(&frame, Some("HU")).unwrap();
+        let image = legend.draw(DynamicImage::ImageLuma8(GrayImage::new(256, 256)));
+        let DynamicImage::ImageLuma8(image) = image else {
+            panic!("gray images stay gray");
+        };
+        // Inside the bar (8 px wide, 4 px from the right edge), away from
+        // its outline
+        let x = 256 - 4 - 4;
+        assert!(image.get_pixel(x, 60).0[0] > 230);
+        assert!(image.get_pixel(x, 195).0[0] < 25);
+    }
+
+    #[test]
+    fn inverted_frames_get_an_inverted_bar() {
+        let frame = DecodedFrame::Mono(Frame {
+            width: 1,
+            height: 1,
+            values: vec![0.0],
+            window: Some(Window::spanning(0.0, 100.0)),
+            invert: true,
+        });
+        let legend = Legend::of(&frame, None).unwrap();
+        let DynamicImage::ImageLuma8(image) =
+            legend.draw(DynamicImage::ImageLuma8(GrayImage::new(256, 256)))
+        else {
+            panic!("gray images stay gray");
+        };
+        assert!(image.get_pixel(248, 60).0[0] < 25);
+    }
+}
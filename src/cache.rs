@@ -0,0 +1,221 @@
+//! On-disk cache of rendered frames (`--cache-dir`).
+//!
+//! Trying another frame rate, overlay, or output format re-decodes the same
+//! slices on every run. With a cache folder, the frames of each file are
+//! saved as lossless PNGs right after the transform stage, under a key made
+//! of a hash of the file's bytes and of the options that change its pixels:
+//! denoise, sharpen, background removal, 16-bit rendering, SUV display,
+//! colorbar, and window, along with the dcm-toolbox version. A later run
+//! with the same file and options reads the frames back instead of decoding
+//! them. Annotations are drawn after the cache, so changing them still hits
+//! it.
+//!
+//! An entry only counts once all frames of its file were saved. Entries are
+//! never removed; delete the folder to reclaim the space.
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::DynamicImage;
+
+use crate::pipeline::RenderOptions;
+
+/// Bump when rendering changes, so older entries are no longer used.
+const FORMAT_VERSION: u32 = 1;
+
+/// File in an entry recording its frame count and `SOPInstanceUID`, written
+/// once every frame is saved.
+const COMPLETE: &str = "frames";
+
+/// Folder of cached frames, shared by all series of a run.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameCache {
+    dir: PathBuf,
+}
+
+/// Cached frames of one file rendered with one set of options.
+#[derive(Debug)]
+pub struct Entry {
+    dir: PathBuf,
+}
+
+/// What a complete entry knows about its file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cached {
+    /// Number of frames.
+    pub count: usize,
+    /// `SOPInstanceUID` of the object, for matching annotations.
+    pub sop_instance_uid: Option<String>,
+}
+
+impl FrameCache {
+    /// Use `dir` as the cache, creating it if needed.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the cache folder: {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// The entry for `path` rendered with `options`; `None` if the file
+    /// cannot be read.
+    pub fn entry(&self, path: &Path, options: &RenderOptions<'_>) -> Option<Entry> {
+        let content = hash_file(path).ok()?;
+        let options = format!(
+            "{FORMAT_VERSION}|{}|{:?}|{:?}|{}|{}|{:?}|{}|{:?}",
+            env!("CARGO_PKG_VERSION"),
+            options.denoise,
+            options.sharpen.map(f32::to_bits),
+            options.strip_background,
+            options.deep,
+            options.suv.map(|display| display.max.to_bits()),
+            options.colorbar,
+            options
+                .window
+                .map(|w| (w.center.to_bits(), w.width.to_bits()))
+        );
+        let key = format!(
+            "{content:016x}-{:016x}",
+            fnv1a64(FNV_OFFSET, options.as_bytes())
+        );
+        Some(Entry {
+            dir: self.dir.join(&key[..2]).join(key),
+        })
+    }
+}
+
+impl Entry {
+    /// The entry's contents, if all frames are cached.
+    pub fn cached(&self) -> Option<Cached> {
+        let text = fs::read_to_string(self.dir.join(COMPLETE)).ok()?;
+        let mut lines = text.lines();
+        let count = lines.next()?.parse().ok()?;
+        let sop_instance_uid = lines.next().map(String::from);
+        Some(Cached {
+            count,
+            sop_instance_uid,
+        })
+    }
+
+    /// Read back frame `number`.
+    pub fn load(&self, number: usize) -> Result<DynamicImage> {
+        let path = self.frame_path(number);
+        image::open(&path)
+            .with_context(|| format!("Failed to read cached frame: {}", path.display()))
+    }
+
+    /// Save frame `number`.
+    pub fn store(&self, number: usize, image: &DynamicImage) -> Result<()> {
+        let path = self.frame_path(number);
+        fs::create_dir_all(&self.dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(image.save_with_format(&path, image::ImageFormat::Png)?))
+            .with_context(|| format!("Failed to cache frame: {}", path.display()))
+    }
+
+    /// Mark the entry complete with `count` frames.
+    pub fn complete(&self, count: usize, sop_instance_uid: Option<&str>) -> Result<()> {
+        let path = self.dir.join(COMPLETE);
+        let mut text = count.to_string();
+        if let Some(uid) = sop_instance_uid {
+            text = format!("{text}\n{uid}");
+        }
+        fs::write(&path, text)
+            .with_context(|| format!("Failed to cache frame count: {}", path.display()))
+    }
+
+    fn frame_path(&self, number: usize) -> PathBuf {
+        self.dir.join(format!("{number:06}.png"))
+    }
+}
+
+/// 64-bit FNV-1a hash of the file's bytes, with its length mixed in.
+fn hash_file(path: &Path) -> std::io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = vec![0; 1 << 16];
+    let (mut hash, mut length) = (FNV_OFFSET, 0_u64);
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(fnv1a64(hash, &length.to_le_bytes()));
+        }
+        hash = fnv1a64(hash, &buffer[..read]);
+        length += read as u64;
+    }
+}
+
+/// 64-bit FNV-1a offset basis.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continue a 64-bit FNV-1a hash over `bytes`; start from [`FNV_OFFSET`].
+fn fnv1a64(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    fn entry(cache: &FrameCache, path: &Path, sharpen: Option<f32>) -> Entry {
+        let options = RenderOptions {
+            sharpen,
+            ..RenderOptions::default()
+        };
+        cache.entry(path, &options).unwrap()
+    }
+
+    #[test]
+    fn key_follows_content_and_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FrameCache::open(&dir.path().join("cache")).unwrap();
+        let (a, b, c) = (
+            dir.path().join("a.dcm"),
+            dir.path().join("b.dcm"),
+            dir.path().join("c.dcm"),
+        );
+        fs::write(&a, b"same bytes").unwrap();
+        fs::write(&b, b"same bytes").unwrap();
+        fs::write(&c, b"other bytes").unwrap();
+
+        assert_eq!(entry(&cache, &a, None).dir, entry(&cache, &b, None).dir);
+        assert_ne!(entry(&cache, &a, None).dir, entry(&cache, &c, None).dir);
+        assert_ne!(
+            entry(&cache, &a, None).dir,
+            entry(&cache, &a, Some(1.0)).dir
+        );
+        assert!(
+            cache
+                .entry(&dir.path().join("missing.dcm"), &RenderOptions::default())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn frames_count_once_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FrameCache::open(dir.path()).unwrap();
+        let source = dir.path().join("a.dcm");
+        fs::write(&source, b"dicom").unwrap();
+        let entry = entry(&cache, &source, None);
+
+        let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(3, 2, Luma([77])));
+        entry.store(0, &image).unwrap();
+        assert_eq!(entry.cached(), None);
+        entry.complete(1, Some("1.2.3")).unwrap();
+        assert_eq!(
+            entry.cached(),
+            Some(Cached {
+                count: 1,
+                sop_instance_uid: Some("1.2.3".to_string())
+            })
+        );
+        assert_eq!(entry.load(0).unwrap(), image);
+    }
+}
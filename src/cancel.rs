@@ -0,0 +1,340 @@
+//! Stopping work early: Ctrl-C and `convert --timeout`.
+//!
+//! The first Ctrl-C only raises a flag. Frame loops stop before their next
+//! frame and ffmpeg children are killed, so errors unwind normally: staging
+//! folders are dropped, partial MP4s deleted, and `convert` still prints what
+//! it completed. A second Ctrl-C exits at once. A [`Cancel`] token also
+//! carries the deadline of one series (`--timeout`).
+//!
+//! The desktop window (`--features gui`) gives each run it starts a
+//! [`CancellationToken`] instead of sending Ctrl-C: cancelling it stops that
+//! run the same way, and pausing it holds the run's frame loops before their
+//! next frame until it is resumed; other builds only read the token. A
+//! series stopped part way is marked with an [`INCOMPLETE_MARKER`] file, so
+//! its folder is not mistaken for a finished one.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{self, Child, ExitStatus};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::error::ToolboxError;
+use crate::i18n::t;
+
+/// Exit code of a process stopped by Ctrl-C (128 + SIGINT).
+pub const INTERRUPTED_CODE: u8 = 130;
+
+/// How often a running child is checked for cancellation.
+const POLL: Duration = Duration::from_millis(50);
+
+/// File left in the folder of a series that stopped part way.
+pub const INCOMPLETE_MARKER: &str = "INCOMPLETE";
+
+/// Set by the first Ctrl-C.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl-C for the rest of the run. Errors (e.g. a handler already set)
+/// leave the default behavior, which kills the process.
+pub fn install() {
+    let _ = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(i32::from(INTERRUPTED_CODE));
+        }
+        eprintln!("\n{}", t!("cancel-interrupted"));
+    });
+}
+
+/// Why the whole process is to stop, if it is: Ctrl-C. A run with a
+/// [`CancellationToken`] also asks [`CancellationToken::ended`].
+pub fn ended() -> Option<Stopped> {
+    INTERRUPTED
+        .load(Ordering::SeqCst)
+        .then_some(Stopped::Interrupted)
+}
+
+/// What the clones of one [`CancellationToken`] share.
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+}
+
+/// Handle for the desktop window to stop or pause one run from its own
+/// thread. Clones control the same run; a new token (`default()`) starts
+/// neither cancelled nor paused.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<TokenState>);
+
+impl CancellationToken {
+    /// Stop the run as Ctrl-C does: before the next frame, killing ffmpeg.
+    #[cfg(feature = "gui")]
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Hold frame loops before their next frame. A running ffmpeg and the
+    /// `--timeout` clock go on.
+    #[cfg(feature = "gui")]
+    pub fn pause(&self) {
+        self.0.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Let paused frame loops go on.
+    #[cfg(feature = "gui")]
+    pub fn resume(&self) {
+        self.0.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether frame loops are held until [`Self::resume`].
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::SeqCst)
+    }
+
+    /// Why the run is to stop, if it is: Ctrl-C or [`Self::cancel`].
+    pub fn ended(&self) -> Option<Stopped> {
+        ended().or_else(|| {
+            self.0
+                .cancelled
+                .load(Ordering::SeqCst)
+                .then_some(Stopped::Cancelled)
+        })
+    }
+}
+
+/// Mark the folder `dir` of a series that `stopped` part way, naming why.
+pub fn mark_incomplete(dir: &Path, stopped: Stopped) -> Result<()> {
+    let path = dir.join(INCOMPLETE_MARKER);
+    fs::write(&path, format!("{stopped}\n"))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Why work stopped early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stopped {
+    /// Ctrl-C was pressed.
+    Interrupted,
+    /// The desktop window cancelled its [`CancellationToken`].
+    Cancelled,
+    /// The series ran past `--timeout`.
+    TimedOut(Duration),
+}
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interrupted => f.write_str("Interrupted by Ctrl-C"),
+            Self::Cancelled => f.write_str("Cancelled"),
+            Self::TimedOut(limit) => write!(f, "Timed out after {}s", limit.as_secs()),
+        }
+    }
+}
+
+impl Stopped {
+    /// Whether the whole run stops, not just the series (`--timeout`).
+    pub const fn ends_run(self) -> bool {
+        matches!(self, Self::Interrupted | Self::Cancelled)
+    }
+}
+
+impl std::error::Error for Stopped {}
+
+/// Cancellation token: Ctrl-C and the run's [`CancellationToken`], plus a
+/// deadline when one was set.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    /// When to give up, and the timeout it came from.
+    deadline: Option<(Instant, Duration)>,
+    /// Cancels or pauses the run this belongs to.
+    token: CancellationToken,
+}
+
+impl Cancel {
+    /// A token that also expires `timeout` from now.
+    pub fn within(timeout: Option<Duration>) -> Self {
+        Self {
+            deadline: timeout.map(|limit| (Instant::now() + limit, limit)),
+            token: CancellationToken::default(),
+        }
+    }
+
+    /// This token, also stopped and paused by `token`.
+    pub fn with_token(self, token: &CancellationToken) -> Self {
+        Self {
+            token: token.clone(),
+            ..self
+        }
+    }
+
+    /// Why to stop, if it is time to. While the run is paused, waits for
+    /// it to be resumed or stopped.
+    pub fn stopped(&self) -> Option<Stopped> {
+        while self.token.is_paused() && self.token.ended().is_none() {
+            thread::sleep(POLL);
+        }
+        if let Some(stopped) = self.token.ended() {
+            return Some(stopped);
+        }
+        self.deadline
+            .filter(|(at, _)| Instant::now() >= *at)
+            .map(|(_, limit)| Stopped::TimedOut(limit))
+    }
+
+    /// Fail with [`ToolboxError::Cancelled`] if it is time to stop.
+    pub fn check(&self) -> Result<(), ToolboxError> {
+        self.stopped().map_or(Ok(()), |stopped| Err(stopped.into()))
+    }
+
+    /// Wait for `child`, killing it when it is time to stop. Its stderr, if
+    /// piped, is drained meanwhile and returned.
+    pub fn wait(&self, child: &mut Child) -> Result<(ExitStatus, String)> {
+        let reader = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut text = String::new();
+                let _ = stderr.read_to_string(&mut text);
+                text
+            })
+        });
+        let status = loop {
+            // A Ctrl-C reaches the child too; report it rather than its error
+            if let Some(stopped) = self.stopped() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(stopped.into());
+            }
+            if let Some(status) = child
+                .try_wait()
+                .context("Failed to wait for a child process")?
+            {
+                break status;
+            }
+            thread::sleep(POLL);
+        };
+        let stderr = reader
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default();
+        Ok((status, stderr))
+    }
+}
+
+/// Why `err` stopped the work, if it comes from a stop, raised as a
+/// [`Stopped`] or a [`ToolboxError::Cancelled`].
+pub fn stopped_by(err: &anyhow::Error) -> Option<Stopped> {
+    err.downcast_ref::<Stopped>()
+        .copied()
+        .or_else(|| err.downcast_ref::<ToolboxError>()?.stopped())
+}
+
+/// Whether `err` comes from a stop, so retrying is pointless.
+pub fn is_stopped(err: &anyhow::Error) -> bool {
+    stopped_by(err).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_deadline_stops() {
+        let cancel = Cancel::within(Some(Duration::ZERO));
+        assert_eq!(cancel.stopped(), Some(Stopped::TimedOut(Duration::ZERO)));
+        let err = anyhow::Error::from(cancel.check().unwrap_err());
+        assert!(is_stopped(&err));
+        assert_eq!(err.to_string(), "Timed out after 0s");
+    }
+
+    #[test]
+    fn stopped_series_are_marked() {
+        let dir = tempfile::tempdir().unwrap();
+        mark_incomplete(dir.path(), Stopped::TimedOut(Duration::from_secs(5))).unwrap();
+        let marker = fs::read_to_string(dir.path().join(INCOMPLETE_MARKER)).unwrap();
+        assert_eq!(marker, "Timed out after 5s\n");
+    }
+
+    #[test]
+    fn only_interrupts_and_cancels_end_the_run() {
+        assert!(Stopped::Interrupted.ends_run());
+        assert!(Stopped::Cancelled.ends_run());
+        assert!(!Stopped::TimedOut(Duration::ZERO).ends_run());
+    }
+
+    #[test]
+    fn no_deadline_runs_on() {
+        assert_eq!(Cancel::default().stopped(), None);
+        assert!(
+            Cancel::within(Some(Duration::from_hours(1)))
+                .check()
+                .is_ok()
+        );
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn tokens_stop_only_their_own_run() {
+        let token = CancellationToken::default();
+        let cancel = Cancel::default().with_token(&token);
+        let other = Cancel::default();
+        token.clone().cancel();
+        assert_eq!(cancel.stopped(), Some(Stopped::Cancelled));
+        assert_eq!(other.stopped(), None);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn paused_runs_wait_until_cancelled() {
+        let token = CancellationToken::default();
+        token.pause();
+        assert!(token.is_paused());
+        let cancel = Cancel::default().with_token(&token);
+        let stopper = thread::spawn({
+            let token = token.clone();
+            move || {
+                thread::sleep(POLL * 2);
+                token.cancel();
+            }
+        });
+        assert_eq!(cancel.stopped(), Some(Stopped::Cancelled));
+        stopper.join().unwrap();
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn resumed_runs_go_on() {
+        let token = CancellationToken::default();
+        token.pause();
+        let cancel = Cancel::default().with_token(&token);
+        let resumer = thread::spawn({
+            let token = token.clone();
+            move || {
+                thread::sleep(POLL * 2);
+                token.resume();
+            }
+        });
+        assert_eq!(cancel.stopped(), None);
+        assert!(!token.is_paused());
+        resumer.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn late_child_is_killed() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        let err = Cancel::within(Some(Duration::from_millis(100)))
+            .wait(&mut child)
+            .unwrap_err();
+        assert!(is_stopped(&err));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        // Already reaped: nothing left running
+        assert!(child.try_wait().unwrap().is_some());
+    }
+}
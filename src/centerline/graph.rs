@@ -0,0 +1,240 @@
+//! Branches of a skeleton.
+//!
+//! Skeleton voxels with exactly two neighbors lie inside a branch; all
+//! others are nodes. Nodes with one neighbor are line ends; touching nodes
+//! with three or more form one junction (thinning leaves small clumps where
+//! branches meet), and every branch reaching a junction is joined to the same
+//! voxel of it, so branches meet at one point. Each branch is traced from a
+//! node through its inner voxels to the next node; loops without any node
+//! are traced from an arbitrary voxel back to itself.
+
+use super::thin::{neighbor, neighborhood};
+
+/// One traced branch: its voxels in order and whether it ends at a line end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    /// Flat voxel indices from one end to the other.
+    pub voxels: Vec<usize>,
+    /// Whether either end is a free line end (not a junction).
+    pub terminal: bool,
+}
+
+/// Trace all branches of a one-voxel-thick skeleton.
+pub fn trace(skeleton: &[bool], dims: [usize; 3]) -> Vec<Branch> {
+    let neighbors = |index: usize| -> Vec<usize> {
+        let cube = neighborhood(skeleton, dims, index);
+        (0..27)
+            .filter(|&cell| cell != 13 && cube[cell])
+            .filter_map(|cell| {
+                let offset = [cell % 3, cell / 3 % 3, cell / 9].map(|c| c.cast_signed() - 1);
+                neighbor(dims, index, offset)
+            })
+            .collect()
+    };
+    let voxels: Vec<usize> = (0..skeleton.len()).filter(|&i| skeleton[i]).collect();
+    let degree: Vec<usize> = (0..skeleton.len())
+        .map(|i| if skeleton[i] { neighbors(i).len() } else { 0 })
+        .collect();
+    let is_node = |index: usize| degree[index] != 2;
+    let is_junction = |index: usize| degree[index] > 2;
+    let hub = junction_hubs(&voxels, &degree, &neighbors);
+
+    let mut visited = vec![false; skeleton.len()];
+    let mut branches = Vec::new();
+    let mut finish = |mut path: Vec<usize>| {
+        let terminal = path.first().is_some_and(|&i| degree[i] <= 1)
+            || path.last().is_some_and(|&i| degree[i] <= 1);
+        if let Some(&first) = path.first()
+            && is_junction(first)
+            && hub[first] != first
+        {
+            path.insert(0, hub[first]);
+        }
+        if let Some(&last) = path.last()
+            && is_junction(last)
+            && hub[last] != last
+        {
+            path.push(hub[last]);
+        }
+        branches.push(Branch {
+            voxels: path,
+            terminal,
+        });
+    };
+
+    for &node in voxels.iter().filter(|&&i| is_node(i)) {
+        visited[node] = true;
+        if degree[node] == 0 {
+            finish(vec![node]);
+        }
+        for next in neighbors(node) {
+            if is_node(next) {
+                // Voxels of one junction are not branches; a line end next
+                // to another node is linked once
+                if node < next && !(is_junction(node) && is_junction(next)) {
+                    finish(vec![node, next]);
+                }
+                continue;
+            }
+            if visited[next] {
+                continue;
+            }
+            let mut path = vec![node, next];
+            visited[next] = true;
+            let (mut previous, mut current) = (node, next);
+            while let Some(step) = neighbors(current).into_iter().find(|&n| n != previous) {
+                path.push(step);
+                if is_node(step) || visited[step] {
+                    break;
+                }
+                visited[step] = true;
+                (previous, current) = (current, step);
+            }
+            finish(path);
+        }
+    }
+
+    // Closed loops: every voxel has two neighbors, so no node was found
+    for &start in &voxels {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut path = vec![start];
+        let (mut previous, mut current) = (start, start);
+        while let Some(step) = neighbors(current)
+            .into_iter()
+            .find(|&n| n != previous && (!visited[n] || n == start && path.len() > 2))
+        {
+            path.push(step);
+            if step == start {
+                break;
+            }
+            visited[step] = true;
+            (previous, current) = (current, step);
+        }
+        finish(path);
+    }
+    branches
+}
+
+/// For each junction voxel, the first voxel of its clump of touching
+/// junction voxels; other entries are unused.
+fn junction_hubs(
+    voxels: &[usize],
+    degree: &[usize],
+    neighbors: &impl Fn(usize) -> Vec<usize>,
+) -> Vec<usize> {
+    let mut hub: Vec<usize> = (0..degree.len()).collect();
+    let mut seen = vec![false; degree.len()];
+    for &start in voxels.iter().filter(|&&i| degree[i] > 2) {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            hub[index] = start;
+            for next in neighbors(index) {
+                if degree[next] > 2 && !seen[next] {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+    }
+    hub
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Skeleton with the given `(x, y, z)` voxels set.
+    fn skeleton(dims: [usize; 3], voxels: &[(usize, usize, usize)]) -> Vec<bool> {
+        let mut mask = vec![false; dims.iter().product()];
+        for &(x, y, z) in voxels {
+            mask[x + y * dims[0] + z * dims[0] * dims[1]] = true;
+        }
+        mask
+    }
+
+    #[test]
+    fn line_is_one_terminal_branch() {
+        let mask = skeleton([5, 1, 1], &[(0, 0, 0), (1, 0, 0), (2, 0, 0), (3, 0, 0)]);
+        let branches = trace(&mask, [5, 1, 1]);
+        assert_eq!(
+            branches,
+            [Branch {
+                voxels: vec![0, 1, 2, 3],
+                terminal: true
+            }]
+        );
+    }
+
+    #[test]
+    fn junction_splits_branches() {
+        // A Y as thinning leaves it: two arms meeting a stem at (2, 1)
+        let dims = [5, 4, 1];
+        let mask = skeleton(
+            dims,
+            &[
+                (0, 0, 0),
+                (1, 0, 0),
+                (2, 1, 0),
+                (3, 0, 0),
+                (4, 0, 0),
+                (2, 2, 0),
+                (2, 3, 0),
+            ],
+        );
+        let branches = trace(&mask, dims);
+
+        assert_eq!(branches.len(), 3, "{branches:?}");
+        assert!(branches.iter().all(|b| b.terminal));
+        let lengths: Vec<usize> = branches.iter().map(|b| b.voxels.len()).collect();
+        assert_eq!(lengths, [3, 3, 3], "junction shared by all three");
+    }
+
+    #[test]
+    fn junction_clump_is_one_meeting_point() {
+        // Thinned T: a bar along X with a stem at x = 2, whose corner voxels
+        // (1..=3, 0) all have three or more neighbors
+        let dims = [5, 4, 1];
+        let mask = skeleton(
+            dims,
+            &[
+                (0, 0, 0),
+                (1, 0, 0),
+                (2, 0, 0),
+                (3, 0, 0),
+                (4, 0, 0),
+                (2, 1, 0),
+                (2, 2, 0),
+                (2, 3, 0),
+            ],
+        );
+        let branches = trace(&mask, dims);
+
+        assert_eq!(branches.len(), 3, "{branches:?}");
+        assert!(branches.iter().all(|b| b.terminal));
+        for branch in &branches {
+            let ends = [branch.voxels[0], branch.voxels[branch.voxels.len() - 1]];
+            assert!(ends.contains(&1), "{branch:?} misses the hub");
+        }
+    }
+
+    #[test]
+    fn loop_without_nodes_closes() {
+        // Diamond of four diagonal voxels: each has exactly two neighbors
+        let dims = [3, 3, 1];
+        let mask = skeleton(dims, &[(1, 0, 0), (2, 1, 0), (1, 2, 0), (0, 1, 0)]);
+        let branches = trace(&mask, dims);
+
+        assert_eq!(branches.len(), 1, "{branches:?}");
+        let branch = &branches[0];
+        assert!(!branch.terminal);
+        assert_eq!(branch.voxels.len(), 5);
+        assert_eq!(branch.voxels.first(), branch.voxels.last());
+    }
+}
@@ -0,0 +1,211 @@
+//! Topology-preserving 3D thinning.
+//!
+//! Peels border voxels off the mask one face direction at a time, removing
+//! only *simple* voxels (whose removal changes neither the number of
+//! objects, tunnels, nor cavities) that are not line ends. What is left is a
+//! one-voxel-thick skeleton along the middle of every tube. Foreground uses
+//! 26-connectivity and background 6-connectivity; outside the volume counts
+//! as background.
+
+/// Index of the center of a 3x3x3 neighborhood.
+const CENTER: usize = 13;
+
+/// Thin `mask` to its skeleton in place; returns the voxels removed.
+pub fn skeletonize(mask: &mut [bool], dims: [usize; 3]) -> usize {
+    let mut active: Vec<usize> = (0..mask.len()).filter(|&i| mask[i]).collect();
+    let mut removed = 0;
+    loop {
+        let mut changed = 0;
+        for face in FACES {
+            // Collect first, then re-check each one, so the border of this
+            // direction is peeled as one layer without breaking topology
+            let candidates: Vec<usize> = active
+                .iter()
+                .copied()
+                .filter(|&i| is_border(mask, dims, i, face) && removable(mask, dims, i))
+                .collect();
+            for index in candidates {
+                if removable(mask, dims, index) {
+                    mask[index] = false;
+                    changed += 1;
+                }
+            }
+            active.retain(|&i| mask[i]);
+        }
+        if changed == 0 {
+            return removed;
+        }
+        removed += changed;
+    }
+}
+
+/// Face directions peeled in turn, as `(dx, dy, dz)`.
+const FACES: [[isize; 3]; 6] = [
+    [0, 0, 1],
+    [0, 0, -1],
+    [0, 1, 0],
+    [0, -1, 0],
+    [1, 0, 0],
+    [-1, 0, 0],
+];
+
+/// Whether the face neighbor of `index` in direction `face` is background.
+fn is_border(mask: &[bool], dims: [usize; 3], index: usize, face: [isize; 3]) -> bool {
+    !neighbor(dims, index, face).is_some_and(|n| mask[n])
+}
+
+/// Whether a voxel can go: simple, and not the end of a line.
+fn removable(mask: &[bool], dims: [usize; 3], index: usize) -> bool {
+    let cube = neighborhood(mask, dims, index);
+    let neighbors = cube.iter().filter(|&&v| v).count() - 1;
+    neighbors > 1 && is_simple(&cube)
+}
+
+/// The 3x3x3 neighborhood of a voxel, X fastest; outside is background.
+pub fn neighborhood(mask: &[bool], dims: [usize; 3], index: usize) -> [bool; 27] {
+    std::array::from_fn(|cell| {
+        let offset = [cell % 3, cell / 3 % 3, cell / 9].map(|c| c.cast_signed() - 1);
+        neighbor(dims, index, offset).is_some_and(|n| mask[n])
+    })
+}
+
+/// Flat index of the voxel at `offset` from `index`, if inside the volume.
+pub fn neighbor(dims: [usize; 3], index: usize, offset: [isize; 3]) -> Option<usize> {
+    let [cols, rows, slices] = dims;
+    let position = [index % cols, index / cols % rows, index / (cols * rows)];
+    let mut moved = [0; 3];
+    for axis in 0..3 {
+        moved[axis] = position[axis].checked_add_signed(offset[axis])?;
+        if moved[axis] >= [cols, rows, slices][axis] {
+            return None;
+        }
+    }
+    Some(moved[0] + moved[1] * cols + moved[2] * cols * rows)
+}
+
+/// Whether the center voxel of a neighborhood is simple: its foreground
+/// neighbors form one 26-connected object, and the background around it
+/// (within the 18-neighborhood) one 6-connected region touching its faces.
+fn is_simple(cube: &[bool; 27]) -> bool {
+    let foreground: [bool; 27] = std::array::from_fn(|c| c != CENTER && cube[c]);
+    let objects = components(&foreground, |a, b| distance(a, b) <= 3, |_| true);
+
+    let background: [bool; 27] =
+        std::array::from_fn(|c| c != CENTER && !cube[c] && distance(c, CENTER) <= 2);
+    let cavities = components(
+        &background,
+        |a, b| distance(a, b) == 1,
+        |c| distance(c, CENTER) == 1,
+    );
+
+    objects == 1 && cavities == 1
+}
+
+/// Squared offset between two cells of a 3x3x3 neighborhood.
+fn distance(a: usize, b: usize) -> usize {
+    let coords = |c: usize| [c % 3, c / 3 % 3, c / 9];
+    let (a, b) = (coords(a), coords(b));
+    (0..3).map(|i| a[i].abs_diff(b[i]).pow(2)).sum()
+}
+
+/// Number of connected groups of `cells` (adjacent per `adjacent`) that
+/// contain at least one cell accepted by `counts`.
+fn components(
+    cells: &[bool; 27],
+    adjacent: impl Fn(usize, usize) -> bool,
+    counts: impl Fn(usize) -> bool,
+) -> usize {
+    let mut seen = [false; 27];
+    let mut found = 0;
+    for start in 0..27 {
+        if !cells[start] || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut stack = vec![start];
+        let mut counted = false;
+        while let Some(cell) = stack.pop() {
+            counted |= counts(cell);
+            for next in 0..27 {
+                if cells[next] && !seen[next] && adjacent(cell, next) {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        found += usize::from(counted);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(dims: [usize; 3], inside: impl Fn(usize, usize, usize) -> bool) -> Vec<bool> {
+        let [cols, rows, slices] = dims;
+        (0..cols * rows * slices)
+            .map(|i| inside(i % cols, i / cols % rows, i / (cols * rows)))
+            .collect()
+    }
+
+    #[test]
+    fn tube_thins_to_its_axis() {
+        // 3x3 bar along Z through a 5x5x9 volume, with air at both ends
+        let dims = [5, 5, 9];
+        let mut mask = solid(dims, |x, y, z| {
+            (1..4).contains(&x) && (1..4).contains(&y) && (1..8).contains(&z)
+        });
+        skeletonize(&mut mask, dims);
+
+        let kept: Vec<usize> = (0..mask.len()).filter(|&i| mask[i]).collect();
+        assert!(kept.len() >= 3, "{kept:?}");
+        for &i in &kept {
+            assert_eq!((i % 5, i / 5 % 5), (2, 2), "voxel {i} off the axis");
+        }
+    }
+
+    #[test]
+    fn ring_keeps_its_hole() {
+        // Square ring in one slice: thinning must not cut the loop open
+        let dims = [9, 9, 3];
+        let mut mask = solid(dims, |x, y, z| {
+            z == 1
+                && (1..8).contains(&x)
+                && (1..8).contains(&y)
+                && !((3..6).contains(&x) && (3..6).contains(&y))
+        });
+        skeletonize(&mut mask, dims);
+
+        let kept = mask.iter().filter(|&&v| v).count();
+        assert!(kept >= 8, "ring collapsed to {kept} voxels");
+        // The hole is still enclosed: its background cannot reach the sides
+        let mut stack = vec![(4, 4)];
+        let mut reached = [[false; 9]; 9];
+        while let Some((x, y)) = stack.pop() {
+            assert!(x > 0 && y > 0 && x < 8 && y < 8, "loop was cut open");
+            if reached[y][x] || mask[x + y * 9 + 81] {
+                continue;
+            }
+            reached[y][x] = true;
+            stack.extend([(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+    }
+
+    #[test]
+    fn simple_point_examples() {
+        let mut cube = [false; 27];
+        cube[CENTER] = true;
+        // Isolated voxel: removing it deletes an object
+        assert!(!is_simple(&cube));
+        // End of a line: simple (the endpoint rule keeps it instead)
+        cube[CENTER - 1] = true;
+        assert!(is_simple(&cube));
+        // Middle of a line: removing it splits the line
+        cube[CENTER + 1] = true;
+        assert!(!is_simple(&cube));
+        // Corner of a plate: still simple
+        let plate: [bool; 27] = std::array::from_fn(|c| c / 9 == 1 && c % 3 >= 1 && c / 3 % 3 >= 1);
+        assert!(is_simple(&plate));
+    }
+}
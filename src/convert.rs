@@ -1,23 +1,72 @@
-//! DICOM to JPG/MP4/STL conversion module.
+//! DICOM to JPG/MP4/STL/point cloud conversion module.
 
+mod archive;
+mod bundle;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+mod dicomweb;
 mod jpeg;
+mod naming;
+mod patches;
+mod pick;
+mod pipe;
+mod pointcloud;
+mod print;
+mod quarantine;
+mod session;
+mod slide;
 mod stl;
+pub mod suv;
 mod video;
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
+use clap::builder::NonEmptyStringValueParser;
 use clap::{Args, Subcommand, ValueEnum};
 use dicom::dictionary_std::tags;
-use dicom::object::open_file;
-use dicom_pixeldata::PixelDecoder;
-use image::DynamicImage;
-
+use dicom::object::{DefaultDicomObject, OpenFileOptions};
+use tempfile::TempDir;
+
+use crate::annotate::Annotations;
+use crate::cache::FrameCache;
+use crate::cancel::{self, Cancel, CancellationToken, Stopped};
+use crate::events::{self, Stage};
+use crate::filter::Denoise;
+use crate::i18n::t;
+use crate::meta::{InstanceMeta, SeriesMeta};
+use crate::notify::parse_webhook;
+use crate::outcome::{BadInput, Summary};
+use crate::perms::PermissionArgs;
+use crate::pipeline::{RenderOptions, RunStats};
+use crate::pixel::Window;
+use crate::select::{Selection, SelectionArgs};
 use crate::utils::{
-    clean_output, is_folder_empty, prompt_to_cleanup, sanitize_filename, validate_input_folder,
-    CleanupChoice,
+    CleanupChoice, clean_output, create_temp_dir, extended_length_path, is_folder_empty,
+    list_dcm_files, prompt_to_cleanup, sanitize_filename, transliterate, validate_audio,
+    validate_input_folder, validate_temp_dir,
+};
+
+use archive::Archive;
+pub use jpeg::JpegSink;
+use naming::{FolderNames, GroupName};
+pub use pointcloud::PointFormat;
+use print::{Layout, PageFormat, Paper, PrintOptions};
+use session::Acquired;
+use slide::SlideOptions;
+pub use stl::{
+    Crop, MIN_SLICES_FOR_3D, MeshCoords, MeshFormat, MeshOptions, MeshOutput, MeshStats, Part,
+    Presets, Target, parse_positive, write_model, write_parts,
+};
+use suv::SuvDisplay;
+pub use video::{
+    Background, Cardiac, EncodeFailure, Encoding, FrameInput, Hwaccel, RawStagingSink, Trim,
+    VideoCodec, VideoOptions, check_ffmpeg, encode_mp4, encode_sequence, parse_size,
+    staging_estimate, video_bitrate,
 };
 
 /// Tag used to split DICOM files into groups/series.
@@ -35,19 +84,178 @@ pub enum SplitBy {
     Orientation,
     /// Split by `StackID` tag (0020,9056)
     StackId,
+    /// Split by `ImageType` tag (0008,0008), e.g. `ORIGINAL_PRIMARY_AXIAL`
+    ImageType,
+    /// Split by `EchoTime` tag (0018,0081), one stack per echo
+    EchoTime,
+    /// Split by `InversionTime` tag (0018,0082)
+    InversionTime,
+    /// Split by `FlipAngle` tag (0018,1314)
+    FlipAngle,
+    /// Split by `AcquisitionDate` tag (0008,0022), e.g. `2024-01-05`
+    AcquisitionDate,
+}
+
+impl SplitBy {
+    /// DICOM tag read for this split option.
+    pub const fn tag(self) -> dicom::core::Tag {
+        match self {
+            Self::SeriesNumber => tags::SERIES_NUMBER,
+            Self::SeriesUid => tags::SERIES_INSTANCE_UID,
+            Self::AcquisitionNumber => tags::ACQUISITION_NUMBER,
+            Self::Description => tags::SERIES_DESCRIPTION,
+            Self::Orientation => tags::IMAGE_ORIENTATION_PATIENT,
+            Self::StackId => dicom::core::Tag(0x0020, 0x9056),
+            Self::ImageType => tags::IMAGE_TYPE,
+            Self::EchoTime => tags::ECHO_TIME,
+            Self::InversionTime => tags::INVERSION_TIME,
+            Self::FlipAngle => tags::FLIP_ANGLE,
+            Self::AcquisitionDate => tags::ACQUISITION_DATE,
+        }
+    }
+
+    /// Normalize a raw tag value into a group key.
+    pub fn normalize(self, raw: &str) -> String {
+        match self {
+            Self::ImageType => normalize_multi_value(raw),
+            Self::EchoTime | Self::InversionTime | Self::FlipAngle => normalize_decimal(raw),
+            Self::AcquisitionDate => normalize_date(raw),
+            _ => raw.trim().to_string(),
+        }
+    }
+}
+
+/// What to do with files that lack the split tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum UnknownGroup {
+    /// Put them all into one `unknown` group
+    #[default]
+    Merge,
+    /// Leave them out, with a note on stderr
+    Skip,
+    /// One `unknown_<SeriesInstanceUID>` group per series
+    SeparateByUid,
+    /// Stop before converting anything
+    Error,
+}
+
+/// Group key of files without the split tag.
+pub const UNKNOWN_KEY: &str = "unknown";
+
+/// A DICOM date (`YYYYMMDD`) as `YYYY-MM-DD`; other values are only trimmed.
+fn normalize_date(raw: &str) -> String {
+    let raw = raw.trim();
+    if raw.len() == 8 && raw.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{}-{}-{}", &raw[..4], &raw[4..6], &raw[6..])
+    } else {
+        raw.to_string()
+    }
+}
+
+/// Join the components of a multi-valued code string with `_`.
+///
+/// `ORIGINAL\PRIMARY\AXIAL ` becomes `ORIGINAL_PRIMARY_AXIAL`; empty
+/// components are dropped and case is normalized to upper case.
+fn normalize_multi_value(raw: &str) -> String {
+    raw.split('\\')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_uppercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Normalize a decimal string (DS) so equal values share one group.
+///
+/// Scanners pad and format DS values inconsistently (`2.460`, `2.46 `), so
+/// the first value is parsed and re-printed in its shortest form. Values
+/// that don't parse are only trimmed.
+fn normalize_decimal(raw: &str) -> String {
+    let first = raw.split('\\').next().unwrap_or_default().trim();
+    first
+        .parse::<f64>()
+        .map_or_else(|_| first.to_string(), |value| value.to_string())
+}
+
+/// Order group keys numerically when both parse as numbers, else as strings.
+///
+/// Numerically equal keys (`1` and `01`) fall back to string order, so the
+/// order is total and never depends on how the keys were collected.
+pub fn compare_group_keys(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a_num), Ok(b_num)) => a_num.total_cmp(&b_num).then_with(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
+/// Path value (`-`) that selects stdin for `--in` or stdout for `--out`.
+const STDIO_PATH: &str = "-";
+
+/// Folder that default outputs (no `--out`) are created in.
+const DEFAULT_OUTPUT_ROOT: &str = "export";
+
+/// Name of the default output folder, as a `--group-name` template.
+const DEFAULT_OUTPUT_NAME: &str = "{PatientID}_{StudyDate}_{Modality}";
+
+/// Encoding used for 2D image output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ImageFormat {
+    /// JPEG (`.jpg`)
+    #[default]
+    Jpeg,
+    /// PNG (`.png`, lossless)
+    Png,
+    /// PNG with 16-bit gray levels (`.png`), keeping high-bit data intact
+    Png16,
+}
+
+impl ImageFormat {
+    /// File extension (without the dot) for this format.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png | Self::Png16 => "png",
+        }
+    }
+
+    /// Equivalent `image` crate format used for encoding.
+    pub const fn encoding(self) -> image::ImageFormat {
+        match self {
+            Self::Jpeg => image::ImageFormat::Jpeg,
+            Self::Png | Self::Png16 => image::ImageFormat::Png,
+        }
+    }
+
+    /// Whether monochrome frames are rendered with 16-bit gray levels.
+    pub const fn is_deep(self) -> bool {
+        matches!(self, Self::Png16)
+    }
 }
 
 /// Shared options for all convert subcommands.
 #[derive(Args, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ConvertShared {
-    /// Input folder containing DICOM (.dcm) files
+    /// Input folder containing DICOM (.dcm) files, or `-` to read one DICOM object from stdin
     #[arg(long = "in")]
     pub input: PathBuf,
 
-    /// Output folder for converted files
+    /// Output folder for converted files, a `.zip` file to write them into,
+    /// or `-` to write a single image to stdout [default:
+    /// ./export/{PatientID}_{StudyDate}_{Modality}, from the first input file]
     #[arg(long = "out")]
+    out: Option<PathBuf>,
+
+    /// Where converted files go: `--out`, or the folder
+    /// [`ConvertShared::resolve_output`] names after the input.
+    #[arg(skip)]
     pub output: PathBuf,
 
+    /// Stops this run between series and frames; the GUI keeps a clone to
+    /// cancel or pause it.
+    #[arg(skip)]
+    pub token: CancellationToken,
+
     /// Force clean the output folder without asking for confirmation
     #[arg(long, short = 'f')]
     pub force: bool,
@@ -55,18 +263,307 @@ pub struct ConvertShared {
     /// Split files by series/cut identifier into separate folders
     #[arg(long, short = 's', value_enum, default_value_t = SplitBy::SeriesNumber)]
     pub split_by: SplitBy,
+
+    /// What to do with files that lack the split tag
+    #[arg(long, value_enum, default_value_t = UnknownGroup::Merge)]
+    pub unknown_group: UnknownGroup,
+
+    /// Also split each group wherever two acquisitions are more than this
+    /// many minutes apart, one folder per session
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub time_window: Option<u64>,
+
+    /// List the detected series with a number and ask which to convert
+    /// (`1,3-5`; empty converts all)
+    #[arg(long)]
+    pub pick: bool,
+
+    /// Name group folders from a tag template instead of the split key,
+    /// e.g. `{SeriesNumber:02}_{SeriesDescription}`
+    #[arg(long, value_name = "TEMPLATE", value_parser = GroupName::parse)]
+    pub group_name: Option<GroupName>,
+
+    /// Spell group folder names in ASCII (`é` as `e`, other scripts as code
+    /// points) for tools that mangle non-ASCII names
+    #[arg(long)]
+    pub ascii_names: bool,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Remove air, table, and noise around the patient before export
+    #[arg(long)]
+    pub strip_background: bool,
+
+    /// Noise reduction filter applied to each slice before encoding (jpeg and video)
+    #[arg(long, value_enum)]
+    pub denoise: Option<Denoise>,
+
+    /// Unsharp mask amount, e.g. 0.5 (jpeg and video)
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_sharpen_amount)]
+    pub sharpen: Option<f32>,
+
+    /// Scale PET images to body-weight SUV from the dose, injection time,
+    /// and weight in their headers (jpeg, video, stl, and pointcloud)
+    #[arg(long)]
+    pub suv: bool,
+
+    /// SUV shown as white; images are windowed from 0 to it (jpeg and video)
+    #[arg(long, value_name = "SUV", default_value_t = 5.0, requires = "suv", value_parser = parse_positive)]
+    pub suv_max: f32,
+
+    /// Window every image with this center and width (e.g. 40,400) instead
+    /// of the file's (jpeg and video)
+    #[arg(
+        long,
+        value_name = "CENTER,WIDTH",
+        allow_hyphen_values = true,
+        value_parser = Window::parse,
+        conflicts_with = "suv"
+    )]
+    pub window: Option<Window>,
+
+    /// Window center in modality units (e.g. 40), with --window-width; the
+    /// same as --window CENTER,WIDTH
+    #[arg(
+        long,
+        value_name = "CENTER",
+        allow_negative_numbers = true,
+        value_parser = Window::parse_center,
+        requires = "window_width",
+        conflicts_with_all = ["window", "suv"]
+    )]
+    pub window_center: Option<f64>,
+
+    /// Window width in modality units (e.g. 400), with --window-center
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        value_parser = Window::parse_width,
+        requires = "window_center"
+    )]
+    pub window_width: Option<f64>,
+
+    /// Draw a colorbar with ticks in modality units (HU for CT, SUV with
+    /// `--suv`) on the right of each image (jpeg and video)
+    #[arg(long)]
+    pub colorbar: bool,
+
+    /// JSON file of boxes/polygons per `SOPInstanceUID` to draw on frames (jpeg and video)
+    #[arg(long, value_name = "FILE")]
+    pub annotations: Option<PathBuf>,
+
+    /// Also save each annotation box as a PNG patch with a CSV index
+    #[arg(long, requires = "annotations")]
+    pub export_patches: bool,
+
+    /// Copy one rendered slice (`--slice`) to the clipboard instead of
+    /// writing files (jpeg; needs the `clipboard` feature)
+    #[arg(long, requires = "slice", conflicts_with = "out")]
+    pub to_clipboard: bool,
+
+    /// Slice to copy with `--to-clipboard`, counted from 1 in slice order
+    /// (or frame of a single file)
+    #[arg(long, value_name = "N", requires = "to_clipboard", value_parser = clap::value_parser!(u32).range(1..))]
+    pub slice: Option<u32>,
+
+    /// Folder for intermediate video frames [default: system temp folder]
+    #[arg(long, value_name = "DIR")]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Save rendered frames in this folder and reuse them in later runs
+    /// with the same files and pixel options (jpeg and video)
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Give up on a series (jpeg and video) after this many seconds, and go
+    /// on with the next one
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Write the run's counts and duration to this file in the Prometheus
+    /// text format (e.g. for node exporter's textfile collector)
+    #[arg(long, value_name = "FILE")]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Post a JSON summary of the run to this URL when it ends
+    #[arg(long, value_name = "URL", value_parser = parse_webhook)]
+    pub notify_webhook: Option<String>,
+
+    /// Pack each study's series folders into one AES-256 ZIP protected by
+    /// this password, removing the unencrypted folders (with a `.zip`
+    /// `--out`, encrypt that archive instead)
+    #[arg(long, value_name = "PASSWORD", value_parser = NonEmptyStringValueParser::new())]
+    pub encrypt_zip: Option<String>,
+
+    #[command(flatten)]
+    pub selection: SelectionArgs,
+
+    #[command(flatten)]
+    pub permissions: PermissionArgs,
+}
+
+impl ConvertShared {
+    /// Transform-stage options for 2D outputs, with already loaded annotations.
+    pub fn render_options<'a>(&self, annotations: Option<&'a Annotations>) -> RenderOptions<'a> {
+        RenderOptions {
+            denoise: self.denoise,
+            sharpen: self.sharpen,
+            strip_background: self.strip_background,
+            deep: false,
+            suv: self.suv.then_some(SuvDisplay { max: self.suv_max }),
+            window: self.window(),
+            colorbar: self.colorbar,
+            annotations,
+            cache: None,
+            cancel: Cancel::default(),
+        }
+    }
+
+    /// The window every image renders with instead of its own: `--window`,
+    /// or `--window-center` with `--window-width`.
+    fn window(&self) -> Option<Window> {
+        self.window.or_else(|| {
+            Some(Window {
+                center: self.window_center?,
+                width: self.window_width?,
+            })
+        })
+    }
+
+    /// Cancellation token for one series, its `--timeout` starting now.
+    fn cancel(&self) -> Cancel {
+        Cancel::within(self.timeout.map(Duration::from_secs)).with_token(&self.token)
+    }
+
+    /// Settle [`Self::output`]: `--out` when given, otherwise a folder under
+    /// `./export` named after the patient, study date, and modality of the
+    /// first input file.
+    pub fn resolve_output(&mut self) -> Result<()> {
+        self.output = match &self.out {
+            Some(out) => out.clone(),
+            // Nothing is written
+            None if self.to_clipboard => PathBuf::new(),
+            None => {
+                let output = default_output(&self.input, self.follow_symlinks)?;
+                println!(
+                    "{}",
+                    t!(
+                        "convert-default-output",
+                        path = output.display().to_string()
+                    )
+                );
+                output
+            }
+        };
+        Ok(())
+    }
 }
 
 /// Output format subcommands for `convert`.
 #[derive(Subcommand, Debug)]
 pub enum ConvertFormat {
-    /// Convert DICOM files to JPEG images
-    Jpeg,
+    /// Convert DICOM files to JPEG (or PNG) images
+    Jpeg {
+        /// Image encoding for the written files
+        #[arg(long, value_enum, default_value_t = ImageFormat::Jpeg)]
+        image_format: ImageFormat,
+
+        /// Also save each slice's cut line drawn over the localizer (scout)
+        /// as `0001_scout.jpg`
+        #[arg(long)]
+        scout_lines: bool,
+
+        /// Embed each image's acquisition time (EXIF or PNG text) and use it
+        /// as the file's modification time
+        #[arg(long)]
+        acquisition_times: bool,
+    },
     /// Convert DICOM files to MP4 video
     Video {
-        /// Frames per second for video output
-        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
-        fps: u32,
+        /// Frames per second for video output [default: the cine rate of
+        /// multi-frame objects, else 10]
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        fps: Option<u32>,
+
+        /// Also keep every frame as an image and encode the video from them
+        /// (one decode for both outputs)
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        with_images: Option<ImageFormat>,
+
+        /// Draw a bar on the right edge marking where each slice lies in the
+        /// scan range
+        #[arg(long)]
+        position_bar: bool,
+
+        /// Narration (e.g. MP3) to add to the MP4, looped or cut to the
+        /// video's length
+        #[arg(long, value_name = "FILE")]
+        audio: Option<PathBuf>,
+
+        /// Join all series into one MP4 with a title card and a chapter per
+        /// series
+        #[arg(long, conflicts_with = "with_images")]
+        combine_series: bool,
+
+        /// Gated cardiac cine: gather the images of all series by slice
+        /// position and write one looping MP4 per slice, phases in
+        /// TriggerTime order
+        #[arg(long, conflicts_with_all = ["combine_series", "cardiac_slice"])]
+        cardiac_loops: bool,
+
+        /// Gated cardiac cine: write one MP4 cycling through the phases of
+        /// slice N only (1 = first along the slice normal)
+        #[arg(
+            long,
+            value_name = "N",
+            value_parser = clap::value_parser!(u32).range(1..),
+            conflicts_with = "combine_series"
+        )]
+        cardiac_slice: Option<u32>,
+
+        /// Open the video with a title card showing this text
+        #[arg(long, value_name = "TEXT", conflicts_with = "with_images")]
+        title: Option<String>,
+
+        /// Logo (e.g. a transparent PNG) to stamp in the bottom-right corner
+        /// of every frame
+        #[arg(long, value_name = "FILE")]
+        watermark: Option<PathBuf>,
+
+        /// Color around frames smaller or shaped differently than the first
+        /// (black, white, or #rrggbb)
+        #[arg(long, value_name = "COLOR", default_value = "black")]
+        background: Background,
+
+        /// Fit each MP4 in this file size (e.g. 25MB) with a two-pass encode
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        target_size: Option<u64>,
+
+        /// Encode on the GPU with this encoder family
+        #[arg(long, value_enum, value_name = "GPU")]
+        hwaccel: Option<Hwaccel>,
+
+        /// If ffmpeg fails, encode once more with software H.264 at a fast
+        /// preset
+        #[arg(long)]
+        encode_fallback: bool,
+
+        /// Only encode frames START:END of each series, counted from 0 with
+        /// the end excluded (e.g. 10:120; either side may be left open)
+        #[arg(long, value_name = "START:END", value_parser = Trim::parse_frames)]
+        frames: Option<Trim>,
+
+        /// Only encode the frames acquired from START to END seconds into
+        /// each series (e.g. 2s:8s), by the cine frame time
+        #[arg(
+            long,
+            value_name = "START:END",
+            value_parser = Trim::parse_time,
+            conflicts_with = "frames"
+        )]
+        time_range: Option<Trim>,
     },
     /// Convert DICOM files to STL 3D model
     Stl {
@@ -74,10 +571,159 @@ pub enum ConvertFormat {
         #[arg(long)]
         iso_level: Option<f32>,
 
+        /// Mesh at a preset level for this target (CT only; picked from the
+        /// body part and the series' histogram)
+        #[arg(long, value_enum, conflicts_with = "iso_level")]
+        target: Option<Target>,
+
         /// Gaussian smoothing sigma (0 disables smoothing)
         #[arg(long, default_value_t = 1.0)]
         smooth: f32,
+
+        /// Mesh file format (ply, obj, and glb add smooth vertex normals)
+        #[arg(long, value_enum, default_value_t = MeshFormat::Stl)]
+        mesh_format: MeshFormat,
+
+        /// Also save an MP4 of each model turning once in this many seconds
+        /// (requires ffmpeg)
+        #[arg(long, value_name = "SECONDS", value_parser = parse_positive)]
+        turntable: Option<f32>,
+
+        #[command(flatten)]
+        mesh: MeshOutput,
     },
+    /// Convert DICOM files to a point cloud (PLY or XYZ) of voxels above a threshold
+    Pointcloud {
+        /// Keep voxels above this value (auto-detected via Otsu if omitted)
+        #[arg(long, allow_negative_numbers = true)]
+        threshold: Option<f32>,
+
+        /// Point cloud file format
+        #[arg(long, value_enum, default_value_t = PointFormat::Ply)]
+        point_format: PointFormat,
+
+        /// Coordinate system of the points
+        #[arg(long, value_enum, default_value_t = MeshCoords::Voxel)]
+        coords: MeshCoords,
+    },
+    /// Lay DICOM files out as a static DICOMweb site (study and series
+    /// lists, metadata, and frames) to open in OHIF
+    Dicomweb,
+    /// Reassemble whole-slide microscopy tiles into one image, or a DeepZoom
+    /// pyramid
+    Slide {
+        /// Shrink the slide by this whole factor from full resolution
+        /// [default: fit --max-size; full resolution with --deepzoom]
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        downsample: Option<u32>,
+
+        /// Longest side of the image when --downsample is not given
+        #[arg(
+            long,
+            value_name = "PIXELS",
+            default_value_t = 8192,
+            value_parser = clap::value_parser!(u32).range(1..=65_535),
+            conflicts_with_all = ["downsample", "deepzoom"]
+        )]
+        max_size: u32,
+
+        /// Write a DeepZoom pyramid (`.dzi` and tile folders) for
+        /// OpenSeadragon and similar viewers instead of one image
+        #[arg(long)]
+        deepzoom: bool,
+
+        /// Side of each DeepZoom tile in pixels
+        #[arg(
+            long,
+            value_name = "PIXELS",
+            default_value_t = 256,
+            value_parser = clap::value_parser!(u32).range(16..=4096),
+            requires = "deepzoom"
+        )]
+        tile_size: u32,
+
+        /// Encoding of the image or tiles
+        #[arg(long, value_enum, default_value_t = ImageFormat::Jpeg)]
+        image_format: ImageFormat,
+    },
+    /// Lay images out on printable A4 or US-letter pages with a
+    /// demographics header, like a film printer's sheets
+    PrintLayout {
+        /// Paper size of the pages
+        #[arg(long, value_enum, default_value_t = Paper::A4)]
+        paper: Paper,
+
+        /// Images per page as COLUMNSxROWS, filled across then down
+        #[arg(long, value_name = "COLSxROWS", default_value = "4x5", value_parser = Layout::parse)]
+        layout: Layout,
+
+        /// File type of the pages: one PDF per series, or a PNG per page
+        #[arg(long, value_enum, default_value_t = PageFormat::Pdf)]
+        page_format: PageFormat,
+
+        /// Print resolution of the pages in dots per inch
+        #[arg(
+            long,
+            value_name = "DPI",
+            default_value_t = 150,
+            value_parser = clap::value_parser!(u32).range(72..=600)
+        )]
+        dpi: u32,
+    },
+}
+
+impl ConvertFormat {
+    /// Cine loops to build per slice position, for `video --cardiac-loops`
+    /// or `--cardiac-slice`.
+    fn cardiac(&self) -> Option<Cardiac> {
+        match self {
+            Self::Video {
+                cardiac_loops: true,
+                ..
+            } => Some(Cardiac::Loops),
+            Self::Video {
+                cardiac_slice: Some(number),
+                ..
+            } => Some(Cardiac::Slice(*number as usize)),
+            _ => None,
+        }
+    }
+
+    /// Export options of the `video` format.
+    fn video_options<'a>(&'a self, shared: &'a ConvertShared) -> Option<VideoOptions<'a>> {
+        let Self::Video {
+            fps,
+            with_images,
+            position_bar,
+            audio,
+            title,
+            watermark,
+            background,
+            target_size,
+            hwaccel,
+            encode_fallback,
+            frames,
+            time_range,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        Some(VideoOptions {
+            fps: *fps,
+            temp_root: shared.temp_dir.as_deref(),
+            with_images: *with_images,
+            position_bar: *position_bar,
+            audio: audio.as_deref(),
+            title: title.as_deref(),
+            watermark: watermark.as_deref(),
+            background: *background,
+            target_size: *target_size,
+            hwaccel: *hwaccel,
+            encode_fallback: *encode_fallback,
+            trim: frames.or(*time_range),
+        })
+    }
 }
 
 /// A prepared group of DICOM files ready for conversion.
@@ -86,108 +732,870 @@ struct PreparedGroup {
     key: String,
     /// Sorted DICOM file paths (by Z-position)
     files: Vec<PathBuf>,
+    /// Header summaries of the same files, in the same order
+    series: SeriesMeta,
     /// Output directory for this group
     output_dir: PathBuf,
 }
 
+/// Largest accepted `--sharpen` amount; beyond this halos dominate.
+const MAX_SHARPEN_AMOUNT: f32 = 5.0;
+
+/// Parse a `--sharpen` amount in `0..=MAX_SHARPEN_AMOUNT`.
+fn parse_sharpen_amount(value: &str) -> std::result::Result<f32, String> {
+    let amount: f32 = value
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))?;
+    if (0.0..=MAX_SHARPEN_AMOUNT).contains(&amount) {
+        Ok(amount)
+    } else {
+        Err(format!("must be between 0 and {MAX_SHARPEN_AMOUNT}"))
+    }
+}
+
+/// Check `video` options that need the file system or several flags.
+fn validate_video_options(shared: &ConvertShared, format: &ConvertFormat) -> Result<()> {
+    if let ConvertFormat::Video {
+        audio,
+        combine_series,
+        watermark,
+        ..
+    } = format
+    {
+        validate_audio(audio.as_deref())?;
+        if let Some(path) = watermark {
+            video::Watermark::load(path)?;
+        }
+        if *combine_series && shared.export_patches {
+            anyhow::bail!(BadInput(
+                "--export-patches writes into series folders and cannot be used with \
+                 --combine-series"
+                    .to_string()
+            ));
+        }
+        if format.cardiac().is_some() && shared.export_patches {
+            anyhow::bail!(BadInput(
+                "--export-patches writes into series folders and cannot be used with \
+                 --cardiac-loops or --cardiac-slice"
+                    .to_string()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that `--encrypt-zip` has series folders to pack.
+fn validate_encrypt_zip(shared: &ConvertShared, format: &ConvertFormat) -> Result<()> {
+    if shared.encrypt_zip.is_none() {
+        return Ok(());
+    }
+    if is_stdio(&shared.input) || is_stdio(&shared.output) || shared.input.is_file() {
+        anyhow::bail!(BadInput(
+            "--encrypt-zip packs series folders and cannot be used with a single file or `-` (stdin/stdout)"
+                .to_string()
+        ));
+    }
+    if let ConvertFormat::Video {
+        combine_series: true,
+        ..
+    } = format
+        && !archive::is_archive(&shared.output)
+    {
+        anyhow::bail!(BadInput(
+            "--encrypt-zip packs series folders and cannot be used with --combine-series"
+                .to_string()
+        ));
+    }
+    if format.cardiac().is_some() && !archive::is_archive(&shared.output) {
+        anyhow::bail!(BadInput(
+            "--encrypt-zip packs series folders and cannot be used with --cardiac-loops or \
+             --cardiac-slice"
+                .to_string()
+        ));
+    }
+    if matches!(format, ConvertFormat::Dicomweb) && !archive::is_archive(&shared.output) {
+        anyhow::bail!(BadInput(
+            "--encrypt-zip with dicomweb needs a `.zip` --out to encrypt".to_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Probe ffmpeg for the encoder `format` needs, before any frame is prepared.
+fn check_encoder(format: &ConvertFormat) -> Result<()> {
+    match format {
+        ConvertFormat::Video { hwaccel, .. } => check_ffmpeg(VideoCodec::H264, *hwaccel),
+        ConvertFormat::Stl {
+            turntable: Some(_), ..
+        } => check_ffmpeg(VideoCodec::H264, None),
+        _ => Ok(()),
+    }
+}
+
+/// Output folder used when `--out` is omitted, named from the first file of
+/// `input`.
+fn default_output(input: &Path, follow_symlinks: bool) -> Result<PathBuf> {
+    if is_stdio(input) {
+        anyhow::bail!(BadInput(
+            "--out is required when reading from stdin (`--in -`)".to_string()
+        ));
+    }
+    validate_input_folder(input)?;
+    let files = list_dcm_files(input, follow_symlinks)?;
+    let template =
+        GroupName::parse(DEFAULT_OUTPUT_NAME).expect("the default output name is a valid template");
+    // Without files every placeholder is `unknown`; the conversion then
+    // reports the empty folder
+    let name = template.render(files.first().map_or(input, PathBuf::as_path));
+    Ok(Path::new(DEFAULT_OUTPUT_ROOT).join(sanitize_filename(&name)))
+}
+
+/// Whether a `--in`/`--out` value selects stdin/stdout.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
 /// Convert DICOM files to the specified output format.
-pub fn run(shared: &ConvertShared, format: &ConvertFormat) -> Result<()> {
-    let groups = prepare_groups(shared)?;
+///
+/// Failures within a series are counted and reported rather than aborting
+/// the remaining series; the returned [`Summary`] decides the exit code.
+pub fn run(shared: &ConvertShared, format: &ConvertFormat) -> Result<Summary> {
+    let annotations = shared
+        .annotations
+        .as_deref()
+        .map(Annotations::load)
+        .transpose()?;
+    let cache = shared
+        .cache_dir
+        .as_deref()
+        .map(FrameCache::open)
+        .transpose()?;
+    let options = RenderOptions {
+        cache: cache.as_ref(),
+        deep: matches!(format, ConvertFormat::Jpeg { image_format, .. } if image_format.is_deep()),
+        ..shared.render_options(annotations.as_ref())
+    };
+
+    validate_encrypt_zip(shared, format)?;
+    if shared.to_clipboard {
+        #[cfg(feature = "clipboard")]
+        return clipboard::run(shared, format, options);
+        #[cfg(not(feature = "clipboard"))]
+        anyhow::bail!(BadInput(
+            "--to-clipboard needs a build with the `clipboard` feature (cargo build --features clipboard)"
+                .to_string()
+        ));
+    }
+    let single_file = shared.input.is_file() && matches!(format, ConvertFormat::Jpeg { .. });
+    if is_stdio(&shared.input) || is_stdio(&shared.output) || single_file {
+        if shared.selection.is_set() {
+            anyhow::bail!(BadInput(
+                "--patients/--studies select files of a folder and cannot be used with a single file or `-` (stdin/stdout)"
+                    .to_string()
+            ));
+        }
+        if shared.pick {
+            anyhow::bail!(BadInput(
+                "--pick chooses among series of a folder and cannot be used with a single file or `-` (stdin/stdout)"
+                    .to_string()
+            ));
+        }
+        return pipe::run(shared, format, options);
+    }
+    let selection = shared.selection.load()?;
+    validate_temp_dir(shared.temp_dir.as_deref())?;
+    validate_video_options(shared, format)?;
+
+    if let Some(annotations) = &annotations {
+        report_annotations(annotations);
+    }
 
+    if shared.pick && matches!(format, ConvertFormat::Dicomweb) {
+        anyhow::bail!(BadInput(
+            "--pick cannot be used with dicomweb, which always publishes every series".to_string()
+        ));
+    }
+
+    validate_input_folder(&shared.input)?;
+    let destination = Destination::open(shared)?;
+    if matches!(format, ConvertFormat::Dicomweb) {
+        let summary = dicomweb::convert_to_dicomweb(shared, selection.as_ref(), &destination)?;
+        destination.finish()?;
+        return Ok(summary);
+    }
+    let groups = prepare_groups(shared, selection.as_ref(), &destination.root)?;
+    check_encoder(format)?;
+    let scouts = match format {
+        ConvertFormat::Jpeg {
+            scout_lines: true, ..
+        } => Some(load_scouts(&groups)),
+        _ => None,
+    };
+    if let ConvertFormat::Video {
+        combine_series: true,
+        ..
+    } = format
+        && let Some(video) = format.video_options(shared)
+    {
+        let summary = convert_study(&groups, &destination.root, shared, options, video);
+        destination.finish()?;
+        return Ok(summary);
+    }
+    if let Some(mode) = format.cardiac()
+        && let Some(video) = format.video_options(shared)
+    {
+        return convert_cardiac(&groups, destination, shared, options, video, mode);
+    }
+    let mut summary = Summary {
+        groups: groups.len(),
+        ..Summary::default()
+    };
+
+    let mut done = 0;
     for group in &groups {
-        println!(
-            "=== Processing series: {} ({} files) ===",
-            group.key,
-            group.files.len()
+        if shared.token.ended().is_some() {
+            break;
+        }
+        events::stage(
+            &group.output_dir,
+            Stage::Started {
+                key: group.key.clone(),
+                files: group.files.len(),
+            },
         );
 
-        match format {
-            ConvertFormat::Jpeg => jpeg::convert_to_jpgs(&group.files, &group.output_dir),
-            ConvertFormat::Video { fps } => {
-                video::convert_to_video(&group.files, &group.output_dir, *fps)?;
-            }
-            ConvertFormat::Stl { iso_level, smooth } => {
-                stl::convert_to_stl(&group.files, &group.output_dir, *iso_level, *smooth)?;
-            }
-        }
+        let options = RenderOptions {
+            cancel: shared.cancel(),
+            ..options
+        };
+        let archive = destination.archive.as_ref();
+        let result = convert_group(
+            group,
+            shared,
+            format,
+            options.clone(),
+            scouts.as_deref(),
+            archive,
+        );
+        let result = match &annotations {
+            Some(annotations) if shared.export_patches => result.and_then(|converted| {
+                export_patches(group, annotations, options)?;
+                Ok(converted)
+            }),
+            _ => result,
+        };
+        mark_stopped(&group.output_dir, &result);
+        // What a failed series wrote is kept, as with a folder
+        let result = destination.collect(&group.output_dir).and(result);
+        done += usize::from(record(&mut summary, &group.key, &group.output_dir, result));
 
         println!();
     }
 
-    println!("Conversion complete! Created {} series.", groups.len());
+    summary.interrupted = shared.token.ended().is_some();
+    finish(shared, destination, &groups, &summary, done)?;
+    Ok(summary)
+}
+
+/// Mark the folder `series` of a series that a stop cut short; what it wrote
+/// stays. Best effort: the stop is reported either way.
+fn mark_stopped(series: &Path, result: &Result<Converted>) {
+    if let Err(e) = result
+        && let Some(stopped) = cancel::stopped_by(e)
+    {
+        let _ = cancel::mark_incomplete(series, stopped);
+    }
+}
+
+/// Add what converting the series `key` into `series` gave to `summary`,
+/// reporting how it ended. Returns whether it succeeded.
+fn record(summary: &mut Summary, key: &str, series: &Path, result: Result<Converted>) -> bool {
+    match result {
+        Ok(converted) => {
+            events::stage(series, Stage::Finished);
+            *summary += converted;
+            true
+        }
+        Err(e) => {
+            events::stage(
+                series,
+                Stage::Failed {
+                    key: key.to_string(),
+                    error: format!("{e:#}"),
+                },
+            );
+            summary.groups_failed += 1;
+            summary
+                .encodes
+                .extend(e.downcast_ref::<EncodeFailure>().cloned());
+            false
+        }
+    }
+}
+
+/// Print how many overlays `--annotations` loaded.
+fn report_annotations(annotations: &Annotations) {
+    let (instances, shapes) = annotations.counts();
+    println!(
+        "{}",
+        t!(
+            "convert-annotations-loaded",
+            instances = instances,
+            shapes = shapes
+        )
+    );
+}
+
+/// Where a run writes its series folders.
+struct Destination {
+    /// Folder holding the series folders: `--out`, or a staging folder that
+    /// is drained into the archive.
+    root: PathBuf,
+    /// The archive a `.zip` `--out` names.
+    archive: Option<Archive>,
+    /// Keeps the staging folder until the run ends.
+    _staging: Option<TempDir>,
+}
+
+impl Destination {
+    /// The output folder, or a staging folder feeding a new `--out` archive.
+    fn open(shared: &ConvertShared) -> Result<Self> {
+        // Series folders and file names can push deep hospital share paths
+        // past the Windows `MAX_PATH` limit
+        let output = extended_length_path(&shared.output);
+        if !archive::is_archive(&shared.output) {
+            return Ok(Self {
+                root: output,
+                archive: None,
+                _staging: None,
+            });
+        }
+        if output.exists() && !shared.force {
+            anyhow::bail!(BadInput(format!(
+                "{} already exists; pass --force to replace it",
+                shared.output.display()
+            )));
+        }
+        if let Some(parent) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create output folder: {}", parent.display()))?;
+        }
+        let staging = create_temp_dir(shared.temp_dir.as_deref())?;
+        let archive = Archive::create(&output, staging.path(), shared.encrypt_zip.as_deref())?;
+        Ok(Self {
+            root: staging.path().to_path_buf(),
+            archive: Some(archive),
+            _staging: Some(staging),
+        })
+    }
+
+    /// Move a finished series folder into the archive, if writing one.
+    fn collect(&self, dir: &Path) -> Result<()> {
+        let Some(archive) = &self.archive else {
+            return Ok(());
+        };
+        archive.add_folder(dir)?;
+        fs::remove_dir_all(dir)
+            .with_context(|| format!("Failed to remove staging folder: {}", dir.display()))
+    }
+
+    /// Write `bytes` to `path` below the root, or into the archive.
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        if let Some(archive) = &self.archive {
+            return archive.add(path, bytes);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create output folder: {}", parent.display()))?;
+        }
+        fs::write(path, bytes).with_context(|| format!("Failed to write file: {}", path.display()))
+    }
+
+    /// Complete the archive, if writing one, with whatever is left in the
+    /// staging folder (e.g. a `--combine-series` MP4).
+    fn finish(self) -> Result<()> {
+        let Some(archive) = self.archive else {
+            return Ok(());
+        };
+        archive.add_folder(&self.root)?;
+        let path = archive.path().display().to_string();
+        archive.finish()?;
+        println!("{}", t!("convert-archive-saved", path = path));
+        Ok(())
+    }
+}
+
+/// Pack the series folders if asked (`--encrypt-zip`), report how many of
+/// them were created, and complete the `--out` archive.
+fn finish(
+    shared: &ConvertShared,
+    destination: Destination,
+    groups: &[PreparedGroup],
+    summary: &Summary,
+    done: usize,
+) -> Result<()> {
+    if summary.interrupted {
+        println!(
+            "{}",
+            t!("convert-interrupted", count = done, total = summary.groups)
+        );
+    } else {
+        if let Some(password) = &shared.encrypt_zip
+            && destination.archive.is_none()
+        {
+            encrypt_studies(groups, &destination.root, password)?;
+        }
+        println!("{}", t!("convert-complete", count = done));
+    }
+    if let Some((path, count)) = quarantine::write(&destination.root)? {
+        eprintln!(
+            "{}",
+            t!(
+                "convert-quarantined",
+                count = count,
+                path = path.display().to_string()
+            )
+        );
+    }
+    destination.finish()
+}
+
+/// Save the annotation patches of a converted group (`--export-patches`).
+fn export_patches(
+    group: &PreparedGroup,
+    annotations: &Annotations,
+    options: RenderOptions<'_>,
+) -> Result<()> {
+    let count = patches::export(&group.files, &group.output_dir, annotations, options)?;
+    if count > 0 {
+        let patch_dir = group.output_dir.join(patches::PATCH_DIR);
+        println!(
+            "{}",
+            t!(
+                "convert-patches-saved",
+                count = count,
+                path = patch_dir.display().to_string()
+            )
+        );
+    }
     Ok(())
 }
 
-/// Collect, group, sort, and prepare output directories for DICOM files.
-///
-/// Handles input validation, file discovery, tag-based grouping,
-/// output directory creation, and overwrite prompts.
-fn prepare_groups(shared: &ConvertShared) -> Result<Vec<PreparedGroup>> {
-    validate_input_folder(&shared.input)?;
+/// Replace the series folders with one encrypted archive per study in
+/// `output_root` (`--encrypt-zip`). On failure the remaining folders stay
+/// unencrypted.
+fn encrypt_studies(groups: &[PreparedGroup], output_root: &Path, password: &str) -> Result<()> {
+    for (study, members) in bundle::by_study(groups) {
+        let path = output_root.join(format!("{study}.zip"));
+        let dirs: Vec<&Path> = members
+            .iter()
+            .map(|group| group.output_dir.as_path())
+            .filter(|dir| dir.exists())
+            .collect();
+        let count = bundle::write_encrypted(&path, output_root, &dirs, password)?;
+        for dir in &dirs {
+            fs::remove_dir_all(dir).with_context(|| {
+                format!("Failed to remove unencrypted folder: {}", dir.display())
+            })?;
+        }
+        println!(
+            "{}",
+            t!(
+                "convert-encrypted-saved",
+                series = dirs.len(),
+                count = count,
+                path = path.display().to_string()
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Write all groups into one MP4 in the output folder (`--combine-series`).
+fn convert_study(
+    groups: &[PreparedGroup],
+    output_root: &Path,
+    shared: &ConvertShared,
+    options: RenderOptions<'_>,
+    video: VideoOptions<'_>,
+) -> Summary {
+    let mut summary = Summary {
+        groups: groups.len(),
+        ..Summary::default()
+    };
+    if groups.is_empty() {
+        return summary;
+    }
 
-    let entries = fs::read_dir(&shared.input)
-        .with_context(|| format!("Failed to read input folder: {}", shared.input.display()))?;
-
-    let dcm_files: Vec<PathBuf> = entries
-        .filter_map(std::result::Result::ok)
-        .map(|entry| entry.path())
-        .filter(|path| {
-            path.is_file()
-                && path
-                    .extension()
-                    .is_some_and(|ext| ext.eq_ignore_ascii_case("dcm"))
+    let series: Vec<video::Series> = groups
+        .iter()
+        .map(|group| video::Series {
+            key: &group.key,
+            files: &group.files,
         })
         .collect();
+    let options = RenderOptions {
+        cancel: shared.cancel(),
+        ..options
+    };
+    match video::convert_study(&series, output_root, options, video) {
+        Ok((stats, recovered)) => {
+            summary += stats;
+            summary.encodes.extend(recovered);
+        }
+        Err(e) => {
+            eprintln!("{}", t!("convert-study-failed", error = format!("{e:#}")));
+            summary.groups_failed = groups.len();
+            summary.interrupted = cancel::stopped_by(&e).is_some_and(Stopped::ends_run);
+            summary
+                .encodes
+                .extend(e.downcast_ref::<EncodeFailure>().cloned());
+        }
+    }
+    // Nothing is written per series; drop their (empty) folders
+    for group in groups {
+        let _ = fs::remove_dir(&group.output_dir);
+    }
 
-    if dcm_files.is_empty() {
-        println!("No .dcm files found in {}", shared.input.display());
-        return Ok(vec![]);
+    println!(
+        "\n{}",
+        t!(
+            "convert-complete",
+            count = summary.groups - summary.groups_failed
+        )
+    );
+    summary
+}
+
+/// Write a cine loop per slice position, gathered from the files of all
+/// groups, into `slice_NN` folders (`--cardiac-loops`, `--cardiac-slice`).
+fn convert_cardiac(
+    groups: &[PreparedGroup],
+    destination: Destination,
+    shared: &ConvertShared,
+    options: RenderOptions<'_>,
+    video: VideoOptions<'_>,
+    mode: Cardiac,
+) -> Result<Summary> {
+    let files: Vec<PathBuf> = groups
+        .iter()
+        .flat_map(|group| group.files.iter().cloned())
+        .collect();
+    // Nothing is written per series; drop their (empty) folders
+    for group in groups {
+        let _ = fs::remove_dir(&group.output_dir);
+    }
+    if files.is_empty() {
+        destination.finish()?;
+        return Ok(Summary::default());
+    }
+    let slices = video::chosen(video::cine_slices(&files)?, mode)?;
+    println!("{}\n", t!("video-cardiac-slices", count = slices.len()));
+
+    let mut summary = Summary {
+        groups: slices.len(),
+        ..Summary::default()
+    };
+    let mut done = 0;
+    for slice in &slices {
+        if shared.token.ended().is_some() {
+            break;
+        }
+        let name = slice.name();
+        println!(
+            "{}",
+            t!(
+                "video-cardiac-slice",
+                slice = name.as_str(),
+                position = format!("{:.1}", slice.position),
+                count = slice.files.len()
+            )
+        );
+        let output_dir = destination.root.join(&name);
+        let options = RenderOptions {
+            cancel: shared.cancel(),
+            ..options
+        };
+        // The loop beats at the acquired rate unless --fps says otherwise
+        let video = VideoOptions {
+            fps: video.fps.or(slice.fps),
+            ..video
+        };
+        let result = fs::create_dir_all(&output_dir)
+            .with_context(|| format!("Failed to create folder: {}", output_dir.display()))
+            .and_then(|()| video::convert_to_video(&slice.files, &output_dir, options, video))
+            .map(|(stats, encode)| Converted {
+                stats,
+                encode,
+                ..Converted::default()
+            });
+        mark_stopped(&output_dir, &result);
+        let result = destination.collect(&output_dir).and(result);
+        done += usize::from(record(&mut summary, &name, &output_dir, result));
+        println!();
     }
 
-    println!("Found {} DICOM file(s) to process", dcm_files.len());
-    println!("Splitting by: {:?}\n", shared.split_by);
+    summary.interrupted = shared.token.ended().is_some();
+    finish(shared, destination, &[], &summary, done)?;
+    Ok(summary)
+}
 
-    // Group files by the split key
-    let mut groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
+/// Find the localizers among all groups for `jpeg --scout-lines`.
+fn load_scouts(groups: &[PreparedGroup]) -> Vec<jpeg::Scout> {
+    let files: Vec<PathBuf> = groups
+        .iter()
+        .flat_map(|group| group.files.iter().cloned())
+        .collect();
+    let scouts = jpeg::find_scouts(&files);
+    if scouts.is_empty() {
+        eprintln!("{}", t!("convert-no-scouts"));
+    } else {
+        println!("{}\n", t!("convert-scouts-found", count = scouts.len()));
+    }
+    scouts
+}
 
-    for dcm_path in dcm_files {
-        let key = open_file(&dcm_path).map_or_else(
-            |_| "unknown".to_string(),
-            |obj| {
-                let tag = match shared.split_by {
-                    SplitBy::SeriesNumber => tags::SERIES_NUMBER,
-                    SplitBy::SeriesUid => tags::SERIES_INSTANCE_UID,
-                    SplitBy::AcquisitionNumber => tags::ACQUISITION_NUMBER,
-                    SplitBy::Description => tags::SERIES_DESCRIPTION,
-                    SplitBy::Orientation => tags::IMAGE_ORIENTATION_PATIENT,
-                    SplitBy::StackId => dicom::core::Tag(0x0020, 0x9056),
-                };
-                obj.element(tag)
-                    .ok()
-                    .and_then(|elem| elem.to_str().ok())
-                    .map_or_else(|| "unknown".to_string(), |s| s.trim().to_string())
+/// What converting one group adds to the run summary.
+#[derive(Debug, Default)]
+struct Converted {
+    stats: RunStats,
+    /// Measurements of the mesh, for STL groups.
+    mesh: Option<MeshStats>,
+    /// Failed encode the fallback recovered from, for videos.
+    encode: Option<EncodeFailure>,
+}
+
+impl AddAssign<Converted> for Summary {
+    fn add_assign(&mut self, converted: Converted) {
+        *self += converted.stats;
+        self.meshes.extend(converted.mesh);
+        self.encodes.extend(converted.encode);
+    }
+}
+
+/// Write one prepared group in the requested format.
+fn convert_group(
+    group: &PreparedGroup,
+    shared: &ConvertShared,
+    format: &ConvertFormat,
+    options: RenderOptions<'_>,
+    scouts: Option<&[jpeg::Scout]>,
+    archive: Option<&Archive>,
+) -> Result<Converted> {
+    if !matches!(format, ConvertFormat::Slide { .. })
+        && group
+            .files
+            .first()
+            .is_some_and(|path| slide::is_slide(path))
+    {
+        // Its frames are tiles of a pyramid, not slices
+        anyhow::bail!(BadInput(
+            "This series is a whole-slide microscopy image; convert it with `slide`".to_string()
+        ));
+    }
+    if shared.suv
+        && let Some(first) = group.files.first()
+    {
+        // Every file would fail the same way
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(first)
+            .with_context(|| format!("Failed to open DICOM file: {}", first.display()))?;
+        suv::factor(&obj)?;
+    }
+    match format {
+        ConvertFormat::Jpeg {
+            image_format,
+            acquisition_times,
+            ..
+        } => {
+            let stats = jpeg::convert_to_jpgs(
+                &group.series,
+                &group.output_dir,
+                *image_format,
+                options.clone(),
+                scouts,
+                archive,
+                *acquisition_times,
+            );
+            // The frame loop ends early when it is time to stop
+            options.cancel.check()?;
+            Ok(Converted {
+                stats,
+                ..Converted::default()
+            })
+        }
+        ConvertFormat::Video { .. } => video::convert_to_video(
+            &group.files,
+            &group.output_dir,
+            options,
+            format.video_options(shared).unwrap_or_default(),
+        )
+        .map(|(stats, encode)| Converted {
+            stats,
+            encode,
+            ..Converted::default()
+        }),
+        ConvertFormat::Stl {
+            iso_level,
+            target,
+            smooth,
+            mesh_format,
+            turntable,
+            mesh,
+        } => stl::convert_to_stl(
+            &group.files,
+            &group.output_dir,
+            MeshOptions {
+                iso_level: *iso_level,
+                target: *target,
+                smooth_sigma: *smooth,
+                strip_background: shared.strip_background,
+                output: *mesh,
+                turntable: *turntable,
+                ..MeshOptions::default()
             },
+            *mesh_format,
+            shared.suv,
+        )
+        .map(|mesh| Converted {
+            stats: RunStats {
+                written: group.files.len(),
+                failed: 0,
+            },
+            mesh: Some(mesh),
+            ..Converted::default()
+        }),
+        ConvertFormat::Pointcloud {
+            threshold,
+            point_format,
+            coords,
+        } => pointcloud::convert_to_point_cloud(
+            &group.files,
+            &group.output_dir,
+            pointcloud::CloudOptions {
+                threshold: *threshold,
+                format: *point_format,
+                coords: *coords,
+                strip_background: shared.strip_background,
+                suv: shared.suv,
+            },
+        )
+        .map(|_| Converted {
+            stats: RunStats {
+                written: group.files.len(),
+                failed: 0,
+            },
+            ..Converted::default()
+        }),
+        ConvertFormat::Slide {
+            downsample,
+            max_size,
+            deepzoom,
+            tile_size,
+            image_format,
+        } => slide::convert_to_slide(
+            &group.files,
+            &group.output_dir,
+            SlideOptions {
+                downsample: *downsample,
+                max_size: *max_size,
+                deepzoom: deepzoom.then_some(*tile_size),
+                format: *image_format,
+            },
+            options,
+        )
+        .map(|stats| Converted {
+            stats,
+            ..Converted::default()
+        }),
+        ConvertFormat::PrintLayout {
+            paper,
+            layout,
+            page_format,
+            dpi,
+        } => {
+            let stats = print::convert_to_pages(
+                &group.series,
+                &group.output_dir,
+                PrintOptions {
+                    paper: *paper,
+                    layout: *layout,
+                    format: *page_format,
+                    dpi: *dpi,
+                },
+                options.clone(),
+            )?;
+            // The frame loop ends early when it is time to stop
+            options.cancel.check()?;
+            Ok(Converted {
+                stats,
+                ..Converted::default()
+            })
+        }
+        ConvertFormat::Dicomweb => unreachable!("dicomweb is written per study, not per group"),
+    }
+}
+
+/// Collect, group, sort, and prepare output directories for DICOM files.
+///
+/// Handles input validation, file discovery, tag-based grouping,
+/// output directory creation, and overwrite prompts.
+fn prepare_groups(
+    shared: &ConvertShared,
+    selection: Option<&Selection>,
+    output_root: &Path,
+) -> Result<Vec<PreparedGroup>> {
+    let dcm_files = input_files(shared, selection)?;
+
+    if dcm_files.is_empty() {
+        println!(
+            "{}",
+            t!("no-dcm-files", path = shared.input.display().to_string())
         );
-        groups.entry(key).or_default().push(dcm_path);
+        return Ok(vec![]);
     }
 
-    println!("Found {} series/groups:\n", groups.len());
+    println!("{}", t!("convert-found-files", count = dcm_files.len()));
+    println!(
+        "{}\n",
+        t!(
+            "convert-splitting-by",
+            tag = format!("{:?}", shared.split_by)
+        )
+    );
+
+    let mut groups = group_files(dcm_files, shared)?;
+
+    println!("{}\n", t!("convert-found-groups", count = groups.len()));
 
     // Sort group keys for consistent output
     let mut sorted_keys: Vec<_> = groups.keys().cloned().collect();
-    sorted_keys.sort_by(|a, b| match (a.parse::<i32>(), b.parse::<i32>()) {
-        (Ok(a_num), Ok(b_num)) => a_num.cmp(&b_num),
-        _ => a.cmp(b),
-    });
+    sorted_keys.sort_by(|a, b| compare_group_keys(a, b));
 
-    for key in &sorted_keys {
-        println!("  - {}: {} files", key, groups[key].len());
+    if shared.pick {
+        sorted_keys = pick_groups(sorted_keys, &groups)?;
+    } else {
+        for key in &sorted_keys {
+            println!(
+                "  {}",
+                t!(
+                    "convert-group-entry",
+                    key = key.as_str(),
+                    count = groups[key].instances.len()
+                )
+            );
+        }
+        println!();
     }
-    println!();
 
     // Ensure output folder exists
-    fs::create_dir_all(&shared.output)
-        .with_context(|| format!("Failed to create output folder: {}", shared.output.display()))?;
+    fs::create_dir_all(output_root).with_context(|| {
+        format!(
+            "Failed to create output folder: {}",
+            shared.output.display()
+        )
+    })?;
 
     // Track saved choice for "to all" options
     let mut saved_choice: Option<CleanupChoice> = if shared.force {
@@ -197,17 +1605,42 @@ fn prepare_groups(shared: &ConvertShared) -> Result<Vec<PreparedGroup>> {
     };
 
     let mut prepared = Vec::with_capacity(sorted_keys.len());
+    let mut folder_names = FolderNames::default();
 
     for key in sorted_keys {
-        let files = groups.remove(&key).unwrap();
-        let safe_key = sanitize_filename(&key);
-        let group_output = shared.output.join(&safe_key);
+        let series = groups.remove(&key).unwrap();
+        let sorted_files = series.files();
+        let folder = shared
+            .group_name
+            .as_ref()
+            .and_then(|template| Some(template.render(sorted_files.first()?)))
+            .unwrap_or_else(|| key.clone());
+        let folder = if shared.ascii_names {
+            sanitize_filename(&transliterate(&folder))
+        } else {
+            sanitize_filename(&folder)
+        };
+        let unique = folder_names.claim(&folder);
+        if unique != folder {
+            eprintln!(
+                "{}",
+                t!(
+                    "convert-folder-collision",
+                    key = key.as_str(),
+                    folder = folder.as_str(),
+                    renamed = unique.as_str()
+                )
+            );
+        }
+        let group_output = output_root.join(&unique);
 
         let folder_exists =
             group_output.exists() && !is_folder_empty(&group_output).unwrap_or(true);
 
         let should_clean = if folder_exists {
-            if let Some(choice) = saved_choice { choice.should_clean() } else {
+            if let Some(choice) = saved_choice {
+                choice.should_clean()
+            } else {
                 let choice = prompt_to_cleanup(&group_output)?;
                 if choice.is_persistent() {
                     saved_choice = Some(choice);
@@ -218,14 +1651,13 @@ fn prepare_groups(shared: &ConvertShared) -> Result<Vec<PreparedGroup>> {
             false
         };
 
-        let sorted_files = sort_files_by_position(&files);
-
         clean_output(&group_output, should_clean)?;
         fs::create_dir_all(&group_output)?;
 
         prepared.push(PreparedGroup {
             key,
             files: sorted_files,
+            series,
             output_dir: group_output,
         });
     }
@@ -233,48 +1665,159 @@ fn prepare_groups(shared: &ConvertShared) -> Result<Vec<PreparedGroup>> {
     Ok(prepared)
 }
 
-/// Sort files by `IMAGE_POSITION_PATIENT` Z-coordinate.
-fn sort_files_by_position(files: &[PathBuf]) -> Vec<PathBuf> {
-    let mut files_with_position: Vec<(PathBuf, f64)> = files
+/// Ask which of the groups under `keys` to convert (`--pick`); the picked
+/// keys, in order.
+fn pick_groups(keys: Vec<String>, groups: &BTreeMap<String, SeriesMeta>) -> Result<Vec<String>> {
+    let entries: Vec<_> = keys
         .iter()
-        .map(|path| {
-            let z_position = open_file(path).map_or(f64::MAX, |obj| {
-                obj.element(tags::IMAGE_POSITION_PATIENT)
-                    .ok()
-                    .and_then(|elem| elem.to_str().ok())
-                    .and_then(|s| {
-                        let coords: Vec<f64> = s
-                            .split('\\')
-                            .filter_map(|v| v.trim().parse::<f64>().ok())
-                            .collect();
-                        coords.get(2).copied()
-                    })
-                    .unwrap_or(f64::MAX)
-            });
-            (path.clone(), z_position)
-        })
+        .map(|key| (key.as_str(), &groups[key]))
         .collect();
+    let picks = pick::prompt(&entries)?;
+    println!(
+        "{}\n",
+        t!("convert-picked", count = picks.len(), total = keys.len())
+    );
+    let mut keys: Vec<_> = keys.into_iter().map(Some).collect();
+    Ok(picks.into_iter().filter_map(|i| keys[i].take()).collect())
+}
+
+/// The `.dcm` files of `--in`, narrowed to the `--patients`/`--studies`
+/// lists if given.
+fn input_files(shared: &ConvertShared, selection: Option<&Selection>) -> Result<Vec<PathBuf>> {
+    let files = list_dcm_files(&shared.input, shared.follow_symlinks)?;
+    Ok(match selection {
+        Some(selection) if !files.is_empty() => selection.filter(files),
+        _ => files,
+    })
+}
+
+/// Group files by the split key, handling files without it per
+/// `--unknown-group`, then cut each group into sessions `--time-window`
+/// minutes apart if given. Each header is read once, into the file's
+/// summary, and every group comes back in slice order.
+fn group_files(
+    dcm_files: Vec<PathBuf>,
+    shared: &ConvertShared,
+) -> Result<BTreeMap<String, SeriesMeta>> {
+    let mut groups: BTreeMap<String, Vec<(InstanceMeta, Option<Acquired>)>> = BTreeMap::new();
+    let mut unknown = 0;
+    for dcm_path in dcm_files {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(&dcm_path)
+            .ok();
+        let key = match split_key(obj.as_ref(), shared.split_by) {
+            Some(key) => key,
+            None => {
+                unknown += 1;
+                match shared.unknown_group {
+                    UnknownGroup::Merge | UnknownGroup::Error => UNKNOWN_KEY.to_string(),
+                    UnknownGroup::Skip => continue,
+                    UnknownGroup::SeparateByUid => split_key(obj.as_ref(), SplitBy::SeriesUid)
+                        .map_or_else(
+                            || UNKNOWN_KEY.to_string(),
+                            |uid| format!("{UNKNOWN_KEY}_{uid}"),
+                        ),
+                }
+            }
+        };
+        let acquired = shared
+            .time_window
+            .and(obj.as_ref())
+            .and_then(Acquired::read);
+        let meta = obj.as_ref().map_or_else(
+            || InstanceMeta::unreadable(&dcm_path),
+            |obj| InstanceMeta::from_object(&dcm_path, obj),
+        );
+        groups.entry(key).or_default().push((meta, acquired));
+    }
+    if unknown > 0 {
+        let tag = format!("{:?}", shared.split_by);
+        match shared.unknown_group {
+            UnknownGroup::Error => anyhow::bail!(BadInput(format!(
+                "{unknown} file(s) have no {tag} to split by (--unknown-group error)"
+            ))),
+            UnknownGroup::Skip => eprintln!(
+                "{}",
+                t!("convert-unknown-skipped", count = unknown, tag = tag)
+            ),
+            UnknownGroup::Merge | UnknownGroup::SeparateByUid => {}
+        }
+    }
 
-    files_with_position.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    let series = |instances| SeriesMeta::new(sort_by_position(instances));
+    let Some(minutes) = shared.time_window else {
+        return Ok(groups
+            .into_iter()
+            .map(|(key, files)| {
+                (
+                    key,
+                    series(files.into_iter().map(|(meta, _)| meta).collect()),
+                )
+            })
+            .collect());
+    };
+    let window_secs = i64::try_from(minutes.saturating_mul(60)).unwrap_or(i64::MAX);
+    Ok(groups
+        .into_iter()
+        .flat_map(|(key, files)| session::split_sessions(&key, files, window_secs))
+        .map(|(key, files)| (key, series(files)))
+        .collect())
+}
 
-    files_with_position
+/// Read the value of the split tag for a file (`None` if unavailable).
+pub fn split_key(obj: Option<&DefaultDicomObject>, split_by: SplitBy) -> Option<String> {
+    obj.and_then(|obj| obj.element(split_by.tag()).ok())
+        .and_then(|elem| elem.to_str().ok())
+        .map(|s| split_by.normalize(&s))
+        .filter(|key| !key.is_empty())
+}
+
+/// Sort files by `IMAGE_POSITION_PATIENT` Z-coordinate.
+///
+/// Ties (equal or missing positions, e.g. several echoes at one location)
+/// are broken by `InstanceNumber`, then by path, so the order is the same on
+/// every run and filesystem. Files without a position or instance number
+/// sort last.
+pub fn sort_files_by_position(files: &[PathBuf]) -> Vec<PathBuf> {
+    let instances = files.iter().map(|path| InstanceMeta::read(path)).collect();
+    sort_by_position(instances)
         .into_iter()
-        .map(|(path, _)| path)
+        .map(|instance| instance.path)
         .collect()
 }
 
-/// Load a DICOM file and decode it as a dynamic image.
-fn load_dcm_as_image(dcm_path: &PathBuf) -> Result<DynamicImage> {
-    let dicom_obj =
-        open_file(dcm_path).with_context(|| format!("Failed to open DICOM file: {}", dcm_path.display()))?;
+/// [`sort_files_by_position`] for headers already summarized.
+pub fn sort_by_position(mut instances: Vec<InstanceMeta>) -> Vec<InstanceMeta> {
+    instances.sort_by(|a, b| {
+        SliceOrder::of(a)
+            .compare(&SliceOrder::of(b))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    instances
+}
 
-    let pixel_data = dicom_obj
-        .decode_pixel_data()
-        .with_context(|| format!("Failed to decode pixel data from: {}", dcm_path.display()))?;
+/// Sort key of a slice: Z position, then `InstanceNumber`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SliceOrder {
+    z: f64,
+    instance: i64,
+}
 
-    pixel_data
-        .to_dynamic_image(0)
-        .with_context(|| format!("Failed to convert to image: {}", dcm_path.display()))
+impl SliceOrder {
+    /// The key of a summarized file; missing values sort last.
+    fn of(meta: &InstanceMeta) -> Self {
+        Self {
+            z: meta.position.map_or(f64::MAX, |position| position[2]),
+            instance: meta.instance_number.unwrap_or(i64::MAX),
+        }
+    }
+
+    fn compare(&self, other: &Self) -> std::cmp::Ordering {
+        self.z
+            .total_cmp(&other.z)
+            .then(self.instance.cmp(&other.instance))
+    }
 }
 
 #[cfg(test)]
@@ -334,11 +1877,91 @@ mod tests {
         }
     }
 
+    // =========================================================================
+    // Split Key Normalization Tests
+    // =========================================================================
+
+    mod split_key_normalization {
+        use super::super::*;
+
+        #[test]
+        fn image_type_components_are_joined() {
+            assert_eq!(
+                SplitBy::ImageType.normalize("ORIGINAL\\PRIMARY\\AXIAL"),
+                "ORIGINAL_PRIMARY_AXIAL"
+            );
+        }
+
+        #[test]
+        fn image_type_drops_empty_components_and_padding() {
+            assert_eq!(
+                SplitBy::ImageType.normalize(" derived\\secondary\\\\MPR "),
+                "DERIVED_SECONDARY_MPR"
+            );
+        }
+
+        #[test]
+        fn image_type_key_is_a_safe_folder_name() {
+            let key = SplitBy::ImageType.normalize("ORIGINAL\\PRIMARY\\M\\ND");
+            assert_eq!(sanitize_filename(&key), key);
+        }
+
+        #[test]
+        fn other_tags_are_only_trimmed() {
+            assert_eq!(SplitBy::SeriesNumber.normalize(" 3 "), "3");
+            assert_eq!(SplitBy::Description.normalize("T1 AX "), "T1 AX");
+        }
+
+        #[test]
+        fn decimal_values_share_one_key() {
+            assert_eq!(SplitBy::EchoTime.normalize("2.460"), "2.46");
+            assert_eq!(SplitBy::EchoTime.normalize(" 2.46 "), "2.46");
+            assert_eq!(SplitBy::FlipAngle.normalize("15.0"), "15");
+            assert_eq!(SplitBy::InversionTime.normalize("2100\\900"), "2100");
+        }
+
+        #[test]
+        fn acquisition_dates_become_iso_dates() {
+            assert_eq!(
+                SplitBy::AcquisitionDate.normalize("20240105 "),
+                "2024-01-05"
+            );
+            assert_eq!(
+                SplitBy::AcquisitionDate.normalize("2024.01.05"),
+                "2024.01.05"
+            );
+        }
+
+        #[test]
+        fn unparsable_decimal_is_kept() {
+            assert_eq!(SplitBy::EchoTime.normalize(" n/a "), "n/a");
+        }
+
+        #[test]
+        fn decimal_keys_sort_numerically() {
+            let mut keys = vec!["10.5", "unknown", "2.46", "4.92"];
+            keys.sort_by(|a, b| compare_group_keys(a, b));
+            assert_eq!(keys, vec!["2.46", "4.92", "10.5", "unknown"]);
+        }
+
+        #[test]
+        fn numerically_equal_keys_fall_back_to_string_order() {
+            let mut forward = vec!["1", "01", "1.0"];
+            let mut backward = vec!["1.0", "01", "1"];
+            forward.sort_by(|a, b| compare_group_keys(a, b));
+            backward.sort_by(|a, b| compare_group_keys(a, b));
+            assert_eq!(forward, vec!["01", "1", "1.0"]);
+            assert_eq!(forward, backward);
+        }
+    }
+
     // =========================================================================
     // Position Parsing Tests (for sort_files_by_position logic)
     // =========================================================================
 
     mod position_parsing {
+        use super::super::*;
+
         #[test]
         fn parse_image_position_patient_z_coordinate() {
             // ImagePositionPatient format: "X\Y\Z"
@@ -416,6 +2039,29 @@ mod tests {
             // The sort should complete without panicking
             assert_eq!(positions.len(), 3);
         }
+
+        #[test]
+        fn equal_positions_sort_by_instance_number() {
+            let at = |z, instance| SliceOrder { z, instance };
+            let mut keys = [at(5.0, 3), at(5.0, 1), at(-2.0, 9), at(5.0, 2)];
+            keys.sort_by(SliceOrder::compare);
+            let instances: Vec<i64> = keys.iter().map(|k| k.instance).collect();
+            assert_eq!(instances, vec![9, 1, 2, 3]);
+        }
+
+        #[test]
+        fn unreadable_files_sort_by_path_regardless_of_input_order() {
+            let files: Vec<PathBuf> = ["c.dcm", "a.dcm", "b.dcm"]
+                .iter()
+                .map(|name| PathBuf::from("/nonexistent").join(name))
+                .collect();
+            let mut reversed = files.clone();
+            reversed.reverse();
+
+            let sorted = sort_files_by_position(&files);
+            assert_eq!(sorted, sort_files_by_position(&reversed));
+            assert!(sorted[0].ends_with("a.dcm") && sorted[2].ends_with("c.dcm"));
+        }
     }
 
     // =========================================================================
@@ -425,21 +2071,20 @@ mod tests {
     mod output_paths {
         use std::path::Path;
 
+        use crate::utils::named_after_folder;
+
         #[test]
         fn video_filename_matches_folder_name() {
             let test_cases = [
                 ("series_001", "series_001.mp4"),
                 ("unknown", "unknown.mp4"),
                 ("T2W_FLAIR", "T2W_FLAIR.mp4"),
+                ("Tórax 胸部", "Tórax 胸部.mp4"),
             ];
 
             for (folder_name, expected_video) in test_cases {
                 let output_dir = Path::new("/output").join(folder_name);
-                let folder_name = output_dir
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("output");
-                let video_path = output_dir.join(format!("{folder_name}.mp4"));
+                let video_path = named_after_folder(&output_dir, "mp4");
 
                 assert!(
                     video_path.ends_with(expected_video),
@@ -468,6 +2113,7 @@ mod tests {
                 ("Series 1", "Series 1"),
                 ("T2W/FLAIR", "T2W_FLAIR"),
                 ("Series:Description", "Series_Description"),
+                ("Tórax/Abdomen 胸部", "Tórax_Abdomen 胸部"),
             ];
 
             let base_output = Path::new("/output");
@@ -483,4 +2129,58 @@ mod tests {
             }
         }
     }
+
+    mod default_output {
+        use std::path::Path;
+
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+        use dicom::dictionary_std::tags;
+        use dicom::object::{FileMetaTableBuilder, InMemDicomObject};
+
+        use super::super::default_output;
+        use crate::outcome::BadInput;
+
+        fn write_header(path: &Path, patient: &str, date: &str) {
+            let obj = InMemDicomObject::from_element_iter([
+                DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from(patient)),
+                DataElement::new(tags::STUDY_DATE, VR::DA, PrimitiveValue::from(date)),
+                DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from("CT")),
+            ]);
+            let meta = FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+                .media_storage_sop_instance_uid("1.2.3")
+                .build()
+                .unwrap();
+            obj.with_exact_meta(meta).write_to_file(path).unwrap();
+        }
+
+        #[test]
+        fn named_after_the_first_file() {
+            let dir = tempfile::tempdir().unwrap();
+            write_header(&dir.path().join("a.dcm"), "PAT/01 ", "20240131");
+            write_header(&dir.path().join("b.dcm"), "OTHER", "20990101");
+            assert_eq!(
+                default_output(dir.path(), false).unwrap(),
+                Path::new("export").join("PAT_01_20240131_CT")
+            );
+        }
+
+        #[test]
+        fn empty_folders_are_unknown() {
+            let dir = tempfile::tempdir().unwrap();
+            assert_eq!(
+                default_output(dir.path(), false).unwrap(),
+                Path::new("export").join("unknown_unknown_unknown")
+            );
+        }
+
+        #[test]
+        fn stdin_and_missing_inputs_are_bad_input() {
+            for input in ["-", "/nonexistent/dicom/folder"] {
+                let err = default_output(Path::new(input), false).unwrap_err();
+                assert!(err.downcast_ref::<BadInput>().is_some(), "{input}");
+            }
+        }
+    }
 }
@@ -0,0 +1,197 @@
+//! Single-file output (`convert --out export.zip`).
+//!
+//! When `--out` names a `.zip` file, the series folders are written into
+//! that archive instead of a folder tree. Images are encoded in memory and
+//! streamed in as they are rendered. Outputs that must exist as files first
+//! (MP4s from ffmpeg, models, patches) go to the series' staging folder and
+//! are moved in as soon as that series finishes, so at most one series is
+//! ever on disk. `--encrypt-zip` uses the same writer with AES-256 entries.
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use zip::write::{FileOptions, SimpleFileOptions};
+use zip::{AesMode, CompressionMethod, ZipWriter};
+
+/// Whether `--out` names an archive rather than a folder.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// A ZIP being written, mirroring the folder tree below `root`.
+pub struct Archive {
+    path: PathBuf,
+    root: PathBuf,
+    writer: RefCell<ZipWriter<BufWriter<File>>>,
+    password: Option<String>,
+}
+
+impl Archive {
+    /// Start the archive at `path` for files below `root`, encrypting every
+    /// entry when a password is given.
+    pub fn create(path: &Path, root: &Path, password: Option<&str>) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create archive: {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            root: root.to_path_buf(),
+            writer: RefCell::new(ZipWriter::new(BufWriter::new(file))),
+            password: password.map(String::from),
+        })
+    }
+
+    /// Location of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write `bytes` as the entry for `path`, a file below the root that is
+    /// never created on disk.
+    pub fn add(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        let name = self.entry_name(path);
+        let mut writer = self.writer.borrow_mut();
+        writer.start_file(name.as_str(), self.options(bytes.len() as u64))?;
+        writer.write_all(bytes)?;
+        Ok(())
+    }
+
+    /// Copy every file below `dir` into the archive, in name order, and
+    /// return how many there were.
+    pub fn add_folder(&self, dir: &Path) -> Result<usize> {
+        let files = files_under(dir)
+            .with_context(|| format!("Failed to list folder: {}", dir.display()))?;
+        for path in &files {
+            let name = self.entry_name(path);
+            let mut source = File::open(path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            let size = source.metadata().map_or(u64::MAX, |meta| meta.len());
+            let mut writer = self.writer.borrow_mut();
+            writer.start_file(name.as_str(), self.options(size))?;
+            io::copy(&mut source, &mut *writer)
+                .with_context(|| format!("Failed to archive file: {}", path.display()))?;
+        }
+        Ok(files.len())
+    }
+
+    /// Write the archive's index; until then it cannot be opened.
+    pub fn finish(self) -> Result<()> {
+        let path = self.path;
+        self.writer
+            .into_inner()
+            .finish()
+            .and_then(|mut out| Ok(out.flush()?))
+            .with_context(|| format!("Failed to write archive: {}", path.display()))
+    }
+
+    /// Entry options for a file of `size` bytes.
+    fn options(&self, size: u64) -> FileOptions<'_, ()> {
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(size >= u64::from(u32::MAX));
+        self.password.as_deref().map_or(options, |password| {
+            options.with_aes_encryption(AesMode::Aes256, password)
+        })
+    }
+
+    /// `/`-separated name of `path` relative to the root.
+    fn entry_name(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        relative
+            .components()
+            .filter_map(|part| match part {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Files below `dir`, in name order.
+fn files_under(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+    let mut files = Vec::new();
+    for entry in entries {
+        if entry.file_type()?.is_dir() {
+            files.extend(files_under(&entry.path())?);
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Entry `name` of the archive at `path`.
+    fn read_entry(path: &Path, name: &str, password: Option<&str>) -> Result<Vec<u8>> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+        let mut entry = match password {
+            Some(password) => archive.by_name_decrypt(name, password.as_bytes())?,
+            None => archive.by_name(name)?,
+        };
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    #[test]
+    fn zip_extension_selects_an_archive() {
+        assert!(is_archive(Path::new("study_export.zip")));
+        assert!(is_archive(Path::new("out/Study.ZIP")));
+        assert!(!is_archive(Path::new("out")));
+        assert!(!is_archive(Path::new("out.zip.d/series")));
+    }
+
+    #[test]
+    fn entries_mirror_the_tree_below_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("staging");
+        fs::create_dir_all(root.join("T2").join("patches")).unwrap();
+        fs::write(root.join("T2").join("T2.mp4"), b"video").unwrap();
+        fs::write(root.join("T2").join("patches").join("index.csv"), b"csv").unwrap();
+        let path = dir.path().join("export.zip");
+
+        let archive = Archive::create(&path, &root, None).unwrap();
+        archive
+            .add(&root.join("T1").join("0001.jpg"), b"streamed")
+            .unwrap();
+        assert_eq!(archive.add_folder(&root.join("T2")).unwrap(), 2);
+        archive.finish().unwrap();
+
+        assert_eq!(read_entry(&path, "T1/0001.jpg", None).unwrap(), b"streamed");
+        assert_eq!(read_entry(&path, "T2/T2.mp4", None).unwrap(), b"video");
+        assert_eq!(
+            read_entry(&path, "T2/patches/index.csv", None).unwrap(),
+            b"csv"
+        );
+        assert!(!root.join("T1").exists());
+    }
+
+    #[test]
+    fn password_encrypts_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+
+        let archive = Archive::create(&path, dir.path(), Some("s3cret")).unwrap();
+        archive
+            .add(&dir.path().join("T1").join("0001.jpg"), b"image")
+            .unwrap();
+        archive.finish().unwrap();
+
+        assert!(read_entry(&path, "T1/0001.jpg", None).is_err());
+        assert!(read_entry(&path, "T1/0001.jpg", Some("wrong")).is_err());
+        assert_eq!(
+            read_entry(&path, "T1/0001.jpg", Some("s3cret")).unwrap(),
+            b"image"
+        );
+    }
+}
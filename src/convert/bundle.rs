@@ -0,0 +1,123 @@
+//! Encrypted study archives (`convert --encrypt-zip`).
+//!
+//! After a run, the series folders of each study (by `StudyInstanceUID`) are
+//! packed into one AES-256 ZIP in the output folder, and the unencrypted
+//! folders are removed. AES ZIPs open in 7-Zip and most other archive tools,
+//! so the recipient needs no dcm-toolbox. Entry
+//! names (series folders and file names) stay readable; only contents are
+//! encrypted.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+
+use super::PreparedGroup;
+use super::archive::Archive;
+use crate::utils::sanitize_filename;
+
+/// Archive name of series without a `StudyInstanceUID`.
+const UNKNOWN_STUDY: &str = "unknown";
+
+/// Output folders of `groups` by study, keyed by the archive's file stem.
+pub(super) fn by_study(groups: &[PreparedGroup]) -> BTreeMap<String, Vec<&PreparedGroup>> {
+    let mut studies: BTreeMap<String, Vec<&PreparedGroup>> = BTreeMap::new();
+    for group in groups {
+        let study = group
+            .files
+            .first()
+            .and_then(|path| study_uid(path))
+            .map(|uid| sanitize_filename(&uid))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| UNKNOWN_STUDY.to_string());
+        studies.entry(study).or_default().push(group);
+    }
+    studies
+}
+
+/// `StudyInstanceUID` of a file, reading its header only.
+fn study_uid(path: &Path) -> Option<String> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()?;
+    let uid = obj.element(tags::STUDY_INSTANCE_UID).ok()?.to_str().ok()?;
+    Some(uid.trim_end_matches(['\0', ' ']).to_string())
+}
+
+/// Write the folders `dirs` below `root` into an AES-256 ZIP at `path`,
+/// each under its own name, and return how many files it holds. A failed
+/// archive is deleted; the folders are left untouched either way.
+pub(super) fn write_encrypted(
+    path: &Path,
+    root: &Path,
+    dirs: &[&Path],
+    password: &str,
+) -> Result<usize> {
+    let result = (|| -> Result<usize> {
+        let archive = Archive::create(path, root, Some(password))?;
+        let mut count = 0;
+        for dir in dirs {
+            count += archive.add_folder(dir)?;
+        }
+        archive.finish()?;
+        Ok(count)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result.with_context(|| format!("Failed to write encrypted archive: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+
+    #[test]
+    fn archive_holds_each_folder_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let (t1, t2) = (dir.path().join("T1"), dir.path().join("T2"));
+        fs::create_dir_all(t1.join("patches")).unwrap();
+        fs::create_dir_all(&t2).unwrap();
+        fs::write(t1.join("0001.jpg"), b"first").unwrap();
+        fs::write(t1.join("patches").join("index.csv"), b"csv").unwrap();
+        fs::write(t2.join("0001.jpg"), b"second").unwrap();
+        let path = dir.path().join("study.zip");
+
+        let count = write_encrypted(&path, dir.path(), &[&t1, &t2], "s3cret").unwrap();
+        assert_eq!(count, 3);
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["T1/0001.jpg", "T1/patches/index.csv", "T2/0001.jpg"]
+        );
+        assert!(archive.by_name("T2/0001.jpg").is_err());
+        assert!(archive.by_name_decrypt("T2/0001.jpg", b"wrong").is_err());
+
+        let mut text = String::new();
+        archive
+            .by_name_decrypt("T2/0001.jpg", b"s3cret")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "second");
+    }
+
+    #[test]
+    fn failed_archive_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("study.zip");
+        let missing = dir.path().join("missing");
+
+        assert!(write_encrypted(&path, dir.path(), &[&missing], "s3cret").is_err());
+        assert!(!path.exists());
+    }
+}
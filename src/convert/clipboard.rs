@@ -0,0 +1,179 @@
+//! Copy one rendered slice to the clipboard (`--to-clipboard --slice N`).
+//!
+//! For pasting a slice into a report or a chat without saving a file: slice
+//! `N` of a series folder (in slice order, frames of multi-frame objects
+//! counted one by one) or frame `N` of a single file is rendered like a
+//! jpeg frame and placed on the system clipboard as an image.
+//!
+//! On Linux (X11 and Wayland) the clipboard holds no data of its own: the
+//! program that copied must stay alive to hand the image over. The run
+//! therefore waits until something else is copied, or for at most two
+//! minutes, before it ends.
+
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use arboard::{Clipboard, ImageData};
+use dicom::object::open_file;
+
+use super::{ConvertFormat, ConvertShared, sort_by_position, suv};
+use crate::i18n::t;
+use crate::meta::InstanceMeta;
+use crate::outcome::{BadInput, Summary};
+use crate::pipeline::{self, RenderOptions, display_name};
+use crate::pixel;
+use crate::utils::list_dcm_files;
+
+/// Longest the run keeps the image on a Linux clipboard for other programs.
+#[cfg(target_os = "linux")]
+const HOLD: Duration = Duration::from_secs(120);
+
+pub(super) fn run(
+    shared: &ConvertShared,
+    format: &ConvertFormat,
+    options: RenderOptions<'_>,
+) -> Result<Summary> {
+    if !matches!(format, ConvertFormat::Jpeg { .. }) {
+        anyhow::bail!(BadInput(
+            "--to-clipboard copies a rendered image and only works with the jpeg format"
+                .to_string()
+        ));
+    }
+    let slice = shared
+        .slice
+        .expect("clap requires --slice with --to-clipboard");
+    let (path, frame) = find_slice(&shared.input, slice, shared.follow_symlinks)?;
+
+    let obj = open_file(&path)
+        .with_context(|| format!("Failed to open DICOM file: {}", path.display()))?;
+    let decoded = pixel::decode_frame(&obj, frame).context("Failed to decode pixel data")?;
+    let decoded = match options.suv {
+        Some(display) => display.apply(decoded, suv::factor(&obj)?),
+        None => decoded,
+    };
+    let number = usize::try_from(frame).unwrap_or(usize::MAX);
+    let image = pipeline::render_annotated(decoded, options, &obj, number).into_rgba8();
+
+    let (width, height) = image.dimensions();
+    let data = ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: image.into_raw().into(),
+    };
+    let mut clipboard = Clipboard::new().context("Failed to open the clipboard")?;
+    copy(&mut clipboard, data, slice, &path)?;
+    Ok(Summary {
+        groups: 1,
+        frames: 1,
+        ..Summary::default()
+    })
+}
+
+/// Put `data` on the clipboard and wait until another program has it.
+#[cfg(target_os = "linux")]
+fn copy(clipboard: &mut Clipboard, data: ImageData<'_>, slice: u32, path: &Path) -> Result<()> {
+    use arboard::SetExtLinux;
+
+    eprintln!(
+        "{}",
+        t!(
+            "convert-clipboard-holding",
+            slice = slice,
+            file = display_name(path),
+            seconds = HOLD.as_secs()
+        )
+    );
+    clipboard
+        .set()
+        .wait_until(Instant::now() + HOLD)
+        .image(data)
+        .context("Failed to copy the image to the clipboard")
+}
+
+/// Put `data` on the clipboard, which keeps it after the run ends.
+#[cfg(not(target_os = "linux"))]
+fn copy(clipboard: &mut Clipboard, data: ImageData<'_>, slice: u32, path: &Path) -> Result<()> {
+    clipboard
+        .set_image(data)
+        .context("Failed to copy the image to the clipboard")?;
+    eprintln!(
+        "{}",
+        t!(
+            "convert-clipboard-copied",
+            slice = slice,
+            file = display_name(path)
+        )
+    );
+    Ok(())
+}
+
+/// File and frame of slice `slice` (1-based) of `input`: a frame of a single
+/// file, or a slice of the one series in a folder.
+fn find_slice(input: &Path, slice: u32, follow_symlinks: bool) -> Result<(PathBuf, u32)> {
+    let instances = if input.is_file() {
+        vec![InstanceMeta::read(input)]
+    } else {
+        let files = list_dcm_files(input, follow_symlinks)?;
+        let instances: Vec<InstanceMeta> = files.iter().map(|f| InstanceMeta::read(f)).collect();
+        let mut series: Vec<_> = instances.iter().map(|i| &i.series_uid).collect();
+        series.sort();
+        series.dedup();
+        if series.len() > 1 {
+            anyhow::bail!(BadInput(format!(
+                "--to-clipboard copies a slice of one series, but {} holds {}; pass a series \
+                 folder or a single file",
+                input.display(),
+                series.len()
+            )));
+        }
+        sort_by_position(instances)
+    };
+    let total: u32 = instances.iter().map(|i| i.frames).sum();
+    locate(&instances, slice).ok_or_else(|| {
+        BadInput(format!(
+            "--slice {slice} is past the last slice of {} ({total})",
+            input.display()
+        ))
+        .into()
+    })
+}
+
+/// File and frame of slice `slice` (1-based) of `instances`, in order.
+fn locate(instances: &[InstanceMeta], slice: u32) -> Option<(PathBuf, u32)> {
+    let mut index = slice.checked_sub(1)?;
+    for instance in instances {
+        if index < instance.frames {
+            return Some((instance.path.clone(), index));
+        }
+        index -= instance.frames;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(path: &str, frames: u32) -> InstanceMeta {
+        InstanceMeta {
+            frames,
+            ..InstanceMeta::unreadable(Path::new(path))
+        }
+    }
+
+    #[test]
+    fn slices_count_every_frame_in_order() {
+        let instances = [
+            instance("a.dcm", 1),
+            instance("b.dcm", 3),
+            instance("c.dcm", 1),
+        ];
+        assert_eq!(locate(&instances, 1), Some((PathBuf::from("a.dcm"), 0)));
+        assert_eq!(locate(&instances, 3), Some((PathBuf::from("b.dcm"), 1)));
+        assert_eq!(locate(&instances, 5), Some((PathBuf::from("c.dcm"), 0)));
+        assert_eq!(locate(&instances, 6), None);
+        assert_eq!(locate(&instances, 0), None);
+    }
+}
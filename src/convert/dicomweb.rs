@@ -0,0 +1,429 @@
+//! Static DICOMweb output (`convert dicomweb`).
+//!
+//! Writes the input as the files a DICOMweb server would answer with, so the
+//! output folder can be put behind any static file server and opened in
+//! OHIF (a DICOMweb data source with `staticWado: true`):
+//!
+//! ```text
+//! studies/index.json                                        studies (QIDO-RS)
+//! studies/<study>/series/index.json                         series (QIDO-RS)
+//! studies/<study>/series/<series>/metadata/index.json       metadata (WADO-RS)
+//! studies/<study>/series/<series>/instances/<sop>/frames/1  pixels of frame 1
+//! ```
+//!
+//! Folder names are the UIDs. Frames are decoded once here and stored as
+//! native little-endian samples, so the viewer needs no codec for
+//! compressed inputs; the metadata is each header in the DICOM JSON model,
+//! without its pixel data and patched to describe the stored frames. Series
+//! are grouped by `SeriesInstanceUID`, whatever `--split-by` says.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use dicom::dictionary_std::tags;
+use dicom::object::{OpenFileOptions, open_file};
+use dicom_pixeldata::{PixelDecoder, PlanarConfiguration};
+use serde_json::{Map, Value, json};
+
+use super::{ConvertShared, Destination, input_files, sort_files_by_position};
+use crate::events::{self, Event, Stage};
+use crate::i18n::t;
+use crate::outcome::Summary;
+use crate::pipeline::{number_of_frames, sop_instance_uid};
+use crate::pixel;
+use crate::select::Selection;
+use crate::throttle;
+use crate::utils::{clean_output, is_folder_empty, prompt_to_cleanup, sanitize_filename};
+
+/// Transfer syntax of the stored frames: Explicit VR Little Endian.
+const NATIVE_SYNTAX: &str = "1.2.840.10008.1.2.1";
+
+/// Study attributes copied from an instance into the study list.
+const STUDY_ATTRIBUTES: [&str; 11] = [
+    "00080020", // StudyDate
+    "00080030", // StudyTime
+    "00080050", // AccessionNumber
+    "00080090", // ReferringPhysicianName
+    "00081030", // StudyDescription
+    "00100010", // PatientName
+    "00100020", // PatientID
+    "00100030", // PatientBirthDate
+    "00100040", // PatientSex
+    "0020000D", // StudyInstanceUID
+    "00200010", // StudyID
+];
+
+/// Series attributes copied from an instance into the series list.
+const SERIES_ATTRIBUTES: [&str; 7] = [
+    "00080021", // SeriesDate
+    "00080031", // SeriesTime
+    "00080060", // Modality
+    "0008103E", // SeriesDescription
+    "0020000D", // StudyInstanceUID
+    "0020000E", // SeriesInstanceUID
+    "00200011", // SeriesNumber
+];
+
+/// Files of one study, by `SeriesInstanceUID`.
+type Study = BTreeMap<String, Vec<PathBuf>>;
+
+/// Write every study below `--in` as a static DICOMweb tree into
+/// `destination`. Series count as groups; an instance that cannot be read
+/// or decoded counts as one failed frame and is left out of its series.
+pub(super) fn convert_to_dicomweb(
+    shared: &ConvertShared,
+    selection: Option<&Selection>,
+    destination: &Destination,
+) -> Result<Summary> {
+    let files = input_files(shared, selection)?;
+    if files.is_empty() {
+        println!(
+            "{}",
+            t!("no-dcm-files", path = shared.input.display().to_string())
+        );
+        return Ok(Summary::default());
+    }
+    println!("{}", t!("convert-found-files", count = files.len()));
+
+    let mut summary = Summary::default();
+    let studies = group_by_uid(files, &mut summary);
+    summary.groups = studies.values().map(BTreeMap::len).sum();
+
+    let studies_dir = destination.root.join("studies");
+    if destination.archive.is_none()
+        && studies_dir.exists()
+        && !is_folder_empty(&studies_dir).unwrap_or(true)
+    {
+        let clean = shared.force || prompt_to_cleanup(&studies_dir)?.should_clean();
+        clean_output(&studies_dir, clean)?;
+    }
+    let mut study_list = Vec::with_capacity(studies.len());
+    let mut done = 0;
+    for (study_uid, series) in &studies {
+        let study_dir = studies_dir.join(sanitize_filename(study_uid));
+        let mut series_list = Vec::with_capacity(series.len());
+        let mut metadata = Vec::new();
+        for (series_uid, files) in series {
+            if shared.token.ended().is_some() {
+                break;
+            }
+            let series_dir = study_dir.join("series").join(sanitize_filename(series_uid));
+            events::stage(
+                &series_dir,
+                Stage::Started {
+                    key: series_uid.clone(),
+                    files: files.len(),
+                },
+            );
+            let instances = write_series(shared, destination, &series_dir, files, &mut summary);
+            if instances.is_empty() {
+                summary.groups_failed += 1;
+                continue;
+            }
+            destination.write(
+                &series_dir.join("metadata").join("index.json"),
+                &serde_json::to_vec(&instances)?,
+            )?;
+            series_list.push(series_entry(&instances));
+            metadata.extend(instances);
+            done += 1;
+            events::stage(&series_dir, Stage::Finished);
+        }
+        if !series_list.is_empty() {
+            destination.write(
+                &study_dir.join("series").join("index.json"),
+                &serde_json::to_vec(&series_list)?,
+            )?;
+            study_list.push(study_entry(&metadata, series_list.len()));
+        }
+    }
+    destination.write(
+        &studies_dir.join("index.json"),
+        &serde_json::to_vec(&study_list)?,
+    )?;
+
+    summary.interrupted = shared.token.ended().is_some();
+    if summary.interrupted {
+        println!(
+            "{}",
+            t!("convert-interrupted", count = done, total = summary.groups)
+        );
+    } else {
+        println!(
+            "{}",
+            t!(
+                "convert-dicomweb-saved",
+                studies = study_list.len(),
+                series = done,
+                path = studies_dir.display().to_string()
+            )
+        );
+    }
+    Ok(summary)
+}
+
+/// Files by study and series UID, read from their headers. Files without
+/// both UIDs are reported and counted as failed.
+fn group_by_uid(files: Vec<PathBuf>, summary: &mut Summary) -> BTreeMap<String, Study> {
+    let mut studies: BTreeMap<String, Study> = BTreeMap::new();
+    for path in files {
+        let Some((study, series)) = read_uids(&path) else {
+            eprintln!(
+                "{}",
+                t!("convert-dicomweb-no-uid", file = path.display().to_string())
+            );
+            summary.frames_failed += 1;
+            continue;
+        };
+        studies
+            .entry(study)
+            .or_default()
+            .entry(series)
+            .or_default()
+            .push(path);
+    }
+    studies
+}
+
+/// `StudyInstanceUID` and `SeriesInstanceUID` of a file, reading its header
+/// only.
+fn read_uids(path: &Path) -> Option<(String, String)> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()?;
+    let uid = |tag| {
+        obj.element(tag)
+            .ok()?
+            .to_str()
+            .ok()
+            .map(|uid| uid.trim_end_matches(['\0', ' ']).to_string())
+            .filter(|uid| !uid.is_empty())
+    };
+    Some((
+        uid(tags::STUDY_INSTANCE_UID)?,
+        uid(tags::SERIES_INSTANCE_UID)?,
+    ))
+}
+
+/// Write the frames of a series' instances, in slice order, and return
+/// their metadata.
+fn write_series(
+    shared: &ConvertShared,
+    destination: &Destination,
+    series_dir: &Path,
+    files: &[PathBuf],
+    summary: &mut Summary,
+) -> Vec<Value> {
+    let cancel = shared.cancel();
+    let mut instances = Vec::with_capacity(files.len());
+    for path in sort_files_by_position(files) {
+        events::emit(Event::FileStarted { path: path.clone() });
+        let written = cancel
+            .check()
+            .map_err(anyhow::Error::from)
+            .and_then(|()| write_instance(destination, series_dir, &path));
+        match written {
+            Ok((metadata, frames)) => {
+                events::emit(Event::FileFinished { path, frames });
+                instances.push(metadata);
+                summary.frames += frames;
+            }
+            Err(e) => {
+                events::emit(Event::FileFailed {
+                    path: path.clone(),
+                    error: format!("{e:#}"),
+                });
+                summary.frames_failed += 1;
+            }
+        }
+    }
+    instances
+}
+
+/// Write each frame of the instance at `path` below its
+/// `instances/<sop>/frames` folder and return its metadata and frame count.
+fn write_instance(
+    destination: &Destination,
+    series_dir: &Path,
+    path: &Path,
+) -> Result<(Value, usize)> {
+    throttle::before_read(path);
+    let mut obj = open_file(path)
+        .with_context(|| format!("Failed to open DICOM file: {}", path.display()))?;
+    let sop = sop_instance_uid(&obj).context("Missing SOPInstanceUID")?;
+    let frames_dir = series_dir
+        .join("instances")
+        .join(sanitize_filename(&sop))
+        .join("frames");
+
+    let count = number_of_frames(&obj);
+    pixel::check_codec(&obj).with_context(|| format!("Failed to decode: {}", path.display()))?;
+    let mut stored = None;
+    for frame in 0..count {
+        let pixels = obj.decode_pixel_data_frame(frame).with_context(|| {
+            format!(
+                "Failed to decode frame {} of {} from: {}",
+                frame + 1,
+                count,
+                path.display()
+            )
+        })?;
+        let data = pixels.frame_data(0)?;
+        destination.write(&frames_dir.join((frame + 1).to_string()), data)?;
+        stored = Some(StoredFrames {
+            photometric: pixels.photometric_interpretation().as_str().to_string(),
+            samples: pixels.samples_per_pixel(),
+            planar: pixels.planar_configuration(),
+        });
+    }
+
+    obj.remove_element(tags::PIXEL_DATA);
+    let mut metadata = dicom_json::to_value(&*obj)?;
+    if let Some(stored) = stored {
+        stored.describe(&mut metadata);
+    }
+    Ok((metadata, count as usize))
+}
+
+/// How the decoded frames of an instance are laid out.
+struct StoredFrames {
+    photometric: String,
+    samples: u16,
+    planar: PlanarConfiguration,
+}
+
+impl StoredFrames {
+    /// Make instance `metadata` describe the stored frames instead of the
+    /// encoded pixel data: decoders may convert color (e.g. YBR to RGB).
+    fn describe(&self, metadata: &mut Value) {
+        let Value::Object(attributes) = metadata else {
+            return;
+        };
+        attributes.insert(
+            "00020010".into(),
+            json!({ "vr": "UI", "Value": [NATIVE_SYNTAX] }),
+        );
+        attributes.insert(
+            "00280004".into(),
+            json!({ "vr": "CS", "Value": [self.photometric] }),
+        );
+        if self.samples > 1 {
+            attributes.insert(
+                "00280006".into(),
+                json!({ "vr": "US", "Value": [self.planar as u16] }),
+            );
+        }
+    }
+}
+
+/// Series list entry for the metadata of its `instances`.
+fn series_entry(instances: &[Value]) -> Value {
+    let mut entry = pick(&instances[0], &SERIES_ATTRIBUTES);
+    entry.insert(
+        "00201209".into(), // NumberOfSeriesRelatedInstances
+        json!({ "vr": "IS", "Value": [instances.len()] }),
+    );
+    Value::Object(entry)
+}
+
+/// Study list entry for the metadata of all its instances.
+fn study_entry(instances: &[Value], series: usize) -> Value {
+    let mut entry = pick(&instances[0], &STUDY_ATTRIBUTES);
+    let mut modalities: Vec<&Value> = instances
+        .iter()
+        .filter_map(|instance| instance.pointer("/00080060/Value/0"))
+        .collect();
+    modalities.sort_by_key(|modality| modality.as_str());
+    modalities.dedup();
+    entry.insert(
+        "00080061".into(), // ModalitiesInStudy
+        json!({ "vr": "CS", "Value": modalities }),
+    );
+    entry.insert(
+        "00201206".into(), // NumberOfStudyRelatedSeries
+        json!({ "vr": "IS", "Value": [series] }),
+    );
+    entry.insert(
+        "00201208".into(), // NumberOfStudyRelatedInstances
+        json!({ "vr": "IS", "Value": [instances.len()] }),
+    );
+    Value::Object(entry)
+}
+
+/// The `attributes` of an instance's metadata that it has.
+fn pick(metadata: &Value, attributes: &[&str]) -> Map<String, Value> {
+    attributes
+        .iter()
+        .filter_map(|&tag| Some((tag.to_string(), metadata.get(tag)?.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(sop: &str, modality: &str) -> Value {
+        json!({
+            "00080018": { "vr": "UI", "Value": [sop] },
+            "00080060": { "vr": "CS", "Value": [modality] },
+            "0008103E": { "vr": "LO", "Value": ["AX T2"] },
+            "00100010": { "vr": "PN", "Value": [{ "Alphabetic": "Doe^Jane" }] },
+            "0020000D": { "vr": "UI", "Value": ["1.2.3"] },
+            "0020000E": { "vr": "UI", "Value": ["1.2.3.4"] },
+            "00280004": { "vr": "CS", "Value": ["YBR_FULL_422"] },
+        })
+    }
+
+    #[test]
+    fn series_entry_counts_its_instances() {
+        let entry = series_entry(&[instance("1", "MR"), instance("2", "MR")]);
+        assert_eq!(entry["0020000E"]["Value"][0], "1.2.3.4");
+        assert_eq!(entry["0008103E"]["Value"][0], "AX T2");
+        assert_eq!(entry["00201209"], json!({ "vr": "IS", "Value": [2] }));
+        assert!(entry.get("00100010").is_none());
+        assert!(entry.get("00080018").is_none());
+    }
+
+    #[test]
+    fn study_entry_lists_each_modality_once() {
+        let instances = [
+            instance("1", "MR"),
+            instance("2", "SR"),
+            instance("3", "MR"),
+        ];
+        let entry = study_entry(&instances, 2);
+        assert_eq!(
+            entry["00080061"],
+            json!({ "vr": "CS", "Value": ["MR", "SR"] })
+        );
+        assert_eq!(entry["00201206"]["Value"][0], 2);
+        assert_eq!(entry["00201208"]["Value"][0], 3);
+        assert_eq!(entry["00100010"]["Value"][0]["Alphabetic"], "Doe^Jane");
+        assert!(entry.get("00080060").is_none());
+    }
+
+    #[test]
+    fn metadata_describes_the_decoded_frames() {
+        let mut metadata = instance("1", "US");
+        StoredFrames {
+            photometric: "RGB".into(),
+            samples: 3,
+            planar: PlanarConfiguration::Standard,
+        }
+        .describe(&mut metadata);
+        assert_eq!(metadata["00020010"]["Value"][0], NATIVE_SYNTAX);
+        assert_eq!(metadata["00280004"]["Value"][0], "RGB");
+        assert_eq!(metadata["00280006"], json!({ "vr": "US", "Value": [0] }));
+
+        let mut metadata = instance("1", "CT");
+        StoredFrames {
+            photometric: "MONOCHROME2".into(),
+            samples: 1,
+            planar: PlanarConfiguration::Standard,
+        }
+        .describe(&mut metadata);
+        assert!(metadata.get("00280006").is_none());
+    }
+}
@@ -1,56 +1,224 @@
 //! DICOM to JPEG image conversion.
 
+mod dated;
+mod scout;
+
+use std::fs;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use dicom::object::open_file;
-use dicom_pixeldata::PixelDecoder;
-use image::ImageFormat;
-
-pub(super) fn convert_to_jpgs(dcm_files: &[PathBuf], output_dir: &Path) {
-    let total = dcm_files.len();
-    let padding = total.to_string().len().max(4); // At least 4 digits
-
-    for (idx, dcm_path) in dcm_files.iter().enumerate() {
-        match convert_dcm_to_jpg(dcm_path, output_dir, idx + 1, padding) {
-            Ok(output_path) => println!(
-                "✓ Converted: {} -> {}",
-                dcm_path.file_name().unwrap().display(),
-                output_path.file_name().unwrap().display()
-            ),
-            Err(e) => eprintln!(
-                "✗ Failed to convert {}: {}",
-                dcm_path.file_name().unwrap().display(),
-                e
-            ),
+use image::DynamicImage;
+
+use super::ImageFormat;
+use super::archive::Archive;
+use super::session::Acquired;
+use crate::error::ToolboxError;
+use crate::i18n::t;
+use crate::meta::{InstanceMeta, SeriesMeta};
+use crate::pipeline::{self, FrameSink, RenderOptions, RunStats, display_name};
+
+use scout::ScoutSink;
+pub use scout::{Scout, find as find_scouts};
+
+/// Writes sequentially-numbered JPG (or PNG) files into a series folder.
+pub struct JpegSink<'a> {
+    output_dir: &'a Path,
+    padding: usize,
+    format: ImageFormat,
+    /// Archive taking the files instead of the folder (`--out *.zip`).
+    archive: Option<&'a Archive>,
+    /// Date each image by its source's acquisition (`--acquisition-times`).
+    dated: bool,
+}
+
+impl<'a> JpegSink<'a> {
+    /// Sink for `total` frames; names are zero-padded to at least 4 digits.
+    pub fn new(output_dir: &'a Path, total: usize, format: ImageFormat) -> Self {
+        Self {
+            output_dir,
+            padding: total.to_string().len().max(4),
+            format,
+            archive: None,
+            dated: false,
+        }
+    }
+
+    /// Embed each source's acquisition time and use it as the file time.
+    const fn dated(self, dated: bool) -> Self {
+        Self { dated, ..self }
+    }
+
+    /// Stream the files into `archive`, if given, instead of the folder.
+    const fn streaming_to(self, archive: Option<&'a Archive>) -> Self {
+        Self { archive, ..self }
+    }
+
+    /// ffmpeg input pattern matching the written files, e.g. `%04d.jpg`.
+    pub fn pattern(&self) -> PathBuf {
+        self.output_dir
+            .join(format!("%0{}d.{}", self.padding, self.format.extension()))
+    }
+
+    /// Path of the file for frame `index`, with `suffix` after its number.
+    fn path(&self, index: usize, suffix: &str) -> PathBuf {
+        let padding = self.padding;
+        let number = index + 1;
+        let extension = self.format.extension();
+        self.output_dir
+            .join(format!("{number:0padding$}{suffix}.{extension}"))
+    }
+
+    /// Save `image`, rendered from `source`, as the file for frame `index`
+    /// with `suffix`, and return its path.
+    fn save(
+        &self,
+        index: usize,
+        suffix: &str,
+        source: &Path,
+        image: &DynamicImage,
+    ) -> Result<PathBuf> {
+        let widened = self.widened(image);
+        let image = widened.as_ref().unwrap_or(image);
+        let acquired = if self.dated {
+            Acquired::read_file(source)
+        } else {
+            None
+        };
+        match acquired {
+            Some(acquired) => self.save_dated(index, suffix, image, &acquired),
+            None => self.save_plain(index, suffix, image),
+        }
+    }
+
+    /// 8-bit gray `image` as 16-bit for `png16`, so that every file of the
+    /// run has the same depth; `None` to save it as it is.
+    fn widened(&self, image: &DynamicImage) -> Option<DynamicImage> {
+        match image {
+            DynamicImage::ImageLuma8(_) if self.format.is_deep() => {
+                Some(DynamicImage::ImageLuma16(image.to_luma16()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Save `image` as it is encoded.
+    fn save_plain(&self, index: usize, suffix: &str, image: &DynamicImage) -> Result<PathBuf> {
+        let path = self.path(index, suffix);
+        let encoder = |e| ToolboxError::encoder(&path, &e);
+        match self.archive {
+            None => image
+                .save_with_format(&path, self.format.encoding())
+                .map_err(encoder)?,
+            Some(archive) => {
+                let mut bytes = Cursor::new(Vec::new());
+                image
+                    .write_to(&mut bytes, self.format.encoding())
+                    .map_err(encoder)?;
+                archive
+                    .add(&path, bytes.get_ref())
+                    .with_context(|| format!("Failed to save image: {}", path.display()))?;
+            }
+        }
+        Ok(path)
+    }
+
+    /// Save `image` with `acquired` embedded, dating the file on disk.
+    fn save_dated(
+        &self,
+        index: usize,
+        suffix: &str,
+        image: &DynamicImage,
+        acquired: &Acquired,
+    ) -> Result<PathBuf> {
+        let path = self.path(index, suffix);
+        let mut bytes = Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, self.format.encoding())
+            .map_err(|e| ToolboxError::encoder(&path, &e))?;
+        let bytes = dated::embed(bytes.into_inner(), self.format, acquired);
+        let saved = match self.archive {
+            Some(archive) => archive.add(&path, &bytes),
+            None => fs::write(&path, bytes).map_err(anyhow::Error::from),
+        };
+        saved.with_context(|| format!("Failed to save image: {}", path.display()))?;
+        if self.archive.is_none() {
+            dated::set_modified(&path, acquired)?;
         }
+        Ok(path)
     }
 }
 
-fn convert_dcm_to_jpg(
-    dcm_path: &PathBuf,
-    output_dir: &Path,
-    index: usize,
-    padding: usize,
-) -> Result<PathBuf> {
-    let dicom_obj =
-        open_file(dcm_path).with_context(|| format!("Failed to open DICOM file: {}", dcm_path.display()))?;
+impl FrameSink for JpegSink<'_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        let output_path = self.save(index, "", source, &image)?;
 
-    let pixel_data = dicom_obj
-        .decode_pixel_data()
-        .with_context(|| format!("Failed to decode pixel data from: {}", dcm_path.display()))?;
+        println!(
+            "{}",
+            t!(
+                "convert-converted",
+                source = display_name(source),
+                output = display_name(&output_path)
+            )
+        );
+        Ok(())
+    }
+}
 
-    let dynamic_image = pixel_data
-        .to_dynamic_image(0)
-        .with_context(|| format!("Failed to convert to image: {}", dcm_path.display()))?;
+pub(super) fn convert_to_jpgs(
+    series: &SeriesMeta,
+    output_dir: &Path,
+    format: ImageFormat,
+    options: RenderOptions<'_>,
+    scouts: Option<&[Scout]>,
+    archive: Option<&Archive>,
+    acquisition_times: bool,
+) -> RunStats {
+    warn_high_bit_depth(series.first(), format);
+    let dcm_files = &series.files();
+    let total = series.frames();
+    let mut sink = JpegSink::new(output_dir, total, format)
+        .streaming_to(archive)
+        .dated(acquisition_times);
+    let stats = if let Some(scouts) = scouts {
+        let mut sink = ScoutSink::new(&mut sink, scouts);
+        let stats = pipeline::run(dcm_files, options, &mut sink);
+        if sink.saved > 0 {
+            println!("{}", t!("convert-scout-lines-saved", count = sink.saved));
+        }
+        stats
+    } else {
+        pipeline::run(dcm_files, options, &mut sink)
+    };
 
-    let output_path = output_dir.join(format!("{index:0padding$}.jpg"));
+    if stats.failed > 0 {
+        eprintln!(
+            "{}",
+            t!(
+                "convert-frames-failed",
+                failed = stats.failed,
+                total = total
+            )
+        );
+    }
 
-    dynamic_image
-        .save_with_format(&output_path, ImageFormat::Jpeg)
-        .with_context(|| format!("Failed to save JPG: {}", output_path.display()))?;
+    stats
+}
 
-    Ok(output_path)
+/// Warn when 8-bit images of the group would merge gray levels: its first
+/// file stores more than 8 bits per sample and sets no window, so each
+/// slice's full range is squeezed into 256 levels.
+fn warn_high_bit_depth(first: Option<&InstanceMeta>, format: ImageFormat) {
+    if format.is_deep() {
+        return;
+    }
+    if let Some(first) = first
+        && let Some(depth) = first.bit_depth
+        && depth.exceeds_8_bits()
+        && first.window.is_none()
+    {
+        eprintln!("{}", t!("convert-high-bit-depth", bits = depth.stored));
+    }
 }
 
 #[cfg(test)]
@@ -71,10 +239,7 @@ mod tests {
 
         for (index, padding, expected) in test_cases {
             let filename = format!("{index:0padding$}.jpg");
-            assert_eq!(
-                filename, expected,
-                "Index {index} with padding {padding}"
-            );
+            assert_eq!(filename, expected, "Index {index} with padding {padding}");
         }
     }
 
@@ -97,6 +262,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn pattern_matches_written_files() {
+        use super::*;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = JpegSink::new(dir.path(), 12_345, ImageFormat::Png);
+        sink.write_frame(0, Path::new("a.dcm"), DynamicImage::new_luma8(2, 2))
+            .unwrap();
+
+        assert_eq!(sink.pattern(), dir.path().join("%05d.png"));
+        assert!(dir.path().join("00001.png").exists());
+    }
+
     #[test]
     fn padding_is_always_at_least_4_digits() {
         for count in [1, 2, 5, 9, 10, 50, 99, 100, 500, 999] {
@@ -120,7 +298,7 @@ mod tests {
 
     #[test]
     fn filename_format_with_various_indices() {
-        // Verify the exact format used in convert_dcm_to_jpg
+        // Verify the exact format used in JpegSink::write_frame
         let padding = 4;
         let test_cases = [(1, "0001"), (10, "0010"), (100, "0100"), (1000, "1000")];
 
@@ -137,7 +315,7 @@ mod tests {
     fn index_starts_at_one_not_zero() {
         // First file should be 0001.jpg, not 0000.jpg
         let idx = 0;
-        let index = idx + 1; // This is how it's done in convert_to_jpgs
+        let index = idx + 1; // This is how it's done in JpegSink::write_frame
         let padding = 4;
         let filename = format!("{index:0padding$}.jpg");
         assert_eq!(filename, "0001.jpg");
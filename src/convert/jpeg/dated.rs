@@ -0,0 +1,209 @@
+//! Acquisition times on exported images (`jpeg --acquisition-times`).
+//!
+//! File managers sort photos by their embedded capture date or, failing
+//! that, by modification time. JPEGs get an EXIF block with `DateTime` and
+//! `DateTimeOriginal`, PNGs a `Creation Time` text chunk, and files written
+//! to disk take the acquisition time as their modification time.
+
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::super::ImageFormat;
+use super::super::session::Acquired;
+
+/// JPEG start-of-image marker.
+const SOI: [u8; 2] = [0xFF, 0xD8];
+
+/// JPEG APP0 (JFIF) marker, which must stay first after the SOI.
+const APP0: [u8; 2] = [0xFF, 0xE0];
+
+/// PNG signature plus the IHDR chunk, which must come first.
+const PNG_HEADER_LEN: usize = 8 + 25;
+
+/// EXIF/TIFF tags written.
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+/// TIFF field types.
+const ASCII: u16 = 2;
+const LONG: u16 = 4;
+
+/// Add the acquisition time to encoded image `bytes`. Images whose date is
+/// unknown, or that do not look like the expected format, are unchanged.
+pub(super) fn embed(bytes: Vec<u8>, format: ImageFormat, acquired: &Acquired) -> Vec<u8> {
+    let Some((date, time, offset)) = acquired.parts() else {
+        return bytes;
+    };
+    match format {
+        ImageFormat::Jpeg => with_exif(bytes, &format!("{} {time}", date.replace('-', ":"))),
+        ImageFormat::Png | ImageFormat::Png16 => {
+            let value = format!("{date}T{time}{}", offset.unwrap_or_default());
+            with_text_chunk(bytes, "Creation Time", &value)
+        }
+    }
+}
+
+/// Set the modification time of the file at `path` to the acquisition.
+pub(super) fn set_modified(path: &Path, acquired: &Acquired) -> Result<()> {
+    let Some(time) = acquired.system_time() else {
+        return Ok(());
+    };
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(time))
+        .with_context(|| format!("Failed to set the file time: {}", path.display()))
+}
+
+/// Insert an EXIF APP1 segment holding `date_time` (`YYYY:MM:DD HH:MM:SS`)
+/// after the SOI and JFIF segments.
+fn with_exif(bytes: Vec<u8>, date_time: &str) -> Vec<u8> {
+    if !bytes.starts_with(&SOI) {
+        return bytes;
+    }
+    let mut at = SOI.len();
+    if bytes[at..].starts_with(&APP0) && bytes.len() >= at + 4 {
+        at += 2 + usize::from(u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]));
+    }
+    if at > bytes.len() {
+        return bytes;
+    }
+
+    let tiff = exif_tiff(date_time);
+    let length = u16::try_from(2 + 6 + tiff.len()).expect("EXIF block is small");
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(&tiff);
+
+    let mut dated = Vec::with_capacity(bytes.len() + segment.len());
+    dated.extend_from_slice(&bytes[..at]);
+    dated.extend_from_slice(&segment);
+    dated.extend_from_slice(&bytes[at..]);
+    dated
+}
+
+/// Little-endian TIFF structure: IFD0 with `DateTime` and a pointer to an
+/// EXIF IFD with `DateTimeOriginal`, both pointing at one string.
+fn exif_tiff(date_time: &str) -> Vec<u8> {
+    const IFD0: u32 = 8;
+    const EXIF_IFD: u32 = IFD0 + 2 + 2 * 12 + 4;
+    const STRING: u32 = EXIF_IFD + 2 + 12 + 4;
+
+    let mut value = date_time.as_bytes().to_vec();
+    value.push(0);
+    let count = u32::try_from(value.len()).expect("date is short");
+
+    let mut tiff = Vec::with_capacity(STRING as usize + value.len());
+    tiff.extend_from_slice(b"II*\0");
+    tiff.extend_from_slice(&IFD0.to_le_bytes());
+    tiff.extend_from_slice(&2u16.to_le_bytes());
+    push_entry(&mut tiff, TAG_DATE_TIME, ASCII, count, STRING);
+    push_entry(&mut tiff, TAG_EXIF_IFD, LONG, 1, EXIF_IFD);
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    push_entry(&mut tiff, TAG_DATE_TIME_ORIGINAL, ASCII, count, STRING);
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(&value);
+    tiff
+}
+
+/// Append one 12-byte IFD entry.
+fn push_entry(tiff: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: u32) {
+    tiff.extend_from_slice(&tag.to_le_bytes());
+    tiff.extend_from_slice(&kind.to_le_bytes());
+    tiff.extend_from_slice(&count.to_le_bytes());
+    tiff.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Insert a `tEXt` chunk after the PNG header.
+fn with_text_chunk(bytes: Vec<u8>, keyword: &str, text: &str) -> Vec<u8> {
+    if bytes.len() < PNG_HEADER_LEN || &bytes[12..16] != b"IHDR" {
+        return bytes;
+    }
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    data.extend_from_slice(text.as_bytes());
+    let mut crc = flate2::Crc::new();
+    crc.update(b"tEXt");
+    crc.update(&data);
+
+    let length = u32::try_from(data.len()).expect("text chunk is small");
+    let mut dated = Vec::with_capacity(bytes.len() + data.len() + 12);
+    dated.extend_from_slice(&bytes[..PNG_HEADER_LEN]);
+    dated.extend_from_slice(&length.to_be_bytes());
+    dated.extend_from_slice(b"tEXt");
+    dated.extend_from_slice(&data);
+    dated.extend_from_slice(&crc.sum().to_be_bytes());
+    dated.extend_from_slice(&bytes[PNG_HEADER_LEN..]);
+    dated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, GrayImage};
+
+    use super::*;
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, image::Luma([90])));
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format.encoding()).unwrap();
+        bytes.into_inner()
+    }
+
+    fn acquired() -> Acquired {
+        let mut obj = dicom::object::InMemDicomObject::new_empty();
+        obj.put(dicom::core::DataElement::new(
+            dicom::dictionary_std::tags::ACQUISITION_DATE_TIME,
+            dicom::core::VR::DT,
+            dicom::core::PrimitiveValue::from("20240105143000+0100"),
+        ));
+        let meta = dicom::object::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        Acquired::read(&obj.with_exact_meta(meta)).unwrap()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn jpeg_gets_exif_dates_and_still_decodes() {
+        let dated = embed(encoded(ImageFormat::Jpeg), ImageFormat::Jpeg, &acquired());
+        assert!(contains(&dated, b"Exif\0\0II*\0"));
+        assert!(contains(&dated, b"2024:01:05 14:30:00\0"));
+        image::load_from_memory(&dated).unwrap();
+    }
+
+    #[test]
+    fn png_gets_a_creation_time_and_still_decodes() {
+        let dated = embed(encoded(ImageFormat::Png), ImageFormat::Png, &acquired());
+        assert!(contains(
+            &dated,
+            b"tEXtCreation Time\x002024-01-05T14:30:00+01:00"
+        ));
+        image::load_from_memory(&dated).unwrap();
+    }
+
+    #[test]
+    fn file_time_is_the_acquisition() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("0001.jpg");
+        std::fs::write(&path, encoded(ImageFormat::Jpeg)).unwrap();
+        set_modified(&path, &acquired()).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(Some(modified), acquired().system_time());
+    }
+}
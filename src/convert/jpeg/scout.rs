@@ -0,0 +1,346 @@
+//! Localizer cross-reference images (`jpeg --scout-lines`).
+//!
+//! Localizers (scouts) are found among all input files by the `LOCALIZER`
+//! value of `ImageType`. For every exported slice the scout sharing its
+//! `FrameOfReferenceUID` that lies most nearly across it is drawn with the
+//! line where the slice's plane cuts it, and saved next to the slice as
+//! `0001_scout.jpg`. Both planes come from `ImagePositionPatient`,
+//! `ImageOrientationPatient`, and `PixelSpacing`, so oblique slices and
+//! scouts are handled.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, InMemDicomObject, OpenFileOptions};
+use image::{DynamicImage, Rgb, RgbImage};
+
+use super::JpegSink;
+use crate::annotate::Canvas;
+use crate::pipeline::{self, FrameSink, RenderOptions};
+
+/// Color of the cut line.
+const LINE: Rgb<u8> = Rgb([255, 255, 0]);
+
+/// Planes closer to parallel than this (|cos| of the angle between their
+/// normals) do not cut each other usefully.
+const MAX_COS: f64 = 0.9;
+
+/// Position and size of an image plane in patient coordinates (mm).
+#[derive(Debug, Clone, PartialEq)]
+struct Plane {
+    /// Center of the first pixel (`ImagePositionPatient`).
+    origin: [f64; 3],
+    /// Direction along a row, toward higher columns.
+    row: [f64; 3],
+    /// Direction down a column, toward higher rows.
+    column: [f64; 3],
+    /// Distance between rows and between columns (`PixelSpacing`).
+    spacing: [f64; 2],
+    /// Columns and rows.
+    size: [u32; 2],
+    frame_of_reference: String,
+}
+
+impl Plane {
+    /// Geometry from a header; `None` when any of it is missing.
+    fn read(obj: &InMemDicomObject) -> Option<Self> {
+        let numbers = |tag: Tag| -> Option<Vec<f64>> {
+            let text = obj.element(tag).ok()?.to_str().ok()?;
+            text.split('\\').map(|v| v.trim().parse().ok()).collect()
+        };
+        let int = |tag: Tag| obj.element(tag).ok()?.to_int::<u32>().ok();
+        let origin = numbers(tags::IMAGE_POSITION_PATIENT)?;
+        let orientation = numbers(tags::IMAGE_ORIENTATION_PATIENT)?;
+        let spacing = numbers(tags::PIXEL_SPACING)?;
+        if origin.len() < 3 || orientation.len() < 6 || spacing.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            origin: [origin[0], origin[1], origin[2]],
+            row: [orientation[0], orientation[1], orientation[2]],
+            column: [orientation[3], orientation[4], orientation[5]],
+            spacing: [spacing[0], spacing[1]],
+            size: [int(tags::COLUMNS)?, int(tags::ROWS)?],
+            frame_of_reference: obj
+                .element(tags::FRAME_OF_REFERENCE_UID)
+                .ok()?
+                .to_str()
+                .ok()?
+                .trim_end_matches(['\0', ' '])
+                .to_string(),
+        })
+    }
+
+    fn normal(&self) -> [f64; 3] {
+        let (row, column) = (self.row, self.column);
+        std::array::from_fn(|i| {
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+            row[j].mul_add(column[k], -(row[k] * column[j]))
+        })
+    }
+
+    /// |cos| of the angle between the two planes' normals.
+    fn alignment(&self, other: &Self) -> f64 {
+        dot(self.normal(), other.normal()).abs()
+    }
+
+    /// End points, in this plane's pixels as `(column, row)`, of the line
+    /// where `other` cuts it; `None` when the line misses the image or the
+    /// planes are parallel.
+    fn cut_line(&self, other: &Self) -> Option<((f64, f64), (f64, f64))> {
+        // Signed distance from `other` of the pixel at (i, j) is a·i + b·j + c
+        let normal = other.normal();
+        let a = dot(normal, self.row) * self.spacing[1];
+        let b = dot(normal, self.column) * self.spacing[0];
+        let c = dot(normal, sub(self.origin, other.origin));
+        let (right, bottom) = (
+            f64::from(self.size[0].saturating_sub(1)),
+            f64::from(self.size[1].saturating_sub(1)),
+        );
+
+        let mut ends: Vec<(f64, f64)> = Vec::new();
+        if b.abs() > f64::EPSILON {
+            for i in [0.0, right] {
+                ends.push((i, -a.mul_add(i, c) / b));
+            }
+        }
+        if a.abs() > f64::EPSILON {
+            for j in [0.0, bottom] {
+                ends.push((-b.mul_add(j, c) / a, j));
+            }
+        }
+        let tolerance = 1e-6;
+        ends.retain(|&(i, j)| {
+            (-tolerance..=right + tolerance).contains(&i)
+                && (-tolerance..=bottom + tolerance).contains(&j)
+        });
+
+        // The two crossings farthest apart (corners show up twice)
+        let mut best: Option<((f64, f64), (f64, f64))> = None;
+        let mut longest = -1.0;
+        for (n, &start) in ends.iter().enumerate() {
+            for &end in &ends[n + 1..] {
+                let length = (end.0 - start.0).hypot(end.1 - start.1);
+                if length > longest {
+                    (longest, best) = (length, Some((start, end)));
+                }
+            }
+        }
+        best
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0].mul_add(b[0], a[1].mul_add(b[1], a[2] * b[2]))
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+/// A localizer image and its plane.
+pub struct Scout {
+    plane: Plane,
+    image: RgbImage,
+}
+
+/// Header of a file, without its pixel data.
+fn header(path: &Path) -> Option<DefaultDicomObject> {
+    OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()
+}
+
+/// Whether `ImageType` marks the object as a localizer.
+fn is_localizer(obj: &InMemDicomObject) -> bool {
+    obj.element(tags::IMAGE_TYPE)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .is_some_and(|s| s.split('\\').any(|v| v.trim() == "LOCALIZER"))
+}
+
+/// Load every localizer among `files` that has a full geometry.
+pub fn find(files: &[PathBuf]) -> Vec<Scout> {
+    files
+        .iter()
+        .filter_map(|path| {
+            let obj = header(path).filter(|obj| is_localizer(obj))?;
+            let plane = Plane::read(&obj)?;
+            let frame = pipeline::load_frame(path, 0).ok()?;
+            let image = pipeline::render_frame(frame, RenderOptions::default()).into_rgb8();
+            Some(Scout { plane, image })
+        })
+        .collect()
+}
+
+/// Pick the scout that best crosses `slice`: same frame of reference, and
+/// the normals furthest from parallel.
+fn pick<'a>(scouts: &'a [Scout], slice: &Plane) -> Option<&'a Scout> {
+    scouts
+        .iter()
+        .filter(|scout| scout.plane.frame_of_reference == slice.frame_of_reference)
+        .filter(|scout| scout.plane.alignment(slice) < MAX_COS)
+        .min_by(|a, b| {
+            a.plane
+                .alignment(slice)
+                .total_cmp(&b.plane.alignment(slice))
+        })
+}
+
+/// The scout drawn with the cut line of `slice`, if it crosses the image.
+#[allow(clippy::cast_possible_truncation)]
+fn draw(scout: &Scout, slice: &Plane) -> Option<RgbImage> {
+    let (start, end) = scout.plane.cut_line(slice)?;
+    let pixel = |(i, j): (f64, f64)| (i.round() as i64, j.round() as i64);
+    let mut canvas = Canvas::new(scout.image.clone());
+    canvas.line(pixel(start), pixel(end), LINE);
+    Some(canvas.into_image())
+}
+
+/// Writes each frame through the JPEG sink, then its scout reference image.
+pub(super) struct ScoutSink<'a, 'b> {
+    inner: &'a mut JpegSink<'b>,
+    scouts: &'a [Scout],
+    /// Slice plane of each source file read so far (`None` when it has no
+    /// geometry or is itself a localizer).
+    planes: HashMap<PathBuf, Option<Plane>>,
+    /// Reference images written.
+    pub(super) saved: usize,
+}
+
+impl<'a, 'b> ScoutSink<'a, 'b> {
+    pub(super) fn new(inner: &'a mut JpegSink<'b>, scouts: &'a [Scout]) -> Self {
+        Self {
+            inner,
+            scouts,
+            planes: HashMap::new(),
+            saved: 0,
+        }
+    }
+}
+
+impl FrameSink for ScoutSink<'_, '_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        self.inner.write_frame(index, source, image)?;
+
+        let plane = self.planes.entry(source.to_path_buf()).or_insert_with(|| {
+            header(source)
+                .filter(|obj| !is_localizer(obj))
+                .and_then(|obj| Plane::read(&obj))
+        });
+        let Some(reference) = plane
+            .as_ref()
+            .and_then(|slice| draw(pick(self.scouts, slice)?, slice))
+        else {
+            return Ok(());
+        };
+
+        self.inner
+            .save(index, "_scout", source, &DynamicImage::ImageRgb8(reference))?;
+        self.saved += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 mm plane of `size` pixels at `origin` along `row` and `column`.
+    fn plane(origin: [f64; 3], row: [f64; 3], column: [f64; 3], size: [u32; 2]) -> Plane {
+        Plane {
+            origin,
+            row,
+            column,
+            spacing: [1.0, 1.0],
+            size,
+            frame_of_reference: "1.2.3".to_string(),
+        }
+    }
+
+    /// Coronal scout: columns run along X, rows down Z from Z = 50.
+    fn coronal() -> Plane {
+        plane(
+            [-50.0, 0.0, 50.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, -1.0],
+            [101, 101],
+        )
+    }
+
+    /// Axial slice at height `z`.
+    fn axial(z: f64) -> Plane {
+        plane(
+            [-50.0, -50.0, z],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [101, 101],
+        )
+    }
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-9 && (actual.1 - expected.1).abs() < 1e-9,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn axial_slice_cuts_coronal_scout_across() {
+        // Z = 20 lies 30 rows below the top of the scout
+        let (start, end) = coronal().cut_line(&axial(20.0)).unwrap();
+        assert_close(start, (0.0, 30.0));
+        assert_close(end, (100.0, 30.0));
+        // Slices above or below the scout miss it
+        assert_eq!(coronal().cut_line(&axial(60.0)), None);
+    }
+
+    #[test]
+    fn oblique_slice_cuts_diagonally() {
+        // Plane through the scout's center tilted 45° about Y
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        let slice = plane([0.0, 0.0, 0.0], [s, 0.0, s], [0.0, 1.0, 0.0], [10, 10]);
+        let (start, end) = coronal().cut_line(&slice).unwrap();
+        // X = Z: from the bottom-left corner to the top-right
+        let (left, right) = if start.0 < end.0 {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        assert_close(left, (0.0, 100.0));
+        assert_close(right, (100.0, 0.0));
+    }
+
+    #[test]
+    fn pick_needs_a_crossing_scout_in_the_same_frame() {
+        let scout = |plane: Plane| Scout {
+            plane,
+            image: RgbImage::new(1, 1),
+        };
+        let slice = axial(0.0);
+        // A scout parallel to the slice is no use
+        let parallel = [scout(axial(10.0))];
+        assert!(pick(&parallel, &slice).is_none());
+
+        let mut other_frame = coronal();
+        other_frame.frame_of_reference = "9.9".to_string();
+        let scouts = [scout(other_frame), scout(axial(10.0)), scout(coronal())];
+        let picked = pick(&scouts, &slice).unwrap();
+        assert_eq!(picked.plane, coronal());
+    }
+
+    #[test]
+    fn reference_image_marks_the_cut_row() {
+        let scout = Scout {
+            plane: coronal(),
+            image: RgbImage::new(101, 101),
+        };
+        let image = draw(&scout, &axial(20.0)).unwrap();
+        assert_eq!(*image.get_pixel(50, 30), LINE);
+        assert_eq!(*image.get_pixel(50, 60), Rgb([0, 0, 0]));
+    }
+}
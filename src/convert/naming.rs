@@ -0,0 +1,232 @@
+//! Group folder names from a tag template (`--group-name`).
+//!
+//! A template such as `{SeriesNumber:02}_{SeriesDescription}` is rendered
+//! from the first file of each group: every `{Keyword}` (or `{gggg,eeee}`)
+//! is replaced by that attribute's value, and `:0N` pads numbers with zeros
+//! to `N` digits. `{{` and `}}` stand for literal braces.
+//!
+//! Whatever names the folders, two groups may end up with the same one once
+//! sanitized (`T1/AX` and `T1:AX` both become `T1_AX`); [`FolderNames`]
+//! gives the later group a numeric suffix so they are not merged.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use dicom::core::Tag;
+use dicom::core::dictionary::DataDictionary;
+use dicom::dictionary_std::{StandardDataDictionary, tags};
+use dicom::object::OpenFileOptions;
+
+/// Value used for attributes a file does not have.
+const MISSING: &str = "unknown";
+
+/// A parsed `--group-name` template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupName {
+    parts: Vec<Part>,
+}
+
+/// Literal text or one attribute placeholder of a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Tag { tag: Tag, width: usize },
+}
+
+impl GroupName {
+    /// Parse a template for `--group-name`.
+    pub fn parse(template: &str) -> std::result::Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed `{{` in `{template}`"))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(parse_placeholder(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("unmatched `}}` in `{template}` (write `}}}}`)")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        if !parts.iter().any(|part| matches!(part, Part::Tag { .. })) {
+            return Err(format!(
+                "`{template}` names no attribute; every group would share one folder"
+            ));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The folder name for a group whose first file is `dcm_path`.
+    pub fn render(&self, dcm_path: &Path) -> String {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(dcm_path)
+            .ok();
+        self.render_with(|tag| {
+            obj.as_ref()?
+                .element(tag)
+                .ok()?
+                .to_str()
+                .ok()
+                .map(|value| value.into_owned())
+        })
+    }
+
+    /// Render with `lookup` giving each attribute's raw value.
+    fn render_with(&self, lookup: impl Fn(Tag) -> Option<String>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Tag { tag, width } => {
+                    let value = lookup(*tag).map(|raw| clean_value(&raw));
+                    match value.filter(|value| !value.is_empty()) {
+                        Some(value) => pad_number(value, *width),
+                        None => MISSING.to_string(),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Folder names handed out so far in one output folder.
+///
+/// Names are compared case-insensitively, since Windows and macOS
+/// filesystems would merge `T1` and `t1` anyway.
+#[derive(Debug, Default)]
+pub struct FolderNames {
+    used: HashSet<String>,
+}
+
+impl FolderNames {
+    /// `name` if no earlier group has it, otherwise `name_2`, `name_3`, ...
+    pub fn claim(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut n = 1;
+        while !self.used.insert(candidate.to_lowercase()) {
+            n += 1;
+            candidate = format!("{name}_{n}");
+        }
+        candidate
+    }
+}
+
+/// Parse `Keyword`, `gggg,eeee`, or either followed by `:0N`.
+fn parse_placeholder(spec: &str) -> std::result::Result<Part, String> {
+    let (name, format) = match spec.rsplit_once(':') {
+        Some((name, format)) => (name.trim(), Some(format.trim())),
+        None => (spec.trim(), None),
+    };
+    let tag = StandardDataDictionary
+        .parse_tag(name)
+        .ok_or_else(|| format!("`{{{spec}}}`: unknown DICOM attribute `{name}`"))?;
+    let width = match format {
+        None => 0,
+        Some(format) => format
+            .strip_prefix('0')
+            .and_then(|width| width.parse::<usize>().ok())
+            .filter(|width| (1..=16).contains(width))
+            .ok_or_else(|| format!("`{{{spec}}}`: format must be `:0N` (zero-pad to N digits)"))?,
+    };
+    Ok(Part::Tag { tag, width })
+}
+
+/// Trim padding and join multiple values with `_`.
+fn clean_value(raw: &str) -> String {
+    raw.split('\\')
+        .map(|value| value.trim_matches(['\0', ' ']))
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Zero-pad an integer value to `width` digits; other values are unchanged.
+fn pad_number(value: String, width: usize) -> String {
+    match value.parse::<i64>() {
+        Ok(number) if width > 0 => format!("{number:0width$}"),
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, values: &[(Tag, &str)]) -> String {
+        GroupName::parse(template).unwrap().render_with(|tag| {
+            values
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map(|(_, value)| (*value).to_string())
+        })
+    }
+
+    #[test]
+    fn placeholders_take_tag_values() {
+        let values = [
+            (tags::SERIES_NUMBER, "3 "),
+            (tags::SERIES_DESCRIPTION, "T2W_FLAIR "),
+        ];
+        assert_eq!(
+            render("{SeriesNumber:02}_{SeriesDescription}", &values),
+            "03_T2W_FLAIR"
+        );
+        assert_eq!(render("s{0020,0011:03}", &values), "s003");
+        assert_eq!(render("{{{SeriesNumber}}}", &values), "{3}");
+    }
+
+    #[test]
+    fn missing_and_non_numeric_values() {
+        let values = [
+            (tags::SERIES_NUMBER, "x"),
+            (tags::IMAGE_TYPE, "ORIGINAL\\PRIMARY"),
+        ];
+        assert_eq!(
+            render("{SeriesNumber:02}-{Modality}-{ImageType}", &values),
+            "x-unknown-ORIGINAL_PRIMARY"
+        );
+    }
+
+    #[test]
+    fn colliding_names_get_a_suffix() {
+        let mut names = FolderNames::default();
+        assert_eq!(names.claim("T1_AX"), "T1_AX");
+        assert_eq!(names.claim("T1_AX"), "T1_AX_2");
+        assert_eq!(names.claim("t1_ax"), "t1_ax_3");
+        assert_eq!(names.claim("T1_AX_2"), "T1_AX_2_2");
+        assert_eq!(names.claim("T2"), "T2");
+    }
+
+    #[test]
+    fn bad_templates_are_rejected() {
+        for template in [
+            "{SeriesNumbr}",
+            "{SeriesNumber",
+            "SeriesNumber}",
+            "{SeriesNumber:2}",
+            "series",
+        ] {
+            assert!(GroupName::parse(template).is_err(), "{template}");
+        }
+    }
+}
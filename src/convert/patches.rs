@@ -0,0 +1,162 @@
+//! Dataset patch extraction (`--export-patches`).
+//!
+//! Every annotation box is cropped out of the rendered frame — without the
+//! overlay drawn on it — and saved as `patches/NNNN.png`. `patches/index.csv`
+//! records where each patch came from, so the folder can be used directly as
+//! a training or review dataset.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::annotate::Annotations;
+use crate::pipeline::{self, Frames, RenderOptions, display_name};
+
+/// Patch folder inside each series output folder.
+pub(super) const PATCH_DIR: &str = "patches";
+
+/// CSV index written next to the patches.
+const INDEX_FILE: &str = "index.csv";
+
+/// Columns of the CSV index.
+const INDEX_HEADER: &str = "patch,source,sop_instance_uid,frame,label,x,y,width,height";
+
+/// Crop every annotation box of a group into `<output_dir>/patches/`.
+///
+/// Returns the number of patches written. Files that cannot be opened or
+/// decoded are skipped; the conversion itself already reports them.
+pub(super) fn export(
+    files: &[PathBuf],
+    output_dir: &Path,
+    annotations: &Annotations,
+    options: RenderOptions<'_>,
+) -> Result<usize> {
+    let patch_dir = output_dir.join(PATCH_DIR);
+    let mut index = String::new();
+    let mut count = 0;
+
+    for path in files {
+        let Ok(frames) = Frames::open(path) else {
+            continue;
+        };
+        let Some(uid) = frames.sop_instance_uid() else {
+            continue;
+        };
+
+        let Ok(factor) = options.suv.map(|_| frames.suv_factor()).transpose() else {
+            continue;
+        };
+
+        for (number, frame) in frames.enumerate() {
+            let boxes: Vec<_> = annotations.boxes(&uid, number).collect();
+            if boxes.is_empty() {
+                continue;
+            }
+            let Ok(frame) = frame else {
+                continue;
+            };
+            let frame = match (options.suv, factor) {
+                (Some(display), Some(factor)) => display.apply(frame, factor),
+                _ => frame,
+            };
+            let image = pipeline::render_frame(frame, options.clone());
+
+            for labeled in boxes {
+                let Some((x, y, width, height)) = labeled.clip(image.width(), image.height())
+                else {
+                    continue;
+                };
+                if count == 0 {
+                    fs::create_dir_all(&patch_dir).with_context(|| {
+                        format!("Failed to create patch folder: {}", patch_dir.display())
+                    })?;
+                }
+                count += 1;
+
+                let name = format!("{count:04}.png");
+                let patch_path = patch_dir.join(&name);
+                image
+                    .crop_imm(x, y, width, height)
+                    .save_with_format(&patch_path, image::ImageFormat::Png)
+                    .with_context(|| format!("Failed to save patch: {}", patch_path.display()))?;
+
+                let fields = [
+                    name,
+                    display_name(path),
+                    uid.clone(),
+                    number.to_string(),
+                    labeled.label.unwrap_or_default().to_string(),
+                    x.to_string(),
+                    y.to_string(),
+                    width.to_string(),
+                    height.to_string(),
+                ];
+                index.push_str(&csv_row(&fields));
+                index.push('\n');
+            }
+        }
+    }
+
+    if count > 0 {
+        let index_path = patch_dir.join(INDEX_FILE);
+        fs::write(&index_path, format!("{INDEX_HEADER}\n{index}"))
+            .with_context(|| format!("Failed to write patch index: {}", index_path.display()))?;
+    }
+    Ok(count)
+}
+
+/// Join fields into a CSV line, quoting those that need it (RFC 4180).
+pub(super) fn csv_row(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(fields: &[&str]) -> String {
+        csv_row(&fields.iter().map(ToString::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(
+            row(&["0001.png", "a.dcm", "1.2.3", "0"]),
+            "0001.png,a.dcm,1.2.3,0"
+        );
+    }
+
+    #[test]
+    fn fields_with_separators_are_quoted() {
+        assert_eq!(
+            row(&["nodule, 0.9", "say \"hi\""]),
+            "\"nodule, 0.9\",\"say \"\"hi\"\"\""
+        );
+    }
+
+    #[test]
+    fn header_matches_row_width() {
+        assert_eq!(INDEX_HEADER.split(',').count(), 9);
+    }
+
+    #[test]
+    fn unreadable_files_produce_no_patches() {
+        let dir = tempfile::tempdir().unwrap();
+        let annotations = Annotations::default();
+        let files = vec![dir.path().join("missing.dcm")];
+        let count = export(&files, dir.path(), &annotations, RenderOptions::default()).unwrap();
+        assert_eq!(count, 0);
+        assert!(!dir.path().join(PATCH_DIR).exists());
+    }
+}
@@ -0,0 +1,110 @@
+//! Choosing series by number before converting (`--pick`).
+//!
+//! The detected groups are listed with a number, their description,
+//! modality, and file count, and the answer picks groups by number and
+//! range (`1,3-5`). An empty answer keeps them all. Answers are read from
+//! stdin line by line, so `echo 2 | dcm-toolbox convert --pick ...` picks
+//! without a terminal.
+
+use std::io::{self, Write};
+
+use anyhow::Result;
+
+use crate::i18n::t;
+use crate::meta::SeriesMeta;
+use crate::outcome::BadInput;
+
+/// List `groups` (key and files) and ask which to convert; the
+/// positions of the picked groups, in order.
+pub fn prompt(groups: &[(&str, &SeriesMeta)]) -> Result<Vec<usize>> {
+    for (number, (key, series)) in groups.iter().enumerate() {
+        println!(
+            "  {}",
+            t!(
+                "convert-pick-entry",
+                number = number + 1,
+                key = *key,
+                description = series.description().unwrap_or("-"),
+                modality = series.modality().unwrap_or("-"),
+                count = series.instances.len()
+            )
+        );
+    }
+    println!();
+
+    loop {
+        print!("{}", t!("convert-pick-prompt"));
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            anyhow::bail!(BadInput(
+                "--pick needs an answer on stdin, but stdin was closed".to_string()
+            ));
+        }
+        match parse_picks(&answer, groups.len()) {
+            Ok(picks) => return Ok(picks),
+            Err(error) => println!("{}", t!("convert-pick-invalid", error = error)),
+        }
+    }
+}
+
+/// Parse `1,3-5` into zero-based positions among `count` groups, sorted and
+/// without repeats. Blank picks everything.
+fn parse_picks(answer: &str, count: usize) -> std::result::Result<Vec<usize>, String> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok((0..count).collect());
+    }
+    let number = |text: &str| {
+        let text = text.trim();
+        match text.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => Ok(n),
+            Ok(n) => Err(format!("{n} is not between 1 and {count}")),
+            Err(_) => Err(format!("`{text}` is not a series number")),
+        }
+    };
+    let mut picks = Vec::new();
+    for part in answer
+        .split([',', ' '])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => {
+                let n = number(part)?;
+                (n, n)
+            }
+        };
+        if first > last {
+            return Err(format!("`{part}` runs backwards"));
+        }
+        picks.extend(first - 1..last);
+    }
+    picks.sort_unstable();
+    picks.dedup();
+    Ok(picks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_and_ranges() {
+        assert_eq!(parse_picks("1,3-5\n", 6), Ok(vec![0, 2, 3, 4]));
+        assert_eq!(parse_picks(" 2 1, 2-2 ", 3), Ok(vec![0, 1]));
+    }
+
+    #[test]
+    fn blank_picks_everything() {
+        assert_eq!(parse_picks("\n", 3), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn bad_answers_are_explained() {
+        for answer in ["0", "4", "2-7", "x", "3-1", "1,,b"] {
+            assert!(parse_picks(answer, 3).is_err(), "{answer}");
+        }
+    }
+}
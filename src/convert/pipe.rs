@@ -0,0 +1,148 @@
+//! Single-object piping mode (`--in -` / `--out -`, or `--in <file>`).
+//!
+//! Reads one DICOM object from stdin (or a single file) and writes the first
+//! frame as an encoded image to stdout (or a single file). Nothing but image
+//! bytes is written to stdout, so the command composes with other tools:
+//!
+//! ```bash
+//! cat scan.dcm | dcm-toolbox convert --in - --out - jpeg --image-format png > scan.png
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Write};
+
+use anyhow::{Context, Result};
+use dicom::object::{DefaultDicomObject, open_file};
+
+use super::{ConvertFormat, ConvertShared, ImageFormat, is_stdio, suv};
+use crate::i18n::t;
+use crate::outcome::{BadInput, Summary};
+use crate::pipeline::{self, RenderOptions};
+use crate::pixel;
+
+pub(super) fn run(
+    shared: &ConvertShared,
+    format: &ConvertFormat,
+    options: RenderOptions<'_>,
+) -> Result<Summary> {
+    let ConvertFormat::Jpeg {
+        image_format,
+        scout_lines,
+        acquisition_times,
+    } = format
+    else {
+        anyhow::bail!(BadInput(
+            "`-` for --in/--out is only supported by the jpeg format".to_string()
+        ));
+    };
+
+    if *scout_lines {
+        anyhow::bail!(BadInput(
+            "--scout-lines needs an input folder, not a single object".to_string()
+        ));
+    }
+
+    if *acquisition_times {
+        anyhow::bail!(BadInput(
+            "--acquisition-times needs an input folder, not a single object".to_string()
+        ));
+    }
+
+    if shared.export_patches {
+        anyhow::bail!(BadInput(
+            "--export-patches needs an output folder, not a single image".to_string()
+        ));
+    }
+
+    if shared.output.is_dir() {
+        anyhow::bail!(BadInput(format!(
+            "A single object is written to one image file, but --out is a folder: {}",
+            shared.output.display()
+        )));
+    }
+
+    let obj = load_object(shared)?;
+    let frame = pixel::decode_frame(&obj, 0).context("Failed to decode pixel data")?;
+    let frame = match options.suv {
+        Some(display) => display.apply(frame, suv::factor(&obj)?),
+        None => frame,
+    };
+    let image = pipeline::render_annotated(frame, options, &obj, 0);
+
+    if is_stdio(&shared.output) {
+        let bytes = encode(&image, *image_format)?;
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(&bytes)
+            .and_then(|()| stdout.flush())
+            .context("Failed to write image to stdout")?;
+    } else {
+        let file = File::create(&shared.output).with_context(|| {
+            format!("Failed to create output file: {}", shared.output.display())
+        })?;
+        let mut writer = BufWriter::new(file);
+        image
+            .write_to(&mut writer, image_format.encoding())
+            .with_context(|| format!("Failed to write image: {}", shared.output.display()))?;
+        eprintln!(
+            "{}",
+            t!(
+                "convert-converted-single",
+                output = shared.output.display().to_string()
+            )
+        );
+    }
+
+    Ok(Summary {
+        groups: 1,
+        frames: 1,
+        ..Summary::default()
+    })
+}
+
+/// Read the input object from stdin or from a single file path.
+fn load_object(shared: &ConvertShared) -> Result<DefaultDicomObject> {
+    if is_stdio(&shared.input) {
+        return pipeline::read_object(io::stdin().lock()).map_err(|e| {
+            BadInput(format!("Failed to read DICOM object from stdin: {e:#}")).into()
+        });
+    }
+
+    if shared.input.is_dir() {
+        anyhow::bail!(BadInput(format!(
+            "--out - requires a single DICOM file or `--in -`, got a folder: {}",
+            shared.input.display()
+        )));
+    }
+
+    open_file(&shared.input)
+        .with_context(|| format!("Failed to open DICOM file: {}", shared.input.display()))
+}
+
+/// Encode an image into memory in the requested format.
+fn encode(image: &image::DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), format.encoding())
+        .context("Failed to encode image")?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_png_signature() {
+        let image = image::DynamicImage::new_luma8(4, 4);
+        let bytes = encode(&image, ImageFormat::Png).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn encodes_jpeg_signature() {
+        let image = image::DynamicImage::new_luma8(4, 4);
+        let bytes = encode(&image, ImageFormat::Jpeg).unwrap();
+        assert!(bytes.starts_with(&[0xFF, 0xD8, 0xFF]));
+    }
+}
@@ -0,0 +1,235 @@
+//! DICOM to point cloud conversion module.
+//!
+//! Writes the center of every voxel above a threshold, with its calibrated
+//! value as an `intensity` attribute, for users who mesh or inspect the data
+//! in their own tools. PLY is binary little-endian; XYZ is one
+//! `x y z intensity` text line per point. Points are streamed in voxel order,
+//! so large volumes are never held as a second copy in memory.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use super::stl::MeshCoords;
+use super::suv;
+use crate::i18n::t;
+use crate::mask::{self, otsu_threshold};
+use crate::utils::named_after_folder;
+use crate::volume::Volume;
+
+/// Point cloud file format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PointFormat {
+    /// Binary PLY with an `intensity` property
+    #[default]
+    Ply,
+    /// Plain text, one `x y z intensity` line per point
+    Xyz,
+}
+
+impl PointFormat {
+    /// File extension for this format.
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Ply => "ply",
+            Self::Xyz => "xyz",
+        }
+    }
+}
+
+/// Options for point cloud export.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CloudOptions {
+    /// Keep voxels above this value; Otsu's threshold when `None`.
+    pub threshold: Option<f32>,
+    /// File format.
+    pub format: PointFormat,
+    /// Coordinate system of the points.
+    pub coords: MeshCoords,
+    /// Remove air, table, and noise around the patient first.
+    pub strip_background: bool,
+    /// Scale PET values to SUV before thresholding (`--suv`).
+    pub suv: bool,
+}
+
+/// Convert a group of sorted DICOM files into a point cloud file.
+///
+/// Returns the number of points written.
+pub fn convert_to_point_cloud(
+    dcm_files: &[PathBuf],
+    output_dir: &Path,
+    options: CloudOptions,
+) -> Result<usize> {
+    println!("  {}", t!("stl-building-volume", count = dcm_files.len()));
+    let mut volume = Volume::load(dcm_files)?;
+    if options.suv {
+        suv::scale_volume(&mut volume, dcm_files)?;
+    }
+
+    if options.strip_background {
+        let removed =
+            mask::strip_background_3d(&mut volume.values, volume.cols, volume.rows, volume.slices);
+        println!("  {}", t!("stl-stripped-background", voxels = removed));
+    }
+
+    let threshold = options.threshold.unwrap_or_else(|| {
+        let threshold = otsu_threshold(&volume.values);
+        let text = format!("{threshold:.2}");
+        println!("  {}", t!("pointcloud-otsu-threshold", threshold = text));
+        threshold
+    });
+
+    let count = points(&volume, threshold, options.coords).count();
+    if count == 0 {
+        anyhow::bail!("No voxels above the threshold {threshold:.2}. Try a lower --threshold");
+    }
+
+    let path = named_after_folder(output_dir, options.format.extension());
+    write(&volume, threshold, options, count, &path)?;
+    println!(
+        "{}",
+        t!(
+            "pointcloud-saved",
+            count = count,
+            path = path.display().to_string()
+        )
+    );
+    Ok(count)
+}
+
+/// Centers and values of the voxels above `threshold`, in voxel order.
+fn points(
+    volume: &Volume,
+    threshold: f32,
+    coords: MeshCoords,
+) -> impl Iterator<Item = ([f32; 3], f32)> + '_ {
+    let (cols, rows) = (volume.cols, volume.rows);
+    volume
+        .values
+        .iter()
+        .enumerate()
+        .filter(move |&(_, &value)| value > threshold)
+        .map(move |(index, &value)| {
+            let (x, y, z) = (index % cols, index / cols % rows, index / (cols * rows));
+            (coords.position(volume, x, y, z), value)
+        })
+}
+
+/// Write `count` points in the requested format.
+fn write(
+    volume: &Volume,
+    threshold: f32,
+    options: CloudOptions,
+    count: usize,
+    path: &Path,
+) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create point cloud file: {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    let points = points(volume, threshold, options.coords);
+
+    let result = match options.format {
+        PointFormat::Ply => write_ply(&mut out, points, count),
+        PointFormat::Xyz => write_xyz(&mut out, points),
+    };
+    result
+        .and_then(|()| out.flush())
+        .with_context(|| format!("Failed to write point cloud data: {}", path.display()))
+}
+
+/// Binary little-endian PLY with `x y z intensity` float properties.
+fn write_ply(
+    out: &mut impl Write,
+    points: impl Iterator<Item = ([f32; 3], f32)>,
+    count: usize,
+) -> std::io::Result<()> {
+    write!(
+        out,
+        "ply\nformat binary_little_endian 1.0\ncomment dcm-toolbox\n\
+         element vertex {count}\n\
+         property float x\nproperty float y\nproperty float z\nproperty float intensity\n\
+         end_header\n"
+    )?;
+    for ([x, y, z], value) in points {
+        for field in [x, y, z, value] {
+            out.write_all(&field.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// One `x y z intensity` line per point.
+fn write_xyz(
+    out: &mut impl Write,
+    points: impl Iterator<Item = ([f32; 3], f32)>,
+) -> std::io::Result<()> {
+    for ([x, y, z], value) in points {
+        writeln!(out, "{x} {y} {z} {value}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x2x2 volume with values 0..12 and 2 mm slices at `origin`.
+    fn ramp(origin: [f64; 3]) -> Volume {
+        Volume {
+            values: (0..12u8).map(f32::from).collect(),
+            cols: 3,
+            rows: 2,
+            slices: 2,
+            spacing_x: 1.0,
+            spacing_y: 1.0,
+            spacing_z: 2.0,
+            origin,
+        }
+    }
+
+    fn text(volume: &Volume, threshold: f32, coords: MeshCoords) -> String {
+        let mut out = Vec::new();
+        write_xyz(&mut out, points(volume, threshold, coords)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn keeps_voxels_above_threshold() {
+        let volume = ramp([0.0; 3]);
+        assert_eq!(
+            text(&volume, 9.0, MeshCoords::Voxel),
+            "1 1 2 10\n2 1 2 11\n"
+        );
+        assert_eq!(points(&volume, 11.0, MeshCoords::Voxel).count(), 0);
+    }
+
+    #[test]
+    fn patient_coordinates_add_the_origin() {
+        let volume = ramp([-100.0, 50.0, 10.0]);
+        assert_eq!(text(&volume, 10.0, MeshCoords::Lps), "-98 51 12 11\n");
+        assert_eq!(text(&volume, 10.0, MeshCoords::Ras), "98 -51 12 11\n");
+    }
+
+    #[test]
+    fn ply_header_counts_points() {
+        let volume = ramp([0.0; 3]);
+        let mut out = Vec::new();
+        write_ply(&mut out, points(&volume, 9.0, MeshCoords::Voxel), 2).unwrap();
+
+        let header_end = b"end_header\n";
+        let body = out
+            .windows(header_end.len())
+            .position(|w| w == header_end)
+            .map(|i| i + header_end.len())
+            .unwrap();
+        let header = String::from_utf8_lossy(&out[..body]);
+        assert!(header.contains("format binary_little_endian 1.0"));
+        assert!(header.contains("element vertex 2\n"));
+        assert!(header.contains("property float intensity\n"));
+        assert_eq!(out.len() - body, 2 * 4 * 4);
+        assert_eq!(out[body..body + 4], 1.0f32.to_le_bytes());
+    }
+}
@@ -0,0 +1,537 @@
+//! DICOM → printable film sheets (`print-layout`), DICOM Basic Print emulated.
+//!
+//! A film printer took an image display format such as `STANDARD\4,5`, filled
+//! its boxes with images in order, and printed the patient's demographics in
+//! a strip above them. Here each series becomes A4 or US-letter pages at
+//! `--dpi`: a header block read from the first file, with the page number,
+//! over a grid of `--layout` cells, each holding one rendered slice shrunk
+//! to fit on black with its number in the corner. Pages are PNG files
+//! (`page_001.png`, ...) or one PDF named after the series folder.
+
+mod pdf;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgb, RgbImage};
+
+use super::normalize_date;
+use crate::annotate::{Canvas, font};
+use crate::i18n::t;
+use crate::meta::{SeriesMeta, text};
+use crate::pipeline::{self, FrameSink, RenderOptions, RunStats};
+use crate::utils::{named_after_folder, transliterate};
+
+use pdf::Pdf;
+
+/// Most columns or rows of a layout; smaller cells are no longer readable.
+const MAX_CELLS_PER_SIDE: u32 = 10;
+
+/// Paper around the page content, in inches.
+const MARGIN_INCHES: f64 = 0.3;
+
+/// Dots per glyph pixel of header text: 50 dpi gives 7-dot glyphs about
+/// 3.5 mm tall.
+const DOTS_PER_TEXT_PIXEL: u32 = 50;
+
+/// Lines of the header block.
+const HEADER_LINES: usize = 3;
+
+/// Color of the paper and of header tags.
+const PAPER: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Color of used cells, the rule under the header, and slice number tags.
+const FILM: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// Paper size of the pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Paper {
+    /// ISO A4, 210 × 297 mm
+    #[default]
+    A4,
+    /// US letter, 8.5 × 11 in
+    Letter,
+}
+
+impl Paper {
+    /// Width and height in points (1/72 in).
+    const fn points(self) -> (u32, u32) {
+        match self {
+            Self::A4 => (595, 842),
+            Self::Letter => (612, 792),
+        }
+    }
+}
+
+/// File type of the pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PageFormat {
+    /// One multi-page PDF per series
+    #[default]
+    Pdf,
+    /// One PNG per page
+    Png,
+}
+
+/// Cells of a page: `columns` across and `rows` down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Layout {
+    /// Parse a `--layout` value: `COLUMNSxROWS`, e.g. `4x5`.
+    pub fn parse(value: &str) -> std::result::Result<Self, String> {
+        let (columns, rows) = value
+            .split_once(['x', 'X'])
+            .ok_or_else(|| format!("`{value}` is not COLUMNSxROWS, e.g. 4x5"))?;
+        let side = |text: &str| {
+            text.trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=MAX_CELLS_PER_SIDE).contains(n))
+                .ok_or_else(|| {
+                    format!("columns and rows must be whole numbers from 1 to {MAX_CELLS_PER_SIDE}")
+                })
+        };
+        Ok(Self {
+            columns: side(columns)?,
+            rows: side(rows)?,
+        })
+    }
+
+    /// Images on one page.
+    const fn cells(self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+/// Options for print-layout export.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintOptions {
+    pub paper: Paper,
+    pub layout: Layout,
+    pub format: PageFormat,
+    /// Resolution of the page raster in dots per inch.
+    pub dpi: u32,
+}
+
+/// Where things go on a page, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Geometry {
+    /// Page width and height.
+    size: (u32, u32),
+    margin: u32,
+    /// Magnification of header text.
+    scale: u32,
+    /// Top of the first row of cells.
+    grid_top: u32,
+    /// Width and height of one cell.
+    cell: (u32, u32),
+    /// Space between cells.
+    gap: u32,
+    layout: Layout,
+}
+
+impl Geometry {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn new(options: PrintOptions) -> Self {
+        let (width, height) = options.paper.points();
+        let dots = |points: u32| points * options.dpi / 72;
+        let size = (dots(width), dots(height));
+        let margin = (MARGIN_INCHES * f64::from(options.dpi)).round() as u32;
+        let scale = (options.dpi / DOTS_PER_TEXT_PIXEL).max(1);
+        let line = (font::HEIGHT + 3) * scale;
+        let gap = 2 * scale;
+        // The rule under the header is one scale thick, with a line of space
+        let grid_top = margin + HEADER_LINES as u32 * line + line;
+        let Layout { columns, rows } = options.layout;
+        let across = size.0.saturating_sub(2 * margin + gap * (columns - 1));
+        let down = size.1.saturating_sub(grid_top + margin + gap * (rows - 1));
+        Self {
+            size,
+            margin,
+            scale,
+            grid_top,
+            cell: ((across / columns).max(1), (down / rows).max(1)),
+            gap,
+            layout: options.layout,
+        }
+    }
+
+    /// Top-left corner of cell `cell`, counted across then down.
+    #[allow(clippy::cast_possible_truncation)]
+    fn cell_origin(&self, cell: usize) -> (u32, u32) {
+        let columns = self.layout.columns as usize;
+        let (column, row) = ((cell % columns) as u32, (cell / columns) as u32);
+        (
+            self.margin + column * (self.cell.0 + self.gap),
+            self.grid_top + row * (self.cell.1 + self.gap),
+        )
+    }
+
+    /// Characters of header text that fit across the page.
+    fn line_chars(&self) -> usize {
+        let glyph = (font::WIDTH + 1) * self.scale;
+        (self.size.0.saturating_sub(2 * self.margin) / glyph).saturating_sub(1) as usize
+    }
+}
+
+/// Header block lines from the demographics of `obj`; missing values are
+/// left out.
+fn header_lines(obj: &InMemDicomObject) -> [String; HEADER_LINES] {
+    let text = |tag| text(obj, tag);
+    let labeled = |label: &str, tag| text(tag).map(|value| format!("{label} {value}"));
+    let date = |tag| text(tag).map(|value| normalize_date(&value));
+    let name = text(tags::PATIENT_NAME).map(|name| {
+        name.split('^')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    let series = match (text(tags::SERIES_NUMBER), text(tags::SERIES_DESCRIPTION)) {
+        (Some(number), Some(description)) => Some(format!("SERIES {number}: {description}")),
+        (Some(number), None) => Some(format!("SERIES {number}")),
+        (None, description) => description,
+    };
+    let join = |parts: Vec<Option<String>>| {
+        transliterate(&parts.into_iter().flatten().collect::<Vec<_>>().join("   "))
+    };
+    [
+        join(vec![
+            name,
+            labeled("ID", tags::PATIENT_ID),
+            date(tags::PATIENT_BIRTH_DATE).map(|date| format!("DOB {date}")),
+            labeled("SEX", tags::PATIENT_SEX),
+        ]),
+        join(vec![
+            date(tags::STUDY_DATE),
+            text(tags::MODALITY),
+            text(tags::STUDY_DESCRIPTION),
+            series,
+        ]),
+        join(vec![text(tags::INSTITUTION_NAME)]),
+    ]
+}
+
+/// Pages written so far.
+enum Output {
+    /// PNG files in the series folder.
+    Png { written: usize },
+    /// One PDF being written.
+    Pdf(Pdf<BufWriter<File>>),
+}
+
+/// Lays rendered frames out on pages and writes each page once it is full.
+struct PrintSink<'a> {
+    output_dir: &'a Path,
+    geometry: Geometry,
+    header: [String; HEADER_LINES],
+    /// Pages the series needs, for `PAGE n/total`.
+    total_pages: usize,
+    /// Number and raster of the page being filled.
+    page: Option<(usize, RgbImage)>,
+    output: Output,
+}
+
+impl<'a> PrintSink<'a> {
+    fn new(
+        output_dir: &'a Path,
+        options: PrintOptions,
+        header: [String; HEADER_LINES],
+        frames: usize,
+    ) -> Result<Self> {
+        let output = match options.format {
+            PageFormat::Png => Output::Png { written: 0 },
+            PageFormat::Pdf => {
+                let path = named_after_folder(output_dir, "pdf");
+                let file = File::create(&path)
+                    .with_context(|| format!("Failed to create PDF: {}", path.display()))?;
+                let pdf = Pdf::new(BufWriter::new(file), options.paper.points())
+                    .with_context(|| format!("Failed to write PDF: {}", path.display()))?;
+                Output::Pdf(pdf)
+            }
+        };
+        Ok(Self {
+            output_dir,
+            geometry: Geometry::new(options),
+            header,
+            total_pages: frames.div_ceil(options.layout.cells()).max(1),
+            page: None,
+            output,
+        })
+    }
+
+    /// A blank page `number` (from 0) with the header block drawn.
+    fn blank(&self, number: usize) -> RgbImage {
+        let Geometry {
+            size: (width, height),
+            margin,
+            scale,
+            grid_top,
+            ..
+        } = self.geometry;
+        let mut canvas =
+            Canvas::new(RgbImage::from_pixel(width, height, PAPER)).scaled(i64::from(scale));
+        let (margin, scale) = (i64::from(margin), i64::from(scale));
+        let chars = self.geometry.line_chars();
+        let page = format!("PAGE {}/{}", number + 1, self.total_pages);
+        let (page_width, line) = canvas.tag_size(&page);
+        let right = i64::from(width) - margin;
+        for (row, text) in (1..).zip(&self.header) {
+            // Keep clear of the page number on the first line
+            let room = if row == 1 {
+                chars.saturating_sub(page.len() + 2)
+            } else {
+                chars
+            };
+            let text: String = text.chars().take(room).collect();
+            canvas.label(&text, (margin, margin + row * line), PAPER);
+        }
+        canvas.label(&page, (right - page_width, margin + line), PAPER);
+        let rule = i64::from(grid_top) - line / 2;
+        canvas.fill((margin, rule), (right - margin, scale), FILM);
+        canvas.into_image()
+    }
+
+    /// Draw `image` in cell `cell` of `page`, numbered `number`.
+    fn place(&self, page: RgbImage, cell: usize, number: usize, image: &DynamicImage) -> RgbImage {
+        let (left, top) = self.geometry.cell_origin(cell);
+        let (width, height) = self.geometry.cell;
+        let mut canvas = Canvas::new(page).scaled(i64::from(self.geometry.scale));
+        canvas.fill(
+            (i64::from(left), i64::from(top)),
+            (i64::from(width), i64::from(height)),
+            FILM,
+        );
+        let mut page = canvas.into_image();
+        let fitted = image
+            .resize(width, height, FilterType::Triangle)
+            .into_rgb8();
+        imageops::overlay(
+            &mut page,
+            &fitted,
+            i64::from(left + (width - fitted.width()) / 2),
+            i64::from(top + (height - fitted.height()) / 2),
+        );
+        let mut canvas = Canvas::new(page).scaled(i64::from(self.geometry.scale));
+        canvas.label(
+            &number.to_string(),
+            (i64::from(left), i64::from(top + height)),
+            FILM,
+        );
+        canvas.into_image()
+    }
+
+    /// Write the page being filled, if any.
+    fn flush(&mut self) -> Result<()> {
+        let Some((number, page)) = self.page.take() else {
+            return Ok(());
+        };
+        match &mut self.output {
+            Output::Png { written } => {
+                let path = self.output_dir.join(format!("page_{:03}.png", number + 1));
+                page.save(&path)
+                    .with_context(|| format!("Failed to save page: {}", path.display()))?;
+                *written += 1;
+            }
+            Output::Pdf(pdf) => pdf
+                .add_page(&page)
+                .context("Failed to add a page to the PDF")?,
+        }
+        Ok(())
+    }
+
+    /// Write the last page and close the PDF; returns the pages written and
+    /// where they are.
+    fn finish(mut self) -> Result<(usize, PathBuf)> {
+        self.flush()?;
+        match self.output {
+            Output::Png { written } => Ok((written, self.output_dir.to_path_buf())),
+            Output::Pdf(pdf) => {
+                let path = named_after_folder(self.output_dir, "pdf");
+                let pages = pdf.page_count();
+                pdf.finish()
+                    .with_context(|| format!("Failed to write PDF: {}", path.display()))?;
+                Ok((pages, path))
+            }
+        }
+    }
+}
+
+impl FrameSink for PrintSink<'_> {
+    fn write_frame(&mut self, index: usize, _: &Path, image: DynamicImage) -> Result<()> {
+        let cells = self.geometry.layout.cells();
+        let number = index / cells;
+        if self
+            .page
+            .as_ref()
+            .is_none_or(|(current, _)| *current != number)
+        {
+            self.flush()?;
+            self.page = Some((number, self.blank(number)));
+        }
+        if let Some((_, page)) = self.page.take() {
+            let page = self.place(page, index % cells, index + 1, &image);
+            self.page = Some((number, page));
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn convert_to_pages(
+    series: &SeriesMeta,
+    output_dir: &Path,
+    print: PrintOptions,
+    options: RenderOptions<'_>,
+) -> Result<RunStats> {
+    let header = series
+        .first()
+        .and_then(|first| {
+            OpenFileOptions::new()
+                .read_until(tags::PIXEL_DATA)
+                .open_file(&first.path)
+                .ok()
+        })
+        .map(|obj| header_lines(&obj))
+        .unwrap_or_default();
+    let total = series.frames();
+    let mut sink = PrintSink::new(output_dir, print, header, total)?;
+    let stats = pipeline::run(&series.files(), options, &mut sink);
+    let (pages, path) = sink.finish()?;
+
+    if stats.failed > 0 {
+        eprintln!(
+            "{}",
+            t!(
+                "convert-frames-failed",
+                failed = stats.failed,
+                total = total
+            )
+        );
+    }
+    println!(
+        "{}",
+        t!(
+            "print-saved",
+            pages = pages,
+            path = path.display().to_string()
+        )
+    );
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::{DataElement, VR};
+
+    use super::*;
+
+    fn options(layout: &str) -> PrintOptions {
+        PrintOptions {
+            paper: Paper::A4,
+            layout: Layout::parse(layout).unwrap(),
+            format: PageFormat::Png,
+            dpi: 150,
+        }
+    }
+
+    #[test]
+    fn layouts_are_columns_by_rows() {
+        assert_eq!(
+            Layout::parse("4x5"),
+            Ok(Layout {
+                columns: 4,
+                rows: 5
+            })
+        );
+        assert_eq!(Layout::parse(" 2X3 ").map(Layout::cells), Ok(6));
+        assert!(Layout::parse("4").is_err());
+        assert!(Layout::parse("0x5").is_err());
+        assert!(Layout::parse("11x1").is_err());
+        assert!(Layout::parse("ax2").is_err());
+    }
+
+    #[test]
+    fn pages_are_paper_sized() {
+        assert_eq!(Geometry::new(options("4x5")).size, (1239, 1754));
+        let letter = PrintOptions {
+            paper: Paper::Letter,
+            dpi: 300,
+            ..options("4x5")
+        };
+        assert_eq!(Geometry::new(letter).size, (2550, 3300));
+    }
+
+    #[test]
+    fn cells_fill_the_page_below_the_header() {
+        let geometry = Geometry::new(options("3x4"));
+        let (width, height) = geometry.size;
+        assert_eq!(
+            geometry.cell_origin(0),
+            (geometry.margin, geometry.grid_top)
+        );
+        let (left, top) = geometry.cell_origin(11);
+        assert!(left + geometry.cell.0 <= width - geometry.margin);
+        assert!(top + geometry.cell.1 <= height - geometry.margin);
+        assert_eq!(geometry.cell_origin(3).0, geometry.margin);
+        assert!(geometry.cell_origin(3).1 > geometry.cell_origin(2).1);
+    }
+
+    #[test]
+    fn header_holds_the_demographics() {
+        let mut obj = InMemDicomObject::new_empty();
+        for (tag, vr, value) in [
+            (tags::PATIENT_NAME, VR::PN, "Pérez^Ana"),
+            (tags::PATIENT_ID, VR::LO, "12345"),
+            (tags::PATIENT_BIRTH_DATE, VR::DA, "19700102"),
+            (tags::STUDY_DATE, VR::DA, "20240105"),
+            (tags::MODALITY, VR::CS, "CT"),
+            (tags::SERIES_NUMBER, VR::IS, "3"),
+            (tags::SERIES_DESCRIPTION, VR::LO, "AXIAL"),
+        ] {
+            obj.put(DataElement::new(tag, vr, value));
+        }
+        assert_eq!(
+            header_lines(&obj),
+            [
+                "Perez Ana   ID 12345   DOB 1970-01-02".to_string(),
+                "2024-01-05   CT   SERIES 3: AXIAL".to_string(),
+                String::new(),
+            ]
+        );
+    }
+
+    #[test]
+    fn frames_fill_pages_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = PrintSink::new(dir.path(), options("2x2"), Default::default(), 5).unwrap();
+        let frame =
+            DynamicImage::ImageLuma8(image::GrayImage::from_pixel(8, 8, image::Luma([200])));
+        for index in 0..5 {
+            sink.write_frame(index, Path::new("a.dcm"), frame.clone())
+                .unwrap();
+        }
+        let (pages, path) = sink.finish().unwrap();
+        assert_eq!((pages, path.as_path()), (2, dir.path()));
+        let second = image::open(dir.path().join("page_002.png"))
+            .unwrap()
+            .into_rgb8();
+        let geometry = Geometry::new(options("2x2"));
+        let (left, top) = geometry.cell_origin(0);
+        let center = |(left, top): (u32, u32)| {
+            *second.get_pixel(left + geometry.cell.0 / 2, top + geometry.cell.1 / 2)
+        };
+        assert_eq!(center((left, top)), Rgb([200, 200, 200]));
+        // The last page's unused cells stay blank paper
+        assert_eq!(center(geometry.cell_origin(1)), PAPER);
+    }
+}
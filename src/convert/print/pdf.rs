@@ -0,0 +1,191 @@
+//! Minimal PDF writer: one full-page image per page.
+//!
+//! Pages are written as they come, each an RGB image compressed with
+//! `FlateDecode` and drawn over the whole page; the page tree, catalog, and
+//! cross-reference table follow when the document is finished. That is all a
+//! PDF reader needs to show and print the sheets.
+
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::RgbImage;
+
+/// Object number of the catalog.
+const CATALOG: usize = 1;
+
+/// Object number of the page tree.
+const PAGES: usize = 2;
+
+/// A PDF document being written to `out`.
+pub struct Pdf<W: Write> {
+    out: W,
+    /// Bytes written so far.
+    position: u64,
+    /// Byte offset of each object, by object number from 1; the catalog and
+    /// page tree are filled in by [`Self::finish`].
+    offsets: Vec<u64>,
+    /// Object numbers of the pages, in order.
+    pages: Vec<usize>,
+    /// Page width and height in points.
+    size: (u32, u32),
+}
+
+impl<W: Write> Pdf<W> {
+    /// Start a document of pages `size` points wide and high.
+    pub fn new(out: W, size: (u32, u32)) -> io::Result<Self> {
+        let mut pdf = Self {
+            out,
+            position: 0,
+            offsets: vec![0; PAGES],
+            pages: Vec::new(),
+            size,
+        };
+        // The binary comment marks the file as binary for transfer tools
+        pdf.write(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(pdf)
+    }
+
+    /// Add a page showing `image` stretched over the whole page.
+    pub fn add_page(&mut self, image: &RgbImage) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(image.as_raw())?;
+        let pixels = encoder.finish()?;
+
+        let (width, height) = self.size;
+        let number = self.offsets.len() + 1;
+        let (image_number, content_number, page_number) = (number, number + 1, number + 2);
+        let header = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+             /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>",
+            image.width(),
+            image.height(),
+            pixels.len()
+        );
+        self.stream(&header, &pixels)?;
+        let content = format!("q {width} 0 0 {height} 0 0 cm /Im0 Do Q");
+        self.stream(
+            &format!("<< /Length {} >>", content.len()),
+            content.as_bytes(),
+        )?;
+        self.object(&format!(
+            "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {width} {height}] \
+             /Resources << /XObject << /Im0 {image_number} 0 R >> >> \
+             /Contents {content_number} 0 R >>"
+        ))?;
+        self.pages.push(page_number);
+        Ok(())
+    }
+
+    /// Number of pages added so far.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Write the page tree, catalog, and cross-reference table, and hand
+    /// back the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        let kids: Vec<String> = self
+            .pages
+            .iter()
+            .map(|number| format!("{number} 0 R"))
+            .collect();
+        self.offsets[PAGES - 1] = self.position;
+        let pages = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            kids.len()
+        );
+        self.write_object(PAGES, pages.as_bytes())?;
+        self.offsets[CATALOG - 1] = self.position;
+        let catalog = format!("<< /Type /Catalog /Pages {PAGES} 0 R >>");
+        self.write_object(CATALOG, catalog.as_bytes())?;
+
+        let xref = self.position;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            // Every entry is exactly 20 bytes, end of line included
+            table.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {CATALOG} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.offsets.len() + 1
+        ));
+        self.write(table.as_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// Write the next object with `body`.
+    fn object(&mut self, body: &str) -> io::Result<()> {
+        self.offsets.push(self.position);
+        self.write_object(self.offsets.len(), body.as_bytes())
+    }
+
+    /// Write the next object: a stream of `data` described by `header`.
+    fn stream(&mut self, header: &str, data: &[u8]) -> io::Result<()> {
+        let mut body = Vec::with_capacity(header.len() + data.len() + 32);
+        body.extend_from_slice(header.as_bytes());
+        body.extend_from_slice(b"\nstream\n");
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.offsets.push(self.position);
+        self.write_object(self.offsets.len(), &body)
+    }
+
+    fn write_object(&mut self, number: usize, body: &[u8]) -> io::Result<()> {
+        self.write(format!("{number} 0 obj\n").as_bytes())?;
+        self.write(body)?;
+        self.write(b"\nendobj\n")
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(pages: usize) -> Vec<u8> {
+        let mut pdf = Pdf::new(Vec::new(), (612, 792)).unwrap();
+        for _ in 0..pages {
+            pdf.add_page(&RgbImage::new(4, 3)).unwrap();
+        }
+        assert_eq!(pdf.page_count(), pages);
+        pdf.finish().unwrap()
+    }
+
+    #[test]
+    fn pages_are_listed_in_order() {
+        let bytes = document(2);
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.contains("/Kids [5 0 R 8 0 R] /Count 2"));
+        assert!(text.contains("/MediaBox [0 0 612 792]"));
+        assert!(text.contains("/Width 4 /Height 3"));
+        assert!(text.ends_with("%%EOF\n"));
+    }
+
+    #[test]
+    fn cross_references_point_at_their_objects() {
+        let bytes = document(2);
+        let marker = b"startxref\n";
+        let tail = bytes
+            .windows(marker.len())
+            .rposition(|window| window == marker)
+            .unwrap();
+        let tail = std::str::from_utf8(&bytes[tail + marker.len()..]).unwrap();
+        let start: usize = tail.lines().next().unwrap().parse().unwrap();
+        let table = std::str::from_utf8(&bytes[start..]).unwrap();
+        assert!(table.starts_with("xref\n0 9\n"));
+        for (number, entry) in (1..).zip(table.lines().skip(3).take(8)) {
+            assert_eq!(entry.len(), 19, "{entry:?} plus its newline is 20 bytes");
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(bytes[offset..].starts_with(format!("{number} 0 obj\n").as_bytes()));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! List of files that could not be read even leniently (`--lenient`).
+//!
+//! Written as `quarantine/files.csv` under the output root at the end of a
+//! run, so the broken instances of an archive can be found and re-sent.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use super::patches::csv_row;
+use crate::lenient;
+
+/// Quarantine folder under the output root.
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// File listing the quarantined files.
+const LIST_FILE: &str = "files.csv";
+
+/// Columns of the list.
+const LIST_HEADER: &str = "file,error";
+
+/// Write the files put in quarantine during the run under `root`. Returns
+/// the list's path and how many files it names, if there were any.
+pub(super) fn write(root: &Path) -> Result<Option<(PathBuf, usize)>> {
+    let files = lenient::take_quarantined();
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let dir = root.join(QUARANTINE_DIR);
+    let path = dir.join(LIST_FILE);
+    let rows: String = files
+        .iter()
+        .map(|(file, error)| csv_row(&[file.display().to_string(), error.clone()]) + "\n")
+        .collect();
+    fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(&path, format!("{LIST_HEADER}\n{rows}")))
+        .with_context(|| format!("Failed to write the quarantine list: {}", path.display()))?;
+    Ok(Some((path, files.len())))
+}
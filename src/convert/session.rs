@@ -0,0 +1,350 @@
+//! Repeat scans split by acquisition time (`--time-window`).
+//!
+//! Interventional and follow-up protocols often repeat a series under the
+//! same description. With a time window, each group is cut wherever two
+//! consecutive acquisitions lie further apart than the window, and every
+//! resulting session gets its own folder named after its first acquisition.
+//! The same acquisition times date exported images (`jpeg --acquisition-times`).
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, OpenFileOptions};
+
+use crate::meta;
+
+/// Seconds in a day.
+const DAY: i64 = 86_400;
+
+/// Suffix of the session holding files without an acquisition time.
+const UNKNOWN_TIME: &str = "unknown-time";
+
+/// When a file was acquired: its DICOM date (`YYYYMMDD`, empty if unknown),
+/// the seconds since midnight, and the UTC offset in seconds if recorded.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct Acquired {
+    date: String,
+    seconds: u32,
+    offset: Option<i32>,
+}
+
+impl Acquired {
+    /// Read the acquisition time from the header of the file at `path`.
+    pub(super) fn read_file(path: &Path) -> Option<Self> {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+            .ok()?;
+        Self::read(&obj)
+    }
+
+    /// Read `AcquisitionDateTime`, then `AcquisitionDate`/`AcquisitionTime`,
+    /// then `ContentDate`/`ContentTime`. The UTC offset comes from the date
+    /// time value or `TimezoneOffsetFromUTC`.
+    pub(super) fn read(obj: &DefaultDicomObject) -> Option<Self> {
+        let text = |tag| meta::text(obj, tag);
+        let mut acquired = text(tags::ACQUISITION_DATE_TIME)
+            .and_then(|dt| Self::parse_dt(&dt))
+            .or_else(|| Self::read_date_and_time(&text))?;
+        acquired.offset = acquired.offset.or_else(|| {
+            text(tags::TIMEZONE_OFFSET_FROM_UTC).and_then(|offset| parse_offset(&offset))
+        });
+        Some(acquired)
+    }
+
+    /// The first of the date/time attribute pairs that has a time.
+    fn read_date_and_time(text: &impl Fn(Tag) -> Option<String>) -> Option<Self> {
+        [
+            (tags::ACQUISITION_DATE, tags::ACQUISITION_TIME),
+            (tags::CONTENT_DATE, tags::CONTENT_TIME),
+        ]
+        .into_iter()
+        .find_map(|(date, time)| {
+            let seconds = parse_time(&text(time)?)?;
+            let date = text(date).filter(|date| parse_date(date).is_some());
+            Some(Self {
+                date: date.unwrap_or_default(),
+                seconds,
+                offset: None,
+            })
+        })
+    }
+
+    /// A DICOM DT value (`YYYYMMDDHHMMSS.FFFFFF&ZZXX`).
+    fn parse_dt(value: &str) -> Option<Self> {
+        let (value, offset) = match value.find(['+', '-']) {
+            Some(at) => (&value[..at], parse_offset(&value[at..])),
+            None => (value, None),
+        };
+        let (date, time) = value.split_at_checked(8)?;
+        parse_date(date)?;
+        Some(Self {
+            date: date.to_string(),
+            seconds: parse_time(time)?,
+            offset,
+        })
+    }
+
+    /// The acquisition as a point in time, if its date is known. Without a
+    /// recorded UTC offset the time is taken as UTC.
+    pub(super) fn system_time(&self) -> Option<SystemTime> {
+        let local = parse_date(&self.date)? * DAY + i64::from(self.seconds);
+        let utc = local - i64::from(self.offset.unwrap_or(0));
+        let since_epoch = Duration::from_secs(utc.unsigned_abs());
+        if utc >= 0 {
+            SystemTime::UNIX_EPOCH.checked_add(since_epoch)
+        } else {
+            SystemTime::UNIX_EPOCH.checked_sub(since_epoch)
+        }
+    }
+
+    /// `YYYY-MM-DD`, `HH:MM:SS`, and the offset as `+HH:MM` if recorded;
+    /// `None` if the date is unknown.
+    pub(super) fn parts(&self) -> Option<(String, String, Option<String>)> {
+        parse_date(&self.date)?;
+        let date = format!(
+            "{}-{}-{}",
+            &self.date[..4],
+            &self.date[4..6],
+            &self.date[6..]
+        );
+        let (h, m, s) = (
+            self.seconds / 3600,
+            self.seconds / 60 % 60,
+            self.seconds % 60,
+        );
+        let offset = self.offset.map(|offset| {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let minutes = offset.unsigned_abs() / 60;
+            format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+        });
+        Some((date, format!("{h:02}:{m:02}:{s:02}"), offset))
+    }
+
+    /// Seconds since 1970-01-01 (or since midnight of an unknown day).
+    fn timestamp(&self) -> i64 {
+        parse_date(&self.date).unwrap_or(0) * DAY + i64::from(self.seconds)
+    }
+
+    /// `HHMMSS`, prefixed with `YYYYMMDD-` if `with_date`.
+    fn label(&self, with_date: bool) -> String {
+        let (h, m, s) = (
+            self.seconds / 3600,
+            self.seconds / 60 % 60,
+            self.seconds % 60,
+        );
+        if with_date && !self.date.is_empty() {
+            format!("{}-{h:02}{m:02}{s:02}", self.date)
+        } else {
+            format!("{h:02}{m:02}{s:02}")
+        }
+    }
+}
+
+/// Days since 1970-01-01 of a DICOM DA value (`YYYYMMDD`).
+pub(super) fn parse_date(value: &str) -> Option<i64> {
+    if value.len() != 8 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = value[..4].parse().ok()?;
+    let month: i64 = value[4..6].parse().ok()?;
+    let day: i64 = value[6..].parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days from civil (proleptic Gregorian), with March as the first month.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+/// Seconds east of UTC of a DICOM offset (`+HHMM` or `-HHMM`).
+fn parse_offset(value: &str) -> Option<i32> {
+    let sign = match value.trim().get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = value.trim().get(1..)?;
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Seconds since midnight of a DICOM TM value (`HH`, `HHMM`, `HHMMSS`,
+/// optionally with fractional seconds or the old `HH:MM:SS` form).
+pub(super) fn parse_time(value: &str) -> Option<u32> {
+    let digits: String = value
+        .split('.')
+        .next()?
+        .chars()
+        .filter(|c| *c != ':')
+        .collect();
+    if !matches!(digits.len(), 2 | 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |at: usize| {
+        digits
+            .get(at..at + 2)
+            .map_or(Some(0), |v| v.parse::<u32>().ok())
+    };
+    let (h, m, s) = (field(0)?, field(2)?, field(4)?);
+    (h < 24 && m < 60 && s < 61).then_some(h * 3600 + m * 60 + s.min(59))
+}
+
+/// Cut one group into sessions whose consecutive acquisitions are at most
+/// `window_secs` apart.
+///
+/// A group that stays in one piece keeps its key; otherwise each session is
+/// keyed `<key>_<HHMMSS>` after its first acquisition (with the date when
+/// the group spans several days) and files without a time go to
+/// `<key>_unknown-time`.
+pub(super) fn split_sessions<T: AsRef<Path>>(
+    key: &str,
+    files: Vec<(T, Option<Acquired>)>,
+    window_secs: i64,
+) -> Vec<(String, Vec<T>)> {
+    let (mut timed, untimed): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|(_, acquired)| acquired.is_some());
+    timed.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.as_ref().cmp(b.0.as_ref())));
+
+    let mut sessions: Vec<(Acquired, Vec<T>)> = Vec::new();
+    let mut last: Option<i64> = None;
+    for (path, acquired) in timed {
+        let acquired = acquired.expect("partitioned on is_some");
+        let at = acquired.timestamp();
+        match sessions.last_mut() {
+            Some((_, paths)) if last.is_some_and(|last| at - last <= window_secs) => {
+                paths.push(path);
+            }
+            _ => sessions.push((acquired, vec![path])),
+        }
+        last = Some(at);
+    }
+
+    let untimed: Vec<T> = untimed.into_iter().map(|(path, _)| path).collect();
+    if sessions.len() <= 1 {
+        let mut files: Vec<T> = sessions.into_iter().flat_map(|(_, paths)| paths).collect();
+        files.extend(untimed);
+        return vec![(key.to_string(), files)];
+    }
+
+    let with_date = sessions
+        .iter()
+        .any(|(acquired, _)| acquired.date != sessions[0].0.date);
+    let mut split: Vec<(String, Vec<T>)> = sessions
+        .into_iter()
+        .map(|(acquired, paths)| (format!("{key}_{}", acquired.label(with_date)), paths))
+        .collect();
+    if !untimed.is_empty() {
+        split.push((format!("{key}_{UNKNOWN_TIME}"), untimed));
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn at(date: &str, time: &str) -> Option<Acquired> {
+        Some(Acquired {
+            date: date.to_string(),
+            seconds: parse_time(time).unwrap(),
+            offset: None,
+        })
+    }
+
+    fn keys(split: &[(String, Vec<PathBuf>)]) -> Vec<(&str, usize)> {
+        split
+            .iter()
+            .map(|(key, files)| (key.as_str(), files.len()))
+            .collect()
+    }
+
+    #[test]
+    fn dicom_dates_and_times_parse() {
+        assert_eq!(parse_date("19700101"), Some(0));
+        assert_eq!(parse_date("20000301"), Some(11_017));
+        assert_eq!(parse_date("2000-03-01"), None);
+        assert_eq!(parse_date("20001301"), None);
+        assert_eq!(parse_time("103015.250"), Some(37_815));
+        assert_eq!(parse_time("10:30:15"), Some(37_815));
+        assert_eq!(parse_time("1030"), Some(37_800));
+        assert_eq!(parse_time("25"), None);
+        let dt = Acquired::parse_dt("20240105143000.000000+0100").unwrap();
+        assert_eq!(dt.label(true), "20240105-143000");
+        assert_eq!(dt.offset, Some(3600));
+        assert_eq!(parse_offset("-0530"), Some(-19_800));
+        assert_eq!(parse_offset("0100"), None);
+    }
+
+    #[test]
+    fn acquisitions_become_points_in_time() {
+        let dt = Acquired::parse_dt("19700102010000+0100").unwrap();
+        let since_epoch = dt
+            .system_time()
+            .unwrap()
+            .duration_since(SystemTime::UNIX_EPOCH);
+        assert_eq!(since_epoch.unwrap().as_secs(), 86_400);
+        let (date, time, offset) = dt.parts().unwrap();
+        assert_eq!((date.as_str(), time.as_str()), ("1970-01-02", "01:00:00"));
+        assert_eq!(offset.as_deref(), Some("+01:00"));
+
+        let undated = at("", "103000").unwrap();
+        assert!(undated.system_time().is_none() && undated.parts().is_none());
+    }
+
+    #[test]
+    fn gaps_longer_than_the_window_start_a_session() {
+        let files = vec![
+            (PathBuf::from("a"), at("20240105", "100000")),
+            (PathBuf::from("b"), at("20240105", "100500")),
+            (PathBuf::from("c"), at("20240105", "113000")),
+            (PathBuf::from("d"), at("20240105", "113200")),
+            (PathBuf::from("e"), None),
+        ];
+        let split = split_sessions("T1 AX", files, 10 * 60);
+        assert_eq!(
+            keys(&split),
+            [
+                ("T1 AX_100000", 2),
+                ("T1 AX_113000", 2),
+                ("T1 AX_unknown-time", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn one_session_keeps_the_key() {
+        let files = vec![
+            (PathBuf::from("a"), at("20240105", "100000")),
+            (PathBuf::from("b"), None),
+            (PathBuf::from("c"), at("20240105", "100100")),
+        ];
+        assert_eq!(keys(&split_sessions("3", files, 60)), [("3", 3)]);
+    }
+
+    #[test]
+    fn sessions_across_midnight_are_dated() {
+        let files = vec![
+            (PathBuf::from("a"), at("20240105", "235900")),
+            (PathBuf::from("b"), at("20240106", "000100")),
+            (PathBuf::from("c"), at("20240106", "090000")),
+        ];
+        assert_eq!(
+            keys(&split_sessions("3", files, 5 * 60)),
+            [("3_20240105-235900", 2), ("3_20240106-090000", 1)]
+        );
+    }
+}
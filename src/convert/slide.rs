@@ -0,0 +1,574 @@
+//! DICOM whole-slide microscopy → one reassembled image or a DeepZoom pyramid.
+//!
+//! A VL Whole Slide Microscopy series stores a slide as a pyramid: one object
+//! per resolution level, each a multi-frame grid of tiles over a total pixel
+//! matrix that may be 100 000 pixels across. Decoded frame by frame it gives
+//! thousands of loose tiles, and decoded whole it would never fit in memory.
+//! Here tiles are placed by their position in the total pixel matrix
+//! (`TILED_FULL` order, else the per-frame Plane Position (Slide) macro), and
+//! read from the coarsest level that still has the detail asked for, so only
+//! the tiles of the output are decoded. Label and overview images are left
+//! out; of several focal planes or optical paths, the first is used.
+
+mod deepzoom;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use dicom::dictionary_std::{tags, uids};
+use dicom::object::{DefaultDicomObject, InMemDicomObject, OpenFileOptions};
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+
+use super::ImageFormat;
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, RenderOptions, RunStats, display_name};
+use crate::pixel::groups;
+use crate::utils::named_after_folder;
+
+/// Longest side of one reassembled image (the JPEG limit).
+const MAX_SIDE: u32 = 65_535;
+
+/// Color of slide areas no tile covers: bare glass.
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// `ImageType` values of images that are not part of the pyramid.
+const NOT_PYRAMID: [&str; 2] = ["LABEL", "OVERVIEW"];
+
+/// Options for slide export.
+#[derive(Debug, Clone, Copy)]
+pub struct SlideOptions {
+    /// Shrink factor from full resolution; `None` fits `max_size`, or keeps
+    /// full resolution for a pyramid.
+    pub downsample: Option<u32>,
+    /// Longest side of the reassembled image when no downsample is given.
+    pub max_size: u32,
+    /// Write a DeepZoom pyramid with tiles this many pixels a side instead.
+    pub deepzoom: Option<u32>,
+    /// Encoding of the image or tiles.
+    pub format: ImageFormat,
+}
+
+/// Whether `path` holds a VL Whole Slide Microscopy image.
+pub fn is_slide(path: &Path) -> bool {
+    OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()
+        .and_then(|obj| {
+            let uid = obj.element(tags::SOP_CLASS_UID).ok()?.to_str().ok()?;
+            Some(uid.trim_end_matches('\0').trim() == uids::VL_WHOLE_SLIDE_MICROSCOPY_IMAGE_STORAGE)
+        })
+        .unwrap_or(false)
+}
+
+/// One resolution level of a slide pyramid.
+#[derive(Debug, Clone, PartialEq)]
+struct Level {
+    path: PathBuf,
+    /// Size of the total pixel matrix.
+    width: u32,
+    height: u32,
+    /// Size of one tile (frame).
+    tile_width: u32,
+    tile_height: u32,
+    /// Frame of each tile, by column and row of the tile grid.
+    tiles: HashMap<(u32, u32), u32>,
+}
+
+impl Level {
+    /// The level stored in `path`; `None` for other objects and for label
+    /// and overview images.
+    fn read(path: &Path) -> Option<Self> {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+            .ok()?;
+        Self::of(&obj, path)
+    }
+
+    fn of(obj: &InMemDicomObject, path: &Path) -> Option<Self> {
+        let image_type = obj.element(tags::IMAGE_TYPE).ok()?.to_str().ok()?;
+        if image_type
+            .split('\\')
+            .nth(2)
+            .is_some_and(|flavor| NOT_PYRAMID.contains(&flavor.trim()))
+        {
+            return None;
+        }
+        let size = |tag| -> Option<u32> { obj.element(tag).ok()?.to_int().ok().filter(|v| *v > 0) };
+        let (width, height) = (
+            size(tags::TOTAL_PIXEL_MATRIX_COLUMNS)?,
+            size(tags::TOTAL_PIXEL_MATRIX_ROWS)?,
+        );
+        let (tile_width, tile_height) = (size(tags::COLUMNS)?, size(tags::ROWS)?);
+        let grid = (width.div_ceil(tile_width), height.div_ceil(tile_height));
+        Some(Self {
+            path: path.to_path_buf(),
+            width,
+            height,
+            tile_width,
+            tile_height,
+            tiles: tile_grid(obj, grid, (tile_width, tile_height)),
+        })
+    }
+
+    /// Shrink factor of this level from `base`.
+    fn downsample(&self, base: &Self) -> f64 {
+        f64::from(base.width) / f64::from(self.width)
+    }
+}
+
+/// Frame of each tile by grid column and row, for a grid of `columns` ×
+/// `rows` tiles: `TILED_FULL` objects store tiles row by row, others give
+/// each frame's place in the Plane Position (Slide) macro (else row by row
+/// too). Frames beyond the first of a place (other focal planes or optical
+/// paths) are skipped.
+fn tile_grid(
+    obj: &InMemDicomObject,
+    (columns, rows): (u32, u32),
+    (tile_width, tile_height): (u32, u32),
+) -> HashMap<(u32, u32), u32> {
+    let full = obj
+        .element(tags::DIMENSION_ORGANIZATION_TYPE)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .is_some_and(|kind| kind.trim() == "TILED_FULL");
+    let placed = |frame: u32| -> Option<(u32, u32)> {
+        let item = groups::item(obj, frame, tags::PLANE_POSITION_SLIDE_SEQUENCE)?;
+        // 1-based; a tile may start left of or above the matrix
+        let at = |tag| -> Option<u32> {
+            let position = item.element(tag).ok()?.to_int::<i64>().ok()?;
+            u32::try_from((position - 1).max(0)).ok()
+        };
+        let (x, y) = (
+            at(tags::COLUMN_POSITION_IN_TOTAL_IMAGE_PIXEL_MATRIX)?,
+            at(tags::ROW_POSITION_IN_TOTAL_IMAGE_PIXEL_MATRIX)?,
+        );
+        Some((x / tile_width, y / tile_height))
+    };
+    let mut grid = HashMap::new();
+    for frame in 0..pipeline::number_of_frames(obj) {
+        let in_order = (frame % columns, frame / columns);
+        let place = if full {
+            in_order
+        } else {
+            placed(frame).unwrap_or(in_order)
+        };
+        if place.0 < columns && place.1 < rows {
+            grid.entry(place).or_insert(frame);
+        }
+    }
+    grid
+}
+
+/// The pyramid levels among `files`, finest first. Fails when there are
+/// none.
+fn pyramid(files: &[PathBuf]) -> Result<Vec<Level>> {
+    let mut levels: Vec<Level> = files
+        .iter()
+        .filter(|path| is_slide(path))
+        .filter_map(|path| Level::read(path))
+        .collect();
+    if levels.is_empty() {
+        anyhow::bail!(BadInput(
+            "No whole-slide microscopy image with a tiled pixel matrix in this series".to_string()
+        ));
+    }
+    levels.sort_by(|a, b| b.width.cmp(&a.width).then_with(|| a.path.cmp(&b.path)));
+    // Other focal planes or optical paths repeat a level
+    levels.dedup_by_key(|level| level.width);
+    Ok(levels)
+}
+
+/// Length of a side of `length` pixels shrunk by `downsample`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn scaled(length: u32, downsample: f64) -> u32 {
+    (f64::from(length) / downsample)
+        .round()
+        .clamp(1.0, f64::from(u32::MAX)) as u32
+}
+
+/// Smallest whole shrink factor that fits `base` in `max_size` pixels a side.
+fn fitting(base: &Level, max_size: u32) -> u32 {
+    base.width.max(base.height).div_ceil(max_size.max(1)).max(1)
+}
+
+/// Reads regions of a slide shrunk by some factor, decoding the tiles of
+/// one level, each once while consecutive regions still need it.
+struct Reader<'a> {
+    level: &'a Level,
+    obj: DefaultDicomObject,
+    /// Output pixels per level pixel.
+    scale: f64,
+    options: RenderOptions<'a>,
+    /// Scaled tiles of the last region, by frame.
+    cache: HashMap<u32, RgbImage>,
+}
+
+impl<'a> Reader<'a> {
+    /// Read the slide of `levels` shrunk by `downsample` from full
+    /// resolution, from the coarsest level with that much detail.
+    fn open(levels: &'a [Level], downsample: f64, options: RenderOptions<'a>) -> Result<Self> {
+        let base = &levels[0];
+        let level = levels
+            .iter()
+            .rev()
+            .find(|level| level.downsample(base) <= downsample * 1.001)
+            .unwrap_or(base);
+        println!(
+            "  {}",
+            t!(
+                "slide-reading-level",
+                width = level.width,
+                height = level.height,
+                file = display_name(&level.path)
+            )
+        );
+        let obj = pipeline::open_object(&level.path)
+            .with_context(|| format!("Failed to open DICOM file: {}", level.path.display()))?;
+        Ok(Self {
+            level,
+            obj,
+            scale: level.downsample(base) / downsample,
+            options,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Where level pixel `position` lands in the output.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn edge(&self, position: u32) -> u32 {
+        (f64::from(position) * self.scale).round() as u32
+    }
+
+    /// The `width` × `height` output pixels from (`x`, `y`).
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn region(&mut self, x: u32, y: u32, width: u32, height: u32) -> Result<RgbImage> {
+        let mut canvas = RgbImage::from_pixel(width, height, BACKGROUND);
+        let level = self.level;
+        let tiles = |start: u32, length: u32, tile: u32| {
+            let first = (f64::from(start) / self.scale / f64::from(tile)).floor() as u32;
+            let end = (f64::from(start + length) / self.scale / f64::from(tile)).ceil() as u32;
+            first..end
+        };
+        let columns = tiles(x, width, level.tile_width);
+        let mut used = HashMap::new();
+        for row in tiles(y, height, level.tile_height) {
+            for column in columns.clone() {
+                let Some(&frame) = level.tiles.get(&(column, row)) else {
+                    continue;
+                };
+                let tile = match self.cache.remove(&frame) {
+                    Some(tile) => tile,
+                    None => self.tile(frame, column, row)?,
+                };
+                let left = i64::from(self.edge(column * level.tile_width)) - i64::from(x);
+                let top = i64::from(self.edge(row * level.tile_height)) - i64::from(y);
+                imageops::replace(&mut canvas, &tile, left, top);
+                used.insert(frame, tile);
+            }
+        }
+        self.cache = used;
+        Ok(canvas)
+    }
+
+    /// Tile `frame`, at `column` and `row` of the grid, cut to the pixel
+    /// matrix and scaled to output pixels.
+    fn tile(&self, frame: u32, column: u32, row: u32) -> Result<RgbImage> {
+        let level = self.level;
+        let decoded = pipeline::decode_opened(&self.obj, &level.path, frame)?;
+        let image = pipeline::render_frame(decoded, self.options.clone()).to_rgb8();
+        let (left, top) = (column * level.tile_width, row * level.tile_height);
+        let (right, bottom) = (
+            (left + level.tile_width).min(level.width),
+            (top + level.tile_height).min(level.height),
+        );
+        let image = if image.dimensions() == (right - left, bottom - top) {
+            image
+        } else {
+            imageops::crop_imm(&image, 0, 0, right - left, bottom - top).to_image()
+        };
+        let size = (
+            (self.edge(right) - self.edge(left)).max(1),
+            (self.edge(bottom) - self.edge(top)).max(1),
+        );
+        if image.dimensions() == size {
+            Ok(image)
+        } else {
+            // Many small tiles: a cheap filter keeps large slides quick
+            Ok(imageops::resize(
+                &image,
+                size.0,
+                size.1,
+                FilterType::Triangle,
+            ))
+        }
+    }
+}
+
+/// Write the slide held by `files` into `output_dir`: one image shrunk to
+/// fit, or a DeepZoom pyramid. Returns the number of images written.
+pub fn convert_to_slide(
+    files: &[PathBuf],
+    output_dir: &Path,
+    slide: SlideOptions,
+    options: RenderOptions<'_>,
+) -> Result<RunStats> {
+    let levels = pyramid(files)?;
+    let base = &levels[0];
+    println!(
+        "  {}",
+        t!(
+            "slide-found",
+            width = base.width,
+            height = base.height,
+            levels = levels.len()
+        )
+    );
+    if let Some(tile_size) = slide.deepzoom {
+        let downsample = slide.downsample.unwrap_or(1);
+        return deepzoom::write(
+            &levels,
+            output_dir,
+            tile_size,
+            downsample,
+            slide.format,
+            options,
+        );
+    }
+
+    let downsample = slide
+        .downsample
+        .unwrap_or_else(|| fitting(base, slide.max_size));
+    let downsample = f64::from(downsample);
+    let (width, height) = (
+        scaled(base.width, downsample),
+        scaled(base.height, downsample),
+    );
+    if width.max(height) > MAX_SIDE {
+        anyhow::bail!(BadInput(format!(
+            "--downsample {downsample} gives a {width}x{height} image, more than {MAX_SIDE} \
+             pixels a side; shrink it further or write a --deepzoom pyramid"
+        )));
+    }
+    let image = Reader::open(&levels, downsample, options)?.region(0, 0, width, height)?;
+    let path = named_after_folder(output_dir, slide.format.extension());
+    image
+        .save_with_format(&path, slide.format.encoding())
+        .with_context(|| format!("Failed to save image: {}", path.display()))?;
+    println!(
+        "{}",
+        t!(
+            "slide-saved",
+            path = path.display().to_string(),
+            width = width,
+            height = height
+        )
+    );
+    Ok(RunStats {
+        written: 1,
+        failed: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+
+    use super::*;
+
+    fn element(
+        tag: dicom::core::Tag,
+        vr: VR,
+        value: PrimitiveValue,
+    ) -> DataElement<InMemDicomObject> {
+        DataElement::new(tag, vr, value)
+    }
+
+    /// A level of 1000 × 600 pixels in 256-pixel tiles (4 × 3), two focal
+    /// planes of `TILED_FULL` frames.
+    fn level() -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            element(
+                tags::IMAGE_TYPE,
+                VR::CS,
+                PrimitiveValue::from("ORIGINAL\\PRIMARY\\VOLUME\\NONE"),
+            ),
+            element(
+                tags::TOTAL_PIXEL_MATRIX_COLUMNS,
+                VR::UL,
+                PrimitiveValue::from(1000_u32),
+            ),
+            element(
+                tags::TOTAL_PIXEL_MATRIX_ROWS,
+                VR::UL,
+                PrimitiveValue::from(600_u32),
+            ),
+            element(tags::COLUMNS, VR::US, PrimitiveValue::from(256_u16)),
+            element(tags::ROWS, VR::US, PrimitiveValue::from(256_u16)),
+            element(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("24")),
+            element(
+                tags::DIMENSION_ORGANIZATION_TYPE,
+                VR::CS,
+                PrimitiveValue::from("TILED_FULL"),
+            ),
+        ])
+    }
+
+    #[test]
+    fn full_tiling_runs_row_by_row() {
+        let level = Level::of(&level(), Path::new("level.dcm")).unwrap();
+        assert_eq!((level.width, level.height), (1000, 600));
+        assert_eq!(level.tiles.len(), 12);
+        assert_eq!(level.tiles[&(0, 0)], 0);
+        assert_eq!(level.tiles[&(3, 0)], 3);
+        assert_eq!(level.tiles[&(1, 2)], 9);
+    }
+
+    #[test]
+    fn sparse_tiles_are_placed_by_position() {
+        let mut obj = level();
+        obj.put(element(
+            tags::DIMENSION_ORGANIZATION_TYPE,
+            VR::CS,
+            PrimitiveValue::from("TILED_SPARSE"),
+        ));
+        obj.put(element(
+            tags::NUMBER_OF_FRAMES,
+            VR::IS,
+            PrimitiveValue::from("2"),
+        ));
+        let frame = |column: i32, row: i32| {
+            let position = InMemDicomObject::from_element_iter([
+                element(
+                    tags::COLUMN_POSITION_IN_TOTAL_IMAGE_PIXEL_MATRIX,
+                    VR::SL,
+                    PrimitiveValue::from(column),
+                ),
+                element(
+                    tags::ROW_POSITION_IN_TOTAL_IMAGE_PIXEL_MATRIX,
+                    VR::SL,
+                    PrimitiveValue::from(row),
+                ),
+            ]);
+            InMemDicomObject::from_element_iter([DataElement::new(
+                tags::PLANE_POSITION_SLIDE_SEQUENCE,
+                VR::SQ,
+                dicom::core::value::DataSetSequence::from(vec![position]),
+            )])
+        };
+        obj.put(DataElement::new(
+            tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE,
+            VR::SQ,
+            dicom::core::value::DataSetSequence::from(vec![frame(513, 257), frame(1, 1)]),
+        ));
+        let level = Level::of(&obj, Path::new("level.dcm")).unwrap();
+        assert_eq!(level.tiles, HashMap::from([((2, 1), 0), ((0, 0), 1)]));
+    }
+
+    #[test]
+    fn labels_are_not_levels() {
+        let mut obj = level();
+        obj.put(element(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            PrimitiveValue::from("ORIGINAL\\PRIMARY\\LABEL\\NONE"),
+        ));
+        assert_eq!(Level::of(&obj, Path::new("label.dcm")), None);
+    }
+
+    /// A 6 × 4 slide in two 4 × 4 tiles, the right one partly past the
+    /// matrix, written to `path`.
+    fn write_slide(path: &Path) {
+        let mut obj = level();
+        let mut put = |tag, vr, value: PrimitiveValue| obj.put(element(tag, vr, value));
+        put(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(uids::VL_WHOLE_SLIDE_MICROSCOPY_IMAGE_STORAGE),
+        );
+        put(
+            tags::TOTAL_PIXEL_MATRIX_COLUMNS,
+            VR::UL,
+            PrimitiveValue::from(6_u32),
+        );
+        put(
+            tags::TOTAL_PIXEL_MATRIX_ROWS,
+            VR::UL,
+            PrimitiveValue::from(4_u32),
+        );
+        put(tags::COLUMNS, VR::US, PrimitiveValue::from(4_u16));
+        put(tags::ROWS, VR::US, PrimitiveValue::from(4_u16));
+        put(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("2"));
+        put(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(1_u16));
+        put(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            PrimitiveValue::from("MONOCHROME2"),
+        );
+        put(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(8_u16));
+        put(tags::BITS_STORED, VR::US, PrimitiveValue::from(8_u16));
+        put(tags::HIGH_BIT, VR::US, PrimitiveValue::from(7_u16));
+        put(
+            tags::PIXEL_REPRESENTATION,
+            VR::US,
+            PrimitiveValue::from(0_u16),
+        );
+        let pixels: Vec<u8> = (0..32).map(|i| i * 8).collect();
+        put(tags::PIXEL_DATA, VR::OB, PrimitiveValue::from(pixels));
+        let meta = dicom::object::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid(uids::VL_WHOLE_SLIDE_MICROSCOPY_IMAGE_STORAGE)
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        obj.with_exact_meta(meta).write_to_file(path).unwrap();
+    }
+
+    #[test]
+    fn tiles_are_reassembled_and_pyramided() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("level.dcm");
+        write_slide(&file);
+        assert!(is_slide(&file));
+        let output_dir = temp_dir.path().join("slide");
+        std::fs::create_dir(&output_dir).unwrap();
+        let slide = SlideOptions {
+            downsample: Some(2),
+            max_size: 8192,
+            deepzoom: None,
+            format: ImageFormat::Png,
+        };
+        let files = [file];
+        convert_to_slide(&files, &output_dir, slide, RenderOptions::default()).unwrap();
+        let image = image::open(output_dir.join("slide.png")).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+
+        let pyramid = SlideOptions {
+            downsample: None,
+            deepzoom: Some(16),
+            ..slide
+        };
+        let stats =
+            convert_to_slide(&files, &output_dir, pyramid, RenderOptions::default()).unwrap();
+        // Levels 3 (6 × 4) down to 0 (1 × 1), one tile each
+        assert_eq!(stats.written, 4);
+        assert!(output_dir.join("slide.dzi").is_file());
+        let top = image::open(output_dir.join("slide_files/3/0_0.png")).unwrap();
+        assert_eq!((top.width(), top.height()), (6, 4));
+        let bottom = image::open(output_dir.join("slide_files/0/0_0.png")).unwrap();
+        assert_eq!((bottom.width(), bottom.height()), (1, 1));
+    }
+
+    #[test]
+    fn downsample_fits_the_longest_side() {
+        let level = Level::of(&level(), Path::new("level.dcm")).unwrap();
+        assert_eq!(fitting(&level, 8192), 1);
+        assert_eq!(fitting(&level, 300), 4);
+        assert_eq!(scaled(1000, 4.0), 250);
+        assert_eq!(scaled(3, 8.0), 1);
+    }
+}
@@ -0,0 +1,123 @@
+//! DeepZoom pyramids of whole slides (`slide --deepzoom`).
+//!
+//! Writes `<name>.dzi` and `<name>_files/<level>/<column>_<row>.<ext>` as
+//! OpenSeadragon and other DeepZoom viewers expect. The top level is the
+//! slide at `--downsample` (full resolution by default); each level below
+//! halves it, down to a single pixel. Levels are rendered in strips one tile
+//! tall, so memory holds one strip whatever the size of the slide. Tiles do
+//! not overlap.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::imageops;
+
+use super::{Level, Reader, scaled};
+use crate::convert::ImageFormat;
+use crate::i18n::t;
+use crate::pipeline::{RenderOptions, RunStats};
+use crate::utils::named_after_folder;
+
+/// Write the pyramid of the slide of `levels` shrunk by `downsample` into
+/// `output_dir`, in tiles of `tile_size` pixels a side.
+pub(super) fn write(
+    levels: &[Level],
+    output_dir: &Path,
+    tile_size: u32,
+    downsample: u32,
+    format: ImageFormat,
+    options: RenderOptions<'_>,
+) -> Result<RunStats> {
+    let base = &levels[0];
+    let downsample = f64::from(downsample);
+    let (width, height) = (
+        scaled(base.width, downsample),
+        scaled(base.height, downsample),
+    );
+    let top = top_level(width, height);
+    let descriptor = named_after_folder(output_dir, "dzi");
+    let mut tiles_dir = descriptor.with_extension("").into_os_string();
+    tiles_dir.push("_files");
+    let tiles_dir = PathBuf::from(tiles_dir);
+
+    let mut written = 0;
+    for level in (0..=top).rev() {
+        let shrink = 1_u64 << (top - level);
+        let size = |length: u32| u32::try_from(u64::from(length).div_ceil(shrink)).unwrap_or(1);
+        let (level_width, level_height) = (size(width), size(height));
+        #[allow(clippy::cast_precision_loss)]
+        let mut reader = Reader::open(levels, downsample * shrink as f64, options.clone())?;
+        let dir = tiles_dir.join(level.to_string());
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        for row in 0..level_height.div_ceil(tile_size) {
+            options.cancel.check()?;
+            let y = row * tile_size;
+            let strip_height = tile_size.min(level_height - y);
+            let strip = reader.region(0, y, level_width, strip_height)?;
+            for column in 0..level_width.div_ceil(tile_size) {
+                let x = column * tile_size;
+                let tile =
+                    imageops::crop_imm(&strip, x, 0, tile_size.min(level_width - x), strip_height);
+                let path = dir.join(format!("{column}_{row}.{}", format.extension()));
+                tile.to_image()
+                    .save_with_format(&path, format.encoding())
+                    .with_context(|| format!("Failed to save image: {}", path.display()))?;
+                written += 1;
+            }
+        }
+    }
+
+    fs::write(&descriptor, dzi(width, height, tile_size, format))
+        .with_context(|| format!("Failed to write {}", descriptor.display()))?;
+    println!(
+        "{}",
+        t!(
+            "slide-deepzoom-saved",
+            path = descriptor.display().to_string(),
+            levels = top + 1,
+            tiles = written
+        )
+    );
+    Ok(RunStats { written, failed: 0 })
+}
+
+/// Number of the full-size level: levels halve down to one pixel at 0.
+fn top_level(width: u32, height: u32) -> u32 {
+    let longest = width.max(height).max(1);
+    u32::BITS - (longest - 1).leading_zeros()
+}
+
+/// The `.dzi` descriptor of a `width` × `height` pyramid.
+fn dzi(width: u32, height: u32, tile_size: u32, format: ImageFormat) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" \
+         Overlap=\"0\" TileSize=\"{tile_size}\">\n  \
+         <Size Width=\"{width}\" Height=\"{height}\"/>\n\
+         </Image>\n",
+        format.extension()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_halve_down_to_one_pixel() {
+        assert_eq!(top_level(1, 1), 0);
+        assert_eq!(top_level(2, 1), 1);
+        assert_eq!(top_level(1000, 600), 10);
+        assert_eq!(top_level(1024, 3), 10);
+        assert_eq!(top_level(100_000, 80_000), 17);
+    }
+
+    #[test]
+    fn descriptor_names_size_and_tiles() {
+        let xml = dzi(1000, 600, 256, ImageFormat::Jpeg);
+        assert!(xml.contains("Format=\"jpg\" Overlap=\"0\" TileSize=\"256\""));
+        assert!(xml.contains("<Size Width=\"1000\" Height=\"600\"/>"));
+    }
+}
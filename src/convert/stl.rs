@@ -1,360 +1,968 @@
 //! DICOM to STL 3D model conversion module.
 //!
-//! Converts a group of DICOM slices into a 3D surface mesh (binary STL format)
-//! using the Marching Cubes algorithm. Supports optional Gaussian smoothing
-//! and automatic Otsu thresholding for isosurface extraction.
-
+//! Converts a group of DICOM slices into a 3D surface mesh (binary STL by
+//! default; PLY, OBJ, glTF binary, and 3MF on request) using the Marching
+//! Cubes algorithm. Supports optional Gaussian smoothing and automatic Otsu
+//! thresholding for isosurface extraction. The standalone `stl` command
+//! reuses [`write_model`] and adds cropping, decimation, and multi-part
+//! models ([`write_parts`]).
+
+mod glb;
+mod obj;
+mod parts;
+mod ply;
+mod preview;
+mod shell;
+mod targets;
+mod threemf;
+mod turntable;
+
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use anyhow::{Context, Result};
-use dicom::dictionary_std::tags;
-use dicom::object::open_file;
-use dicom_pixeldata::PixelDecoder;
+use clap::{Args, ValueEnum};
 use lin_alg::f32::Vec3;
-use mcubes::{MarchingCubes, MeshSide};
+use mcubes::{MarchingCubes, Mesh, MeshSide, Vertex};
+
+use super::suv;
+use crate::events::{self, Stage};
+use crate::i18n::t;
+use crate::mask::{self, otsu_threshold};
+use crate::outcome::BadInput;
+use crate::pipeline;
+use crate::utils::named_after_folder;
+use crate::volume::Volume;
+
+pub use parts::Part;
+pub use targets::{Presets, Target};
 
 /// Minimum number of slices required for meaningful 3D reconstruction.
-const MIN_SLICES_FOR_3D: usize = 5;
+pub const MIN_SLICES_FOR_3D: usize = 5;
+
+/// Voxel box kept by `stl --crop`: half-open index ranges along X, Y, Z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    /// First kept voxel per axis.
+    pub min: [usize; 3],
+    /// One past the last kept voxel per axis (clamped to the volume).
+    pub max: [usize; 3],
+}
 
-/// Default slice thickness when metadata is unavailable (mm).
-const DEFAULT_SLICE_THICKNESS: f32 = 1.0;
+impl FromStr for Crop {
+    type Err = String;
 
-/// Default pixel spacing when metadata is unavailable (mm).
-const DEFAULT_PIXEL_SPACING: f32 = 1.0;
+    /// Parse `X0:X1,Y0:Y1,Z0:Z1`; a missing bound leaves that side open.
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let ranges: Vec<&str> = s.split(',').collect();
+        let [x, y, z] = ranges.as_slice() else {
+            return Err("expected X0:X1,Y0:Y1,Z0:Z1".to_string());
+        };
 
-/// Number of histogram bins for Otsu thresholding.
-const HISTOGRAM_BINS: usize = 256;
+        let mut crop = Self {
+            min: [0; 3],
+            max: [usize::MAX; 3],
+        };
+        for (axis, range) in [x, y, z].into_iter().enumerate() {
+            let (lo, hi) = range
+                .split_once(':')
+                .ok_or_else(|| format!("`{range}` is not a START:END range"))?;
+            let bound = |text: &str, open: usize| {
+                let text = text.trim();
+                if text.is_empty() {
+                    Ok(open)
+                } else {
+                    text.parse()
+                        .map_err(|_| format!("`{text}` is not a voxel index"))
+                }
+            };
+            crop.min[axis] = bound(lo, 0)?;
+            crop.max[axis] = bound(hi, usize::MAX)?;
+            if crop.min[axis] >= crop.max[axis] {
+                return Err(format!("`{range}` is empty (end must be after start)"));
+            }
+        }
+        Ok(crop)
+    }
+}
 
-/// Holds the 3D volumetric data built from stacked DICOM slices.
-struct VolumeData {
-    /// Flat array of voxel intensities (0.0–255.0), packed X-fastest.
-    values: Vec<f32>,
-    /// Number of columns (X dimension).
-    cols: usize,
-    /// Number of rows (Y dimension).
-    rows: usize,
-    /// Number of slices (Z dimension).
-    slices: usize,
-    /// Physical pixel spacing along X in mm.
-    spacing_x: f32,
-    /// Physical pixel spacing along Y in mm.
-    spacing_y: f32,
-    /// Physical slice spacing along Z in mm.
-    spacing_z: f32,
+/// Settings for turning a volume into a surface mesh.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeshOptions {
+    /// Isosurface level; Otsu's threshold when `None`.
+    pub iso_level: Option<f32>,
+    /// Gaussian smoothing sigma in voxels (0 disables smoothing).
+    pub smooth_sigma: f32,
+    /// Remove air, table, and noise around the patient first.
+    pub strip_background: bool,
+    /// Only mesh this voxel box.
+    pub crop: Option<Crop>,
+    /// Vertex clustering cell size in mm.
+    pub decimate: Option<f32>,
+    /// Hollow the model to walls this thick, in mm.
+    pub shell_thickness: Option<f32>,
+    /// Units, scale, and placement of the written mesh.
+    pub output: MeshOutput,
+    /// Mesh at this target's preset level (needs `presets`).
+    pub target: Option<Target>,
+    /// CT presets of the series, printed as suggestions when known.
+    pub presets: Option<Presets>,
+    /// Also render an MP4 of the model turning once in this many seconds.
+    pub turntable: Option<f32>,
 }
 
-/// Convert a group of sorted DICOM files into a binary STL 3D model.
-#[allow(clippy::cast_precision_loss)]
-pub fn convert_to_stl(
-    dcm_files: &[PathBuf],
-    output_dir: &Path,
-    iso_level: Option<f32>,
-    smooth_sigma: f32,
-) -> Result<()> {
-    if dcm_files.len() < MIN_SLICES_FOR_3D {
-        anyhow::bail!(
-            "Need at least {MIN_SLICES_FOR_3D} slices for 3D reconstruction, got {}",
-            dcm_files.len()
-        );
-    }
+/// Length unit of STL coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MeshUnits {
+    /// Millimeters (DICOM patient coordinates)
+    #[default]
+    Mm,
+    /// Centimeters
+    Cm,
+    /// Meters
+    M,
+}
 
-    println!("  Building 3D volume from {} slices...", dcm_files.len());
-    let volume = build_volume(dcm_files)?;
-    println!(
-        "  Volume: {}x{}x{} (spacing: {:.2}x{:.2}x{:.2} mm)",
-        volume.cols,
-        volume.rows,
-        volume.slices,
-        volume.spacing_x,
-        volume.spacing_y,
-        volume.spacing_z
-    );
+impl MeshUnits {
+    /// Units per millimeter.
+    const fn per_mm(self) -> f32 {
+        match self {
+            Self::Mm => 1.0,
+            Self::Cm => 0.1,
+            Self::M => 0.001,
+        }
+    }
+}
 
-    // Apply Gaussian smoothing if sigma > 0
-    let smoothed_values = if smooth_sigma > 0.0 {
-        println!("  Applying Gaussian smoothing (sigma={smooth_sigma:.2})...");
-        gaussian_smooth_3d(
-            &volume.values,
-            volume.cols,
-            volume.rows,
-            volume.slices,
-            smooth_sigma,
-        )
-    } else {
-        volume.values.clone()
-    };
+/// Coordinate system of written meshes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MeshCoords {
+    /// Millimeters from the first voxel of the series
+    #[default]
+    Voxel,
+    /// DICOM patient coordinates (+X left, +Y posterior, +Z superior)
+    Lps,
+    /// Patient coordinates as used by 3D Slicer (+X right, +Y anterior, +Z superior)
+    Ras,
+}
 
-    // Determine iso level via Otsu or use user-provided value
-    let threshold = iso_level.unwrap_or_else(|| {
-        let t = otsu_threshold(&smoothed_values);
-        println!("  Auto-detected Otsu threshold: {t:.2}");
-        t
-    });
-    if iso_level.is_some() {
-        println!("  Using user-specified iso-level: {threshold:.2}");
+impl MeshCoords {
+    /// Position (mm) of the voxel center at `(x, y, z)` in this system.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn position(self, volume: &Volume, x: usize, y: usize, z: usize) -> [f32; 3] {
+        match self {
+            Self::Voxel => [
+                x as f32 * volume.spacing_x,
+                y as f32 * volume.spacing_y,
+                z as f32 * volume.spacing_z,
+            ],
+            Self::Lps => volume.position(x, y, z).map(|c| c as f32),
+            Self::Ras => {
+                let lps = volume.position(x, y, z).map(|c| c as f32);
+                [-lps[0], -lps[1], lps[2]]
+            }
+        }
     }
+}
 
-    println!("  Running Marching Cubes...");
-    let mc = MarchingCubes::new(
-        (volume.cols, volume.rows, volume.slices),
-        (
-            volume.cols as f32 * volume.spacing_x,
-            volume.rows as f32 * volume.spacing_y,
-            volume.slices as f32 * volume.spacing_z,
-        ),
-        (volume.cols as f32, volume.rows as f32, volume.slices as f32),
-        Vec3::new_zero(),
-        smoothed_values,
-        threshold,
-    )?;
-    let mesh = mc.generate(MeshSide::OutsideOnly);
+/// Mesh file format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MeshFormat {
+    /// Binary STL (face normals only)
+    #[default]
+    Stl,
+    /// Binary PLY with vertex normals and part colors
+    Ply,
+    /// Wavefront OBJ with vertex normals, one object per part
+    Obj,
+    /// Binary glTF with vertex normals and part colors
+    Glb,
+    /// 3MF with named, colored parts
+    #[value(name = "3mf")]
+    ThreeMf,
+}
 
-    let vertex_count = mesh.vertices.len();
-    let triangle_count = mesh.indices.len() / 3;
+impl MeshFormat {
+    /// File extension for this format.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Stl => "stl",
+            Self::Ply => "ply",
+            Self::Obj => "obj",
+            Self::Glb => "glb",
+            Self::ThreeMf => "3mf",
+        }
+    }
 
-    if triangle_count == 0 {
-        anyhow::bail!(
-            "Marching Cubes produced no triangles. Try adjusting --iso-level (current: {threshold:.2})"
-        );
+    /// Format picked by a model path's extension; STL when unknown.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ply") => Self::Ply,
+            Some("obj") => Self::Obj,
+            Some("glb") => Self::Glb,
+            Some("3mf") => Self::ThreeMf,
+            _ => Self::Stl,
+        }
     }
 
-    println!("  Mesh: {vertex_count} vertices, {triangle_count} triangles");
+    /// Format name for messages.
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Stl => "STL",
+            Self::Ply => "PLY",
+            Self::Obj => "OBJ",
+            Self::Glb => "glTF",
+            Self::ThreeMf => "3MF",
+        }
+    }
+}
 
-    // Write binary STL
-    let stl_name = output_dir
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("output");
-    let stl_path = output_dir.join(format!("{stl_name}.stl"));
-    write_stl_file(&mesh, &stl_path)?;
+/// Units, scale, and placement of written meshes, shared by `convert … stl`
+/// and `stl`.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug, Clone, Copy)]
+pub struct MeshOutput {
+    /// Coordinate system of the STL
+    #[arg(long, value_enum, default_value_t = MeshCoords::Voxel)]
+    pub mesh_coords: MeshCoords,
+
+    /// Mirror the model along X (negates X coordinates)
+    #[arg(long)]
+    pub flip_x: bool,
+
+    /// Mirror the model along Y (negates Y coordinates)
+    #[arg(long)]
+    pub flip_y: bool,
+
+    /// Mirror the model along Z (negates Z coordinates)
+    #[arg(long)]
+    pub flip_z: bool,
+
+    /// Length unit of the STL coordinates
+    #[arg(long, value_enum, default_value_t = MeshUnits::Mm)]
+    pub mesh_units: MeshUnits,
+
+    /// Scale the model by this factor (e.g. 0.5 for a half-size print)
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_positive)]
+    pub mesh_scale: f32,
+
+    /// Move the center of the model's bounding box to the origin
+    #[arg(long)]
+    pub center: bool,
+}
 
-    println!("✓ STL saved to: {}", stl_path.display());
-    Ok(())
+impl Default for MeshOutput {
+    fn default() -> Self {
+        Self {
+            mesh_coords: MeshCoords::Voxel,
+            flip_x: false,
+            flip_y: false,
+            flip_z: false,
+            mesh_units: MeshUnits::Mm,
+            mesh_scale: 1.0,
+            center: false,
+        }
+    }
 }
 
-/// Build a 3D volume from sorted DICOM slices.
-///
-/// Each slice is converted to 8-bit grayscale. Pixel spacing and slice
-/// thickness are extracted from DICOM metadata when available.
-#[allow(clippy::cast_possible_truncation)]
-fn build_volume(dcm_files: &[PathBuf]) -> Result<VolumeData> {
-    // Read metadata from the first file to establish dimensions
-    let first_obj = open_file(&dcm_files[0])
-        .with_context(|| format!("Failed to open first DICOM file: {}", dcm_files[0].display()))?;
-
-    let rows = first_obj
-        .element(tags::ROWS)
-        .ok()
-        .and_then(|e| e.to_int::<u32>().ok())
-        .unwrap_or(0) as usize;
-    let cols = first_obj
-        .element(tags::COLUMNS)
-        .ok()
-        .and_then(|e| e.to_int::<u32>().ok())
-        .unwrap_or(0) as usize;
-
-    if rows == 0 || cols == 0 {
-        anyhow::bail!("Invalid image dimensions: {cols}x{rows}");
-    }
-
-    // Extract pixel spacing (Y\X format in DICOM)
-    let (spacing_y, spacing_x) = first_obj
-        .element(tags::PIXEL_SPACING)
-        .ok()
-        .and_then(|e| e.to_str().ok())
-        .and_then(|s| {
-            let parts: Vec<f32> = s
-                .split('\\')
-                .filter_map(|v| v.trim().parse::<f32>().ok())
-                .collect();
-            if parts.len() >= 2 {
-                Some((parts[0], parts[1]))
-            } else {
-                None
+impl MeshOutput {
+    /// Move mesh coordinates (mm from the first voxel) into the requested
+    /// coordinate system, mirror, center, and scale them in place; `origin`
+    /// is the patient position of the first voxel.
+    ///
+    /// Parts of one model are centered on their combined bounding box so
+    /// they stay in register. Mirroring an odd number of axes reverses the
+    /// triangle winding so normals keep pointing outward.
+    #[allow(clippy::cast_possible_truncation)]
+    fn place(self, meshes: &mut [Mesh], origin: [f64; 3]) {
+        let origin = match self.mesh_coords {
+            MeshCoords::Voxel => [0.0; 3],
+            MeshCoords::Lps | MeshCoords::Ras => origin.map(|c| c as f32),
+        };
+        let mut sign = match self.mesh_coords {
+            MeshCoords::Ras => [-1.0, -1.0, 1.0],
+            MeshCoords::Voxel | MeshCoords::Lps => [1.0; 3],
+        };
+        for (axis, flip) in [self.flip_x, self.flip_y, self.flip_z]
+            .into_iter()
+            .enumerate()
+        {
+            if flip {
+                sign[axis] = -sign[axis];
             }
-        })
-        .unwrap_or((DEFAULT_PIXEL_SPACING, DEFAULT_PIXEL_SPACING));
-
-    // Compute Z spacing from first two slice positions, or fall back to SliceThickness
-    let spacing_z = compute_slice_spacing(dcm_files).unwrap_or_else(|| {
-        first_obj
-            .element(tags::SLICE_THICKNESS)
-            .ok()
-            .and_then(|e| e.to_str().ok())
-            .and_then(|s| s.trim().parse::<f32>().ok())
-            .unwrap_or(DEFAULT_SLICE_THICKNESS)
-    });
+        }
+        let mirrored = sign[0] * sign[1] * sign[2] < 0.0;
+        for mesh in meshes.iter_mut() {
+            for vertex in &mut mesh.vertices {
+                let p = &mut vertex.posit;
+                p.x = (p.x + origin[0]) * sign[0];
+                p.y = (p.y + origin[1]) * sign[1];
+                p.z = (p.z + origin[2]) * sign[2];
+            }
+            if mirrored {
+                for tri in mesh.indices.chunks_exact_mut(3) {
+                    tri.swap(1, 2);
+                }
+            }
+        }
 
-    let num_slices = dcm_files.len();
-    let slice_size = cols * rows;
-    let mut values = vec![0.0_f32; slice_size * num_slices];
+        let shift = if self.center {
+            let (min, max) = meshes.iter().map(bounds).fold(
+                ([f32::MAX; 3], [f32::MIN; 3]),
+                |(lo, hi), (min, max)| {
+                    (
+                        std::array::from_fn(|i| lo[i].min(min[i])),
+                        std::array::from_fn(|i| hi[i].max(max[i])),
+                    )
+                },
+            );
+            Vec3::new(
+                min[0].midpoint(max[0]),
+                min[1].midpoint(max[1]),
+                min[2].midpoint(max[2]),
+            )
+        } else {
+            Vec3::new_zero()
+        };
+        for vertex in meshes.iter_mut().flat_map(|mesh| &mut mesh.vertices) {
+            vertex.posit = (vertex.posit - shift) * self.mesh_scale;
+            let n = &mut vertex.normal;
+            (n.x, n.y, n.z) = (n.x * sign[0], n.y * sign[1], n.z * sign[2]);
+        }
+    }
+}
 
-    for (z, dcm_path) in dcm_files.iter().enumerate() {
-        let dicom_obj = open_file(dcm_path)
-            .with_context(|| format!("Failed to open DICOM file: {}", dcm_path.display()))?;
+/// Parse a positive, finite number (`--decimate`, `--mesh-scale`).
+pub fn parse_positive(value: &str) -> std::result::Result<f32, String> {
+    let number: f32 = value
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))?;
+    if number.is_finite() && number > 0.0 {
+        Ok(number)
+    } else {
+        Err("must be greater than 0".to_string())
+    }
+}
 
-        let pixel_data = dicom_obj
-            .decode_pixel_data()
-            .with_context(|| format!("Failed to decode pixel data: {}", dcm_path.display()))?;
+/// Bounding box `(min, max)` of the mesh vertices; inverted when empty.
+fn bounds(mesh: &Mesh) -> ([f32; 3], [f32; 3]) {
+    mesh.vertices
+        .iter()
+        .map(|v| [v.posit.x, v.posit.y, v.posit.z])
+        .fold(([f32::MAX; 3], [f32::MIN; 3]), |(lo, hi), p| {
+            (
+                std::array::from_fn(|i| lo[i].min(p[i])),
+                std::array::from_fn(|i| hi[i].max(p[i])),
+            )
+        })
+}
 
-        let img = pixel_data
-            .to_dynamic_image(0)
-            .with_context(|| format!("Failed to convert to image: {}", dcm_path.display()))?;
+/// Join meshes into one (e.g. all parts for an STL or the preview).
+fn merge<'a>(meshes: impl IntoIterator<Item = &'a Mesh>) -> Mesh {
+    let mut merged = Mesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+    for mesh in meshes {
+        let base = merged.vertices.len();
+        merged.vertices.extend(mesh.vertices.iter().map(|v| Vertex {
+            posit: v.posit,
+            normal: v.normal,
+        }));
+        merged.indices.extend(mesh.indices.iter().map(|i| i + base));
+    }
+    merged
+}
 
-        let gray = img.to_luma8();
+/// Measurements of a written mesh, for 3D printing estimates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshStats {
+    /// The STL file.
+    pub path: PathBuf,
+    /// Vertex count.
+    pub vertices: usize,
+    /// Triangle count.
+    pub triangles: usize,
+    /// Total triangle area in mm².
+    pub surface_area: f32,
+    /// Enclosed volume in mm³ (approximate where the mesh is open, e.g. at a
+    /// crop boundary).
+    pub volume: f32,
+    /// Bounding box size along X, Y, Z in mm.
+    pub size: [f32; 3],
+}
 
-        // Ensure consistent dimensions
-        if gray.width() as usize != cols || gray.height() as usize != rows {
-            anyhow::bail!(
-                "Inconsistent slice dimensions: expected {cols}x{rows}, got {}x{} in {}",
-                gray.width(),
-                gray.height(),
-                dcm_path.display()
-            );
+impl MeshStats {
+    /// Measure a mesh written to `path`.
+    pub fn measure(mesh: &Mesh, path: &Path) -> Self {
+        let (min, max) = bounds(mesh);
+        let surface_area = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|tri| {
+                let [v0, v1, v2] = [tri[0], tri[1], tri[2]].map(|i| mesh.vertices[i].posit);
+                (v1 - v0).cross(v2 - v0).magnitude() / 2.0
+            })
+            .sum();
+
+        Self {
+            path: path.to_path_buf(),
+            vertices: mesh.vertices.len(),
+            triangles: mesh.indices.len() / 3,
+            surface_area,
+            volume: mesh.volume(),
+            size: std::array::from_fn(|i| (max[i] - min[i]).max(0.0)),
         }
+    }
 
-        // Pack into the flat volume array
-        // mcubes indexes as: values[x + y * cols + z * cols * rows]
-        // (X varies fastest, Z varies slowest)
-        for y in 0..rows {
-            for x in 0..cols {
-                let pixel_val = f32::from(gray.get_pixel(x as u32, y as u32).0[0]);
-                let idx = x + y * cols + z * cols * rows;
-                values[idx] = pixel_val;
-            }
-        }
+    /// Bounding box as `XxYxZ` in mm.
+    pub fn size_text(&self) -> String {
+        let [x, y, z] = self.size;
+        format!("{x:.1}x{y:.1}x{z:.1}")
+    }
 
-        println!(
-            "  ✓ Loaded slice {}/{}: {}",
-            z + 1,
-            num_slices,
-            dcm_path.file_name().unwrap().display()
+    /// `mesh key=value ...` line for the run summary. The path comes last
+    /// and is written as-is, so it may contain spaces.
+    pub fn line(&self) -> String {
+        format!(
+            "mesh vertices={} triangles={} area_mm2={:.1} volume_mm3={:.1} size_mm={} path={}",
+            self.vertices,
+            self.triangles,
+            self.surface_area,
+            self.volume,
+            self.size_text(),
+            self.path.display()
+        )
+    }
+}
+
+/// Convert a group of sorted DICOM files into a 3D model file, with PET
+/// values scaled to SUV first if `suv`.
+pub fn convert_to_stl(
+    dcm_files: &[PathBuf],
+    output_dir: &Path,
+    mut options: MeshOptions,
+    format: MeshFormat,
+    suv: bool,
+) -> Result<MeshStats> {
+    // A multi-frame object (e.g. tomosynthesis) holds a slice per frame
+    let slices = pipeline::count_frames(dcm_files);
+    if slices < MIN_SLICES_FOR_3D {
+        anyhow::bail!(
+            "Need at least {MIN_SLICES_FOR_3D} slices for 3D reconstruction, got {slices}"
         );
     }
 
-    Ok(VolumeData {
-        values,
-        cols,
-        rows,
-        slices: num_slices,
-        spacing_x,
-        spacing_y,
-        spacing_z,
-    })
+    println!("  {}", t!("stl-building-volume", count = slices));
+    let mut volume = Volume::load(dcm_files)?;
+    if suv {
+        suv::scale_volume(&mut volume, dcm_files)?;
+    }
+    options.presets = Presets::detect(&dcm_files[0], &volume.values);
+    let path = named_after_folder(output_dir, format.extension());
+    events::stage(output_dir, Stage::Meshing);
+    write_model(volume, options, &path)
 }
 
-/// Compute the Z spacing between slices from `ImagePositionPatient` tags.
-#[allow(clippy::cast_possible_truncation)]
-fn compute_slice_spacing(dcm_files: &[PathBuf]) -> Option<f32> {
-    if dcm_files.len() < 2 {
-        return None;
-    }
-
-    let z_pos = |path: &PathBuf| -> Option<f64> {
-        let obj = open_file(path).ok()?;
-        let s = obj
-            .element(tags::IMAGE_POSITION_PATIENT)
-            .ok()?
-            .to_str()
-            .ok()?;
-        let coords: Vec<f64> = s
-            .split('\\')
-            .filter_map(|v| v.trim().parse::<f64>().ok())
-            .collect();
-        coords.get(2).copied()
-    };
-
-    let z0 = z_pos(&dcm_files[0])?;
-    let z1 = z_pos(&dcm_files[1])?;
-    let spacing = (z1 - z0).abs();
+/// Mesh a loaded volume and save it in the format of `path`'s extension
+/// (binary STL unless it is `.ply`, `.obj`, `.glb`, or `.3mf`).
+///
+/// Coordinates are in mm from the first voxel of the uncropped volume, so a
+/// cropped model lines up with the full one.
+pub fn write_model(volume: Volume, options: MeshOptions, path: &Path) -> Result<MeshStats> {
+    let prepared = prepare(volume, options)?;
+    let mut values = smooth(
+        &prepared.volume,
+        &prepared.volume.values,
+        options.smooth_sigma,
+    );
+    let threshold = iso_threshold(&values, options)?;
+    if let Some(thickness) = options.shell_thickness {
+        let volume = &prepared.volume;
+        let removed = shell::hollow(
+            &mut values,
+            [volume.cols, volume.rows, volume.slices],
+            [volume.spacing_x, volume.spacing_y, volume.spacing_z],
+            threshold,
+            thickness,
+        );
+        println!(
+            "  {}",
+            t!(
+                "stl-shell",
+                thickness = format!("{thickness:.1}"),
+                voxels = removed
+            )
+        );
+    }
 
-    if spacing > 0.0 {
-        Some(spacing as f32)
-    } else {
-        None
+    let mesh = surface(&prepared, values, threshold)?;
+    if mesh.indices.is_empty() {
+        anyhow::bail!(
+            "Marching Cubes produced no triangles. Try adjusting --iso-level (current: {threshold:.2})"
+        );
     }
+    let mesh = simplify(mesh, options.decimate);
+
+    let name = path
+        .file_stem()
+        .map_or_else(|| "model".to_string(), |s| s.to_string_lossy().into_owned());
+    let parts = vec![(name, parts::palette(0), mesh)];
+    let mut stats = save(parts, path, options, prepared.origin)?;
+    Ok(stats.remove(0))
 }
 
-/// Compute the optimal threshold using Otsu's method.
+/// Segment a loaded volume into value bands and save them as one 3MF with
+/// a named, colored object per part.
 ///
-/// Maximizes inter-class variance on a 256-bin histogram to find the
-/// threshold that best separates foreground from background.
-#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-fn otsu_threshold(values: &[f32]) -> f32 {
-    if values.is_empty() {
-        return 0.0;
+/// Parts that are empty (e.g. cut away by `--crop`) are reported and left
+/// out; the model needs at least one non-empty part.
+pub fn write_parts(
+    volume: Volume,
+    options: MeshOptions,
+    parts: &[Part],
+    path: &Path,
+) -> Result<Vec<MeshStats>> {
+    let prepared = prepare(volume, options)?;
+
+    let mut meshes = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        println!(
+            "  {}",
+            t!(
+                "stl-part",
+                name = part.name.as_str(),
+                range = part.range_text()
+            )
+        );
+        let mask = part.mask(&prepared.volume.values);
+        let values = smooth(&prepared.volume, &mask, options.smooth_sigma);
+        let mesh = surface(&prepared, values, 0.5)?;
+        if mesh.indices.is_empty() {
+            eprintln!("  {}", t!("stl-part-empty", name = part.name.as_str()));
+            continue;
+        }
+        let color = parts::color(part, index);
+        meshes.push((part.name.clone(), color, simplify(mesh, options.decimate)));
     }
 
-    // Find value range
-    let min_val = values.iter().copied().reduce(f32::min).unwrap_or(0.0);
-    let max_val = values.iter().copied().reduce(f32::max).unwrap_or(255.0);
-    let range = max_val - min_val;
+    if meshes.is_empty() {
+        anyhow::bail!(BadInput(
+            "Every --part is empty; check the value bands against the volume's range".to_string()
+        ));
+    }
+    save(meshes, path, options, prepared.origin)
+}
 
-    if range <= 0.0 {
-        return min_val;
+/// Volume ready for meshing, with the placement of its first voxel.
+struct Prepared {
+    volume: Volume,
+    /// Mesh offset (mm) of the cropped volume within the full one.
+    offset: Vec3,
+    /// Patient position of the first voxel of the uncropped volume.
+    origin: [f64; 3],
+}
+
+/// Report the volume, then apply `--crop` and `--strip-background`.
+fn prepare(mut volume: Volume, options: MeshOptions) -> Result<Prepared> {
+    println!(
+        "  {}",
+        t!(
+            "stl-volume",
+            cols = volume.cols,
+            rows = volume.rows,
+            slices = volume.slices,
+            spacing = format!(
+                "{:.2}x{:.2}x{:.2}",
+                volume.spacing_x, volume.spacing_y, volume.spacing_z
+            )
+        )
+    );
+
+    let origin = volume.origin;
+    let mut offset = Vec3::new_zero();
+    if let Some(crop) = options.crop {
+        (volume, offset) = crop_volume(&volume, crop)?;
     }
 
-    // Build histogram
-    let mut histogram = [0u64; HISTOGRAM_BINS];
-    let scale = (HISTOGRAM_BINS - 1) as f32 / range;
+    if options.strip_background {
+        let removed =
+            mask::strip_background_3d(&mut volume.values, volume.cols, volume.rows, volume.slices);
+        println!("  {}", t!("stl-stripped-background", voxels = removed));
+    }
 
-    for &val in values {
-        let bin = ((val - min_val) * scale) as usize;
-        let bin = bin.min(HISTOGRAM_BINS - 1);
-        histogram[bin] += 1;
+    Ok(Prepared {
+        volume,
+        offset,
+        origin,
+    })
+}
+
+/// Apply Gaussian smoothing to voxel values if sigma > 0.
+fn smooth(volume: &Volume, values: &[f32], sigma: f32) -> Vec<f32> {
+    if sigma > 0.0 {
+        println!("  {}", t!("stl-smoothing", sigma = format!("{sigma:.2}")));
+        gaussian_smooth_3d(values, volume.cols, volume.rows, volume.slices, sigma)
+    } else {
+        values.to_vec()
     }
+}
 
-    let total = values.len() as f64;
+/// Run Marching Cubes over values laid out like the prepared volume.
+#[allow(clippy::cast_precision_loss)]
+fn surface(prepared: &Prepared, values: Vec<f32>, level: f32) -> Result<Mesh> {
+    let volume = &prepared.volume;
+    println!("  {}", t!("stl-marching-cubes"));
+    let mc = MarchingCubes::new(
+        (volume.cols, volume.rows, volume.slices),
+        (
+            volume.cols as f32 * volume.spacing_x,
+            volume.rows as f32 * volume.spacing_y,
+            volume.slices as f32 * volume.spacing_z,
+        ),
+        (volume.cols as f32, volume.rows as f32, volume.slices as f32),
+        prepared.offset,
+        values,
+        level,
+    )?;
+    Ok(weld(&mc.generate(MeshSide::OutsideOnly)))
+}
 
-    // Compute total weighted sum
-    let total_sum: f64 = histogram
+/// Share vertices between triangles that meet at the same position.
+///
+/// Marching Cubes emits three vertices per triangle; welding them is what
+/// makes averaged vertex normals (and slicer-friendly meshes) possible.
+/// Triangles that collapse to an edge or point are dropped.
+fn weld(mesh: &Mesh) -> Mesh {
+    let mut ids: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut vertices = Vec::new();
+    let remap: Vec<usize> = mesh
+        .vertices
         .iter()
-        .enumerate()
-        .map(|(i, &count)| i as f64 * count as f64)
-        .sum();
-
-    let mut best_threshold_first = 0;
-    let mut best_threshold_last = 0;
-    let mut best_variance = 0.0_f64;
-    let mut background_count = 0.0_f64;
-    let mut background_sum = 0.0_f64;
-
-    for (t, &count) in histogram.iter().enumerate() {
-        background_count += count as f64;
-        if background_count == 0.0 {
-            continue;
-        }
+        .map(|vertex| {
+            let p = vertex.posit;
+            *ids.entry([p.x, p.y, p.z].map(f32::to_bits))
+                .or_insert_with(|| {
+                    vertices.push(Vertex {
+                        posit: p,
+                        normal: vertex.normal,
+                    });
+                    vertices.len() - 1
+                })
+        })
+        .collect();
+
+    let indices = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|tri| [remap[tri[0]], remap[tri[1]], remap[tri[2]]])
+        .filter(|&[a, b, c]| a != b && b != c && a != c)
+        .flatten()
+        .collect();
+    Mesh { vertices, indices }
+}
 
-        let foreground_count = total - background_count;
-        if foreground_count == 0.0 {
-            break;
+/// Set every vertex normal to the area-weighted mean of its face normals.
+///
+/// Vertices without faces get a zero normal.
+fn smooth_normals(mesh: &mut Mesh) {
+    let mut sums = vec![Vec3::new_zero(); mesh.vertices.len()];
+    for tri in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| mesh.vertices[i].posit);
+        // Unnormalized, so larger faces weigh more
+        let face = (b - a).cross(c - a);
+        for &i in tri {
+            sums[i] += face;
         }
+    }
+    for (vertex, sum) in mesh.vertices.iter_mut().zip(sums) {
+        let length = sum.magnitude();
+        vertex.normal = if length > 0.0 {
+            sum / length
+        } else {
+            Vec3::new_zero()
+        };
+    }
+}
 
-        background_sum = (t as f64).mul_add(count as f64, background_sum);
-        let foreground_sum = total_sum - background_sum;
+/// Report the mesh size and apply `--decimate`.
+fn simplify(mesh: Mesh, decimate_cell: Option<f32>) -> Mesh {
+    println!(
+        "  {}",
+        t!(
+            "stl-mesh",
+            vertices = mesh.vertices.len(),
+            triangles = mesh.indices.len() / 3
+        )
+    );
+
+    let Some(cell) = decimate_cell else {
+        return mesh;
+    };
+    let mesh = decimate(&mesh, cell);
+    println!(
+        "  {}",
+        t!(
+            "stl-decimated",
+            cell = format!("{cell:.2}"),
+            vertices = mesh.vertices.len(),
+            triangles = mesh.indices.len() / 3
+        )
+    );
+    mesh
+}
 
-        let background_mean = background_sum / background_count;
-        let foreground_mean = foreground_sum / foreground_count;
-        let diff = background_mean - foreground_mean;
+/// One named, colored part of a model, ready to write.
+struct Object<'a> {
+    name: &'a str,
+    color: [u8; 3],
+    mesh: &'a Mesh,
+}
 
-        let variance = background_count * foreground_count * diff * diff;
+/// Write the model in the format of its extension, plus its preview (and
+/// turntable video, if asked for), and report the measurements of every
+/// part.
+///
+/// Vertex normals are averaged over the surrounding faces first, so formats
+/// that carry them (and the preview) shade smoothly instead of faceted. The
+/// preview and turntable show the meshes before [`MeshOutput::place`], so
+/// their views keep their meaning whatever coordinate system or flips are
+/// written.
+/// Measurements stay in mm (of the scaled model) whatever the file units.
+fn save(
+    parts: Vec<(String, [u8; 3], Mesh)>,
+    path: &Path,
+    options: MeshOptions,
+    origin: [f64; 3],
+) -> Result<Vec<MeshStats>> {
+    let output = options.output;
+    let (labels, mut meshes): (Vec<_>, Vec<_>) = parts
+        .into_iter()
+        .map(|(name, color, mesh)| ((name, color), mesh))
+        .unzip();
+    meshes.iter_mut().for_each(smooth_normals);
+
+    let preview_path = path.with_extension("png");
+    preview::write(&merge(&meshes), &preview_path)?;
+    if let Some(seconds) = options.turntable {
+        write_turntable(&labels, &meshes, seconds, path)?;
+    }
 
-        if variance > best_variance {
-            best_variance = variance;
-            best_threshold_first = t;
-            best_threshold_last = t;
-        } else if (variance - best_variance).abs() < f64::EPSILON * best_variance.abs() {
-            best_threshold_last = t;
+    output.place(&mut meshes, origin);
+    let objects: Vec<Object<'_>> = labels
+        .iter()
+        .zip(&meshes)
+        .map(|((name, color), mesh)| Object {
+            name,
+            color: *color,
+            mesh,
+        })
+        .collect();
+    let format = MeshFormat::from_path(path);
+    let per_mm = output.mesh_units.per_mm();
+    match format {
+        MeshFormat::Stl => write_stl_file(&merge(&meshes), path, per_mm)?,
+        MeshFormat::Ply => ply::write(&objects, path, per_mm)?,
+        MeshFormat::Obj => obj::write(&objects, path, per_mm)?,
+        MeshFormat::Glb => glb::write(&objects, path, per_mm)?,
+        MeshFormat::ThreeMf => threemf::write(&objects, path, output.mesh_units)?,
+    }
+    println!(
+        "{}",
+        t!(
+            "stl-saved",
+            format = format.label(),
+            path = path.display().to_string()
+        )
+    );
+    println!(
+        "{}",
+        t!(
+            "stl-preview-saved",
+            path = preview_path.display().to_string()
+        )
+    );
+
+    let report_names = labels.len() > 1;
+    let mut all_stats = Vec::with_capacity(meshes.len());
+    for ((name, _), mesh) in labels.iter().zip(&meshes) {
+        let stats = MeshStats::measure(mesh, path);
+        if report_names {
+            println!("  {name}:");
         }
+        println!(
+            "  {}",
+            t!(
+                "stl-measurements",
+                area = format!("{:.1}", stats.surface_area),
+                volume = format!("{:.1}", stats.volume),
+                ml = format!("{:.1}", stats.volume / 1000.0),
+                size = stats.size_text()
+            )
+        );
+        all_stats.push(stats);
     }
+    Ok(all_stats)
+}
+
+/// Render the turntable video next to the model. A single model is shaded
+/// in gray like the preview; parts keep their colors.
+fn write_turntable(
+    labels: &[(String, [u8; 3])],
+    meshes: &[Mesh],
+    seconds: f32,
+    path: &Path,
+) -> Result<()> {
+    let video_path = path.with_extension("mp4");
+    let colored: Vec<([u8; 3], &Mesh)> = labels
+        .iter()
+        .zip(meshes)
+        .map(|((_, color), mesh)| (if labels.len() > 1 { *color } else { [255; 3] }, mesh))
+        .collect();
+    println!(
+        "  {}",
+        t!("stl-turntable", seconds = format!("{seconds:.1}"))
+    );
+    let frames = turntable::write(&colored, seconds, &video_path)?;
+    println!(
+        "{}",
+        t!(
+            "stl-turntable-saved",
+            frames = frames,
+            path = video_path.display().to_string()
+        )
+    );
+    Ok(())
+}
+
+/// Iso level to mesh at: the user-provided value, a `--target` preset, or
+/// Otsu's threshold. CT presets are printed first as suggestions.
+fn iso_threshold(values: &[f32], options: MeshOptions) -> Result<f32> {
+    if let Some(presets) = options.presets {
+        let [bone, skin, airways] =
+            [presets.bone, presets.skin, presets.airways].map(|level| format!("{level:.0}"));
+        println!(
+            "  {}",
+            t!(
+                "stl-target-suggestions",
+                bone = bone,
+                skin = skin,
+                airways = airways
+            )
+        );
+    }
+
+    let (threshold, message) = match (options.iso_level, options.target) {
+        (Some(threshold), _) => {
+            let text = format!("{threshold:.2}");
+            (threshold, t!("stl-user-iso-level", threshold = text))
+        }
+        (None, Some(target)) => {
+            let presets = options.presets.ok_or_else(|| {
+                BadInput(format!(
+                    "--target {} needs a CT series (values in Hounsfield units)",
+                    target.name()
+                ))
+            })?;
+            let threshold = presets.level(target);
+            let text = format!("{threshold:.0}");
+            (
+                threshold,
+                t!("stl-target-level", target = target.name(), threshold = text),
+            )
+        }
+        (None, None) => {
+            let threshold = otsu_threshold(values);
+            let text = format!("{threshold:.2}");
+            (threshold, t!("stl-otsu-threshold", threshold = text))
+        }
+    };
+    println!("  {message}");
+    Ok(threshold)
+}
+
+/// Cut a volume down to a crop box.
+///
+/// Also returns the mesh offset (mm) that keeps the cropped model aligned
+/// with one built from the whole volume.
+#[allow(clippy::cast_precision_loss)]
+fn crop_volume(volume: &Volume, crop: Crop) -> Result<(Volume, Vec3)> {
+    let cropped = volume.crop(crop.min, crop.max).ok_or_else(|| {
+        BadInput(format!(
+            "Crop box is outside the {}x{}x{} volume",
+            volume.cols, volume.rows, volume.slices
+        ))
+    })?;
+    println!(
+        "  {}",
+        t!(
+            "stl-cropped",
+            cols = cropped.cols,
+            rows = cropped.rows,
+            slices = cropped.slices
+        )
+    );
+    let offset = Vec3::new(
+        crop.min[0] as f32 * volume.spacing_x,
+        crop.min[1] as f32 * volume.spacing_y,
+        crop.min[2] as f32 * volume.spacing_z,
+    );
+    Ok((cropped, offset))
+}
 
-    // Average first and last bins with max variance for symmetric distributions
-    let best_threshold = usize::midpoint(best_threshold_first, best_threshold_last);
+/// Simplify a mesh by vertex clustering on a grid of `cell` mm.
+///
+/// All vertices in a grid cell merge into their mean position; triangles
+/// that collapse to an edge or point, and duplicates, are dropped. Clusters
+/// are numbered in vertex order so the output is deterministic.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn decimate(mesh: &Mesh, cell: f32) -> Mesh {
+    let mut clusters: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut sums: Vec<(Vec3, usize)> = Vec::new();
+    let remap: Vec<usize> = mesh
+        .vertices
+        .iter()
+        .map(|vertex| {
+            let p = vertex.posit;
+            let key = [p.x, p.y, p.z].map(|c| (c / cell).floor() as i64);
+            let id = *clusters.entry(key).or_insert_with(|| {
+                sums.push((Vec3::new_zero(), 0));
+                sums.len() - 1
+            });
+            sums[id].0 += p;
+            sums[id].1 += 1;
+            id
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for tri in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [remap[tri[0]], remap[tri[1]], remap[tri[2]]];
+        if a == b || b == c || a == c {
+            continue;
+        }
+        let mut key = [a, b, c];
+        key.sort_unstable();
+        if seen.insert(key) {
+            indices.extend([a, b, c]);
+        }
+    }
 
-    // Convert bin index back to value
-    min_val + best_threshold as f32 / scale
+    let vertices = sums
+        .into_iter()
+        .map(|(sum, count)| Vertex {
+            posit: sum / count as f32,
+            normal: Vec3::new_zero(),
+        })
+        .collect();
+    Mesh { vertices, indices }
 }
 
 /// Apply 3D Gaussian smoothing using separable convolution.
@@ -438,7 +1046,11 @@ fn gaussian_smooth_3d(
 }
 
 /// Build a 1D Gaussian kernel with the given sigma.
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
 fn build_gaussian_kernel(sigma: f32) -> Vec<f32> {
     let radius = (3.0 * sigma).ceil() as usize;
     let size = 2 * radius + 1;
@@ -460,8 +1072,29 @@ fn build_gaussian_kernel(sigma: f32) -> Vec<f32> {
     kernel
 }
 
-/// Write a marching cubes mesh as a binary STL file.
-fn write_stl_file(mesh: &mcubes::Mesh, path: &Path) -> Result<()> {
+/// Unit normal of a triangle from the cross product of its edges.
+///
+/// Degenerate triangles get `+Z`.
+fn face_normal([v0, v1, v2]: [Vec3; 3]) -> [f32; 3] {
+    let edge1 = [v1.x - v0.x, v1.y - v0.y, v1.z - v0.z];
+    let edge2 = [v2.x - v0.x, v2.y - v0.y, v2.z - v0.z];
+
+    let nx = edge1[2].mul_add(-edge2[1], edge1[1] * edge2[2]);
+    let ny = edge1[0].mul_add(-edge2[2], edge1[2] * edge2[0]);
+    let nz = edge1[1].mul_add(-edge2[0], edge1[0] * edge2[1]);
+
+    // Normalize
+    let len = nz.mul_add(nz, ny.mul_add(ny, nx * nx)).sqrt();
+    if len > 0.0 {
+        [nx / len, ny / len, nz / len]
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+/// Write a marching cubes mesh as a binary STL file, converting mm
+/// coordinates with `per_mm`.
+fn write_stl_file(mesh: &mcubes::Mesh, path: &Path, per_mm: f32) -> Result<()> {
     let indices = &mesh.indices;
     let vertices = &mesh.vertices;
 
@@ -477,42 +1110,17 @@ fn write_stl_file(mesh: &mcubes::Mesh, path: &Path) -> Result<()> {
         let v1 = &vertices[tri[1]];
         let v2 = &vertices[tri[2]];
 
-        // Compute face normal from cross product
-        let edge1 = [
-            v1.posit.x - v0.posit.x,
-            v1.posit.y - v0.posit.y,
-            v1.posit.z - v0.posit.z,
-        ];
-        let edge2 = [
-            v2.posit.x - v0.posit.x,
-            v2.posit.y - v0.posit.y,
-            v2.posit.z - v0.posit.z,
-        ];
-
-        let nx = edge1[2].mul_add(-edge2[1], edge1[1] * edge2[2]);
-        let ny = edge1[0].mul_add(-edge2[2], edge1[2] * edge2[0]);
-        let nz = edge1[1].mul_add(-edge2[0], edge1[0] * edge2[1]);
-
-        // Normalize
-        let len = nz.mul_add(nz, ny.mul_add(ny, nx * nx)).sqrt();
-        let normal: [f32; 3] = if len > 0.0 {
-            [nx / len, ny / len, nz / len]
-        } else {
-            [0.0, 0.0, 1.0]
-        };
-
         stl_io::Triangle {
-            normal: stl_io::Normal::new(normal),
-            vertices: [
-                stl_io::Vertex::new([v0.posit.x, v0.posit.y, v0.posit.z]),
-                stl_io::Vertex::new([v1.posit.x, v1.posit.y, v1.posit.z]),
-                stl_io::Vertex::new([v2.posit.x, v2.posit.y, v2.posit.z]),
-            ],
+            normal: stl_io::Normal::new(face_normal([v0.posit, v1.posit, v2.posit])),
+            vertices: [v0, v1, v2].map(|v| {
+                stl_io::Vertex::new([v.posit.x, v.posit.y, v.posit.z].map(|c| c * per_mm))
+            }),
         }
     });
 
     let mut file = BufWriter::new(
-        File::create(path).with_context(|| format!("Failed to create STL file: {}", path.display()))?,
+        File::create(path)
+            .with_context(|| format!("Failed to create STL file: {}", path.display()))?,
     );
     stl_io::write_stl(&mut file, triangles)
         .with_context(|| format!("Failed to write STL data: {}", path.display()))?;
@@ -524,53 +1132,6 @@ fn write_stl_file(mesh: &mcubes::Mesh, path: &Path) -> Result<()> {
 mod tests {
     use super::*;
 
-    // =========================================================================
-    // Otsu Threshold Tests
-    // =========================================================================
-
-    mod otsu {
-        use super::*;
-
-        #[test]
-        fn bimodal_distribution_finds_midpoint() {
-            // 50 values at 50.0, 50 values at 200.0
-            let mut values = vec![50.0_f32; 50];
-            values.extend(vec![200.0_f32; 50]);
-
-            let threshold = otsu_threshold(&values);
-
-            // Threshold should be between the two peaks
-            assert!(
-                threshold > 50.0 && threshold < 200.0,
-                "Expected threshold between 50 and 200, got {threshold}"
-            );
-        }
-
-        #[test]
-        fn uniform_values_returns_minimum() {
-            let values = vec![100.0_f32; 100];
-            let threshold = otsu_threshold(&values);
-            assert!(
-                (threshold - 100.0).abs() < f32::EPSILON,
-                "Expected ~100.0 for uniform data, got {threshold}"
-            );
-        }
-
-        #[test]
-        fn empty_input_returns_zero() {
-            assert!((otsu_threshold(&[]) - 0.0).abs() < f32::EPSILON);
-        }
-
-        #[test]
-        fn single_value_returns_that_value() {
-            let threshold = otsu_threshold(&[42.0]);
-            assert!(
-                (threshold - 42.0).abs() < f32::EPSILON,
-                "Expected 42.0, got {threshold}"
-            );
-        }
-    }
-
     // =========================================================================
     // Gaussian Smoothing Tests
     // =========================================================================
@@ -648,7 +1209,13 @@ mod tests {
             let files: Vec<PathBuf> = (0..3)
                 .map(|i| PathBuf::from(format!("test_{i}.dcm")))
                 .collect();
-            let result = convert_to_stl(&files, Path::new("/tmp/out"), None, 1.0);
+            let result = convert_to_stl(
+                &files,
+                Path::new("/tmp/out"),
+                MeshOptions::default(),
+                MeshFormat::Stl,
+                false,
+            );
             assert!(result.is_err());
             let err = result.unwrap_err().to_string();
             assert!(
@@ -656,5 +1223,556 @@ mod tests {
                 "Expected 'at least' in error: {err}"
             );
         }
+
+        #[test]
+        fn crop_outside_volume_is_bad_input() {
+            let volume = Volume {
+                values: vec![0.0; 27],
+                cols: 3,
+                rows: 3,
+                slices: 3,
+                spacing_x: 1.0,
+                spacing_y: 1.0,
+                spacing_z: 1.0,
+                origin: [0.0; 3],
+            };
+            let options = MeshOptions {
+                crop: Some("5:,:,:".parse().unwrap()),
+                ..MeshOptions::default()
+            };
+            let err = write_model(volume, options, Path::new("/tmp/out.stl")).unwrap_err();
+            assert!(err.downcast_ref::<BadInput>().is_some(), "{err}");
+        }
+
+        #[test]
+        fn target_needs_ct_presets() {
+            let options = MeshOptions {
+                target: Some(Target::Bone),
+                ..MeshOptions::default()
+            };
+            let err = iso_threshold(&[0.0, 1.0], options).unwrap_err();
+            assert!(err.downcast_ref::<BadInput>().is_some(), "{err}");
+
+            let presets = Presets {
+                bone: 300.0,
+                skin: -480.0,
+                airways: -900.0,
+            };
+            let options = MeshOptions {
+                presets: Some(presets),
+                ..options
+            };
+            assert_eq!(
+                iso_threshold(&[0.0, 1.0], options).unwrap().to_string(),
+                "300"
+            );
+            let options = MeshOptions {
+                iso_level: Some(50.0),
+                ..options
+            };
+            assert_eq!(
+                iso_threshold(&[0.0, 1.0], options).unwrap().to_string(),
+                "50"
+            );
+        }
+    }
+
+    // =========================================================================
+    // Crop Tests
+    // =========================================================================
+
+    mod crop {
+        use super::*;
+
+        #[test]
+        fn parses_three_ranges() {
+            let crop: Crop = "10:20,0:64,5:9".parse().unwrap();
+            assert_eq!(crop.min, [10, 0, 5]);
+            assert_eq!(crop.max, [20, 64, 9]);
+        }
+
+        #[test]
+        fn missing_bounds_are_open() {
+            let crop: Crop = ":,32:,:40".parse().unwrap();
+            assert_eq!(crop.min, [0, 32, 0]);
+            assert_eq!(crop.max, [usize::MAX, usize::MAX, 40]);
+        }
+
+        #[test]
+        fn rejects_malformed_crops() {
+            for bad in ["0:10,0:10", "0:10,0:10,5", "0:10,a:4,0:1", "0:10,0:10,7:7"] {
+                assert!(bad.parse::<Crop>().is_err(), "{bad}");
+            }
+        }
+    }
+
+    // =========================================================================
+    // Decimation Tests
+    // =========================================================================
+
+    mod decimation {
+        use super::*;
+
+        fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+            Vertex {
+                posit: Vec3::new(x, y, z),
+                normal: Vec3::new_zero(),
+            }
+        }
+
+        /// Two triangles sharing an edge, plus a sliver near vertex 0.
+        fn strip() -> Mesh {
+            Mesh {
+                vertices: vec![
+                    vertex(0.0, 0.0, 0.0),
+                    vertex(10.0, 0.0, 0.0),
+                    vertex(0.0, 10.0, 0.0),
+                    vertex(10.0, 10.0, 0.0),
+                    vertex(0.2, 0.2, 0.0),
+                ],
+                indices: vec![0, 1, 2, 1, 3, 2, 0, 4, 1],
+            }
+        }
+
+        #[test]
+        fn fine_grid_keeps_the_mesh() {
+            let mesh = decimate(&strip(), 0.1);
+            assert_eq!(mesh.vertices.len(), 5);
+            assert_eq!(mesh.indices, strip().indices);
+        }
+
+        #[test]
+        fn close_vertices_merge_and_slivers_drop() {
+            let mesh = decimate(&strip(), 1.0);
+            assert_eq!(mesh.vertices.len(), 4);
+            assert_eq!(mesh.indices, [0, 1, 2, 1, 3, 2]);
+            let merged = mesh.vertices[0].posit;
+            assert!((merged.x - 0.1).abs() < 1e-6 && (merged.y - 0.1).abs() < 1e-6);
+        }
+
+        #[test]
+        fn coarse_grid_collapses_everything() {
+            let mesh = decimate(&strip(), 100.0);
+            assert_eq!(mesh.vertices.len(), 1);
+            assert!(mesh.indices.is_empty());
+        }
+    }
+
+    // =========================================================================
+    // Measurement Tests
+    // =========================================================================
+
+    mod measurements {
+        use super::*;
+
+        /// Closed tetrahedron with 10 mm legs along each axis.
+        fn tetrahedron() -> Mesh {
+            let vertex = |x, y, z| Vertex {
+                posit: Vec3::new(x, y, z),
+                normal: Vec3::new_zero(),
+            };
+            Mesh {
+                vertices: vec![
+                    vertex(0.0, 0.0, 0.0),
+                    vertex(10.0, 0.0, 0.0),
+                    vertex(0.0, 10.0, 0.0),
+                    vertex(0.0, 0.0, 10.0),
+                ],
+                indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+            }
+        }
+
+        #[test]
+        fn measures_tetrahedron() {
+            let stats = MeshStats::measure(&tetrahedron(), Path::new("model.stl"));
+            assert_eq!((stats.vertices, stats.triangles), (4, 4));
+            // Three right triangles plus an equilateral one with 10√2 sides
+            let area = 3.0f32.sqrt().mul_add(50.0, 150.0);
+            assert!(
+                (stats.surface_area - area).abs() < 1e-3,
+                "{}",
+                stats.surface_area
+            );
+            assert!(
+                (stats.volume - 1000.0 / 6.0).abs() < 1e-3,
+                "{}",
+                stats.volume
+            );
+            assert_eq!(stats.size_text(), "10.0x10.0x10.0");
+        }
+
+        #[test]
+        fn empty_mesh_measures_zero() {
+            let mesh = Mesh {
+                vertices: Vec::new(),
+                indices: Vec::new(),
+            };
+            let stats = MeshStats::measure(&mesh, Path::new("model.stl"));
+            assert_eq!(stats.size_text(), "0.0x0.0x0.0");
+            assert!(stats.surface_area.abs() < f32::EPSILON);
+        }
+
+        #[test]
+        fn shell_keeps_outside_and_cuts_volume() {
+            // 10³ solid cube in a 12³ volume
+            let mut values = vec![0.0; 12 * 12 * 12];
+            for z in 1..11 {
+                for y in 1..11 {
+                    values[(z * 12 + y) * 12 + 1..(z * 12 + y) * 12 + 11].fill(100.0);
+                }
+            }
+            let volume = Volume {
+                values,
+                cols: 12,
+                rows: 12,
+                slices: 12,
+                spacing_x: 1.0,
+                spacing_y: 1.0,
+                spacing_z: 1.0,
+                origin: [0.0; 3],
+            };
+            let dir = tempfile::tempdir().unwrap();
+            let options = MeshOptions {
+                iso_level: Some(50.0),
+                smooth_sigma: 0.0,
+                ..MeshOptions::default()
+            };
+            let solid =
+                write_model(volume.clone(), options, &dir.path().join("solid.stl")).unwrap();
+            let options = MeshOptions {
+                shell_thickness: Some(2.0),
+                ..options
+            };
+            let hollow = write_model(volume, options, &dir.path().join("hollow.stl")).unwrap();
+
+            assert_eq!(hollow.size_text(), solid.size_text());
+            assert!(hollow.volume < solid.volume * 0.8, "{}", hollow.volume);
+            assert!(hollow.surface_area > solid.surface_area);
+        }
+    }
+
+    // =========================================================================
+    // Multi-Part Tests
+    // =========================================================================
+
+    mod multi_part {
+        use super::*;
+
+        /// 8³ volume: a block of 1s around a core of 2s.
+        fn layered() -> Volume {
+            let mut values = vec![0.0; 512];
+            for z in 1..7 {
+                for y in 1..7 {
+                    for x in 1..7 {
+                        let core =
+                            (3..5).contains(&x) && (3..5).contains(&y) && (3..5).contains(&z);
+                        values[(z * 8 + y) * 8 + x] = if core { 2.0 } else { 1.0 };
+                    }
+                }
+            }
+            Volume {
+                values,
+                cols: 8,
+                rows: 8,
+                slices: 8,
+                spacing_x: 1.0,
+                spacing_y: 1.0,
+                spacing_z: 1.0,
+                origin: [0.0; 3],
+            }
+        }
+
+        fn parts(specs: &[&str]) -> Vec<Part> {
+            specs.iter().map(|s| s.parse().unwrap()).collect()
+        }
+
+        #[test]
+        fn writes_one_object_per_non_empty_part() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("model.3mf");
+            let stats = write_parts(
+                layered(),
+                MeshOptions::default(),
+                &parts(&["shell:0.5:1.5", "core:1.5:", "missing:5:"]),
+                &path,
+            )
+            .unwrap();
+
+            assert_eq!(stats.len(), 2);
+            assert!(stats[0].volume > stats[1].volume);
+            assert!(path.exists());
+            assert!(path.with_extension("png").exists());
+        }
+
+        #[test]
+        fn extension_picks_the_writer() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("model.obj");
+            let options = MeshOptions {
+                iso_level: Some(0.5),
+                ..MeshOptions::default()
+            };
+            write_model(layered(), options, &path).unwrap();
+
+            let obj = std::fs::read_to_string(&path).unwrap();
+            assert!(obj.contains("\no model\n"));
+            assert!(obj.contains("\nvn "));
+            assert!(obj.contains("\nf 1//1 "));
+        }
+
+        #[test]
+        fn all_empty_parts_are_bad_input() {
+            let dir = tempfile::tempdir().unwrap();
+            let err = write_parts(
+                layered(),
+                MeshOptions::default(),
+                &parts(&["missing:5:"]),
+                &dir.path().join("model.3mf"),
+            )
+            .unwrap_err();
+            assert!(err.downcast_ref::<BadInput>().is_some(), "{err}");
+        }
+
+        #[test]
+        fn model_path_picks_format_by_extension() {
+            let format = |path| MeshFormat::from_path(Path::new(path));
+            assert_eq!(format("out/model.3MF"), MeshFormat::ThreeMf);
+            assert_eq!(format("out/model.glb"), MeshFormat::Glb);
+            assert_eq!(format("out/model.stl"), MeshFormat::Stl);
+            assert_eq!(format("out/3mf"), MeshFormat::Stl);
+        }
+    }
+
+    // =========================================================================
+    // Welding & Normal Tests
+    // =========================================================================
+
+    mod normals {
+        use super::*;
+
+        fn vertex(x: f32, y: f32, z: f32) -> Vertex {
+            Vertex {
+                posit: Vec3::new(x, y, z),
+                normal: Vec3::new_zero(),
+            }
+        }
+
+        /// Two triangles of a unit square in z = 0, with unshared vertices.
+        fn square() -> Mesh {
+            Mesh {
+                vertices: vec![
+                    vertex(0.0, 0.0, 0.0),
+                    vertex(1.0, 0.0, 0.0),
+                    vertex(1.0, 1.0, 0.0),
+                    vertex(0.0, 0.0, 0.0),
+                    vertex(1.0, 1.0, 0.0),
+                    vertex(0.0, 1.0, 0.0),
+                ],
+                indices: (0..6).collect(),
+            }
+        }
+
+        #[test]
+        fn weld_shares_coincident_vertices() {
+            let mesh = weld(&square());
+            assert_eq!(mesh.vertices.len(), 4);
+            assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        }
+
+        #[test]
+        fn weld_drops_collapsed_triangles() {
+            let mut mesh = square();
+            mesh.vertices[4].posit = Vec3::new(0.0, 0.0, 0.0);
+            assert_eq!(weld(&mesh).indices, [0, 1, 2]);
+        }
+
+        #[test]
+        fn normals_average_adjacent_faces() {
+            // Fold the square along its diagonal: the shared edge gets the
+            // mean of both face normals
+            let mut mesh = weld(&square());
+            mesh.vertices[3].posit = Vec3::new(0.0, 1.0, 1.0);
+            smooth_normals(&mut mesh);
+
+            let n = mesh.vertices[0].normal;
+            assert!((n.magnitude() - 1.0).abs() < 1e-6);
+            // (0, 0, 1) + (1, -1, 1), normalized
+            let expected = 6f32.sqrt().recip();
+            assert!((n.x - expected).abs() < 1e-6, "{n:?}");
+            assert!((n.y + expected).abs() < 1e-6, "{n:?}");
+            let corner = mesh.vertices[1].normal;
+            assert!((corner.z - 1.0).abs() < 1e-6, "{corner:?}");
+        }
+
+        #[test]
+        fn mirroring_flips_normals() {
+            let mut meshes = [weld(&square())];
+            smooth_normals(&mut meshes[0]);
+            MeshOutput {
+                flip_z: true,
+                ..MeshOutput::default()
+            }
+            .place(&mut meshes, [0.0; 3]);
+            assert!((meshes[0].vertices[0].normal.z + 1.0).abs() < 1e-6);
+        }
+    }
+
+    // =========================================================================
+    // Units & Placement Tests
+    // =========================================================================
+
+    mod placement {
+        use super::*;
+
+        fn segment() -> Mesh {
+            let vertex = |x, y, z| Vertex {
+                posit: Vec3::new(x, y, z),
+                normal: Vec3::new_zero(),
+            };
+            Mesh {
+                vertices: vec![vertex(10.0, 20.0, 30.0), vertex(30.0, 60.0, 40.0)],
+                indices: Vec::new(),
+            }
+        }
+
+        fn positions(mesh: &Mesh) -> Vec<[f32; 3]> {
+            mesh.vertices
+                .iter()
+                .map(|v| [v.posit.x, v.posit.y, v.posit.z])
+                .collect()
+        }
+
+        #[test]
+        fn default_keeps_coordinates() {
+            let mut mesh = segment();
+            MeshOutput::default().place(std::slice::from_mut(&mut mesh), [5.0; 3]);
+            assert_eq!(positions(&mesh), positions(&segment()));
+        }
+
+        #[test]
+        fn centers_then_scales() {
+            let mut mesh = segment();
+            let output = MeshOutput {
+                mesh_scale: 0.5,
+                center: true,
+                ..MeshOutput::default()
+            };
+            output.place(std::slice::from_mut(&mut mesh), [5.0; 3]);
+            assert_eq!(positions(&mesh), [[-5.0, -10.0, -2.5], [5.0, 10.0, 2.5]]);
+        }
+
+        #[test]
+        fn patient_coordinates_add_the_origin() {
+            let origin = [-100.0, -50.0, 200.0];
+            let mut lps = segment();
+            MeshOutput {
+                mesh_coords: MeshCoords::Lps,
+                ..MeshOutput::default()
+            }
+            .place(std::slice::from_mut(&mut lps), origin);
+            assert_eq!(
+                positions(&lps),
+                [[-90.0, -30.0, 230.0], [-70.0, 10.0, 240.0]]
+            );
+
+            let mut ras = segment();
+            MeshOutput {
+                mesh_coords: MeshCoords::Ras,
+                ..MeshOutput::default()
+            }
+            .place(std::slice::from_mut(&mut ras), origin);
+            assert_eq!(positions(&ras), [[90.0, 30.0, 230.0], [70.0, -10.0, 240.0]]);
+        }
+
+        #[test]
+        fn odd_flips_reverse_winding() {
+            let mut mesh = segment();
+            mesh.indices = vec![0, 1, 0];
+            let flip_x = MeshOutput {
+                flip_x: true,
+                ..MeshOutput::default()
+            };
+            flip_x.place(std::slice::from_mut(&mut mesh), [0.0; 3]);
+            assert_eq!(positions(&mesh)[..1], [[-10.0, 20.0, 30.0]]);
+            assert_eq!(mesh.indices, [0, 0, 1]);
+
+            // RAS plus a Z flip mirrors three axes: odd again
+            let mut mesh = segment();
+            mesh.indices = vec![0, 1, 0];
+            let ras_flipped = MeshOutput {
+                mesh_coords: MeshCoords::Ras,
+                flip_z: true,
+                ..MeshOutput::default()
+            };
+            ras_flipped.place(std::slice::from_mut(&mut mesh), [0.0; 3]);
+            assert_eq!(mesh.indices, [0, 0, 1]);
+
+            let mut mesh = segment();
+            mesh.indices = vec![0, 1, 0];
+            MeshOutput {
+                mesh_coords: MeshCoords::Ras,
+                ..MeshOutput::default()
+            }
+            .place(std::slice::from_mut(&mut mesh), [0.0; 3]);
+            assert_eq!(mesh.indices, [0, 1, 0]);
+        }
+
+        #[test]
+        fn parts_share_one_center() {
+            let mut parts = [segment(), segment()];
+            parts[1].vertices[1].posit = Vec3::new(50.0, 60.0, 40.0);
+            MeshOutput {
+                center: true,
+                ..MeshOutput::default()
+            }
+            .place(&mut parts, [0.0; 3]);
+            assert_eq!(
+                positions(&parts[0]),
+                [[-20.0, -20.0, -5.0], [0.0, 20.0, 5.0]]
+            );
+            assert_eq!(positions(&parts[1])[1..], [[20.0, 20.0, 5.0]]);
+        }
+
+        #[test]
+        fn merge_offsets_indices() {
+            let (mut a, mut b) = (segment(), segment());
+            a.indices = vec![0, 1, 0];
+            b.indices = vec![1, 0, 1];
+            let merged = merge([&a, &b]);
+            assert_eq!(merged.vertices.len(), 4);
+            assert_eq!(merged.indices, [0, 1, 0, 3, 2, 3]);
+        }
+
+        #[test]
+        fn units_convert_millimeters() {
+            assert!(MeshUnits::Cm.per_mm().mul_add(250.0, -25.0).abs() < 1e-6);
+            assert!(MeshUnits::M.per_mm().mul_add(250.0, -0.25).abs() < 1e-6);
+        }
+
+        #[test]
+        fn written_file_uses_units() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("model.stl");
+            let mut mesh = segment();
+            mesh.vertices.push(Vertex {
+                posit: Vec3::new(50.0, 20.0, 30.0),
+                normal: Vec3::new_zero(),
+            });
+            mesh.indices = vec![0, 1, 2];
+            write_stl_file(&mesh, &path, MeshUnits::Cm.per_mm()).unwrap();
+
+            let mut file = File::open(&path).unwrap();
+            let stl = stl_io::read_stl(&mut file).unwrap();
+            let xs: Vec<f32> = stl.vertices.iter().map(|v| v[0]).collect();
+            assert!(xs.iter().any(|&x| (x - 5.0).abs() < 1e-5), "{xs:?}");
+        }
+
+        #[test]
+        fn scale_must_be_positive() {
+            assert_eq!(parse_positive("0.5"), Ok(0.5));
+            assert!(parse_positive("0").is_err());
+            assert!(parse_positive("-1").is_err());
+            assert!(parse_positive("abc").is_err());
+        }
     }
 }
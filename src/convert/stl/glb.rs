@@ -0,0 +1,281 @@
+//! Binary glTF (`.glb`) writer with vertex normals and part colors.
+//!
+//! Each part becomes a named node with its own mesh and a matte material in
+//! the part's color. Positions, normals, and `u32` indices of all parts are
+//! packed into the single binary chunk that follows the JSON chunk.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use mcubes::Mesh;
+use serde_json::{Value, json};
+
+use super::{Object, bounds};
+
+/// `glTF` magic at the start of every GLB file.
+const MAGIC: u32 = 0x4654_6C67;
+
+/// Chunk type of the JSON chunk (`JSON`).
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+
+/// Chunk type of the binary chunk (`BIN\0`).
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+/// Accessor component type for `f32`.
+const FLOAT: u32 = 5126;
+
+/// Accessor component type for `u32`.
+const UNSIGNED_INT: u32 = 5125;
+
+/// Buffer view target for vertex attributes.
+const ARRAY_BUFFER: u32 = 34962;
+
+/// Buffer view target for indices.
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Write parts (coordinates in mm) as GLB, scaled by `per_mm`.
+pub(super) fn write(objects: &[Object<'_>], path: &Path, per_mm: f32) -> Result<()> {
+    fs::write(path, encode(objects, per_mm)?)
+        .with_context(|| format!("Failed to write glTF file: {}", path.display()))
+}
+
+/// Build the whole GLB file in memory.
+fn encode(objects: &[Object<'_>], per_mm: f32) -> Result<Vec<u8>> {
+    let mut bin = Vec::new();
+    let (mut views, mut accessors) = (Vec::new(), Vec::new());
+    let (mut nodes, mut meshes, mut materials) = (Vec::new(), Vec::new(), Vec::new());
+
+    for (index, object) in objects.iter().enumerate() {
+        let first = accessors.len();
+        push_mesh(object.mesh, per_mm, &mut bin, &mut views, &mut accessors)?;
+        meshes.push(json!({
+            "name": object.name,
+            "primitives": [{
+                "attributes": { "POSITION": first, "NORMAL": first + 1 },
+                "indices": first + 2,
+                "material": index,
+            }],
+        }));
+        nodes.push(json!({ "name": object.name, "mesh": index }));
+        let [r, g, b] = object.color.map(srgb_to_linear);
+        materials.push(json!({
+            "name": object.name,
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [r, g, b, 1.0],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+        }));
+    }
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "dcm-toolbox" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..objects.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "accessors": accessors,
+        "bufferViews": views,
+        "buffers": [{ "byteLength": bin.len() }],
+    });
+    package(&document, bin)
+}
+
+/// Append the positions, normals, and indices of a mesh to the binary chunk,
+/// with one accessor each (in that order).
+fn push_mesh(
+    mesh: &Mesh,
+    per_mm: f32,
+    bin: &mut Vec<u8>,
+    views: &mut Vec<Value>,
+    accessors: &mut Vec<Value>,
+) -> Result<()> {
+    let vertices = mesh.vertices.len();
+    let (min, max) = bounds(mesh);
+
+    let positions = mesh
+        .vertices
+        .iter()
+        .flat_map(|v| [v.posit.x, v.posit.y, v.posit.z].map(|c| c * per_mm));
+    let view = push_view(
+        bin,
+        views,
+        positions.flat_map(f32::to_le_bytes),
+        ARRAY_BUFFER,
+    );
+    accessors.push(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": vertices,
+        "type": "VEC3",
+        "min": min.map(|c| c * per_mm),
+        "max": max.map(|c| c * per_mm),
+    }));
+
+    let normals = mesh
+        .vertices
+        .iter()
+        .flat_map(|v| [v.normal.x, v.normal.y, v.normal.z]);
+    let view = push_view(bin, views, normals.flat_map(f32::to_le_bytes), ARRAY_BUFFER);
+    accessors.push(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": vertices,
+        "type": "VEC3",
+    }));
+
+    let indices: Vec<u32> = mesh
+        .indices
+        .iter()
+        .map(|&i| u32::try_from(i))
+        .collect::<std::result::Result<_, _>>()
+        .context("Mesh too large for glTF")?;
+    let view = push_view(
+        bin,
+        views,
+        indices.iter().flat_map(|i| i.to_le_bytes()),
+        ELEMENT_ARRAY_BUFFER,
+    );
+    accessors.push(json!({
+        "bufferView": view,
+        "componentType": UNSIGNED_INT,
+        "count": indices.len(),
+        "type": "SCALAR",
+    }));
+    Ok(())
+}
+
+/// Wrap the JSON document and binary chunk in the GLB container.
+fn package(document: &Value, mut bin: Vec<u8>) -> Result<Vec<u8>> {
+    let mut json = serde_json::to_vec(document)?;
+    pad(&mut json, b' ');
+    pad(&mut bin, 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let too_large = "Mesh too large for glTF";
+    let mut out = Vec::with_capacity(total);
+    for word in [MAGIC, 2, u32::try_from(total).context(too_large)?] {
+        out.extend(word.to_le_bytes());
+    }
+    for (kind, data) in [(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+        out.extend(u32::try_from(data.len()).context(too_large)?.to_le_bytes());
+        out.extend(kind.to_le_bytes());
+        out.extend(data.iter());
+    }
+    Ok(out)
+}
+
+/// Append bytes to the binary chunk as a new buffer view; returns its index.
+fn push_view(
+    bin: &mut Vec<u8>,
+    views: &mut Vec<Value>,
+    bytes: impl Iterator<Item = u8>,
+    target: u32,
+) -> usize {
+    let offset = bin.len();
+    bin.extend(bytes);
+    views.push(json!({
+        "buffer": 0,
+        "byteOffset": offset,
+        "byteLength": bin.len() - offset,
+        "target": target,
+    }));
+    views.len() - 1
+}
+
+/// Pad a chunk to a multiple of 4 bytes, as GLB requires.
+fn pad(data: &mut Vec<u8>, fill: u8) {
+    data.resize(data.len().next_multiple_of(4), fill);
+}
+
+/// glTF color factors are linear; part colors are sRGB.
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = f32::from(channel) / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lin_alg::f32::Vec3;
+    use mcubes::Vertex;
+
+    fn triangle() -> Mesh {
+        let vertex = |x, y| Vertex {
+            posit: Vec3::new(x, y, 5.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+        };
+        Mesh {
+            vertices: vec![vertex(0.0, 0.0), vertex(10.0, 0.0), vertex(0.0, 10.0)],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    fn word(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn layout_follows_the_glb_spec() {
+        let mesh = triangle();
+        let objects = [Object {
+            name: "bone",
+            color: [255, 255, 255],
+            mesh: &mesh,
+        }];
+        let glb = encode(&objects, 0.5).unwrap();
+
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(word(&glb, 4), 2);
+        assert_eq!(word(&glb, 8) as usize, glb.len());
+
+        let json_len = word(&glb, 12) as usize;
+        assert_eq!(json_len % 4, 0);
+        assert_eq!(&glb[16..20], b"JSON");
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+
+        let bin_at = 20 + json_len;
+        let bin_len = word(&glb, bin_at) as usize;
+        assert_eq!(&glb[bin_at + 4..bin_at + 8], b"BIN\0");
+        assert_eq!(bin_at + 8 + bin_len, glb.len());
+        // Positions, normals (3 x 12 bytes each), and 3 indices
+        assert_eq!(document["buffers"][0]["byteLength"], 3 * 12 * 2 + 3 * 4);
+
+        assert_eq!(document["nodes"][0]["name"], "bone");
+        assert_eq!(document["accessors"][0]["max"], json!([5.0, 5.0, 2.5]));
+        assert_eq!(
+            document["materials"][0]["pbrMetallicRoughness"]["baseColorFactor"],
+            json!([1.0, 1.0, 1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn parts_get_their_own_buffers() {
+        let mesh = triangle();
+        let objects = [
+            Object {
+                name: "a",
+                color: [0; 3],
+                mesh: &mesh,
+            },
+            Object {
+                name: "b",
+                color: [0; 3],
+                mesh: &mesh,
+            },
+        ];
+        let glb = encode(&objects, 1.0).unwrap();
+        let json_len = word(&glb, 12) as usize;
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+
+        assert_eq!(document["scenes"][0]["nodes"], json!([0, 1]));
+        assert_eq!(document["meshes"][1]["primitives"][0]["indices"], 5);
+        assert_eq!(document["bufferViews"][3]["byteOffset"], 3 * 12 * 2 + 3 * 4);
+    }
+}
@@ -0,0 +1,85 @@
+//! Wavefront OBJ writer with vertex normals.
+//!
+//! Each part becomes an `o` object. Vertex and normal numbers run on across
+//! objects, as OBJ indices are file-global and 1-based.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::Object;
+
+/// Write parts (coordinates in mm) as OBJ text, scaled by `per_mm`.
+pub(super) fn write(objects: &[Object<'_>], path: &Path, per_mm: f32) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create OBJ file: {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    write_to(&mut out, objects, per_mm)
+        .and_then(|()| out.flush())
+        .with_context(|| format!("Failed to write OBJ data: {}", path.display()))
+}
+
+/// Write all objects as `v`/`vn`/`f` lines.
+fn write_to(out: &mut impl Write, objects: &[Object<'_>], per_mm: f32) -> std::io::Result<()> {
+    writeln!(out, "# dcm-toolbox")?;
+    let mut base = 1;
+    for object in objects {
+        writeln!(out, "o {}", object.name.replace(char::is_whitespace, "_"))?;
+        for vertex in &object.mesh.vertices {
+            let p = vertex.posit * per_mm;
+            writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for vertex in &object.mesh.vertices {
+            let n = vertex.normal;
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for tri in object.mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| base + i);
+            writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}")?;
+        }
+        base += object.mesh.vertices.len();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lin_alg::f32::Vec3;
+    use mcubes::{Mesh, Vertex};
+
+    #[test]
+    fn numbers_run_on_across_objects() {
+        let vertex = |x| Vertex {
+            posit: Vec3::new(x, 0.0, 20.0),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+        };
+        let mesh = Mesh {
+            vertices: vec![vertex(0.0), vertex(10.0), vertex(30.0)],
+            indices: vec![0, 1, 2],
+        };
+        let objects = [
+            Object {
+                name: "soft tissue",
+                color: [0; 3],
+                mesh: &mesh,
+            },
+            Object {
+                name: "bone",
+                color: [0; 3],
+                mesh: &mesh,
+            },
+        ];
+        let mut out = Vec::new();
+        write_to(&mut out, &objects, 0.1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("o soft_tissue\nv 0 0 2\nv 1 0 2\nv 3 0 2\nvn 0 1 0\n"));
+        assert!(text.contains("f 1//1 2//2 3//3\n"));
+        assert!(text.ends_with(
+            "o bone\nv 0 0 2\nv 1 0 2\nv 3 0 2\nvn 0 1 0\nvn 0 1 0\nvn 0 1 0\nf 4//4 5//5 6//6\n"
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+//! Multi-threshold segmentation into named parts (`stl --part`).
+//!
+//! Each part is a value band `[low, high)`; its surface is the boundary of
+//! the voxels inside the band, so parts of one model do not overlap.
+
+use std::str::FromStr;
+
+/// Colors used for parts given without one.
+const PALETTE: [[u8; 3]; 6] = [
+    [0xE8, 0xDC, 0xC8],
+    [0xC8, 0x64, 0x5A],
+    [0x5A, 0x8C, 0xC8],
+    [0x78, 0xB4, 0x64],
+    [0xD2, 0xB4, 0x3C],
+    [0x96, 0x78, 0xB4],
+];
+
+/// One part of a multi-part model: `NAME:LOW:HIGH[:#RRGGBB]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    /// Name shown in the slicer.
+    pub name: String,
+    /// Lowest included value (open when `None`).
+    pub low: Option<f32>,
+    /// First excluded value (open when `None`).
+    pub high: Option<f32>,
+    /// Display color; taken from a palette when `None`.
+    pub color: Option<[u8; 3]>,
+}
+
+impl Part {
+    /// Whether a voxel value falls inside the band.
+    fn contains(&self, value: f32) -> bool {
+        self.low.is_none_or(|low| value >= low) && self.high.is_none_or(|high| value < high)
+    }
+
+    /// Binary mask of the band: 1 inside, 0 outside.
+    pub fn mask(&self, values: &[f32]) -> Vec<f32> {
+        values
+            .iter()
+            .map(|&v| if self.contains(v) { 1.0 } else { 0.0 })
+            .collect()
+    }
+
+    /// Band as text for messages, e.g. `300..` or `-300..300`.
+    pub fn range_text(&self) -> String {
+        let bound = |b: Option<f32>| b.map(|v| v.to_string()).unwrap_or_default();
+        format!("{}..{}", bound(self.low), bound(self.high))
+    }
+}
+
+/// Display color of the `index`th part.
+pub fn color(part: &Part, index: usize) -> [u8; 3] {
+    part.color.unwrap_or_else(|| palette(index))
+}
+
+/// Palette color of the `index`th part, repeating after the last one.
+pub const fn palette(index: usize) -> [u8; 3] {
+    PALETTE[index % PALETTE.len()]
+}
+
+impl FromStr for Part {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let fields: Vec<&str> = s.split(':').collect();
+        let (name, low, high, color) = match fields.as_slice() {
+            [name, low, high] => (name, low, high, None),
+            [name, low, high, color] => (name, low, high, Some(*color)),
+            _ => return Err("expected NAME:LOW:HIGH or NAME:LOW:HIGH:#RRGGBB".to_string()),
+        };
+
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("part name is empty".to_string());
+        }
+        let bound = |text: &str| -> std::result::Result<Option<f32>, String> {
+            let text = text.trim();
+            if text.is_empty() {
+                return Ok(None);
+            }
+            text.parse()
+                .map(Some)
+                .map_err(|_| format!("`{text}` is not a number"))
+        };
+        let (low, high) = (bound(low)?, bound(high)?);
+        if let (Some(low), Some(high)) = (low, high)
+            && low >= high
+        {
+            return Err(format!("`{name}` is empty: {low} is not below {high}"));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            low,
+            high,
+            color: color.map(parse_color).transpose()?,
+        })
+    }
+}
+
+/// Parse `#RRGGBB` (the `#` is optional).
+fn parse_color(text: &str) -> std::result::Result<[u8; 3], String> {
+    let hex = text.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(format!("`{text}` is not a #RRGGBB color")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_band_and_color() {
+        let part: Part = "bone:300::#E8DCC8".parse().unwrap();
+        assert_eq!(part.name, "bone");
+        assert_eq!((part.low, part.high), (Some(300.0), None));
+        assert_eq!(part.color, Some([0xE8, 0xDC, 0xC8]));
+        assert_eq!(part.range_text(), "300..");
+    }
+
+    #[test]
+    fn negative_bounds_and_default_color() {
+        let part: Part = "soft tissue:-300:300".parse().unwrap();
+        assert_eq!((part.low, part.high), (Some(-300.0), Some(300.0)));
+        assert_eq!(color(&part, 1), PALETTE[1]);
+        assert_eq!(color(&part, PALETTE.len()), PALETTE[0]);
+    }
+
+    #[test]
+    fn rejects_malformed_parts() {
+        for bad in [
+            "bone",
+            "bone:300",
+            ":300:",
+            "bone:abc:",
+            "bone:300:100",
+            "bone:1:2:#12345",
+            "bone:1:2:#GG0000",
+        ] {
+            assert!(bad.parse::<Part>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn mask_is_half_open() {
+        let part: Part = "band:0:10".parse().unwrap();
+        assert_eq!(part.mask(&[-1.0, 0.0, 5.0, 10.0]), [0.0, 1.0, 1.0, 0.0]);
+    }
+}
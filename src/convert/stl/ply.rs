@@ -0,0 +1,123 @@
+//! PLY writer with vertex normals and colors.
+//!
+//! All parts go into one binary little-endian vertex/face list; each vertex
+//! carries its part's color, so parts stay distinguishable in viewers.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::Object;
+
+/// Write parts (coordinates in mm) as binary PLY, scaled by `per_mm`.
+pub(super) fn write(objects: &[Object<'_>], path: &Path, per_mm: f32) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create PLY file: {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    write_to(&mut out, objects, per_mm)
+        .and_then(|()| out.flush().map_err(Into::into))
+        .with_context(|| format!("Failed to write PLY data: {}", path.display()))
+}
+
+/// Write the header and the binary body.
+fn write_to(out: &mut impl Write, objects: &[Object<'_>], per_mm: f32) -> Result<()> {
+    let vertices: usize = objects.iter().map(|o| o.mesh.vertices.len()).sum();
+    let faces: usize = objects.iter().map(|o| o.mesh.indices.len() / 3).sum();
+    write!(
+        out,
+        "ply\nformat binary_little_endian 1.0\ncomment dcm-toolbox\n\
+         element vertex {vertices}\n\
+         property float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         property uchar red\nproperty uchar green\nproperty uchar blue\n\
+         element face {faces}\n\
+         property list uchar uint vertex_indices\n\
+         end_header\n"
+    )?;
+
+    for object in objects {
+        for vertex in &object.mesh.vertices {
+            let (p, n) = (vertex.posit, vertex.normal);
+            for field in [p.x * per_mm, p.y * per_mm, p.z * per_mm, n.x, n.y, n.z] {
+                out.write_all(&field.to_le_bytes())?;
+            }
+            out.write_all(&object.color)?;
+        }
+    }
+
+    let mut base = 0;
+    for object in objects {
+        for tri in object.mesh.indices.chunks_exact(3) {
+            out.write_all(&[3])?;
+            for &index in tri {
+                let index = u32::try_from(base + index).context("Mesh too large for PLY")?;
+                out.write_all(&index.to_le_bytes())?;
+            }
+        }
+        base += object.mesh.vertices.len();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lin_alg::f32::Vec3;
+    use mcubes::{Mesh, Vertex};
+
+    fn triangle() -> Mesh {
+        let vertex = |x, y| Vertex {
+            posit: Vec3::new(x, y, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+        };
+        Mesh {
+            vertices: vec![vertex(0.0, 0.0), vertex(10.0, 0.0), vertex(0.0, 10.0)],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    #[test]
+    fn parts_share_one_vertex_list() {
+        let mesh = triangle();
+        let objects = [
+            Object {
+                name: "a",
+                color: [1, 2, 3],
+                mesh: &mesh,
+            },
+            Object {
+                name: "b",
+                color: [4, 5, 6],
+                mesh: &mesh,
+            },
+        ];
+        let mut out = Vec::new();
+        write_to(&mut out, &objects, 0.1).unwrap();
+
+        let header_end = b"end_header\n";
+        let body = out
+            .windows(header_end.len())
+            .position(|w| w == header_end)
+            .unwrap()
+            + header_end.len();
+        let header = String::from_utf8_lossy(&out[..body]);
+        assert!(header.contains("element vertex 6\n"));
+        assert!(header.contains("element face 2\n"));
+
+        // 6 floats + 3 color bytes per vertex, then 1 + 3 * 4 bytes per face
+        let vertex_size = 6 * 4 + 3;
+        assert_eq!(out.len() - body, 6 * vertex_size + 2 * 13);
+        let second_x = body + vertex_size;
+        assert_eq!(out[second_x..second_x + 4], 1.0f32.to_le_bytes());
+        assert_eq!(
+            out[body + 4 * vertex_size - 3..body + 4 * vertex_size],
+            [4, 5, 6]
+        );
+
+        let last_face = out.len() - 13;
+        assert_eq!(out[last_face], 3);
+        assert_eq!(out[last_face + 1..last_face + 5], 3u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,286 @@
+//! Shaded preview image of a generated mesh.
+//!
+//! The mesh is rasterized in three orthographic views (front, side, top)
+//! placed next to each other in one grayscale PNG, so the iso-level can be
+//! sanity-checked without opening a mesh viewer. Shading is interpolated
+//! from the vertex normals across each triangle, so the surface looks smooth
+//! rather than faceted.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::{GrayImage, Luma};
+use mcubes::Mesh;
+
+use super::{bounds, face_normal};
+
+/// Edge length of each view, in pixels.
+const VIEW_SIZE: u32 = 256;
+
+/// Blank border around the mesh in each view, in pixels.
+pub(super) const MARGIN: f32 = 8.0;
+
+/// Gray level of surfaces seen edge-on; surfaces facing the viewer are white.
+const AMBIENT: f32 = 60.0;
+
+/// A mesh axis and the direction it runs in a view.
+type Axis = (usize, f32);
+
+/// Orthographic view: mesh axes along image right, image up, and toward the
+/// viewer.
+struct View {
+    right: Axis,
+    up: Axis,
+    toward: Axis,
+}
+
+/// Front (from -Y), side (from +X), and top (from +Z) views. Mesh axes follow
+/// column, row, and slice order, so for axial series these are the anterior,
+/// left lateral, and superior views.
+const VIEWS: [View; 3] = [
+    View {
+        right: (0, 1.0),
+        up: (2, 1.0),
+        toward: (1, -1.0),
+    },
+    View {
+        right: (1, 1.0),
+        up: (2, 1.0),
+        toward: (0, 1.0),
+    },
+    View {
+        right: (0, -1.0),
+        up: (1, -1.0),
+        toward: (2, 1.0),
+    },
+];
+
+/// Render the preview of a mesh and save it as PNG.
+pub(super) fn write(mesh: &Mesh, path: &Path) -> Result<()> {
+    render(mesh)
+        .save_with_format(path, image::ImageFormat::Png)
+        .with_context(|| format!("Failed to save mesh preview: {}", path.display()))
+}
+
+/// Rasterize all views side by side, with one shared scale so their
+/// proportions match.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn render(mesh: &Mesh) -> GrayImage {
+    let positions: Vec<[f32; 3]> = mesh
+        .vertices
+        .iter()
+        .map(|v| [v.posit.x, v.posit.y, v.posit.z])
+        .collect();
+    let (min, max) = bounds(mesh);
+    let center: [f32; 3] = std::array::from_fn(|i| min[i].midpoint(max[i]));
+    let largest = (0..3).map(|i| max[i] - min[i]).fold(0.0, f32::max);
+    let scale = if largest > 0.0 {
+        2.0f32.mul_add(-MARGIN, VIEW_SIZE as f32) / largest
+    } else {
+        1.0
+    };
+
+    let mut image = GrayImage::new(VIEW_SIZE * 3, VIEW_SIZE);
+    for (panel, view) in (0..).zip(&VIEWS) {
+        let half = VIEW_SIZE as f32 / 2.0;
+        let project = |p: [f32; 3]| {
+            [
+                (view.right.1 * (p[view.right.0] - center[view.right.0])).mul_add(scale, half),
+                (-view.up.1 * (p[view.up.0] - center[view.up.0])).mul_add(scale, half),
+                view.toward.1 * p[view.toward.0],
+            ]
+        };
+
+        let mut toward = [0.0; 3];
+        toward[view.toward.0] = 1.0;
+        let mut depth = vec![f32::NEG_INFINITY; (VIEW_SIZE * VIEW_SIZE) as usize];
+        for tri in mesh.indices.chunks_exact(3) {
+            let tri = [tri[0], tri[1], tri[2]];
+            let projected = tri.map(|i| project(positions[i]));
+            fill(
+                &projected,
+                shades(mesh, tri, toward),
+                &mut depth,
+                VIEW_SIZE,
+                |x, y, shade| {
+                    image.put_pixel(panel * VIEW_SIZE + x, y, Luma([shade.round() as u8]));
+                },
+            );
+        }
+    }
+    image
+}
+
+/// Gray level at the corners of a triangle seen along the unit vector
+/// `toward`: white facing the viewer, [`AMBIENT`] edge-on.
+pub(super) fn shades(mesh: &Mesh, tri: [usize; 3], toward: [f32; 3]) -> [f32; 3] {
+    let face = face_normal(tri.map(|i| mesh.vertices[i].posit));
+    tri.map(|i| {
+        let n = mesh.vertices[i].normal;
+        // Vertices without a normal fall back to flat shading
+        let normal = if n.magnitude() > 0.0 {
+            [n.x, n.y, n.z]
+        } else {
+            face
+        };
+        let facing = toward[0].mul_add(
+            normal[0],
+            toward[1].mul_add(normal[1], toward[2] * normal[2]),
+        );
+        (255.0 - AMBIENT).mul_add(facing.abs(), AMBIENT)
+    })
+}
+
+/// Fill one projected triangle of a `size`-pixel square view, keeping the
+/// pixels nearest to the viewer.
+///
+/// `tri` holds pixel x, pixel y, and depth (larger is nearer) per corner;
+/// `shades` the gray level at each corner. `plot` receives each pixel that
+/// comes out on top, with its interpolated gray level.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub(super) fn fill(
+    tri: &[[f32; 3]; 3],
+    shades: [f32; 3],
+    depth: &mut [f32],
+    size: u32,
+    mut plot: impl FnMut(u32, u32, f32),
+) {
+    let [v0, v1, v2] = *tri;
+    let area = edge(v0, v1, v2);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+
+    let last = (size - 1) as f32;
+    let lo = |i: usize| v0[i].min(v1[i]).min(v2[i]).floor().clamp(0.0, last) as u32;
+    let hi = |i: usize| v0[i].max(v1[i]).max(v2[i]).ceil().clamp(0.0, last) as u32;
+
+    for y in lo(1)..=hi(1) {
+        for x in lo(0)..=hi(0) {
+            let pixel = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+            let weights = [
+                edge(v1, v2, pixel),
+                edge(v2, v0, pixel),
+                edge(v0, v1, pixel),
+            ]
+            .map(|w| w / area);
+            if weights.iter().any(|&w| w < 0.0) {
+                continue;
+            }
+            let blend =
+                |a: [f32; 3]| weights[2].mul_add(a[2], weights[0].mul_add(a[0], weights[1] * a[1]));
+            let z = blend([v0[2], v1[2], v2[2]]);
+            let idx = (y * size + x) as usize;
+            if z > depth[idx] {
+                depth[idx] = z;
+                plot(x, y, blend(shades));
+            }
+        }
+    }
+}
+
+/// Twice the signed area of `(a, b, p)` in the image plane.
+fn edge(a: [f32; 3], b: [f32; 3], p: [f32; 3]) -> f32 {
+    (b[0] - a[0]).mul_add(p[1] - a[1], -((b[1] - a[1]) * (p[0] - a[0])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lin_alg::f32::Vec3;
+    use mcubes::Vertex;
+
+    /// Closed tetrahedron spanning 10 mm along each axis.
+    fn tetrahedron() -> Mesh {
+        let vertex = |x, y, z| Vertex {
+            posit: Vec3::new(x, y, z),
+            normal: Vec3::new_zero(),
+        };
+        Mesh {
+            vertices: vec![
+                vertex(0.0, 0.0, 0.0),
+                vertex(10.0, 0.0, 0.0),
+                vertex(0.0, 10.0, 0.0),
+                vertex(0.0, 0.0, 10.0),
+            ],
+            indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn every_view_shows_the_mesh() {
+        let image = render(&tetrahedron());
+        assert_eq!(image.dimensions(), (VIEW_SIZE * 3, VIEW_SIZE));
+        for panel in 0..3 {
+            let x0 = panel * VIEW_SIZE;
+            let lit = (x0..x0 + VIEW_SIZE)
+                .flat_map(|x| (0..VIEW_SIZE).map(move |y| (x, y)))
+                .filter(|&(x, y)| image.get_pixel(x, y)[0] > 0)
+                .count();
+            assert!(lit > 1000, "view {panel} has only {lit} lit pixels");
+            assert_eq!(image.get_pixel(x0, 0)[0], 0, "corner of view {panel}");
+        }
+    }
+
+    #[test]
+    fn facing_surfaces_are_brightest() {
+        let image = render(&tetrahedron());
+        // Front view looks along Y: the face on y = 0 faces the viewer
+        let (x, y) = (VIEW_SIZE / 2 - 40, VIEW_SIZE / 2 + 40);
+        assert_eq!(image.get_pixel(x, y)[0], 255);
+    }
+
+    #[test]
+    fn shading_follows_vertex_normals() {
+        // One triangle in the y = 0 plane, seen face-on from the front
+        let vertex = |x, z, normal| Vertex {
+            posit: Vec3::new(x, 0.0, z),
+            normal,
+        };
+        let facing = Vec3::new(0.0, -1.0, 0.0);
+        let edge_on = Vec3::new(1.0, 0.0, 0.0);
+        let mesh = Mesh {
+            vertices: vec![
+                vertex(0.0, 0.0, facing),
+                vertex(10.0, 0.0, edge_on),
+                vertex(0.0, 10.0, facing),
+            ],
+            indices: vec![0, 1, 2],
+        };
+        let image = render(&mesh);
+        let gray = |x| image.get_pixel(x, VIEW_SIZE / 2)[0];
+        // Brightness falls off toward the corner whose normal is edge-on
+        let (near, far) = (gray(VIEW_SIZE / 2 - 110), gray(VIEW_SIZE / 2 - 10));
+        assert!(near > far, "{near} <= {far}");
+        assert!(f32::from(far) > AMBIENT, "{far}");
+    }
+
+    #[test]
+    fn degenerate_mesh_renders_blank() {
+        let mut mesh = tetrahedron();
+        mesh.vertices
+            .iter_mut()
+            .for_each(|v| v.posit = Vec3::new_zero());
+        let image = render(&mesh);
+        assert!(image.pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn writes_png_next_to_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.png");
+        write(&tetrahedron(), &path).unwrap();
+        assert_eq!(
+            image::image_dimensions(&path).unwrap(),
+            (VIEW_SIZE * 3, VIEW_SIZE)
+        );
+    }
+}
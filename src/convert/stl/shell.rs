@@ -0,0 +1,197 @@
+//! Hollowing for printable models (`--shell-thickness`).
+//!
+//! Voxels deeper inside the solid than the wall thickness are pushed below
+//! the iso-level, so Marching Cubes adds an inner surface that follows the
+//! outer one. Depth is the Euclidean distance in mm to the nearest voxel
+//! outside the solid or to the edge of the volume, from a separable distance
+//! transform that honors anisotropic voxel spacing.
+
+/// Hollow the solid (values above `threshold`) to a shell `thickness` mm
+/// thick; returns the number of voxels removed.
+pub(super) fn hollow(
+    values: &mut [f32],
+    dims: [usize; 3],
+    spacing: [f32; 3],
+    threshold: f32,
+    thickness: f32,
+) -> usize {
+    let solid: Vec<bool> = values.iter().map(|&v| v > threshold).collect();
+    let depth = depth_squared(&solid, dims, spacing);
+
+    // Below the iso-level even when every voxel is solid
+    let lowest = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let fill = lowest.min(threshold - 1.0);
+
+    let limit = thickness * thickness;
+    let mut removed = 0;
+    for (value, &depth) in values.iter_mut().zip(&depth) {
+        if depth > limit {
+            *value = fill;
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Squared distance in mm from each voxel to the nearest non-solid voxel or
+/// the outside of the volume (0 for non-solid voxels).
+#[allow(clippy::cast_precision_loss)]
+fn depth_squared(solid: &[bool], dims: [usize; 3], spacing: [f32; 3]) -> Vec<f32> {
+    let [cols, rows, _] = dims;
+    let mut depth: Vec<f32> = solid
+        .iter()
+        .map(|&s| if s { f32::INFINITY } else { 0.0 })
+        .collect();
+
+    let strides = [1, cols, cols * rows];
+    let (mut line, mut out) = (Vec::new(), Vec::new());
+    for axis in 0..3 {
+        let (len, stride) = (dims[axis], strides[axis]);
+        for start in (0..depth.len()).filter(|&i| i / stride % len == 0) {
+            line.clear();
+            line.extend((0..len).map(|k| depth[start + k * stride]));
+            out.resize(len, 0.0);
+            transform_1d(&line, spacing[axis], &mut out);
+            for (k, &d) in out.iter().enumerate() {
+                depth[start + k * stride] = d;
+            }
+        }
+    }
+
+    // Everything past the edge of the volume counts as outside
+    for (index, d) in depth.iter_mut().enumerate() {
+        let position = [index % cols, index / cols % rows, index / (cols * rows)];
+        for axis in 0..3 {
+            let steps = (position[axis] + 1).min(dims[axis] - position[axis]);
+            let edge = steps as f32 * spacing[axis];
+            *d = d.min(edge * edge);
+        }
+    }
+    depth
+}
+
+/// One pass of the Felzenszwalb–Huttenlocher distance transform: for each
+/// sample `i`, the minimum over `j` of `((i - j) * step)² + f[j]`.
+///
+/// Builds the lower envelope of the parabolas rooted at finite samples, then
+/// reads it off left to right. Writes infinity when no sample is finite.
+#[allow(clippy::cast_precision_loss)]
+fn transform_1d(f: &[f32], step: f32, out: &mut [f32]) {
+    let position = |q: usize| q as f32 * step;
+    // Apexes of the envelope's parabolas, and where each becomes the lowest
+    let (mut apexes, mut starts): (Vec<usize>, Vec<f32>) = (Vec::new(), Vec::new());
+
+    for q in (0..f.len()).filter(|&q| f[q].is_finite()) {
+        let lifted = position(q).mul_add(position(q), f[q]);
+        let mut start = f32::NEG_INFINITY;
+        while let (Some(&v), Some(&v_start)) = (apexes.last(), starts.last()) {
+            let v_lifted = position(v).mul_add(position(v), f[v]);
+            start = (lifted - v_lifted) / (2.0 * (position(q) - position(v)));
+            if start > v_start {
+                break;
+            }
+            apexes.pop();
+            starts.pop();
+            start = f32::NEG_INFINITY;
+        }
+        apexes.push(q);
+        starts.push(start);
+    }
+
+    if apexes.is_empty() {
+        out.fill(f32::INFINITY);
+        return;
+    }
+    let mut k = 0;
+    for (q, d) in out.iter_mut().enumerate() {
+        while k + 1 < apexes.len() && starts[k + 1] < position(q) {
+            k += 1;
+        }
+        let offset = position(q) - position(apexes[k]);
+        *d = offset.mul_add(offset, f[apexes[k]]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn transform_matches_brute_force() {
+        let f = [
+            f32::INFINITY,
+            4.0,
+            f32::INFINITY,
+            0.0,
+            9.0,
+            f32::INFINITY,
+            1.0,
+        ];
+        let mut out = [0.0; 7];
+        transform_1d(&f, 2.0, &mut out);
+
+        for (i, &d) in out.iter().enumerate() {
+            let expected = f
+                .iter()
+                .enumerate()
+                .map(|(j, &fj)| {
+                    let offset = (i as f32 - j as f32) * 2.0;
+                    offset.mul_add(offset, fj)
+                })
+                .fold(f32::INFINITY, f32::min);
+            assert!((d - expected).abs() < 1e-4, "sample {i}: {d} vs {expected}");
+        }
+
+        transform_1d(&[f32::INFINITY; 3], 1.0, &mut out[..3]);
+        assert_eq!(out[..3], [f32::INFINITY; 3]);
+    }
+
+    #[test]
+    fn hollows_a_cube_to_its_walls() {
+        // 9³ solid cube inside an 11³ volume of air
+        let n = 11;
+        let mut values = vec![0.0; n * n * n];
+        for z in 1..10 {
+            for y in 1..10 {
+                for x in 1..10 {
+                    values[(z * n + y) * n + x] = 1.0;
+                }
+            }
+        }
+        let removed = hollow(&mut values, [n; 3], [1.0; 3], 0.5, 2.0);
+
+        // Walls keep the two outer layers; the 5³ core goes
+        assert_eq!(removed, 5 * 5 * 5);
+        assert!(values[(5 * n + 5) * n + 5] < 0.5);
+        assert!(values[(5 * n + 5) * n + 2] > 0.5);
+        assert!(values[(5 * n + 5) * n + 3] < 0.5);
+    }
+
+    #[test]
+    fn spacing_scales_wall_depth() {
+        // A solid slab 9 voxels thick along z only, at 0.5 mm slices
+        let (cols, rows, slices) = (3, 3, 11);
+        let mut values = vec![0.0; cols * rows * slices];
+        values[cols * rows..cols * rows * 10].fill(1.0);
+        let dims = [cols, rows, slices];
+        let depth = depth_squared(
+            &values.iter().map(|&v| v > 0.5).collect::<Vec<_>>(),
+            dims,
+            [10.0, 10.0, 0.5],
+        );
+
+        // Center slice is 5 slices (2.5 mm) from the air below and above
+        let center = 5 * cols * rows + cols + 1;
+        assert!((depth[center] - 6.25).abs() < 1e-4, "{}", depth[center]);
+    }
+
+    #[test]
+    fn solid_volume_is_walled_at_its_edges() {
+        let mut values = vec![5.0; 5 * 5 * 5];
+        let removed = hollow(&mut values, [5; 3], [1.0; 3], 1.0, 1.0);
+
+        assert_eq!(removed, 27);
+        assert_eq!(values.iter().filter(|&&v| v < 1.0).count(), 27);
+    }
+}
@@ -0,0 +1,175 @@
+//! Iso-level presets for common CT targets (`--target`).
+//!
+//! Otsu's threshold splits the histogram into two classes, which on CT
+//! usually lands between air and soft tissue, so it rarely gives bone. The
+//! presets are Hounsfield levels picked by `BodyPartExamined` and corrected
+//! with the series' own air and soft-tissue peaks, so scanners with a
+//! slightly off calibration still get sensible surfaces.
+
+use std::path::Path;
+
+use clap::ValueEnum;
+use dicom::dictionary_std::tags;
+use dicom::object::open_file;
+
+/// Width of the histogram bins used to find peaks, in HU.
+const BIN_HU: f32 = 10.0;
+
+/// Where the air peak is searched for, in HU.
+const AIR_RANGE: (f32, f32) = (-1100.0, -700.0);
+
+/// Where the soft-tissue peak is searched for, in HU.
+const TISSUE_RANGE: (f32, f32) = (-200.0, 200.0);
+
+/// Nominal air and soft-tissue values, used when a peak is missing.
+const AIR_HU: f32 = -1000.0;
+const TISSUE_HU: f32 = 40.0;
+
+/// What the surface should wrap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    /// Cortical bone
+    Bone,
+    /// The body outline (air to soft tissue)
+    Skin,
+    /// Air-filled spaces such as the trachea and bronchi
+    Airways,
+}
+
+impl Target {
+    /// Name for messages.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Bone => "bone",
+            Self::Skin => "skin",
+            Self::Airways => "airways",
+        }
+    }
+}
+
+/// Suggested iso-levels of one CT series, in HU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Presets {
+    pub bone: f32,
+    pub skin: f32,
+    pub airways: f32,
+}
+
+impl Presets {
+    /// Presets for a series whose first slice is `first_file`; `None`
+    /// unless it is CT, as other modalities have no fixed value scale.
+    pub fn detect(first_file: &Path, values: &[f32]) -> Option<Self> {
+        let obj = open_file(first_file).ok()?;
+        let text = |tag| {
+            obj.element(tag)
+                .ok()
+                .and_then(|e| e.to_str().ok())
+                .map(|s| s.trim().to_ascii_uppercase())
+        };
+        if text(tags::MODALITY).as_deref() != Some("CT") {
+            return None;
+        }
+        Some(Self::for_scan(
+            text(tags::BODY_PART_EXAMINED).as_deref(),
+            values,
+        ))
+    }
+
+    /// Presets from the body part and the value histogram.
+    fn for_scan(body_part: Option<&str>, values: &[f32]) -> Self {
+        let air = peak(values, AIR_RANGE).unwrap_or(AIR_HU);
+        let tissue = peak(values, TISSUE_RANGE).unwrap_or(TISSUE_HU);
+
+        // Thick skull cortex takes a higher level (and skips contrast in
+        // vessels); thin cortex of hands and feet needs a lower one
+        let bone = match body_part {
+            Some("HEAD" | "SKULL" | "BRAIN" | "HEADNECK") => 400.0,
+            Some(
+                "HAND" | "FOOT" | "WRIST" | "ANKLE" | "FINGER" | "TOE" | "ELBOW" | "EXTREMITY",
+            ) => 200.0,
+            _ => 300.0,
+        };
+        Self {
+            bone: bone + (tissue - TISSUE_HU),
+            skin: air.midpoint(tissue),
+            airways: air + 100.0,
+        }
+    }
+
+    /// Iso-level for a target.
+    pub const fn level(self, target: Target) -> f32 {
+        match target {
+            Target::Bone => self.bone,
+            Target::Skin => self.skin,
+            Target::Airways => self.airways,
+        }
+    }
+}
+
+/// Center of the fullest histogram bin in `[low, high)`, if any value is there.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn peak(values: &[f32], (low, high): (f32, f32)) -> Option<f32> {
+    let mut bins = vec![0usize; ((high - low) / BIN_HU) as usize];
+    for &v in values {
+        if (low..high).contains(&v) {
+            bins[((v - low) / BIN_HU) as usize] += 1;
+        }
+    }
+    let (bin, &count) = bins
+        .iter()
+        .enumerate()
+        .max_by_key(|&(i, &count)| (count, std::cmp::Reverse(i)))?;
+    (count > 0).then(|| (bin as f32 + 0.5).mul_add(BIN_HU, low))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Air, fat, soft tissue, and a little bone, shifted by `offset` HU.
+    fn ct(offset: f32) -> Vec<f32> {
+        let mut values = vec![-1000.0 + offset; 400];
+        values.extend(vec![-100.0 + offset; 100]);
+        values.extend(vec![40.0 + offset; 300]);
+        values.extend(vec![700.0 + offset; 50]);
+        values
+    }
+
+    #[test]
+    fn levels_sit_between_the_peaks() {
+        // Peaks are bin centers, so levels are good to one bin
+        let presets = Presets::for_scan(Some("CHEST"), &ct(0.0));
+        assert!((presets.skin + 480.0).abs() < BIN_HU, "{presets:?}");
+        assert!((presets.airways + 900.0).abs() < BIN_HU, "{presets:?}");
+        assert!((presets.bone - 300.0).abs() < BIN_HU, "{presets:?}");
+        assert!((presets.level(Target::Skin) - presets.skin).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn body_part_moves_the_bone_level() {
+        let head = Presets::for_scan(Some("HEAD"), &ct(0.0));
+        let hand = Presets::for_scan(Some("HAND"), &ct(0.0));
+        assert!(head.bone > hand.bone + 150.0, "{head:?} {hand:?}");
+    }
+
+    #[test]
+    fn calibration_offset_shifts_every_level() {
+        let nominal = Presets::for_scan(None, &ct(0.0));
+        let shifted = Presets::for_scan(None, &ct(30.0));
+        for target in [Target::Bone, Target::Skin, Target::Airways] {
+            let moved = shifted.level(target) - nominal.level(target);
+            assert!((moved - 30.0).abs() < 1.0, "{target:?} moved {moved}");
+        }
+    }
+
+    #[test]
+    fn missing_peaks_fall_back_to_nominal_values() {
+        let presets = Presets::for_scan(None, &[1500.0; 10]);
+        assert!((presets.skin + 480.0).abs() < 1.0, "{presets:?}");
+        assert_eq!(peak(&[], AIR_RANGE), None);
+    }
+}
@@ -0,0 +1,231 @@
+//! 3MF writer for multi-part models.
+//!
+//! A 3MF file is a ZIP package with one XML model. Each part becomes its own
+//! named object with a display color from a shared `basematerials` group, so
+//! slicers can assign parts to different filaments. Entries carry the fixed
+//! ZIP default timestamp, which keeps output byte-identical across runs.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
+
+use super::{MeshUnits, Object};
+
+/// Package part listing the content types.
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+/// Package relationships pointing at the model.
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+/// Path of the model inside the package.
+const MODEL_PATH: &str = "3D/3dmodel.model";
+
+/// Write parts (coordinates in mm) as a 3MF package in the given units.
+pub(super) fn write(objects: &[Object<'_>], path: &Path, units: MeshUnits) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create 3MF file: {}", path.display()))?;
+    let mut zip = zip::ZipWriter::new(BufWriter::new(file));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let result = (|| -> Result<()> {
+        zip.start_file("[Content_Types].xml", options)?;
+        zip.write_all(CONTENT_TYPES.as_bytes())?;
+        zip.start_file("_rels/.rels", options)?;
+        zip.write_all(RELATIONSHIPS.as_bytes())?;
+        zip.start_file(MODEL_PATH, options)?;
+        write_model(&mut zip, objects, units)?;
+        zip.finish()?.flush()?;
+        Ok(())
+    })();
+    result.with_context(|| format!("Failed to write 3MF data: {}", path.display()))
+}
+
+/// Write the `3dmodel.model` XML.
+fn write_model(out: &mut impl Write, objects: &[Object<'_>], units: MeshUnits) -> Result<()> {
+    let per_mm = units.per_mm();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<model unit="{}" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">"#,
+        unit_name(units)
+    )?;
+    writeln!(
+        out,
+        r#"  <metadata name="Application">dcm-toolbox</metadata>"#
+    )?;
+    writeln!(out, "  <resources>")?;
+    writeln!(out, r#"    <basematerials id="1">"#)?;
+    for object in objects {
+        let [r, g, b] = object.color;
+        writeln!(
+            out,
+            r##"      <base name="{}" displaycolor="#{r:02X}{g:02X}{b:02X}FF"/>"##,
+            escape(object.name)
+        )?;
+    }
+    writeln!(out, "    </basematerials>")?;
+
+    for ((id, index), object) in (2..).zip(0..).zip(objects) {
+        writeln!(
+            out,
+            r#"    <object id="{id}" type="model" name="{}" pid="1" pindex="{index}">"#,
+            escape(object.name)
+        )?;
+        writeln!(out, "      <mesh>")?;
+        writeln!(out, "        <vertices>")?;
+        for vertex in &object.mesh.vertices {
+            let p = vertex.posit;
+            writeln!(
+                out,
+                r#"          <vertex x="{}" y="{}" z="{}"/>"#,
+                p.x * per_mm,
+                p.y * per_mm,
+                p.z * per_mm
+            )?;
+        }
+        writeln!(out, "        </vertices>")?;
+        writeln!(out, "        <triangles>")?;
+        for tri in object.mesh.indices.chunks_exact(3) {
+            writeln!(
+                out,
+                r#"          <triangle v1="{}" v2="{}" v3="{}"/>"#,
+                tri[0], tri[1], tri[2]
+            )?;
+        }
+        writeln!(out, "        </triangles>")?;
+        writeln!(out, "      </mesh>")?;
+        writeln!(out, "    </object>")?;
+    }
+    writeln!(out, "  </resources>")?;
+
+    writeln!(out, "  <build>")?;
+    for id in (2..).take(objects.len()) {
+        writeln!(out, r#"    <item objectid="{id}"/>"#)?;
+    }
+    writeln!(out, "  </build>")?;
+    writeln!(out, "</model>")?;
+    Ok(())
+}
+
+/// 3MF `unit` attribute value.
+const fn unit_name(units: MeshUnits) -> &'static str {
+    match units {
+        MeshUnits::Mm => "millimeter",
+        MeshUnits::Cm => "centimeter",
+        MeshUnits::M => "meter",
+    }
+}
+
+/// Escape text for an XML attribute.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lin_alg::f32::Vec3;
+    use mcubes::{Mesh, Vertex};
+    use std::io::Read;
+
+    fn triangle(x: f32) -> Mesh {
+        let vertex = |x, y| Vertex {
+            posit: Vec3::new(x, y, 0.0),
+            normal: Vec3::new_zero(),
+        };
+        Mesh {
+            vertices: vec![vertex(x, 0.0), vertex(x + 10.0, 0.0), vertex(x, 10.0)],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    fn read_entry(path: &Path, name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn writes_named_colored_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.3mf");
+        let (bone, skin) = (triangle(0.0), triangle(20.0));
+        let objects = [
+            Object {
+                name: "bone",
+                color: [0xE8, 0xDC, 0xC8],
+                mesh: &bone,
+            },
+            Object {
+                name: "skin & fat",
+                color: [200, 120, 100],
+                mesh: &skin,
+            },
+        ];
+        write(&objects, &path, MeshUnits::Mm).unwrap();
+
+        assert!(read_entry(&path, "[Content_Types].xml").contains("3dmodel+xml"));
+        assert!(read_entry(&path, "_rels/.rels").contains("/3D/3dmodel.model"));
+        let model = read_entry(&path, MODEL_PATH);
+        assert!(model.contains(r#"unit="millimeter""#));
+        assert!(model.contains(r##"<base name="bone" displaycolor="#E8DCC8FF"/>"##));
+        assert!(model.contains(r#"name="skin &amp; fat" pid="1" pindex="1""#));
+        assert!(model.contains(r#"<vertex x="30" y="0" z="0"/>"#));
+        assert!(model.contains(r#"<item objectid="3"/>"#));
+    }
+
+    #[test]
+    fn coordinates_follow_units() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.3mf");
+        let mesh = triangle(0.0);
+        let objects = [Object {
+            name: "part",
+            color: [0; 3],
+            mesh: &mesh,
+        }];
+        write(&objects, &path, MeshUnits::Cm).unwrap();
+
+        let model = read_entry(&path, MODEL_PATH);
+        assert!(model.contains(r#"unit="centimeter""#));
+        assert!(model.contains(r#"<vertex x="1" y="0" z="0"/>"#), "{model}");
+    }
+
+    #[test]
+    fn output_is_reproducible() {
+        let dir = tempfile::tempdir().unwrap();
+        let mesh = triangle(0.0);
+        let objects = [Object {
+            name: "part",
+            color: [1, 2, 3],
+            mesh: &mesh,
+        }];
+        let (a, b) = (dir.path().join("a.3mf"), dir.path().join("b.3mf"));
+        write(&objects, &a, MeshUnits::Mm).unwrap();
+        write(&objects, &b, MeshUnits::Mm).unwrap();
+        assert_eq!(std::fs::read(a).unwrap(), std::fs::read(b).unwrap());
+    }
+}
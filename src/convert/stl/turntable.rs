@@ -0,0 +1,196 @@
+//! Turntable video of a generated mesh (`--turntable`).
+//!
+//! A camera circles the model once about its slice axis (head to feet for
+//! axial series), starting from the front view of the preview. Frames are
+//! shaded like the preview, tinted with each part's color, staged as PNG,
+//! and encoded to MP4 with ffmpeg. The scale fits the widest view, so the
+//! model keeps its size through the whole turn.
+
+use std::f32::consts::TAU;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use image::{Rgb, RgbImage};
+use mcubes::Mesh;
+
+use super::bounds;
+use super::preview::{MARGIN, fill, shades};
+use crate::convert::{FrameInput, encode_mp4};
+use crate::utils::create_temp_dir;
+
+/// Frames per second of the video.
+const FPS: u32 = 30;
+
+/// Edge length of the square frames, in pixels.
+const FRAME_SIZE: u32 = 512;
+
+/// Render one turn of the colored meshes over `seconds` and encode it to
+/// `path`; returns the number of frames.
+#[allow(clippy::cast_precision_loss)]
+pub(super) fn write(parts: &[([u8; 3], &Mesh)], seconds: f32, path: &Path) -> Result<usize> {
+    let frames = frame_count(seconds);
+    let orbit = Orbit::around(parts);
+    let staging = create_temp_dir(None)?;
+    for frame in 0..frames {
+        let angle = TAU * frame as f32 / frames as f32;
+        let frame_path = staging.path().join(format!("frame_{frame:06}.png"));
+        orbit
+            .render(parts, angle)
+            .save_with_format(&frame_path, image::ImageFormat::Png)
+            .with_context(|| format!("Failed to save frame: {}", frame_path.display()))?;
+    }
+    let input = FrameInput::images(staging.path().join("frame_%06d.png"), 0);
+    encode_mp4(&input, FPS, path)?;
+    Ok(frames)
+}
+
+/// Frames in `seconds` of video; at least two, so the model turns.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn frame_count(seconds: f32) -> usize {
+    ((seconds * FPS as f32).round() as usize).max(2)
+}
+
+/// Camera path: the point circled and the pixels per mm.
+struct Orbit {
+    center: [f32; 3],
+    scale: f32,
+}
+
+impl Orbit {
+    /// Fit the orbit to the meshes: their bounding box center, and a scale at
+    /// which the widest horizontal extent and the height both fit.
+    #[allow(clippy::cast_precision_loss)]
+    fn around(parts: &[([u8; 3], &Mesh)]) -> Self {
+        let (min, max) = parts.iter().map(|(_, mesh)| bounds(mesh)).fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(lo, hi), (min, max)| {
+                (
+                    std::array::from_fn(|i| lo[i].min(min[i])),
+                    std::array::from_fn(|i| hi[i].max(max[i])),
+                )
+            },
+        );
+        let center: [f32; 3] = std::array::from_fn(|i| min[i].midpoint(max[i]));
+        let radius = parts
+            .iter()
+            .flat_map(|(_, mesh)| &mesh.vertices)
+            .map(|v| (v.posit.x - center[0]).hypot(v.posit.y - center[1]))
+            .fold(0.0, f32::max);
+        let extent = (2.0 * radius).max(max[2] - min[2]);
+        let scale = if extent > 0.0 {
+            2.0f32.mul_add(-MARGIN, FRAME_SIZE as f32) / extent
+        } else {
+            1.0
+        };
+        Self { center, scale }
+    }
+
+    /// The frame seen from `angle` radians around the orbit; 0 is the front.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn render(&self, parts: &[([u8; 3], &Mesh)], angle: f32) -> RgbImage {
+        let (sin, cos) = angle.sin_cos();
+        // Image right and toward the viewer; up is always +Z
+        let (right, toward) = ([cos, sin], [sin, -cos]);
+        let [cx, cy, cz] = self.center;
+        let half = FRAME_SIZE as f32 / 2.0;
+        let project = |v: &mcubes::Vertex| {
+            let (dx, dy) = (v.posit.x - cx, v.posit.y - cy);
+            [
+                right[0]
+                    .mul_add(dx, right[1] * dy)
+                    .mul_add(self.scale, half),
+                (cz - v.posit.z).mul_add(self.scale, half),
+                toward[0].mul_add(dx, toward[1] * dy),
+            ]
+        };
+
+        let mut image = RgbImage::new(FRAME_SIZE, FRAME_SIZE);
+        let mut depth = vec![f32::NEG_INFINITY; (FRAME_SIZE * FRAME_SIZE) as usize];
+        for &(color, mesh) in parts {
+            for tri in mesh.indices.chunks_exact(3) {
+                let tri = [tri[0], tri[1], tri[2]];
+                let projected = tri.map(|i| project(&mesh.vertices[i]));
+                fill(
+                    &projected,
+                    shades(mesh, tri, [toward[0], toward[1], 0.0]),
+                    &mut depth,
+                    FRAME_SIZE,
+                    |x, y, shade| {
+                        let tint = color.map(|c| (f32::from(c) * shade / 255.0).round() as u8);
+                        image.put_pixel(x, y, Rgb(tint));
+                    },
+                );
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lin_alg::f32::Vec3;
+    use mcubes::Vertex;
+
+    /// Flat 20 x 10 mm square in the y = 0 plane, facing the front.
+    fn card() -> Mesh {
+        let vertex = |x, z| Vertex {
+            posit: Vec3::new(x, 0.0, z),
+            normal: Vec3::new(0.0, -1.0, 0.0),
+        };
+        Mesh {
+            vertices: vec![
+                vertex(0.0, 0.0),
+                vertex(20.0, 0.0),
+                vertex(20.0, 10.0),
+                vertex(0.0, 10.0),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    fn lit(image: &RgbImage) -> usize {
+        image.pixels().filter(|p| p.0 != [0; 3]).count()
+    }
+
+    #[test]
+    fn frame_count_follows_duration() {
+        assert_eq!(frame_count(4.0), 120);
+        assert_eq!(frame_count(0.01), 2);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn scale_fits_the_widest_turn() {
+        let mesh = card();
+        let orbit = Orbit::around(&[([255; 3], &mesh)]);
+        assert_eq!(orbit.center.to_vec(), [10.0, 0.0, 5.0]);
+        // 20 mm across fills the frame minus its margins
+        let expected = 2.0f32.mul_add(-MARGIN, FRAME_SIZE as f32) / 20.0;
+        assert!((orbit.scale - expected).abs() < 1e-4, "{}", orbit.scale);
+    }
+
+    #[test]
+    fn card_turns_edge_on_and_back() {
+        let mesh = card();
+        let parts = [([255, 0, 0], &mesh)];
+        let orbit = Orbit::around(&parts);
+
+        let front = orbit.render(&parts, 0.0);
+        let side = orbit.render(&parts, TAU / 4.0);
+        let back = orbit.render(&parts, TAU / 2.0);
+        assert!(lit(&front) > 50_000, "{}", lit(&front));
+        assert!(lit(&side) < lit(&front) / 20, "{}", lit(&side));
+        assert!(lit(&back).abs_diff(lit(&front)) < 1000);
+        // Facing the camera, the part color comes through at full strength
+        assert_eq!(front.get_pixel(256, 256).0, [255, 0, 0]);
+    }
+}
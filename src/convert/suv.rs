@@ -0,0 +1,270 @@
+//! Standardized uptake values of PET images (`--suv`).
+//!
+//! PET pixels hold activity concentrations (Bq/ml) that depend on how much
+//! tracer was injected, how long ago, and into how heavy a patient, so raw
+//! values mean nothing on their own. With `--suv` they are divided by the
+//! injected dose, decayed to the scan, per gram of body weight (SUVbw):
+//!
+//! `SUV = value × weight (g) / (dose (Bq) × 2^(−elapsed / half-life))`
+//!
+//! The dose, half-life, and injection time come from the Radiopharmaceutical
+//! Information Sequence. Images decay-corrected to the series start
+//! (`DecayCorrection` `START`, the usual case) decay the dose to
+//! `SeriesTime`, uncorrected ones (`NONE`) to `AcquisitionTime`, and those
+//! corrected to the injection (`ADMIN`) not at all. Frames are then shown on
+//! a fixed SUV window from 0, so every slice of a series is on the same
+//! scale.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+
+use super::session::{parse_date, parse_time};
+use crate::meta::text;
+use crate::outcome::BadInput;
+use crate::pipeline;
+use crate::pixel::{DecodedFrame, Window, read_first_f64};
+use crate::volume::Volume;
+
+/// Seconds in a day.
+const DAY: i64 = 86_400;
+
+/// How SUV-scaled frames are shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SuvDisplay {
+    /// SUV shown as white; the window runs from 0 to it.
+    pub max: f32,
+}
+
+impl SuvDisplay {
+    /// Scale the values of `frame` by the SUV `factor` of its object and
+    /// window them from 0 to [`Self::max`], higher uptake brighter.
+    pub fn apply(self, frame: DecodedFrame, factor: f64) -> DecodedFrame {
+        match frame {
+            DecodedFrame::Mono(mut frame) => {
+                #[allow(clippy::cast_possible_truncation)]
+                let factor = factor as f32;
+                for value in &mut frame.values {
+                    *value *= factor;
+                }
+                frame.window = Some(Window::spanning(0.0, self.max));
+                frame.invert = false;
+                DecodedFrame::Mono(frame)
+            }
+            color @ DecodedFrame::Color(_) => color,
+        }
+    }
+}
+
+/// Factor turning the calibrated values of `obj` into SUVbw. Bad input when
+/// the header lacks what SUV needs or its pixels are not Bq/ml.
+pub fn factor(obj: &InMemDicomObject) -> Result<f64> {
+    let missing = |what: &str| BadInput(format!("Cannot compute SUV: no {what} in the header"));
+    match text(obj, tags::UNITS).as_deref() {
+        Some("BQML") => {}
+        // Already body-weight SUV
+        Some("GML") => return Ok(1.0),
+        Some(units) => anyhow::bail!(BadInput(format!(
+            "Cannot compute SUV of images in {units}; only BQML (Bq/ml) is supported"
+        ))),
+        None => anyhow::bail!(missing("Units")),
+    }
+    let weight = read_first_f64(obj, tags::PATIENT_WEIGHT)
+        .filter(|kg| *kg > 0.0)
+        .ok_or_else(|| missing("PatientWeight"))?;
+    let tracer = obj
+        .element(tags::RADIOPHARMACEUTICAL_INFORMATION_SEQUENCE)
+        .ok()
+        .and_then(|e| e.items())
+        .and_then(|items| items.first())
+        .ok_or_else(|| missing("RadiopharmaceuticalInformationSequence"))?;
+    let dose = read_first_f64(tracer, tags::RADIONUCLIDE_TOTAL_DOSE)
+        .filter(|bq| *bq > 0.0)
+        .ok_or_else(|| missing("RadionuclideTotalDose"))?;
+    let half_life = read_first_f64(tracer, tags::RADIONUCLIDE_HALF_LIFE)
+        .filter(|s| *s > 0.0)
+        .ok_or_else(|| missing("RadionuclideHalfLife"))?;
+
+    let elapsed = match text(obj, tags::DECAY_CORRECTION).as_deref() {
+        Some("ADMIN") => 0,
+        correction => {
+            let injected = injection(tracer).ok_or_else(|| missing("injection time"))?;
+            let (date, time) = if correction == Some("NONE") {
+                (tags::ACQUISITION_DATE, tags::ACQUISITION_TIME)
+            } else {
+                (tags::SERIES_DATE, tags::SERIES_TIME)
+            };
+            let scanned = moment(obj, date, time)
+                .or_else(|| moment(obj, tags::SERIES_DATE, tags::SERIES_TIME))
+                .ok_or_else(|| missing("scan time"))?;
+            elapsed(injected, scanned).ok_or_else(|| {
+                BadInput("Cannot compute SUV: the injection is recorded after the scan".into())
+            })?
+        }
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let decayed = dose * (-(elapsed as f64) / half_life).exp2();
+    Ok(weight * 1000.0 / decayed)
+}
+
+/// Scale every slice of `volume`, built from `files` in order, to SUVbw.
+pub fn scale_volume(volume: &mut Volume, files: &[PathBuf]) -> Result<()> {
+    let plane = volume.cols * volume.rows;
+    let mut start = 0;
+    for path in files {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)?;
+        #[allow(clippy::cast_possible_truncation)]
+        let factor = factor(&obj)? as f32;
+        // A multi-frame object holds a slice per frame
+        let end = (start + pipeline::frames_in(path) * plane).min(volume.values.len());
+        for value in &mut volume.values[start..end] {
+            *value *= factor;
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// A point in time: days since 1970 if the date is known, and seconds since
+/// midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Moment {
+    day: Option<i64>,
+    seconds: i64,
+}
+
+/// The moment of the `date` and `time` tags of `obj`.
+fn moment(obj: &InMemDicomObject, date: Tag, time: Tag) -> Option<Moment> {
+    Some(Moment {
+        day: text(obj, date).and_then(|date| parse_date(&date)),
+        seconds: i64::from(parse_time(&text(obj, time)?)?),
+    })
+}
+
+/// When the tracer was injected: `RadiopharmaceuticalStartDateTime`, else
+/// `RadiopharmaceuticalStartTime` (without a date).
+fn injection(tracer: &InMemDicomObject) -> Option<Moment> {
+    let from_date_time = text(tracer, tags::RADIOPHARMACEUTICAL_START_DATE_TIME).and_then(|dt| {
+        // The UTC offset, if any, is the scan's too
+        let dt = dt.split(['+', '-']).next()?;
+        let (date, time) = dt.split_at_checked(8)?;
+        Some(Moment {
+            day: Some(parse_date(date)?),
+            seconds: i64::from(parse_time(time)?),
+        })
+    });
+    from_date_time.or_else(|| {
+        Some(Moment {
+            day: None,
+            seconds: i64::from(parse_time(&text(
+                tracer,
+                tags::RADIOPHARMACEUTICAL_START_TIME,
+            )?)?),
+        })
+    })
+}
+
+/// Seconds from `from` to `to`; `None` if `to` is earlier. Without both
+/// dates, a scan time before the injection time is taken as the next day.
+fn elapsed(from: Moment, to: Moment) -> Option<i64> {
+    let seconds = match (from.day, to.day) {
+        (Some(a), Some(b)) => (b - a) * DAY + to.seconds - from.seconds,
+        _ => (to.seconds - from.seconds).rem_euclid(DAY),
+    };
+    (seconds >= 0).then_some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::{DataElement, VR};
+
+    use super::*;
+    use crate::meta::text_element;
+    use crate::pixel::Frame;
+
+    /// An FDG scan whose series starts an hour after 370 MBq were injected
+    /// into 70 kg.
+    fn pet(decay_correction: &str, units: &str) -> InMemDicomObject {
+        let tracer = InMemDicomObject::from_element_iter([
+            text_element(tags::RADIONUCLIDE_TOTAL_DOSE, VR::DS, "370000000"),
+            text_element(tags::RADIONUCLIDE_HALF_LIFE, VR::DS, "6586.2"),
+            text_element(tags::RADIOPHARMACEUTICAL_START_TIME, VR::TM, "093000"),
+        ]);
+        InMemDicomObject::from_element_iter([
+            text_element(tags::UNITS, VR::CS, units),
+            text_element(tags::DECAY_CORRECTION, VR::CS, decay_correction),
+            text_element(tags::PATIENT_WEIGHT, VR::DS, "70"),
+            text_element(tags::SERIES_DATE, VR::DA, "20240102"),
+            text_element(tags::SERIES_TIME, VR::TM, "103000"),
+            text_element(tags::ACQUISITION_DATE, VR::DA, "20240102"),
+            text_element(tags::ACQUISITION_TIME, VR::TM, "104500.25"),
+            DataElement::new(
+                tags::RADIOPHARMACEUTICAL_INFORMATION_SEQUENCE,
+                VR::SQ,
+                dicom::core::value::DataSetSequence::from(vec![tracer]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn the_dose_decays_to_the_series_start() {
+        let decayed = 370e6 * (-3600.0_f64 / 6586.2).exp2();
+        let factor = factor(&pet("START", "BQML")).unwrap();
+        assert!((factor - 70_000.0 / decayed).abs() < 1e-12, "{factor}");
+        // 5000 Bq/ml in a 70 kg patient given 370 MBq an hour ago
+        assert!((5000.0 * factor - 1.38).abs() < 0.01, "{}", 5000.0 * factor);
+    }
+
+    #[test]
+    fn decay_follows_the_correction() {
+        let at = |seconds: f64| 70_000.0 / (370e6 * (-seconds / 6586.2).exp2());
+        assert!((factor(&pet("NONE", "BQML")).unwrap() - at(4500.0)).abs() < 1e-12);
+        assert!((factor(&pet("ADMIN", "BQML")).unwrap() - at(0.0)).abs() < 1e-12);
+        assert!((factor(&pet("START", "GML")).unwrap() - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn missing_or_other_units_are_bad_input() {
+        let mut obj = pet("START", "CNTS");
+        let err = factor(&obj).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        assert!(err.to_string().contains("CNTS"), "{err}");
+
+        obj.put(text_element(tags::UNITS, VR::CS, "BQML"));
+        obj.remove_element(tags::PATIENT_WEIGHT);
+        let err = factor(&obj).unwrap_err();
+        assert!(err.to_string().contains("PatientWeight"), "{err}");
+    }
+
+    #[test]
+    fn a_scan_past_midnight_is_the_next_day() {
+        let at = |day, seconds| Moment { day, seconds };
+        assert_eq!(elapsed(at(None, 86_000), at(None, 400)), Some(800));
+        assert_eq!(elapsed(at(Some(1), 86_000), at(Some(2), 400)), Some(800));
+        assert_eq!(elapsed(at(Some(2), 400), at(Some(1), 86_000)), None);
+    }
+
+    #[test]
+    fn frames_are_windowed_in_suv() {
+        let display = SuvDisplay { max: 5.0 };
+        let frame = DecodedFrame::Mono(Frame {
+            width: 2,
+            height: 1,
+            values: vec![1000.0, 20_000.0],
+            window: None,
+            invert: true,
+        });
+        let DecodedFrame::Mono(frame) = display.apply(frame, 2.5e-4) else {
+            unreachable!();
+        };
+        assert_eq!(frame.values, vec![0.25, 5.0]);
+        let window = frame.window.unwrap();
+        assert_eq!((window.apply(0.0), window.apply(5.0)), (0, 255));
+        assert!(!frame.invert);
+    }
+}
@@ -1,135 +1,1125 @@
 //! DICOM to MP4 video conversion.
 
+mod aspect;
+mod brand;
+mod cardiac;
+mod hwaccel;
+mod position;
+mod rate;
+mod staging;
+mod study;
+mod trim;
+
+use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
 
 use anyhow::{Context, Result};
-use image::ImageFormat;
+use clap::ValueEnum;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
 use tempfile::TempDir;
 
-pub(super) fn convert_to_video(dcm_files: &[PathBuf], output_dir: &Path, fps: u32) -> Result<()> {
+use self::aspect::SquarePixels;
+pub use self::brand::Watermark;
+use self::brand::WatermarkSink;
+pub use self::cardiac::{Cardiac, chosen, cine_slices};
+pub use self::hwaccel::Hwaccel;
+use self::position::PositionSink;
+use self::rate::{AUDIO_BITRATE, Rate};
+pub use self::rate::{parse_size, video_bitrate};
+pub use self::staging::{FrameInput, RawStagingSink};
+pub use self::study::{Series, convert_study};
+use self::trim::Clip;
+pub use self::trim::Trim;
+use super::{ImageFormat, JpegSink};
+use crate::annotate::parse_hex_color;
+use crate::cancel::{self, Cancel};
+use crate::events::{self, Stage};
+use crate::ffmpeg;
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, FrameSink, RenderOptions, RunStats};
+use crate::pixel::read_first_f64;
+use crate::utils::{create_temp_dir, ensure_free_space, format_bytes, named_after_folder};
+
+/// Frame rate when `--fps` is not given and no multi-frame object records one.
+const DEFAULT_FPS: u32 = 10;
+
+/// Extra room kept on top of the staging estimate, as a divisor (10%).
+const STAGING_HEADROOM: u64 = 10;
+
+/// Video codec used for MP4 encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum VideoCodec {
+    /// H.264 (libx264), plays everywhere
+    #[default]
+    H264,
+    /// H.265/HEVC (libx265), smaller files
+    H265,
+}
+
+impl VideoCodec {
+    /// ffmpeg's software encoder for this codec.
+    const fn software(self) -> &'static str {
+        match self {
+            Self::H264 => "libx264",
+            Self::H265 => "libx265",
+        }
+    }
+
+    /// ffmpeg encoder for this codec, on the GPU if `hwaccel`.
+    fn encoder(self, hwaccel: Option<Hwaccel>) -> &'static str {
+        hwaccel.map_or_else(|| self.software(), |hw| hw.encoder(self))
+    }
+
+    /// ffmpeg encoder arguments for this codec, on the GPU if `hwaccel`.
+    fn encoder_args(self, hwaccel: Option<Hwaccel>) -> Vec<&'static str> {
+        let mut args = vec!["-c:v", self.encoder(hwaccel)];
+        if self == Self::H265 {
+            // `hvc1` lets Apple players open the file
+            args.extend(["-tag:v", "hvc1"]);
+        }
+        args
+    }
+}
+
+/// Color around frames letterboxed to the video's size (`--background`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Background(pub Rgb<u8>);
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::BLACK
+    }
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    /// `black`, `white`, or `#rrggbb`.
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "black" => Ok(Self::BLACK),
+            "white" => Ok(Self(Rgb([255, 255, 255]))),
+            _ => parse_hex_color(value)
+                .map(Self)
+                .ok_or_else(|| format!("`{value}` is not black, white, or #rrggbb")),
+        }
+    }
+}
+
+impl Background {
+    const BLACK: Self = Self(Rgb([0, 0, 0]));
+
+    /// Whether the color has no hue, so gray frames can stay gray.
+    const fn is_gray(self) -> bool {
+        let [r, g, b] = self.0.0;
+        r == g && g == b
+    }
+
+    /// Color as ffmpeg writes it, `0xrrggbb`.
+    fn ffmpeg(self) -> String {
+        let [r, g, b] = self.0.0;
+        format!("0x{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+/// Scale `image` to fit `width` x `height` with its aspect ratio kept,
+/// centered on `background`.
+fn letterbox(
+    image: &DynamicImage,
+    (width, height): (u32, u32),
+    background: Background,
+) -> DynamicImage {
+    let fitted = image.resize(width, height, FilterType::Lanczos3);
+    let left = i64::from((width - fitted.width()) / 2);
+    let top = i64::from((height - fitted.height()) / 2);
+    if let (DynamicImage::ImageLuma8(gray), true) = (&fitted, background.is_gray()) {
+        let mut frame = GrayImage::from_pixel(width, height, Luma([background.0[0]]));
+        imageops::overlay(&mut frame, gray, left, top);
+        return DynamicImage::ImageLuma8(frame);
+    }
+    let mut frame = RgbImage::from_pixel(width, height, background.0);
+    imageops::overlay(&mut frame, &fitted.into_rgb8(), left, top);
+    DynamicImage::ImageRgb8(frame)
+}
+
+/// Passes frames on to another sink, remembering the size of the first one
+/// written.
+struct SizedSink<S> {
+    inner: S,
+    size: Option<(u32, u32)>,
+}
+
+impl<S: FrameSink> FrameSink for SizedSink<S> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        let size = (image.width(), image.height());
+        self.inner.write_frame(index, source, image)?;
+        self.size.get_or_insert(size);
+        Ok(())
+    }
+}
+
+/// Frames rendered for ffmpeg, and where they live.
+struct StagedFrames {
+    stats: RunStats,
+    /// The written frames; `None` when nothing was written.
+    input: Option<FrameInput>,
+    /// Size of the first frame; `None` when nothing was written.
+    size: Option<(u32, u32)>,
+    /// Set when frames were written to the series folder as kept images,
+    /// which may differ in size and must be scaled while encoding.
+    kept: bool,
+    /// Title card frames staged ahead of the slices.
+    intro: usize,
+    /// Staging folder, removed once dropped.
+    _temp_dir: Option<TempDir>,
+}
+
+/// Options for video export.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VideoOptions<'a> {
+    /// Frames per second; the cine rate or [`DEFAULT_FPS`] when `None`.
+    pub fps: Option<u32>,
+    /// Folder for staged frames (`--temp-dir`).
+    pub temp_root: Option<&'a Path>,
+    /// Keep every frame in this format and encode from them.
+    pub with_images: Option<ImageFormat>,
+    /// Draw the slice position bar.
+    pub position_bar: bool,
+    /// Narration muxed into the MP4.
+    pub audio: Option<&'a Path>,
+    /// Text of the title card that opens the video.
+    pub title: Option<&'a str>,
+    /// Image stamped in the corner of every frame.
+    pub watermark: Option<&'a Path>,
+    /// Color around frames of another size than the first.
+    pub background: Background,
+    /// File size to fit the MP4 in, in bytes.
+    pub target_size: Option<u64>,
+    /// Encode on the GPU.
+    pub hwaccel: Option<Hwaccel>,
+    /// Encode once more with the [`Encoding::fallback`] if ffmpeg fails.
+    pub encode_fallback: bool,
+    /// Only encode part of each series' frames.
+    pub trim: Option<Trim>,
+}
+
+impl<'a> VideoOptions<'a> {
+    /// How to encode `frames` at `fps` into the MP4.
+    fn encoding(&self, fps: u32, frames: u32) -> Result<Encoding<'a>> {
+        let bitrate = self
+            .target_size
+            .map(|size| video_bitrate(size, frames, fps, self.audio.is_some()))
+            .transpose()?;
+        Ok(Encoding {
+            fps,
+            background: self.background,
+            audio: self.audio,
+            bitrate,
+            hwaccel: self.hwaccel,
+            retry: self.encode_fallback,
+            ..Encoding::default()
+        })
+    }
+}
+
+pub(super) fn convert_to_video(
+    dcm_files: &[PathBuf],
+    output_dir: &Path,
+    options: RenderOptions<'_>,
+    video: VideoOptions<'_>,
+) -> Result<(RunStats, Option<EncodeFailure>)> {
+    let fps = resolve_fps(video.fps, dcm_files);
     if fps == 0 {
         anyhow::bail!("FPS must be greater than 0");
     }
+    let watermark = video.watermark.map(Watermark::load).transpose()?;
+    let clip = Clip::of(dcm_files, video.trim, fps)?;
 
     // Derive video name from the folder name
-    let folder_name = output_dir
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("output");
-    let video_path = output_dir.join(format!("{folder_name}.mp4"));
-
-    // Create temporary directory for intermediate frames
-    let temp_dir = TempDir::new().with_context(|| "Failed to create temporary directory")?;
+    let video_path = named_after_folder(output_dir, "mp4");
+
+    let staged = match video.with_images {
+        Some(format) => keep_frames(
+            &clip,
+            output_dir,
+            format,
+            options.clone(),
+            video.position_bar,
+            watermark.as_ref(),
+        ),
+        None => stage_frames(&clip, options.clone(), &video, fps, watermark.as_ref())?,
+    };
+    let stats = staged.stats;
+
+    let (Some(input), Some((target_width, target_height))) = (&staged.input, staged.size) else {
+        anyhow::bail!("No frames were successfully processed for video creation");
+    };
+    // Frames stop coming when it is time to stop; don't encode the rest
+    options.cancel.check()?;
+    let frame_count =
+        u32::try_from(stats.written + staged.intro).context("Too many frames for video")?;
+    let encoding = video.encoding(fps, frame_count)?;
+
+    println!(
+        "{}",
+        t!(
+            "video-creating",
+            width = target_width,
+            height = target_height,
+            fps = fps
+        )
+    );
+    if stats.failed > 0 {
+        eprintln!("{}", t!("video-skipped-frames", count = stats.failed));
+    }
+
+    events::stage(output_dir, Stage::Encoding);
+    let scale = staged.kept.then_some((target_width, target_height));
+    let recovered = encode_sequence(
+        input,
+        Encoding {
+            scale,
+            cancel: options.cancel,
+            ..encoding
+        },
+        &video_path,
+    )?;
+
+    println!(
+        "\n{}",
+        t!("video-saved", path = video_path.display().to_string())
+    );
+    println!("  {}", t!("video-total-frames", count = frame_count));
+    println!(
+        "  {}",
+        t!(
+            "video-duration",
+            seconds = format!("{:.2}", f64::from(frame_count) / f64::from(fps))
+        )
+    );
+
+    // The staging folder, if any, is cleaned up when `staged` is dropped
+    Ok((stats, recovered))
+}
+
+/// Render frames into a temporary folder as raw pixels, all resized to the first
+/// frame, after making sure they fit on the temp disk. The title card, if
+/// any, comes first.
+fn stage_frames(
+    clip: &Clip<'_>,
+    options: RenderOptions<'_>,
+    video: &VideoOptions<'_>,
+    fps: u32,
+    watermark: Option<&Watermark>,
+) -> Result<StagedFrames> {
+    let temp_dir = create_temp_dir(video.temp_root)?;
     let temp_path = temp_dir.path();
+    let intro = if video.title.is_some() {
+        brand::hold_frames(fps)
+    } else {
+        0
+    };
+    let total = clip.frames() + intro;
+    let color = options.annotations.is_some()
+        || !video.background.is_gray()
+        || video.position_bar
+        || video.title.is_some()
+        || watermark.is_some();
+    let samples = if color { 3 } else { 1 };
+    if let Some(first) = clip.files.first() {
+        let required = staging_estimate(frame_bytes(first, samples), total);
+        println!(
+            "{}",
+            t!(
+                "video-staging-space",
+                size = format_bytes(required),
+                path = temp_path.display().to_string()
+            )
+        );
+        ensure_free_space(temp_path, required)?;
+    }
 
-    println!("Preparing frames for video encoding...");
-
-    // Load first frame to determine dimensions for consistent sizing
-    let first_image = super::load_dcm_as_image(&dcm_files[0])?;
-    let (target_width, target_height) = (first_image.width(), first_image.height());
-
-    println!("Creating video: {target_width}x{target_height} @ {fps} fps");
-
-    // Save all frames as PNG files with sequential numbering
-    let mut frame_count = 0;
-    for (idx, dcm_path) in dcm_files.iter().enumerate() {
-        match super::load_dcm_as_image(dcm_path) {
-            Ok(img) => {
-                // Resize if dimensions don't match first frame
-                let img = if img.width() != target_width || img.height() != target_height {
-                    img.resize_exact(
-                        target_width,
-                        target_height,
-                        image::imageops::FilterType::Lanczos3,
-                    )
-                } else {
-                    img
-                };
-
-                // Save as PNG with zero-padded numbering for ffmpeg
-                let frame_path = temp_path.join(format!("frame_{idx:06}.png"));
-                img.save_with_format(&frame_path, ImageFormat::Png)
-                    .with_context(|| format!("Failed to save frame: {}", frame_path.display()))?;
-
-                frame_count += 1;
-                println!(
-                    "✓ Prepared frame {}/{}: {}",
-                    idx + 1,
-                    dcm_files.len(),
-                    dcm_path.file_name().unwrap().display()
-                );
-            }
-            Err(e) => {
-                eprintln!(
-                    "✗ Failed to load {}: {}",
-                    dcm_path.file_name().unwrap().display(),
-                    e
-                );
-            }
+    println!("{}", t!("video-preparing"));
+
+    let mut staging = RawStagingSink::new(temp_path, total)
+        .with_background(video.background)
+        .in_color(color);
+    let mut marked;
+    let sink: &mut dyn FrameSink = match watermark {
+        Some(watermark) => {
+            marked = WatermarkSink::new(&mut staging, watermark);
+            &mut marked
         }
+        None => &mut staging,
+    };
+    if let Some(title) = video.title {
+        let first = clip.files.first().map(PathBuf::as_path);
+        brand::write_card(sink, 0, title, None, first, fps)?;
     }
+    let mut frames = Offset {
+        inner: sink,
+        offset: intro,
+    };
+    let stats = render(clip, options, video.position_bar, &mut frames);
+    Ok(StagedFrames {
+        stats,
+        input: staging.finish()?,
+        size: staging.target_size,
+        kept: false,
+        intro,
+        _temp_dir: Some(temp_dir),
+    })
+}
 
-    if frame_count == 0 {
-        anyhow::bail!("No frames were successfully processed for video creation");
+/// Render frames once as the series' images (`--with-images`), which then
+/// double as the ffmpeg input instead of a separate staging pass.
+fn keep_frames(
+    clip: &Clip<'_>,
+    output_dir: &Path,
+    format: ImageFormat,
+    options: RenderOptions<'_>,
+    position_bar: bool,
+    watermark: Option<&Watermark>,
+) -> StagedFrames {
+    println!("{}", t!("video-keeping-images"));
+
+    let mut sized = SizedSink {
+        inner: JpegSink::new(output_dir, clip.frames(), format),
+        size: None,
+    };
+    let stats = match watermark {
+        Some(watermark) => render(
+            clip,
+            options,
+            position_bar,
+            &mut WatermarkSink::new(&mut sized, watermark),
+        ),
+        None => render(clip, options, position_bar, &mut sized),
+    };
+    StagedFrames {
+        stats,
+        input: Some(FrameInput::images(sized.inner.pattern(), 1)),
+        size: sized.size,
+        kept: true,
+        intro: 0,
+        _temp_dir: None,
+    }
+}
+
+/// Shifts frame indices so progress counts across the whole study.
+struct Offset<'a> {
+    inner: &'a mut dyn FrameSink,
+    offset: usize,
+}
+
+impl FrameSink for Offset<'_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        self.inner.write_frame(self.offset + index, source, image)
+    }
+}
+
+/// Run the pipeline over the frames of `clip` into `sink`, stretched to
+/// square pixels, then through the position bar if asked for.
+fn render(
+    clip: &Clip<'_>,
+    options: RenderOptions<'_>,
+    position_bar: bool,
+    sink: &mut dyn FrameSink,
+) -> RunStats {
+    let span = clip.span.clone();
+    if position_bar {
+        let mut bar = PositionSink::new(sink, clip.files, clip.frames());
+        pipeline::run_span(clip.files, span, options, &mut SquarePixels::new(&mut bar))
+    } else {
+        pipeline::run_span(clip.files, span, options, &mut SquarePixels::new(sink))
+    }
+}
+
+/// Estimated disk usage of `frames` staged raw frames of `frame_bytes`
+/// bytes each, plus headroom.
+pub const fn staging_estimate(frame_bytes: u64, frames: usize) -> u64 {
+    let total = frame_bytes.saturating_mul(frames as u64);
+    total.saturating_add(total / STAGING_HEADROOM)
+}
+
+/// Raw size of one 8-bit staged frame sized like `first`, with `samples`
+/// channels (3 once annotations add color). An unreadable header gives 0.
+fn frame_bytes(first: &Path, samples: u64) -> u64 {
+    let Ok(obj) = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(first)
+    else {
+        return 0;
+    };
+    let read = |tag| {
+        obj.element(tag)
+            .ok()
+            .and_then(|e| e.to_int::<u32>().ok())
+            .unwrap_or(0)
+    };
+    // Frames with non-square pixels are staged stretched
+    let (width, height) = aspect::square_size(&obj, read(tags::COLUMNS), read(tags::ROWS));
+    u64::from(width) * u64::from(height) * samples
+}
+
+/// `--fps` if given, else the cine rate of `dcm_files`, else [`DEFAULT_FPS`].
+fn resolve_fps(fps: Option<u32>, dcm_files: &[PathBuf]) -> u32 {
+    fps.unwrap_or_else(|| {
+        cine_fps(dcm_files).map_or(DEFAULT_FPS, |fps| {
+            println!("{}", t!("video-cine-fps", fps = fps));
+            fps
+        })
+    })
+}
+
+/// Playback rate recorded by the first multi-frame (cine) object of a group.
+fn cine_fps(dcm_files: &[PathBuf]) -> Option<u32> {
+    dcm_files.iter().find_map(|path| {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+            .ok()?;
+        let frames = obj
+            .element(tags::NUMBER_OF_FRAMES)
+            .ok()?
+            .to_int::<u32>()
+            .ok()?;
+        if frames < 2 {
+            return None;
+        }
+        let read = |tag| read_first_f64(&obj, tag);
+        frame_rate(
+            read(tags::RECOMMENDED_DISPLAY_FRAME_RATE),
+            read(tags::CINE_RATE),
+            read(tags::FRAME_TIME),
+        )
+    })
+}
+
+/// Whole frames per second from cine timing: `RecommendedDisplayFrameRate`,
+/// then `CineRate`, then `FrameTime` (ms between frames). Rates round to the
+/// nearest frame and never drop below 1.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn frame_rate(
+    recommended: Option<f64>,
+    cine_rate: Option<f64>,
+    frame_time_ms: Option<f64>,
+) -> Option<u32> {
+    let positive = |value: Option<f64>| value.filter(|v| v.is_finite() && *v > 0.0);
+    positive(recommended)
+        .or_else(|| positive(cine_rate))
+        .or_else(|| positive(frame_time_ms).map(|ms| 1000.0 / ms))
+        .map(|rate| rate.round().clamp(1.0, f64::from(u32::MAX)) as u32)
+}
+
+/// Encode `input` at `fps` into an MP4 with ffmpeg.
+pub fn encode_mp4(input: &FrameInput, fps: u32, video_path: &Path) -> Result<()> {
+    encode_sequence(
+        input,
+        Encoding {
+            fps,
+            ..Encoding::default()
+        },
+        video_path,
+    )
+    .map(|_| ())
+}
+
+/// How [`encode_sequence`] turns images into an MP4.
+#[derive(Debug, Clone, Default)]
+pub struct Encoding<'a> {
+    pub fps: u32,
+    pub codec: VideoCodec,
+    /// Scale every frame to fit this size, for frames that may differ in
+    /// size; the rest is filled with `background`.
+    pub scale: Option<(u32, u32)>,
+    pub background: Background,
+    /// Audio track, looped or cut to the length of the video.
+    pub audio: Option<&'a Path>,
+    /// ffmpeg metadata file with the video's chapters.
+    pub chapters: Option<&'a Path>,
+    /// Average video bit rate in bits/s, reached in two passes (one on the
+    /// GPU); constant quality when `None`.
+    pub bitrate: Option<u64>,
+    /// Encode on the GPU.
+    pub hwaccel: Option<Hwaccel>,
+    /// If ffmpeg fails, encode once more with [`Encoding::fallback`].
+    pub retry: bool,
+    /// One pass at a fast preset, as the fallback encodes.
+    pub simple: bool,
+    /// Kills ffmpeg on Ctrl-C or past the series' deadline.
+    pub cancel: Cancel,
+}
+
+impl Encoding<'_> {
+    /// The plainer encoding tried after a failure: software H.264 in one
+    /// pass at a fast preset, which nearly every ffmpeg build can run.
+    /// `None` when this already is it.
+    fn fallback(&self) -> Option<Self> {
+        if self.simple {
+            return None;
+        }
+        Some(Self {
+            codec: VideoCodec::H264,
+            hwaccel: None,
+            retry: false,
+            simple: true,
+            ..self.clone()
+        })
+    }
+}
+
+/// An MP4 ffmpeg failed to encode, for the run summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeFailure {
+    /// The MP4; removed after the failure unless the fallback wrote it.
+    pub path: PathBuf,
+    /// Encoder that failed.
+    pub encoder: &'static str,
+    /// Whether the fallback encode (`--encode-fallback`) wrote the MP4.
+    pub recovered: bool,
+}
+
+impl EncodeFailure {
+    /// `encode key=value ...` line for the run summary. The path comes last
+    /// and is written as-is, so it may contain spaces.
+    pub fn line(&self) -> String {
+        let status = if self.recovered { "retried" } else { "failed" };
+        format!(
+            "encode status={status} encoder={} path={}",
+            self.encoder,
+            self.path.display()
+        )
+    }
+}
+
+impl fmt::Display for EncodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to encode {}", self.path.display())
+    }
+}
+
+/// ffmpeg filter doing what [`letterbox`] does for staged frames: same size,
+/// filter, and padding.
+fn letterbox_filter((width, height): (u32, u32), background: Background) -> String {
+    format!(
+        "scale={width}:{height}:force_original_aspect_ratio=decrease:flags=lanczos,\
+         pad={width}:{height}:(ow-iw)/2:(oh-ih)/2:color={}",
+        background.ffmpeg()
+    )
+}
+
+/// Make sure ffmpeg can encode `codec` before any frame is prepared, and say
+/// which fallback [`encode_sequence`] will use for a missing encoder.
+pub fn check_ffmpeg(codec: VideoCodec, hwaccel: Option<Hwaccel>) -> Result<()> {
+    let ffmpeg = ffmpeg::probe()?;
+    let (used, gpu) = available(codec, hwaccel, |name| ffmpeg.has_encoder(name));
+    if let Some(hwaccel) = hwaccel
+        && gpu.is_none()
+    {
+        eprintln!(
+            "{}",
+            t!("ffmpeg-no-gpu-encoder", encoder = hwaccel.encoder(codec))
+        );
+    }
+    if used != codec {
+        eprintln!(
+            "{}",
+            t!(
+                "ffmpeg-codec-fallback",
+                encoder = codec.software(),
+                fallback = used.software()
+            )
+        );
+    }
+    let encoder = used.encoder(gpu);
+    if !ffmpeg.has_encoder(encoder) {
+        anyhow::bail!(BadInput(format!(
+            "ffmpeg at {} has no {encoder} encoder; install a full build \
+             (https://ffmpeg.org/download.html) or pass --ffmpeg-path",
+            ffmpeg.path().display()
+        )));
+    }
+    Ok(())
+}
+
+/// Codec and GPU family actually used, given the encoders `has` reports: a
+/// missing GPU encoder falls back to software, a missing libx265 to libx264.
+fn available(
+    codec: VideoCodec,
+    hwaccel: Option<Hwaccel>,
+    has: impl Fn(&str) -> bool,
+) -> (VideoCodec, Option<Hwaccel>) {
+    let hwaccel = hwaccel.filter(|hw| has(hw.encoder(codec)));
+    let fallback = VideoCodec::H264;
+    if hwaccel.is_none() && !has(codec.software()) && has(fallback.software()) {
+        return (fallback, None);
     }
+    (codec, hwaccel)
+}
 
-    println!("\nEncoding video with ffmpeg...");
+/// Encode the frames of `input` into an MP4 with ffmpeg. With a bit rate, ffmpeg runs
+/// twice: an analysis pass, then the encode.
+///
+/// A failed encode leaves no MP4 behind, not even an empty or truncated one,
+/// and carries an [`EncodeFailure`] as context. With `encoding.retry` it is
+/// first tried once more with the [`Encoding::fallback`]; the failure that
+/// fallback recovered from is returned.
+pub fn encode_sequence(
+    input: &FrameInput,
+    encoding: Encoding<'_>,
+    video_path: &Path,
+) -> Result<Option<EncodeFailure>> {
+    let mut encoding = encoding;
+    if let Some(ffmpeg) = ffmpeg::probed() {
+        (encoding.codec, encoding.hwaccel) = available(encoding.codec, encoding.hwaccel, |name| {
+            ffmpeg.has_encoder(name)
+        });
+    }
+    let Err(err) = encode(input, &encoding, video_path) else {
+        return Ok(None);
+    };
+    remove_partial(video_path);
+    let mut failure = EncodeFailure {
+        path: video_path.to_path_buf(),
+        encoder: encoding.codec.encoder(encoding.hwaccel),
+        recovered: false,
+    };
+    let retry = encoding.retry && !cancel::is_stopped(&err);
+    let Some(fallback) = encoding.fallback().filter(|_| retry) else {
+        return Err(err.context(failure));
+    };
+    eprintln!(
+        "{}",
+        t!(
+            "video-encode-retry",
+            encoder = failure.encoder,
+            error = format!("{err:#}")
+        )
+    );
+    match encode(input, &fallback, video_path) {
+        Ok(()) => {
+            failure.recovered = true;
+            Ok(Some(failure))
+        }
+        Err(err) => {
+            remove_partial(video_path);
+            Err(err.context(failure))
+        }
+    }
+}
+
+/// Encode with exactly `encoding`, naming the GPU encoder when it failed.
+fn encode(input: &FrameInput, encoding: &Encoding<'_>, video_path: &Path) -> Result<()> {
+    let encoded = encode_passes(input, encoding, video_path);
+    match encoding.hwaccel {
+        Some(hwaccel) => encoded.with_context(|| {
+            format!(
+                "{} failed; --hwaccel needs a matching GPU and an ffmpeg build with that encoder",
+                hwaccel.encoder(encoding.codec)
+            )
+        }),
+        None => encoded,
+    }
+}
+
+/// Delete what a failed encode left at `video_path`: an empty file, a
+/// truncated MP4, or one from an earlier run.
+fn remove_partial(video_path: &Path) {
+    if video_path.is_file() {
+        let _ = fs::remove_file(video_path);
+    }
+}
 
-    // Call ffmpeg to encode frames into video
+/// Run the ffmpeg pass(es) of [`encode_sequence`].
+fn encode_passes(input: &FrameInput, encoding: &Encoding<'_>, video_path: &Path) -> Result<()> {
+    let rate = match encoding.bitrate {
+        None => Rate::Quality,
+        Some(bitrate) if encoding.hwaccel.is_some() || encoding.simple => Rate::Average { bitrate },
+        Some(bitrate) => {
+            return encode_two_pass(input, encoding, bitrate, video_path);
+        }
+    };
+    run_ffmpeg(
+        ffmpeg_command(input, encoding, rate).arg(video_path),
+        &encoding.cancel,
+    )
+}
+
+/// Encode at `bitrate` in two passes of the software encoder.
+fn encode_two_pass(
+    input: &FrameInput,
+    encoding: &Encoding<'_>,
+    bitrate: u64,
+    video_path: &Path,
+) -> Result<()> {
+    let log_dir = tempfile::tempdir().context("Failed to create a folder for the pass log")?;
+    let log = log_dir.path().join("pass");
+    println!("{}", t!("video-first-pass", bitrate = bitrate / 1000));
+    let analysis = Encoding {
+        audio: None,
+        chapters: None,
+        ..encoding.clone()
+    };
+    let first = Rate::Pass {
+        bitrate,
+        pass: 1,
+        log: &log,
+    };
+    run_ffmpeg(
+        ffmpeg_command(input, &analysis, first).args(["-an", "-f", "null", "-"]), // Statistics only
+        &encoding.cancel,
+    )?;
+
+    println!("{}", t!("video-second-pass"));
+    let second = Rate::Pass {
+        bitrate,
+        pass: 2,
+        log: &log,
+    };
+    run_ffmpeg(
+        ffmpeg_command(input, encoding, second).arg(video_path),
+        &encoding.cancel,
+    )
+}
+
+/// ffmpeg command encoding the images at `rate`, without its output.
+fn ffmpeg_command(input: &FrameInput, encoding: &Encoding<'_>, rate: Rate<'_>) -> Command {
     // Settings optimized for AI context in medical imaging:
-    // - H.264 codec for broad compatibility
-    // - CRF 18 for high quality (near-lossless)
+    // - H.264 codec for broad compatibility (H.265 on request)
+    // - CRF 18 for high quality (near-lossless), unless a size is targeted
+    //   (GPU encoders use their closest quality setting)
     // - YUV420p pixel format for standard playback
-    // - preset slow for better compression
-    let frame_pattern = temp_path.join("frame_%06d.png");
-    let frame_pattern_str = frame_pattern
-        .to_str()
-        .with_context(|| format!("Frame pattern path is not valid UTF-8: {}", frame_pattern.display()))?;
-    let video_path_str = video_path
-        .to_str()
-        .with_context(|| format!("Video output path is not valid UTF-8: {}", video_path.display()))?;
-
-    let output = Command::new("ffmpeg")
-        .args([
-            "-y", // Overwrite output
-            "-framerate",
-            &fps.to_string(), // Input framerate
-            "-i",
-            frame_pattern_str, // Input pattern
-            "-c:v",
-            "libx264", // H.264 codec
-            "-crf",
-            "18", // High quality
-            "-preset",
-            "slow", // Better compression
-            "-pix_fmt",
-            "yuv420p", // Standard pixel format
-            "-movflags",
-            "+faststart",   // Web optimization
-            video_path_str, // Output file
-        ])
-        .output()
+    // - preset slow for better compression (veryfast for the fallback)
+    //
+    // Paths are passed as `OsStr` so non-UTF-8 and Unicode paths reach
+    // ffmpeg unchanged.
+    let Encoding {
+        fps,
+        codec,
+        scale,
+        background,
+        audio,
+        chapters,
+        hwaccel,
+        simple,
+        ..
+    } = *encoding;
+    let mut command = ffmpeg::command();
+    command.arg("-y"); // Overwrite output
+    if let Some(hwaccel) = hwaccel {
+        command.args(hwaccel.device_args());
+    }
+    command.args(input.args(fps));
+    // Further inputs first: options after them apply to the output
+    if let Some(audio) = audio {
+        println!("{}", t!("video-audio", path = audio.display().to_string()));
+        command
+            .args(["-stream_loop", "-1", "-i"]) // Repeat short narration
+            .arg(audio);
+    }
+    if let Some(chapters) = chapters {
+        command.arg("-i").arg(chapters);
+    }
+    if audio.is_some() {
+        command.args([
+            "-map",
+            "0:v",
+            "-map",
+            "1:a",
+            "-c:a",
+            "aac",
+            "-b:a",
+            &AUDIO_BITRATE.to_string(),
+            "-shortest", // Stop with the last frame
+        ]);
+    }
+    if chapters.is_some() {
+        // The metadata file is the last input
+        let input = 1 + usize::from(audio.is_some());
+        command.args(["-map_chapters", &input.to_string()]);
+    }
+    let filters: Vec<String> = scale
+        .map(|size| letterbox_filter(size, background))
+        .into_iter()
+        .chain(hwaccel.and_then(Hwaccel::upload_filter).map(String::from))
+        .collect();
+    if !filters.is_empty() {
+        command.args(["-vf", &filters.join(",")]);
+    }
+    let software: &[&str] = if simple {
+        // The fallback favors getting a video at all
+        &["-preset", "veryfast", "-pix_fmt", "yuv420p"]
+    } else {
+        &[
+            "-preset", "slow", // Better compression
+            "-pix_fmt", "yuv420p", // Standard pixel format
+        ]
+    };
+    command
+        .args(codec.encoder_args(hwaccel))
+        .args(rate.args(codec, hwaccel))
+        .args(hwaccel.map_or(software, Hwaccel::output_args))
+        .args(["-movflags", "+faststart"]); // Web optimization
+    command
+}
+
+/// Run an ffmpeg command, failing with its error output. `cancel` kills it
+/// early.
+fn run_ffmpeg(command: &mut Command, cancel: &Cancel) -> Result<()> {
+    let _slot = ffmpeg::slots::acquire(cancel)?;
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
         .with_context(|| "Failed to execute ffmpeg. Is ffmpeg installed?")?;
+    let (status, stderr) = cancel.wait(&mut child)?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    if !status.success() {
+        // Ctrl-C reaches ffmpeg too; say so instead of quoting its error
+        cancel.check()?;
         anyhow::bail!("ffmpeg encoding failed: {stderr}");
     }
 
-    println!("\n✓ Video saved to: {}", video_path.display());
-    println!("  Total frames: {frame_count}");
-    println!(
-        "  Duration: {:.2}s",
-        f64::from(frame_count) / f64::from(fps)
-    );
-
-    // temp_dir is automatically cleaned up when dropped
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    // =========================================================================
+    // Staging Space Estimate Tests
+    // =========================================================================
+
+    mod staging_space {
+        use super::super::*;
+
+        #[test]
+        fn estimate_adds_headroom() {
+            assert_eq!(staging_estimate(512 * 512, 100), 512 * 512 * 110);
+        }
+
+        #[test]
+        fn unreadable_header_needs_no_space() {
+            assert_eq!(frame_bytes(Path::new("/nonexistent.dcm"), 1), 0);
+        }
+    }
+
+    // =========================================================================
+    // Cine Frame Rate Tests
+    // =========================================================================
+
+    mod cine_rate {
+        use super::super::*;
+
+        #[test]
+        fn recommended_rate_comes_first() {
+            assert_eq!(frame_rate(Some(25.0), Some(30.0), Some(20.0)), Some(25));
+            assert_eq!(frame_rate(None, Some(30.0), Some(20.0)), Some(30));
+        }
+
+        #[test]
+        fn frame_time_is_milliseconds_between_frames() {
+            assert_eq!(frame_rate(None, None, Some(33.3)), Some(30));
+            // Slow loops still play at one frame per second or faster
+            assert_eq!(frame_rate(None, None, Some(4000.0)), Some(1));
+        }
+
+        #[test]
+        fn missing_or_zero_timing_gives_no_rate() {
+            assert_eq!(frame_rate(None, None, None), None);
+            assert_eq!(frame_rate(Some(0.0), None, Some(0.0)), None);
+        }
+
+        #[test]
+        fn single_frame_series_have_no_cine_rate() {
+            assert_eq!(cine_fps(&[PathBuf::from("/nonexistent.dcm")]), None);
+        }
+    }
+
+    // =========================================================================
+    // Kept Image Tests (--with-images)
+    // =========================================================================
+
+    mod kept_images {
+        use super::super::*;
+
+        #[test]
+        fn sized_sink_remembers_first_written_frame() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut sink = SizedSink {
+                inner: JpegSink::new(dir.path(), 2, ImageFormat::Png),
+                size: None,
+            };
+            let source = Path::new("a.dcm");
+            sink.write_frame(0, source, DynamicImage::new_luma8(4, 2))
+                .unwrap();
+            sink.write_frame(1, source, DynamicImage::new_luma8(8, 8))
+                .unwrap();
+            assert_eq!(sink.size, Some((4, 2)));
+        }
+
+        #[test]
+        fn kept_frames_are_the_series_images() {
+            let dir = tempfile::tempdir().unwrap();
+            let staged = keep_frames(
+                &Clip::whole(&[]),
+                dir.path(),
+                ImageFormat::Jpeg,
+                RenderOptions::default(),
+                false,
+                None,
+            );
+            assert!(staged.kept);
+            assert!(staged.size.is_none());
+            assert_eq!(
+                staged.input,
+                Some(FrameInput::images(dir.path().join("%04d.jpg"), 1))
+            );
+        }
+    }
+
+    // =========================================================================
+    // Encoder Fallback Tests
+    // =========================================================================
+
+    mod encoder_fallback {
+        use super::super::*;
+
+        fn offers(names: &'static [&'static str]) -> impl Fn(&str) -> bool {
+            move |name| names.contains(&name)
+        }
+
+        #[test]
+        fn missing_gpu_encoder_falls_back_to_software() {
+            assert_eq!(
+                available(VideoCodec::H264, Some(Hwaccel::Nvenc), offers(&["libx264"])),
+                (VideoCodec::H264, None)
+            );
+            assert_eq!(
+                available(
+                    VideoCodec::H265,
+                    Some(Hwaccel::Nvenc),
+                    offers(&["hevc_nvenc"])
+                ),
+                (VideoCodec::H265, Some(Hwaccel::Nvenc))
+            );
+        }
+
+        #[test]
+        fn missing_libx265_falls_back_to_libx264() {
+            assert_eq!(
+                available(VideoCodec::H265, None, offers(&["libx264"])),
+                (VideoCodec::H264, None)
+            );
+            // Nothing to fall back to: the check reports the missing encoder
+            assert_eq!(
+                available(VideoCodec::H265, None, offers(&[])),
+                (VideoCodec::H265, None)
+            );
+        }
+    }
+
+    // =========================================================================
+    // Failed Encode Tests (--encode-fallback)
+    // =========================================================================
+
+    mod failed_encode {
+        use super::super::*;
+
+        #[test]
+        fn fallback_is_one_fast_software_pass() {
+            let encoding = Encoding {
+                codec: VideoCodec::H265,
+                hwaccel: Some(Hwaccel::Qsv),
+                bitrate: Some(500_000),
+                retry: true,
+                ..Encoding::default()
+            };
+            let fallback = encoding.fallback().unwrap();
+            assert_eq!(fallback.codec, VideoCodec::H264);
+            assert_eq!(fallback.hwaccel, None);
+            assert_eq!(fallback.bitrate, Some(500_000));
+            assert!(fallback.simple && !fallback.retry);
+            assert!(fallback.fallback().is_none());
+        }
+
+        #[test]
+        fn failure_removes_the_stale_video() {
+            let dir = tempfile::tempdir().unwrap();
+            let video_path = dir.path().join("series.mp4");
+            fs::write(&video_path, b"").unwrap();
+            let encoding = Encoding {
+                fps: 10,
+                retry: true,
+                ..Encoding::default()
+            };
+
+            // No frames to encode: both attempts fail
+            let input = FrameInput::images(dir.path().join("%06d.png"), 0);
+            let err = encode_sequence(&input, encoding, &video_path).unwrap_err();
+            let failure = err.downcast_ref::<EncodeFailure>().unwrap();
+            assert_eq!(failure.encoder, "libx264");
+            assert!(!failure.recovered);
+            assert!(!video_path.exists());
+        }
+    }
+
+    // =========================================================================
+    // Background Tests (--background)
+    // =========================================================================
+
+    mod background {
+        use super::super::*;
+
+        #[test]
+        fn parses_names_and_hex() {
+            assert_eq!("black".parse(), Ok(Background(Rgb([0, 0, 0]))));
+            assert_eq!("White".parse(), Ok(Background(Rgb([255, 255, 255]))));
+            assert_eq!("#1a2B3c".parse(), Ok(Background(Rgb([26, 43, 60]))));
+            for bad in ["red", "#fff", "1a2b3c", "#gg0000"] {
+                assert!(bad.parse::<Background>().is_err(), "{bad}");
+            }
+        }
+
+        #[test]
+        fn letterbox_keeps_the_aspect_ratio() {
+            let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(10, 10, Luma([200])));
+            let framed = letterbox(&image, (20, 10), Background(Rgb([0, 0, 255]))).to_rgb8();
+            assert_eq!(framed.dimensions(), (20, 10));
+            assert_eq!(*framed.get_pixel(0, 5), Rgb([0, 0, 255]));
+            assert_eq!(*framed.get_pixel(10, 5), Rgb([200, 200, 200]));
+            assert_eq!(*framed.get_pixel(19, 5), Rgb([0, 0, 255]));
+        }
+
+        #[test]
+        fn gray_frames_stay_gray_on_a_gray_background() {
+            let image = DynamicImage::new_luma8(4, 8);
+            let framed = letterbox(&image, (8, 8), Background(Rgb([255, 255, 255])));
+            let DynamicImage::ImageLuma8(gray) = framed else {
+                panic!("expected a gray frame");
+            };
+            assert_eq!(*gray.get_pixel(0, 0), Luma([255]));
+            assert_eq!(*gray.get_pixel(4, 4), Luma([0]));
+        }
+
+        #[test]
+        fn ffmpeg_pads_with_the_same_color() {
+            assert_eq!(
+                letterbox_filter((640, 480), Background(Rgb([255, 0, 16]))),
+                "scale=640:480:force_original_aspect_ratio=decrease:flags=lanczos,\
+                 pad=640:480:(ow-iw)/2:(oh-ih)/2:color=0xff0010"
+            );
+        }
+    }
+
     // =========================================================================
     // Video Duration Calculation Tests
     // =========================================================================
@@ -0,0 +1,126 @@
+//! Square pixels for videos of images with asymmetric pixel spacing.
+//!
+//! Breast tomosynthesis and some projection images space their rows further
+//! apart than their columns (or the other way round), so played pixel for
+//! pixel the anatomy looks squashed. Frames of such images are stretched
+//! along the more widely spaced axis before anything is drawn on them, so
+//! every pixel covers a square. Spacing is read from `PixelSpacing`, the
+//! Pixel Measures functional group, or `ImagerPixelSpacing`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+use image::DynamicImage;
+use image::imageops::FilterType;
+
+use crate::i18n::t;
+use crate::pipeline::FrameSink;
+use crate::pixel::groups;
+
+/// Spacing ratios this close to 1 are left alone.
+const TOLERANCE: f64 = 0.01;
+
+/// Stretches frames of sources with non-square pixels before passing them on.
+pub(super) struct SquarePixels<'a> {
+    inner: &'a mut dyn FrameSink,
+    /// Row over column spacing of each source seen.
+    ratios: HashMap<PathBuf, f64>,
+    /// Set once the stretch has been reported.
+    reported: bool,
+}
+
+impl<'a> SquarePixels<'a> {
+    pub(super) fn new(inner: &'a mut dyn FrameSink) -> Self {
+        Self {
+            inner,
+            ratios: HashMap::new(),
+            reported: false,
+        }
+    }
+}
+
+impl FrameSink for SquarePixels<'_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        let ratio = *self
+            .ratios
+            .entry(source.to_path_buf())
+            .or_insert_with(|| spacing_ratio(source));
+        let Some((width, height)) = stretched(image.width(), image.height(), ratio) else {
+            return self.inner.write_frame(index, source, image);
+        };
+        if !self.reported {
+            println!(
+                "{}",
+                t!("video-square-pixels", ratio = format!("{ratio:.3}"))
+            );
+            self.reported = true;
+        }
+        let image = image.resize_exact(width, height, FilterType::Lanczos3);
+        self.inner.write_frame(index, source, image)
+    }
+}
+
+/// Row spacing over column spacing of `path`, read from its header; 1 when
+/// not recorded.
+fn spacing_ratio(path: &Path) -> f64 {
+    OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .map_or(1.0, |obj| ratio_of(&obj))
+}
+
+/// Row spacing over column spacing of `obj`; 1 when not recorded.
+fn ratio_of(obj: &InMemDicomObject) -> f64 {
+    groups::pixel_spacing(obj, 0).map_or(1.0, |[row, column]| row / column)
+}
+
+/// Size that `width` × `height` frames of `obj` are stretched to.
+pub(super) fn square_size(obj: &InMemDicomObject, width: u32, height: u32) -> (u32, u32) {
+    stretched(width, height, ratio_of(obj)).unwrap_or((width, height))
+}
+
+/// Size of a `width` × `height` frame with square pixels, given its spacing
+/// `ratio`; `None` when its pixels are square already. Frames only grow, so
+/// no detail is lost.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn stretched(width: u32, height: u32, ratio: f64) -> Option<(u32, u32)> {
+    if !ratio.is_finite() || ratio <= 0.0 || (ratio - 1.0).abs() <= TOLERANCE {
+        return None;
+    }
+    let scale = |side: u32, factor: f64| {
+        (f64::from(side) * factor)
+            .round()
+            .clamp(1.0, f64::from(u32::MAX)) as u32
+    };
+    if ratio > 1.0 {
+        Some((width, scale(height, ratio)))
+    } else {
+        Some((scale(width, ratio.recip()), height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_wider_spaced_axis_is_stretched() {
+        // Rows 0.1 mm apart, columns 0.085 mm apart: taller pixels
+        assert_eq!(stretched(850, 1000, 0.1 / 0.085), Some((850, 1176)));
+        assert_eq!(stretched(1000, 850, 0.085 / 0.1), Some((1176, 850)));
+    }
+
+    #[test]
+    fn square_pixels_are_left_alone() {
+        assert_eq!(stretched(512, 512, 1.0), None);
+        assert_eq!(stretched(512, 512, 1.005), None);
+        assert_eq!(stretched(512, 512, f64::NAN), None);
+    }
+}
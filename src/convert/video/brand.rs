@@ -0,0 +1,238 @@
+//! Title cards and watermarks for branded videos (`--title`, `--watermark`).
+//!
+//! A title card is a black frame with centered text, held for
+//! [`TITLE_SECONDS`]; it opens a video (`--title`) and every series of a
+//! combined one. The watermark image is shrunk to fit a fifth of the frame
+//! and blended into its bottom-right corner through its own alpha channel,
+//! at [`OPACITY`].
+
+use std::path::Path;
+
+use anyhow::Result;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgb, RgbImage, RgbaImage};
+
+use crate::annotate::Canvas;
+use crate::outcome::BadInput;
+use crate::pipeline::FrameSink;
+
+/// How long a title card stays on screen.
+const TITLE_SECONDS: u32 = 2;
+
+/// Title card size when no frame size is known yet.
+const CARD_SIZE: (u32, u32) = (512, 512);
+
+/// Color of the title tag.
+const TITLE: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Opacity of the watermark on top of its own alpha.
+const OPACITY: f32 = 0.7;
+
+/// Largest share of the frame's width and height the watermark covers.
+const WATERMARK_FRACTION: u32 = 5;
+
+/// Frames a title card is held for at `fps`.
+pub(super) fn hold_frames(fps: u32) -> usize {
+    usize::try_from(fps.saturating_mul(TITLE_SECONDS)).unwrap_or(usize::MAX)
+}
+
+/// Write the title card for `title` as [`hold_frames`] frames from `index`,
+/// sized like `size` or, when unknown, like the frames of `first`.
+pub(super) fn write_card(
+    sink: &mut dyn FrameSink,
+    index: usize,
+    title: &str,
+    size: Option<(u32, u32)>,
+    first: Option<&Path>,
+    fps: u32,
+) -> Result<usize> {
+    let size = size
+        .or_else(|| first.and_then(frame_size))
+        .unwrap_or(CARD_SIZE);
+    let card = title_card(title, size);
+    let hold = hold_frames(fps);
+    for offset in 0..hold {
+        sink.write_frame(index + offset, Path::new(title), card.clone())?;
+    }
+    Ok(hold)
+}
+
+/// Columns and rows from a file's header.
+fn frame_size(path: &Path) -> Option<(u32, u32)> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()?;
+    let read = |tag| obj.element(tag).ok()?.to_int::<u32>().ok();
+    Some((read(tags::COLUMNS)?, read(tags::ROWS)?)).filter(|&(w, h)| w > 0 && h > 0)
+}
+
+/// Black frame of `size` with `title` centered on it.
+pub(super) fn title_card(title: &str, (width, height): (u32, u32)) -> DynamicImage {
+    let mut canvas = Canvas::new(RgbImage::new(width, height));
+    let (canvas_width, canvas_height) = canvas.size();
+    let (tag_width, tag_height) = canvas.tag_size(title);
+    canvas.label(
+        title,
+        (
+            (canvas_width - tag_width) / 2,
+            canvas_height.midpoint(tag_height),
+        ),
+        TITLE,
+    );
+    DynamicImage::ImageRgb8(canvas.into_image())
+}
+
+/// A logo blended into the corner of every frame.
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    logo: RgbaImage,
+}
+
+impl Watermark {
+    /// Read the image at `path` (PNG keeps its transparency).
+    pub fn load(path: &Path) -> Result<Self> {
+        let logo = image::open(path).map_err(|e| {
+            BadInput(format!(
+                "Failed to read watermark image {}: {e}",
+                path.display()
+            ))
+        })?;
+        Ok(Self {
+            logo: logo.into_rgba8(),
+        })
+    }
+
+    /// The logo shrunk to fit a frame of `width` x `height`; never enlarged.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn fitted(&self, (width, height): (u32, u32)) -> RgbaImage {
+        let (logo_width, logo_height) = self.logo.dimensions();
+        let scale = f64::min(
+            f64::from(width / WATERMARK_FRACTION) / f64::from(logo_width),
+            f64::from(height / WATERMARK_FRACTION) / f64::from(logo_height),
+        );
+        if scale >= 1.0 {
+            return self.logo.clone();
+        }
+        let size = |side: u32| ((f64::from(side) * scale).round() as u32).max(1);
+        imageops::resize(
+            &self.logo,
+            size(logo_width),
+            size(logo_height),
+            FilterType::Lanczos3,
+        )
+    }
+}
+
+/// Blend `logo` into the bottom-right corner of `frame`.
+fn stamp(frame: &mut RgbImage, logo: &RgbaImage) {
+    let margin = (frame.width().max(frame.height()) / 64).max(2);
+    let left = frame.width().saturating_sub(logo.width() + margin);
+    let top = frame.height().saturating_sub(logo.height() + margin);
+    for (x, y, pixel) in logo.enumerate_pixels() {
+        let (fx, fy) = (left + x, top + y);
+        if fx >= frame.width() || fy >= frame.height() {
+            continue;
+        }
+        let alpha = f32::from(pixel[3]) / 255.0 * OPACITY;
+        let under = frame.get_pixel_mut(fx, fy);
+        for channel in 0..3 {
+            let blended =
+                f32::from(pixel[channel]).mul_add(alpha, f32::from(under[channel]) * (1.0 - alpha));
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            {
+                under[channel] = blended.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// Stamps the watermark on frames before passing them on.
+pub(super) struct WatermarkSink<'a> {
+    inner: &'a mut dyn FrameSink,
+    watermark: &'a Watermark,
+    /// Logo fitted to the last frame size seen.
+    fitted: Option<((u32, u32), RgbaImage)>,
+}
+
+impl<'a> WatermarkSink<'a> {
+    pub(super) const fn new(inner: &'a mut dyn FrameSink, watermark: &'a Watermark) -> Self {
+        Self {
+            inner,
+            watermark,
+            fitted: None,
+        }
+    }
+}
+
+impl FrameSink for WatermarkSink<'_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        let mut frame = image.into_rgb8();
+        let size = frame.dimensions();
+        if self
+            .fitted
+            .as_ref()
+            .is_none_or(|(fitted, _)| *fitted != size)
+        {
+            self.fitted = Some((size, self.watermark.fitted(size)));
+        }
+        if let Some((_, logo)) = &self.fitted {
+            stamp(&mut frame, logo);
+        }
+        self.inner
+            .write_frame(index, source, DynamicImage::ImageRgb8(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn title_is_centered_on_a_black_card() {
+        let card = title_card("AX", (200, 100)).to_rgb8();
+        assert_eq!((card.width(), card.height()), (200, 100));
+        assert_eq!(*card.get_pixel(0, 0), Rgb([0, 0, 0]));
+        // Top edge of the 13x9 tag, filled white above its glyphs
+        assert_eq!(*card.get_pixel(94, 45), TITLE);
+        assert_eq!(*card.get_pixel(100, 10), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn watermark_shrinks_to_a_fifth_of_the_frame() {
+        let watermark = Watermark {
+            logo: RgbaImage::new(100, 50),
+        };
+        assert_eq!(watermark.fitted((200, 200)).dimensions(), (40, 20));
+        // Small logos keep their size
+        assert_eq!(watermark.fitted((1000, 1000)).dimensions(), (100, 50));
+    }
+
+    #[test]
+    fn stamp_blends_into_the_bottom_right_corner() {
+        let mut frame = RgbImage::new(100, 100);
+        let mut logo = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        logo.put_pixel(0, 0, Rgba([255, 255, 255, 0]));
+        stamp(&mut frame, &logo);
+
+        // 2 px margin: the logo covers 94..98 on both axes
+        // Opaque white at 70% over black
+        assert_eq!(*frame.get_pixel(97, 97), Rgb([179; 3]));
+        assert_eq!(*frame.get_pixel(98, 98), Rgb([0, 0, 0]));
+        // Transparent logo pixels leave the frame alone
+        assert_eq!(*frame.get_pixel(94, 94), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn unreadable_watermark_is_bad_input() {
+        let err = Watermark::load(Path::new("/nonexistent/logo.png")).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+    }
+}
@@ -0,0 +1,305 @@
+//! Cardiac cine loops (`video --cardiac-loops`, `--cardiac-slice`).
+//!
+//! Retrospectively gated cardiac MR often stores each slice position as a
+//! series of its own, one image per phase of the heart cycle. These modes
+//! gather the images of every series by slice position (along the slice
+//! normal, so oblique short-axis stacks sort too), order each slice's
+//! phases by `TriggerTime`, and play them at the rate they were acquired
+//! over the cycle, so a loop beats in real time unless `--fps` is given.
+//! `CardiacNumberOfImages` tells when a slice has phases missing or extra.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::pixel::read_first_f64;
+
+/// Slice positions closer than this (mm) are one slice.
+const POSITION_TOLERANCE: f64 = 0.01;
+
+/// Which loops to build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cardiac {
+    /// One loop per slice position.
+    Loops,
+    /// One loop of this slice only, counted from 1.
+    Slice(usize),
+}
+
+/// The phases of one slice position, in playing order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CineSlice {
+    /// Place of the slice along the slice normal, counted from 1.
+    pub number: usize,
+    /// Position along the slice normal, in mm.
+    pub position: f64,
+    pub files: Vec<PathBuf>,
+    /// Frame rate at which the phases span the heart cycle they came from.
+    pub fps: Option<u32>,
+}
+
+impl CineSlice {
+    /// Name of the slice's output folder and video.
+    pub fn name(&self) -> String {
+        format!("slice_{:02}", self.number)
+    }
+}
+
+/// What a phase image says about where and when it was taken.
+#[derive(Debug, Clone, PartialEq)]
+struct Phase {
+    path: PathBuf,
+    position: Option<f64>,
+    /// `TriggerTime`, in ms after the R wave.
+    trigger: Option<f64>,
+    /// `CardiacNumberOfImages`.
+    phases: Option<usize>,
+    instance: i64,
+}
+
+impl Phase {
+    fn read(path: &Path) -> Self {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+            .ok();
+        let obj = obj.as_deref();
+        Self {
+            path: path.to_path_buf(),
+            position: obj.and_then(slice_position),
+            trigger: obj.and_then(|obj| read_first_f64(obj, tags::TRIGGER_TIME)),
+            phases: obj
+                .and_then(|obj| obj.element(tags::CARDIAC_NUMBER_OF_IMAGES).ok())
+                .and_then(|elem| elem.to_int::<usize>().ok())
+                .filter(|&n| n > 0),
+            instance: obj
+                .and_then(|obj| obj.element(tags::INSTANCE_NUMBER).ok())
+                .and_then(|elem| elem.to_int::<i64>().ok())
+                .unwrap_or(i64::MAX),
+        }
+    }
+}
+
+/// Position of an image along its slice normal (`ImagePositionPatient`
+/// projected on the normal of `ImageOrientationPatient`), else its
+/// `SliceLocation`.
+fn slice_position(obj: &InMemDicomObject) -> Option<f64> {
+    let numbers = |tag: Tag| -> Option<Vec<f64>> {
+        let text = obj.element(tag).ok()?.to_str().ok()?;
+        text.split('\\').map(|v| v.trim().parse().ok()).collect()
+    };
+    let projected = numbers(tags::IMAGE_POSITION_PATIENT)
+        .zip(numbers(tags::IMAGE_ORIENTATION_PATIENT))
+        .filter(|(origin, orientation)| origin.len() >= 3 && orientation.len() >= 6)
+        .map(|(origin, orientation)| {
+            let (row, column) = (&orientation[..3], &orientation[3..6]);
+            (0..3)
+                .map(|i| {
+                    let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+                    row[j].mul_add(column[k], -(row[k] * column[j])) * origin[i]
+                })
+                .sum()
+        });
+    projected.or_else(|| read_first_f64(obj, tags::SLICE_LOCATION))
+}
+
+/// Gather `files` of all series into cine slices, ordered along the slice
+/// normal. Fails when no file has a `TriggerTime`, as then they are no
+/// gated cine.
+pub fn cine_slices(files: &[PathBuf]) -> Result<Vec<CineSlice>> {
+    let phases: Vec<Phase> = files.iter().map(|path| Phase::read(path)).collect();
+    if phases.iter().all(|phase| phase.trigger.is_none()) {
+        anyhow::bail!(BadInput(
+            "No image has a TriggerTime; --cardiac-loops and --cardiac-slice need gated cine \
+             images"
+                .to_string()
+        ));
+    }
+    let unplaced = phases
+        .iter()
+        .filter(|phase| phase.position.is_none())
+        .count();
+    if unplaced > 0 {
+        eprintln!("{}", t!("video-cardiac-unplaced", count = unplaced));
+    }
+    let slices = group_slices(phases);
+    for slice in &slices {
+        report_phase_count(slice);
+    }
+    Ok(slices.into_iter().map(|(slice, _)| slice).collect())
+}
+
+/// Group phases with a position into slices, each with the phase count its
+/// headers call for.
+#[allow(clippy::cast_possible_truncation)]
+fn group_slices(phases: Vec<Phase>) -> Vec<(CineSlice, Option<usize>)> {
+    let mut by_position: BTreeMap<i64, Vec<Phase>> = BTreeMap::new();
+    for phase in phases {
+        if let Some(position) = phase.position {
+            let key = (position / POSITION_TOLERANCE).round() as i64;
+            by_position.entry(key).or_default().push(phase);
+        }
+    }
+    by_position
+        .into_values()
+        .enumerate()
+        .map(|(index, mut phases)| {
+            phases.sort_by(|a, b| {
+                let trigger = |phase: &Phase| phase.trigger.unwrap_or(f64::MAX);
+                trigger(a)
+                    .total_cmp(&trigger(b))
+                    .then(a.instance.cmp(&b.instance))
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+            let triggers: Vec<f64> = phases.iter().filter_map(|phase| phase.trigger).collect();
+            let expected = phases.iter().find_map(|phase| phase.phases);
+            let slice = CineSlice {
+                number: index + 1,
+                position: phases[0].position.unwrap_or_default(),
+                fps: cycle_fps(&triggers),
+                files: phases.into_iter().map(|phase| phase.path).collect(),
+            };
+            (slice, expected)
+        })
+        .collect()
+}
+
+/// Warn when a slice has another number of phases than
+/// `CardiacNumberOfImages`.
+fn report_phase_count((slice, expected): &(CineSlice, Option<usize>)) {
+    if let Some(expected) = *expected
+        && expected != slice.files.len()
+    {
+        eprintln!(
+            "{}",
+            t!(
+                "video-cardiac-phase-count",
+                slice = slice.name(),
+                count = slice.files.len(),
+                expected = expected
+            )
+        );
+    }
+}
+
+/// Whole frames per second at which phases `triggers` ms after the R wave
+/// play in real time: one frame per mean trigger interval.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn cycle_fps(triggers: &[f64]) -> Option<u32> {
+    let (first, last) = (triggers.first()?, triggers.last()?);
+    let interval = (last - first) / (triggers.len().checked_sub(1)? as f64);
+    (interval.is_finite() && interval > 0.0)
+        .then(|| (1000.0 / interval).round().clamp(1.0, f64::from(u32::MAX)) as u32)
+}
+
+/// The slices `mode` asks for.
+pub fn chosen(slices: Vec<CineSlice>, mode: Cardiac) -> Result<Vec<CineSlice>> {
+    match mode {
+        Cardiac::Loops => Ok(slices),
+        Cardiac::Slice(number) => {
+            let count = slices.len();
+            let slice = slices
+                .into_iter()
+                .find(|slice| slice.number == number)
+                .ok_or_else(|| {
+                    BadInput(format!(
+                        "--cardiac-slice {number} is out of range: the input has {count} slice \
+                         position(s)"
+                    ))
+                })?;
+            Ok(vec![slice])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase(path: &str, position: f64, trigger: f64, instance: i64) -> Phase {
+        Phase {
+            path: PathBuf::from(path),
+            position: Some(position),
+            trigger: Some(trigger),
+            phases: Some(3),
+            instance,
+        }
+    }
+
+    #[test]
+    fn phases_of_all_series_are_gathered_by_position() {
+        let slices = group_slices(vec![
+            phase("b2.dcm", 10.0, 80.0, 2),
+            phase("a1.dcm", -5.0, 0.0, 1),
+            phase("b1.dcm", 10.004, 0.0, 1),
+            phase("a2.dcm", -5.0, 40.0, 2),
+            phase("b3.dcm", 10.0, 160.0, 3),
+            Phase {
+                position: None,
+                ..phase("lost.dcm", 0.0, 0.0, 1)
+            },
+        ]);
+        let names: Vec<Vec<&str>> = slices
+            .iter()
+            .map(|(slice, _)| slice.files.iter().map(|p| p.to_str().unwrap()).collect())
+            .collect();
+        assert_eq!(
+            names,
+            [vec!["a1.dcm", "a2.dcm"], vec!["b1.dcm", "b2.dcm", "b3.dcm"]]
+        );
+        assert_eq!(slices[1].0.number, 2);
+        assert_eq!(slices[1].1, Some(3));
+        assert_eq!(slices[0].0.fps, Some(25));
+        assert_eq!(slices[1].0.fps, Some(13));
+    }
+
+    #[test]
+    fn cycle_rate_follows_trigger_spacing() {
+        assert_eq!(cycle_fps(&[0.0, 33.3, 66.6]), Some(30));
+        assert_eq!(cycle_fps(&[0.0]), None);
+        assert_eq!(cycle_fps(&[40.0, 40.0]), None);
+    }
+
+    #[test]
+    fn one_slice_can_be_chosen() {
+        let slice = |number| CineSlice {
+            number,
+            position: 0.0,
+            files: vec![],
+            fps: None,
+        };
+        let chosen = chosen(vec![slice(1), slice(2)], Cardiac::Slice(2)).unwrap();
+        assert_eq!(chosen, [slice(2)]);
+        assert_eq!(chosen[0].name(), "slice_02");
+        assert!(super::chosen(vec![slice(1)], Cardiac::Slice(3)).is_err());
+    }
+
+    #[test]
+    fn position_is_taken_along_the_slice_normal() {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let mut obj = InMemDicomObject::new_empty();
+        // Sagittal: rows along Y, columns along Z, normal along X
+        obj.put(DataElement::new(
+            tags::IMAGE_ORIENTATION_PATIENT,
+            VR::DS,
+            PrimitiveValue::from("0\\1\\0\\0\\0\\-1"),
+        ));
+        obj.put(DataElement::new(
+            tags::IMAGE_POSITION_PATIENT,
+            VR::DS,
+            PrimitiveValue::from("12.5\\-80\\40"),
+        ));
+        assert_eq!(slice_position(&obj), Some(-12.5));
+    }
+}
@@ -0,0 +1,114 @@
+//! GPU encoders for videos (`video --hwaccel`).
+//!
+//! Each family maps to ffmpeg's hardware H.264/H.265 encoders, with the
+//! settings closest to the software encoder's CRF 18. Hardware encoders have
+//! no two-pass mode, so `--target-size` runs one pass capped at the target's
+//! bit rate instead.
+
+use clap::ValueEnum;
+
+use super::VideoCodec;
+
+/// VAAPI render node, the first GPU on Linux.
+const VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+/// GPU encoder family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Hwaccel {
+    /// NVIDIA NVENC
+    Nvenc,
+    /// Intel Quick Sync Video
+    Qsv,
+    /// Apple Video Toolbox (macOS)
+    Videotoolbox,
+    /// VA-API (Linux, Intel and AMD)
+    Vaapi,
+}
+
+impl Hwaccel {
+    /// ffmpeg encoder for `codec`.
+    pub(super) const fn encoder(self, codec: VideoCodec) -> &'static str {
+        match (self, codec) {
+            (Self::Nvenc, VideoCodec::H264) => "h264_nvenc",
+            (Self::Nvenc, VideoCodec::H265) => "hevc_nvenc",
+            (Self::Qsv, VideoCodec::H264) => "h264_qsv",
+            (Self::Qsv, VideoCodec::H265) => "hevc_qsv",
+            (Self::Videotoolbox, VideoCodec::H264) => "h264_videotoolbox",
+            (Self::Videotoolbox, VideoCodec::H265) => "hevc_videotoolbox",
+            (Self::Vaapi, VideoCodec::H264) => "h264_vaapi",
+            (Self::Vaapi, VideoCodec::H265) => "hevc_vaapi",
+        }
+    }
+
+    /// Arguments opening the device, before the inputs.
+    pub(super) const fn device_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Vaapi => &["-vaapi_device", VAAPI_DEVICE],
+            Self::Nvenc | Self::Qsv | Self::Videotoolbox => &[],
+        }
+    }
+
+    /// Filter moving frames to the GPU, last in the filter chain.
+    pub(super) const fn upload_filter(self) -> Option<&'static str> {
+        match self {
+            Self::Vaapi => Some("format=nv12,hwupload"),
+            Self::Nvenc | Self::Qsv | Self::Videotoolbox => None,
+        }
+    }
+
+    /// Constant quality close to the software encoder's CRF 18.
+    pub(super) const fn quality_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Nvenc => &["-rc", "vbr", "-cq", "19", "-b:v", "0"],
+            Self::Qsv => &["-global_quality", "19"],
+            // Constant quality needs Apple silicon
+            Self::Videotoolbox => &["-q:v", "65"],
+            Self::Vaapi => &["-rc_mode", "CQP", "-qp", "19"],
+        }
+    }
+
+    /// Speed preset and pixel format the encoder accepts.
+    pub(super) const fn output_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Nvenc => &["-preset", "p6", "-pix_fmt", "yuv420p"],
+            Self::Qsv => &["-preset", "slow", "-pix_fmt", "nv12"],
+            Self::Videotoolbox => &["-pix_fmt", "yuv420p"],
+            // Uploaded frames are already NV12 on the GPU
+            Self::Vaapi => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_family_has_both_codecs() {
+        for hwaccel in Hwaccel::value_variants() {
+            let h264 = hwaccel.encoder(VideoCodec::H264);
+            let h265 = hwaccel.encoder(VideoCodec::H265);
+            assert!(h264.starts_with("h264_"), "{h264}");
+            assert!(h265.starts_with("hevc_"), "{h265}");
+            assert_eq!(h264[5..], h265[5..]);
+        }
+    }
+
+    #[test]
+    fn gpu_h265_keeps_the_apple_tag() {
+        assert_eq!(
+            VideoCodec::H265.encoder_args(Some(Hwaccel::Nvenc)),
+            ["-c:v", "hevc_nvenc", "-tag:v", "hvc1"]
+        );
+        assert_eq!(VideoCodec::H264.encoder_args(None), ["-c:v", "libx264"]);
+    }
+
+    #[test]
+    fn only_vaapi_uploads_frames() {
+        for hwaccel in Hwaccel::value_variants() {
+            let vaapi = *hwaccel == Hwaccel::Vaapi;
+            assert_eq!(hwaccel.upload_filter().is_some(), vaapi);
+            assert_eq!(!hwaccel.device_args().is_empty(), vaapi);
+        }
+    }
+}
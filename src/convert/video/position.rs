@@ -0,0 +1,193 @@
+//! Slice position indicator for videos (`video --position-bar`).
+//!
+//! A thin bar along the right edge of every frame stands for the scanned
+//! range; a marker shows where the current slice lies in it, labeled with its
+//! Z position in mm (`ImagePositionPatient`), highest Z at the top, which is
+//! the head for axial series. When positions are missing, or a group holds
+//! multi-frame objects, the marker follows the frame number instead.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use image::{DynamicImage, Rgb};
+
+use crate::annotate::Canvas;
+use crate::pipeline::FrameSink;
+
+/// Color of the bar's track.
+const TRACK: Rgb<u8> = Rgb([96, 96, 96]);
+
+/// Color of the marker and its label.
+const MARKER: Rgb<u8> = Rgb([255, 255, 0]);
+
+/// Draws the position bar on frames before passing them on.
+pub(super) struct PositionSink<'a> {
+    inner: &'a mut dyn FrameSink,
+    /// Z position of each file, when every file of the group has one.
+    positions: HashMap<PathBuf, f64>,
+    /// Lowest and highest Z of the group.
+    range: (f64, f64),
+    /// Frames in the group.
+    total: usize,
+}
+
+impl<'a> PositionSink<'a> {
+    /// Wrap `inner` for a group of `files` rendering to `total` frames.
+    pub(super) fn new(inner: &'a mut dyn FrameSink, files: &[PathBuf], total: usize) -> Self {
+        // Frames of one multi-frame object would share a position
+        let mut positions: HashMap<PathBuf, f64> = if total == files.len() {
+            files
+                .iter()
+                .filter_map(|path| Some((path.clone(), slice_z(path)?)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let range = positions
+            .values()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &z| {
+                (lo.min(z), hi.max(z))
+            });
+        if positions.len() < files.len() || range.0 >= range.1 {
+            positions.clear();
+        }
+        Self {
+            inner,
+            positions,
+            range,
+            total,
+        }
+    }
+
+    /// How far down the bar frame `index` of `source` sits (0 at the top),
+    /// and its label.
+    #[allow(clippy::cast_precision_loss)]
+    fn marker(&self, index: usize, source: &Path) -> (f64, String) {
+        let (lo, hi) = self.range;
+        if let Some(&z) = self.positions.get(source) {
+            return ((hi - z) / (hi - lo), format!("{z:.1} mm"));
+        }
+        let last = self.total.saturating_sub(1).max(1);
+        (
+            index.min(last) as f64 / last as f64,
+            format!("{}/{}", index + 1, self.total),
+        )
+    }
+}
+
+impl FrameSink for PositionSink<'_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        let (fraction, label) = self.marker(index, source);
+        self.inner
+            .write_frame(index, source, draw(image, fraction, &label))
+    }
+}
+
+/// Z of `ImagePositionPatient`, from the header only.
+fn slice_z(path: &Path) -> Option<f64> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()?;
+    let text = obj
+        .element(tags::IMAGE_POSITION_PATIENT)
+        .ok()?
+        .to_str()
+        .ok()?;
+    text.split('\\').nth(2)?.trim().parse().ok()
+}
+
+/// Draw the bar with its marker `fraction` of the way down, labeled.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn draw(image: DynamicImage, fraction: f64, label: &str) -> DynamicImage {
+    let mut canvas = Canvas::new(image.into_rgb8());
+    let (width, height) = canvas.size();
+    let t = canvas.thickness();
+    let (margin, bar_width) = (4 * t, 2 * t);
+    let left = width - margin - bar_width;
+    let (top, length) = (margin, (height - 2 * margin).max(1));
+
+    canvas.fill((left, top), (bar_width, length), TRACK);
+    let y = top + (fraction.clamp(0.0, 1.0) * (length - 1) as f64).round() as i64;
+    canvas.fill((left - t, y - t), (bar_width + 2 * t, 2 * t + 1), MARKER);
+
+    // Label left of the bar, centered on the marker
+    let (tag_width, tag_height) = canvas.tag_size(label);
+    canvas.label(
+        label,
+        (left - 2 * t - tag_width, y + tag_height / 2),
+        MARKER,
+    );
+    DynamicImage::ImageRgb8(canvas.into_image())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    /// Sink that keeps what it receives.
+    #[derive(Default)]
+    struct Collect(Vec<DynamicImage>);
+
+    impl FrameSink for Collect {
+        fn write_frame(&mut self, _: usize, _: &Path, image: DynamicImage) -> Result<()> {
+            self.0.push(image);
+            Ok(())
+        }
+    }
+
+    /// Row of the marker's center in the bar's column, if drawn.
+    fn marker_row(image: &DynamicImage) -> Option<u32> {
+        let rgb = image.to_rgb8();
+        let column = rgb.width() - 4 - 1;
+        let rows: Vec<u32> = (0..rgb.height())
+            .filter(|&y| *rgb.get_pixel(column, y) == MARKER)
+            .collect();
+        (!rows.is_empty()).then(|| rows[rows.len() / 2])
+    }
+
+    #[test]
+    fn marker_moves_down_with_the_frame_number() {
+        let mut collect = Collect::default();
+        let files = vec![PathBuf::from("a.dcm"), PathBuf::from("b.dcm")];
+        let mut sink = PositionSink::new(&mut collect, &files, 3);
+        for index in 0..3 {
+            let frame = DynamicImage::ImageLuma8(GrayImage::new(64, 100));
+            sink.write_frame(index, &files[0], frame).unwrap();
+        }
+
+        let rows: Vec<Option<u32>> = collect.0.iter().map(marker_row).collect();
+        // Bar from row 4 to row 95
+        assert_eq!(rows, [Some(4), Some(50), Some(95)]);
+    }
+
+    #[test]
+    fn positions_put_the_highest_slice_on_top() {
+        let mut collect = Collect::default();
+        let mut sink = PositionSink::new(&mut collect, &[], 0);
+        sink.positions = HashMap::from([
+            (PathBuf::from("head.dcm"), 40.0),
+            (PathBuf::from("feet.dcm"), -60.0),
+        ]);
+        sink.range = (-60.0, 40.0);
+
+        let (top, label) = sink.marker(1, Path::new("head.dcm"));
+        assert!(top.abs() < 1e-9, "{top}");
+        assert_eq!(label, "40.0 mm");
+        let (bottom, _) = sink.marker(0, Path::new("feet.dcm"));
+        assert!((bottom - 1.0).abs() < 1e-9, "{bottom}");
+    }
+
+    #[test]
+    fn missing_positions_fall_back_to_frame_numbers() {
+        let mut collect = Collect::default();
+        let files = vec![PathBuf::from("/nonexistent/1.dcm")];
+        let sink = PositionSink::new(&mut collect, &files, 1);
+        assert!(sink.positions.is_empty());
+        assert_eq!(sink.marker(0, &files[0]), (0.0, "1/1".to_string()));
+    }
+}
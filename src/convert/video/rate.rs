@@ -0,0 +1,217 @@
+//! File size targets for videos (`video --target-size`).
+//!
+//! The target is spread over the video's length as an average bit rate, less
+//! the narration track and a little room for the MP4 container. ffmpeg then
+//! reaches it in two passes: the first only analyzes the frames, the second
+//! encodes them with what the first one learned. GPU encoders (`--hwaccel`)
+//! get one pass capped at the bit rate instead.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::{Hwaccel, VideoCodec};
+use crate::outcome::BadInput;
+
+/// Bit rate of the AAC narration track (`--audio`).
+pub(super) const AUDIO_BITRATE: u64 = 192_000;
+
+/// Share of the target left to the MP4 container, in percent.
+const CONTAINER_OVERHEAD: u64 = 3;
+
+/// Lowest video bit rate worth encoding; below it frames turn to blocks.
+const MIN_VIDEO_BITRATE: u64 = 100_000;
+
+/// Parse a file size such as `25MB`, `1.5GB`, or `800KiB` into bytes. KB,
+/// MB, and GB are powers of 1000, KiB, MiB, and GiB powers of 1024; a bare
+/// number is bytes.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+pub fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let text = value.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let factor: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "kib" => 1 << 10,
+        "mb" => 1_000_000,
+        "mib" => 1 << 20,
+        "gb" => 1_000_000_000,
+        "gib" => 1 << 30,
+        _ => return Err(format!("unknown unit in `{value}`, expected KB, MB, or GB")),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{value}` is not a size such as 25MB"))?;
+    let bytes = (number * factor as f64).round();
+    if bytes >= 1.0 && bytes < u64::MAX as f64 {
+        Ok(bytes as u64)
+    } else {
+        Err("must be greater than 0".to_string())
+    }
+}
+
+/// Average video bit rate, in bits/s, for `frames` at `fps` to fit in
+/// `target` bytes, next to the narration track when `audio` is set.
+/// Targets too small for a watchable video are [`BadInput`].
+pub fn video_bitrate(target: u64, frames: u32, fps: u32, audio: bool) -> Result<u64> {
+    let frames = u64::from(frames.max(1));
+    let fps = u64::from(fps);
+    let audio = if audio { AUDIO_BITRATE } else { 0 };
+    let budget = target.saturating_mul(8) / 100 * (100 - CONTAINER_OVERHEAD);
+    let bitrate = (budget.saturating_mul(fps) / frames).saturating_sub(audio);
+    if bitrate >= MIN_VIDEO_BITRATE {
+        return Ok(bitrate);
+    }
+
+    let needed =
+        ((MIN_VIDEO_BITRATE + audio) * frames / fps / 8) * 100 / (100 - CONTAINER_OVERHEAD);
+    #[allow(clippy::cast_precision_loss)]
+    let (seconds, megabytes) = (frames as f64 / fps as f64, needed as f64 / 1e6);
+    anyhow::bail!(BadInput(format!(
+        "--target-size is too small for a {seconds:.1}-second video; it needs at least \
+         {megabytes:.1}MB"
+    )))
+}
+
+/// How the encoder spends its bits.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Rate<'a> {
+    /// Constant quality (CRF 18, near-lossless).
+    Quality,
+    /// One pass at about `bitrate` bits/s, never above it for long; for GPU
+    /// encoders, which have no two-pass mode.
+    Average { bitrate: u64 },
+    /// One pass of a two-pass encode at `bitrate` bits/s; both passes share
+    /// the statistics file `log`.
+    Pass {
+        bitrate: u64,
+        pass: u8,
+        log: &'a Path,
+    },
+}
+
+impl Rate<'_> {
+    /// ffmpeg arguments for `codec`, on the GPU if `hwaccel`.
+    pub(super) fn args(self, codec: VideoCodec, hwaccel: Option<Hwaccel>) -> Vec<OsString> {
+        let (bitrate, pass, log) = match self {
+            Self::Quality => {
+                let args = hwaccel.map_or(&["-crf", "18"][..], Hwaccel::quality_args);
+                return args.iter().map(OsString::from).collect();
+            }
+            Self::Average { bitrate } => {
+                let bufsize = bitrate.saturating_mul(2);
+                return [bitrate, bitrate, bufsize]
+                    .iter()
+                    .zip(["-b:v", "-maxrate", "-bufsize"])
+                    .flat_map(|(value, name)| [name.into(), value.to_string().into()])
+                    .collect();
+            }
+            Self::Pass { bitrate, pass, log } => (bitrate, pass, log),
+        };
+        let mut args = vec!["-b:v".into(), bitrate.to_string().into()];
+        match codec {
+            VideoCodec::H264 => args.extend([
+                "-pass".into(),
+                pass.to_string().into(),
+                "-passlogfile".into(),
+                log.into(),
+            ]),
+            // libx265 takes its passes through its own parameters
+            VideoCodec::H265 => args.extend([
+                "-x265-params".into(),
+                format!("pass={pass}:stats={}", escape(log)).into(),
+            ]),
+        }
+        args
+    }
+}
+
+/// `path` with the characters that are special in `-x265-params` escaped.
+fn escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        if matches!(c, ':' | '=' | '\\' | '\'') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_take_decimal_and_binary_units() {
+        assert_eq!(parse_size("25MB"), Ok(25_000_000));
+        assert_eq!(parse_size("1.5 gb"), Ok(1_500_000_000));
+        assert_eq!(parse_size("800KiB"), Ok(800 * 1024));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        for bad in ["", "MB", "25XB", "0MB", "-3MB", "1.2.3MB"] {
+            assert!(parse_size(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn bitrate_spreads_the_target_over_the_video() {
+        // 10 MB over 100 s: 800 kbit/s, less 3% for the container
+        assert_eq!(video_bitrate(10_000_000, 1000, 10, false).unwrap(), 776_000);
+        // Narration takes its share first
+        assert_eq!(video_bitrate(10_000_000, 1000, 10, true).unwrap(), 584_000);
+    }
+
+    #[test]
+    fn tiny_targets_are_bad_input() {
+        let err = video_bitrate(100_000, 6000, 10, true).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        assert!(err.to_string().contains("600.0-second"), "{err}");
+    }
+
+    #[test]
+    fn passes_use_the_codec_specific_options() {
+        let log = Path::new("/tmp/pass");
+        let rate = Rate::Pass {
+            bitrate: 500_000,
+            pass: 2,
+            log,
+        };
+        assert_eq!(
+            rate.args(VideoCodec::H264, None),
+            ["-b:v", "500000", "-pass", "2", "-passlogfile", "/tmp/pass"]
+        );
+        assert_eq!(
+            rate.args(VideoCodec::H265, None),
+            ["-b:v", "500000", "-x265-params", "pass=2:stats=/tmp/pass"]
+        );
+        assert_eq!(Rate::Quality.args(VideoCodec::H264, None), ["-crf", "18"]);
+    }
+
+    #[test]
+    fn gpu_encoders_cap_one_pass_at_the_bit_rate() {
+        let rate = Rate::Average { bitrate: 500_000 };
+        assert_eq!(
+            rate.args(VideoCodec::H264, Some(Hwaccel::Nvenc)),
+            [
+                "-b:v", "500000", "-maxrate", "500000", "-bufsize", "1000000"
+            ]
+        );
+        assert_eq!(
+            Rate::Quality.args(VideoCodec::H264, Some(Hwaccel::Qsv)),
+            ["-global_quality", "19"]
+        );
+    }
+
+    #[test]
+    fn stats_paths_are_escaped_for_x265() {
+        assert_eq!(escape(Path::new(r"C:\Temp\pass")), r"C\:\\Temp\\pass");
+    }
+}
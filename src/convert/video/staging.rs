@@ -0,0 +1,304 @@
+//! Rendered frames staged for ffmpeg.
+//!
+//! Slices go to ffmpeg as raw 8-bit pixels, written back to back into one
+//! file straight from the rendered buffers: no PNG is compressed only for
+//! ffmpeg to decompress it again. The file can be read more than once, which
+//! two-pass and fallback encodes need, and its size is exactly what
+//! [`staging_estimate`](super::staging_estimate) reserves.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::DynamicImage;
+
+use super::{Background, letterbox};
+use crate::i18n::t;
+use crate::pipeline::{FrameSink, display_name};
+
+/// File holding the frames written by [`RawStagingSink`].
+const STAGED_FRAMES: &str = "frames.raw";
+
+/// Pixel layout of raw staged frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawFormat {
+    /// One byte per pixel.
+    Gray,
+    /// Three bytes per pixel, red first.
+    Rgb,
+}
+
+impl RawFormat {
+    /// Layout that keeps `image` as it is.
+    const fn of(image: &DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageLuma8(_) => Self::Gray,
+            _ => Self::Rgb,
+        }
+    }
+
+    /// ffmpeg's name for the layout.
+    const fn ffmpeg(self) -> &'static str {
+        match self {
+            Self::Gray => "gray",
+            Self::Rgb => "rgb24",
+        }
+    }
+}
+
+/// Frames for ffmpeg to encode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameInput {
+    /// Numbered image files matching `pattern`, e.g. `%04d.jpg`.
+    Images {
+        pattern: PathBuf,
+        /// Number of the first file.
+        start_number: u32,
+    },
+    /// Raw frames of one size and layout, back to back in one file.
+    Raw {
+        path: PathBuf,
+        size: (u32, u32),
+        format: RawFormat,
+    },
+}
+
+impl FrameInput {
+    /// Images matching `pattern`, numbered from `start_number`.
+    pub const fn images(pattern: PathBuf, start_number: u32) -> Self {
+        Self::Images {
+            pattern,
+            start_number,
+        }
+    }
+
+    /// ffmpeg options and `-i` reading these frames at `fps`.
+    pub(super) fn args(&self, fps: u32) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-framerate".into(), fps.to_string().into()];
+        match self {
+            Self::Images {
+                pattern,
+                start_number,
+            } => {
+                args.extend(["-start_number".into(), start_number.to_string().into()]);
+                args.extend(["-i".into(), pattern.into()]);
+            }
+            Self::Raw { path, size, format } => {
+                let (width, height) = size;
+                args.extend([
+                    "-f".into(),
+                    "rawvideo".into(),
+                    "-pixel_format".into(),
+                    format.ffmpeg().into(),
+                    "-video_size".into(),
+                    format!("{width}x{height}").into(),
+                ]);
+                args.extend(["-i".into(), path.into()]);
+            }
+        }
+        args
+    }
+}
+
+/// Stages rendered frames as raw pixels for ffmpeg.
+///
+/// All frames are letterboxed to the dimensions of the first frame so that
+/// the encoder receives a consistent frame size. They are gray when the
+/// first frame is, unless [`RawStagingSink::in_color`] asks for RGB up
+/// front; later frames are converted to that layout.
+pub struct RawStagingSink<'a> {
+    frame_dir: &'a Path,
+    total: usize,
+    pub(super) target_size: Option<(u32, u32)>,
+    format: Option<RawFormat>,
+    pub(super) frame_count: usize,
+    background: Background,
+    writer: Option<BufWriter<File>>,
+}
+
+impl<'a> RawStagingSink<'a> {
+    /// Stage frames into `frame_dir`; `total` is only used for progress.
+    pub const fn new(frame_dir: &'a Path, total: usize) -> Self {
+        Self {
+            frame_dir,
+            total,
+            target_size: None,
+            format: None,
+            frame_count: 0,
+            background: Background::BLACK,
+            writer: None,
+        }
+    }
+
+    /// Pad frames of another size with `background`.
+    #[must_use]
+    pub const fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Stage RGB frames even if the first one is gray, for runs where only
+    /// some frames get color (annotations, title cards).
+    #[must_use]
+    pub const fn in_color(mut self, color: bool) -> Self {
+        if color {
+            self.format = Some(RawFormat::Rgb);
+        }
+        self
+    }
+
+    /// Flush the staged frames and describe them for ffmpeg; `None` when
+    /// nothing was written.
+    pub fn finish(&mut self) -> Result<Option<FrameInput>> {
+        let (Some(writer), Some(size), Some(format)) =
+            (&mut self.writer, self.target_size, self.format)
+        else {
+            return Ok(None);
+        };
+        let path = self.frame_dir.join(STAGED_FRAMES);
+        writer
+            .flush()
+            .with_context(|| format!("Failed to save frames: {}", path.display()))?;
+        Ok(Some(FrameInput::Raw { path, size, format }))
+    }
+
+    /// Append the pixels of `image` in `format`, borrowing them when the
+    /// image already has that layout.
+    fn append(&mut self, image: &DynamicImage, format: RawFormat) -> Result<()> {
+        let path = self.frame_dir.join(STAGED_FRAMES);
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let file = File::create(&path)
+                    .with_context(|| format!("Failed to create: {}", path.display()))?;
+                self.writer.insert(BufWriter::new(file))
+            }
+        };
+        let written = match (format, image) {
+            (RawFormat::Gray, DynamicImage::ImageLuma8(gray)) => writer.write_all(gray.as_raw()),
+            (RawFormat::Rgb, DynamicImage::ImageRgb8(rgb)) => writer.write_all(rgb.as_raw()),
+            (RawFormat::Gray, _) => writer.write_all(image.to_luma8().as_raw()),
+            (RawFormat::Rgb, _) => writer.write_all(image.to_rgb8().as_raw()),
+        };
+        written.with_context(|| format!("Failed to save frame: {}", path.display()))
+    }
+}
+
+impl FrameSink for RawStagingSink<'_> {
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()> {
+        let target = *self
+            .target_size
+            .get_or_insert_with(|| (image.width(), image.height()));
+
+        // Letterbox if dimensions don't match first frame
+        let image = if (image.width(), image.height()) == target {
+            image
+        } else {
+            letterbox(&image, target, self.background)
+        };
+
+        let format = *self.format.get_or_insert(RawFormat::of(&image));
+        self.append(&image, format)?;
+
+        self.frame_count += 1;
+        println!(
+            "{}",
+            t!(
+                "video-prepared-frame",
+                index = index + 1,
+                total = self.total,
+                file = display_name(source)
+            )
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn frames_are_written_back_to_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = RawStagingSink::new(dir.path(), 2);
+        let source = Path::new("a.dcm");
+        let gray = |value| DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([value])));
+        sink.write_frame(0, source, gray(10)).unwrap();
+        sink.write_frame(1, source, gray(20)).unwrap();
+
+        let input = sink.finish().unwrap().unwrap();
+        let path = dir.path().join(STAGED_FRAMES);
+        assert_eq!(
+            input,
+            FrameInput::Raw {
+                path: path.clone(),
+                size: (2, 2),
+                format: RawFormat::Gray
+            }
+        );
+        assert_eq!(
+            std::fs::read(path).unwrap(),
+            [10, 10, 10, 10, 20, 20, 20, 20]
+        );
+    }
+
+    #[test]
+    fn color_layout_converts_later_gray_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = RawStagingSink::new(dir.path(), 2).in_color(true);
+        let source = Path::new("a.dcm");
+        sink.write_frame(0, source, DynamicImage::new_luma8(1, 1))
+            .unwrap();
+        let red = RgbImage::from_pixel(2, 1, Rgb([255, 0, 0]));
+        sink.write_frame(1, source, DynamicImage::ImageRgb8(red))
+            .unwrap();
+
+        let Some(FrameInput::Raw { size, format, .. }) = sink.finish().unwrap() else {
+            panic!("expected raw frames");
+        };
+        assert_eq!((size, format), ((1, 1), RawFormat::Rgb));
+        // The second frame is letterboxed down to the first one's size
+        let bytes = std::fs::read(dir.path().join(STAGED_FRAMES)).unwrap();
+        assert_eq!(bytes.len(), 2 * 3);
+    }
+
+    #[test]
+    fn nothing_written_gives_no_input() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(RawStagingSink::new(dir.path(), 0).finish().unwrap(), None);
+    }
+
+    #[test]
+    fn raw_input_tells_ffmpeg_the_layout() {
+        let input = FrameInput::Raw {
+            path: PathBuf::from("frames.raw"),
+            size: (512, 256),
+            format: RawFormat::Rgb,
+        };
+        let args: Vec<String> = input
+            .args(24)
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "-framerate",
+                "24",
+                "-f",
+                "rawvideo",
+                "-pixel_format",
+                "rgb24",
+                "-video_size",
+                "512x256",
+                "-i",
+                "frames.raw"
+            ]
+        );
+    }
+}
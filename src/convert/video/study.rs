@@ -0,0 +1,264 @@
+//! One MP4 for a whole study (`video --combine-series`).
+//!
+//! Series are staged one after another into a single raw frame file, each behind
+//! a title card with its `SeriesDescription`. Every series becomes a chapter
+//! starting at its card, handed to ffmpeg as a metadata file, so players can
+//! jump from series to series.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+
+use super::brand::{self, Watermark, WatermarkSink};
+use super::trim::Clip;
+use super::{
+    EncodeFailure, Encoding, Offset, RawStagingSink, VideoOptions, encode_sequence, frame_bytes,
+    render, resolve_fps, staging_estimate,
+};
+use crate::events::{self, Stage};
+use crate::i18n::t;
+use crate::pipeline::{FrameSink, RenderOptions, RunStats};
+use crate::utils::{create_temp_dir, ensure_free_space, format_bytes, named_after_folder};
+
+/// One series of the study.
+pub struct Series<'a> {
+    /// Group key; the title when the series has no description.
+    pub key: &'a str,
+    /// Files of the series, sorted.
+    pub files: &'a [PathBuf],
+}
+
+/// A chapter of the combined video, in frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chapter {
+    title: String,
+    start: usize,
+    end: usize,
+}
+
+/// Stage every series behind its title card and encode them as one MP4 in
+/// `output_dir`, with a chapter per series.
+pub fn convert_study(
+    series: &[Series<'_>],
+    output_dir: &Path,
+    options: RenderOptions<'_>,
+    video: VideoOptions<'_>,
+) -> Result<(RunStats, Option<EncodeFailure>)> {
+    let files: Vec<PathBuf> = series
+        .iter()
+        .flat_map(|entry| entry.files.iter().cloned())
+        .collect();
+    let fps = resolve_fps(video.fps, &files);
+    if fps == 0 {
+        anyhow::bail!("FPS must be greater than 0");
+    }
+    let watermark = video.watermark.map(Watermark::load).transpose()?;
+    let cards = series.len() + usize::from(video.title.is_some());
+    println!("{}", t!("video-combining", count = series.len()));
+
+    let temp_dir = create_temp_dir(video.temp_root)?;
+    let temp_path = temp_dir.path();
+    let clips = series
+        .iter()
+        .map(|entry| Clip::of(entry.files, video.trim, fps))
+        .collect::<Result<Vec<_>>>()?;
+    let total = clips.iter().map(Clip::frames).sum::<usize>() + brand::hold_frames(fps) * cards;
+    if let Some(first) = files.first() {
+        reserve_staging(first, total, temp_path)?;
+    }
+
+    println!("{}", t!("video-preparing"));
+    // Title cards are color, so every frame is staged as RGB
+    let mut staging = RawStagingSink::new(temp_path, total)
+        .with_background(video.background)
+        .in_color(true);
+    let mut marked;
+    let sink: &mut dyn FrameSink = match &watermark {
+        Some(watermark) => {
+            marked = WatermarkSink::new(&mut staging, watermark);
+            &mut marked
+        }
+        None => &mut staging,
+    };
+
+    let first = files.first().map(PathBuf::as_path);
+    let mut position = match video.title {
+        Some(title) => brand::write_card(sink, 0, title, None, first, fps)?,
+        None => 0,
+    };
+    let mut stats = RunStats::default();
+    let mut chapters = Vec::with_capacity(series.len());
+    for (entry, clip) in series.iter().zip(&clips) {
+        let title = series_title(entry);
+        let start = position;
+        // Series after the first are scaled to the first frame anyway
+        let first = entry.files.first().map(PathBuf::as_path);
+        position += brand::write_card(sink, position, &title, None, first, fps)?;
+
+        let mut frames = Offset {
+            inner: &mut *sink,
+            offset: position,
+        };
+        let run = render(clip, options.clone(), video.position_bar, &mut frames);
+        position += run.written;
+        stats.written += run.written;
+        stats.failed += run.failed;
+        chapters.push(Chapter {
+            title,
+            start,
+            end: position,
+        });
+    }
+
+    options.cancel.check()?;
+    let (Some(input), Some((width, height))) = (staging.finish()?, staging.target_size) else {
+        anyhow::bail!("No frames were successfully processed for video creation");
+    };
+    let frame_count = u32::try_from(staging.frame_count).context("Too many frames for video")?;
+    let encoding = video.encoding(fps, frame_count)?;
+    let chapter_path = temp_path.join("chapters.txt");
+    fs::write(&chapter_path, metadata(&chapters, fps))
+        .with_context(|| format!("Failed to write chapters: {}", chapter_path.display()))?;
+
+    println!(
+        "{}",
+        t!("video-creating", width = width, height = height, fps = fps)
+    );
+    if stats.failed > 0 {
+        eprintln!("{}", t!("video-skipped-frames", count = stats.failed));
+    }
+
+    events::stage(output_dir, Stage::Encoding);
+    let video_path = named_after_folder(output_dir, "mp4");
+    let recovered = encode_sequence(
+        &input,
+        Encoding {
+            chapters: Some(&chapter_path),
+            cancel: options.cancel,
+            ..encoding
+        },
+        &video_path,
+    )?;
+
+    println!(
+        "\n{}",
+        t!("video-saved", path = video_path.display().to_string())
+    );
+    println!("  {}", t!("video-chapters", count = chapters.len()));
+    println!("  {}", t!("video-total-frames", count = frame_count));
+    println!(
+        "  {}",
+        t!(
+            "video-duration",
+            seconds = format!("{:.2}", f64::from(frame_count) / f64::from(fps))
+        )
+    );
+    Ok((stats, recovered))
+}
+
+/// Make sure `total` frames like `first` fit in the staging folder.
+fn reserve_staging(first: &Path, total: usize, temp_path: &Path) -> Result<()> {
+    // Title cards are color, so every frame is estimated as RGB
+    let required = staging_estimate(frame_bytes(first, 3), total);
+    println!(
+        "{}",
+        t!(
+            "video-staging-space",
+            size = format_bytes(required),
+            path = temp_path.display().to_string()
+        )
+    );
+    ensure_free_space(temp_path, required)
+}
+
+/// `SeriesDescription` of the first file, or the group key without one.
+fn series_title(series: &Series<'_>) -> String {
+    series
+        .files
+        .first()
+        .and_then(|path| {
+            OpenFileOptions::new()
+                .read_until(tags::PIXEL_DATA)
+                .open_file(path)
+                .ok()
+        })
+        .and_then(|obj| {
+            let text = obj.element(tags::SERIES_DESCRIPTION).ok()?.to_str().ok()?;
+            Some(text.trim_end_matches(['\0', ' ']).trim().to_string())
+        })
+        .filter(|description| !description.is_empty())
+        .unwrap_or_else(|| series.key.to_string())
+}
+
+/// ffmpeg metadata file with one chapter per series, timed in frames.
+fn metadata(chapters: &[Chapter], fps: u32) -> String {
+    let mut text = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        let _ = write!(
+            text,
+            "[CHAPTER]\nTIMEBASE=1/{fps}\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start,
+            chapter.end,
+            escape(&chapter.title)
+        );
+    }
+    text
+}
+
+/// Escape the characters that are special in ffmpeg metadata values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chapters_are_timed_in_frames() {
+        let chapters = [
+            Chapter {
+                title: "AX T2".to_string(),
+                start: 0,
+                end: 40,
+            },
+            Chapter {
+                title: "COR".to_string(),
+                start: 40,
+                end: 65,
+            },
+        ];
+        assert_eq!(
+            metadata(&chapters, 10),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/10\nSTART=0\nEND=40\ntitle=AX T2\n\
+             [CHAPTER]\nTIMEBASE=1/10\nSTART=40\nEND=65\ntitle=COR\n"
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(escape("T1 #2; a=b\\c"), "T1 \\#2\\; a\\=b\\\\c");
+    }
+
+    #[test]
+    fn series_without_description_use_their_key() {
+        let files = [PathBuf::from("/nonexistent/1.dcm")];
+        let series = Series {
+            key: "3",
+            files: &files,
+        };
+        assert_eq!(series_title(&series), "3");
+    }
+}
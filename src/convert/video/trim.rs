@@ -0,0 +1,264 @@
+//! Trimming cine and 4D series to part of their frames (`video --frames`,
+//! `--time-range`).
+//!
+//! A range picks frame positions across the whole series, so one multi-frame
+//! object and a folder of single-frame files (one per time point) trim
+//! alike. Times become frames through the acquisition frame time of the
+//! series (`FrameTime`, else `CineRate`), or the video's frame rate when
+//! neither is recorded. Files wholly outside the range are never decoded.
+
+use std::ops::Range;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::pipeline;
+use crate::pixel::read_first_f64;
+
+/// Part of a series to keep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trim {
+    /// Frames `start..end`, counted from 0.
+    Frames { start: usize, end: usize },
+    /// Frames acquired from `start` up to `end` seconds into the series.
+    Time { start: f64, end: f64 },
+}
+
+impl Trim {
+    /// Parse `--frames START:END`; a missing bound leaves that side open.
+    pub fn parse_frames(value: &str) -> std::result::Result<Self, String> {
+        let (start, end) = split_range(value)?;
+        let bound = |text: &str, open: usize| {
+            if text.is_empty() {
+                Ok(open)
+            } else {
+                text.parse()
+                    .map_err(|_| format!("`{text}` is not a frame number"))
+            }
+        };
+        let (start, end) = (bound(start, 0)?, bound(end, usize::MAX)?);
+        if start >= end {
+            return Err(format!("`{value}` is empty (end must be after start)"));
+        }
+        Ok(Self::Frames { start, end })
+    }
+
+    /// Parse `--time-range START:END` in seconds (`2s`, `2.5`, or
+    /// `2500ms`); a missing bound leaves that side open.
+    pub fn parse_time(value: &str) -> std::result::Result<Self, String> {
+        let (start, end) = split_range(value)?;
+        let bound = |text: &str, open: f64| {
+            if text.is_empty() {
+                return Ok(open);
+            }
+            let (number, scale) = match text.strip_suffix("ms") {
+                Some(ms) => (ms, 0.001),
+                None => (text.strip_suffix('s').unwrap_or(text), 1.0),
+            };
+            number
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(|seconds| seconds * scale)
+                .ok_or_else(|| format!("`{text}` is not a time like 2s or 500ms"))
+        };
+        let (start, end) = (bound(start, 0.0)?, bound(end, f64::INFINITY)?);
+        if start >= end {
+            return Err(format!("`{value}` is empty (end must be after start)"));
+        }
+        Ok(Self::Time { start, end })
+    }
+
+    /// Frame positions kept, for frames `frame_time` ms apart.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn span(self, frame_time: impl FnOnce() -> f64) -> Range<usize> {
+        match self {
+            Self::Frames { start, end } => start..end,
+            Self::Time { start, end } => {
+                // Frame `i` was acquired at `i * frame_time`
+                let ms = frame_time();
+                let frame =
+                    |seconds: f64| (seconds * 1000.0 / ms).ceil().min(usize::MAX as f64) as usize;
+                frame(start)..frame(end)
+            }
+        }
+    }
+}
+
+/// `START:END`, each side trimmed.
+fn split_range(value: &str) -> std::result::Result<(&str, &str), String> {
+    value
+        .split_once(':')
+        .map(|(start, end)| (start.trim(), end.trim()))
+        .ok_or_else(|| format!("`{value}` is not a START:END range"))
+}
+
+/// The frames of a series that go into the video.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clip<'a> {
+    /// Files holding the kept frames.
+    pub files: &'a [PathBuf],
+    /// Positions of the kept frames within `files`.
+    pub span: Range<usize>,
+}
+
+impl<'a> Clip<'a> {
+    /// Every frame of `files`.
+    pub fn whole(files: &'a [PathBuf]) -> Self {
+        Self {
+            files,
+            span: 0..pipeline::count_frames(files),
+        }
+    }
+
+    /// The frames of `files` that `trim` keeps; times count at `fps` when
+    /// the series records no frame time.
+    pub fn of(files: &'a [PathBuf], trim: Option<Trim>, fps: u32) -> Result<Self> {
+        let Some(trim) = trim else {
+            return Ok(Self::whole(files));
+        };
+        let counts: Vec<usize> = files.iter().map(|path| pipeline::frames_in(path)).collect();
+        let total: usize = counts.iter().sum();
+        let span = trim.span(|| {
+            frame_time(files).unwrap_or_else(|| {
+                println!("{}", t!("video-trim-playback-time", fps = fps));
+                1000.0 / f64::from(fps)
+            })
+        });
+        let span = span.start.min(total)..span.end.min(total);
+        let Some((kept, first)) = select(&counts, &span) else {
+            anyhow::bail!(BadInput(format!(
+                "--frames/--time-range keeps none of the {total} frames of the series"
+            )));
+        };
+        println!(
+            "{}",
+            t!(
+                "video-trimmed",
+                start = span.start,
+                end = span.end,
+                total = total
+            )
+        );
+        Ok(Self {
+            files: &files[kept],
+            span: span.start - first..span.end - first,
+        })
+    }
+
+    /// Number of frames kept.
+    pub fn frames(&self) -> usize {
+        self.span.len()
+    }
+}
+
+/// Files holding the frames at positions `span`, given each file's frame
+/// count, and the position of the first of them. `None` when `span` is
+/// empty.
+fn select(counts: &[usize], span: &Range<usize>) -> Option<(Range<usize>, usize)> {
+    if span.is_empty() {
+        return None;
+    }
+    let (mut first, mut position) = (0, 0);
+    while position + counts.get(first)? <= span.start {
+        position += counts[first];
+        first += 1;
+    }
+    let (mut last, mut end) = (first, position + counts[first]);
+    while end < span.end {
+        last += 1;
+        end += counts.get(last)?;
+    }
+    Some((first..last + 1, position))
+}
+
+/// Milliseconds between acquired frames, from the first multi-frame object
+/// of `files`: `FrameTime`, else `CineRate`.
+fn frame_time(files: &[PathBuf]) -> Option<f64> {
+    let positive = |value: Option<f64>| value.filter(|v| v.is_finite() && *v > 0.0);
+    files.iter().find_map(|path| {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+            .ok()?;
+        if pipeline::number_of_frames(&obj) < 2 {
+            return None;
+        }
+        positive(read_first_f64(&obj, tags::FRAME_TIME))
+            .or_else(|| positive(read_first_f64(&obj, tags::CINE_RATE)).map(|rate| 1000.0 / rate))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_ranges_may_be_open() {
+        assert_eq!(
+            Trim::parse_frames("10:120"),
+            Ok(Trim::Frames {
+                start: 10,
+                end: 120
+            })
+        );
+        assert_eq!(
+            Trim::parse_frames(":5"),
+            Ok(Trim::Frames { start: 0, end: 5 })
+        );
+        assert_eq!(
+            Trim::parse_frames("5:"),
+            Ok(Trim::Frames {
+                start: 5,
+                end: usize::MAX
+            })
+        );
+        assert!(Trim::parse_frames("8:8").is_err());
+        assert!(Trim::parse_frames("8").is_err());
+        assert!(Trim::parse_frames("a:b").is_err());
+    }
+
+    #[test]
+    fn times_take_seconds_or_milliseconds() {
+        assert_eq!(
+            Trim::parse_time("2s:8s"),
+            Ok(Trim::Time {
+                start: 2.0,
+                end: 8.0
+            })
+        );
+        assert_eq!(
+            Trim::parse_time("500ms:1.5"),
+            Ok(Trim::Time {
+                start: 0.5,
+                end: 1.5
+            })
+        );
+        assert!(Trim::parse_time("8s:2s").is_err());
+        assert!(Trim::parse_time("-1s:2s").is_err());
+        assert!(Trim::parse_time("2m:3m").is_err());
+    }
+
+    #[test]
+    fn times_become_frames_by_the_frame_time() {
+        let trim = Trim::parse_time("2s:8s").unwrap();
+        // 40 ms apart: frames 50 (2.0 s) to 199 (7.96 s)
+        assert_eq!(trim.span(|| 40.0), 50..200);
+        assert_eq!(trim.span(|| 30.0), 67..267);
+    }
+
+    #[test]
+    fn only_files_holding_kept_frames_are_selected() {
+        // Single-frame files (4D): the span picks files one to one
+        assert_eq!(select(&[1; 10], &(3..6)), Some((3..6, 3)));
+        // Multi-frame objects of 10 frames each
+        assert_eq!(select(&[10, 10, 10], &(12..25)), Some((1..3, 10)));
+        assert_eq!(select(&[10, 10, 10], &(0..10)), Some((0..1, 0)));
+        assert_eq!(select(&[10, 10, 10], &(5..5)), None);
+    }
+}
@@ -0,0 +1,547 @@
+//! RT Dose grids and isodose overlays (`dose`).
+//!
+//! An RT Dose object holds the dose a treatment plan delivers as one
+//! multi-frame grid: a frame per plane, placed along Z by its
+//! `GridFrameOffsetVector` and scaled to Gy by `DoseGridScaling`. The grid
+//! is exported as a NIfTI-1 or NRRD volume, and its isodose lines (the
+//! levels given in percent of the prescription, or of the maximum) are drawn
+//! on the CT the plan was made on, sampled at each CT pixel by patient
+//! position so the two grids need not match.
+
+mod isodose;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+use image::{DynamicImage, Rgb};
+
+use crate::align::{self, Alignment, Interpolation};
+use crate::annotate::Canvas;
+use crate::convert::{ImageFormat, JpegSink, parse_positive};
+use crate::i18n::t;
+use crate::meta::text;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, FrameSink};
+use crate::pixel::{Frame, Window, groups, read_first_f64};
+use crate::utils::extended_length_path;
+use crate::volume::{self, Volume};
+
+use self::isodose::isolines;
+
+/// Colors of the isodose lines, from the highest level down.
+const PALETTE: [Rgb<u8>; 6] = [
+    Rgb([255, 0, 0]),
+    Rgb([255, 128, 0]),
+    Rgb([255, 255, 0]),
+    Rgb([0, 200, 0]),
+    Rgb([0, 200, 255]),
+    Rgb([0, 64, 255]),
+];
+
+/// Offsets of consecutive planes may differ by this much (mm) and still
+/// count as evenly spaced.
+const SPACING_TOLERANCE: f64 = 1e-3;
+
+/// CLI arguments for the `dose` subcommand.
+#[derive(Args, Debug)]
+pub struct DoseArgs {
+    /// RT Dose file (.dcm)
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Save the dose grid as a volume; the extension picks the format
+    /// (.nrrd, .nii, or .nii.gz)
+    #[arg(long = "out", required_unless_present = "overlays")]
+    pub output: Option<PathBuf>,
+
+    /// CT series folder the plan was made on, for `--overlays`
+    #[arg(long, requires = "overlays")]
+    pub ct: Option<PathBuf>,
+
+    /// Folder for the CT slices with isodose lines drawn on them
+    #[arg(long, value_name = "DIR", requires = "ct")]
+    pub overlays: Option<PathBuf>,
+
+    /// Isodose levels in percent of the reference dose
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_delimiter = ',',
+        default_values_t = [95.0, 80.0, 50.0, 20.0],
+        value_parser = parse_positive
+    )]
+    pub levels: Vec<f32>,
+
+    /// Reference dose the levels are percentages of, in the grid's units
+    /// (Gy) [default: maximum of the grid]
+    #[arg(long, value_name = "GY", value_parser = parse_positive)]
+    pub prescription: Option<f32>,
+
+    /// Image encoding for the overlays
+    #[arg(long, value_enum, default_value_t = ImageFormat::Png)]
+    pub image_format: ImageFormat,
+
+    /// Include symlinked .dcm files of the CT (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// A dose grid in patient space.
+#[derive(Debug, Clone)]
+struct Dose {
+    /// Dose per voxel, already scaled by `DoseGridScaling`.
+    grid: Volume,
+    /// `Gy`, or `None` for relative doses.
+    unit: Option<&'static str>,
+}
+
+/// One isodose line to draw.
+#[derive(Debug, Clone, PartialEq)]
+struct Isodose {
+    /// Level in percent of the reference dose.
+    percent: f32,
+    /// Level in the grid's units.
+    dose: f32,
+    color: Rgb<u8>,
+}
+
+/// Export an RT Dose grid and draw its isodose lines on the planning CT.
+pub fn run(args: &DoseArgs) -> Result<()> {
+    let format = args.output.as_deref().map(VolumeFormat::of).transpose()?;
+    let dose = load(&args.input)?;
+    let (_, max) = dose.grid.value_range();
+    let unit = dose.unit.unwrap_or("");
+    println!(
+        "  {}",
+        t!(
+            "dose-loaded",
+            cols = dose.grid.cols,
+            rows = dose.grid.rows,
+            planes = dose.grid.slices,
+            max = format!("{max:.2} {unit}").trim_end().to_string()
+        )
+    );
+
+    if let (Some(output), Some(format)) = (&args.output, format) {
+        let path = extended_length_path(output);
+        match format {
+            VolumeFormat::Nrrd => volume::write_nrrd(&dose.grid, &path)?,
+            VolumeFormat::Nifti => volume::write_nifti(&dose.grid, &path)?,
+        }
+        println!("{}", t!("dose-saved", path = output.display().to_string()));
+    }
+
+    if let (Some(ct), Some(folder)) = (&args.ct, &args.overlays) {
+        let (files, ct) = volume::load_series(ct, 1, args.follow_symlinks)?;
+        align::check_same_frame(&files, std::slice::from_ref(&args.input))?;
+        let window = pipeline::open_object(&files[0])
+            .ok()
+            .and_then(|obj| Window::from_object(&obj))
+            .unwrap_or_else(|| {
+                let (lo, hi) = ct.value_range();
+                Window::spanning(lo, hi)
+            });
+        let reference = args.prescription.unwrap_or(max);
+        let levels = isodoses(&args.levels, reference);
+
+        let output = extended_length_path(folder);
+        fs::create_dir_all(&output)
+            .with_context(|| format!("Failed to create output folder: {}", folder.display()))?;
+        let mut sink = JpegSink::new(&output, ct.slices, args.image_format);
+        // A multi-frame CT holds one slice per frame
+        let sources = files
+            .iter()
+            .flat_map(|path| std::iter::repeat_n(path, pipeline::frames_in(path)));
+        for (z, source) in sources.enumerate().take(ct.slices) {
+            let image = overlay(&ct, z, window, &dose, &levels);
+            sink.write_frame(z, source, image)?;
+        }
+        println!(
+            "{}",
+            t!(
+                "dose-overlays-saved",
+                count = ct.slices,
+                path = folder.display().to_string()
+            )
+        );
+    }
+    Ok(())
+}
+
+/// File format of `--out`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VolumeFormat {
+    Nrrd,
+    Nifti,
+}
+
+impl VolumeFormat {
+    /// Format picked by the file name of `path`.
+    fn of(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".nrrd") {
+            Ok(Self::Nrrd)
+        } else if name.ends_with(".nii") || name.ends_with(".nii.gz") {
+            Ok(Self::Nifti)
+        } else {
+            anyhow::bail!(BadInput(format!(
+                "Unsupported volume file {}; expected .nrrd, .nii, or .nii.gz",
+                path.display()
+            )))
+        }
+    }
+}
+
+/// Read the dose grid of an RT Dose file.
+fn load(path: &Path) -> Result<Dose> {
+    if !path.is_file() {
+        anyhow::bail!(BadInput(format!(
+            "RT Dose file not found: {}",
+            path.display()
+        )));
+    }
+    let obj = pipeline::open_object(path)
+        .with_context(|| format!("Failed to open DICOM file: {}", path.display()))?;
+    let modality = text(&obj, tags::MODALITY).unwrap_or_default();
+    if modality != "RTDOSE" {
+        anyhow::bail!(BadInput(format!(
+            "{} is not an RT Dose object (Modality {modality:?})",
+            path.display()
+        )));
+    }
+    let missing = |what: &str| BadInput(format!("{}: no {what} in the header", path.display()));
+    let [spacing_y, spacing_x] =
+        groups::pixel_spacing(&obj, 0).ok_or_else(|| missing("PixelSpacing"))?;
+    let position =
+        groups::image_position(&obj, 0).ok_or_else(|| missing("ImagePositionPatient"))?;
+    let frames = pipeline::number_of_frames(&obj) as usize;
+    let planes = plane_positions(&obj, position[2], frames)
+        .ok_or_else(|| missing("GridFrameOffsetVector"))?;
+    let spacing_z = match planes.as_slice() {
+        [first, second, ..] => second - first,
+        _ => read_first_f64(&obj, tags::SLICE_THICKNESS).unwrap_or(1.0),
+    };
+    if planes
+        .windows(2)
+        .any(|pair| (pair[1] - pair[0] - spacing_z).abs() > SPACING_TOLERANCE)
+    {
+        anyhow::bail!(BadInput(format!(
+            "{}: dose planes are unevenly spaced, which is not supported",
+            path.display()
+        )));
+    }
+    let scaling = read_first_f64(&obj, tags::DOSE_GRID_SCALING).unwrap_or(1.0);
+
+    let mut frames = (0..frames)
+        .map(|number| {
+            let frame = pipeline::decode_opened(&obj, path, u32::try_from(number)?)?;
+            Ok(frame.into_mono())
+        })
+        .collect::<Result<Vec<Frame>>>()?;
+    // Planes run from feet to head in the volume
+    let descending = spacing_z < 0.0;
+    if descending {
+        frames.reverse();
+    }
+    let (cols, rows) = (frames[0].width as usize, frames[0].height as usize);
+    #[allow(clippy::cast_possible_truncation)]
+    let values = frames
+        .iter()
+        .flat_map(|frame| &frame.values)
+        .map(|&v| (f64::from(v) * scaling) as f32)
+        .collect();
+    let first = if descending {
+        planes.last().copied()
+    } else {
+        planes.first().copied()
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let grid = Volume {
+        values,
+        cols,
+        rows,
+        slices: frames.len(),
+        spacing_x: spacing_x as f32,
+        spacing_y: spacing_y as f32,
+        spacing_z: spacing_z.abs() as f32,
+        origin: [position[0], position[1], first.unwrap_or(position[2])],
+    };
+    let unit = match text(&obj, tags::DOSE_UNITS).as_deref() {
+        Some("GY") => Some("Gy"),
+        _ => None,
+    };
+    Ok(Dose { grid, unit })
+}
+
+/// Z position (mm) of each of the `frames` planes. `GridFrameOffsetVector`
+/// holds offsets from `z` when it starts at 0, else the positions
+/// themselves; a single plane may go without it.
+fn plane_positions(obj: &InMemDicomObject, z: f64, frames: usize) -> Option<Vec<f64>> {
+    let offsets: Option<Vec<f64>> = text(obj, tags::GRID_FRAME_OFFSET_VECTOR)
+        .map(|value| value.split('\\').map(|v| v.trim().parse().ok()).collect())
+        .and_then(|offsets| offsets);
+    match offsets {
+        Some(offsets) if offsets.len() >= frames => {
+            let relative = offsets.first().is_some_and(|first| *first == 0.0);
+            Some(
+                offsets[..frames]
+                    .iter()
+                    .map(|offset| if relative { z + offset } else { *offset })
+                    .collect(),
+            )
+        }
+        _ if frames == 1 => Some(vec![z]),
+        _ => None,
+    }
+}
+
+/// Isodose lines for `percents` of `reference`, highest first, colored hot
+/// to cold.
+fn isodoses(percents: &[f32], reference: f32) -> Vec<Isodose> {
+    let mut percents = percents.to_vec();
+    percents.sort_by(|a, b| b.total_cmp(a));
+    percents.dedup();
+    percents
+        .into_iter()
+        .zip(PALETTE.into_iter().cycle())
+        .map(|(percent, color)| Isodose {
+            percent,
+            dose: reference * percent / 100.0,
+            color,
+        })
+        .collect()
+}
+
+/// Slice `z` of the CT with the isodose lines of `dose` drawn on it, and a
+/// key to the levels where the slice crosses the dose grid.
+fn overlay(ct: &Volume, z: usize, window: Window, dose: &Dose, levels: &[Isodose]) -> DynamicImage {
+    let plane = ct.cols * ct.rows;
+    let slice = Frame {
+        width: u32::try_from(ct.cols).unwrap_or(u32::MAX),
+        height: u32::try_from(ct.rows).unwrap_or(u32::MAX),
+        values: ct.values[z * plane..(z + 1) * plane].to_vec(),
+        window: Some(window),
+        invert: false,
+    };
+    let mut canvas = Canvas::new(DynamicImage::ImageLuma8(slice.to_luma8()).into_rgb8());
+
+    let alignment = Alignment::by_position(Interpolation::Linear);
+    let doses: Vec<f32> = (0..plane)
+        .map(|i| {
+            let point = ct.position(i % ct.cols, i / ct.cols, z);
+            alignment.sample(&dose.grid, point).unwrap_or(f32::NAN)
+        })
+        .collect();
+    if doses.iter().all(|d| d.is_nan()) {
+        return DynamicImage::ImageRgb8(canvas.into_image());
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    let pixel = |point: [f64; 2]| (point[0].round() as i64, point[1].round() as i64);
+    for level in levels {
+        for (from, to) in isolines(&doses, ct.cols, ct.rows, level.dose) {
+            canvas.line(pixel(from), pixel(to), level.color);
+        }
+    }
+    let margin = canvas.thickness() * 2;
+    let mut top = margin;
+    for level in levels {
+        let text = match dose.unit {
+            Some(unit) => format!("{}% {:.1} {unit}", level.percent, level.dose),
+            None => format!("{}%", level.percent),
+        };
+        top += canvas.tag_size(&text).1;
+        canvas.label(&text, (margin, top), level.color);
+    }
+    DynamicImage::ImageRgb8(canvas.into_image())
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+
+    use super::*;
+    use crate::meta::text_element;
+
+    #[test]
+    fn offsets_from_zero_are_relative() {
+        let obj = InMemDicomObject::from_element_iter([text_element(
+            tags::GRID_FRAME_OFFSET_VECTOR,
+            VR::DS,
+            "0\\2.5\\5",
+        )]);
+        assert_eq!(
+            plane_positions(&obj, -100.0, 3),
+            Some(vec![-100.0, -97.5, -95.0])
+        );
+    }
+
+    #[test]
+    fn other_offsets_are_positions() {
+        let obj = InMemDicomObject::from_element_iter([text_element(
+            tags::GRID_FRAME_OFFSET_VECTOR,
+            VR::DS,
+            "-40\\-43",
+        )]);
+        assert_eq!(plane_positions(&obj, -40.0, 2), Some(vec![-40.0, -43.0]));
+        assert_eq!(plane_positions(&obj, -40.0, 3), None);
+        let single = InMemDicomObject::new_empty();
+        assert_eq!(plane_positions(&single, 7.0, 1), Some(vec![7.0]));
+    }
+
+    /// A 2x1 RT Dose grid of three planes listed head to feet at absolute
+    /// positions, stored in cGy steps.
+    fn write_dose(path: &Path, modality: &str) {
+        use dicom::dictionary_std::uids;
+
+        let mut obj = InMemDicomObject::new_empty();
+        let mut put = |tag, vr, value| obj.put(DataElement::new(tag, vr, value));
+        put(tags::MODALITY, VR::CS, PrimitiveValue::from(modality));
+        put(
+            tags::SOP_CLASS_UID,
+            VR::UI,
+            PrimitiveValue::from(uids::RT_DOSE_STORAGE),
+        );
+        put(tags::PIXEL_SPACING, VR::DS, PrimitiveValue::from("2.5\\3"));
+        put(
+            tags::IMAGE_POSITION_PATIENT,
+            VR::DS,
+            PrimitiveValue::from("-10\\-20\\6"),
+        );
+        put(
+            tags::GRID_FRAME_OFFSET_VECTOR,
+            VR::DS,
+            PrimitiveValue::from("6\\4\\2"),
+        );
+        put(
+            tags::DOSE_GRID_SCALING,
+            VR::DS,
+            PrimitiveValue::from("0.01"),
+        );
+        put(tags::DOSE_UNITS, VR::CS, PrimitiveValue::from("GY"));
+        put(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16));
+        put(tags::ROWS, VR::US, PrimitiveValue::from(1_u16));
+        put(tags::NUMBER_OF_FRAMES, VR::IS, PrimitiveValue::from("3"));
+        put(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(1_u16));
+        put(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            PrimitiveValue::from("MONOCHROME2"),
+        );
+        put(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16_u16));
+        put(tags::BITS_STORED, VR::US, PrimitiveValue::from(16_u16));
+        put(tags::HIGH_BIT, VR::US, PrimitiveValue::from(15_u16));
+        put(
+            tags::PIXEL_REPRESENTATION,
+            VR::US,
+            PrimitiveValue::from(0_u16),
+        );
+        let pixels: Vec<u8> = [100_u16, 200, 300, 400, 500, 600]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        put(tags::PIXEL_DATA, VR::OB, PrimitiveValue::from(pixels));
+        let meta = dicom::object::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid(uids::RT_DOSE_STORAGE)
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        obj.with_exact_meta(meta).write_to_file(path).unwrap();
+    }
+
+    #[test]
+    fn dose_grids_load_feet_first_in_gy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("RD.dcm");
+        write_dose(&path, "RTDOSE");
+        let dose = load(&path).unwrap();
+        let grid = &dose.grid;
+        assert_eq!((grid.cols, grid.rows, grid.slices), (2, 1, 3));
+        assert_eq!(grid.spacing(), [3.0, 2.5, 2.0]);
+        assert_eq!(grid.origin, [-10.0, -20.0, 2.0]);
+        // The plane at z = 2 was stored last
+        let expected = [5.0, 6.0, 3.0, 4.0, 1.0, 2.0];
+        for (value, expected) in grid.values.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-5, "{:?}", grid.values);
+        }
+        assert_eq!(dose.unit, Some("Gy"));
+    }
+
+    #[test]
+    fn other_objects_are_bad_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("CT.dcm");
+        write_dose(&path, "CT");
+        let err = load(&path).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        assert!(err.to_string().contains("not an RT Dose"), "{err}");
+    }
+
+    #[test]
+    fn levels_run_hot_to_cold() {
+        let levels = isodoses(&[50.0, 95.0, 50.0, 100.0], 60.0);
+        let percents: Vec<f32> = levels.iter().map(|l| l.percent).collect();
+        assert_eq!(percents, [100.0, 95.0, 50.0]);
+        assert!((levels[1].dose - 57.0).abs() < 1e-4);
+        assert_eq!(levels[0].color, PALETTE[0]);
+    }
+
+    #[test]
+    fn volume_format_follows_the_extension() {
+        assert_eq!(
+            VolumeFormat::of(Path::new("a/dose.NRRD")).unwrap(),
+            VolumeFormat::Nrrd
+        );
+        assert_eq!(
+            VolumeFormat::of(Path::new("dose.nii.gz")).unwrap(),
+            VolumeFormat::Nifti
+        );
+        let err = VolumeFormat::of(Path::new("dose.mha")).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+    }
+
+    #[test]
+    fn lines_are_drawn_where_the_grid_covers_the_slice() {
+        // 20x20 CT at 1 mm; dose rises along X over its left half only
+        let ct = Volume {
+            values: vec![0.0; 400],
+            cols: 20,
+            rows: 20,
+            slices: 1,
+            spacing_x: 1.0,
+            spacing_y: 1.0,
+            spacing_z: 1.0,
+            origin: [0.0; 3],
+        };
+        let grid = Volume {
+            values: (0..200)
+                .map(|i| f32::from(u8::try_from(i % 10).unwrap()))
+                .collect(),
+            cols: 10,
+            rows: 10,
+            slices: 2,
+            spacing_x: 1.0,
+            spacing_y: 2.0,
+            spacing_z: 1.0,
+            origin: [0.0, 0.0, -0.5],
+        };
+        let dose = Dose {
+            grid,
+            unit: Some("Gy"),
+        };
+        let levels = isodoses(&[50.0], 9.0);
+        let image = overlay(&ct, 0, Window::spanning(0.0, 1.0), &dose, &levels).to_rgb8();
+        // The 4.5 Gy line runs down x = 4.5, drawn at x = 5, below the key
+        assert_eq!(image.get_pixel(5, 15).0, PALETTE[0].0);
+        assert_eq!(image.get_pixel(15, 15).0, [0, 0, 0]);
+    }
+}
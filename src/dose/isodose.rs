@@ -0,0 +1,136 @@
+//! Isodose lines by marching squares.
+//!
+//! Each square between four neighbouring pixels is crossed by the line
+//! wherever the level falls between two of its corners, at the point found
+//! by linear interpolation along that edge. Saddle squares, where diagonal
+//! corners are on the same side, are split by the mean of the four. Squares
+//! touching a pixel outside the dose grid (NaN) are skipped.
+
+/// A piece of line between two points, in pixels.
+pub type Segment = ([f64; 2], [f64; 2]);
+
+/// Where the level crosses the edges of a square.
+#[derive(Debug, Clone, Copy)]
+enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+/// Pieces of the line at `level` through a `width` x `height` plane of
+/// `values`, packed row by row.
+pub fn isolines(values: &[f32], width: usize, height: usize, level: f32) -> Vec<Segment> {
+    use Edge::{Bottom, Left, Right, Top};
+
+    let mut segments = Vec::new();
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let at = |dx: usize, dy: usize| values[x + dx + (y + dy) * width];
+            // Clockwise from the top left
+            let corners = [at(0, 0), at(1, 0), at(1, 1), at(0, 1)];
+            if corners.iter().any(|v| v.is_nan()) {
+                continue;
+            }
+            let case = corners
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| **v >= level)
+                .fold(0, |case, (i, _)| case | 1 << i);
+            let center_above = || corners.iter().sum::<f32>() / 4.0 >= level;
+            let pairs: &[(Edge, Edge)] = match case {
+                1 | 14 => &[(Left, Top)],
+                2 | 13 => &[(Top, Right)],
+                3 | 12 => &[(Left, Right)],
+                4 | 11 => &[(Right, Bottom)],
+                6 | 9 => &[(Top, Bottom)],
+                7 | 8 => &[(Bottom, Left)],
+                // Top left and bottom right above
+                5 if center_above() => &[(Top, Right), (Bottom, Left)],
+                5 => &[(Left, Top), (Right, Bottom)],
+                // Top right and bottom left above
+                10 if center_above() => &[(Left, Top), (Right, Bottom)],
+                10 => &[(Top, Right), (Bottom, Left)],
+                _ => &[],
+            };
+            for &(from, to) in pairs {
+                segments.push((
+                    crossing(from, x, y, corners, level),
+                    crossing(to, x, y, corners, level),
+                ));
+            }
+        }
+    }
+    segments
+}
+
+/// Point on `edge` of the square at `(x, y)` where `level` is reached.
+#[allow(clippy::cast_precision_loss)]
+fn crossing(edge: Edge, x: usize, y: usize, corners: [f32; 4], level: f32) -> [f64; 2] {
+    let [top_left, top_right, bottom_right, bottom_left] = corners;
+    let share = |a: f32, b: f32| {
+        if (b - a).abs() < f32::EPSILON {
+            0.5
+        } else {
+            f64::from(((level - a) / (b - a)).clamp(0.0, 1.0))
+        }
+    };
+    let (x, y) = (x as f64, y as f64);
+    match edge {
+        Edge::Top => [x + share(top_left, top_right), y],
+        Edge::Right => [x + 1.0, y + share(top_right, bottom_right)],
+        Edge::Bottom => [x + share(bottom_left, bottom_right), y + 1.0],
+        Edge::Left => [x, y + share(top_left, bottom_left)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 21x21 plane falling off by 1 per pixel from 10 at its center.
+    fn cone() -> Vec<f32> {
+        (0..21 * 21)
+            .map(|i| {
+                let (x, y) = (f64::from(i % 21) - 10.0, f64::from(i / 21) - 10.0);
+                #[allow(clippy::cast_possible_truncation)]
+                let value = (10.0 - x.hypot(y)) as f32;
+                value
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_line_follows_the_level() {
+        let segments = isolines(&cone(), 21, 21, 5.0);
+        assert!(segments.len() > 20, "{}", segments.len());
+        for point in segments.iter().flat_map(|(a, b)| [a, b]) {
+            let radius = (point[0] - 10.0).hypot(point[1] - 10.0);
+            assert!((radius - 5.0).abs() < 0.1, "{point:?}");
+        }
+    }
+
+    #[test]
+    fn flat_planes_have_no_lines() {
+        assert!(isolines(&[3.0; 16], 4, 4, 5.0).is_empty());
+        assert!(isolines(&[3.0; 16], 4, 4, 1.0).is_empty());
+    }
+
+    #[test]
+    fn squares_outside_the_grid_are_skipped() {
+        let values = [0.0, 10.0, f32::NAN, 0.0, 10.0, f32::NAN];
+        let segments = isolines(&values, 3, 2, 5.0);
+        assert_eq!(segments, vec![([0.5, 0.0], [0.5, 1.0])]);
+    }
+
+    #[test]
+    fn saddles_split_by_their_mean() {
+        // Top left and bottom right high; mean above the level joins them
+        let segments = isolines(&[10.0, 4.0, 4.0, 10.0], 2, 2, 5.0);
+        assert_eq!(segments.len(), 2);
+        let corners: Vec<[f64; 2]> = segments.iter().flat_map(|(a, b)| [*a, *b]).collect();
+        // Each piece cuts off a low corner: top right, then bottom left
+        assert!(corners[0][1] < 0.5 && corners[1][0] > 0.5);
+        assert!(corners[2][1] > 0.5 && corners[3][0] < 0.5);
+    }
+}
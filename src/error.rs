@@ -0,0 +1,175 @@
+//! Typed errors of the load layer.
+//!
+//! Opening, parsing, and decoding DICOM data ([`crate::pipeline`],
+//! [`crate::pixel`]) fail with a [`ToolboxError`] rather than an opaque
+//! `anyhow::Error`, so code embedding that layer can tell a missing file from
+//! a damaged one, or a codec left out of the build, by matching on the
+//! variant. The commands still report through `anyhow`: a `ToolboxError`
+//! converts with `?` and can be found again with `downcast_ref`.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cancel::Stopped;
+
+/// Why loading, decoding, or encoding failed.
+#[derive(Debug)]
+pub enum ToolboxError {
+    /// A file or stream could not be read or written.
+    Io { path: PathBuf, source: io::Error },
+    /// The data is not DICOM, or its pixel data cannot be understood.
+    Parse { path: PathBuf, reason: String },
+    /// The pixel data is compressed with a codec this build cannot decode;
+    /// `feature` names the cargo feature that adds it, if one does.
+    UnsupportedTransferSyntax {
+        uid: String,
+        name: String,
+        feature: Option<&'static str>,
+    },
+    /// A required attribute is absent.
+    MissingTag { path: PathBuf, tag: &'static str },
+    /// An image encoder rejected a frame.
+    Encoder { path: PathBuf, reason: String },
+    /// Ctrl-C or `--timeout` stopped the work.
+    Cancelled(Stopped),
+}
+
+impl ToolboxError {
+    /// A [`Parse`](Self::Parse) error from `error` and its causes.
+    pub fn parse(path: &Path, error: &dyn Error) -> Self {
+        Self::Parse {
+            path: path.to_path_buf(),
+            reason: causes(error),
+        }
+    }
+
+    /// An [`Encoder`](Self::Encoder) error from `error` and its causes.
+    pub fn encoder(path: &Path, error: &dyn Error) -> Self {
+        Self::Encoder {
+            path: path.to_path_buf(),
+            reason: causes(error),
+        }
+    }
+
+    /// The same error about `path`, for errors raised where only the
+    /// object, not the file it came from, was known.
+    #[must_use]
+    pub fn at(mut self, file: &Path) -> Self {
+        match &mut self {
+            Self::Io { path, .. }
+            | Self::Parse { path, .. }
+            | Self::MissingTag { path, .. }
+            | Self::Encoder { path, .. }
+                if path.as_os_str().is_empty() =>
+            {
+                *path = file.to_path_buf();
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Why the work stopped, for a [`Cancelled`](Self::Cancelled) error.
+    pub const fn stopped(&self) -> Option<Stopped> {
+        match self {
+            Self::Cancelled(stopped) => Some(*stopped),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ToolboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}{source}", prefix(path)),
+            Self::Parse { path, reason } => write!(f, "{}{reason}", prefix(path)),
+            Self::UnsupportedTransferSyntax { uid, name, feature } => {
+                write!(
+                    f,
+                    "Pixel data is compressed with {name} ({uid}), which this build cannot decode"
+                )?;
+                match feature {
+                    Some(feature) => write!(
+                        f,
+                        "; reinstall with `cargo install dcm-toolbox --features {feature}`"
+                    ),
+                    None => Ok(()),
+                }
+            }
+            Self::MissingTag { path, tag } => write!(f, "{}No {tag}", prefix(path)),
+            Self::Encoder { path, reason } => {
+                write!(f, "{}Failed to encode: {reason}", prefix(path))
+            }
+            Self::Cancelled(stopped) => stopped.fmt(f),
+        }
+    }
+}
+
+impl Error for ToolboxError {}
+
+impl From<Stopped> for ToolboxError {
+    fn from(stopped: Stopped) -> Self {
+        Self::Cancelled(stopped)
+    }
+}
+
+/// `<path>: ` before a message, or nothing while the path is unknown.
+fn prefix(path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        String::new()
+    } else {
+        format!("{}: ", path.display())
+    }
+}
+
+/// `error` and its sources, `: `-separated like `{:#}` of an `anyhow::Error`.
+fn causes(error: &dyn Error) -> String {
+    let mut text = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        text.push_str(": ");
+        text.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_filled_in_once() {
+        let error = ToolboxError::MissingTag {
+            path: PathBuf::new(),
+            tag: "PixelData",
+        };
+        assert_eq!(error.to_string(), "No PixelData");
+        let error = error.at(Path::new("a.dcm")).at(Path::new("b.dcm"));
+        assert_eq!(error.to_string(), "a.dcm: No PixelData");
+    }
+
+    #[test]
+    fn causes_are_kept() {
+        let cause = anyhow::anyhow!("Truncated element").context("Could not read data set token");
+        let error = ToolboxError::parse(Path::new("a.dcm"), &*cause);
+        assert_eq!(
+            error.to_string(),
+            "a.dcm: Could not read data set token: Truncated element"
+        );
+    }
+
+    #[test]
+    fn stops_are_cancellations() {
+        let error = ToolboxError::from(Stopped::Interrupted);
+        assert_eq!(error.stopped(), Some(Stopped::Interrupted));
+        assert_eq!(error.to_string(), "Interrupted by Ctrl-C");
+        assert!(
+            ToolboxError::encoder(Path::new("a.png"), &io::Error::other("full"))
+                .stopped()
+                .is_none()
+        );
+    }
+}
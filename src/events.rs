@@ -0,0 +1,162 @@
+//! Progress events of a conversion.
+//!
+//! `convert` reports what it is doing as [`Event`]s instead of printing
+//! directly: a series starting, moving to its encoding or meshing stage, and
+//! finishing or failing, and each file being opened, finished, or failing.
+//! Events go to one [`Listener`] per process. Without one, [`Console`]
+//! prints them as the CLI always has; a GUI installs its own with
+//! [`listen`], or hands over the sending half of a channel and reads
+//! [`Event`]s on another thread.
+//!
+//! A series is named by its output folder, which is unique within a run.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::mpsc::Sender;
+
+use crate::i18n::t;
+use crate::pipeline::display_name;
+
+/// Something that happened during a conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The series writing into `series` moved to `stage`.
+    Series { series: PathBuf, stage: Stage },
+    /// A file is about to be read.
+    FileStarted { path: PathBuf },
+    /// Every frame of a file was handled; `frames` of them were written.
+    FileFinished { path: PathBuf, frames: usize },
+    /// A file, or one of its frames, could not be converted.
+    FileFailed { path: PathBuf, error: String },
+}
+
+/// Where a series is in its conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// Conversion begins: the series' group key and number of files.
+    Started { key: String, files: usize },
+    /// Rendered frames are being encoded into a video.
+    Encoding,
+    /// The loaded volume is being turned into a mesh.
+    Meshing,
+    /// Conversion ended; some files may still have failed.
+    Finished,
+    /// Conversion of the series with group key `key` stopped with `error`.
+    Failed { key: String, error: String },
+}
+
+/// Receives every [`Event`] of the process, from any thread.
+pub trait Listener: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+/// Events sent down a channel; a closed channel drops them.
+impl Listener for Sender<Event> {
+    fn on_event(&self, event: &Event) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// The CLI's listener: the usual progress lines on stdout and stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Console;
+
+impl Listener for Console {
+    fn on_event(&self, event: &Event) {
+        match event {
+            Event::Series {
+                stage: Stage::Started { key, files },
+                ..
+            } => println!(
+                "{}",
+                t!(
+                    "convert-processing-series",
+                    key = key.as_str(),
+                    count = *files
+                )
+            ),
+            Event::Series {
+                stage: Stage::Encoding,
+                ..
+            } => println!("\n{}", t!("video-encoding")),
+            Event::Series {
+                stage: Stage::Failed { key, error },
+                ..
+            } => eprintln!(
+                "{}",
+                t!(
+                    "convert-series-failed",
+                    key = key.as_str(),
+                    error = error.as_str()
+                )
+            ),
+            Event::FileFailed { path, error } => eprintln!(
+                "{}",
+                t!(
+                    "convert-file-failed",
+                    file = display_name(path),
+                    error = error.as_str()
+                )
+            ),
+            Event::Series { .. } | Event::FileStarted { .. } | Event::FileFinished { .. } => {}
+        }
+    }
+}
+
+/// The listener of this process, [`Console`] unless [`listen`] came first.
+static LISTENER: OnceLock<Box<dyn Listener>> = OnceLock::new();
+
+/// Send every event from now on to `listener` instead of [`Console`]. Only
+/// the first call before any event counts.
+pub fn listen(listener: impl Listener + 'static) {
+    let _ = LISTENER.set(Box::new(listener));
+}
+
+/// Hand `event` to the listener.
+pub fn emit(event: Event) {
+    LISTENER.get_or_init(|| Box::new(Console)).on_event(&event);
+}
+
+/// [`Event::Series`] for the series writing into `series`.
+pub fn stage(series: &Path, stage: Stage) {
+    emit(Event::Series {
+        series: series.to_path_buf(),
+        stage,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn channels_receive_events_in_order() {
+        let (sender, receiver) = mpsc::channel();
+        let events = [
+            Event::FileStarted {
+                path: PathBuf::from("a.dcm"),
+            },
+            Event::FileFinished {
+                path: PathBuf::from("a.dcm"),
+                frames: 3,
+            },
+        ];
+        for event in &events {
+            sender.on_event(event);
+        }
+        drop(sender);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), events);
+    }
+
+    #[test]
+    fn closed_channels_drop_events() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        sender.on_event(&Event::FileFailed {
+            path: PathBuf::from("a.dcm"),
+            error: "broken".to_string(),
+        });
+    }
+}
@@ -0,0 +1,194 @@
+//! Locating ffmpeg and probing its encoders.
+//!
+//! The executable is `--ffmpeg-path` when given, else the first `ffmpeg` on
+//! `PATH`. Commands that encode video [`probe`] it before preparing any
+//! frame, so a missing or broken ffmpeg fails right away with what to do
+//! about it. The probe also lists the encoders the build offers
+//! (`ffmpeg -encoders`), letting missing ones fall back up front instead of
+//! failing after every frame was staged.
+
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+
+use crate::outcome::BadInput;
+
+pub mod slots;
+
+/// File name of the executable.
+const EXECUTABLE: &str = if cfg!(windows) {
+    "ffmpeg.exe"
+} else {
+    "ffmpeg"
+};
+
+/// `--ffmpeg-path`, set once at startup.
+static EXPLICIT: OnceLock<PathBuf> = OnceLock::new();
+
+/// The probed executable, once a command needed it.
+static PROBED: OnceLock<Ffmpeg> = OnceLock::new();
+
+/// An ffmpeg executable and the encoders it was built with.
+#[derive(Debug)]
+pub struct Ffmpeg {
+    path: PathBuf,
+    encoders: HashSet<String>,
+}
+
+impl Ffmpeg {
+    /// Location of the executable.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the build offers the encoder `name` (e.g. `libx265`).
+    pub fn has_encoder(&self, name: &str) -> bool {
+        self.encoders.contains(name)
+    }
+}
+
+/// Use `path` instead of searching `PATH` (`--ffmpeg-path`).
+pub fn set_path(path: PathBuf) {
+    let _ = EXPLICIT.set(path);
+}
+
+/// Locate ffmpeg and list its encoders, once per run. A missing or broken
+/// executable is [`BadInput`].
+pub fn probe() -> Result<&'static Ffmpeg> {
+    if let Some(ffmpeg) = PROBED.get() {
+        return Ok(ffmpeg);
+    }
+    let path = locate(
+        EXPLICIT.get().map(PathBuf::as_path),
+        env::var_os("PATH").as_deref(),
+    )?;
+    let output = Command::new(&path)
+        .args(["-hide_banner", "-encoders"])
+        .output()
+        .map_err(|e| BadInput(format!("Failed to run ffmpeg at {}: {e}", path.display())))?;
+    if !output.status.success() {
+        anyhow::bail!(BadInput(format!(
+            "ffmpeg at {} could not list its encoders: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let encoders = parse_encoders(&String::from_utf8_lossy(&output.stdout));
+    Ok(PROBED.get_or_init(|| Ffmpeg { path, encoders }))
+}
+
+/// The executable found by [`probe`], if it ran.
+pub fn probed() -> Option<&'static Ffmpeg> {
+    PROBED.get()
+}
+
+/// A command running the probed ffmpeg, else `--ffmpeg-path` or `ffmpeg`
+/// from `PATH`.
+pub fn command() -> Command {
+    let path = PROBED
+        .get()
+        .map(Ffmpeg::path)
+        .or_else(|| EXPLICIT.get().map(PathBuf::as_path))
+        .unwrap_or_else(|| Path::new(EXECUTABLE));
+    Command::new(path)
+}
+
+/// The executable: `explicit` (a file, or a folder holding it) if given,
+/// else the first one in the `search` path list.
+fn locate(explicit: Option<&Path>, search: Option<&OsStr>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        let path = if path.is_dir() {
+            path.join(EXECUTABLE)
+        } else {
+            path.to_path_buf()
+        };
+        if path.is_file() {
+            return Ok(path);
+        }
+        anyhow::bail!(BadInput(format!(
+            "--ffmpeg-path {} is not an ffmpeg executable",
+            path.display()
+        )));
+    }
+    search
+        .into_iter()
+        .flat_map(env::split_paths)
+        .map(|dir| dir.join(EXECUTABLE))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            BadInput(
+                "ffmpeg was not found on PATH; install it (https://ffmpeg.org/download.html) \
+                 or pass --ffmpeg-path with its location"
+                    .to_string(),
+            )
+            .into()
+        })
+}
+
+/// Encoder names from `ffmpeg -encoders`, listed below its `------` line.
+fn parse_encoders(listing: &str) -> HashSet<String> {
+    listing
+        .lines()
+        .skip_while(|line| line.trim() != "------")
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn encoders_are_read_below_the_legend() {
+        let listing = "Encoders:\n \
+                       V..... = Video\n \
+                       ------\n \
+                       V....D libx264              libx264 H.264 / AVC\n \
+                       V....D h264_nvenc           NVIDIA NVENC H.264 encoder\n \
+                       A....D aac                  AAC (Advanced Audio Coding)\n";
+        let encoders = parse_encoders(listing);
+        assert_eq!(encoders.len(), 3);
+        assert!(encoders.contains("h264_nvenc"));
+        assert!(!encoders.contains("Video"));
+    }
+
+    #[test]
+    fn path_is_searched_in_order() {
+        let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(second.path().join(EXECUTABLE), "").unwrap();
+        let search = env::join_paths([first.path(), second.path()]).unwrap();
+
+        let found = locate(None, Some(&search)).unwrap();
+        assert_eq!(found, second.path().join(EXECUTABLE));
+    }
+
+    #[test]
+    fn explicit_folder_holds_the_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(EXECUTABLE), "").unwrap();
+        assert_eq!(
+            locate(Some(dir.path()), None).unwrap(),
+            dir.path().join(EXECUTABLE)
+        );
+    }
+
+    #[test]
+    fn missing_ffmpeg_is_bad_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let search = dir.path().as_os_str();
+        let err = locate(None, Some(search)).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        assert!(err.to_string().contains("--ffmpeg-path"), "{err}");
+
+        let err = locate(Some(&dir.path().join("nope")), Some(search)).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+    }
+}
@@ -0,0 +1,163 @@
+//! A limit on ffmpeg processes encoding at once (`--max-encoders`).
+//!
+//! Each encode holds one of `N` slots while ffmpeg runs. A slot is an
+//! exclusive lock on a file in a folder shared by every dcm-toolbox process
+//! of the same user, so the limit also holds across runs started side by
+//! side (e.g. one per study with `xargs -P`), while other users' runs keep
+//! their own limit. Locks go away with the process, so a killed run never
+//! keeps its slot. Where no slot can be taken, the encode fails rather than
+//! run past the limit.
+
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::cancel::Cancel;
+use crate::i18n::t;
+
+/// How often a waiting encode looks for a free slot.
+const POLL: Duration = Duration::from_millis(250);
+
+/// `--max-encoders`, set once at startup.
+static MAX_ENCODERS: OnceLock<usize> = OnceLock::new();
+
+/// Allow at most `max` encodes at once from now on.
+pub fn set_max(max: usize) {
+    let _ = MAX_ENCODERS.set(max);
+}
+
+/// An encoder slot, released when dropped.
+#[derive(Debug)]
+pub struct Slot {
+    _lock: Option<File>,
+}
+
+/// Wait for a free slot if `--max-encoders` is set. `cancel` ends the wait
+/// on Ctrl-C or past the series' deadline.
+pub fn acquire(cancel: &Cancel) -> Result<Slot> {
+    match MAX_ENCODERS.get() {
+        Some(&max) => acquire_in(&folder(), max, cancel),
+        None => Ok(Slot { _lock: None }),
+    }
+}
+
+/// Folder holding the slot files of this user: under `$XDG_RUNTIME_DIR`
+/// when it is set, otherwise under the temp folder, named after the user ID
+/// where the temp folder is shared (Unix).
+fn folder() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("dcm-toolbox-encoders"),
+        None => std::env::temp_dir().join(user_folder_name()),
+    }
+}
+
+#[cfg(unix)]
+fn user_folder_name() -> String {
+    // SAFETY: getuid cannot fail and has no side effects.
+    let uid = unsafe { libc::getuid() };
+    format!("dcm-toolbox-encoders-{uid}")
+}
+
+/// The temp folder is already per user elsewhere (e.g. `%TEMP%` on Windows).
+#[cfg(not(unix))]
+fn user_folder_name() -> String {
+    "dcm-toolbox-encoders".to_string()
+}
+
+fn acquire_in(dir: &Path, max: usize, cancel: &Cancel) -> Result<Slot> {
+    let mut slots = open_slots(dir, max).with_context(|| {
+        format!(
+            "Cannot limit concurrent encodes to --max-encoders {max}: failed to open the slot files in {}",
+            dir.display()
+        )
+    })?;
+    let mut waiting = false;
+    loop {
+        for i in 0..slots.len() {
+            match slots[i].try_lock() {
+                Ok(()) => {
+                    return Ok(Slot {
+                        _lock: Some(slots.swap_remove(i)),
+                    });
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Cannot limit concurrent encodes to --max-encoders {max}: failed to lock a slot in {}",
+                            dir.display()
+                        )
+                    });
+                }
+            }
+        }
+        if !waiting {
+            println!("{}", t!("ffmpeg-waiting-slot", count = max));
+            waiting = true;
+        }
+        cancel.check()?;
+        thread::sleep(POLL);
+    }
+}
+
+/// Open (creating) the `max` slot files in `dir`. A new folder is private to
+/// this user on Unix.
+fn open_slots(dir: &Path, max: usize) -> std::io::Result<Vec<File>> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)?;
+    (0..max)
+        .map(|slot| {
+            File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(dir.join(format!("slot-{slot}.lock")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_exclusive_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = acquire_in(dir.path(), 1, &Cancel::default()).unwrap();
+        assert!(first._lock.is_some());
+
+        // The only slot is taken: the wait ends at the deadline
+        let deadline = Cancel::within(Some(Duration::ZERO));
+        assert!(acquire_in(dir.path(), 1, &deadline).is_err());
+
+        drop(first);
+        let again = acquire_in(dir.path(), 1, &Cancel::default()).unwrap();
+        assert!(again._lock.is_some());
+    }
+
+    #[test]
+    fn unusable_folder_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("not-a-folder");
+        fs::write(&file, b"").unwrap();
+        let err = acquire_in(&file, 1, &Cancel::default()).unwrap_err();
+        assert!(format!("{err:#}").contains("--max-encoders"));
+    }
+
+    #[test]
+    fn each_slot_is_used_before_waiting() {
+        let dir = tempfile::tempdir().unwrap();
+        let deadline = Cancel::within(Some(Duration::ZERO));
+        let first = acquire_in(dir.path(), 2, &deadline).unwrap();
+        let second = acquire_in(dir.path(), 2, &deadline).unwrap();
+        assert!(first._lock.is_some() && second._lock.is_some());
+        assert!(acquire_in(dir.path(), 2, &deadline).is_err());
+    }
+}
@@ -0,0 +1,399 @@
+//! Per-slice image filters: noise reduction (`--denoise`) and sharpening
+//! (`--sharpen`).
+//!
+//! Filters run on calibrated values (e.g. HU) before windowing, so they see
+//! the full dynamic range instead of 8-bit gray levels and JPEG compression
+//! no longer spends its bits on noise.
+//!
+//! The edge-preserving denoising filters (bilateral, non-local means) take their
+//! strength from a noise estimate of the slice itself, so the same flag works
+//! across modalities and dose levels.
+
+use clap::ValueEnum;
+
+/// Neighborhood radius of the median filter (3x3).
+const MEDIAN_RADIUS: usize = 1;
+
+/// Neighborhood radius of the bilateral filter (5x5).
+const BILATERAL_RADIUS: usize = 2;
+
+/// Spatial Gaussian sigma of the bilateral filter, in pixels.
+const BILATERAL_SPATIAL_SIGMA: f32 = 1.5;
+
+/// Bilateral range sigma as a multiple of the estimated noise sigma.
+const BILATERAL_RANGE_FACTOR: f32 = 2.0;
+
+/// Patch radius for non-local means (3x3 patches).
+const NLM_PATCH_RADIUS: usize = 1;
+
+/// Search radius for non-local means (11x11 window).
+const NLM_SEARCH_RADIUS: usize = 5;
+
+/// Non-local means filtering strength `h` as a multiple of the noise sigma.
+const NLM_STRENGTH: f32 = 1.0;
+
+/// Radius of the Gaussian blur subtracted by the unsharp mask (5x5).
+const UNSHARP_RADIUS: usize = 2;
+
+/// Sigma of the unsharp mask blur, in pixels.
+const UNSHARP_SIGMA: f32 = 1.0;
+
+/// Noise reduction filter applied to each slice before export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Denoise {
+    /// 3x3 median: removes speckle and salt-and-pepper noise
+    Median,
+    /// Edge-preserving bilateral filter
+    Bilateral,
+    /// Non-local means: strongest and slowest, best for low-dose CT
+    Nlm,
+}
+
+/// Filter a `width` x `height` slice and return the denoised values.
+pub fn denoise(filter: Denoise, values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    match filter {
+        Denoise::Median => median(values, width, height),
+        Denoise::Bilateral => bilateral(values, width, height, noise_sigma(values, width)),
+        Denoise::Nlm => non_local_means(values, width, height, noise_sigma(values, width)),
+    }
+}
+
+/// Sharpen a `width` x `height` slice with an unsharp mask.
+///
+/// Adds `amount` times the difference between the slice and a Gaussian-blurred
+/// copy, so `0` leaves the slice unchanged and `1` doubles local contrast.
+pub fn unsharp_mask(values: &[f32], width: usize, height: usize, amount: f32) -> Vec<f32> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let blurred = gaussian_blur(values, width, height);
+    values
+        .iter()
+        .zip(blurred)
+        .map(|(&v, b)| amount.mul_add(v - b, v))
+        .collect()
+}
+
+/// Separable Gaussian blur with [`UNSHARP_SIGMA`].
+#[allow(clippy::cast_precision_loss)]
+fn gaussian_blur(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let r = UNSHARP_RADIUS;
+    let kernel: Vec<f32> = (0..=2 * r)
+        .map(|i| {
+            let d = i.abs_diff(r) as f32;
+            (-d * d / (2.0 * UNSHARP_SIGMA * UNSHARP_SIGMA)).exp()
+        })
+        .collect();
+    let total: f32 = kernel.iter().sum();
+
+    let padded = Padded::new(values, width, height, r);
+    let horizontal: Vec<f32> = (0..height + 2 * r)
+        .flat_map(|y| {
+            let (padded, kernel) = (&padded, &kernel);
+            (0..width).map(move |x| {
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &k)| k * padded.at(x + i, y))
+                    .sum::<f32>()
+                    / total
+            })
+        })
+        .collect();
+
+    let mut out = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let sum: f32 = kernel
+                .iter()
+                .enumerate()
+                .map(|(i, &k)| k * horizontal[(y + i) * width + x])
+                .sum();
+            out.push(sum / total);
+        }
+    }
+    out
+}
+
+/// A slice padded by edge replication, so neighborhoods never leave the buffer.
+struct Padded {
+    values: Vec<f32>,
+    width: usize,
+}
+
+impl Padded {
+    fn new(values: &[f32], width: usize, height: usize, radius: usize) -> Self {
+        let padded_width = width + 2 * radius;
+        let mut padded = Vec::with_capacity(padded_width * (height + 2 * radius));
+        for py in 0..height + 2 * radius {
+            let y = py.saturating_sub(radius).min(height - 1);
+            let row = &values[y * width..(y + 1) * width];
+            padded
+                .extend((0..padded_width).map(|px| row[px.saturating_sub(radius).min(width - 1)]));
+        }
+        Self {
+            values: padded,
+            width: padded_width,
+        }
+    }
+
+    /// Value at padded coordinates `(x, y)`; slice pixel `(x, y)` is at
+    /// `(x + radius, y + radius)`.
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.values[y * self.width + x]
+    }
+}
+
+fn median(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let r = MEDIAN_RADIUS;
+    let padded = Padded::new(values, width, height, r);
+    let mut window = Vec::with_capacity((2 * r + 1) * (2 * r + 1));
+
+    let mut out = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            window.clear();
+            for dy in 0..=2 * r {
+                window.extend((0..=2 * r).map(|dx| padded.at(x + dx, y + dy)));
+            }
+            let mid = window.len() / 2;
+            let (_, value, _) = window.select_nth_unstable_by(mid, f32::total_cmp);
+            out.push(*value);
+        }
+    }
+    out
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn bilateral(values: &[f32], width: usize, height: usize, noise: f32) -> Vec<f32> {
+    if noise <= 0.0 {
+        return values.to_vec();
+    }
+
+    let r = BILATERAL_RADIUS;
+    let size = 2 * r + 1;
+    let spatial: Vec<f32> = (0..size * size)
+        .map(|i| {
+            let dx = (i % size).abs_diff(r) as f32;
+            let dy = (i / size).abs_diff(r) as f32;
+            (-dx.mul_add(dx, dy * dy) / (2.0 * BILATERAL_SPATIAL_SIGMA.powi(2))).exp()
+        })
+        .collect();
+    let range_denominator = 2.0 * (BILATERAL_RANGE_FACTOR * noise).powi(2);
+
+    let padded = Padded::new(values, width, height, r);
+    let mut out = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let center = padded.at(x + r, y + r);
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for (i, &spatial_weight) in spatial.iter().enumerate() {
+                let v = padded.at(x + i % size, y + i / size);
+                let weight = spatial_weight * (-(v - center).powi(2) / range_denominator).exp();
+                sum = v.mul_add(weight, sum);
+                weights += weight;
+            }
+            out.push(sum / weights);
+        }
+    }
+    out
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn non_local_means(values: &[f32], width: usize, height: usize, noise: f32) -> Vec<f32> {
+    if noise <= 0.0 {
+        return values.to_vec();
+    }
+
+    let (p, s) = (NLM_PATCH_RADIUS, NLM_SEARCH_RADIUS);
+    let margin = p + s;
+    let patch_len = ((2 * p + 1) * (2 * p + 1)) as f32;
+    let h2 = (NLM_STRENGTH * noise).powi(2);
+    // Patch distances are corrected for the noise both patches carry.
+    let bias = 2.0 * noise * noise;
+
+    let padded = Padded::new(values, width, height, margin);
+    let mut out = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (cx, cy) = (x + margin, y + margin);
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for sy in cy - s..=cy + s {
+                for sx in cx - s..=cx + s {
+                    let mut distance = 0.0;
+                    for dy in 0..=2 * p {
+                        for dx in 0..=2 * p {
+                            let a = padded.at(cx - p + dx, cy - p + dy);
+                            let b = padded.at(sx - p + dx, sy - p + dy);
+                            distance = (a - b).mul_add(a - b, distance);
+                        }
+                    }
+                    let excess = (distance / patch_len - bias).max(0.0);
+                    let weight = (-excess / h2).exp();
+                    sum = padded.at(sx, sy).mul_add(weight, sum);
+                    weights += weight;
+                }
+            }
+            out.push(sum / weights);
+        }
+    }
+    out
+}
+
+/// Robust noise sigma from horizontal neighbor differences.
+///
+/// Uses the median absolute difference (scaled for Gaussian noise) so edges
+/// barely affect it. Exactly flat runs, such as padding outside the field of
+/// view, are ignored.
+fn noise_sigma(values: &[f32], width: usize) -> f32 {
+    let mut differences: Vec<f32> = values
+        .chunks_exact(width)
+        .flat_map(|row| row.windows(2).map(|pair| (pair[1] - pair[0]).abs()))
+        .filter(|&d| d > 0.0)
+        .collect();
+    if differences.is_empty() {
+        return 0.0;
+    }
+    let mid = differences.len() / 2;
+    let (_, median, _) = differences.select_nth_unstable_by(mid, f32::total_cmp);
+    // 1.4826 turns a median absolute deviation into a Gaussian sigma; the
+    // difference of two noisy pixels has sqrt(2) times the noise.
+    *median * 1.4826 / std::f32::consts::SQRT_2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // =========================================================================
+    // Denoising Tests
+    // =========================================================================
+
+    mod denoising {
+        use super::*;
+
+        const FILTERS: [Denoise; 3] = [Denoise::Median, Denoise::Bilateral, Denoise::Nlm];
+
+        /// 16x16 step edge (0 left, 100 right) with deterministic +-10 noise.
+        fn noisy_edge() -> (Vec<f32>, usize, usize) {
+            let (width, height) = (16_u16, 16_u16);
+            let values = (0..width * height)
+                .map(|i| {
+                    let level = if i % width < width / 2 { 0.0 } else { 100.0 };
+                    let noise = f32::from((i * 37 + 13) % 21) - 10.0;
+                    level + noise
+                })
+                .collect();
+            (values, usize::from(width), usize::from(height))
+        }
+
+        fn variance(values: impl Iterator<Item = f32> + Clone) -> f32 {
+            let count = values.clone().count();
+            let mean = values.clone().sum::<f32>() / f32::from(u16::try_from(count).unwrap());
+            values.map(|v| (v - mean).powi(2)).sum::<f32>()
+                / f32::from(u16::try_from(count).unwrap())
+        }
+
+        /// Left half, away from the edge.
+        fn flat_region(values: &[f32], width: usize) -> impl Iterator<Item = f32> + Clone + '_ {
+            values
+                .chunks_exact(width)
+                .flat_map(move |row| row[..width / 2 - 3].iter().copied())
+        }
+
+        #[test]
+        fn every_filter_reduces_noise() {
+            let (values, w, h) = noisy_edge();
+            let before = variance(flat_region(&values, w));
+            for filter in FILTERS {
+                let out = denoise(filter, &values, w, h);
+                let after = variance(flat_region(&out, w));
+                assert!(after < before / 2.0, "{filter:?}: {before} -> {after}");
+            }
+        }
+
+        #[test]
+        fn every_filter_keeps_the_edge() {
+            let (values, w, h) = noisy_edge();
+            for filter in FILTERS {
+                let out = denoise(filter, &values, w, h);
+                let row = &out[8 * w..9 * w];
+                assert!(row[w / 2 - 1] < 30.0, "{filter:?}: {row:?}");
+                assert!(row[w / 2] > 70.0, "{filter:?}: {row:?}");
+            }
+        }
+
+        #[test]
+        fn median_removes_impulse() {
+            let mut values = vec![10.0; 25];
+            values[12] = 5000.0;
+            let out = denoise(Denoise::Median, &values, 5, 5);
+            assert!(out.iter().all(|&v| (v - 10.0).abs() < f32::EPSILON));
+        }
+
+        #[test]
+        fn uniform_slice_is_unchanged() {
+            let values = vec![-1000.0; 36];
+            for filter in FILTERS {
+                let out = denoise(filter, &values, 6, 6);
+                assert!(
+                    out.iter().all(|&v| (v + 1000.0).abs() < 1e-3),
+                    "{filter:?}: {out:?}"
+                );
+            }
+        }
+
+        #[test]
+        fn noise_estimate_ignores_flat_padding() {
+            let mut values = vec![-2000.0; 64];
+            values.extend((0_u16..64).map(|i| if i % 2 == 0 { 10.0 } else { -10.0 }));
+            let sigma = noise_sigma(&values, 8);
+            assert!((sigma - 20.0 * 1.4826 / std::f32::consts::SQRT_2).abs() < 1e-3);
+        }
+    }
+
+    // =========================================================================
+    // Sharpening Tests
+    // =========================================================================
+
+    mod sharpening {
+        use super::*;
+
+        #[test]
+        fn zero_amount_is_identity() {
+            let values: Vec<f32> = (0_u16..25).map(f32::from).collect();
+            let out = unsharp_mask(&values, 5, 5, 0.0);
+            assert!(out.iter().zip(&values).all(|(a, b)| (a - b).abs() < 1e-4));
+        }
+
+        #[test]
+        fn uniform_slice_is_unchanged() {
+            let out = unsharp_mask(&[40.0; 36], 6, 6, 2.0);
+            assert!(out.iter().all(|&v| (v - 40.0).abs() < 1e-3), "{out:?}");
+        }
+
+        #[test]
+        fn step_edge_gains_contrast() {
+            let (width, height) = (10, 3);
+            let values: Vec<f32> = (0..width * height)
+                .map(|i| if i % width < width / 2 { 0.0 } else { 100.0 })
+                .collect();
+            let out = unsharp_mask(&values, width, height, 1.0);
+            let row = &out[width..2 * width];
+            assert!(row[width / 2 - 1] < 0.0, "dark side undershoots: {row:?}");
+            assert!(row[width / 2] > 100.0, "bright side overshoots: {row:?}");
+            assert!(row[0].abs() < 1e-3, "flat area is untouched: {row:?}");
+        }
+
+        #[test]
+        fn blur_preserves_mean() {
+            let values: Vec<f32> = (0_u16..64).map(|i| f32::from(i % 7)).collect();
+            let blurred = gaussian_blur(&values, 8, 8);
+            let mean = |v: &[f32]| v.iter().sum::<f32>() / 64.0;
+            assert!((mean(&blurred) - mean(&values)).abs() < 1.0);
+        }
+    }
+}
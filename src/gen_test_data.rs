@@ -0,0 +1,297 @@
+//! Synthetic DICOM series (`gen-test-data`).
+//!
+//! Writes small CT series of made-up phantoms into one folder, as a scanner
+//! export would: a gradient, whose HU change smoothly across the plane and
+//! from slice to slice, and a bright sphere in air, which `convert stl`
+//! turns into a ball. Every file is a complete CT image (geometry, rescale,
+//! window, UIDs), so all commands accept them, and the same options always
+//! write the same bytes. Nothing in them comes from a patient.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::{tags, uids};
+use dicom::object::{FileMetaTableBuilder, InMemDicomObject};
+
+use crate::i18n::t;
+
+/// Added to HU to get stored values (`RescaleIntercept` is its negative).
+const HU_OFFSET: f64 = 1024.0;
+
+/// Start of every UID written, under the UUID-derived `2.25` root.
+const UID_ROOT: &str = "2.25.2949531722304108238871905313911";
+
+/// CLI arguments for the `gen-test-data` subcommand.
+#[derive(Args, Debug)]
+pub struct GenTestDataArgs {
+    /// Folder to write the .dcm files into (created if missing)
+    #[arg(long = "out")]
+    pub output: PathBuf,
+
+    /// Phantoms to write, one series each
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Phantom::Gradient, Phantom::Sphere])]
+    pub phantoms: Vec<Phantom>,
+
+    /// Columns and rows of every slice
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u16).range(8..=1024))]
+    pub size: u16,
+
+    /// Slices per series
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(2..=1024))]
+    pub slices: u16,
+}
+
+/// What a synthetic series shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Phantom {
+    /// HU rising from -1000 to 1000 across the plane, shifted slice by slice
+    Gradient,
+    /// A 400 HU sphere, a third of the field across, in -1000 HU air
+    Sphere,
+}
+
+impl Phantom {
+    /// File name stem and `SeriesDescription`.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Gradient => "gradient",
+            Self::Sphere => "sphere",
+        }
+    }
+
+    /// HU at voxel `(x, y, z)` of a `size` x `size` x `slices` grid.
+    #[allow(clippy::cast_precision_loss)]
+    fn hu(self, x: usize, y: usize, z: usize, size: usize, slices: usize) -> f64 {
+        match self {
+            Self::Gradient => {
+                let plane = (x + y) as f64 / (2 * (size - 1)) as f64;
+                let depth = z as f64 / (slices - 1) as f64;
+                -1000.0 + 2000.0 * (0.8 * plane + 0.2 * depth)
+            }
+            Self::Sphere => {
+                let center = |n: usize| (n as f64 - 1.0) / 2.0;
+                let distance = (x as f64 - center(size))
+                    .hypot(y as f64 - center(size))
+                    .hypot(z as f64 - center(slices));
+                if distance <= size.min(slices) as f64 / 3.0 {
+                    400.0
+                } else {
+                    -1000.0
+                }
+            }
+        }
+    }
+}
+
+/// One series to write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Series {
+    pub phantom: Phantom,
+    /// `SeriesNumber`, also part of the series' UIDs.
+    pub number: u16,
+    pub size: u16,
+    pub slices: u16,
+}
+
+impl Series {
+    /// Write the slices into `dir` as `<phantom>_0001.dcm`, ...; their paths.
+    pub fn write(self, dir: &Path) -> Result<Vec<PathBuf>> {
+        (1..=self.slices)
+            .map(|instance| {
+                let path = dir.join(format!("{}_{instance:04}.dcm", self.phantom.name()));
+                self.slice(instance)
+                    .write_to_file(&path)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// Slice `instance` (1-based, feet to head) as a CT image file.
+    fn slice(self, instance: u16) -> dicom::object::DefaultDicomObject {
+        let (size, slices) = (usize::from(self.size), usize::from(self.slices));
+        let z = usize::from(instance - 1);
+        let pixels: Vec<u8> = (0..size * size)
+            .flat_map(|i| {
+                let hu = self.phantom.hu(i % size, i / size, z, size, slices);
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let stored = (hu + HU_OFFSET).round().clamp(0.0, 4095.0) as u16;
+                stored.to_le_bytes()
+            })
+            .collect();
+        let half = f64::from(self.size) / 2.0;
+        let location = f64::from(instance - 1);
+        let sop_instance = uid(&[1, self.number, instance]);
+
+        let mut obj = InMemDicomObject::new_empty();
+        let mut put = |tag, vr, value: PrimitiveValue| obj.put(DataElement::new(tag, vr, value));
+        put(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE.into());
+        put(tags::SOP_INSTANCE_UID, VR::UI, sop_instance.as_str().into());
+        put(tags::STUDY_DATE, VR::DA, "20240101".into());
+        put(tags::STUDY_TIME, VR::TM, "120000".into());
+        put(tags::MODALITY, VR::CS, "CT".into());
+        put(tags::MANUFACTURER, VR::LO, "dcm-toolbox".into());
+        put(tags::STUDY_DESCRIPTION, VR::LO, "Synthetic phantoms".into());
+        put(tags::SERIES_DESCRIPTION, VR::LO, self.phantom.name().into());
+        put(tags::PATIENT_NAME, VR::PN, "Phantom^Test".into());
+        put(tags::PATIENT_ID, VR::LO, "PHANTOM".into());
+        put(tags::SLICE_THICKNESS, VR::DS, "1".into());
+        put(tags::STUDY_INSTANCE_UID, VR::UI, uid(&[0]).as_str().into());
+        put(
+            tags::SERIES_INSTANCE_UID,
+            VR::UI,
+            uid(&[1, self.number]).as_str().into(),
+        );
+        put(
+            tags::SERIES_NUMBER,
+            VR::IS,
+            self.number.to_string().as_str().into(),
+        );
+        put(tags::ACQUISITION_NUMBER, VR::IS, "1".into());
+        put(
+            tags::INSTANCE_NUMBER,
+            VR::IS,
+            instance.to_string().as_str().into(),
+        );
+        put(
+            tags::IMAGE_POSITION_PATIENT,
+            VR::DS,
+            format!("{}\\{}\\{location}", -half, -half).as_str().into(),
+        );
+        put(
+            tags::IMAGE_ORIENTATION_PATIENT,
+            VR::DS,
+            "1\\0\\0\\0\\1\\0".into(),
+        );
+        put(
+            tags::FRAME_OF_REFERENCE_UID,
+            VR::UI,
+            uid(&[2]).as_str().into(),
+        );
+        put(
+            tags::SLICE_LOCATION,
+            VR::DS,
+            location.to_string().as_str().into(),
+        );
+        put(tags::SAMPLES_PER_PIXEL, VR::US, 1_u16.into());
+        put(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            "MONOCHROME2".into(),
+        );
+        put(tags::ROWS, VR::US, self.size.into());
+        put(tags::COLUMNS, VR::US, self.size.into());
+        put(tags::PIXEL_SPACING, VR::DS, "1\\1".into());
+        put(tags::BITS_ALLOCATED, VR::US, 16_u16.into());
+        put(tags::BITS_STORED, VR::US, 12_u16.into());
+        put(tags::HIGH_BIT, VR::US, 11_u16.into());
+        put(tags::PIXEL_REPRESENTATION, VR::US, 0_u16.into());
+        put(tags::WINDOW_CENTER, VR::DS, "0".into());
+        put(tags::WINDOW_WIDTH, VR::DS, "2000".into());
+        put(tags::RESCALE_INTERCEPT, VR::DS, "-1024".into());
+        put(tags::RESCALE_SLOPE, VR::DS, "1".into());
+        put(tags::RESCALE_TYPE, VR::LO, "HU".into());
+        put(tags::PIXEL_DATA, VR::OW, PrimitiveValue::from(pixels));
+
+        let meta = FileMetaTableBuilder::new()
+            .transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN)
+            .media_storage_sop_class_uid(uids::CT_IMAGE_STORAGE)
+            .media_storage_sop_instance_uid(sop_instance)
+            .build()
+            .expect("the file meta group has every required attribute");
+        obj.with_exact_meta(meta)
+    }
+}
+
+/// Fixed UID below [`UID_ROOT`]: `0` for the study, `1.<series>` for a
+/// series, `1.<series>.<instance>` for an image, `2` for the frame of
+/// reference.
+fn uid(parts: &[u16]) -> String {
+    let parts: Vec<String> = parts.iter().map(ToString::to_string).collect();
+    format!("{UID_ROOT}.{}", parts.join("."))
+}
+
+/// Write the requested phantoms into `--out`, one series each.
+pub fn run(args: &GenTestDataArgs) -> Result<()> {
+    fs::create_dir_all(&args.output)
+        .with_context(|| format!("Failed to create output folder: {}", args.output.display()))?;
+    let mut files = 0;
+    let mut phantoms: Vec<Phantom> = Vec::new();
+    for phantom in &args.phantoms {
+        if !phantoms.contains(phantom) {
+            phantoms.push(*phantom);
+        }
+    }
+    for (number, phantom) in (1..).zip(phantoms.iter().copied()) {
+        let series = Series {
+            phantom,
+            number,
+            size: args.size,
+            slices: args.slices,
+        };
+        files += series.write(&args.output)?.len();
+    }
+    println!(
+        "{}",
+        t!(
+            "gen-test-data-saved",
+            count = files,
+            series = phantoms.len(),
+            path = args.output.display().to_string()
+        )
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume;
+
+    #[test]
+    fn uids_are_fixed_and_distinct() {
+        let study = uid(&[0]);
+        let series = uid(&[1, 2]);
+        let image = uid(&[1, 2, 10]);
+        assert!(study.starts_with("2.25.") && study.len() <= 64);
+        assert_eq!(image, format!("{series}.10"));
+        assert_ne!(study, uid(&[2]));
+        assert!(
+            image
+                .split('.')
+                .all(|part| part == "0" || !part.starts_with('0'))
+        );
+    }
+
+    #[test]
+    fn series_load_as_volumes_in_hu() {
+        let dir = tempfile::tempdir().unwrap();
+        let series = Series {
+            phantom: Phantom::Sphere,
+            number: 1,
+            size: 16,
+            slices: 9,
+        };
+        let files = series.write(dir.path()).unwrap();
+        assert_eq!(files.len(), 9);
+
+        let (_, volume) = volume::load_series(dir.path(), 2, false).unwrap();
+        assert_eq!((volume.cols, volume.rows, volume.slices), (16, 16, 9));
+        // Air around the sphere, bone-like inside
+        assert_eq!(volume.value_range(), (-1000.0, 400.0));
+        let center = volume.center();
+        assert_eq!(volume.nearest(center), Some(400.0));
+    }
+
+    #[test]
+    fn gradients_span_the_hu_range() {
+        let (size, slices) = (8, 4);
+        let hu = |x, y, z| Phantom::Gradient.hu(x, y, z, size, slices);
+        assert!((hu(0, 0, 0) + 1000.0).abs() < 1e-9);
+        assert!((hu(7, 7, 3) - 1000.0).abs() < 1e-9);
+        assert!(hu(3, 0, 0) < hu(4, 0, 0) && hu(0, 0, 1) < hu(0, 0, 2));
+    }
+}
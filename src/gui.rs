@@ -0,0 +1,659 @@
+//! Desktop window (`gui`, built with the `gui` cargo feature).
+//!
+//! For people who will never open a terminal: drop a folder of DICOM files
+//! on the window (or pass `--in`), look at a thumbnail of every series, set
+//! the output folder, window, frame rate, or threshold, and convert with a
+//! progress bar, pausing or cancelling if needed. A conversion is the same
+//! `convert` the command line runs, with the settings turned into its
+//! arguments, on a worker thread; its progress arrives as [`Event`]s over a
+//! channel.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Args, Parser};
+use eframe::egui::{self, ColorImage, TextureHandle, TextureOptions};
+
+use crate::cancel::CancellationToken;
+use crate::convert;
+use crate::events::{self, Event, Stage};
+use crate::i18n::t;
+use crate::meta::{InstanceMeta, SeriesMeta};
+use crate::outcome::Status;
+use crate::pipeline::{self, RenderOptions};
+use crate::pixel::{DecodedFrame, Window};
+use crate::utils;
+
+/// Longest edge of series thumbnails, in pixels.
+const THUMBNAIL_SIZE: u32 = 128;
+
+/// How often the window checks on a running load or conversion.
+const POLL: Duration = Duration::from_millis(100);
+
+/// Newest failures kept in the log below the series.
+const MAX_LOG_LINES: usize = 200;
+
+/// CLI arguments for the `gui` subcommand.
+#[derive(Args, Debug)]
+pub struct GuiArgs {
+    /// Folder of DICOM (.dcm) files to open right away
+    #[arg(long = "in")]
+    pub input: Option<PathBuf>,
+}
+
+/// What the window converts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Jpeg,
+    Video,
+    Stl,
+}
+
+/// The choices on the settings panel.
+#[derive(Debug, Clone, PartialEq)]
+struct Settings {
+    output: String,
+    format: Format,
+    /// Window with [`Self::window`] instead of each file's own.
+    custom_window: bool,
+    window: Window,
+    fps: u32,
+    /// Pick the STL iso-level with Otsu instead of [`Self::threshold`].
+    auto_threshold: bool,
+    threshold: f32,
+    /// Replace series folders already in the output (`--force`).
+    force: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            output: String::new(),
+            format: Format::Jpeg,
+            custom_window: false,
+            window: Window {
+                center: 40.0,
+                width: 400.0,
+            },
+            fps: 10,
+            auto_threshold: true,
+            threshold: 300.0,
+            force: false,
+        }
+    }
+}
+
+impl Settings {
+    /// The window images render with, if not each file's own.
+    fn window(&self) -> Option<Window> {
+        (self.custom_window && self.format != Format::Stl).then_some(self.window)
+    }
+
+    /// Command line converting `input` with these settings.
+    fn args(&self, input: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = ["dcm-toolbox", "convert", "--in"]
+            .map(OsString::from)
+            .into();
+        args.push(input.into());
+        args.extend(["--out".into(), self.output.clone().into()]);
+        if self.force {
+            args.push("--force".into());
+        }
+        match self.format {
+            Format::Jpeg => args.push("jpeg".into()),
+            Format::Video => {
+                args.extend(["video".into(), "--fps".into()]);
+                args.push(self.fps.to_string().into());
+            }
+            Format::Stl => {
+                args.push("stl".into());
+                if !self.auto_threshold {
+                    args.push("--iso-level".into());
+                    args.push(self.threshold.to_string().into());
+                }
+            }
+        }
+        args
+    }
+
+    /// Convert command for `input` with these settings, stopped or paused
+    /// through `token`; the window is set on the parsed command, as it is not
+    /// a command-line option.
+    fn command(
+        &self,
+        input: &Path,
+        token: &CancellationToken,
+    ) -> clap::error::Result<crate::CliArgs> {
+        let mut cli = crate::CliArgs::try_parse_from(self.args(input))?;
+        if let crate::Commands::Convert { shared, .. } = &mut cli.command {
+            shared.window = self.window();
+            shared.token = token.clone();
+        }
+        Ok(cli)
+    }
+}
+
+/// A series of the open folder, with the middle slice for its thumbnail.
+struct Preview {
+    number: Option<i64>,
+    series: SeriesMeta,
+    frame: Option<DecodedFrame>,
+}
+
+/// Group the DICOM files of `input` by `SeriesNumber`, as `convert` does by
+/// default, and decode the middle slice of each.
+fn read_series(input: &Path) -> Result<Vec<Preview>> {
+    utils::validate_input_folder(input)?;
+    let mut groups: BTreeMap<Option<i64>, Vec<InstanceMeta>> = BTreeMap::new();
+    for path in utils::list_dcm_files(input, false)? {
+        let meta = InstanceMeta::read(&path);
+        groups.entry(meta.series_number).or_default().push(meta);
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(number, instances)| {
+            let series = SeriesMeta::new(convert::sort_by_position(instances));
+            let frame = series
+                .instances
+                .get(series.instances.len() / 2)
+                .and_then(|middle| pipeline::load_frame(&middle.path, 0).ok());
+            Preview {
+                number,
+                series,
+                frame,
+            }
+        })
+        .collect())
+}
+
+/// `frame` rendered with `window` and shrunk to a thumbnail.
+fn thumbnail(frame: &DecodedFrame, window: Option<Window>) -> ColorImage {
+    let options = RenderOptions {
+        window,
+        ..RenderOptions::default()
+    };
+    let image = pipeline::render_frame(frame.clone(), options)
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .into_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    ColorImage::from_rgba_unmultiplied(size, image.as_raw())
+}
+
+/// How far a conversion has come, from its events.
+#[derive(Debug, Default, PartialEq)]
+struct Progress {
+    series: usize,
+    series_done: usize,
+    /// Files of the series in progress, and how many of them are done.
+    files: usize,
+    files_done: usize,
+}
+
+impl Progress {
+    const fn new(series: usize) -> Self {
+        Self {
+            series,
+            series_done: 0,
+            files: 0,
+            files_done: 0,
+        }
+    }
+
+    fn on(&mut self, event: &Event) {
+        match event {
+            Event::Series {
+                stage: Stage::Started { files, .. },
+                ..
+            } => (self.files, self.files_done) = (*files, 0),
+            Event::Series {
+                stage: Stage::Finished | Stage::Failed { .. },
+                ..
+            } => {
+                self.series_done += 1;
+                (self.files, self.files_done) = (0, 0);
+            }
+            Event::FileFinished { .. } => self.files_done = (self.files_done + 1).min(self.files),
+            Event::Series { .. } | Event::FileStarted { .. } | Event::FileFailed { .. } => {}
+        }
+    }
+
+    /// Share of the run done, from 0 to 1.
+    #[allow(clippy::cast_precision_loss)]
+    fn fraction(&self) -> f32 {
+        if self.series == 0 {
+            return 0.0;
+        }
+        let current = if self.files == 0 {
+            0.0
+        } else {
+            self.files_done as f32 / self.files as f32
+        };
+        ((self.series_done as f32 + current) / self.series as f32).min(1.0)
+    }
+}
+
+/// A conversion running on its worker thread.
+struct Job {
+    worker: JoinHandle<Result<Status>>,
+    progress: Progress,
+    /// Cancels or pauses this job only.
+    token: CancellationToken,
+}
+
+/// A series shown in the window.
+struct Shown {
+    preview: Preview,
+    texture: Option<TextureHandle>,
+}
+
+struct App {
+    input: String,
+    loading: Option<JoinHandle<Result<Vec<Preview>>>>,
+    series: Vec<Shown>,
+    /// Window the thumbnails were rendered with.
+    thumbnails_window: Option<Window>,
+    settings: Settings,
+    events: Receiver<Event>,
+    job: Option<Job>,
+    /// How the last load or conversion ended.
+    status: Option<String>,
+    log: Vec<String>,
+}
+
+impl App {
+    fn new(input: Option<PathBuf>, events: Receiver<Event>) -> Self {
+        let mut app = Self {
+            input: String::new(),
+            loading: None,
+            series: Vec::new(),
+            thumbnails_window: None,
+            settings: Settings::default(),
+            events,
+            job: None,
+            status: None,
+            log: Vec::new(),
+        };
+        if let Some(input) = input {
+            app.open(input);
+        }
+        app
+    }
+
+    /// Start reading the series of `input`, with an output folder beside it.
+    fn open(&mut self, input: PathBuf) {
+        if self.job.is_some() {
+            return;
+        }
+        let name = input.file_name().map_or_else(
+            || "export".to_string(),
+            |name| format!("{}_export", name.to_string_lossy()),
+        );
+        self.settings.output = input.with_file_name(name).display().to_string();
+        self.input = input.display().to_string();
+        self.series.clear();
+        self.status = None;
+        self.loading = Some(thread::spawn(move || read_series(&input)));
+    }
+
+    /// Take in dropped folders, finished loads and conversions, and events.
+    fn poll(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.first().and_then(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            self.open(path);
+        }
+
+        if self.loading.as_ref().is_some_and(JoinHandle::is_finished) {
+            let loaded = self.loading.take().map(JoinHandle::join);
+            match loaded {
+                Some(Ok(Ok(previews))) => {
+                    if previews.is_empty() {
+                        self.status = Some(t!("gui-no-series"));
+                    }
+                    self.series = previews
+                        .into_iter()
+                        .map(|preview| Shown {
+                            preview,
+                            texture: None,
+                        })
+                        .collect();
+                    self.render_thumbnails(ctx);
+                }
+                Some(Ok(Err(e))) => self.status = Some(format!("{e:#}")),
+                Some(Err(_)) | None => self.status = Some(t!("gui-no-series")),
+            }
+        }
+        if self.thumbnails_window != self.settings.window() {
+            self.render_thumbnails(ctx);
+        }
+
+        while let Ok(event) = self.events.try_recv() {
+            let failure = match &event {
+                Event::FileFailed { path, error } => {
+                    Some(format!("{}: {error}", pipeline::display_name(path)))
+                }
+                Event::Series {
+                    stage: Stage::Failed { key, error },
+                    ..
+                } => Some(format!("{key}: {error}")),
+                _ => None,
+            };
+            if let Some(line) = failure {
+                if self.log.len() == MAX_LOG_LINES {
+                    self.log.remove(0);
+                }
+                self.log.push(line);
+            }
+            if let Some(job) = &mut self.job {
+                job.progress.on(&event);
+            }
+        }
+
+        if self
+            .job
+            .as_ref()
+            .is_some_and(|job| job.worker.is_finished())
+        {
+            let ended = self.job.take().map(|job| job.worker.join());
+            self.status = Some(match ended {
+                Some(Ok(Ok(status))) => t!(
+                    "gui-finished",
+                    status = status.label(),
+                    path = self.settings.output.as_str()
+                ),
+                Some(Ok(Err(e))) => t!("gui-failed", error = format!("{e:#}")),
+                Some(Err(_)) | None => t!("gui-failed", error = "panic"),
+            });
+        }
+    }
+
+    fn render_thumbnails(&mut self, ctx: &egui::Context) {
+        let window = self.settings.window();
+        for shown in &mut self.series {
+            shown.texture = shown.preview.frame.as_ref().map(|frame| {
+                let name = format!("series-{:?}", shown.preview.number);
+                ctx.load_texture(name, thumbnail(frame, window), TextureOptions::default())
+            });
+        }
+        self.thumbnails_window = window;
+    }
+
+    /// Why the conversion cannot start yet, if it cannot.
+    fn blocker(&self) -> Option<String> {
+        if self.loading.is_some() || self.series.is_empty() {
+            return Some(t!("gui-open-hint"));
+        }
+        let output = PathBuf::from(self.settings.output.trim());
+        if output.as_os_str().is_empty() {
+            return Some(t!("gui-output-missing"));
+        }
+        let occupied = output.is_dir() && !utils::is_folder_empty(&output).unwrap_or(false);
+        (occupied && !self.settings.force).then(|| t!("gui-output-not-empty"))
+    }
+
+    fn start(&mut self) {
+        let token = CancellationToken::default();
+        let command = self.settings.command(Path::new(&self.input), &token);
+        let worker = thread::spawn(move || crate::run(command?));
+        self.log.clear();
+        self.status = None;
+        self.job = Some(Job {
+            worker,
+            progress: Progress::new(self.series.len()),
+            token,
+        });
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        let running = self.job.is_some();
+        ui.heading(t!("gui-input"));
+        ui.label(t!("gui-drop-hint"));
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.input);
+            if ui
+                .add_enabled(!running, egui::Button::new(t!("gui-open")))
+                .clicked()
+            {
+                self.open(PathBuf::from(self.input.trim()));
+            }
+        });
+        ui.separator();
+
+        ui.add_enabled_ui(!running, |ui| {
+            let settings = &mut self.settings;
+            ui.heading(t!("gui-output"));
+            ui.text_edit_singleline(&mut settings.output);
+            ui.checkbox(&mut settings.force, t!("gui-force"));
+            ui.separator();
+
+            ui.heading(t!("gui-format"));
+            ui.radio_value(&mut settings.format, Format::Jpeg, t!("gui-format-jpeg"));
+            ui.radio_value(&mut settings.format, Format::Video, t!("gui-format-video"));
+            ui.radio_value(&mut settings.format, Format::Stl, t!("gui-format-stl"));
+            ui.separator();
+
+            if settings.format == Format::Stl {
+                ui.checkbox(&mut settings.auto_threshold, t!("gui-auto-threshold"));
+                ui.add_enabled_ui(!settings.auto_threshold, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(t!("gui-threshold"));
+                        ui.add(egui::DragValue::new(&mut settings.threshold).speed(5.0));
+                    });
+                });
+            } else {
+                ui.checkbox(&mut settings.custom_window, t!("gui-custom-window"));
+                ui.add_enabled_ui(settings.custom_window, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(t!("gui-window-center"));
+                        ui.add(egui::DragValue::new(&mut settings.window.center).speed(5.0));
+                        ui.label(t!("gui-window-width"));
+                        ui.add(
+                            egui::DragValue::new(&mut settings.window.width)
+                                .speed(5.0)
+                                .range(1.0..=f64::MAX),
+                        );
+                    });
+                });
+            }
+            if settings.format == Format::Video {
+                ui.horizontal(|ui| {
+                    ui.label(t!("gui-fps"));
+                    ui.add(egui::DragValue::new(&mut settings.fps).range(1..=120));
+                });
+            }
+        });
+    }
+
+    fn run_ui(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(4.0);
+        if let Some(job) = &self.job {
+            let token = &job.token;
+            let text = t!(
+                "gui-progress",
+                done = job.progress.series_done,
+                total = job.progress.series
+            );
+            ui.add(egui::ProgressBar::new(job.progress.fraction()).text(text));
+            ui.horizontal(|ui| {
+                if token.is_paused() {
+                    if ui.button(t!("gui-resume")).clicked() {
+                        token.resume();
+                    }
+                } else if ui.button(t!("gui-pause")).clicked() {
+                    token.pause();
+                }
+                if ui.button(t!("gui-cancel")).clicked() {
+                    token.cancel();
+                }
+            });
+        } else {
+            let blocker = self.blocker();
+            ui.horizontal(|ui| {
+                let convert = egui::Button::new(t!("gui-convert"));
+                if ui.add_enabled(blocker.is_none(), convert).clicked() {
+                    self.start();
+                }
+                if let Some(blocker) = &blocker {
+                    ui.label(blocker);
+                }
+            });
+        }
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+        ui.add_space(4.0);
+    }
+
+    fn series_ui(&self, ui: &mut egui::Ui) {
+        if self.loading.is_some() {
+            ui.spinner();
+            ui.label(t!("gui-loading"));
+            return;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for shown in &self.series {
+                    ui.vertical(|ui| {
+                        ui.set_width(THUMBNAIL_SIZE as f32);
+                        match &shown.texture {
+                            Some(texture) => ui.image(texture),
+                            None => ui.label("?"),
+                        };
+                        let series = &shown.preview.series;
+                        ui.label(t!(
+                            "gui-series",
+                            number = shown
+                                .preview
+                                .number
+                                .map_or_else(|| "?".to_string(), |n| n.to_string()),
+                            description = series.description().unwrap_or_default(),
+                            count = series.instances.len()
+                        ));
+                    });
+                }
+            });
+            if !self.log.is_empty() {
+                ui.separator();
+                for line in &self.log {
+                    ui.label(line);
+                }
+            }
+        });
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll(ctx);
+        egui::SidePanel::left("settings").show(ctx, |ui| self.settings_ui(ui));
+        egui::TopBottomPanel::bottom("run").show(ctx, |ui| self.run_ui(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.series_ui(ui));
+        if self.loading.is_some() || self.job.is_some() {
+            ctx.request_repaint_after(POLL);
+        }
+    }
+}
+
+/// Open the window and block until it is closed.
+pub fn run(args: &GuiArgs) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    events::listen(sender);
+    let app = App::new(args.input.clone(), receiver);
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title(t!("gui-title"))
+            .with_inner_size([960.0, 640.0]),
+        ..eframe::NativeOptions::default()
+    };
+    eframe::run_native("dcm-toolbox", options, Box::new(|_| Ok(Box::new(app))))
+        .map_err(|e| anyhow::anyhow!("Failed to open the window: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::convert::ConvertFormat;
+    use crate::{CliArgs, Commands};
+
+    fn parse(settings: &Settings) -> CliArgs {
+        settings
+            .command(Path::new("/scans/ct"), &CancellationToken::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn settings_become_a_convert_command() {
+        let settings = Settings {
+            output: "/scans/out".to_string(),
+            format: Format::Video,
+            custom_window: true,
+            fps: 24,
+            force: true,
+            ..Settings::default()
+        };
+        let Commands::Convert { mut shared, format } = parse(&settings).command else {
+            panic!("not a convert command");
+        };
+        shared.resolve_output().unwrap();
+        assert_eq!(shared.input, PathBuf::from("/scans/ct"));
+        assert_eq!(shared.output, PathBuf::from("/scans/out"));
+        assert!(shared.force);
+        assert_eq!(shared.window, Some(settings.window));
+        assert!(matches!(format, ConvertFormat::Video { fps: Some(24), .. }));
+    }
+
+    #[test]
+    fn meshes_ignore_the_window() {
+        let settings = Settings {
+            output: "out".to_string(),
+            format: Format::Stl,
+            custom_window: true,
+            auto_threshold: false,
+            threshold: 150.0,
+            ..Settings::default()
+        };
+        let Commands::Convert { shared, format } = parse(&settings).command else {
+            panic!("not a convert command");
+        };
+        assert_eq!(shared.window, None);
+        assert!(matches!(
+            format,
+            ConvertFormat::Stl {
+                iso_level: Some(150.0),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn progress_counts_series_and_their_files() {
+        let series = PathBuf::from("out/1");
+        let mut progress = Progress::new(2);
+        progress.on(&Event::Series {
+            series: series.clone(),
+            stage: Stage::Started {
+                key: "1".to_string(),
+                files: 4,
+            },
+        });
+        for _ in 0..2 {
+            progress.on(&Event::FileFinished {
+                path: PathBuf::from("a.dcm"),
+                frames: 1,
+            });
+        }
+        assert!((progress.fraction() - 0.25).abs() < f32::EPSILON);
+        progress.on(&Event::Series {
+            series,
+            stage: Stage::Finished,
+        });
+        assert!((progress.fraction() - 0.5).abs() < f32::EPSILON);
+        assert_eq!(Progress::new(0).fraction(), 0.0);
+    }
+}
@@ -0,0 +1,471 @@
+//! Checksums of source files (`hash`).
+//!
+//! Hashes every `.dcm` file below a folder and writes the checksums as a
+//! manifest in the `sha256sum`/`md5sum` format, with paths relative to the
+//! folder. Given a manifest instead, it checks the files against it, so a
+//! transfer from an imaging core can be validated before it is converted.
+//!
+//! Each study also gets a checksum of its own: the hash of its files'
+//! checksums in sorted order. It depends on the content of the files only,
+//! not on their names or layout, so two copies of a study can be compared
+//! even after one of them was reorganized.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::cancel;
+use crate::i18n::t;
+use crate::inventory;
+use crate::outcome::BadInput;
+use crate::throttle;
+use crate::utils::validate_input_folder;
+
+/// Study of files without a readable `StudyInstanceUID`.
+const UNKNOWN: &str = "unknown";
+
+/// CLI arguments for the `hash` subcommand.
+#[derive(Args, Debug)]
+pub struct HashArgs {
+    /// Folder to hash; .dcm files in all of its subfolders are included
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Write the file checksums to this manifest
+    #[arg(long = "out", conflicts_with = "verify")]
+    pub output: Option<PathBuf>,
+
+    /// Check the files against this manifest instead of listing checksums
+    #[arg(long, value_name = "MANIFEST")]
+    pub verify: Option<PathBuf>,
+
+    /// Checksum algorithm, for both writing and verifying
+    #[arg(long, value_enum, default_value_t = Algorithm::Sha256)]
+    pub algorithm: Algorithm,
+
+    /// Include symlinked .dcm files and folders (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Checksum algorithm.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    /// SHA-256 (`sha256sum`)
+    #[default]
+    Sha256,
+    /// MD5 (`md5sum`), as many older manifests use
+    Md5,
+}
+
+impl Algorithm {
+    /// Hex digits of one checksum.
+    const fn hex_len(self) -> usize {
+        match self {
+            Self::Sha256 => 64,
+            Self::Md5 => 32,
+        }
+    }
+
+    /// Name of the algorithm, as `--algorithm` takes it.
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Md5 => "md5",
+        }
+    }
+
+    /// Checksum of everything `reader` yields, as lowercase hex.
+    fn hash(self, reader: impl Read) -> io::Result<String> {
+        match self {
+            Self::Sha256 => digest::<Sha256>(reader),
+            Self::Md5 => digest::<Md5>(reader),
+        }
+    }
+}
+
+/// A hashed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hashed {
+    /// Path relative to `--in`, with `/` separators.
+    name: String,
+    /// Checksum, or why the file could not be read.
+    checksum: Result<String, String>,
+    study: String,
+}
+
+/// Outcome of checking the files against a manifest.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Verification {
+    matched: usize,
+    /// Files whose checksum differs, or that could not be read.
+    changed: Vec<String>,
+    /// Files in the manifest that are not there.
+    missing: Vec<String>,
+    /// Files that are there but not in the manifest.
+    extra: Vec<String>,
+}
+
+/// Hash the files below `--in`, then print and save the checksums or check
+/// them against `--verify`.
+pub fn run(args: &HashArgs) -> Result<()> {
+    validate_input_folder(&args.input)?;
+    let manifest = args
+        .verify
+        .as_deref()
+        .map(|path| read_manifest(path, args.algorithm))
+        .transpose()?;
+
+    let files = inventory::walk(&args.input, args.follow_symlinks)?;
+    if files.is_empty() {
+        println!(
+            "{}",
+            t!("no-dcm-files", path = args.input.display().to_string())
+        );
+        return Ok(());
+    }
+    println!(
+        "{}\n",
+        t!(
+            "hash-reading",
+            count = files.len(),
+            algorithm = args.algorithm.label()
+        )
+    );
+
+    let mut hashed = Vec::with_capacity(files.len());
+    for path in &files {
+        if let Some(stopped) = cancel::ended() {
+            anyhow::bail!(stopped);
+        }
+        hashed.push(hash_file(path, &args.input, args.algorithm));
+    }
+    for file in &hashed {
+        if let Err(error) = &file.checksum {
+            eprintln!(
+                "{}",
+                t!(
+                    "hash-unreadable",
+                    file = file.name.as_str(),
+                    error = error.as_str()
+                )
+            );
+        }
+    }
+
+    if let Some(manifest) = manifest {
+        return verify(&hashed, &manifest, args.algorithm);
+    }
+
+    println!("{}", t!("hash-studies-header"));
+    for (uid, (checksum, count)) in study_checksums(&hashed, args.algorithm) {
+        println!(
+            "  {}",
+            t!("hash-study", uid = uid, checksum = checksum, count = count)
+        );
+    }
+    if let Some(path) = &args.output {
+        write_manifest(path, &hashed)?;
+        println!("\n{}", t!("hash-saved", path = path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Hash `path`, found below `root`, and read its study from the header.
+fn hash_file(path: &Path, root: &Path, algorithm: Algorithm) -> Hashed {
+    throttle::before_read(path);
+    let checksum = File::open(path)
+        .and_then(|file| algorithm.hash(BufReader::new(file)))
+        .map_err(|e| e.to_string());
+    let study = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()
+        .and_then(|obj| {
+            obj.element(tags::STUDY_INSTANCE_UID)
+                .ok()
+                .and_then(|elem| elem.to_str().ok())
+                .map(|uid| uid.trim_end_matches(['\0', ' ']).to_string())
+        })
+        .filter(|uid| !uid.is_empty())
+        .unwrap_or_else(|| UNKNOWN.to_string());
+    Hashed {
+        name: relative_name(path, root),
+        checksum,
+        study,
+    }
+}
+
+/// `path` relative to `root`, with `/` separators as in manifests.
+fn relative_name(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Checksum and file count of each study, by `StudyInstanceUID`. Studies
+/// with an unreadable file get none.
+fn study_checksums(hashed: &[Hashed], algorithm: Algorithm) -> BTreeMap<&str, (String, usize)> {
+    let mut studies: BTreeMap<&str, Vec<Option<&str>>> = BTreeMap::new();
+    for file in hashed {
+        studies
+            .entry(&file.study)
+            .or_default()
+            .push(file.checksum.as_deref().ok());
+    }
+    studies
+        .into_iter()
+        .map(|(uid, checksums)| {
+            let count = checksums.len();
+            let checksum = checksums
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .map_or_else(
+                    || "-".to_string(),
+                    |mut checksums| {
+                        checksums.sort_unstable();
+                        algorithm
+                            .hash(checksums.join("\n").as_bytes())
+                            .unwrap_or_default()
+                    },
+                );
+            (uid, (checksum, count))
+        })
+        .collect()
+}
+
+/// Write the readable files' checksums as `<checksum>  <name>` lines.
+fn write_manifest(path: &Path, hashed: &[Hashed]) -> Result<()> {
+    let lines: String = hashed
+        .iter()
+        .filter_map(|file| {
+            let checksum = file.checksum.as_ref().ok()?;
+            Some(format!("{checksum}  {}\n", file.name))
+        })
+        .collect();
+    fs::write(path, lines).with_context(|| format!("Failed to write manifest: {}", path.display()))
+}
+
+/// Read a `sha256sum`/`md5sum` manifest into checksums by file name.
+fn read_manifest(path: &Path, algorithm: Algorithm) -> Result<BTreeMap<String, String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+    parse_manifest(&text, algorithm).map_err(|line| {
+        BadInput(format!(
+            "{} line {line} is not a `<checksum>  <file>` entry with a {}-digit checksum",
+            path.display(),
+            algorithm.hex_len()
+        ))
+        .into()
+    })
+}
+
+/// Parse manifest lines, skipping blank ones and `#` comments. Fails with
+/// the number of the first malformed line.
+fn parse_manifest(text: &str, algorithm: Algorithm) -> Result<BTreeMap<String, String>, usize> {
+    let mut entries = BTreeMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (checksum, name) = line.split_once(' ').ok_or(number + 1)?;
+        // `<checksum>  <file>` (text mode) or `<checksum> *<file>` (binary)
+        let name = name
+            .strip_prefix(' ')
+            .or_else(|| name.strip_prefix('*'))
+            .ok_or(number + 1)?;
+        let valid = checksum.len() == algorithm.hex_len()
+            && checksum.bytes().all(|b| b.is_ascii_hexdigit());
+        if !valid || name.is_empty() {
+            return Err(number + 1);
+        }
+        let name = name.trim_start_matches("./").replace('\\', "/");
+        entries.insert(name, checksum.to_ascii_lowercase());
+    }
+    Ok(entries)
+}
+
+/// Check `hashed` against `manifest`, print the differences, and fail if
+/// any listed file changed or is missing.
+fn verify(
+    hashed: &[Hashed],
+    manifest: &BTreeMap<String, String>,
+    algorithm: Algorithm,
+) -> Result<()> {
+    let verification = compare(hashed, manifest);
+    for name in &verification.changed {
+        println!("{}", t!("hash-changed", file = name.as_str()));
+    }
+    for name in &verification.missing {
+        println!("{}", t!("hash-missing", file = name.as_str()));
+    }
+    for name in &verification.extra {
+        println!("{}", t!("hash-extra", file = name.as_str()));
+    }
+
+    let studies = study_checksums(hashed, algorithm);
+    let damaged: Vec<&str> = hashed
+        .iter()
+        .filter(|file| verification.changed.contains(&file.name))
+        .map(|file| file.study.as_str())
+        .collect();
+    let intact = studies.keys().filter(|uid| !damaged.contains(uid)).count();
+    println!(
+        "\n{}",
+        t!(
+            "hash-verified",
+            matched = verification.matched,
+            total = manifest.len(),
+            intact = intact,
+            studies = studies.len()
+        )
+    );
+
+    let failed = verification.changed.len() + verification.missing.len();
+    if failed > 0 {
+        anyhow::bail!("Verification failed: {failed} file(s) changed or missing");
+    }
+    Ok(())
+}
+
+/// Sort the files into matched, changed, missing, and extra.
+fn compare(hashed: &[Hashed], manifest: &BTreeMap<String, String>) -> Verification {
+    let mut verification = Verification::default();
+    let mut seen = Vec::with_capacity(hashed.len());
+    for file in hashed {
+        let Some(expected) = manifest.get(&file.name) else {
+            verification.extra.push(file.name.clone());
+            continue;
+        };
+        seen.push(file.name.as_str());
+        if file.checksum.as_ref() == Ok(expected) {
+            verification.matched += 1;
+        } else {
+            verification.changed.push(file.name.clone());
+        }
+    }
+    verification.missing = manifest
+        .keys()
+        .filter(|name| !seen.contains(&name.as_str()))
+        .cloned()
+        .collect();
+    verification
+}
+
+/// Hash everything `reader` yields with `D`, as lowercase hex.
+fn digest<D: Digest>(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashed(name: &str, checksum: &str, study: &str) -> Hashed {
+        Hashed {
+            name: name.to_string(),
+            checksum: Ok(checksum.to_string()),
+            study: study.to_string(),
+        }
+    }
+
+    #[test]
+    fn checksums_match_the_standard_tools() {
+        assert_eq!(
+            Algorithm::Sha256.hash(&b"abc"[..]).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            Algorithm::Md5.hash(&b"abc"[..]).unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+    }
+
+    #[test]
+    fn manifest_accepts_text_and_binary_entries() {
+        let a = "a".repeat(32);
+        let b = "B".repeat(32);
+        let text = format!("# from the core\n{a}  one/1.dcm\r\n\n{b} *./two/2.dcm\n");
+        let entries = parse_manifest(&text, Algorithm::Md5).unwrap();
+        assert_eq!(entries.get("one/1.dcm"), Some(&a));
+        assert_eq!(entries.get("two/2.dcm"), Some(&"b".repeat(32)));
+    }
+
+    #[test]
+    fn manifest_rejects_the_wrong_algorithm() {
+        let text = format!("{}  one.dcm\n", "a".repeat(32));
+        assert_eq!(parse_manifest(&text, Algorithm::Sha256), Err(1));
+        assert_eq!(parse_manifest("nonsense\n", Algorithm::Md5), Err(1));
+    }
+
+    #[test]
+    fn comparison_sorts_files() {
+        let files = [
+            hashed("same.dcm", "aa", "1"),
+            hashed("edited.dcm", "bb", "1"),
+            hashed("new.dcm", "cc", "2"),
+        ];
+        let manifest = BTreeMap::from([
+            ("same.dcm".to_string(), "aa".to_string()),
+            ("edited.dcm".to_string(), "00".to_string()),
+            ("gone.dcm".to_string(), "dd".to_string()),
+        ]);
+        assert_eq!(
+            compare(&files, &manifest),
+            Verification {
+                matched: 1,
+                changed: vec!["edited.dcm".to_string()],
+                missing: vec!["gone.dcm".to_string()],
+                extra: vec!["new.dcm".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn study_checksum_ignores_names_and_order() {
+        let first = [hashed("a.dcm", "11", "1"), hashed("b.dcm", "22", "1")];
+        let moved = [hashed("x/b.dcm", "22", "1"), hashed("y/a.dcm", "11", "1")];
+        let edited = [hashed("a.dcm", "11", "1"), hashed("b.dcm", "33", "1")];
+        let checksum = |files: &[Hashed]| study_checksums(files, Algorithm::Sha256)["1"].clone();
+        assert_eq!(checksum(&first), checksum(&moved));
+        assert_ne!(checksum(&first), checksum(&edited));
+        assert_eq!(checksum(&first).1, 2);
+    }
+
+    #[test]
+    fn names_are_relative_with_forward_slashes() {
+        let root = Path::new("archive");
+        assert_eq!(
+            relative_name(&root.join("study").join("1.dcm"), root),
+            "study/1.dcm"
+        );
+    }
+}
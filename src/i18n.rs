@@ -0,0 +1,196 @@
+//! Localized user-facing messages.
+//!
+//! Prompts and progress output are looked up by message id in Fluent
+//! catalogs (`locales/*.ftl`) that are compiled into the binary. Use the
+//! [`t!`] macro at call sites:
+//!
+//! ```ignore
+//! println!("{}", t!("convert-found-files", count = dcm_files.len()));
+//! ```
+//!
+//! The language comes from `--lang`, then `DCM_TOOLBOX_LANG`, then the usual
+//! `LC_ALL` / `LC_MESSAGES` / `LANG` variables, and defaults to English.
+//! Messages missing from a catalog fall back to English.
+
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use fluent::concurrent::FluentBundle;
+use fluent::{FluentArgs, FluentResource};
+use unic_langid::LanguageIdentifier;
+
+/// Environment variables consulted (in order) when `--lang` is not given.
+const LANG_ENV_VARS: [&str; 4] = ["DCM_TOOLBOX_LANG", "LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// Language of user-facing messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    /// English
+    #[default]
+    En,
+    /// Spanish (Español)
+    Es,
+}
+
+impl Lang {
+    /// Detect the language from the environment.
+    pub fn from_env() -> Self {
+        LANG_ENV_VARS
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+            .map_or(Self::En, |value| Self::from_locale(&value))
+    }
+
+    /// Map a POSIX locale value (e.g. `es_AR.UTF-8`) to a supported language.
+    fn from_locale(value: &str) -> Self {
+        if value.to_ascii_lowercase().starts_with("es") {
+            Self::Es
+        } else {
+            Self::En
+        }
+    }
+
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+        }
+    }
+
+    const fn source(self) -> &'static str {
+        match self {
+            Self::En => include_str!("../locales/en.ftl"),
+            Self::Es => include_str!("../locales/es.ftl"),
+        }
+    }
+}
+
+/// Active message catalog plus the English fallback.
+struct Catalog {
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Catalog {
+    fn new(lang: Lang) -> Self {
+        let mut bundles = vec![build_bundle(lang)];
+        if lang != Lang::En {
+            bundles.push(build_bundle(Lang::En));
+        }
+        Self { bundles }
+    }
+
+    fn format(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        for bundle in &self.bundles {
+            if let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) {
+                let mut errors = vec![];
+                return bundle
+                    .format_pattern(pattern, args, &mut errors)
+                    .into_owned();
+            }
+        }
+        id.to_string()
+    }
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+fn build_bundle(lang: Lang) -> FluentBundle<FluentResource> {
+    let resource = FluentResource::try_new(lang.source().to_string())
+        .unwrap_or_else(|(_, errors)| panic!("Invalid {} catalog: {errors:?}", lang.tag()));
+    let langid: LanguageIdentifier = lang.tag().parse().expect("valid language tag");
+
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // Terminal output: no Unicode bidi isolation marks around arguments
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .unwrap_or_else(|errors| panic!("Duplicate ids in {} catalog: {errors:?}", lang.tag()));
+    bundle
+}
+
+/// Select the message language. Must be called before the first message is
+/// formatted; later calls are ignored.
+pub fn init(lang: Lang) {
+    let _ = CATALOG.set(Catalog::new(lang));
+}
+
+/// Format a message by id. Prefer the [`t!`] macro.
+pub fn tr(id: &str, args: Option<&FluentArgs>) -> String {
+    CATALOG
+        .get_or_init(|| Catalog::new(Lang::from_env()))
+        .format(id, args)
+}
+
+/// Format a localized message: `t!("id")` or `t!("id", name = value, ...)`.
+macro_rules! t {
+    ($id:literal) => {
+        $crate::i18n::tr($id, None)
+    };
+    ($id:literal, $($key:ident = $value:expr),+ $(,)?) => {{
+        let mut args = ::fluent::FluentArgs::new();
+        $(args.set(stringify!($key), $value);)+
+        $crate::i18n::tr($id, Some(&args))
+    }};
+}
+pub(crate) use t;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Message ids defined at the start of lines in a catalog source.
+    fn message_ids(lang: Lang) -> Vec<&'static str> {
+        lang.source()
+            .lines()
+            .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+            .filter_map(|line| line.split_once(" = ").map(|(id, _)| id))
+            .collect()
+    }
+
+    #[test]
+    fn catalogs_parse() {
+        for lang in [Lang::En, Lang::Es] {
+            assert!(!Catalog::new(lang).bundles.is_empty());
+        }
+    }
+
+    #[test]
+    fn spanish_catalog_covers_every_english_message() {
+        let spanish = build_bundle(Lang::Es);
+        let missing: Vec<_> = message_ids(Lang::En)
+            .into_iter()
+            .filter(|id| !spanish.has_message(id))
+            .collect();
+        assert!(missing.is_empty(), "Missing Spanish messages: {missing:?}");
+    }
+
+    #[test]
+    fn formats_arguments_without_isolation_marks() {
+        let mut args = FluentArgs::new();
+        args.set("path", "/tmp/in");
+        let text = Catalog::new(Lang::En).format("no-dcm-files", Some(&args));
+        assert_eq!(text, "No .dcm files found in /tmp/in");
+    }
+
+    #[test]
+    fn spanish_messages_are_translated() {
+        let text = Catalog::new(Lang::Es).format("video-encoding", None);
+        assert_eq!(text, "Codificando video con ffmpeg...");
+    }
+
+    #[test]
+    fn unknown_id_falls_back_to_id() {
+        assert_eq!(
+            Catalog::new(Lang::Es).format("no-such-id", None),
+            "no-such-id"
+        );
+    }
+
+    #[test]
+    fn locale_values_map_to_languages() {
+        assert_eq!(Lang::from_locale("es_AR.UTF-8"), Lang::Es);
+        assert_eq!(Lang::from_locale("ES"), Lang::Es);
+        assert_eq!(Lang::from_locale("en_US.UTF-8"), Lang::En);
+        assert_eq!(Lang::from_locale("C"), Lang::En);
+    }
+}
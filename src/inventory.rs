@@ -0,0 +1,528 @@
+//! Archive inventory (`inventory`).
+//!
+//! Walks a whole data dump, folder by folder, and counts what is in it from
+//! the file headers alone: studies, series, instances, and bytes, by modality
+//! and by scanner, with the range of study dates and how many series the
+//! studies hold. The totals are printed; `--out` also writes them as JSON,
+//! or as CSV with one row per study for a spreadsheet.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+use serde::Serialize;
+
+use crate::cancel;
+use crate::i18n::t;
+use crate::meta;
+use crate::outcome::BadInput;
+use crate::utils::{format_bytes, list_dcm_files, validate_input_folder};
+
+/// Value counted for files without the attribute.
+const UNKNOWN: &str = "unknown";
+
+/// Columns of the CSV output.
+const CSV_HEADER: &str = "study_instance_uid,study_date,modalities,scanners,series,instances,bytes";
+
+/// CLI arguments for the `inventory` subcommand.
+#[derive(Args, Debug)]
+pub struct InventoryArgs {
+    /// Archive folder; .dcm files in all of its subfolders are counted
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Also write the inventory to this file; the extension picks the
+    /// format (.json, or .csv with one row per study)
+    #[arg(long = "out")]
+    pub output: Option<PathBuf>,
+
+    /// Include symlinked .dcm files and folders (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Inventory file format, from the output extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    Json,
+    Csv,
+}
+
+/// What one readable file adds to the inventory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Instance {
+    study: String,
+    series: String,
+    modality: String,
+    /// Manufacturer and model name.
+    scanner: String,
+    /// `StudyDate` as `YYYY-MM-DD`.
+    date: Option<String>,
+    bytes: u64,
+}
+
+/// Counts of a set of instances.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct Tally {
+    studies: usize,
+    series: usize,
+    instances: usize,
+    bytes: u64,
+}
+
+/// Earliest and latest study date.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct DateRange {
+    first: String,
+    last: String,
+}
+
+/// One study of the archive.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Study {
+    #[serde(rename = "study_instance_uid")]
+    uid: String,
+    #[serde(rename = "study_date")]
+    date: Option<String>,
+    modalities: Vec<String>,
+    scanners: Vec<String>,
+    series: usize,
+    instances: usize,
+    bytes: u64,
+}
+
+/// Everything the inventory reports.
+#[derive(Debug, Serialize)]
+struct Inventory {
+    total: Tally,
+    /// `.dcm` files whose header could not be read.
+    unreadable: usize,
+    study_dates: Option<DateRange>,
+    by_modality: BTreeMap<String, Tally>,
+    by_scanner: BTreeMap<String, Tally>,
+    /// How many studies hold each number of series.
+    series_per_study: BTreeMap<usize, usize>,
+    studies: Vec<Study>,
+}
+
+/// Count the archive below `--in`, print the totals, and write them to
+/// `--out` if given.
+pub fn run(args: &InventoryArgs) -> Result<()> {
+    let format = args.output.as_deref().map(report_format).transpose()?;
+    validate_input_folder(&args.input)?;
+
+    let files = walk(&args.input, args.follow_symlinks)?;
+    if files.is_empty() {
+        println!(
+            "{}",
+            t!("no-dcm-files", path = args.input.display().to_string())
+        );
+        return Ok(());
+    }
+    println!("{}\n", t!("inventory-reading", count = files.len()));
+
+    let mut instances = Vec::with_capacity(files.len());
+    let mut unreadable = 0;
+    for path in &files {
+        if let Some(stopped) = cancel::ended() {
+            anyhow::bail!(stopped);
+        }
+        match read_instance(path) {
+            Some(instance) => instances.push(instance),
+            None => unreadable += 1,
+        }
+    }
+
+    let inventory = Inventory::new(&instances, unreadable);
+    print(&inventory);
+    if let (Some(path), Some(format)) = (&args.output, format) {
+        write(path, format, &inventory)?;
+        println!(
+            "\n{}",
+            t!("inventory-saved", path = path.display().to_string())
+        );
+    }
+    Ok(())
+}
+
+/// Output format picked by the file extension.
+fn report_format(path: &Path) -> Result<ReportFormat> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("json") => Ok(ReportFormat::Json),
+        Some("csv") => Ok(ReportFormat::Csv),
+        _ => anyhow::bail!(BadInput(format!(
+            "Unsupported inventory file {}; expected .json or .csv",
+            path.display()
+        ))),
+    }
+}
+
+/// `.dcm` files of `dir` and all its subfolders, folder by folder in name
+/// order. Symlinked folders are only entered with `follow_symlinks`, and
+/// each real folder only once.
+pub fn walk(dir: &Path, follow_symlinks: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if !visited.insert(fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        files.extend(list_dcm_files(&dir, follow_symlinks)?);
+        let entries = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read input folder: {}", dir.display()))?;
+        let mut subdirs: Vec<PathBuf> = entries
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| {
+                entry
+                    .file_type()
+                    .is_ok_and(|kind| kind.is_dir() || (follow_symlinks && kind.is_symlink()))
+            })
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        // Popped in reverse, so folders are visited in name order
+        subdirs.sort_by(|a, b| b.cmp(a));
+        pending.extend(subdirs);
+    }
+    Ok(files)
+}
+
+/// The counted attributes of a file, reading its header only; `None` if it
+/// is not readable DICOM.
+fn read_instance(path: &Path) -> Option<Instance> {
+    let obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .ok()?;
+    let text = |tag| meta::text(&obj, tag);
+    let scanner = [
+        text(tags::MANUFACTURER),
+        text(tags::MANUFACTURER_MODEL_NAME),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ");
+    Some(Instance {
+        study: text(tags::STUDY_INSTANCE_UID).unwrap_or_else(|| UNKNOWN.to_string()),
+        series: text(tags::SERIES_INSTANCE_UID).unwrap_or_else(|| UNKNOWN.to_string()),
+        modality: text(tags::MODALITY).unwrap_or_else(|| UNKNOWN.to_string()),
+        scanner: if scanner.is_empty() {
+            UNKNOWN.to_string()
+        } else {
+            scanner
+        },
+        date: text(tags::STUDY_DATE).and_then(|date| iso_date(&date)),
+        bytes: fs::metadata(path).map_or(0, |meta| meta.len()),
+    })
+}
+
+/// A DICOM date (`YYYYMMDD`) as `YYYY-MM-DD`.
+fn iso_date(value: &str) -> Option<String> {
+    let valid = value.len() == 8 && value.bytes().all(|b| b.is_ascii_digit());
+    valid.then(|| format!("{}-{}-{}", &value[..4], &value[4..6], &value[6..]))
+}
+
+impl Inventory {
+    fn new(instances: &[Instance], unreadable: usize) -> Self {
+        let mut studies: BTreeMap<&str, Vec<&Instance>> = BTreeMap::new();
+        let mut by_modality: BTreeMap<&str, Vec<&Instance>> = BTreeMap::new();
+        let mut by_scanner: BTreeMap<&str, Vec<&Instance>> = BTreeMap::new();
+        for instance in instances {
+            studies.entry(&instance.study).or_default().push(instance);
+            by_modality
+                .entry(&instance.modality)
+                .or_default()
+                .push(instance);
+            by_scanner
+                .entry(&instance.scanner)
+                .or_default()
+                .push(instance);
+        }
+        let studies: Vec<Study> = studies
+            .into_iter()
+            .map(|(uid, instances)| Study::new(uid, &instances))
+            .collect();
+
+        let mut series_per_study = BTreeMap::new();
+        for study in &studies {
+            *series_per_study.entry(study.series).or_default() += 1;
+        }
+        let dates = instances.iter().filter_map(|i| i.date.as_deref());
+        let study_dates = dates
+            .clone()
+            .min()
+            .zip(dates.max())
+            .map(|(first, last)| DateRange {
+                first: first.to_string(),
+                last: last.to_string(),
+            });
+        let tallies = |groups: BTreeMap<&str, Vec<&Instance>>| {
+            groups
+                .into_iter()
+                .map(|(key, instances)| (key.to_string(), tally(instances)))
+                .collect()
+        };
+        Self {
+            total: tally(instances),
+            unreadable,
+            study_dates,
+            by_modality: tallies(by_modality),
+            by_scanner: tallies(by_scanner),
+            series_per_study,
+            studies,
+        }
+    }
+}
+
+impl Study {
+    fn new(uid: &str, instances: &[&Instance]) -> Self {
+        let distinct = |value: fn(&Instance) -> &str| {
+            instances
+                .iter()
+                .map(|&i| value(i).to_string())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        let counts = tally(instances.iter().copied());
+        Self {
+            uid: uid.to_string(),
+            date: instances.iter().find_map(|i| i.date.clone()),
+            modalities: distinct(|i| &i.modality),
+            scanners: distinct(|i| &i.scanner),
+            series: counts.series,
+            instances: counts.instances,
+            bytes: counts.bytes,
+        }
+    }
+
+    /// The study as a CSV line; lists are joined with `;`.
+    fn csv_row(&self) -> String {
+        [
+            self.uid.clone(),
+            self.date.clone().unwrap_or_default(),
+            self.modalities.join(";"),
+            self.scanners.join(";"),
+            self.series.to_string(),
+            self.instances.to_string(),
+            self.bytes.to_string(),
+        ]
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// Distinct studies and series, instances, and bytes of `instances`.
+fn tally<'a>(instances: impl IntoIterator<Item = &'a Instance>) -> Tally {
+    let mut studies = BTreeSet::new();
+    let mut series = BTreeSet::new();
+    let mut counts = Tally::default();
+    for instance in instances {
+        studies.insert(&instance.study);
+        series.insert((&instance.study, &instance.series));
+        counts.instances += 1;
+        counts.bytes += instance.bytes;
+    }
+    counts.studies = studies.len();
+    counts.series = series.len();
+    counts
+}
+
+/// Print the totals and breakdowns.
+fn print(inventory: &Inventory) {
+    let total = &inventory.total;
+    println!(
+        "{}",
+        t!(
+            "inventory-total",
+            studies = total.studies,
+            series = total.series,
+            instances = total.instances,
+            size = format_bytes(total.bytes)
+        )
+    );
+    if let Some(dates) = &inventory.study_dates {
+        println!(
+            "{}",
+            t!(
+                "inventory-dates",
+                first = dates.first.as_str(),
+                last = dates.last.as_str()
+            )
+        );
+    }
+    if let (Some((&fewest, _)), Some((&most, _))) = (
+        inventory.series_per_study.first_key_value(),
+        inventory.series_per_study.last_key_value(),
+    ) {
+        println!(
+            "{}",
+            t!("inventory-series-per-study", fewest = fewest, most = most)
+        );
+    }
+    if inventory.unreadable > 0 {
+        println!(
+            "{}",
+            t!("inventory-unreadable", count = inventory.unreadable)
+        );
+    }
+    for (header, groups) in [
+        (t!("inventory-by-modality"), &inventory.by_modality),
+        (t!("inventory-by-scanner"), &inventory.by_scanner),
+    ] {
+        println!("\n{header}");
+        for (key, counts) in groups {
+            println!(
+                "  {}",
+                t!(
+                    "inventory-entry",
+                    key = key.as_str(),
+                    studies = counts.studies,
+                    series = counts.series,
+                    instances = counts.instances,
+                    size = format_bytes(counts.bytes)
+                )
+            );
+        }
+    }
+}
+
+/// Write the inventory in `format`.
+fn write(path: &Path, format: ReportFormat, inventory: &Inventory) -> Result<()> {
+    let text = match format {
+        ReportFormat::Json => serde_json::to_string_pretty(inventory)? + "\n",
+        ReportFormat::Csv => {
+            let mut text = format!("{CSV_HEADER}\n");
+            for study in &inventory.studies {
+                text.push_str(&study.csv_row());
+                text.push('\n');
+            }
+            text
+        }
+    };
+    fs::write(path, text).with_context(|| format!("Failed to write inventory: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(study: &str, series: &str, modality: &str, date: &str) -> Instance {
+        Instance {
+            study: study.into(),
+            series: series.into(),
+            modality: modality.into(),
+            scanner: "SIEMENS Skyra".into(),
+            date: iso_date(date),
+            bytes: 1000,
+        }
+    }
+
+    fn archive() -> Vec<Instance> {
+        vec![
+            instance("1.1", "1.1.1", "MR", "20190302"),
+            instance("1.1", "1.1.1", "MR", "20190302"),
+            instance("1.1", "1.1.2", "SR", "20190302"),
+            instance("1.2", "1.2.1", "CT", "20230115"),
+            instance("1.3", "1.3.1", "CT", ""),
+        ]
+    }
+
+    #[test]
+    fn dicom_dates_become_iso_dates() {
+        assert_eq!(iso_date("20190302").as_deref(), Some("2019-03-02"));
+        assert_eq!(iso_date("2019"), None);
+        assert_eq!(iso_date("2019-03-02"), None);
+    }
+
+    #[test]
+    fn counts_studies_series_and_bytes() {
+        let inventory = Inventory::new(&archive(), 2);
+        assert_eq!(
+            inventory.total,
+            Tally {
+                studies: 3,
+                series: 4,
+                instances: 5,
+                bytes: 5000
+            }
+        );
+        assert_eq!(inventory.unreadable, 2);
+        assert_eq!(
+            inventory.study_dates,
+            Some(DateRange {
+                first: "2019-03-02".into(),
+                last: "2023-01-15".into()
+            })
+        );
+        assert_eq!(inventory.series_per_study, BTreeMap::from([(1, 2), (2, 1)]));
+    }
+
+    #[test]
+    fn breakdowns_count_studies_per_key() {
+        let inventory = Inventory::new(&archive(), 0);
+        let ct = &inventory.by_modality["CT"];
+        assert_eq!((ct.studies, ct.series, ct.instances), (2, 2, 2));
+        let mr = &inventory.by_modality["MR"];
+        assert_eq!((mr.studies, mr.series, mr.instances), (1, 1, 2));
+        assert_eq!(inventory.by_scanner["SIEMENS Skyra"].studies, 3);
+    }
+
+    #[test]
+    fn csv_has_one_row_per_study() {
+        let inventory = Inventory::new(&archive(), 0);
+        let rows: Vec<_> = inventory.studies.iter().map(Study::csv_row).collect();
+        assert_eq!(
+            rows,
+            [
+                "1.1,2019-03-02,MR;SR,SIEMENS Skyra,2,3,3000",
+                "1.2,2023-01-15,CT,SIEMENS Skyra,1,1,1000",
+                "1.3,,CT,SIEMENS Skyra,1,1,1000",
+            ]
+        );
+        assert_eq!(CSV_HEADER.split(',').count(), rows[0].split(',').count());
+    }
+
+    #[test]
+    fn walk_descends_into_subfolders() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("patient").join("study");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join("a.dcm"), b"").unwrap();
+        fs::write(nested.join("b.dcm"), b"").unwrap();
+        fs::write(nested.join("notes.txt"), b"").unwrap();
+
+        let files = walk(dir.path(), false).unwrap();
+        assert_eq!(files, [dir.path().join("a.dcm"), nested.join("b.dcm")]);
+    }
+
+    #[test]
+    fn output_extension_picks_the_format() {
+        assert_eq!(
+            report_format(Path::new("dump.JSON")).unwrap(),
+            ReportFormat::Json
+        );
+        assert_eq!(
+            report_format(Path::new("dump.csv")).unwrap(),
+            ReportFormat::Csv
+        );
+        assert!(report_format(Path::new("dump.txt")).is_err());
+    }
+}
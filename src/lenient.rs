@@ -0,0 +1,242 @@
+//! Salvaging damaged DICOM files (`--lenient`).
+//!
+//! Real archives always hold a few broken instances: transfers cut short,
+//! elements with odd lengths, headers written by buggy modalities. A strict
+//! parse rejects the whole file. With `--lenient`, a file that fails is
+//! parsed again, rounding odd lengths up to the next even one, and failing
+//! that its header is read up to the pixel data and the pixel bytes that
+//! are there are taken as they are, with missing ones filled with black.
+//! Unknown VRs are always read as `UN`. Every salvaged file is reported.
+//!
+//! Files that cannot be salvaged either are put in quarantine: `convert`
+//! lists them with their error in `quarantine/files.csv` under the output.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Result;
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::file::OddLengthStrategy;
+use dicom::object::{DefaultDicomObject, OpenFileOptions};
+
+use crate::error::ToolboxError;
+use crate::i18n::t;
+use crate::pipeline::display_name;
+
+/// `--lenient`, set once at startup.
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Files that could not be read even leniently, with their error.
+static QUARANTINE: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Implicit VR Little Endian, the one syntax without VRs in the data set.
+const IMPLICIT_VR_LE: &str = "1.2.840.10008.1.2";
+
+/// Explicit VR Big Endian, whose pixel data is not salvaged.
+const EXPLICIT_VR_BE: &str = "1.2.840.10008.1.2.2";
+
+/// Pixel Data (7FE0,0010) as it appears in a little endian stream.
+const PIXEL_DATA_TAG: [u8; 4] = [0xE0, 0x7F, 0x10, 0x00];
+
+/// Salvage damaged files from now on.
+pub fn set(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+/// Whether `--lenient` is on.
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/// Parse `stream` (Part 10 data after the preamble) that a strict parse
+/// rejected, reporting what was salvaged from `source`.
+pub fn salvage(stream: &[u8], source: &Path) -> Result<DefaultDicomObject> {
+    let file = display_name(source);
+    let next_even = OpenFileOptions::new().odd_length_strategy(OddLengthStrategy::NextEven);
+    if let Ok(obj) = next_even.clone().from_reader(stream) {
+        eprintln!("{}", t!("lenient-odd-lengths", file = file));
+        return Ok(obj);
+    }
+
+    let header = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .from_reader(stream)
+        .or_else(|_| next_even.read_until(tags::PIXEL_DATA).from_reader(stream))?;
+    let mut obj = header;
+    let (read, expected) = restore_pixel_data(&mut obj, stream)?;
+    if read < expected {
+        eprintln!(
+            "{}",
+            t!(
+                "lenient-pixel-data-padded",
+                file = file,
+                read = read,
+                expected = expected
+            )
+        );
+    } else {
+        eprintln!("{}", t!("lenient-trailing-data", file = file));
+    }
+    Ok(obj)
+}
+
+/// Put the pixel bytes found in `stream` into `obj`, whose header was read
+/// up to them, padded to the size the header calls for. Returns the bytes
+/// found and the bytes expected.
+fn restore_pixel_data(obj: &mut DefaultDicomObject, stream: &[u8]) -> Result<(usize, usize)> {
+    let transfer_syntax = obj.meta().transfer_syntax().trim_end_matches(['\0', ' ']);
+    if transfer_syntax == EXPLICIT_VR_BE {
+        anyhow::bail!("Cannot salvage big endian pixel data");
+    }
+    let expected = expected_length(obj)
+        .ok_or_else(|| anyhow::anyhow!("Header lacks the image size; no pixels to salvage"))?;
+    let start = pixel_data_start(stream, transfer_syntax == IMPLICIT_VR_LE)
+        .ok_or_else(|| anyhow::anyhow!("No pixel data found to salvage"))?;
+
+    let mut pixels = stream[start..].to_vec();
+    let read = pixels.len().min(expected);
+    pixels.resize(expected, 0);
+    let vr = if obj
+        .element(tags::BITS_ALLOCATED)
+        .ok()
+        .and_then(|e| e.to_int::<u16>().ok())
+        .is_some_and(|bits| bits > 8)
+    {
+        VR::OW
+    } else {
+        VR::OB
+    };
+    obj.put(DataElement::new(
+        tags::PIXEL_DATA,
+        vr,
+        PrimitiveValue::from(pixels),
+    ));
+    Ok((read, expected))
+}
+
+/// Bytes of native pixel data the header describes, over all frames.
+fn expected_length(obj: &DefaultDicomObject) -> Option<usize> {
+    let read = |tag| {
+        obj.element(tag)
+            .ok()
+            .and_then(|e| e.to_int::<u32>().ok())
+            .and_then(|n| usize::try_from(n).ok())
+    };
+    let rows = read(tags::ROWS)?;
+    let columns = read(tags::COLUMNS)?;
+    let bytes_per_sample = read(tags::BITS_ALLOCATED)?.div_ceil(8);
+    let samples = read(tags::SAMPLES_PER_PIXEL).unwrap_or(1);
+    let frames = read(tags::NUMBER_OF_FRAMES).unwrap_or(1).max(1);
+    rows.checked_mul(columns)?
+        .checked_mul(bytes_per_sample)?
+        .checked_mul(samples)?
+        .checked_mul(frames)
+}
+
+/// Offset of the first pixel byte in `stream`, for native (not
+/// encapsulated) pixel data.
+fn pixel_data_start(stream: &[u8], implicit: bool) -> Option<usize> {
+    let header_len = if implicit { 8 } else { 12 };
+    let at = stream.windows(4).enumerate().position(|(at, tag)| {
+        tag == PIXEL_DATA_TAG
+            && (implicit || matches!(stream.get(at + 4..at + 6), Some(b"OB" | b"OW")))
+    })?;
+    let length_at = at + header_len - 4;
+    let length = stream.get(length_at..length_at + 4)?;
+    // Undefined length: encapsulated fragments, not raw samples
+    if length == [0xFF; 4] {
+        return None;
+    }
+    Some((at + header_len).min(stream.len()))
+}
+
+/// Put `path` in quarantine, if `--lenient` is on.
+pub fn quarantine(path: &Path, error: &ToolboxError) {
+    if enabled() {
+        lock().push((path.to_path_buf(), error.to_string()));
+    }
+}
+
+/// The files put in quarantine so far, emptying it.
+pub fn take_quarantined() -> Vec<(PathBuf, String)> {
+    std::mem::take(&mut *lock())
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Vec<(PathBuf, String)>> {
+    QUARANTINE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::object::{FileMetaTableBuilder, InMemDicomObject};
+
+    use super::*;
+
+    /// A Part 10 stream (after the preamble) of a 4x2 16-bit image.
+    fn stream(transfer_syntax: &str) -> Vec<u8> {
+        let mut obj = InMemDicomObject::new_empty();
+        let put = |obj: &mut InMemDicomObject, tag, value: u16| {
+            obj.put(DataElement::new(tag, VR::US, PrimitiveValue::from(value)));
+        };
+        put(&mut obj, tags::ROWS, 2);
+        put(&mut obj, tags::COLUMNS, 4);
+        put(&mut obj, tags::BITS_ALLOCATED, 16);
+        put(&mut obj, tags::BITS_STORED, 12);
+        put(&mut obj, tags::SAMPLES_PER_PIXEL, 1);
+        obj.put(DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OW,
+            PrimitiveValue::from((1_u8..=16).collect::<Vec<_>>()),
+        ));
+        let meta = FileMetaTableBuilder::new()
+            .transfer_syntax(transfer_syntax)
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        let mut bytes = Vec::new();
+        obj.with_exact_meta(meta).write_all(&mut bytes).unwrap();
+        // Drop the preamble, as callers do
+        bytes.split_off(128)
+    }
+
+    fn pixels(obj: &DefaultDicomObject) -> Vec<u8> {
+        obj.element(tags::PIXEL_DATA)
+            .unwrap()
+            .to_bytes()
+            .unwrap()
+            .into_owned()
+    }
+
+    #[test]
+    fn truncated_pixel_data_is_padded() {
+        for syntax in ["1.2.840.10008.1.2.1", IMPLICIT_VR_LE] {
+            let mut bytes = stream(syntax);
+            bytes.truncate(bytes.len() - 6);
+            assert!(OpenFileOptions::new().from_reader(&bytes[..]).is_err());
+
+            let obj = salvage(&bytes, Path::new("cut.dcm")).unwrap();
+            let mut expected: Vec<u8> = (1..=10).collect();
+            expected.resize(16, 0);
+            assert_eq!(pixels(&obj), expected, "{syntax}");
+        }
+    }
+
+    #[test]
+    fn headers_cut_short_are_not_salvaged() {
+        let bytes = stream("1.2.840.10008.1.2.1");
+        assert!(salvage(&bytes[..40], Path::new("cut.dcm")).is_err());
+    }
+
+    #[test]
+    fn encapsulated_data_is_not_taken_as_samples() {
+        let mut bytes = b"\xE0\x7F\x10\x00OB\0\0".to_vec();
+        bytes.extend([0xFF; 4]);
+        assert_eq!(pixel_data_start(&bytes, false), None);
+        bytes[8..12].copy_from_slice(&[4, 0, 0, 0]);
+        assert_eq!(pixel_data_start(&bytes, false), Some(12));
+    }
+}
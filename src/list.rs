@@ -0,0 +1,192 @@
+//! Series of a folder as tab-separated lines (`list`).
+//!
+//! One line per group, `key<TAB>description<TAB>files`, in the order
+//! `convert` lists and numbers them. There is no header and nothing is
+//! translated, so the output is the same on every machine and can be fed to
+//! `fzf`, `awk`, or `cut` as it is. Tabs and line breaks inside values
+//! become spaces.
+
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+
+use crate::convert::{SplitBy, UNKNOWN_KEY, compare_group_keys, sort_by_position, split_key};
+use crate::i18n::t;
+use crate::meta::{InstanceMeta, SeriesMeta};
+use crate::utils::{list_dcm_files, validate_input_folder};
+
+/// CLI arguments for the `list` subcommand.
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// Input folder containing DICOM (.dcm) files
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Tag that groups files into series, as in `convert`
+    #[arg(long, short = 's', value_enum, default_value_t = SplitBy::SeriesNumber)]
+    pub split_by: SplitBy,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// One line of the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Row {
+    key: String,
+    /// `SeriesDescription` of the group's first slice, empty if missing.
+    description: String,
+    files: usize,
+}
+
+impl Row {
+    fn line(&self) -> String {
+        format!(
+            "{}\t{}\t{}",
+            field(&self.key),
+            field(&self.description),
+            self.files
+        )
+    }
+}
+
+/// Print the series of `--in`, one tab-separated line each.
+pub fn run(args: &ListArgs) -> Result<()> {
+    validate_input_folder(&args.input)?;
+    let files = list_dcm_files(&args.input, args.follow_symlinks)?;
+    if files.is_empty() {
+        eprintln!(
+            "{}",
+            t!("no-dcm-files", path = args.input.display().to_string())
+        );
+        return Ok(());
+    }
+
+    let mut out = io::stdout().lock();
+    for row in rows(&files, args.split_by) {
+        match writeln!(out, "{}", row.line()) {
+            // The reader (`head`, `fzf`) has what it wanted
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+            result => result.context("Failed to write the series list")?,
+        }
+    }
+    match out.flush() {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("Failed to write the series list"),
+    }
+}
+
+/// The groups of `files` by `split_by`, in `convert`'s order. Files without
+/// the tag share the `unknown` group.
+fn rows(files: &[PathBuf], split_by: SplitBy) -> Vec<Row> {
+    let mut groups: BTreeMap<String, Vec<InstanceMeta>> = BTreeMap::new();
+    for file in files {
+        let obj = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(file)
+            .ok();
+        let key = split_key(obj.as_ref(), split_by).unwrap_or_else(|| UNKNOWN_KEY.to_string());
+        let meta = obj.as_ref().map_or_else(
+            || InstanceMeta::unreadable(file),
+            |obj| InstanceMeta::from_object(file, obj),
+        );
+        groups.entry(key).or_default().push(meta);
+    }
+    let mut rows: Vec<Row> = groups
+        .into_iter()
+        .map(|(key, instances)| {
+            let series = SeriesMeta::new(sort_by_position(instances));
+            Row {
+                key,
+                description: series.description().unwrap_or_default().to_string(),
+                files: series.instances.len(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| compare_group_keys(&a.key, &b.key));
+    rows
+}
+
+/// `value` with tabs and line breaks as spaces, so it stays one field.
+fn field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom::object::{FileMetaTableBuilder, InMemDicomObject};
+
+    use super::*;
+
+    fn write_header(path: &Path, number: Option<&str>, description: &str) {
+        let mut elements = vec![DataElement::new(
+            tags::SERIES_DESCRIPTION,
+            VR::LO,
+            PrimitiveValue::from(description),
+        )];
+        if let Some(number) = number {
+            elements.push(DataElement::new(
+                tags::SERIES_NUMBER,
+                VR::IS,
+                PrimitiveValue::from(number),
+            ));
+        }
+        let meta = FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        InMemDicomObject::from_element_iter(elements)
+            .with_exact_meta(meta)
+            .write_to_file(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn groups_are_counted_in_convert_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = [
+            (Some("10"), "Axial 1mm"),
+            (Some("2"), "Scout "),
+            (Some("10"), "Axial 1mm"),
+            (None, "Dose report"),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (number, description))| {
+            let path = dir.path().join(format!("{i}.dcm"));
+            write_header(&path, *number, description);
+            path
+        })
+        .collect();
+
+        let lines: Vec<String> = rows(&files, SplitBy::SeriesNumber)
+            .iter()
+            .map(Row::line)
+            .collect();
+        assert_eq!(
+            lines,
+            ["2\tScout\t1", "10\tAxial 1mm\t2", "unknown\tDose report\t1"]
+        );
+    }
+
+    #[test]
+    fn values_stay_one_field() {
+        let row = Row {
+            key: "a\tb".to_string(),
+            description: "two\nlines\r".to_string(),
+            files: 3,
+        };
+        assert_eq!(row.line(), "a b\ttwo lines \t3");
+    }
+}
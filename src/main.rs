@@ -5,11 +5,29 @@
 //!
 //! ## Features
 //!
-//! - Convert DICOM files to JPEG images, MP4 video, or STL 3D models
+//! - Convert DICOM files to JPEG images, MP4 video, STL 3D models, or point clouds
 //! - Analyze DICOM metadata to identify optimal splitting strategies
+//! - List series as tab-separated lines for fzf/awk-driven scripts
+//! - Inventory a whole archive by modality, scanner, and study date (CSV or JSON)
+//! - Checksum source files and studies, and verify them against a manifest
 //! - Split output by series/groups based on configurable DICOM tags
+//! - Pick which detected series to convert from a numbered list
+//! - Default output folders named after the patient, study date, and modality
 //! - Automatic Otsu thresholding for STL isosurface extraction
 //! - Configurable Gaussian smoothing for 3D model generation
+//! - Standalone STL export with voxel cropping and mesh decimation
+//! - Rigid registration between two series (mutual information)
+//! - Subtraction imaging (post minus pre) as image stack, MIP, or video
+//! - Airway and vessel centerlines with branch lengths (VTK or JSON polylines)
+//! - Dental panoramic images (curved MPR along the arch) from CBCT
+//! - Mean and standard deviation of HU (or other modality units) in circle and box ROIs
+//! - PET exports in body-weight SUV, with a fixed SUV window
+//! - RT Dose grids as NIfTI or NRRD, and isodose lines drawn on the planning CT
+//! - Calibrated colorbars with tick labels (HU, SUV) on exported frames
+//! - Reassemble whole-slide microscopy tiles into one image or a DeepZoom pyramid
+//! - Re-encode exported image series to MP4 without decoding DICOM again
+//! - Synthetic CT phantoms (gradient, sphere) to try the commands without patient data
+//! - A desktop window with series thumbnails and a progress bar (`gui` feature)
 //!
 //! ## Usage
 //!
@@ -17,34 +35,126 @@
 //! dcm-toolbox convert --in <input> --out <output> --split-by <tag> jpeg
 //! dcm-toolbox convert --in <input> --out <output> video --fps 10
 //! dcm-toolbox convert --in <input> --out <output> stl --smooth 1.0
+//! dcm-toolbox convert --in <input> --out <output> pointcloud --threshold 300
+//! dcm-toolbox convert --in <input> jpeg    # writes to ./export/{PatientID}_{StudyDate}_{Modality}
 //! dcm-toolbox analyze --in <input_folder>
+//! dcm-toolbox list --in <input_folder> | fzf
+//! dcm-toolbox gen-test-data --out ./phantoms
+//! dcm-toolbox inventory --in <archive> --out inventory.csv
+//! dcm-toolbox hash --in <archive> --verify manifest.sha256
+//! dcm-toolbox register --fixed <ct_folder> --moving <pet_folder> --out transform.json
+//! dcm-toolbox subtract --post <post> --pre <pre> --out <output> --mode mip
+//! dcm-toolbox stl --in <series> --out model.stl --crop :,:,20:80 --decimate 1.5
+//! dcm-toolbox centerline --in <series> --out airways.vtk --threshold -500 --below
+//! dcm-toolbox panoramic --in <cbct> --out panoramic.png --thickness 12
+//! dcm-toolbox measure --in <series> --roi L1=circle:40:256,300,15 --out hu.csv
+//! dcm-toolbox dose --in RD.dcm --out dose.nii.gz --ct <ct> --overlays <output>
+//! dcm-toolbox video-from-images --in <output>/<series> --fps 24
+//! dcm-toolbox gui --in <input_folder>    # built with --features gui
+//! dcm-toolbox scan.dcm                   # quick convert: scan.jpg beside it
 //! ```
 //!
 //! The `<output>` folder will contain subfolders for each series/group.
+//!
+//! ## Exit codes
+//!
+//! `0` ok, `1` unexpected error, `2` partial failures, `3` nothing converted,
+//! `4` bad input, `130` stopped by Ctrl-C. See the [`outcome`] module for
+//! details.
 
+mod align;
 mod analyze;
+mod annotate;
+mod cache;
+mod cancel;
+mod centerline;
 mod convert;
+mod dose;
+mod error;
+mod events;
+mod ffmpeg;
+mod filter;
+mod gen_test_data;
+#[cfg(feature = "gui")]
+mod gui;
+mod hash;
+mod i18n;
+mod inventory;
+mod lenient;
+mod list;
+mod mask;
+mod measure;
+mod meta;
+mod notify;
+mod outcome;
+mod panoramic;
+mod perms;
+mod pipeline;
+mod pixel;
+mod prefetch;
+mod private_tags;
+mod quick;
+mod register;
+mod select;
+mod stl;
+mod subtract;
+mod throttle;
 mod utils;
+mod verify_pixels;
+mod video_from_images;
+mod volume;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
 use convert::{ConvertFormat, ConvertShared};
+use outcome::Status;
 
 #[derive(Parser, Debug)]
 #[command(name = "dcm-toolbox")]
 #[command(about = "Convert DICOM medical images to JPG, video, or 3D model format")]
 struct CliArgs {
+    /// Language for prompts and progress messages (default: from LANG)
+    #[arg(long, global = true, value_enum)]
+    lang: Option<i18n::Lang>,
+
+    /// ffmpeg executable, or the folder holding it, for video output
+    /// (default: ffmpeg from PATH)
+    #[arg(long, global = true, value_name = "PATH")]
+    ffmpeg_path: Option<PathBuf>,
+
+    /// Read up to N DICOM files ahead while the current one decodes, to
+    /// hide the latency of network shares (SMB, NFS)
+    #[arg(long, global = true, value_name = "N", default_value_t = 0)]
+    prefetch: usize,
+
+    /// Run at most N ffmpeg encodes at once, counting other dcm-toolbox
+    /// runs on this machine; more wait for a free slot
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    max_encoders: Option<u16>,
+
+    /// Salvage damaged DICOM files (truncated pixel data, odd lengths)
+    /// instead of failing them; `convert` lists the rest in `quarantine/`
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    #[command(flatten)]
+    throttle: throttle::ThrottleArgs,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Convert DICOM files to JPG images, MP4 video, or STL 3D model
+    /// Convert DICOM files to JPG images, MP4 video, STL 3D model, or point cloud
     Convert {
         #[command(flatten)]
-        shared: ConvertShared,
+        shared: Box<ConvertShared>,
 
         #[command(subcommand)]
         format: ConvertFormat,
@@ -54,13 +164,186 @@ enum Commands {
         #[command(flatten)]
         args: analyze::AnalyzeArgs,
     },
+    /// Print each series' key, description, and file count as tab-separated
+    /// lines for scripts
+    List {
+        #[command(flatten)]
+        args: list::ListArgs,
+    },
+    /// Count studies, series, and bytes of a whole archive by modality and scanner
+    Inventory {
+        #[command(flatten)]
+        args: inventory::InventoryArgs,
+    },
+    /// Compute checksums of DICOM files and studies, or verify them against a manifest
+    Hash {
+        #[command(flatten)]
+        args: hash::HashArgs,
+    },
+    /// Rigidly register one series volume onto another (mutual information)
+    Register {
+        #[command(flatten)]
+        args: register::RegisterArgs,
+    },
+    /// Subtract two series voxel-wise (e.g. post minus pre contrast)
+    Subtract {
+        #[command(flatten)]
+        args: subtract::SubtractArgs,
+    },
+    /// Build an STL model from one series folder (with crop and decimation)
+    Stl {
+        #[command(flatten)]
+        args: stl::StlArgs,
+    },
+    /// Trace airway or vessel centerlines of one series as polylines (VTK or JSON)
+    Centerline {
+        #[command(flatten)]
+        args: centerline::CenterlineArgs,
+    },
+    /// Reconstruct a dental panoramic image from a CBCT series (curved MPR
+    /// along the fitted arch)
+    Panoramic {
+        #[command(flatten)]
+        args: panoramic::PanoramicArgs,
+    },
+    /// Report mean and standard deviation of calibrated values (e.g. HU) in
+    /// circle and box regions of a series
+    Measure {
+        #[command(flatten)]
+        args: measure::MeasureArgs,
+    },
+    /// Export an RT Dose grid as a volume and draw its isodose lines on the
+    /// planning CT
+    Dose {
+        #[command(flatten)]
+        args: dose::DoseArgs,
+    },
+    /// Write synthetic CT series (gradient and sphere phantoms) to try the
+    /// commands without patient data
+    GenTestData {
+        #[command(flatten)]
+        args: gen_test_data::GenTestDataArgs,
+    },
+    /// Encode an exported image series folder into an MP4 without decoding DICOM again
+    VideoFromImages {
+        #[command(flatten)]
+        args: video_from_images::VideoFromImagesArgs,
+    },
+    /// Decode original and transcoded or anonymized copies and check that
+    /// their pixels match (PSNR for lossy copies)
+    VerifyPixels {
+        #[command(flatten)]
+        args: verify_pixels::VerifyPixelsArgs,
+    },
+    /// Copy DICOM files with only the private tag blocks a TOML policy keeps
+    PrivateTags {
+        #[command(flatten)]
+        args: private_tags::PrivateTagsArgs,
+    },
+    /// Open a window to drop a folder in, preview its series, and convert
+    /// them with a progress bar
+    #[cfg(feature = "gui")]
+    Gui {
+        #[command(flatten)]
+        args: gui::GuiArgs,
+    },
 }
 
-fn main() -> Result<()> {
-    let args = CliArgs::parse();
+fn main() -> ExitCode {
+    let args = match CliArgs::try_parse_from(quick::expand(std::env::args_os().collect())) {
+        Ok(args) => args,
+        Err(e) => {
+            // Help/version requests are not errors
+            let status = if e.use_stderr() {
+                Status::BadInput
+            } else {
+                Status::Ok
+            };
+            let _ = e.print();
+            return ExitCode::from(status.code());
+        }
+    };
+
+    i18n::init(args.lang.unwrap_or_else(i18n::Lang::from_env));
+    cancel::install();
+    // The window shows progress itself
+    #[cfg(feature = "gui")]
+    let is_gui = matches!(args.command, Commands::Gui { .. });
+    #[cfg(not(feature = "gui"))]
+    let is_gui = false;
+    if !is_gui {
+        events::listen(events::Console);
+    }
+    if let Some(path) = &args.ffmpeg_path {
+        ffmpeg::set_path(path.clone());
+    }
+    throttle::apply(&args.throttle);
+    prefetch::set(args.prefetch);
+    lenient::set(args.lenient);
+    if let Some(max) = args.max_encoders {
+        ffmpeg::slots::set_max(usize::from(max));
+    }
+
+    let is_convert = matches!(args.command, Commands::Convert { .. });
+
+    let status = match run(args) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            let status = Status::from_error(&e);
+            if is_convert {
+                eprintln!("{}", status.summary_line());
+            }
+            status
+        }
+    };
+
+    ExitCode::from(status.code())
+}
 
+fn run(args: CliArgs) -> Result<Status> {
     match args.command {
-        Commands::Convert { shared, format } => convert::run(&shared, &format),
-        Commands::Analyze { args } => analyze::run(&args),
+        Commands::Convert { mut shared, format } => {
+            let started = Instant::now();
+            shared.resolve_output()?;
+            shared.permissions.apply_umask();
+            let existing = shared.permissions.existing(&shared.output);
+            let run = convert::run(&shared, &format);
+            shared.permissions.apply_to(&shared.output, &existing);
+            if let Some(path) = &shared.metrics_file {
+                outcome::record_metrics(path, &run, started.elapsed());
+            }
+            if let Some(url) = &shared.notify_webhook {
+                let notice = notify::Notice {
+                    input: &shared.input,
+                    output: &shared.output,
+                    run: &run,
+                    elapsed: started.elapsed(),
+                };
+                notice.send(url);
+            }
+            let summary = run?;
+            for line in summary.lines() {
+                eprintln!("{line}");
+            }
+            Ok(summary.status())
+        }
+        Commands::Analyze { args } => analyze::run(&args).map(|()| Status::Ok),
+        Commands::List { args } => list::run(&args).map(|()| Status::Ok),
+        Commands::Inventory { args } => inventory::run(&args).map(|()| Status::Ok),
+        Commands::Hash { args } => hash::run(&args).map(|()| Status::Ok),
+        Commands::Register { args } => register::run(&args).map(|()| Status::Ok),
+        Commands::Subtract { args } => subtract::run(&args).map(|()| Status::Ok),
+        Commands::Stl { args } => stl::run(&args).map(|()| Status::Ok),
+        Commands::Centerline { args } => centerline::run(&args).map(|()| Status::Ok),
+        Commands::Panoramic { args } => panoramic::run(&args).map(|()| Status::Ok),
+        Commands::Measure { args } => measure::run(&args).map(|()| Status::Ok),
+        Commands::Dose { args } => dose::run(&args).map(|()| Status::Ok),
+        Commands::GenTestData { args } => gen_test_data::run(&args).map(|()| Status::Ok),
+        Commands::VideoFromImages { args } => video_from_images::run(&args).map(|()| Status::Ok),
+        Commands::VerifyPixels { args } => verify_pixels::run(&args).map(|()| Status::Ok),
+        Commands::PrivateTags { args } => private_tags::run(&args).map(|()| Status::Ok),
+        #[cfg(feature = "gui")]
+        Commands::Gui { args } => gui::run(&args).map(|()| Status::Ok),
     }
 }
@@ -0,0 +1,376 @@
+//! Foreground masks for background (air) removal.
+//!
+//! `--strip-background` separates the patient from air, table, and noise:
+//!
+//! 1. **Threshold** — Otsu's method splits air from tissue.
+//! 2. **Largest component** — only the biggest connected foreground region
+//!    survives, dropping the table, headrest, and stray noise.
+//! 3. **Hole fill** — background regions not reachable from the image border
+//!    (sinuses, airways, ventricles below threshold) are put back.
+//!
+//! Removed voxels are set to the lowest foreground value, so auto-stretched
+//! exports spend their gray levels on tissue and STL meshes lose the table.
+
+use std::collections::VecDeque;
+
+/// Number of histogram bins for Otsu thresholding.
+const HISTOGRAM_BINS: usize = 256;
+
+/// Strip the background of a single `width` x `height` slice in place.
+///
+/// Returns the number of pixels that were removed.
+pub fn strip_background_2d(values: &mut [f32], width: usize, height: usize) -> usize {
+    strip_background(values, [width, height, 1])
+}
+
+/// Strip the background of a `cols` x `rows` x `slices` volume in place.
+///
+/// The connected component is found in 3D; holes are filled slice by slice
+/// so structures open at the top or bottom of the stack are still filled.
+///
+/// Returns the number of voxels that were removed.
+pub fn strip_background_3d(values: &mut [f32], cols: usize, rows: usize, slices: usize) -> usize {
+    strip_background(values, [cols, rows, slices])
+}
+
+fn strip_background(values: &mut [f32], dims: [usize; 3]) -> usize {
+    let mask = foreground_mask(values, dims);
+    let Some(fill) = values
+        .iter()
+        .zip(&mask)
+        .filter(|&(_, &keep)| keep)
+        .map(|(&v, _)| v)
+        .reduce(f32::min)
+    else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for (value, keep) in values.iter_mut().zip(mask) {
+        if !keep {
+            *value = fill;
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Foreground mask: Otsu threshold, largest component, holes filled per slice.
+pub fn foreground_mask(values: &[f32], dims: [usize; 3]) -> Vec<bool> {
+    let threshold = otsu_threshold(values);
+    let binary: Vec<bool> = values.iter().map(|&v| v > threshold).collect();
+    let mut mask = largest_component(&binary, dims);
+
+    let slice_size = dims[0] * dims[1];
+    if slice_size > 0 {
+        for slice in mask.chunks_exact_mut(slice_size) {
+            fill_holes(slice, dims[0], dims[1]);
+        }
+    }
+    mask
+}
+
+/// Keep only the largest 6-connected (4-connected in 2D) region of `binary`.
+fn largest_component(binary: &[bool], dims: [usize; 3]) -> Vec<bool> {
+    let mut labels = vec![0_u32; binary.len()];
+    let mut best_label = 0;
+    let mut best_size = 0;
+    let mut next_label = 0;
+    let mut queue = VecDeque::new();
+
+    for start in 0..binary.len() {
+        if !binary[start] || labels[start] != 0 {
+            continue;
+        }
+        next_label += 1;
+        labels[start] = next_label;
+        queue.push_back(start);
+        let mut size = 0;
+        while let Some(index) = queue.pop_front() {
+            size += 1;
+            for neighbor in neighbors(index, dims) {
+                if binary[neighbor] && labels[neighbor] == 0 {
+                    labels[neighbor] = next_label;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        if size > best_size {
+            best_size = size;
+            best_label = next_label;
+        }
+    }
+
+    labels
+        .into_iter()
+        .map(|label| label != 0 && label == best_label)
+        .collect()
+}
+
+/// Set background pixels that are not connected to the border to foreground.
+fn fill_holes(mask: &mut [bool], width: usize, height: usize) {
+    let dims = [width, height, 1];
+    let mut outside = vec![false; mask.len()];
+    let mut queue = VecDeque::new();
+
+    let border = (0..width)
+        .flat_map(|x| [x, x + (height - 1) * width])
+        .chain((0..height).flat_map(|y| [y * width, y * width + width - 1]));
+    for index in border {
+        if !mask[index] && !outside[index] {
+            outside[index] = true;
+            queue.push_back(index);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        for neighbor in neighbors(index, dims) {
+            if !mask[neighbor] && !outside[neighbor] {
+                outside[neighbor] = true;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    for (value, outside) in mask.iter_mut().zip(outside) {
+        *value = !outside;
+    }
+}
+
+/// Face neighbors of a flat index (X fastest, then Y, then Z).
+pub fn neighbors(index: usize, dims: [usize; 3]) -> impl Iterator<Item = usize> {
+    let [cols, rows, slices] = dims;
+    let slice = cols * rows;
+    let (x, y, z) = (index % cols, (index / cols) % rows, index / slice);
+    [
+        (x > 0).then(|| index - 1),
+        (x + 1 < cols).then(|| index + 1),
+        (y > 0).then(|| index - cols),
+        (y + 1 < rows).then(|| index + cols),
+        (z > 0).then(|| index - slice),
+        (z + 1 < slices).then(|| index + slice),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Compute the optimal threshold using Otsu's method.
+///
+/// Maximizes inter-class variance on a 256-bin histogram to find the
+/// threshold that best separates foreground from background.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn otsu_threshold(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    // Find value range
+    let min_val = values.iter().copied().reduce(f32::min).unwrap_or(0.0);
+    let max_val = values.iter().copied().reduce(f32::max).unwrap_or(255.0);
+    let range = max_val - min_val;
+
+    if range <= 0.0 {
+        return min_val;
+    }
+
+    // Build histogram
+    let mut histogram = [0u64; HISTOGRAM_BINS];
+    let scale = (HISTOGRAM_BINS - 1) as f32 / range;
+
+    for &val in values {
+        let bin = ((val - min_val) * scale) as usize;
+        let bin = bin.min(HISTOGRAM_BINS - 1);
+        histogram[bin] += 1;
+    }
+
+    let total = values.len() as f64;
+
+    // Compute total weighted sum
+    let total_sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| i as f64 * count as f64)
+        .sum();
+
+    let mut best_threshold_first = 0;
+    let mut best_threshold_last = 0;
+    let mut best_variance = 0.0_f64;
+    let mut background_count = 0.0_f64;
+    let mut background_sum = 0.0_f64;
+
+    for (t, &count) in histogram.iter().enumerate() {
+        background_count += count as f64;
+        if background_count == 0.0 {
+            continue;
+        }
+
+        let foreground_count = total - background_count;
+        if foreground_count == 0.0 {
+            break;
+        }
+
+        background_sum = (t as f64).mul_add(count as f64, background_sum);
+        let foreground_sum = total_sum - background_sum;
+
+        let background_mean = background_sum / background_count;
+        let foreground_mean = foreground_sum / foreground_count;
+        let diff = background_mean - foreground_mean;
+
+        let variance = background_count * foreground_count * diff * diff;
+
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold_first = t;
+            best_threshold_last = t;
+        } else if (variance - best_variance).abs() < f64::EPSILON * best_variance.abs() {
+            best_threshold_last = t;
+        }
+    }
+
+    // Average first and last bins with max variance for symmetric distributions
+    let best_threshold = usize::midpoint(best_threshold_first, best_threshold_last);
+
+    // Convert bin index back to value
+    min_val + best_threshold as f32 / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // =========================================================================
+    // Otsu Threshold Tests
+    // =========================================================================
+
+    mod otsu {
+        use super::*;
+
+        #[test]
+        fn bimodal_distribution_finds_midpoint() {
+            // 50 values at 50.0, 50 values at 200.0
+            let mut values = vec![50.0_f32; 50];
+            values.extend(vec![200.0_f32; 50]);
+
+            let threshold = otsu_threshold(&values);
+
+            // Threshold should be between the two peaks
+            assert!(
+                threshold > 50.0 && threshold < 200.0,
+                "Expected threshold between 50 and 200, got {threshold}"
+            );
+        }
+
+        #[test]
+        fn uniform_values_returns_minimum() {
+            let values = vec![100.0_f32; 100];
+            let threshold = otsu_threshold(&values);
+            assert!(
+                (threshold - 100.0).abs() < f32::EPSILON,
+                "Expected ~100.0 for uniform data, got {threshold}"
+            );
+        }
+
+        #[test]
+        fn empty_input_returns_zero() {
+            assert!((otsu_threshold(&[]) - 0.0).abs() < f32::EPSILON);
+        }
+
+        #[test]
+        fn single_value_returns_that_value() {
+            let threshold = otsu_threshold(&[42.0]);
+            assert!(
+                (threshold - 42.0).abs() < f32::EPSILON,
+                "Expected 42.0, got {threshold}"
+            );
+        }
+    }
+
+    // =========================================================================
+    // Background Stripping Tests
+    // =========================================================================
+
+    mod strip {
+        use super::*;
+
+        /// Parse a picture: `#` tissue (100), `o` low-density inside tissue (0),
+        /// `.` air (-1000).
+        fn picture(rows: &[&str]) -> (Vec<f32>, usize, usize) {
+            let values = rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .map(|c| match c {
+                    '#' => 100.0,
+                    'o' => 0.0,
+                    _ => -1000.0,
+                })
+                .collect();
+            (values, rows[0].len(), rows.len())
+        }
+
+        #[test]
+        fn keeps_largest_component_only() {
+            let (values, w, h) = picture(&[
+                "........", //
+                ".####...", //
+                ".####..#", //
+                ".####..#", //
+                "........", //
+            ]);
+            let mask = foreground_mask(&values, [w, h, 1]);
+            assert!(mask[w + 1], "body is kept");
+            assert!(!mask[2 * w + 7], "small table region is dropped");
+        }
+
+        #[test]
+        fn fills_enclosed_holes() {
+            let (mut values, w, h) = picture(&[
+                "..........", //
+                ".########.", //
+                ".#......#.", //
+                ".#......#.", //
+                ".########.", //
+                "..........", //
+            ]);
+            let mask = foreground_mask(&values, [w, h, 1]);
+            assert!(mask[2 * w + 4], "enclosed air is filled");
+            assert!(!mask[0], "outside air stays background");
+
+            // 20 ring pixels + 12 filled interior pixels survive
+            assert_eq!(strip_background_2d(&mut values, w, h), w * h - 32);
+        }
+
+        #[test]
+        fn removed_pixels_take_lowest_foreground_value() {
+            let (mut values, w, h) = picture(&[
+                ".....", //
+                ".#o#.", //
+                ".###.", //
+                ".....", //
+            ]);
+            strip_background_2d(&mut values, w, h);
+            assert!((values[0] - 0.0).abs() < f32::EPSILON, "got {}", values[0]);
+        }
+
+        #[test]
+        fn component_is_connected_across_slices() {
+            // Two slices: the body spans both, a speck sits alone on slice 2
+            let (mut first, w, h) = picture(&["....", ".##.", ".##.", "...."]);
+            let (second, _, _) = picture(&["#...", ".##.", ".##.", "...."]);
+            first.extend(second);
+            let mask = foreground_mask(&first, [w, h, 2]);
+            assert!(mask[w + 1] && mask[w * h + w + 1]);
+            assert!(!mask[w * h], "isolated corner speck is dropped");
+        }
+
+        #[test]
+        fn uniform_image_is_untouched() {
+            let mut values = vec![5.0_f32; 16];
+            assert_eq!(strip_background_2d(&mut values, 4, 4), 0);
+            assert!(values.iter().all(|&v| (v - 5.0).abs() < f32::EPSILON));
+        }
+    }
+}
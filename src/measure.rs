@@ -0,0 +1,431 @@
+//! ROI measurements (`measure`).
+//!
+//! Reports the mean, standard deviation, and range of the calibrated values
+//! (Hounsfield units for CT) inside circles and boxes drawn on slices of a
+//! series, e.g. to check bone density on a vertebra or the attenuation of a
+//! lesion without opening a workstation. Each `--roi` names a shape on one
+//! slice in pixel coordinates, as viewers show them; slices are counted from
+//! 0 in position order, frames of multi-frame objects included. PET images
+//! are measured in SUV with `--suv`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+
+use crate::convert::suv;
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::volume::{self, Volume};
+
+/// Columns of the CSV output.
+const CSV_HEADER: &str = "roi,shape,slice,pixels,area_mm2,mean,std,min,max";
+
+/// CLI arguments for the `measure` subcommand.
+#[derive(Args, Debug)]
+pub struct MeasureArgs {
+    /// Series folder (all .dcm files form one volume)
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Region to measure, repeatable: `circle:SLICE:X,Y,R` or
+    /// `box:SLICE:X0,Y0,X1,Y1` in pixels, optionally named as `NAME=...`
+    #[arg(long = "roi", value_name = "ROI", required = true, value_parser = parse_roi)]
+    pub rois: Vec<Roi>,
+
+    /// Measure PET images in body-weight SUV instead of Bq/ml
+    #[arg(long)]
+    pub suv: bool,
+
+    /// Also write the measurements to this file; the extension picks the
+    /// format (.json or .csv)
+    #[arg(long = "out")]
+    pub output: Option<PathBuf>,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// A region on one slice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Roi {
+    /// Name given on the command line.
+    name: Option<String>,
+    /// Slice index in position order.
+    slice: usize,
+    shape: Shape,
+}
+
+/// Outline of a region, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Shape {
+    /// Pixels whose centers lie within `radius` of (`x`, `y`).
+    Circle { x: f64, y: f64, radius: f64 },
+    /// Pixels from (`x0`, `y0`) to (`x1`, `y1`), both corners included.
+    Box {
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    },
+}
+
+/// Measurements file format, from the output extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    Json,
+    Csv,
+}
+
+/// Statistics of one region.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Measurement {
+    roi: String,
+    shape: &'static str,
+    slice: usize,
+    pixels: usize,
+    area_mm2: f64,
+    mean: f64,
+    std: f64,
+    min: f64,
+    max: f64,
+}
+
+/// Measure every region of `args` and print the results.
+pub fn run(args: &MeasureArgs) -> Result<()> {
+    let format = args.output.as_deref().map(report_format).transpose()?;
+    let (files, mut volume) = volume::load_series(&args.input, 1, args.follow_symlinks)?;
+    println!("  {}", t!("stl-building-volume", count = files.len()));
+    if args.suv {
+        suv::scale_volume(&mut volume, &files)?;
+    }
+
+    let measurements = args
+        .rois
+        .iter()
+        .enumerate()
+        .map(|(index, roi)| measure(&volume, roi, &roi.name(index)))
+        .collect::<Result<Vec<_>>>()?;
+    for m in &measurements {
+        println!(
+            "{}",
+            t!(
+                "measure-roi",
+                roi = m.roi.as_str(),
+                shape = m.shape,
+                slice = m.slice,
+                pixels = m.pixels,
+                area = format!("{:.1}", m.area_mm2),
+                mean = format!("{:.1}", m.mean),
+                std = format!("{:.1}", m.std),
+                min = format!("{:.1}", m.min),
+                max = format!("{:.1}", m.max)
+            )
+        );
+    }
+
+    if let (Some(path), Some(format)) = (&args.output, format) {
+        write(path, format, &measurements)?;
+        println!("{}", t!("measure-saved", path = path.display().to_string()));
+    }
+    Ok(())
+}
+
+/// Parse `[NAME=]circle:SLICE:X,Y,R` or `[NAME=]box:SLICE:X0,Y0,X1,Y1`.
+fn parse_roi(value: &str) -> std::result::Result<Roi, String> {
+    let (name, spec) = match value.split_once('=') {
+        Some((name, spec)) if !name.trim().is_empty() => (Some(name.trim().to_string()), spec),
+        Some(_) => return Err(format!("`{value}` has an empty name before `=`")),
+        None => (None, value),
+    };
+    let mut parts = spec.splitn(3, ':');
+    let (Some(kind), Some(slice), Some(coords)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!(
+            "`{value}` is not a region; expected circle:SLICE:X,Y,R or box:SLICE:X0,Y0,X1,Y1"
+        ));
+    };
+    let slice = slice
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("`{slice}` is not a slice index"))?;
+    let coords: Vec<&str> = coords.split(',').map(str::trim).collect();
+    let shape = match (kind.trim().to_ascii_lowercase().as_str(), coords.as_slice()) {
+        ("circle", [x, y, r]) => {
+            let number = |text: &str| {
+                text.parse::<f64>()
+                    .ok()
+                    .filter(|n| n.is_finite())
+                    .ok_or_else(|| format!("`{text}` is not a number"))
+            };
+            let (x, y, radius) = (number(x)?, number(y)?, number(r)?);
+            if radius <= 0.0 {
+                return Err(format!("`{value}` needs a radius above 0"));
+            }
+            Shape::Circle { x, y, radius }
+        }
+        ("box", [x0, y0, x1, y1]) => {
+            let index = |text: &str| {
+                text.parse::<usize>()
+                    .map_err(|_| format!("`{text}` is not a pixel index"))
+            };
+            let (x0, y0, x1, y1) = (index(x0)?, index(y0)?, index(x1)?, index(y1)?);
+            Shape::Box {
+                x0: x0.min(x1),
+                y0: y0.min(y1),
+                x1: x0.max(x1),
+                y1: y0.max(y1),
+            }
+        }
+        ("circle", _) => return Err(format!("`{value}` needs X,Y,R after the slice")),
+        ("box", _) => return Err(format!("`{value}` needs X0,Y0,X1,Y1 after the slice")),
+        _ => {
+            return Err(format!(
+                "`{kind}` is not a region shape; expected circle or box"
+            ));
+        }
+    };
+    Ok(Roi { name, slice, shape })
+}
+
+impl Roi {
+    /// Name to report the region under: its own, else its position among
+    /// the `--roi` options, counted from 1.
+    fn name(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("roi{}", index + 1))
+    }
+
+    /// `(column, row)` of the pixels of a `cols` × `rows` slice inside the
+    /// region.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn pixels(&self, cols: usize, rows: usize) -> Vec<(usize, usize)> {
+        if cols == 0 || rows == 0 {
+            return Vec::new();
+        }
+        match self.shape {
+            Shape::Circle { x, y, radius } => {
+                let clamp = |v: f64, len: usize| v.clamp(0.0, (len - 1) as f64) as usize;
+                let (left, right) = (clamp((x - radius).ceil(), cols), clamp(x + radius, cols));
+                let (top, bottom) = (clamp((y - radius).ceil(), rows), clamp(y + radius, rows));
+                (top..=bottom)
+                    .flat_map(|row| (left..=right).map(move |col| (col, row)))
+                    .filter(|&(col, row)| (col as f64 - x).hypot(row as f64 - y) <= radius)
+                    .collect()
+            }
+            Shape::Box { x0, y0, x1, y1 } => {
+                if x0 >= cols || y0 >= rows {
+                    return Vec::new();
+                }
+                (y0..=y1.min(rows - 1))
+                    .flat_map(|row| (x0..=x1.min(cols - 1)).map(move |col| (col, row)))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Statistics of `volume` inside `roi`, reported as `name`.
+#[allow(clippy::cast_precision_loss)]
+fn measure(volume: &Volume, roi: &Roi, name: &str) -> Result<Measurement> {
+    if roi.slice >= volume.slices {
+        anyhow::bail!(BadInput(format!(
+            "ROI {name} is on slice {}, but the series has {} slices (counted from 0)",
+            roi.slice, volume.slices
+        )));
+    }
+    let pixels = roi.pixels(volume.cols, volume.rows);
+    if pixels.is_empty() {
+        anyhow::bail!(BadInput(format!(
+            "ROI {name} holds no pixels of the {}×{} slices",
+            volume.cols, volume.rows
+        )));
+    }
+    let plane = roi.slice * volume.cols * volume.rows;
+    let values: Vec<f64> = pixels
+        .iter()
+        .map(|&(col, row)| f64::from(volume.values[plane + row * volume.cols + col]))
+        .collect();
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    // Sample standard deviation, as workstations report it
+    let std = if values.len() > 1 {
+        (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    let pixel_area = f64::from(volume.spacing_x) * f64::from(volume.spacing_y);
+    Ok(Measurement {
+        roi: name.to_string(),
+        shape: match roi.shape {
+            Shape::Circle { .. } => "circle",
+            Shape::Box { .. } => "box",
+        },
+        slice: roi.slice,
+        pixels: values.len(),
+        area_mm2: count * pixel_area,
+        mean,
+        std,
+        min: values.iter().copied().fold(f64::INFINITY, f64::min),
+        max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    })
+}
+
+/// Output format picked by the file extension.
+fn report_format(path: &Path) -> Result<ReportFormat> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("json") => Ok(ReportFormat::Json),
+        Some("csv") => Ok(ReportFormat::Csv),
+        _ => anyhow::bail!(BadInput(format!(
+            "Unsupported measurements file {}; expected .json or .csv",
+            path.display()
+        ))),
+    }
+}
+
+impl Measurement {
+    /// The measurement as a CSV line.
+    fn csv_row(&self) -> String {
+        let roi = if self.roi.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", self.roi.replace('"', "\"\""))
+        } else {
+            self.roi.clone()
+        };
+        format!(
+            "{roi},{},{},{},{:.3},{:.3},{:.3},{},{}",
+            self.shape,
+            self.slice,
+            self.pixels,
+            self.area_mm2,
+            self.mean,
+            self.std,
+            self.min,
+            self.max
+        )
+    }
+}
+
+/// Write the measurements in `format`.
+fn write(path: &Path, format: ReportFormat, measurements: &[Measurement]) -> Result<()> {
+    let text = match format {
+        ReportFormat::Json => serde_json::to_string_pretty(measurements)? + "\n",
+        ReportFormat::Csv => {
+            let mut text = format!("{CSV_HEADER}\n");
+            for measurement in measurements {
+                text.push_str(&measurement.csv_row());
+                text.push('\n');
+            }
+            text
+        }
+    };
+    fs::write(path, text)
+        .with_context(|| format!("Failed to write measurements: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two 4×3 slices valued by position: `100 * slice + 10 * row + col`.
+    fn volume() -> Volume {
+        let values = (0..2)
+            .flat_map(|z| {
+                (0..3).flat_map(move |y| (0..4).map(move |x| (100 * z + 10 * y + x) as f32))
+            })
+            .collect();
+        Volume {
+            values,
+            cols: 4,
+            rows: 3,
+            slices: 2,
+            spacing_x: 0.5,
+            spacing_y: 0.5,
+            spacing_z: 1.0,
+            origin: [0.0; 3],
+        }
+    }
+
+    #[test]
+    fn regions_are_parsed() {
+        assert_eq!(
+            parse_roi("L1=circle:40:256.5,300,15").unwrap(),
+            Roi {
+                name: Some("L1".into()),
+                slice: 40,
+                shape: Shape::Circle {
+                    x: 256.5,
+                    y: 300.0,
+                    radius: 15.0
+                }
+            }
+        );
+        // Corners in any order
+        assert_eq!(
+            parse_roi("box:3:20,30,10,5").unwrap().shape,
+            Shape::Box {
+                x0: 10,
+                y0: 5,
+                x1: 20,
+                y1: 30
+            }
+        );
+        for bad in [
+            "circle:1:2,3",
+            "circle:1:2,3,0",
+            "box:1:2,3,4",
+            "ellipse:1:2,3,4",
+            "circle:-1:2,3,4",
+            "=box:1:2,3,4,5",
+            "box:1",
+        ] {
+            assert!(parse_roi(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn a_box_averages_its_pixels() {
+        let roi = parse_roi("box:1:1,0,2,1").unwrap();
+        let m = measure(&volume(), &roi, "b").unwrap();
+        // 101, 102, 111, 112
+        assert_eq!(m.pixels, 4);
+        assert!((m.mean - 106.5).abs() < 1e-9);
+        assert!((m.std - (101.0_f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!((m.min, m.max), (101.0, 112.0));
+        assert!((m.area_mm2 - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_circle_is_clipped_to_the_slice() {
+        // Radius 1 around the corner: the corner, right and below
+        let roi = parse_roi("circle:0:0,0,1").unwrap();
+        assert_eq!(roi.pixels(4, 3), vec![(0, 0), (1, 0), (0, 1)]);
+        let m = measure(&volume(), &roi, "c").unwrap();
+        assert!((m.mean - 11.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn regions_off_the_series_are_bad_input() {
+        let volume = volume();
+        for roi in ["box:2:0,0,1,1", "box:0:4,0,5,1", "circle:0:10,10,2"] {
+            let err = measure(&volume, &parse_roi(roi).unwrap(), "r").unwrap_err();
+            assert!(err.downcast_ref::<BadInput>().is_some(), "{roi}");
+        }
+    }
+
+    #[test]
+    fn csv_has_one_row_per_region() {
+        let m = measure(&volume(), &parse_roi("a,b=box:0:0,0,0,0").unwrap(), "a,b").unwrap();
+        assert_eq!(m.csv_row(), "\"a,b\",box,0,1,0.250,0.000,0.000,0,0");
+    }
+}
@@ -0,0 +1,267 @@
+//! Header summaries of DICOM files and series.
+//!
+//! Grouping, sorting, listing, and the converters all need the same few
+//! attributes of every file: which study and series it belongs to, where
+//! its slice lies, how it is sampled and windowed, and how many frames it
+//! holds. [`InstanceMeta`] reads them once from a file's header (or from an
+//! object already opened for something else), so later stages work from
+//! typed values instead of opening the file again. [`SeriesMeta`] gathers
+//! the instances of one group in slice order.
+
+use std::path::{Path, PathBuf};
+
+use dicom::core::Tag;
+#[cfg(test)]
+use dicom::core::{DataElement, PrimitiveValue, VR};
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, OpenFileOptions};
+
+use crate::pipeline;
+use crate::pixel::{BitDepth, Window, groups};
+
+/// What the header of one file says about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceMeta {
+    pub path: PathBuf,
+    pub modality: Option<String>,
+    pub study_uid: Option<String>,
+    pub series_uid: Option<String>,
+    pub sop_instance_uid: Option<String>,
+    pub frame_of_reference_uid: Option<String>,
+    pub series_number: Option<i64>,
+    pub series_description: Option<String>,
+    pub instance_number: Option<i64>,
+    /// `ImagePositionPatient` of the first frame (mm).
+    pub position: Option<[f64; 3]>,
+    /// `ImageOrientationPatient`: row then column direction cosines.
+    pub orientation: Option<[f64; 6]>,
+    /// Between rows, between columns (mm).
+    pub pixel_spacing: Option<[f64; 2]>,
+    pub rows: Option<u16>,
+    pub columns: Option<u16>,
+    /// `NumberOfFrames`, 1 for single-frame objects.
+    pub frames: u32,
+    pub bit_depth: Option<BitDepth>,
+    /// First VOI window of the first frame.
+    pub window: Option<Window>,
+}
+
+impl InstanceMeta {
+    /// Read the header of the file at `path`. A file that cannot be read
+    /// gets a summary with nothing but its path and a single frame, so it
+    /// still sorts (last) and counts.
+    pub fn read(path: &Path) -> Self {
+        OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+            .map_or_else(
+                |_| Self::unreadable(path),
+                |obj| Self::from_object(path, &obj),
+            )
+    }
+
+    /// Summary of `obj`, already opened from `path`.
+    pub fn from_object(path: &Path, obj: &InMemDicomObject) -> Self {
+        let text = |tag| text(obj, tag);
+        let int = |tag| obj.element(tag).ok().and_then(|e| e.to_int::<i64>().ok());
+        let small = |tag| obj.element(tag).ok().and_then(|e| e.to_int::<u16>().ok());
+        Self {
+            path: path.to_path_buf(),
+            modality: text(tags::MODALITY),
+            study_uid: text(tags::STUDY_INSTANCE_UID),
+            series_uid: text(tags::SERIES_INSTANCE_UID),
+            sop_instance_uid: text(tags::SOP_INSTANCE_UID),
+            frame_of_reference_uid: text(tags::FRAME_OF_REFERENCE_UID),
+            series_number: int(tags::SERIES_NUMBER),
+            series_description: text(tags::SERIES_DESCRIPTION),
+            instance_number: int(tags::INSTANCE_NUMBER),
+            position: groups::image_position(obj, 0),
+            orientation: orientation(obj),
+            pixel_spacing: groups::pixel_spacing(obj, 0),
+            rows: small(tags::ROWS),
+            columns: small(tags::COLUMNS),
+            frames: pipeline::number_of_frames(obj),
+            bit_depth: BitDepth::from_object(obj),
+            window: Window::of_frame(obj, 0),
+        }
+    }
+
+    /// Summary of a file whose header could not be read: its path and a
+    /// single frame.
+    pub fn unreadable(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            modality: None,
+            study_uid: None,
+            series_uid: None,
+            sop_instance_uid: None,
+            frame_of_reference_uid: None,
+            series_number: None,
+            series_description: None,
+            instance_number: None,
+            position: None,
+            orientation: None,
+            pixel_spacing: None,
+            rows: None,
+            columns: None,
+            frames: 1,
+            bit_depth: None,
+            window: None,
+        }
+    }
+}
+
+impl AsRef<Path> for InstanceMeta {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The instances of one group, in slice order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SeriesMeta {
+    pub instances: Vec<InstanceMeta>,
+}
+
+impl SeriesMeta {
+    /// The group of `instances`, which must already be in slice order.
+    pub const fn new(instances: Vec<InstanceMeta>) -> Self {
+        Self { instances }
+    }
+
+    /// The first instance, which speaks for the series.
+    pub fn first(&self) -> Option<&InstanceMeta> {
+        self.instances.first()
+    }
+
+    pub fn modality(&self) -> Option<&str> {
+        self.first()?.modality.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.first()?.series_description.as_deref()
+    }
+
+    /// Paths of the instances, in slice order.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.instances.iter().map(|i| i.path.clone()).collect()
+    }
+
+    /// Frames across all instances.
+    pub fn frames(&self) -> usize {
+        self.instances
+            .iter()
+            .map(|i| usize::try_from(i.frames).unwrap_or(usize::MAX))
+            .sum()
+    }
+}
+
+/// A text element `value` of `tag`, for building test objects.
+#[cfg(test)]
+pub(crate) fn text_element(tag: Tag, vr: VR, value: &str) -> DataElement<InMemDicomObject> {
+    DataElement::new(tag, vr, PrimitiveValue::from(value))
+}
+
+/// Trimmed text of `tag`; `None` when missing or blank.
+pub(crate) fn text(obj: &InMemDicomObject, tag: Tag) -> Option<String> {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .map(|value| value.trim_end_matches(['\0', ' ']).trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// `ImageOrientationPatient`, top-level or from the Plane Orientation macro.
+fn orientation(obj: &InMemDicomObject) -> Option<[f64; 6]> {
+    let six = |obj: &InMemDicomObject| {
+        let text = obj
+            .element(tags::IMAGE_ORIENTATION_PATIENT)
+            .ok()?
+            .to_str()
+            .ok()?;
+        let values: Vec<f64> = text
+            .split('\\')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        values.try_into().ok()
+    };
+    six(obj).or_else(|| six(groups::item(obj, 0, tags::PLANE_ORIENTATION_SEQUENCE)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::VR;
+
+    use super::*;
+
+    fn object(elements: &[(Tag, VR, &str)]) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter(
+            elements
+                .iter()
+                .map(|(tag, vr, value)| text_element(*tag, *vr, value)),
+        )
+    }
+
+    #[test]
+    fn header_values_are_typed_and_trimmed() {
+        let obj = object(&[
+            (tags::MODALITY, VR::CS, "CT"),
+            (tags::SERIES_DESCRIPTION, VR::LO, "Axial 1mm "),
+            (tags::SERIES_NUMBER, VR::IS, "3 "),
+            (tags::INSTANCE_NUMBER, VR::IS, "12"),
+            (tags::IMAGE_POSITION_PATIENT, VR::DS, "-10\\-20\\35.5"),
+            (tags::IMAGE_ORIENTATION_PATIENT, VR::DS, "1\\0\\0\\0\\1\\0"),
+            (tags::PIXEL_SPACING, VR::DS, "0.5\\0.75"),
+            (tags::WINDOW_CENTER, VR::DS, "40"),
+            (tags::WINDOW_WIDTH, VR::DS, "400"),
+            (tags::SERIES_INSTANCE_UID, VR::UI, "1.2.3\0"),
+        ]);
+        let meta = InstanceMeta::from_object(Path::new("a.dcm"), &obj);
+        assert_eq!(meta.modality.as_deref(), Some("CT"));
+        assert_eq!(meta.series_description.as_deref(), Some("Axial 1mm"));
+        assert_eq!(meta.series_uid.as_deref(), Some("1.2.3"));
+        assert_eq!(
+            (meta.series_number, meta.instance_number),
+            (Some(3), Some(12))
+        );
+        assert_eq!(meta.position, Some([-10.0, -20.0, 35.5]));
+        assert_eq!(meta.orientation, Some([1.0, 0.0, 0.0, 0.0, 1.0, 0.0]));
+        assert_eq!(meta.pixel_spacing, Some([0.5, 0.75]));
+        assert_eq!(meta.frames, 1);
+        assert_eq!(
+            meta.window,
+            Some(Window {
+                center: 40.0,
+                width: 400.0
+            })
+        );
+        assert_eq!(meta.study_uid, None);
+    }
+
+    #[test]
+    fn unreadable_files_keep_their_path() {
+        let meta = InstanceMeta::read(Path::new("/nonexistent/a.dcm"));
+        assert_eq!(meta.path, Path::new("/nonexistent/a.dcm"));
+        assert_eq!((meta.position, meta.frames), (None, 1));
+    }
+
+    #[test]
+    fn series_speak_through_their_first_instance() {
+        let mut multi = InstanceMeta::from_object(
+            Path::new("b.dcm"),
+            &object(&[(tags::NUMBER_OF_FRAMES, VR::IS, "4")]),
+        );
+        multi.modality = Some("MR".to_string());
+        let series = SeriesMeta::new(vec![
+            multi,
+            InstanceMeta::read(Path::new("/nonexistent/c.dcm")),
+        ]);
+        assert_eq!(series.modality(), Some("MR"));
+        assert_eq!(series.description(), None);
+        assert_eq!(series.frames(), 5);
+        assert_eq!(
+            series.files(),
+            [PathBuf::from("b.dcm"), PathBuf::from("/nonexistent/c.dcm")]
+        );
+    }
+}
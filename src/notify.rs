@@ -0,0 +1,164 @@
+//! Completion notices for hands-off pipelines (`convert --notify-webhook`).
+//!
+//! When a run ends, its summary is posted as JSON to a webhook: a chat
+//! channel, a ticketing system, or a relay that emails whoever waits for the
+//! export. Aborted runs are posted too, with their error. Delivery is best
+//! effort: a failed post only warns, since the conversion is already done.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+
+use crate::convert::{EncodeFailure, MeshStats};
+use crate::i18n::t;
+use crate::outcome::{Status, Summary};
+
+/// Longest wait for the webhook to answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Accept only `http://` and `https://` URLs for `--notify-webhook`.
+pub fn parse_webhook(value: &str) -> std::result::Result<String, String> {
+    let scheme = value.split_once("://").map(|(scheme, _)| scheme);
+    match scheme.map(str::to_ascii_lowercase).as_deref() {
+        Some("http" | "https") => Ok(value.to_string()),
+        _ => Err(format!("`{value}` is not an http:// or https:// URL")),
+    }
+}
+
+/// What a finished `convert` run reports.
+pub struct Notice<'a> {
+    /// Input folder.
+    pub input: &'a Path,
+    /// Output folder.
+    pub output: &'a Path,
+    /// The run's summary, or why it aborted.
+    pub run: &'a Result<Summary>,
+    /// How long the run took.
+    pub elapsed: Duration,
+}
+
+impl Notice<'_> {
+    /// Post this notice to `url`, warning when it could not be delivered.
+    pub fn send(&self, url: &str) {
+        if let Err(e) = post(url, &self.payload()) {
+            eprintln!("{}", t!("notify-failed", error = format!("{e:#}")));
+        }
+    }
+
+    /// JSON body: the summary's counts plus where the output went.
+    fn payload(&self) -> Value {
+        let (status, summary, error) = match self.run {
+            Ok(summary) => (summary.status(), Some(summary), None),
+            Err(e) => (Status::from_error(e), None, Some(format!("{e:#}"))),
+        };
+        let mut payload = json!({
+            "tool": "dcm-toolbox",
+            "version": env!("CARGO_PKG_VERSION"),
+            "command": "convert",
+            "status": status.label(),
+            "exit": status.code(),
+            "input": self.input.display().to_string(),
+            "output": self.output.display().to_string(),
+            "duration_seconds": self.elapsed.as_secs_f64(),
+        });
+        if let Some(summary) = summary {
+            payload["groups"] = summary.groups.into();
+            payload["groups_failed"] = summary.groups_failed.into();
+            payload["frames"] = summary.frames.into();
+            payload["frames_failed"] = summary.frames_failed.into();
+            payload["meshes"] = summary.meshes.iter().map(mesh).collect();
+            payload["encode_failures"] = summary.encodes.iter().map(encode).collect();
+        }
+        if let Some(error) = error {
+            payload["error"] = error.into();
+        }
+        payload
+    }
+}
+
+/// A written model in the notice.
+fn mesh(mesh: &MeshStats) -> Value {
+    json!({
+        "path": mesh.path.display().to_string(),
+        "triangles": mesh.triangles,
+        "volume_mm3": mesh.volume,
+    })
+}
+
+/// A failed encode in the notice.
+fn encode(failure: &EncodeFailure) -> Value {
+    json!({
+        "path": failure.path.display().to_string(),
+        "encoder": failure.encoder,
+        "retried": failure.recovered,
+    })
+}
+
+/// POST `payload` as JSON, failing on anything but a 2xx answer.
+fn post(url: &str, payload: &Value) -> Result<()> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .new_agent();
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(payload.to_string())
+        .with_context(|| format!("Failed to notify {url}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outcome::BadInput;
+
+    #[test]
+    fn only_http_urls_are_webhooks() {
+        assert!(parse_webhook("https://hooks.example.org/T0/B1").is_ok());
+        assert!(parse_webhook("HTTP://10.0.0.5:8080/done").is_ok());
+        for bad in ["hooks.example.org", "ftp://example.org", "mailto:a@b.org"] {
+            assert!(parse_webhook(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn notice_carries_the_summary() {
+        let run = Ok(Summary {
+            groups: 3,
+            groups_failed: 1,
+            frames: 118,
+            ..Summary::default()
+        });
+        let notice = Notice {
+            input: Path::new("in"),
+            output: Path::new("out"),
+            run: &run,
+            elapsed: Duration::from_secs(42),
+        };
+        let payload = notice.payload();
+        assert_eq!(payload["status"], "partial");
+        assert_eq!(payload["exit"], 2);
+        assert_eq!(payload["groups_failed"], 1);
+        assert_eq!(payload["output"], "out");
+        assert_eq!(payload["meshes"], json!([]));
+        assert!(payload.get("error").is_none());
+    }
+
+    #[test]
+    fn aborted_run_sends_its_error() {
+        let run = Err(BadInput("Input folder not found".into()).into());
+        let notice = Notice {
+            input: Path::new("in"),
+            output: Path::new("out"),
+            run: &run,
+            elapsed: Duration::ZERO,
+        };
+        let payload = notice.payload();
+        assert_eq!(payload["status"], "bad_input");
+        assert_eq!(payload["error"], "Input folder not found");
+        assert!(payload.get("frames").is_none());
+    }
+}
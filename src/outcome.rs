@@ -0,0 +1,304 @@
+//! Exit codes and the machine-readable run summary.
+//!
+//! Wrapper scripts can branch on the process exit code:
+//!
+//! | Code | Status              | Meaning                                              |
+//! | ---- | ------------------- | ---------------------------------------------------- |
+//! | 0    | `ok`                | Everything was converted                             |
+//! | 1    | `error`             | Unexpected failure (I/O, encoder, ...)               |
+//! | 2    | `partial`           | Some frames or series failed, the rest was converted |
+//! | 3    | `nothing_converted` | No input found, or every input failed                |
+//! | 4    | `bad_input`         | Invalid arguments, missing input, unreadable stream  |
+//! | 130  | `interrupted`       | Stopped by Ctrl-C; the summary counts what completed |
+//!
+//! `convert` always finishes with a single `summary key=value ...` line on
+//! stderr (stdout may carry image bytes in piping mode). STL runs print one
+//! `mesh key=value ... path=<file>` line per written model just before it,
+//! and videos an `encode status=failed|retried encoder=... path=<file>` line
+//! per MP4 ffmpeg failed on (`retried` when `--encode-fallback` wrote it).
+//! `--metrics-file` writes the same counts for Prometheus.
+
+mod metrics;
+
+use std::fmt;
+use std::ops::AddAssign;
+
+use crate::cancel::{self, INTERRUPTED_CODE, Stopped};
+use crate::convert::{EncodeFailure, MeshStats};
+use crate::pipeline::RunStats;
+
+pub use self::metrics::record_metrics;
+
+/// Outcome of a run, mapped to a process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Everything was converted
+    Ok,
+    /// Unexpected failure
+    Error,
+    /// Some frames or series failed
+    Partial,
+    /// Nothing was converted
+    NothingConverted,
+    /// Invalid arguments or input
+    BadInput,
+    /// Stopped by Ctrl-C
+    Interrupted,
+}
+
+impl Status {
+    /// Process exit code for this status.
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Ok => 0,
+            Self::Error => 1,
+            Self::Partial => 2,
+            Self::NothingConverted => 3,
+            Self::BadInput => 4,
+            Self::Interrupted => INTERRUPTED_CODE,
+        }
+    }
+
+    /// Stable label used in the summary line.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+            Self::Partial => "partial",
+            Self::NothingConverted => "nothing_converted",
+            Self::BadInput => "bad_input",
+            Self::Interrupted => "interrupted",
+        }
+    }
+
+    /// Classify an error that aborted the run.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        if err.downcast_ref::<BadInput>().is_some() {
+            Self::BadInput
+        } else if cancel::stopped_by(err).is_some_and(Stopped::ends_run) {
+            Self::Interrupted
+        } else {
+            Self::Error
+        }
+    }
+
+    /// Summary line for a run that aborted before producing counts.
+    pub fn summary_line(self) -> String {
+        format!("summary status={} exit={}", self.label(), self.code())
+    }
+}
+
+/// Error raised for invalid user input (exit code 4).
+#[derive(Debug)]
+pub struct BadInput(pub String);
+
+impl fmt::Display for BadInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BadInput {}
+
+/// Counts accumulated over a whole `convert` run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Series/groups processed.
+    pub groups: usize,
+    /// Series/groups that failed as a whole (e.g. encoder or mesh errors).
+    pub groups_failed: usize,
+    /// Frames (or slices) converted.
+    pub frames: usize,
+    /// Frames that failed to load, render, or write.
+    pub frames_failed: usize,
+    /// Measurements of every STL written.
+    pub meshes: Vec<MeshStats>,
+    /// Every MP4 ffmpeg failed on, recovered or not.
+    pub encodes: Vec<EncodeFailure>,
+    /// Ctrl-C stopped the run before every group was converted.
+    pub interrupted: bool,
+}
+
+impl Summary {
+    /// Overall status derived from the counts.
+    pub const fn status(&self) -> Status {
+        if self.interrupted {
+            Status::Interrupted
+        } else if self.frames == 0 {
+            Status::NothingConverted
+        } else if self.groups_failed > 0 || self.frames_failed > 0 {
+            Status::Partial
+        } else {
+            Status::Ok
+        }
+    }
+
+    /// One-line `key=value` summary for scripts.
+    pub fn line(&self) -> String {
+        let status = self.status();
+        format!(
+            "summary status={} exit={} groups={} groups_failed={} frames={} frames_failed={}",
+            status.label(),
+            status.code(),
+            self.groups,
+            self.groups_failed,
+            self.frames,
+            self.frames_failed
+        )
+    }
+
+    /// Summary lines: one `mesh` line per model and one `encode` line per
+    /// failed encode, then the `summary` line.
+    pub fn lines(&self) -> Vec<String> {
+        self.meshes
+            .iter()
+            .map(MeshStats::line)
+            .chain(self.encodes.iter().map(EncodeFailure::line))
+            .chain([self.line()])
+            .collect()
+    }
+}
+
+impl AddAssign<RunStats> for Summary {
+    fn add_assign(&mut self, stats: RunStats) {
+        self.frames += stats.written;
+        self.frames_failed += stats.failed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_run_is_nothing_converted() {
+        assert_eq!(Summary::default().status(), Status::NothingConverted);
+    }
+
+    #[test]
+    fn all_frames_failed_is_nothing_converted() {
+        let summary = Summary {
+            groups: 1,
+            frames_failed: 5,
+            ..Summary::default()
+        };
+        assert_eq!(summary.status().code(), 3);
+    }
+
+    #[test]
+    fn some_failures_are_partial() {
+        let mut summary = Summary {
+            groups: 2,
+            ..Summary::default()
+        };
+        summary += RunStats {
+            written: 8,
+            failed: 2,
+        };
+        assert_eq!(summary.status(), Status::Partial);
+        assert_eq!(summary.status().code(), 2);
+
+        let summary = Summary {
+            groups: 2,
+            groups_failed: 1,
+            frames: 10,
+            ..Summary::default()
+        };
+        assert_eq!(summary.status(), Status::Partial);
+    }
+
+    #[test]
+    fn clean_run_is_ok() {
+        let summary = Summary {
+            groups: 1,
+            frames: 3,
+            ..Summary::default()
+        };
+        assert_eq!(summary.status().code(), 0);
+        assert_eq!(
+            summary.line(),
+            "summary status=ok exit=0 groups=1 groups_failed=0 frames=3 frames_failed=0"
+        );
+    }
+
+    #[test]
+    fn mesh_lines_come_before_the_summary() {
+        let summary = Summary {
+            groups: 1,
+            frames: 40,
+            meshes: vec![MeshStats {
+                path: "out/series 1/series 1.stl".into(),
+                vertices: 1200,
+                triangles: 2400,
+                surface_area: 5321.44,
+                volume: 18000.0,
+                size: [40.0, 35.25, 60.0],
+            }],
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.lines(),
+            [
+                "mesh vertices=1200 triangles=2400 area_mm2=5321.4 volume_mm3=18000.0 size_mm=40.0x35.2x60.0 path=out/series 1/series 1.stl",
+                "summary status=ok exit=0 groups=1 groups_failed=0 frames=40 frames_failed=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_lines_come_before_the_summary() {
+        let summary = Summary {
+            groups: 2,
+            groups_failed: 1,
+            frames: 40,
+            encodes: vec![
+                EncodeFailure {
+                    path: "out/series 1/series 1.mp4".into(),
+                    encoder: "h264_nvenc",
+                    recovered: true,
+                },
+                EncodeFailure {
+                    path: "out/2/2.mp4".into(),
+                    encoder: "libx265",
+                    recovered: false,
+                },
+            ],
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.lines(),
+            [
+                "encode status=retried encoder=h264_nvenc path=out/series 1/series 1.mp4",
+                "encode status=failed encoder=libx265 path=out/2/2.mp4",
+                "summary status=partial exit=2 groups=2 groups_failed=1 frames=40 frames_failed=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn interrupted_run_exits_130() {
+        let summary = Summary {
+            groups: 3,
+            frames: 40,
+            interrupted: true,
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.line(),
+            "summary status=interrupted exit=130 groups=3 groups_failed=0 frames=40 frames_failed=0"
+        );
+        let err = anyhow::Error::from(Stopped::Interrupted).context("while encoding");
+        assert_eq!(Status::from_error(&err), Status::Interrupted);
+    }
+
+    #[test]
+    fn bad_input_is_detected_through_context() {
+        use anyhow::Context;
+
+        let err = Err::<(), _>(BadInput("missing".into()))
+            .context("while converting")
+            .unwrap_err();
+        assert_eq!(Status::from_error(&err), Status::BadInput);
+        assert_eq!(Status::from_error(&anyhow::anyhow!("boom")), Status::Error);
+    }
+}
@@ -0,0 +1,165 @@
+//! Prometheus metrics of a `convert` run (`--metrics-file`).
+//!
+//! dcm-toolbox runs as a batch job, not a server, so there is no endpoint to
+//! scrape. Instead each run writes its counts in the Prometheus text format
+//! to a file, for node exporter's textfile collector (or a push to a
+//! Pushgateway). Every value describes the last run, so all are gauges.
+//! The file is replaced in one rename, never read half-written.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use super::{Status, Summary};
+use crate::i18n::t;
+
+/// Prefix of every metric name.
+const PREFIX: &str = "dcm_toolbox";
+
+/// Write the metrics of a finished run to `path`; a run that aborted is
+/// recorded with its status and no counts. Failing to write only warns:
+/// the conversion itself is done.
+pub fn record_metrics(path: &Path, run: &Result<Summary>, elapsed: Duration) {
+    let empty = Summary::default();
+    let (summary, status) = match run {
+        Ok(summary) => (summary, summary.status()),
+        Err(e) => (&empty, Status::from_error(e)),
+    };
+    let finished = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let text = render(summary, status, elapsed, finished);
+    if let Err(e) = write(path, &text) {
+        eprintln!("{}", t!("metrics-write-failed", error = format!("{e:#}")));
+    }
+}
+
+/// Metrics in the Prometheus text exposition format.
+fn render(summary: &Summary, status: Status, elapsed: Duration, finished: Duration) -> String {
+    let retried = summary.encodes.iter().filter(|e| e.recovered).count();
+    let encodes_failed = summary.encodes.len() - retried;
+    let mut text = String::new();
+    let mut gauge = |name: &str, help: &str, samples: &[(&str, String)]| {
+        let _ = writeln!(text, "# HELP {PREFIX}_{name} {help}");
+        let _ = writeln!(text, "# TYPE {PREFIX}_{name} gauge");
+        for (labels, value) in samples {
+            let _ = writeln!(text, "{PREFIX}_{name}{labels} {value}");
+        }
+    };
+    gauge(
+        "series",
+        "Series found by the last convert run, by result.",
+        &[
+            (
+                "{result=\"converted\"}",
+                (summary.groups - summary.groups_failed).to_string(),
+            ),
+            ("{result=\"failed\"}", summary.groups_failed.to_string()),
+        ],
+    );
+    gauge(
+        "frames",
+        "Frames (or slices) of the last convert run, by result.",
+        &[
+            ("{result=\"converted\"}", summary.frames.to_string()),
+            ("{result=\"failed\"}", summary.frames_failed.to_string()),
+        ],
+    );
+    gauge(
+        "encode_failures",
+        "MP4s ffmpeg failed on in the last convert run, by whether the fallback wrote them.",
+        &[
+            ("{result=\"retried\"}", retried.to_string()),
+            ("{result=\"failed\"}", encodes_failed.to_string()),
+        ],
+    );
+    gauge(
+        "meshes",
+        "Models written by the last convert run.",
+        &[("", summary.meshes.len().to_string())],
+    );
+    gauge(
+        "exit_code",
+        "Exit code of the last convert run.",
+        &[("", status.code().to_string())],
+    );
+    gauge(
+        "run_duration_seconds",
+        "How long the last convert run took.",
+        &[("", format!("{:.3}", elapsed.as_secs_f64()))],
+    );
+    gauge(
+        "last_run_timestamp_seconds",
+        "When the last convert run finished, in Unix time.",
+        &[("", finished.as_secs().to_string())],
+    );
+    text
+}
+
+/// Replace `path` with `text` through a sibling temporary file.
+fn write(path: &Path, text: &str) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, text)
+        .with_context(|| format!("Failed to write metrics: {}", Path::new(&temp).display()))?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to write metrics: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::EncodeFailure;
+
+    #[test]
+    fn counts_become_labeled_gauges() {
+        let summary = Summary {
+            groups: 3,
+            groups_failed: 1,
+            frames: 118,
+            frames_failed: 2,
+            encodes: vec![EncodeFailure {
+                path: "out/2/2.mp4".into(),
+                encoder: "h264_nvenc",
+                recovered: false,
+            }],
+            ..Summary::default()
+        };
+        let text = render(
+            &summary,
+            summary.status(),
+            Duration::from_millis(12_500),
+            Duration::from_secs(1_700_000_000),
+        );
+        for line in [
+            "# TYPE dcm_toolbox_series gauge",
+            "dcm_toolbox_series{result=\"converted\"} 2",
+            "dcm_toolbox_frames{result=\"failed\"} 2",
+            "dcm_toolbox_encode_failures{result=\"failed\"} 1",
+            "dcm_toolbox_meshes 0",
+            "dcm_toolbox_exit_code 2",
+            "dcm_toolbox_run_duration_seconds 12.500",
+            "dcm_toolbox_last_run_timestamp_seconds 1700000000",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "{line} missing in:\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn aborted_run_records_its_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dcm.prom");
+        let run = Err(crate::outcome::BadInput("missing".into()).into());
+        record_metrics(&path, &run, Duration::ZERO);
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("dcm_toolbox_exit_code 4\n"), "{text}");
+        assert!(text.contains("dcm_toolbox_series{result=\"converted\"} 0\n"));
+        assert!(!dir.path().join("dcm.prom.tmp").exists());
+    }
+}
@@ -0,0 +1,457 @@
+//! Dental panoramic reconstruction (`panoramic`).
+//!
+//! Builds the panoramic image dentists know from a CBCT series, as a curved
+//! MPR: the axial maximum intensity projection of the jaws shows the dental
+//! arch as a bright U, a curve is fitted through its teeth and bone, and at
+//! even steps along the curve the volume is averaged (or its maximum taken)
+//! over a slab `--thickness` mm deep across it, slice by slice. The arch
+//! unrolls into one image with the patient's right on the left, head up, and
+//! square pixels, so lengths along the arch and heights can be measured.
+
+mod arch;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use image::{DynamicImage, Rgb};
+
+use crate::annotate::Canvas;
+use crate::convert::{MIN_SLICES_FOR_3D, parse_positive};
+use crate::i18n::t;
+use crate::mask::otsu_threshold;
+use crate::outcome::BadInput;
+use crate::pixel::{Frame, Window};
+use crate::utils::extended_length_path;
+use crate::volume::{self, Volume};
+
+use self::arch::{Arch, Point, Sample};
+
+/// Share of the darkest and brightest pixels clipped by the display window,
+/// so metal fillings do not flatten the rest.
+const CLIP: f64 = 0.005;
+
+/// Color of the fitted arch on the preview.
+const CURVE: Rgb<u8> = Rgb([255, 255, 0]);
+
+/// Color of the slab edges on the preview.
+const SLAB: Rgb<u8> = Rgb([0, 160, 255]);
+
+/// CLI arguments for the `panoramic` subcommand.
+#[derive(Args, Debug)]
+pub struct PanoramicArgs {
+    /// CBCT series folder (all .dcm files form one volume)
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Output image; the extension picks the format (.png or .jpg)
+    #[arg(long = "out")]
+    pub output: PathBuf,
+
+    /// Depth of the slab across the arch in mm
+    #[arg(long, value_name = "MM", default_value_t = 10.0, value_parser = parse_positive)]
+    pub thickness: f32,
+
+    /// Show the brightest value across the slab instead of the mean
+    #[arg(long)]
+    pub mip: bool,
+
+    /// Fit the arch on slices START:END only (0-based, end excluded), e.g.
+    /// those through the crowns when the spine or skull base gets in the way
+    #[arg(long, value_name = "START:END", value_parser = parse_slices)]
+    pub arch_slices: Option<(usize, usize)>,
+
+    /// Teeth and bone level for fitting the arch (auto-detected via Otsu if
+    /// omitted)
+    #[arg(long, allow_negative_numbers = true)]
+    pub threshold: Option<f32>,
+
+    /// Also save the axial projection with the fitted arch and slab drawn on
+    /// it (.png or .jpg)
+    #[arg(long, value_name = "FILE")]
+    pub arch_preview: Option<PathBuf>,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Reconstruct the panoramic image of a CBCT series.
+pub fn run(args: &PanoramicArgs) -> Result<()> {
+    let format = image_format(&args.output)?;
+    let preview_format = args.arch_preview.as_deref().map(image_format).transpose()?;
+    let (files, volume) =
+        volume::load_series(&args.input, MIN_SLICES_FOR_3D, args.follow_symlinks)?;
+    println!("  {}", t!("stl-building-volume", count = files.len()));
+
+    let slices = match args.arch_slices {
+        Some((start, end)) if end > volume.slices => anyhow::bail!(BadInput(format!(
+            "--arch-slices {start}:{end} goes past the {} slices of the series",
+            volume.slices
+        ))),
+        Some((start, end)) => start..end,
+        None => 0..volume.slices,
+    };
+    let projection = axial_mip(&volume, slices);
+    let threshold = args.threshold.unwrap_or_else(|| {
+        let threshold = bright_threshold(&projection);
+        let text = format!("{threshold:.2}");
+        println!("  {}", t!("panoramic-threshold", threshold = text));
+        threshold
+    });
+    let arch = Arch::fit(&arch_points(&volume, &projection, threshold)).ok_or_else(|| {
+        BadInput(format!(
+            "Could not fit a dental arch through the projection above {threshold:.2}. \
+             Try another --threshold or --arch-slices"
+        ))
+    })?;
+
+    let step = f64::from(volume.spacing_x.min(volume.spacing_y));
+    let samples = arch.samples(step);
+    #[allow(clippy::cast_precision_loss)]
+    let length = format!("{:.1}", samples.len() as f64 * step);
+    println!("  {}", t!("panoramic-arch", length = length));
+
+    let half = f64::from(args.thickness) / 2.0;
+    let panoramic = unroll(&volume, &samples, half, args.mip, step);
+    let (width, height) = (panoramic.width, panoramic.height);
+    save(&panoramic, &args.output, format)?;
+    println!(
+        "{}",
+        t!(
+            "panoramic-saved",
+            width = width,
+            height = height,
+            path = args.output.display().to_string()
+        )
+    );
+
+    if let (Some(path), Some(format)) = (&args.arch_preview, preview_format) {
+        write_preview(&volume, projection, &samples, half, path, format)?;
+        println!(
+            "{}",
+            t!("panoramic-preview-saved", path = path.display().to_string())
+        );
+    }
+    Ok(())
+}
+
+/// Parse `START:END` slice indices.
+fn parse_slices(value: &str) -> std::result::Result<(usize, usize), String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or_else(|| format!("`{value}` is not a START:END range"))?;
+    let index = |text: &str| {
+        text.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("`{text}` is not a slice index"))
+    };
+    let (start, end) = (index(start)?, index(end)?);
+    if start >= end {
+        return Err(format!("`{value}` is empty (end must be after start)"));
+    }
+    Ok((start, end))
+}
+
+/// Image format picked by the file extension.
+fn image_format(path: &Path) -> Result<image::ImageFormat> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => Ok(image::ImageFormat::Png),
+        Some("jpg" | "jpeg") => Ok(image::ImageFormat::Jpeg),
+        _ => anyhow::bail!(BadInput(format!(
+            "Unsupported image file {}; expected .png or .jpg",
+            path.display()
+        ))),
+    }
+}
+
+/// Brightest value of each column and row over `slices`, as one frame.
+fn axial_mip(volume: &Volume, slices: std::ops::Range<usize>) -> Frame {
+    let slice_size = volume.cols * volume.rows;
+    let mut values = vec![f32::MIN; slice_size];
+    for slice in volume
+        .values
+        .chunks_exact(slice_size)
+        .take(slices.end)
+        .skip(slices.start)
+    {
+        for (max, &v) in values.iter_mut().zip(slice) {
+            *max = max.max(v);
+        }
+    }
+    Frame {
+        width: u32::try_from(volume.cols).unwrap_or(u32::MAX),
+        height: u32::try_from(volume.rows).unwrap_or(u32::MAX),
+        values,
+        window: None,
+        invert: false,
+    }
+}
+
+/// Level between soft tissue and teeth or bone: Otsu's threshold of what is
+/// brighter than Otsu's threshold (which splits air from the patient).
+fn bright_threshold(projection: &Frame) -> f32 {
+    let body = otsu_threshold(&projection.values);
+    let bright: Vec<f32> = projection
+        .values
+        .iter()
+        .copied()
+        .filter(|&v| v > body)
+        .collect();
+    if bright.is_empty() {
+        body
+    } else {
+        otsu_threshold(&bright)
+    }
+}
+
+/// Pixels of `projection` above `threshold` in mm, weighted by how far above.
+#[allow(clippy::cast_precision_loss)]
+fn arch_points(volume: &Volume, projection: &Frame, threshold: f32) -> Vec<Point> {
+    let cols = volume.cols;
+    projection
+        .values
+        .iter()
+        .enumerate()
+        .filter(|&(_, &v)| v > threshold)
+        .map(|(index, &v)| Point {
+            x: (index % cols) as f64 * f64::from(volume.spacing_x),
+            y: (index / cols) as f64 * f64::from(volume.spacing_y),
+            weight: f64::from(v - threshold),
+        })
+        .collect()
+}
+
+/// Value of slice `z` at (`x`, `y`) mm, interpolated between the four
+/// nearest voxels; `None` outside the slice.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn interpolate(volume: &Volume, z: usize, x: f64, y: f64) -> Option<f32> {
+    let (column, row) = (
+        x / f64::from(volume.spacing_x),
+        y / f64::from(volume.spacing_y),
+    );
+    if column < 0.0 || row < 0.0 {
+        return None;
+    }
+    let (left, top) = (column.floor() as usize, row.floor() as usize);
+    if left + 1 >= volume.cols || top + 1 >= volume.rows {
+        return None;
+    }
+    let (dx, dy) = ((column - left as f64) as f32, (row - top as f64) as f32);
+    let at =
+        |c: usize, r: usize| volume.values[c + r * volume.cols + z * volume.cols * volume.rows];
+    let upper = at(left, top) * (1.0 - dx) + at(left + 1, top) * dx;
+    let lower = at(left, top + 1) * (1.0 - dx) + at(left + 1, top + 1) * dx;
+    Some(upper * (1.0 - dy) + lower * dy)
+}
+
+/// The panoramic image: a column per arch sample, each the slab `half` mm
+/// either side of the arch projected by mean or maximum, resampled from
+/// slices to rows `step` mm apart with the head up.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn unroll(volume: &Volume, samples: &[Sample], half: f64, mip: bool, step: f64) -> Frame {
+    let depth = (half / step).round() as i64;
+    let width = samples.len();
+    // One row per slice first, in slice order
+    let mut by_slice = vec![0.0_f32; width * volume.slices];
+    for z in 0..volume.slices {
+        for (column, sample) in samples.iter().enumerate() {
+            let values = (-depth..=depth).filter_map(|i| {
+                let offset = i as f64 * step;
+                let (x, y) = (
+                    sample.normal.0.mul_add(offset, sample.x),
+                    sample.normal.1.mul_add(offset, sample.y),
+                );
+                interpolate(volume, z, x, y)
+            });
+            let value = if mip {
+                values.fold(f32::MIN, f32::max)
+            } else {
+                let (sum, count) =
+                    values.fold((0.0, 0_u32), |(sum, count), v| (sum + v, count + 1));
+                if count == 0 {
+                    f32::MIN
+                } else {
+                    sum / count as f32
+                }
+            };
+            by_slice[column + z * width] = value;
+        }
+    }
+    let floor = volume.values.iter().copied().fold(f32::MAX, f32::min);
+    for value in &mut by_slice {
+        if *value == f32::MIN {
+            *value = floor;
+        }
+    }
+
+    // Rows `step` mm apart, the highest slice (head) on top
+    let span = (volume.slices - 1) as f64 * f64::from(volume.spacing_z);
+    let height = (span / step).round() as usize + 1;
+    let mut values = vec![0.0_f32; width * height];
+    for row in 0..height {
+        let z = ((height - 1 - row) as f64 * step / f64::from(volume.spacing_z))
+            .min((volume.slices - 1) as f64);
+        let below = z.floor() as usize;
+        let above = (below + 1).min(volume.slices - 1);
+        let weight = (z - below as f64) as f32;
+        for column in 0..width {
+            let lower = by_slice[column + below * width];
+            let upper = by_slice[column + above * width];
+            values[column + row * width] = lower * (1.0 - weight) + upper * weight;
+        }
+    }
+    let mut frame = Frame {
+        width: u32::try_from(width).unwrap_or(u32::MAX),
+        height: u32::try_from(height).unwrap_or(u32::MAX),
+        values,
+        window: None,
+        invert: false,
+    };
+    frame.window = Some(clipped_window(&frame.values));
+    frame
+}
+
+/// Window spanning `values` without the darkest and brightest [`CLIP`].
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn clipped_window(values: &[f32]) -> Window {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let at = |share: f64| sorted[((sorted.len() - 1) as f64 * share).round() as usize];
+    Window::spanning(at(CLIP), at(1.0 - CLIP))
+}
+
+/// Write `frame` to `path`, creating its folder.
+fn save(frame: &Frame, path: &Path, format: image::ImageFormat) -> Result<()> {
+    let output = extended_length_path(path);
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output folder: {}", parent.display()))?;
+    }
+    DynamicImage::ImageLuma8(frame.to_luma8())
+        .save_with_format(&output, format)
+        .with_context(|| format!("Failed to save image: {}", path.display()))
+}
+
+/// Write the axial projection with the arch and the slab edges drawn on it.
+#[allow(clippy::cast_possible_truncation)]
+fn write_preview(
+    volume: &Volume,
+    mut projection: Frame,
+    samples: &[Sample],
+    half: f64,
+    path: &Path,
+    format: image::ImageFormat,
+) -> Result<()> {
+    projection.window = Some(clipped_window(&projection.values));
+    let mut canvas = Canvas::new(DynamicImage::ImageLuma8(projection.to_luma8()).into_rgb8());
+    let pixel = |x: f64, y: f64| {
+        (
+            (x / f64::from(volume.spacing_x)).round() as i64,
+            (y / f64::from(volume.spacing_y)).round() as i64,
+        )
+    };
+    for pair in samples.windows(2) {
+        for (offset, color) in [(-half, SLAB), (half, SLAB), (0.0, CURVE)] {
+            let [a, b] = [pair[0], pair[1]].map(|s| {
+                pixel(
+                    s.normal.0.mul_add(offset, s.x),
+                    s.normal.1.mul_add(offset, s.y),
+                )
+            });
+            canvas.line(a, b, color);
+        }
+    }
+    let output = extended_length_path(path);
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output folder: {}", parent.display()))?;
+    }
+    canvas
+        .into_image()
+        .save_with_format(&output, format)
+        .with_context(|| format!("Failed to save image: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 100 × 100 × 5 volume (1 mm voxels) of soft tissue holding a bright
+    /// U-shaped arch, brighter in the top slices.
+    #[allow(clippy::cast_precision_loss)]
+    fn jaw() -> Volume {
+        let (cols, rows, slices) = (100, 100, 5);
+        let mut values = vec![-1000.0_f32; cols * rows * slices];
+        for z in 0..slices {
+            for y in 0..rows {
+                for x in 0..cols {
+                    let arch = 0.02 * (x as f32 - 50.0).powi(2) + 20.0;
+                    let value = if (y as f32 - arch).abs() < 2.0 && (10..90).contains(&x) {
+                        1000.0 + 100.0 * z as f32
+                    } else if (y as f32 - 50.0).abs() < 45.0 {
+                        40.0
+                    } else {
+                        -1000.0
+                    };
+                    values[x + y * cols + z * cols * rows] = value;
+                }
+            }
+        }
+        Volume {
+            values,
+            cols,
+            rows,
+            slices,
+            spacing_x: 1.0,
+            spacing_y: 1.0,
+            spacing_z: 2.0,
+            origin: [0.0; 3],
+        }
+    }
+
+    #[test]
+    fn the_arch_is_found_and_unrolled() {
+        let volume = jaw();
+        let projection = axial_mip(&volume, 0..volume.slices);
+        let threshold = bright_threshold(&projection);
+        assert!(threshold > 40.0 && threshold < 1400.0, "{threshold}");
+        let arch = Arch::fit(&arch_points(&volume, &projection, threshold)).unwrap();
+        assert!((arch.y(50.0) - 20.0).abs() < 1.0, "{}", arch.y(50.0));
+
+        let samples = arch.samples(1.0);
+        let panoramic = unroll(&volume, &samples, 1.0, false, 1.0);
+        assert_eq!(panoramic.width as usize, samples.len());
+        // 4 gaps of 2 mm, at 1 mm rows
+        assert_eq!(panoramic.height, 9);
+        // The head (brightest, last slice) is on top
+        let middle = samples.len() / 2;
+        let top = panoramic.values[middle];
+        let bottom = panoramic.values[middle + 8 * samples.len()];
+        assert!(top > bottom, "{top} vs {bottom}");
+        assert!(bottom > 900.0, "{bottom}");
+    }
+
+    #[test]
+    fn slice_ranges_need_an_end_after_the_start() {
+        assert_eq!(parse_slices("10:40"), Ok((10, 40)));
+        assert!(parse_slices("40:10").is_err());
+        assert!(parse_slices("10").is_err());
+    }
+}
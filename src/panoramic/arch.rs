@@ -0,0 +1,258 @@
+//! Dental arch curve fitted through an axial projection.
+//!
+//! The arch is modeled as a quartic giving the row position (front to back)
+//! of the teeth for each column position (right to left), which follows a
+//! U-shaped arch seen from above without folding back on itself. Fitting is
+//! weighted least squares over bright pixels, repeated on the pixels close
+//! to the previous curve so the spine and other bone away from the teeth
+//! stop pulling on it.
+
+/// Quartic: five coefficients.
+const TERMS: usize = 5;
+
+/// Refits on the pixels near the previous curve.
+const REFITS: usize = 2;
+
+/// Pixels further than this from the curve (mm) are left out of a refit.
+const OUTLIER_MM: f64 = 10.0;
+
+/// Share of points left out at each end of the arch, so stray bright
+/// pixels do not stretch it.
+const END_TRIM: f64 = 0.01;
+
+/// Fewest points to fit through.
+const MIN_POINTS: usize = 20;
+
+/// A point of the projection in mm, with its weight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub weight: f64,
+}
+
+/// A point on the arch and the unit normal across it, in mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub x: f64,
+    pub y: f64,
+    pub normal: (f64, f64),
+}
+
+/// A dental arch: row position as a quartic of column position, in mm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arch {
+    /// Coefficients in the normalized column position, lowest power first.
+    coefficients: [f64; TERMS],
+    /// Center and half-span (mm) normalizing column positions.
+    center: f64,
+    scale: f64,
+    /// Column positions (mm) where the arch starts and ends.
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Arch {
+    /// Fit an arch through `points`; `None` when there are too few.
+    pub fn fit(points: &[Point]) -> Option<Self> {
+        let mut arch = Self::fit_once(points)?;
+        for _ in 0..REFITS {
+            let near: Vec<Point> = points
+                .iter()
+                .filter(|p| (arch.y(p.x) - p.y).abs() <= OUTLIER_MM)
+                .copied()
+                .collect();
+            arch = Self::fit_once(&near)?;
+        }
+        Some(arch)
+    }
+
+    /// One weighted least-squares fit through all of `points`.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn fit_once(points: &[Point]) -> Option<Self> {
+        if points.len() < MIN_POINTS {
+            return None;
+        }
+        let mut xs: Vec<f64> = points.iter().map(|p| p.x).collect();
+        xs.sort_by(f64::total_cmp);
+        let trim = (xs.len() as f64 * END_TRIM) as usize;
+        let (start, end) = (xs[trim], xs[xs.len() - 1 - trim]);
+        if end <= start {
+            return None;
+        }
+        let (center, scale) = (f64::midpoint(start, end), (end - start) / 2.0);
+
+        // Normal equations of the weighted fit
+        let mut matrix = [[0.0; TERMS]; TERMS];
+        let mut rhs = [0.0; TERMS];
+        for point in points {
+            let powers = powers((point.x - center) / scale);
+            for (i, row) in matrix.iter_mut().enumerate() {
+                for (j, cell) in row.iter_mut().enumerate() {
+                    *cell += point.weight * powers[i] * powers[j];
+                }
+                rhs[i] += point.weight * powers[i] * point.y;
+            }
+        }
+        Some(Self {
+            coefficients: solve(matrix, rhs)?,
+            center,
+            scale,
+            start,
+            end,
+        })
+    }
+
+    /// Row position (mm) of the arch at column position `x` (mm).
+    pub fn y(&self, x: f64) -> f64 {
+        let t = (x - self.center) / self.scale;
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |sum, &c| sum.mul_add(t, c))
+    }
+
+    /// Slope of the arch at `x`.
+    #[allow(clippy::cast_precision_loss)]
+    fn slope(&self, x: f64) -> f64 {
+        let t = (x - self.center) / self.scale;
+        let derivative = (1..TERMS).rev().fold(0.0_f64, |sum, i| {
+            sum.mul_add(t, i as f64 * self.coefficients[i])
+        });
+        derivative / self.scale
+    }
+
+    /// Points every `step` mm along the arch, from start to end.
+    pub fn samples(&self, step: f64) -> Vec<Sample> {
+        // Arc length summed over sub-steps much finer than `step`
+        let dx = step / 16.0;
+        let mut samples = vec![self.sample(self.start)];
+        let (mut x, mut travelled) = (self.start, 0.0);
+        while x < self.end {
+            let next = (x + dx).min(self.end);
+            let segment = (next - x).hypot(self.y(next) - self.y(x));
+            if travelled + segment >= step {
+                // Land on the exact length within the sub-step
+                let share = (step - travelled) / segment;
+                samples.push(self.sample(share.mul_add(next - x, x)));
+                travelled -= step;
+            }
+            travelled += segment;
+            x = next;
+        }
+        samples
+    }
+
+    fn sample(&self, x: f64) -> Sample {
+        let slope = self.slope(x);
+        let norm = slope.hypot(1.0);
+        Sample {
+            x,
+            y: self.y(x),
+            normal: (-slope / norm, 1.0 / norm),
+        }
+    }
+}
+
+/// `1, t, t², …` up to the quartic term.
+fn powers(t: f64) -> [f64; TERMS] {
+    let mut powers = [1.0; TERMS];
+    for i in 1..TERMS {
+        powers[i] = powers[i - 1] * t;
+    }
+    powers
+}
+
+/// Solve `matrix · x = rhs` by Gaussian elimination with partial pivoting;
+/// `None` when the matrix is singular.
+fn solve(mut matrix: [[f64; TERMS]; TERMS], mut rhs: [f64; TERMS]) -> Option<[f64; TERMS]> {
+    for column in 0..TERMS {
+        let pivot = (column..TERMS)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        let pivot_row = matrix[column];
+        for row in column + 1..TERMS {
+            let factor = matrix[row][column] / pivot_row[column];
+            for (cell, &above) in matrix[row].iter_mut().zip(&pivot_row).skip(column) {
+                *cell -= factor * above;
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+    let mut solution = [0.0; TERMS];
+    for row in (0..TERMS).rev() {
+        let known: f64 = (row + 1..TERMS).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on `y = 0.01 (x - 50)² + 20` for x in 0..=100 mm.
+    fn parabola() -> Vec<Point> {
+        (0..=100)
+            .map(|x| {
+                let x = f64::from(x);
+                Point {
+                    x,
+                    y: 0.01 * (x - 50.0).powi(2) + 20.0,
+                    weight: 1.0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_parabola_is_recovered() {
+        let arch = Arch::fit(&parabola()).unwrap();
+        assert!((arch.y(50.0) - 20.0).abs() < 1e-6);
+        assert!((arch.y(90.0) - 36.0).abs() < 1e-6);
+        assert!((arch.slope(60.0) - 0.2).abs() < 1e-6);
+        assert!(arch.start <= 1.0 && arch.end >= 99.0);
+    }
+
+    #[test]
+    fn bone_away_from_the_teeth_is_dropped() {
+        let mut points = parabola();
+        // The spine: bright, far behind the arch
+        points.extend((0..30).map(|i| Point {
+            x: 48.0 + f64::from(i % 5),
+            y: 80.0,
+            weight: 1.0,
+        }));
+        let arch = Arch::fit(&points).unwrap();
+        assert!((arch.y(50.0) - 20.0).abs() < 0.5, "{}", arch.y(50.0));
+    }
+
+    #[test]
+    fn samples_are_evenly_spaced_along_the_curve() {
+        let arch = Arch::fit(&parabola()).unwrap();
+        let samples = arch.samples(1.0);
+        for pair in samples.windows(2) {
+            let gap = (pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y);
+            assert!((gap - 1.0).abs() < 0.05, "{gap}");
+        }
+        // At the apex the normal points straight back
+        let apex = samples
+            .iter()
+            .min_by(|a, b| (a.x - 50.0).abs().total_cmp(&(b.x - 50.0).abs()))
+            .unwrap();
+        assert!(apex.normal.0.abs() < 0.05 && apex.normal.1 > 0.99);
+    }
+
+    #[test]
+    fn too_few_points_fit_nothing() {
+        assert_eq!(Arch::fit(&parabola()[..5]), None);
+    }
+}
@@ -0,0 +1,352 @@
+//! Output permissions for shared drives (`--umask`, `--chmod`, `--chown`).
+//!
+//! Outputs written by a service account are often unreadable by the
+//! clinicians who need them. `--umask` is set before anything is written, so
+//! every folder and file starts with the intended mode; `--chmod` and
+//! `--chown` are applied once the run ends, whether it succeeded or not, to
+//! what it wrote below `--out`: paths that were not there before it started,
+//! and files it rewrote. Earlier outputs in the same folder keep their mode
+//! and owner. Unix only.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use clap::Args;
+
+use crate::i18n::t;
+
+/// Options setting the mode and owner of written outputs.
+#[derive(Args, Debug, Default)]
+pub struct PermissionArgs {
+    /// Create outputs with this umask, in octal (e.g. 002 for group-writable
+    /// files); Unix only
+    #[arg(long, value_name = "MASK", value_parser = parse_umask)]
+    pub umask: Option<u32>,
+
+    /// Set this mode, in octal (e.g. 640), on every file the run writes
+    /// below --out when it ends; folders also get search (x) wherever read
+    /// is granted; Unix only
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    pub chmod: Option<u32>,
+
+    /// Give everything the run writes below --out to this USER[:GROUP]
+    /// (names or numeric IDs) when it ends; usually needs root; Unix only
+    #[arg(long, value_name = "USER[:GROUP]", value_parser = parse_owner)]
+    pub chown: Option<Owner>,
+}
+
+/// What `--out` held before the run: the modification time of each file,
+/// and `None` for each folder.
+#[derive(Debug, Default)]
+pub struct Existing(HashMap<PathBuf, Option<SystemTime>>);
+
+impl Existing {
+    /// Whether the run created `path` or, for a file, wrote it again.
+    fn written(&self, path: &Path, meta: &Metadata) -> bool {
+        match self.0.get(path) {
+            None => true,
+            Some(None) => false,
+            Some(Some(modified)) => meta.modified().ok() != Some(*modified),
+        }
+    }
+}
+
+/// User and group IDs for `--chown`; `None` leaves that part unchanged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Owner {
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl PermissionArgs {
+    /// Set the umask of this process, before any output is created.
+    pub fn apply_umask(&self) {
+        if let Some(mask) = self.umask {
+            set_umask(mask);
+        }
+    }
+
+    /// Note what `output` holds before the run, for [`Self::apply_to`].
+    /// Nothing is read when there is nothing to apply.
+    pub fn existing(&self, output: &Path) -> Existing {
+        let mut existing = Existing::default();
+        if self.applies_to(output) {
+            walk(output, &mut |path, meta| {
+                let modified = meta.is_file().then(|| meta.modified().ok()).flatten();
+                existing.0.insert(path.to_path_buf(), modified);
+            });
+        }
+        existing
+    }
+
+    /// Apply `--chmod` and `--chown` to what the run wrote at or below
+    /// `output`: everything not in `existing`, and files modified since.
+    /// Entries that cannot be changed only warn; stdout (`-`) is skipped.
+    pub fn apply_to(&self, output: &Path, existing: &Existing) {
+        if !self.applies_to(output) {
+            return;
+        }
+        let mut failed = 0;
+        let mut first_error = None;
+        walk(output, &mut |path, meta| {
+            if !existing.written(path, meta) {
+                return;
+            }
+            if let Err(e) = self.apply_one(path, meta.is_dir()) {
+                failed += 1;
+                first_error.get_or_insert_with(|| format!("{}: {e}", path.display()));
+            }
+        });
+        if let Some(error) = first_error {
+            eprintln!("{}", t!("perms-failed", count = failed, error = error));
+        }
+    }
+
+    /// Whether `--chmod` or `--chown` is set, and `output` is not stdout.
+    fn applies_to(&self, output: &Path) -> bool {
+        (self.chmod.is_some() || self.chown.is_some()) && output.as_os_str() != "-"
+    }
+
+    fn apply_one(&self, path: &Path, is_dir: bool) -> std::io::Result<()> {
+        if let Some(mode) = self.chmod {
+            set_mode(path, if is_dir { with_search(mode) } else { mode })?;
+        }
+        if let Some(owner) = self.chown {
+            set_owner(path, owner)?;
+        }
+        Ok(())
+    }
+}
+
+/// Visit `path` and, for a folder, everything below it. Symlinks are not
+/// followed or changed.
+fn walk(path: &Path, visit: &mut impl FnMut(&Path, &Metadata)) {
+    let Ok(meta) = path.symlink_metadata() else {
+        return;
+    };
+    if meta.file_type().is_symlink() {
+        return;
+    }
+    if meta.is_dir()
+        && let Ok(entries) = path.read_dir()
+    {
+        for entry in entries.flatten() {
+            walk(&entry.path(), visit);
+        }
+    }
+    visit(path, &meta);
+}
+
+/// A folder mode granting search (x) to everyone who may read it.
+const fn with_search(mode: u32) -> u32 {
+    mode | ((mode & 0o444) >> 2)
+}
+
+/// Parse an octal mode for `--chmod` (at most `7777`).
+fn parse_mode(value: &str) -> Result<u32, String> {
+    parse_octal(value, 0o7777)
+}
+
+/// Parse an octal mask for `--umask` (at most `777`).
+fn parse_umask(value: &str) -> Result<u32, String> {
+    parse_octal(value, 0o777)
+}
+
+fn parse_octal(value: &str, max: u32) -> Result<u32, String> {
+    u32::from_str_radix(value.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= max)
+        .ok_or_else(|| format!("`{value}` is not an octal mode up to {max:o}"))
+        .and_then(|mode| unix_only().map(|()| mode))
+}
+
+/// Parse `USER`, `USER:GROUP`, or `:GROUP` for `--chown`.
+fn parse_owner(value: &str) -> Result<Owner, String> {
+    unix_only()?;
+    let (user, group) = match value.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (value, None),
+    };
+    let uid = (!user.is_empty()).then(|| lookup_user(user)).transpose()?;
+    let gid = group
+        .filter(|group| !group.is_empty())
+        .map(lookup_group)
+        .transpose()?;
+    if uid.is_none() && gid.is_none() {
+        return Err(format!("`{value}` names no user or group"));
+    }
+    Ok(Owner { uid, gid })
+}
+
+/// Permission options only exist where Unix modes and owners do.
+fn unix_only() -> Result<(), String> {
+    if cfg!(unix) {
+        Ok(())
+    } else {
+        Err("only supported on Unix".to_string())
+    }
+}
+
+#[cfg(unix)]
+fn set_umask(mask: u32) {
+    // SAFETY: umask only changes this process's file creation mask and
+    // cannot fail.
+    unsafe {
+        libc::umask(mask as libc::mode_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn set_umask(_: u32) {}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &Path, _: u32) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, owner: Owner) -> std::io::Result<()> {
+    std::os::unix::fs::chown(path, owner.uid, owner.gid)
+}
+
+#[cfg(not(unix))]
+fn set_owner(_: &Path, _: Owner) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// The UID of a user name or number.
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Result<u32, String> {
+    if let Ok(uid) = name.parse() {
+        return Ok(uid);
+    }
+    let c_name = std::ffi::CString::new(name).map_err(|_| format!("bad user name `{name}`"))?;
+    // SAFETY: `c_name` is a valid C string; the returned record is only read
+    // before any other passwd lookup, on the thread parsing the arguments.
+    let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("unknown user `{name}`"));
+    }
+    // SAFETY: a non-null result points at a valid passwd record.
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+#[cfg(not(unix))]
+fn lookup_user(_: &str) -> Result<u32, String> {
+    unix_only().map(|()| 0)
+}
+
+/// The GID of a group name or number.
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Result<u32, String> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let c_name = std::ffi::CString::new(name).map_err(|_| format!("bad group name `{name}`"))?;
+    // SAFETY: as in `lookup_user`, for the group database.
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("unknown group `{name}`"));
+    }
+    // SAFETY: a non-null result points at a valid group record.
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(not(unix))]
+fn lookup_group(_: &str) -> Result<u32, String> {
+    unix_only().map(|()| 0)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn modes_and_owners_parse() {
+        assert_eq!(parse_mode("640"), Ok(0o640));
+        assert_eq!(parse_mode("0o2775"), Ok(0o2775));
+        assert!(parse_mode("648").is_err());
+        assert!(parse_umask("1777").is_err());
+        assert_eq!(
+            parse_owner("0:0"),
+            Ok(Owner {
+                uid: Some(0),
+                gid: Some(0)
+            })
+        );
+        assert_eq!(
+            parse_owner(":0"),
+            Ok(Owner {
+                uid: None,
+                gid: Some(0)
+            })
+        );
+        assert!(parse_owner(":").is_err());
+        assert!(parse_owner("no-such-user-here").is_err());
+    }
+
+    #[test]
+    fn folders_get_search_where_read_is_granted() {
+        assert_eq!(with_search(0o640), 0o750);
+        assert_eq!(with_search(0o600), 0o700);
+        assert_eq!(with_search(0o664), 0o775);
+    }
+
+    #[test]
+    fn chmod_reaches_every_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let series = dir.path().join("out").join("3");
+        fs::create_dir_all(&series).unwrap();
+        fs::write(series.join("0001.jpg"), b"jpg").unwrap();
+
+        let args = PermissionArgs {
+            chmod: Some(0o640),
+            ..PermissionArgs::default()
+        };
+        args.apply_to(&dir.path().join("out"), &Existing::default());
+        assert_eq!(mode(&series.join("0001.jpg")), 0o640);
+        assert_eq!(mode(&series), 0o750);
+        assert_eq!(mode(&dir.path().join("out")), 0o750);
+    }
+
+    #[test]
+    fn earlier_outputs_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let earlier = out.join("2");
+        fs::create_dir_all(&earlier).unwrap();
+        fs::write(earlier.join("0001.jpg"), b"jpg").unwrap();
+        fs::set_permissions(&out, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(earlier.join("0001.jpg"), fs::Permissions::from_mode(0o600)).unwrap();
+
+        let args = PermissionArgs {
+            chmod: Some(0o640),
+            ..PermissionArgs::default()
+        };
+        let existing = args.existing(&out);
+        let series = out.join("3");
+        fs::create_dir(&series).unwrap();
+        fs::write(series.join("0001.jpg"), b"jpg").unwrap();
+        args.apply_to(&out, &existing);
+
+        assert_eq!(mode(&series.join("0001.jpg")), 0o640);
+        assert_eq!(mode(&series), 0o750);
+        assert_eq!(mode(&earlier.join("0001.jpg")), 0o600);
+        assert_eq!(mode(&out), 0o700);
+    }
+}
@@ -0,0 +1,847 @@
+//! Shared decode pipeline for 2D exports: load → transform → sink.
+//!
+//! Every image-producing output goes through the same three stages:
+//!
+//! 1. **Load** — open a DICOM file and decode its frames ([`Frames`], [`load_frame`]).
+//! 2. **Transform** — calibrate and render it to a display image ([`render_frame`]).
+//! 3. **Sink** — hand the image to a format-specific writer ([`FrameSink`]).
+//!
+//! Per-frame features (windowing, overlays, filters) belong in the transform
+//! stage so that every output format picks them up without duplication.
+//!
+//! Multi-frame objects are streamed through [`Frames`]: each frame is decoded
+//! only when the sink is ready for it, so a 2000-frame enhanced object never
+//! has more than one decoded frame in memory. [`run`] also recycles the
+//! calibrated sample buffers through a [`FramePool`], so a long series does
+//! not allocate and free a frame-sized buffer (or two, with filters) per
+//! slice, and opens files through a [`ReadAhead`], so `--prefetch` overlaps
+//! reading the next files with decoding this one. With a [`FrameCache`], the
+//! frames of a file rendered in an earlier run are read back instead of
+//! decoded.
+
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, InMemDicomObject, OpenFileOptions, ReadError, open_file};
+use image::DynamicImage;
+
+use crate::annotate::{Annotations, Legend};
+use crate::cache::{Entry, FrameCache};
+use crate::cancel::Cancel;
+use crate::convert::suv::{self, SuvDisplay};
+use crate::error::ToolboxError;
+use crate::events::{self, Event};
+use crate::filter::{self, Denoise};
+use crate::i18n::t;
+use crate::lenient;
+use crate::mask;
+use crate::pixel::{self, DecodedFrame, Window};
+use crate::prefetch::ReadAhead;
+use crate::throttle;
+
+/// Destination for rendered frames (JPEG files, video staging, ...).
+pub trait FrameSink {
+    /// Consume the rendered image at output position `index` within its group.
+    ///
+    /// Indices run across files, so a multi-frame object occupies as many
+    /// consecutive positions as it has frames.
+    fn write_frame(&mut self, index: usize, source: &Path, image: DynamicImage) -> Result<()>;
+}
+
+/// Options for the transform stage, shared by every 2D output.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions<'a> {
+    /// Noise reduction filter (monochrome only).
+    pub denoise: Option<Denoise>,
+    /// Unsharp mask amount (monochrome only).
+    pub sharpen: Option<f32>,
+    /// Remove air, table, and noise around the patient (monochrome only).
+    pub strip_background: bool,
+    /// Render monochrome frames with 16-bit gray levels (`png16`).
+    pub deep: bool,
+    /// Scale PET frames to SUV and window them in SUV (`--suv`).
+    pub suv: Option<SuvDisplay>,
+    /// Window every monochrome frame with this instead of its own (`--window`).
+    pub window: Option<Window>,
+    /// Draw a calibrated colorbar on monochrome frames (`--colorbar`).
+    pub colorbar: bool,
+    /// Overlays drawn on frames with a matching `SOPInstanceUID`.
+    pub annotations: Option<&'a Annotations>,
+    /// Rendered frames saved and reused across runs (`--cache-dir`).
+    pub cache: Option<&'a FrameCache>,
+    /// Stops the frame loop on Ctrl-C or past the series' deadline.
+    pub cancel: Cancel,
+}
+
+/// Most spare buffers a [`FramePool`] keeps: one for the next frame and
+/// one for a filter's output.
+const MAX_SPARE_BUFFERS: usize = 2;
+
+/// Sample buffers recycled across the frames of a run.
+///
+/// Slices of a series share one size, so after the first frame every decode
+/// and filter pass finds a buffer with enough capacity and allocates nothing.
+#[derive(Debug, Default)]
+pub struct FramePool {
+    spare: Vec<Vec<f32>>,
+}
+
+impl FramePool {
+    /// An empty buffer, reusing a returned one if there is any.
+    pub fn take(&mut self) -> Vec<f32> {
+        self.spare.pop().unwrap_or_default()
+    }
+
+    /// Return a buffer for later frames; extra buffers are freed.
+    pub fn give(&mut self, mut buffer: Vec<f32>) {
+        if self.spare.len() < MAX_SPARE_BUFFERS && buffer.capacity() > 0 {
+            buffer.clear();
+            self.spare.push(buffer);
+        }
+    }
+}
+
+/// Frame counts reported by [`run`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunStats {
+    /// Frames successfully handed to the sink.
+    pub written: usize,
+    /// Frames that failed to load, render, or write.
+    pub failed: usize,
+}
+
+/// Load stage: open a DICOM file and decode a single frame.
+pub fn load_frame(path: &Path, frame: u32) -> Result<DecodedFrame, ToolboxError> {
+    throttle::before_read(path);
+    let obj = open_object(path)?;
+    decode_opened(&obj, path, frame)
+}
+
+/// [`load_frame`] for an object already opened from `path`.
+pub fn decode_opened(
+    obj: &DefaultDicomObject,
+    path: &Path,
+    frame: u32,
+) -> Result<DecodedFrame, ToolboxError> {
+    pixel::decode_frame(obj, frame).map_err(|e| e.at(path))
+}
+
+/// Length of the Part 10 preamble that precedes the `DICM` magic code.
+const PREAMBLE_LEN: usize = 128;
+
+/// Load stage for streams: read a whole DICOM object from a reader (e.g. stdin).
+///
+/// Accepts Part 10 data with or without the 128-byte preamble.
+pub fn read_object(mut reader: impl Read) -> Result<DefaultDicomObject, ToolboxError> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|source| ToolboxError::Io {
+            path: PathBuf::from("-"),
+            source,
+        })?;
+    parse_object(&bytes, Path::new("-"))
+}
+
+/// Parse a whole DICOM object already in memory, with or without the
+/// preamble. Under `--lenient`, what a strict parse rejects is salvaged,
+/// naming `source` in the report.
+pub fn parse_object(bytes: &[u8], source: &Path) -> Result<DefaultDicomObject, ToolboxError> {
+    let stream = &bytes[preamble_len(bytes)..];
+    OpenFileOptions::new()
+        .from_reader(stream)
+        .or_else(|e| {
+            if lenient::enabled() {
+                lenient::salvage(stream, source).map_err(|_| e)
+            } else {
+                Err(e)
+            }
+        })
+        .map_err(|e| read_error(source, e))
+}
+
+/// Open the DICOM file at `path`, salvaging it under `--lenient`.
+pub fn open_object(path: &Path) -> Result<DefaultDicomObject, ToolboxError> {
+    if lenient::enabled() {
+        let bytes = fs::read(path).map_err(|source| ToolboxError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        parse_object(&bytes, path)
+    } else {
+        open_file(path).map_err(|e| read_error(path, e))
+    }
+}
+
+/// Sort a failure to read the object from `path` into a [`ToolboxError`].
+fn read_error(path: &Path, error: ReadError) -> ToolboxError {
+    match error {
+        ReadError::OpenFile { source, .. } | ReadError::ReadFile { source, .. } => {
+            ToolboxError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        }
+        ReadError::ReadUnsupportedTransferSyntax { uid, name, .. }
+        | ReadError::ReadUnsupportedTransferSyntaxWithSuggestion { uid, name, .. } => {
+            ToolboxError::UnsupportedTransferSyntax {
+                uid: uid.to_string(),
+                name: name.to_string(),
+                feature: None,
+            }
+        }
+        other => ToolboxError::parse(path, &other),
+    }
+}
+
+/// Number of leading bytes to skip: the preamble, if the `DICM` magic follows it.
+fn preamble_len(bytes: &[u8]) -> usize {
+    match bytes.get(PREAMBLE_LEN..PREAMBLE_LEN + 4) {
+        Some(b"DICM") => PREAMBLE_LEN,
+        _ => 0,
+    }
+}
+
+/// Streaming iterator over the frames of a single DICOM object.
+///
+/// The object is opened once; pixel data is decoded one frame per call to
+/// [`Iterator::next`].
+pub struct Frames {
+    obj: DefaultDicomObject,
+    path: PathBuf,
+    next: u32,
+    count: u32,
+}
+
+impl Frames {
+    /// Open a DICOM file for frame-by-frame decoding.
+    pub fn open(path: &Path) -> Result<Self, ToolboxError> {
+        throttle::before_read(path);
+        let obj = open_object(path)?;
+        Ok(Self::of(obj, path))
+    }
+
+    /// Frames of `obj`, read from `path`.
+    fn of(obj: DefaultDicomObject, path: &Path) -> Self {
+        let count = number_of_frames(&obj);
+        Self {
+            obj,
+            path: path.to_path_buf(),
+            next: 0,
+            count,
+        }
+    }
+
+    /// `SOPInstanceUID` of the object, if present.
+    pub fn sop_instance_uid(&self) -> Option<String> {
+        sop_instance_uid(&self.obj)
+    }
+
+    /// Number of frames in the object.
+    pub const fn frame_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Factor turning the object's values into SUV (see [`suv::factor`]).
+    pub fn suv_factor(&self) -> Result<f64> {
+        suv::factor(&self.obj)
+    }
+
+    /// Move on to frame `frame` without decoding the ones before it.
+    pub fn seek(&mut self, frame: u32) {
+        self.next = frame.min(self.count);
+    }
+
+    /// Decode the next frame into `buffer` (see [`pixel::decode_frame_into`]).
+    pub fn next_into(&mut self, buffer: Vec<f32>) -> Option<Result<DecodedFrame, ToolboxError>> {
+        if self.next >= self.count {
+            return None;
+        }
+        let frame = self.next;
+        self.next += 1;
+
+        Some(pixel::decode_frame_into(&self.obj, frame, buffer).map_err(|e| e.at(&self.path)))
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Result<DecodedFrame, ToolboxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_into(Vec::new())
+    }
+
+    /// Skip frames without decoding them (used by `step_by`).
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let skip = u32::try_from(n).unwrap_or(u32::MAX);
+        self.next = self.next.saturating_add(skip).min(self.count);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = usize::try_from(self.count - self.next).unwrap_or(usize::MAX);
+        (remaining, Some(remaining))
+    }
+}
+
+/// `SOPInstanceUID` of an object, trimmed of DICOM padding.
+pub fn sop_instance_uid(obj: &InMemDicomObject) -> Option<String> {
+    obj.element(tags::SOP_INSTANCE_UID)
+        .ok()?
+        .to_str()
+        .ok()
+        .map(|uid| uid.trim_end_matches(['\0', ' ']).to_string())
+}
+
+/// Number of frames in an object (`NumberOfFrames`, defaulting to 1).
+pub fn number_of_frames(obj: &InMemDicomObject) -> u32 {
+    obj.element(tags::NUMBER_OF_FRAMES)
+        .ok()
+        .and_then(|e| e.to_int::<u32>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(1)
+}
+
+/// Total number of frames across a group, reading headers only.
+///
+/// Files whose header cannot be read count as a single frame so that the
+/// total still reflects them as a (failing) entry.
+pub fn count_frames(files: &[PathBuf]) -> usize {
+    files.iter().map(|path| frames_in(path)).sum()
+}
+
+/// Number of frames in one file, reading its header only (1 when the header
+/// cannot be read).
+pub fn frames_in(path: &Path) -> usize {
+    let frames = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(path)
+        .map_or(1, |obj| number_of_frames(&obj));
+    usize::try_from(frames).unwrap_or(usize::MAX)
+}
+
+/// Transform stage: turn a decoded frame into a display image.
+pub fn render_frame(frame: DecodedFrame, options: RenderOptions<'_>) -> DynamicImage {
+    render_pooled(
+        windowed(frame, &options),
+        &options,
+        &mut FramePool::default(),
+    )
+}
+
+/// `frame` with the window of `options`, if it sets one.
+fn windowed(frame: DecodedFrame, options: &RenderOptions<'_>) -> DecodedFrame {
+    match (frame, options.window) {
+        (DecodedFrame::Mono(mut frame), Some(window)) => {
+            frame.window = Some(window);
+            DecodedFrame::Mono(frame)
+        }
+        (frame, _) => frame,
+    }
+}
+
+/// [`render_frame`], handing every sample buffer it is done with to `pool`.
+fn render_pooled(
+    frame: DecodedFrame,
+    options: &RenderOptions<'_>,
+    pool: &mut FramePool,
+) -> DynamicImage {
+    match frame {
+        DecodedFrame::Mono(mut frame) => {
+            let (width, height) = (frame.width as usize, frame.height as usize);
+            if let Some(filter) = options.denoise {
+                let denoised = filter::denoise(filter, &frame.values, width, height);
+                pool.give(std::mem::replace(&mut frame.values, denoised));
+            }
+            if let Some(amount) = options.sharpen {
+                let sharpened = filter::unsharp_mask(&frame.values, width, height, amount);
+                pool.give(std::mem::replace(&mut frame.values, sharpened));
+            }
+            if options.strip_background {
+                mask::strip_background_2d(&mut frame.values, width, height);
+            }
+            let image = if options.deep {
+                DynamicImage::ImageLuma16(frame.to_luma16())
+            } else {
+                DynamicImage::ImageLuma8(frame.to_luma8())
+            };
+            pool.give(frame.values);
+            image
+        }
+        DecodedFrame::Color(image) => image,
+    }
+}
+
+/// Transform stage for frame `number` of `obj`: render it with its colorbar,
+/// then draw the annotations that match the object's `SOPInstanceUID`.
+pub fn render_annotated(
+    frame: DecodedFrame,
+    options: RenderOptions<'_>,
+    obj: &InMemDicomObject,
+    number: usize,
+) -> DynamicImage {
+    let unit = Legend::unit_of(obj, options.suv.is_some());
+    let image = render_with_legend(frame, &options, unit, &mut FramePool::default());
+    annotate(image, &options, sop_instance_uid(obj).as_deref(), number)
+}
+
+/// Draw the annotations for frame `number` of the object `sop_instance_uid`.
+fn annotate(
+    image: DynamicImage,
+    options: &RenderOptions<'_>,
+    sop_instance_uid: Option<&str>,
+    number: usize,
+) -> DynamicImage {
+    match (options.annotations, sop_instance_uid) {
+        (Some(annotations), Some(uid)) => annotations.draw(image, uid, number),
+        _ => image,
+    }
+}
+
+/// [`render_pooled`], with the colorbar of the frame's window drawn on the
+/// image when `options.colorbar` asks for one, labeled in `unit`.
+fn render_with_legend(
+    frame: DecodedFrame,
+    options: &RenderOptions<'_>,
+    unit: Option<&'static str>,
+    pool: &mut FramePool,
+) -> DynamicImage {
+    let frame = windowed(frame, options);
+    let legend = options.colorbar.then(|| Legend::of(&frame, unit)).flatten();
+    let image = render_pooled(frame, options, pool);
+    match legend {
+        Some(legend) => legend.draw(image),
+        None => image,
+    }
+}
+
+/// Run every frame of every file in a group through load → transform → sink.
+///
+/// Frames are decoded one at a time. Failures are reported per frame (or per
+/// file when it cannot be opened) and do not abort the remaining work. When
+/// `options.cancel` says to stop, the loop ends before the next frame; the
+/// caller checks the token to tell a short run from a finished one.
+pub fn run(files: &[PathBuf], options: RenderOptions<'_>, sink: &mut dyn FrameSink) -> RunStats {
+    run_span(files, 0..usize::MAX, options, sink)
+}
+
+/// [`run`] for the frames at positions `span` of the group only. Frames
+/// before the span are skipped without decoding them, and files after it
+/// are not opened.
+pub fn run_span(
+    files: &[PathBuf],
+    span: Range<usize>,
+    options: RenderOptions<'_>,
+    sink: &mut dyn FrameSink,
+) -> RunStats {
+    let mut tally = Tally::default();
+    let mut pool = FramePool::default();
+    let mut ahead = ReadAhead::start(files);
+    // Position of the file's first frame in the group
+    let mut position = 0;
+
+    'files: for path in files {
+        if position >= span.end {
+            break;
+        }
+        events::emit(Event::FileStarted { path: path.clone() });
+        let written = tally.stats.written;
+        let entry = options.cache.and_then(|cache| cache.entry(path, &options));
+        if let Some(entry) = &entry
+            && let Some(cached) = entry.cached()
+        {
+            ahead.skip();
+            let uid = cached.sop_instance_uid.as_deref();
+            let kept = within(&span, position, cached.count);
+            position += cached.count;
+            for number in kept {
+                if options.cancel.stopped().is_some() {
+                    break 'files;
+                }
+                let result = entry
+                    .load(number)
+                    .map(|image| annotate(image, &options, uid, number))
+                    .and_then(|image| sink.write_frame(tally.index, path, image));
+                tally.record(path, result);
+            }
+            tally.finish(path, written);
+            continue;
+        }
+
+        let opened = ahead.open(path).map(|obj| Frames::of(obj, path));
+        let mut frames = match opened {
+            Ok(frames) => frames,
+            Err(e) => {
+                lenient::quarantine(path, &e);
+                // It counts as one frame, like in `count_frames`
+                if span.contains(&position) {
+                    tally.record(path, Err(e.into()));
+                }
+                position += 1;
+                continue;
+            }
+        };
+
+        let uid = frames.sop_instance_uid();
+        let count = usize::try_from(frames.frame_count()).unwrap_or(usize::MAX);
+        let kept = within(&span, position, count);
+        position += count;
+        let unit = Legend::unit_of(&frames.obj, options.suv.is_some());
+        // The SUV factor comes from the header, so one serves every frame
+        let factor = match options.suv.map(|_| frames.suv_factor()).transpose() {
+            Ok(factor) => factor,
+            Err(e) => {
+                if !kept.is_empty() {
+                    tally.record(path, Err(e));
+                }
+                continue;
+            }
+        };
+        // Only a file rendered whole is worth caching
+        let mut caching = entry.filter(|_| kept.len() == count);
+        frames.seek(u32::try_from(kept.start).unwrap_or(u32::MAX));
+        let (mut number, mut stored) = (kept.start, 0);
+        while number < kept.end
+            && let Some(frame) = frames.next_into(pool.take())
+        {
+            if options.cancel.stopped().is_some() {
+                break 'files;
+            }
+            let result = frame
+                .map_err(anyhow::Error::from)
+                .map(|frame| match (options.suv, factor) {
+                    (Some(display), Some(factor)) => display.apply(frame, factor),
+                    _ => frame,
+                })
+                .map(|frame| render_with_legend(frame, &options, unit, &mut pool))
+                .map(|image| {
+                    if let Some(entry) = &caching {
+                        match entry.store(number, &image) {
+                            Ok(()) => stored += 1,
+                            Err(e) => caching = report_cache_failure(&e),
+                        }
+                    }
+                    annotate(image, &options, uid.as_deref(), number)
+                })
+                .and_then(|image| sink.write_frame(tally.index, path, image));
+            number += 1;
+            tally.record(path, result);
+        }
+        // A frame that failed to decode is missing from the entry
+        if let Some(entry) = caching
+            && stored == number
+            && let Err(e) = entry.complete(number, uid.as_deref())
+        {
+            report_cache_failure(&e);
+        }
+        tally.finish(path, written);
+    }
+
+    tally.stats
+}
+
+/// Frames of a file of `count` frames, starting at group position `first`,
+/// that fall in `span`.
+fn within(span: &Range<usize>, first: usize, count: usize) -> Range<usize> {
+    let start = span.start.saturating_sub(first).min(count);
+    let end = span.end.saturating_sub(first).min(count);
+    start..end.max(start)
+}
+
+/// Frame counts of a run, and the output position of the next frame.
+#[derive(Default)]
+struct Tally {
+    stats: RunStats,
+    index: usize,
+}
+
+impl Tally {
+    /// Count one frame of `path` as written or failed.
+    fn record(&mut self, path: &Path, result: Result<()>) {
+        match result {
+            Ok(()) => {
+                self.stats.written += 1;
+                self.index += 1;
+            }
+            Err(e) => {
+                events::emit(Event::FileFailed {
+                    path: path.to_path_buf(),
+                    error: e.to_string(),
+                });
+                self.stats.failed += 1;
+            }
+        }
+    }
+
+    /// Report `path` as finished, `written` being the frame count before it.
+    fn finish(&self, path: &Path, written: usize) {
+        events::emit(Event::FileFinished {
+            path: path.to_path_buf(),
+            frames: self.stats.written - written,
+        });
+    }
+}
+
+/// Warn that frames could not be cached; the file goes on uncached.
+fn report_cache_failure(error: &anyhow::Error) -> Option<Entry> {
+    eprintln!("{}", t!("cache-failed", error = format!("{error:#}")));
+    None
+}
+
+/// File name of a path for progress messages (the full path if it has none).
+pub fn display_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sink that records which indices it received.
+    struct RecordingSink(Vec<usize>);
+
+    impl FrameSink for RecordingSink {
+        fn write_frame(&mut self, index: usize, _: &Path, _: DynamicImage) -> Result<()> {
+            self.0.push(index);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unreadable_files_are_counted_as_failures() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bogus = temp_dir.path().join("bogus.dcm");
+        std::fs::write(&bogus, "not a dicom file").unwrap();
+
+        let mut sink = RecordingSink(vec![]);
+        let stats = run(
+            &[bogus, temp_dir.path().join("missing.dcm")],
+            RenderOptions::default(),
+            &mut sink,
+        );
+
+        assert_eq!(
+            stats,
+            RunStats {
+                written: 0,
+                failed: 2
+            }
+        );
+        assert!(sink.0.is_empty(), "Sink should not receive failed frames");
+    }
+
+    #[test]
+    fn unreadable_headers_count_as_one_frame() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let files = [temp_dir.path().join("a.dcm"), temp_dir.path().join("b.dcm")];
+        assert_eq!(count_frames(&files), 2);
+    }
+
+    #[test]
+    fn preamble_is_skipped_before_magic() {
+        let mut bytes = vec![0_u8; PREAMBLE_LEN];
+        bytes.extend_from_slice(b"DICM rest");
+        assert_eq!(preamble_len(&bytes), 128);
+    }
+
+    #[test]
+    fn data_without_preamble_is_kept() {
+        assert_eq!(preamble_len(b"DICM rest"), 0);
+    }
+
+    #[test]
+    fn garbage_stream_is_rejected() {
+        assert!(read_object(&b"not a dicom stream"[..]).is_err());
+    }
+
+    #[test]
+    fn pool_hands_back_returned_buffers() {
+        let mut pool = FramePool::default();
+        let mut buffer = pool.take();
+        assert_eq!(buffer.capacity(), 0);
+        buffer.extend([1.0; 512]);
+        pool.give(buffer);
+
+        let reused = pool.take();
+        assert!(reused.is_empty() && reused.capacity() >= 512);
+        assert_eq!(pool.take().capacity(), 0);
+    }
+
+    #[test]
+    fn pool_keeps_a_bounded_number_of_buffers() {
+        let mut pool = FramePool::default();
+        for _ in 0..5 {
+            pool.give(vec![0.0; 16]);
+        }
+        assert_eq!(pool.spare.len(), MAX_SPARE_BUFFERS);
+    }
+
+    #[test]
+    fn pooled_render_matches_a_fresh_one() {
+        let frame = || {
+            DecodedFrame::Mono(pixel::Frame {
+                width: 4,
+                height: 4,
+                values: (0_u16..16).map(f32::from).collect(),
+                window: None,
+                invert: false,
+            })
+        };
+        let options = RenderOptions {
+            denoise: Some(Denoise::Median),
+            sharpen: Some(1.0),
+            ..RenderOptions::default()
+        };
+        let mut pool = FramePool::default();
+        let first = render_pooled(frame(), &options, &mut pool);
+        let second = render_pooled(frame(), &options, &mut pool);
+        assert_eq!(first, render_frame(frame(), options));
+        assert_eq!(first, second);
+        assert_eq!(pool.spare.len(), MAX_SPARE_BUFFERS);
+    }
+
+    #[test]
+    fn windows_replace_the_frames_own() {
+        let frame = DecodedFrame::Mono(pixel::Frame {
+            width: 3,
+            height: 1,
+            values: vec![-100.0, 0.0, 100.0],
+            window: None,
+            invert: false,
+        });
+        let options = RenderOptions {
+            window: Some(Window {
+                center: 50.0,
+                width: 100.0,
+            }),
+            ..RenderOptions::default()
+        };
+        let image = render_frame(frame, options).into_luma8();
+        let levels: Vec<u8> = image.pixels().map(|p| p.0[0]).collect();
+        assert_eq!(levels, [0, 0, 255]);
+    }
+
+    #[test]
+    fn deep_render_is_sixteen_bit() {
+        let frame = DecodedFrame::Mono(pixel::Frame {
+            width: 2,
+            height: 1,
+            values: vec![0.0, 4095.0],
+            window: None,
+            invert: false,
+        });
+        let options = RenderOptions {
+            deep: true,
+            ..RenderOptions::default()
+        };
+        let image = render_frame(frame, options);
+        assert_eq!(image.color(), image::ColorType::L16);
+        assert_eq!(image.to_luma16().get_pixel(1, 0).0[0], u16::MAX);
+    }
+
+    /// A 2x2 8-bit monochrome object at `path`.
+    fn write_tiny_image(path: &Path) {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        let mut obj = InMemDicomObject::new_empty();
+        let mut put = |tag, vr, value: PrimitiveValue| obj.put(DataElement::new(tag, vr, value));
+        put(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(1_u16));
+        put(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            PrimitiveValue::from("MONOCHROME2"),
+        );
+        put(tags::ROWS, VR::US, PrimitiveValue::from(2_u16));
+        put(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16));
+        put(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(8_u16));
+        put(tags::BITS_STORED, VR::US, PrimitiveValue::from(8_u16));
+        put(tags::HIGH_BIT, VR::US, PrimitiveValue::from(7_u16));
+        put(
+            tags::PIXEL_REPRESENTATION,
+            VR::US,
+            PrimitiveValue::from(0_u16),
+        );
+        put(
+            tags::PIXEL_DATA,
+            VR::OB,
+            PrimitiveValue::from(vec![0_u8, 80, 160, 240]),
+        );
+        let meta = dicom::object::FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+            .media_storage_sop_instance_uid("1.2.3")
+            .build()
+            .unwrap();
+        obj.with_exact_meta(meta).write_to_file(path).unwrap();
+    }
+
+    /// Sink that keeps the images it received.
+    struct ImageSink(Vec<DynamicImage>);
+
+    impl FrameSink for ImageSink {
+        fn write_frame(&mut self, _: usize, _: &Path, image: DynamicImage) -> Result<()> {
+            self.0.push(image);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cached_frames_are_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.dcm");
+        write_tiny_image(&source);
+        let cache = FrameCache::open(&dir.path().join("cache")).unwrap();
+        let options = RenderOptions {
+            cache: Some(&cache),
+            ..RenderOptions::default()
+        };
+        let files = [source.clone()];
+
+        let mut decoded = ImageSink(vec![]);
+        assert_eq!(run(&files, options.clone(), &mut decoded).written, 1);
+        let entry = cache.entry(&source, &options).unwrap();
+        assert_eq!(entry.cached().map(|cached| cached.count), Some(1));
+
+        let mut replayed = ImageSink(vec![]);
+        assert_eq!(run(&files, options, &mut replayed).written, 1);
+        assert_eq!(replayed.0, decoded.0);
+    }
+
+    #[test]
+    fn span_keeps_only_its_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..4)
+            .map(|i| {
+                let path = dir.path().join(format!("{i}.dcm"));
+                write_tiny_image(&path);
+                path
+            })
+            .collect();
+        let mut sink = RecordingSink(vec![]);
+        let stats = run_span(&files, 1..3, RenderOptions::default(), &mut sink);
+        assert_eq!(stats.written, 2);
+        assert_eq!(sink.0, [0, 1]);
+    }
+
+    #[test]
+    fn span_is_clipped_to_each_file() {
+        assert_eq!(within(&(12..25), 10, 10), 2..10);
+        assert_eq!(within(&(12..25), 20, 10), 0..5);
+        assert_eq!(within(&(12..25), 0, 10), 10..10);
+        assert_eq!(within(&(0..usize::MAX), 30, 10), 0..10);
+    }
+
+    #[test]
+    fn empty_group_produces_no_frames() {
+        let mut sink = RecordingSink(vec![]);
+        let stats = run(&[], RenderOptions::default(), &mut sink);
+        assert_eq!(stats, RunStats::default());
+    }
+}
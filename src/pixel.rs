@@ -0,0 +1,774 @@
+//! Pixel value calibration shared by all export paths.
+//!
+//! Decoded samples are mapped from stored values to modality units (e.g.
+//! Hounsfield units for CT) using `RescaleSlope`/`RescaleIntercept` or a
+//! Modality LUT Sequence, so that thresholds, statistics, and windows all
+//! operate on true values. Rendering to 8-bit (or 16-bit) happens as the last
+//! step.
+//! Both per-pixel transforms are vectorized in [`simd`].
+
+pub mod groups;
+mod simd;
+
+use std::path::{Path, PathBuf};
+
+use dicom::dictionary_std::tags;
+use dicom::object::{DefaultDicomObject, InMemDicomObject};
+use dicom_pixeldata::PixelDecoder;
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma};
+
+use crate::error::ToolboxError;
+
+/// Mapping from stored pixel values to modality units.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModalityLut {
+    /// Linear rescale: `value = stored * slope + intercept`.
+    Rescale { slope: f64, intercept: f64 },
+    /// Lookup table from the Modality LUT Sequence (0028,3000).
+    Table {
+        /// First stored value mapped by the table.
+        first_mapped: i32,
+        /// Output values, indexed from `first_mapped`.
+        entries: Vec<f32>,
+    },
+}
+
+impl ModalityLut {
+    /// Read the modality transform from a DICOM object.
+    ///
+    /// A Modality LUT Sequence takes precedence over rescale tags. Missing or
+    /// zero slopes fall back to the identity transform.
+    pub fn from_object(obj: &InMemDicomObject) -> Self {
+        if let Some(table) = read_lut_sequence(obj) {
+            return table;
+        }
+
+        let slope = read_first_f64(obj, tags::RESCALE_SLOPE)
+            .filter(|s| *s != 0.0)
+            .unwrap_or(1.0);
+        let intercept = read_first_f64(obj, tags::RESCALE_INTERCEPT).unwrap_or(0.0);
+        Self::Rescale { slope, intercept }
+    }
+
+    /// [`ModalityLut::from_object`] for frame `frame`, falling back to the
+    /// Pixel Value Transformation macro of enhanced multi-frame objects.
+    pub fn of_frame(obj: &InMemDicomObject, frame: u32) -> Self {
+        let top_level = obj.element(tags::MODALITY_LUT_SEQUENCE).is_ok()
+            || obj.element(tags::RESCALE_SLOPE).is_ok();
+        match groups::item(obj, frame, tags::PIXEL_VALUE_TRANSFORMATION_SEQUENCE) {
+            Some(item) if !top_level => Self::from_object(item),
+            _ => Self::from_object(obj),
+        }
+    }
+
+    /// Map each stored value to modality units, appending to `out`.
+    pub fn extend(&self, stored: impl Iterator<Item = i32>, out: &mut Vec<f32>) {
+        match self {
+            Self::Rescale { slope, intercept } => simd::rescale(stored, *slope, *intercept, out),
+            Self::Table { .. } => out.extend(stored.map(|value| self.apply(value))),
+        }
+    }
+
+    /// Map a stored value to modality units.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn apply(&self, stored: i32) -> f32 {
+        match self {
+            Self::Rescale { slope, intercept } => {
+                f64::from(stored).mul_add(*slope, *intercept) as f32
+            }
+            Self::Table {
+                first_mapped,
+                entries,
+            } => {
+                let offset = i64::from(stored) - i64::from(*first_mapped);
+                let last = entries.len().saturating_sub(1);
+                let idx = usize::try_from(offset.max(0)).unwrap_or(last).min(last);
+                entries.get(idx).copied().unwrap_or(0.0)
+            }
+        }
+    }
+}
+
+/// A VOI window expressed in modality units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    /// Window center (0028,1050).
+    pub center: f64,
+    /// Window width (0028,1051).
+    pub width: f64,
+}
+
+impl Window {
+    /// Read the first window from `WindowCenter`/`WindowWidth`, if present.
+    pub fn from_object(obj: &InMemDicomObject) -> Option<Self> {
+        let center = read_first_f64(obj, tags::WINDOW_CENTER)?;
+        let width = read_first_f64(obj, tags::WINDOW_WIDTH)?;
+        (width >= 1.0).then_some(Self { center, width })
+    }
+
+    /// [`Window::from_object`] for frame `frame`, falling back to the Frame
+    /// VOI LUT macro of enhanced multi-frame objects.
+    pub fn of_frame(obj: &InMemDicomObject, frame: u32) -> Option<Self> {
+        Self::from_object(obj)
+            .or_else(|| Self::from_object(groups::item(obj, frame, tags::FRAME_VOILUT_SEQUENCE)?))
+    }
+
+    /// Parse `CENTER,WIDTH` (e.g. `40,400`), as given to `--window`.
+    pub fn parse(value: &str) -> std::result::Result<Self, String> {
+        let (center, width) = value
+            .split_once(',')
+            .ok_or_else(|| format!("`{value}` is not CENTER,WIDTH"))?;
+        Ok(Self {
+            center: Self::parse_center(center)?,
+            width: Self::parse_width(width)?,
+        })
+    }
+
+    /// Parse a window center, as given to `--window-center`.
+    pub fn parse_center(value: &str) -> std::result::Result<f64, String> {
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| format!("`{value}` is not a number"))
+    }
+
+    /// Parse a window width of at least 1, as given to `--window-width`.
+    pub fn parse_width(value: &str) -> std::result::Result<f64, String> {
+        let width = Self::parse_center(value)?;
+        if width < 1.0 {
+            return Err("the width must be at least 1".to_string());
+        }
+        Ok(width)
+    }
+
+    /// Window that stretches `[lo, hi]` over the full output range.
+    pub fn spanning(lo: f32, hi: f32) -> Self {
+        let width = f64::from(hi - lo).max(1.0) + 1.0;
+        Self {
+            center: f64::from(lo) + width / 2.0,
+            width,
+        }
+    }
+
+    /// Lowest and highest value the window spans: values below it render
+    /// black, values above it white.
+    pub fn bounds(self) -> (f64, f64) {
+        let half = (self.width - 1.0) / 2.0;
+        (self.center - 0.5 - half, self.center - 0.5 + half)
+    }
+
+    /// Map a modality value to an 8-bit display value (DICOM linear VOI function).
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn apply(self, value: f32) -> u8 {
+        self.scale(value, f64::from(u8::MAX)) as u8
+    }
+
+    /// Map a modality value to a 16-bit display value, for `png16` output.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn apply16(self, value: f32) -> u16 {
+        self.scale(value, f64::from(u16::MAX)) as u16
+    }
+
+    /// The linear VOI function with output range `0..=top`, rounded.
+    fn scale(self, value: f32, top: f64) -> f64 {
+        let x = f64::from(value);
+        let lower = self.center - 0.5 - (self.width - 1.0) / 2.0;
+        let upper = self.center - 0.5 + (self.width - 1.0) / 2.0;
+
+        if x <= lower {
+            0.0
+        } else if x > upper {
+            top
+        } else {
+            let normalized = (x - (self.center - 0.5)) / (self.width - 1.0) + 0.5;
+            (normalized * top).round().clamp(0.0, top)
+        }
+    }
+}
+
+/// A single monochrome frame in modality units, stored row-major.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Number of columns.
+    pub width: u32,
+    /// Number of rows.
+    pub height: u32,
+    /// Calibrated pixel values (`values[x + y * width]`).
+    pub values: Vec<f32>,
+    /// Default window from the file, if any.
+    pub window: Option<Window>,
+    /// Whether the photometric interpretation is `MONOCHROME1` (inverted).
+    pub invert: bool,
+}
+
+impl Frame {
+    /// Minimum and maximum value of the frame.
+    pub fn value_range(&self) -> (f32, f32) {
+        simd::value_range(&self.values)
+    }
+
+    /// Window the frame renders with: the file's when present, otherwise one
+    /// stretching the full value range of the frame.
+    pub fn display_window(&self) -> Window {
+        self.window.unwrap_or_else(|| {
+            let (lo, hi) = self.value_range();
+            Window::spanning(lo, hi)
+        })
+    }
+
+    /// Render the frame to 8-bit grayscale.
+    ///
+    /// Uses the file's window when present, otherwise stretches the full
+    /// value range of the frame.
+    pub fn to_luma8(&self) -> GrayImage {
+        let window = self.display_window();
+
+        let mut pixels = Vec::with_capacity(self.values.len());
+        simd::window(window, self.invert, &self.values, &mut pixels);
+
+        GrayImage::from_raw(self.width, self.height, pixels)
+            .expect("frame buffer matches its dimensions")
+    }
+
+    /// Render the frame to 16-bit grayscale, windowed like [`Frame::to_luma8`].
+    ///
+    /// A full-range stretch keeps every level of data up to 16 bits deep.
+    pub fn to_luma16(&self) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        let window = self.display_window();
+
+        let pixels = self
+            .values
+            .iter()
+            .map(|&value| {
+                let level = window.apply16(value);
+                if self.invert { u16::MAX - level } else { level }
+            })
+            .collect();
+
+        ImageBuffer::from_raw(self.width, self.height, pixels)
+            .expect("frame buffer matches its dimensions")
+    }
+}
+
+/// How the samples of an object are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BitDepth {
+    /// Bits holding data per sample (0028,0101).
+    pub stored: u16,
+    /// Bits per sample in the pixel data (0028,0100).
+    pub allocated: u16,
+    /// Whether samples are two's complement (PixelRepresentation 1).
+    pub signed: bool,
+}
+
+impl BitDepth {
+    /// Read the sample layout; `None` without `BitsAllocated`.
+    pub fn from_object(obj: &InMemDicomObject) -> Option<Self> {
+        let read = |tag| obj.element(tag).ok().and_then(|e| e.to_int::<u16>().ok());
+        let allocated = read(tags::BITS_ALLOCATED)?;
+        Some(Self {
+            stored: read(tags::BITS_STORED).unwrap_or(allocated),
+            allocated,
+            signed: read(tags::PIXEL_REPRESENTATION) == Some(1),
+        })
+    }
+
+    /// Whether 8-bit output has fewer gray levels than the data.
+    pub const fn exceeds_8_bits(self) -> bool {
+        self.stored > 8
+    }
+}
+
+/// Result of decoding a single frame.
+#[derive(Clone)]
+pub enum DecodedFrame {
+    /// Monochrome data calibrated to modality units.
+    Mono(Frame),
+    /// Color data, passed through as decoded.
+    Color(DynamicImage),
+}
+
+impl DecodedFrame {
+    /// Obtain scalar values, converting color frames to luma.
+    pub fn into_mono(self) -> Frame {
+        match self {
+            Self::Mono(frame) => frame,
+            Self::Color(img) => {
+                let gray = img.to_luma8();
+                Frame {
+                    width: gray.width(),
+                    height: gray.height(),
+                    values: gray.pixels().map(|p| f32::from(p.0[0])).collect(),
+                    window: None,
+                    invert: false,
+                }
+            }
+        }
+    }
+}
+
+/// Decode one frame of a DICOM object and calibrate it to modality units.
+///
+/// Errors name no file; callers that know it add it with
+/// [`ToolboxError::at`].
+pub fn decode_frame(obj: &DefaultDicomObject, frame: u32) -> Result<DecodedFrame, ToolboxError> {
+    decode_frame_into(obj, frame, Vec::new())
+}
+
+/// [`decode_frame`], writing monochrome values into `buffer` (cleared
+/// first) so a buffer from an earlier frame can be reused.
+pub fn decode_frame_into(
+    obj: &DefaultDicomObject,
+    frame: u32,
+    mut buffer: Vec<f32>,
+) -> Result<DecodedFrame, ToolboxError> {
+    check_codec(obj)?;
+    for (tag, name) in [
+        (tags::PIXEL_DATA, "PixelData"),
+        (tags::ROWS, "Rows"),
+        (tags::COLUMNS, "Columns"),
+    ] {
+        if obj.element(tag).is_err() {
+            return Err(ToolboxError::MissingTag {
+                path: PathBuf::new(),
+                tag: name,
+            });
+        }
+    }
+    let pixel_data = obj
+        .decode_pixel_data_frame(frame)
+        .map_err(|e| ToolboxError::parse(Path::new(""), &e))?;
+
+    if pixel_data.samples_per_pixel() != 1 {
+        let img = pixel_data
+            .to_dynamic_image(0)
+            .map_err(|e| ToolboxError::parse(Path::new(""), &e))?;
+        return Ok(DecodedFrame::Color(img));
+    }
+
+    let width = pixel_data.columns();
+    let height = pixel_data.rows();
+    let count = width as usize * height as usize;
+    let bits_stored = pixel_data.bits_stored();
+    let signed = obj
+        .element(tags::PIXEL_REPRESENTATION)
+        .ok()
+        .and_then(|e| e.to_int::<u16>().ok())
+        == Some(1);
+    let lut = ModalityLut::of_frame(obj, frame);
+    let bytes = pixel_data.data();
+
+    let to_stored = |raw: u32| sample_to_stored(raw, bits_stored, signed);
+    buffer.clear();
+    buffer.reserve_exact(count);
+    match pixel_data.bits_allocated() {
+        8 => lut.extend(
+            bytes.iter().take(count).map(|&b| to_stored(u32::from(b))),
+            &mut buffer,
+        ),
+        16 => lut.extend(
+            bytes
+                .chunks_exact(2)
+                .take(count)
+                .map(|c| to_stored(u32::from(u16::from_le_bytes([c[0], c[1]])))),
+            &mut buffer,
+        ),
+        32 => lut.extend(
+            bytes
+                .chunks_exact(4)
+                .take(count)
+                .map(|c| to_stored(u32::from_le_bytes([c[0], c[1], c[2], c[3]]))),
+            &mut buffer,
+        ),
+        other => {
+            return Err(ToolboxError::Parse {
+                path: PathBuf::new(),
+                reason: format!("Unsupported BitsAllocated: {other}"),
+            });
+        }
+    }
+    let values = buffer;
+
+    if values.len() != count {
+        return Err(ToolboxError::Parse {
+            path: PathBuf::new(),
+            reason: format!(
+                "Pixel data too short: expected {count} samples, got {}",
+                values.len()
+            ),
+        });
+    }
+
+    let invert = obj
+        .element(tags::PHOTOMETRIC_INTERPRETATION)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .is_some_and(|s| s.trim() == "MONOCHROME1");
+
+    Ok(DecodedFrame::Mono(Frame {
+        width,
+        height,
+        values,
+        window: Window::of_frame(obj, frame),
+        invert,
+    }))
+}
+
+/// A compression whose decoder is an optional cargo feature.
+struct OptionalCodec {
+    /// Name shown to the user.
+    name: &'static str,
+    /// Transfer syntax UIDs it decodes.
+    uids: &'static [&'static str],
+    /// Cargo feature bundling the decoder.
+    feature: &'static str,
+    /// Whether this build has it.
+    enabled: bool,
+}
+
+/// Compressions beyond those always built in (JPEG baseline/lossless, RLE,
+/// deflate).
+const OPTIONAL_CODECS: [OptionalCodec; 2] = [
+    OptionalCodec {
+        name: "JPEG 2000",
+        uids: &["1.2.840.10008.1.2.4.90", "1.2.840.10008.1.2.4.91"],
+        feature: "jpeg2000",
+        enabled: cfg!(feature = "jpeg2000"),
+    },
+    OptionalCodec {
+        name: "JPEG-LS",
+        uids: &["1.2.840.10008.1.2.4.80", "1.2.840.10008.1.2.4.81"],
+        feature: "jpeg-ls",
+        enabled: cfg!(feature = "jpeg-ls"),
+    },
+];
+
+/// Fail if the object's pixel data is compressed with a codec this build
+/// leaves out, naming the cargo feature that adds it.
+pub fn check_codec(obj: &DefaultDicomObject) -> Result<(), ToolboxError> {
+    let uid = obj.meta().transfer_syntax().trim_end_matches(['\0', ' ']);
+    match OPTIONAL_CODECS
+        .iter()
+        .find(|codec| !codec.enabled && codec.uids.contains(&uid))
+    {
+        Some(codec) => Err(ToolboxError::UnsupportedTransferSyntax {
+            uid: uid.to_string(),
+            name: codec.name.to_string(),
+            feature: Some(codec.feature),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Interpret a raw sample according to `BitsStored` and `PixelRepresentation`.
+fn sample_to_stored(raw: u32, bits_stored: u16, signed: bool) -> i32 {
+    let bits = u32::from(bits_stored.clamp(1, 32));
+    let value = if bits == 32 {
+        raw
+    } else {
+        raw & ((1_u32 << bits) - 1)
+    };
+
+    if signed && value & (1 << (bits - 1)) != 0 {
+        let wrapped = i64::from(value) - (1_i64 << bits);
+        i32::try_from(wrapped).unwrap_or(i32::MIN)
+    } else {
+        i32::try_from(value).unwrap_or(i32::MAX)
+    }
+}
+
+/// Read the first value of a (possibly multi-valued) numeric string tag.
+pub fn read_first_f64(obj: &InMemDicomObject, tag: dicom::core::Tag) -> Option<f64> {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .and_then(|s| {
+            s.split('\\')
+                .next()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+        })
+}
+
+/// Read a Modality LUT Sequence into a lookup table.
+fn read_lut_sequence(obj: &InMemDicomObject) -> Option<ModalityLut> {
+    let item = obj
+        .element(tags::MODALITY_LUT_SEQUENCE)
+        .ok()?
+        .items()?
+        .first()?;
+
+    // LUTDescriptor: number of entries (0 means 65536), first mapped value, bits
+    let descriptor = item
+        .element(tags::LUT_DESCRIPTOR)
+        .ok()?
+        .to_multi_int::<i32>()
+        .ok()?;
+    let declared = match *descriptor.first()? {
+        0 => 65_536,
+        n => usize::try_from(n).ok()?,
+    };
+    let first_mapped = *descriptor.get(1)?;
+
+    let data = item
+        .element(tags::LUT_DATA)
+        .ok()?
+        .to_multi_int::<u16>()
+        .ok()?;
+    let entries: Vec<f32> = data.into_iter().take(declared).map(f32::from).collect();
+
+    (!entries.is_empty()).then_some(ModalityLut::Table {
+        first_mapped,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // =========================================================================
+    // Stored Value Tests
+    // =========================================================================
+
+    mod stored_values {
+        use super::*;
+
+        #[test]
+        fn unsigned_values_are_masked_to_bits_stored() {
+            assert_eq!(sample_to_stored(0xF0FF, 12, false), 0x00FF);
+            assert_eq!(sample_to_stored(4095, 12, false), 4095);
+        }
+
+        #[test]
+        fn signed_values_are_sign_extended() {
+            assert_eq!(sample_to_stored(0xFFFF, 16, true), -1);
+            assert_eq!(sample_to_stored(0x0800, 12, true), -2048);
+            assert_eq!(sample_to_stored(0x07FF, 12, true), 2047);
+        }
+
+        #[test]
+        fn eight_bit_signed_values() {
+            assert_eq!(sample_to_stored(0x80, 8, true), -128);
+            assert_eq!(sample_to_stored(0x7F, 8, true), 127);
+        }
+    }
+
+    // =========================================================================
+    // Modality LUT Tests
+    // =========================================================================
+
+    mod modality_lut {
+        use super::*;
+
+        #[test]
+        fn rescale_maps_to_hounsfield_units() {
+            let lut = ModalityLut::Rescale {
+                slope: 1.0,
+                intercept: -1024.0,
+            };
+            assert!((lut.apply(0) - -1024.0).abs() < f32::EPSILON);
+            assert!((lut.apply(1024) - 0.0).abs() < f32::EPSILON);
+        }
+
+        #[test]
+        fn rescale_applies_slope() {
+            let lut = ModalityLut::Rescale {
+                slope: 0.5,
+                intercept: 10.0,
+            };
+            assert!((lut.apply(100) - 60.0).abs() < f32::EPSILON);
+        }
+
+        #[test]
+        fn table_lookup_clamps_out_of_range() {
+            let lut = ModalityLut::Table {
+                first_mapped: 10,
+                entries: vec![1.0, 2.0, 3.0],
+            };
+            assert!((lut.apply(5) - 1.0).abs() < f32::EPSILON);
+            assert!((lut.apply(11) - 2.0).abs() < f32::EPSILON);
+            assert!((lut.apply(99) - 3.0).abs() < f32::EPSILON);
+        }
+    }
+
+    // =========================================================================
+    // Window Tests
+    // =========================================================================
+
+    mod window {
+        use super::*;
+
+        #[test]
+        fn values_outside_window_saturate() {
+            let window = Window {
+                center: 40.0,
+                width: 400.0,
+            };
+            assert_eq!(window.apply(-1000.0), 0);
+            assert_eq!(window.apply(1000.0), 255);
+        }
+
+        #[test]
+        fn center_maps_to_mid_gray() {
+            let window = Window {
+                center: 40.0,
+                width: 400.0,
+            };
+            let mid = window.apply(40.0);
+            assert!((127..=128).contains(&mid), "Center mapped to {mid}");
+        }
+
+        #[test]
+        fn windows_parse_from_center_and_width() {
+            assert_eq!(
+                Window::parse("-600, 1500"),
+                Ok(Window {
+                    center: -600.0,
+                    width: 1500.0
+                })
+            );
+            assert!(Window::parse("40").is_err());
+            assert!(Window::parse("40,wide").is_err());
+            assert!(Window::parse("40,0").is_err());
+        }
+
+        #[test]
+        fn frame_without_window_stretches_full_range() {
+            let frame = Frame {
+                width: 3,
+                height: 1,
+                values: vec![-1000.0, 0.0, 1000.0],
+                window: None,
+                invert: false,
+            };
+            let img = frame.to_luma8();
+            assert_eq!(img.get_pixel(0, 0).0[0], 0);
+            assert_eq!(img.get_pixel(2, 0).0[0], 255);
+        }
+
+        #[test]
+        fn sixteen_bit_rendering_keeps_every_level() {
+            let frame = Frame {
+                width: 4,
+                height: 1,
+                values: vec![0.0, 1.0, 2.0, 4095.0],
+                window: None,
+                invert: false,
+            };
+            let levels: Vec<u16> = frame.to_luma16().pixels().map(|p| p.0[0]).collect();
+            assert_eq!(levels[0], 0);
+            assert_eq!(levels[3], u16::MAX);
+            assert!(levels[0] < levels[1] && levels[1] < levels[2]);
+            // The same frame in 8 bits merges the lowest levels
+            let img = frame.to_luma8();
+            assert_eq!(img.get_pixel(0, 0), img.get_pixel(1, 0));
+        }
+
+        #[test]
+        fn monochrome1_is_inverted() {
+            let frame = Frame {
+                width: 2,
+                height: 1,
+                values: vec![0.0, 100.0],
+                window: None,
+                invert: true,
+            };
+            let img = frame.to_luma8();
+            assert_eq!(img.get_pixel(0, 0).0[0], 255);
+            assert_eq!(img.get_pixel(1, 0).0[0], 0);
+        }
+    }
+    // =========================================================================
+    // Bit Depth Tests
+    // =========================================================================
+
+    mod bit_depth {
+        use dicom::core::{DataElement, PrimitiveValue, VR};
+
+        use super::*;
+
+        fn object(tags_values: &[(dicom::core::Tag, u16)]) -> InMemDicomObject {
+            let mut obj = InMemDicomObject::new_empty();
+            for &(tag, value) in tags_values {
+                obj.put(DataElement::new(tag, VR::US, PrimitiveValue::from(value)));
+            }
+            obj
+        }
+
+        #[test]
+        fn reads_stored_allocated_and_sign() {
+            let obj = object(&[
+                (tags::BITS_ALLOCATED, 16),
+                (tags::BITS_STORED, 12),
+                (tags::PIXEL_REPRESENTATION, 1),
+            ]);
+            let depth = BitDepth::from_object(&obj).unwrap();
+            assert_eq!(
+                depth,
+                BitDepth {
+                    stored: 12,
+                    allocated: 16,
+                    signed: true
+                }
+            );
+            assert!(depth.exceeds_8_bits());
+        }
+
+        #[test]
+        fn stored_defaults_to_allocated() {
+            let depth = BitDepth::from_object(&object(&[(tags::BITS_ALLOCATED, 8)])).unwrap();
+            assert_eq!(depth.stored, 8);
+            assert!(!depth.signed);
+            assert!(!depth.exceeds_8_bits());
+        }
+
+        #[test]
+        fn missing_allocation_is_unknown() {
+            assert_eq!(BitDepth::from_object(&object(&[])), None);
+        }
+    }
+
+    // =========================================================================
+    // Optional Codec Tests
+    // =========================================================================
+
+    mod optional_codecs {
+        use dicom::object::FileMetaTableBuilder;
+
+        use super::*;
+
+        fn compressed_with(transfer_syntax: &str) -> DefaultDicomObject {
+            let meta = FileMetaTableBuilder::new()
+                .transfer_syntax(transfer_syntax)
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+                .media_storage_sop_instance_uid("1.2.3")
+                .build()
+                .unwrap();
+            InMemDicomObject::new_empty().with_exact_meta(meta)
+        }
+
+        #[test]
+        fn missing_decoders_name_their_feature() {
+            let check = check_codec(&compressed_with("1.2.840.10008.1.2.4.90"));
+            if cfg!(feature = "jpeg2000") {
+                assert!(check.is_ok());
+            } else {
+                let message = check.unwrap_err().to_string();
+                assert!(message.contains("JPEG 2000") && message.contains("--features jpeg2000"));
+            }
+        }
+
+        #[test]
+        fn built_in_syntaxes_pass() {
+            for uid in [
+                "1.2.840.10008.1.2.1",
+                "1.2.840.10008.1.2.5",
+                "1.2.840.10008.1.2.4.50",
+            ] {
+                assert!(check_codec(&compressed_with(uid)).is_ok(), "{uid}");
+            }
+        }
+    }
+}
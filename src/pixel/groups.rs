@@ -0,0 +1,158 @@
+//! Attributes of enhanced multi-frame objects (functional groups).
+//!
+//! Enhanced objects such as Breast Tomosynthesis Image Storage keep pixel
+//! spacing, frame positions, windows, and rescale values in functional
+//! group macros instead of top-level tags: per frame in the
+//! `PerFrameFunctionalGroupsSequence`, or once for all frames in the
+//! `SharedFunctionalGroupsSequence`. The readers here look at the top level
+//! first, then the frame's own group, then the shared one.
+
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::InMemDicomObject;
+
+/// The item of macro `sequence` that applies to `frame` (counted from 0):
+/// the frame's own, else the shared one.
+pub fn item(obj: &InMemDicomObject, frame: u32, sequence: Tag) -> Option<&InMemDicomObject> {
+    let per_frame = obj
+        .element(tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE)
+        .ok()
+        .and_then(|e| e.items())
+        .and_then(|items| items.get(usize::try_from(frame).ok()?))
+        .and_then(|group| first_item(group, sequence));
+    per_frame.or_else(|| {
+        let shared = first_item(obj, tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE)?;
+        first_item(shared, sequence)
+    })
+}
+
+/// First item of sequence `tag` of `obj`.
+fn first_item(obj: &InMemDicomObject, tag: Tag) -> Option<&InMemDicomObject> {
+    obj.element(tag).ok()?.items()?.first()
+}
+
+/// Numbers of a multi-valued decimal tag.
+fn numbers(obj: &InMemDicomObject, tag: Tag) -> Option<Vec<f64>> {
+    let text = obj.element(tag).ok()?.to_str().ok()?;
+    text.split('\\').map(|v| v.trim().parse().ok()).collect()
+}
+
+/// `PixelSpacing` of `frame` as (between rows, between columns) in mm:
+/// top-level, else from the Pixel Measures macro, else `ImagerPixelSpacing`
+/// (projection images). Rows and columns may be spaced differently.
+pub fn pixel_spacing(obj: &InMemDicomObject, frame: u32) -> Option<[f64; 2]> {
+    let pair = |obj: &InMemDicomObject, tag| {
+        numbers(obj, tag)
+            .filter(|values| values.len() >= 2 && values[..2].iter().all(|v| *v > 0.0))
+            .map(|values| [values[0], values[1]])
+    };
+    pair(obj, tags::PIXEL_SPACING)
+        .or_else(|| {
+            pair(
+                item(obj, frame, tags::PIXEL_MEASURES_SEQUENCE)?,
+                tags::PIXEL_SPACING,
+            )
+        })
+        .or_else(|| pair(obj, tags::IMAGER_PIXEL_SPACING))
+}
+
+/// `ImagePositionPatient` of `frame`: top-level, else from the Plane
+/// Position macro.
+pub fn image_position(obj: &InMemDicomObject, frame: u32) -> Option<[f64; 3]> {
+    let triple = |obj: &InMemDicomObject| {
+        numbers(obj, tags::IMAGE_POSITION_PATIENT)
+            .filter(|values| values.len() >= 3)
+            .map(|values| [values[0], values[1], values[2]])
+    };
+    triple(obj).or_else(|| triple(item(obj, frame, tags::PLANE_POSITION_SEQUENCE)?))
+}
+
+/// `SpacingBetweenSlices`, else `SliceThickness`, of the Pixel Measures
+/// macro of `frame`, in mm.
+pub fn slice_spacing(obj: &InMemDicomObject, frame: u32) -> Option<f64> {
+    let measures = item(obj, frame, tags::PIXEL_MEASURES_SEQUENCE)?;
+    [tags::SPACING_BETWEEN_SLICES, tags::SLICE_THICKNESS]
+        .into_iter()
+        .find_map(|tag| {
+            numbers(measures, tag)?
+                .first()
+                .copied()
+                .filter(|v| *v > 0.0)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::{DataElement, VR};
+    use dicom::object::InMemDicomObject;
+
+    use super::*;
+    use crate::meta::text_element;
+
+    fn sequence(tag: Tag, items: Vec<InMemDicomObject>) -> DataElement<InMemDicomObject> {
+        DataElement::new(
+            tag,
+            VR::SQ,
+            dicom::core::value::DataSetSequence::from(items),
+        )
+    }
+
+    /// A two-frame object: shared measures, per-frame positions.
+    fn tomosynthesis() -> InMemDicomObject {
+        let measures = InMemDicomObject::from_element_iter([
+            text_element(tags::PIXEL_SPACING, VR::DS, "0.085\\0.1"),
+            text_element(tags::SLICE_THICKNESS, VR::DS, "1"),
+        ]);
+        let shared = InMemDicomObject::from_element_iter([sequence(
+            tags::PIXEL_MEASURES_SEQUENCE,
+            vec![measures],
+        )]);
+        let frame = |z: &str| {
+            let position = InMemDicomObject::from_element_iter([text_element(
+                tags::IMAGE_POSITION_PATIENT,
+                VR::DS,
+                &format!("0\\0\\{z}"),
+            )]);
+            InMemDicomObject::from_element_iter([sequence(
+                tags::PLANE_POSITION_SEQUENCE,
+                vec![position],
+            )])
+        };
+        InMemDicomObject::from_element_iter([
+            sequence(tags::SHARED_FUNCTIONAL_GROUPS_SEQUENCE, vec![shared]),
+            sequence(
+                tags::PER_FRAME_FUNCTIONAL_GROUPS_SEQUENCE,
+                vec![frame("10"), frame("11")],
+            ),
+        ])
+    }
+
+    #[test]
+    fn shared_measures_apply_to_every_frame() {
+        let obj = tomosynthesis();
+        assert_eq!(pixel_spacing(&obj, 0), Some([0.085, 0.1]));
+        assert_eq!(pixel_spacing(&obj, 1), Some([0.085, 0.1]));
+        assert_eq!(slice_spacing(&obj, 1), Some(1.0));
+    }
+
+    #[test]
+    fn positions_are_per_frame() {
+        let obj = tomosynthesis();
+        assert_eq!(image_position(&obj, 0), Some([0.0, 0.0, 10.0]));
+        assert_eq!(image_position(&obj, 1), Some([0.0, 0.0, 11.0]));
+        assert_eq!(image_position(&obj, 2), None);
+    }
+
+    #[test]
+    fn top_level_tags_come_first() {
+        let mut obj = tomosynthesis();
+        obj.put(text_element(tags::PIXEL_SPACING, VR::DS, "0.5\\0.5"));
+        assert_eq!(pixel_spacing(&obj, 0), Some([0.5, 0.5]));
+        let projection = InMemDicomObject::from_element_iter([text_element(
+            tags::IMAGER_PIXEL_SPACING,
+            VR::DS,
+            "0.07\\0.07",
+        )]);
+        assert_eq!(pixel_spacing(&projection, 0), Some([0.07, 0.07]));
+    }
+}
@@ -0,0 +1,176 @@
+//! Vectorized intensity transforms.
+//!
+//! Rescaling stored samples and windowing them to 8 bits touch every pixel
+//! of every frame, and dominate the run time once decoding and encoding are
+//! cheap (fast disks, low JPEG quality). Both run here on `wide` vectors,
+//! four `f64` lanes at a time so results match the scalar
+//! [`ModalityLut::apply`](super::ModalityLut::apply) and [`Window::apply`]
+//! they replace; leftover samples go through the scalar code. Rescaling
+//! rounds once, like the `mul_add` of `apply`: on vectors where the target
+//! has FMA, lane by lane otherwise, since `wide` then multiplies and adds
+//! separately.
+
+use wide::{f32x8, f64x4};
+
+use super::Window;
+
+/// Lanes of the `f64` vectors.
+const LANES: usize = 4;
+
+/// Append `stored * slope + intercept` for each stored value to `out`.
+#[allow(clippy::cast_possible_truncation)]
+pub(super) fn rescale(
+    stored: impl Iterator<Item = i32>,
+    slope: f64,
+    intercept: f64,
+    out: &mut Vec<f32>,
+) {
+    let (slope_lanes, intercept_lanes) = (f64x4::splat(slope), f64x4::splat(intercept));
+    let mut lanes = [0.0; LANES];
+    let mut filled = 0;
+    for value in stored {
+        lanes[filled] = f64::from(value);
+        filled += 1;
+        if filled == LANES {
+            let values = mul_add(f64x4::new(lanes), slope_lanes, intercept_lanes);
+            out.extend(values.to_array().map(|value| value as f32));
+            filled = 0;
+        }
+    }
+    out.extend(
+        lanes[..filled]
+            .iter()
+            .map(|&value| value.mul_add(slope, intercept) as f32),
+    );
+}
+
+/// `values * slope + intercept`, rounded once as [`f64::mul_add`] does.
+fn mul_add(values: f64x4, slope: f64x4, intercept: f64x4) -> f64x4 {
+    if cfg!(target_feature = "fma") {
+        values.mul_add(slope, intercept)
+    } else {
+        let (values, slope, intercept) =
+            (values.to_array(), slope.to_array(), intercept.to_array());
+        f64x4::new(std::array::from_fn(|lane| {
+            values[lane].mul_add(slope[lane], intercept[lane])
+        }))
+    }
+}
+
+/// Append the 8-bit display value of each of `values` under `window` to
+/// `out`, inverted for `MONOCHROME1`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(super) fn window(window: Window, invert: bool, values: &[f32], out: &mut Vec<u8>) {
+    let offset = f64x4::splat(window.center - 0.5);
+    let scale = f64x4::splat(window.width - 1.0);
+    let (half, max) = (f64x4::splat(0.5), f64x4::splat(255.0));
+    let quantize = |level: u8| if invert { 255 - level } else { level };
+
+    let chunks = values.chunks_exact(LANES);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let x = f64x4::new([
+            f64::from(chunk[0]),
+            f64::from(chunk[1]),
+            f64::from(chunk[2]),
+            f64::from(chunk[3]),
+        ]);
+        // Below the window clamps to 0 and above it to 255, as the scalar
+        // branches do; `+ 0.5` then `floor` rounds halves up like `round`.
+        let level = (((x - offset) / scale + half) * max)
+            .max(f64x4::ZERO)
+            .min(max);
+        let level = (level + half).floor();
+        out.extend(level.to_array().map(|level| quantize(level as u8)));
+    }
+    out.extend(tail.iter().map(|&value| quantize(window.apply(value))));
+}
+
+/// Minimum and maximum of `values`, or `(f32::MAX, f32::MIN)` if empty.
+pub(super) fn value_range(values: &[f32]) -> (f32, f32) {
+    let chunks = values.chunks_exact(8);
+    let tail = chunks.remainder();
+    let (mut lo, mut hi) = (f32x8::splat(f32::MAX), f32x8::splat(f32::MIN));
+    for chunk in chunks {
+        let lanes = f32x8::new(chunk.try_into().expect("chunks have eight values"));
+        lo = lo.min(lanes);
+        hi = hi.max(lanes);
+    }
+    lo.to_array()
+        .into_iter()
+        .zip(hi.to_array())
+        .chain(tail.iter().map(|&value| (value, value)))
+        .fold((f32::MAX, f32::MIN), |(lo, hi), (l, h)| {
+            (lo.min(l), hi.max(h))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::ModalityLut;
+
+    /// Values around and between the window edges, including halves.
+    fn sweep() -> Vec<f32> {
+        (-600..=600).map(|step| step as f32 * 0.25).collect()
+    }
+
+    #[test]
+    fn window_matches_the_scalar_transform() {
+        let values = sweep();
+        for window in [
+            Window {
+                center: 40.0,
+                width: 400.0,
+            },
+            Window {
+                center: 0.0,
+                width: 1.0,
+            },
+            Window::spanning(-150.0, 150.0),
+        ] {
+            for invert in [false, true] {
+                let mut out = Vec::new();
+                super::window(window, invert, &values, &mut out);
+                let expected: Vec<u8> = values
+                    .iter()
+                    .map(|&value| {
+                        let level = window.apply(value);
+                        if invert { 255 - level } else { level }
+                    })
+                    .collect();
+                assert_eq!(out, expected, "{window:?} invert={invert}");
+            }
+        }
+    }
+
+    #[test]
+    fn rescale_covers_every_sample() {
+        let mut out = Vec::new();
+        rescale((0..7).map(|stored| stored * 100), 1.0, -1024.0, &mut out);
+        assert_eq!(
+            out,
+            [-1024.0, -924.0, -824.0, -724.0, -624.0, -524.0, -424.0]
+        );
+    }
+
+    #[test]
+    fn rescale_matches_the_scalar_transform() {
+        let stored = -2048..4099;
+        for (slope, intercept) in [(0.37, -1024.3), (2.5e-3, 0.1), (-1.7, 3.3)] {
+            let lut = ModalityLut::Rescale { slope, intercept };
+            let mut out = Vec::new();
+            rescale(stored.clone(), slope, intercept, &mut out);
+            let expected: Vec<f32> = stored.clone().map(|value| lut.apply(value)).collect();
+            assert_eq!(out, expected, "slope={slope} intercept={intercept}");
+        }
+    }
+
+    #[test]
+    fn range_includes_the_tail() {
+        let mut values = sweep();
+        values.push(999.0);
+        assert_eq!(value_range(&values), (-150.0, 999.0));
+        assert_eq!(value_range(&[]), (f32::MAX, f32::MIN));
+    }
+}
@@ -0,0 +1,157 @@
+//! Reading DICOM files ahead of their decoding (`--prefetch`).
+//!
+//! On network shares (SMB, NFS) each small file costs a round trip or more,
+//! and the CPU waits for every one of them. With `--prefetch N`, a
+//! background thread reads the next `N` files of a series into memory while
+//! the current one decodes, so the two overlap. Reads keep their order and
+//! stay paced by `--throttle-read`; at most `N` files wait in memory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use dicom::object::DefaultDicomObject;
+
+use crate::error::ToolboxError;
+use crate::pipeline::{open_object, parse_object};
+use crate::throttle;
+
+/// Files read ahead, once set by `--prefetch`.
+static DEPTH: OnceLock<usize> = OnceLock::new();
+
+/// Read up to `depth` files ahead from now on; 0 reads each file when it is
+/// opened.
+pub fn set(depth: usize) {
+    let _ = DEPTH.set(depth);
+}
+
+/// The files of one series, opened in order.
+///
+/// Without `--prefetch`, [`ReadAhead::open`] reads each file from disk; with
+/// it, the bytes usually wait in memory already.
+pub struct ReadAhead {
+    /// Bytes of the upcoming files, or the error reading them.
+    ahead: Option<Receiver<std::io::Result<Vec<u8>>>>,
+}
+
+impl ReadAhead {
+    /// Start reading `files` ahead, if `--prefetch` asks for it.
+    pub fn start(files: &[PathBuf]) -> Self {
+        Self::with_depth(files, DEPTH.get().copied().unwrap_or(0))
+    }
+
+    fn with_depth(files: &[PathBuf], depth: usize) -> Self {
+        if depth == 0 || files.len() < 2 {
+            return Self { ahead: None };
+        }
+        // One file in the channel is the one being read, not yet ahead
+        let (sender, receiver) = mpsc::sync_channel(depth - 1);
+        let files = files.to_vec();
+        thread::spawn(move || {
+            for path in files {
+                throttle::before_read(&path);
+                // The run stopped early when nobody receives any more
+                if sender.send(fs::read(&path)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            ahead: Some(receiver),
+        }
+    }
+
+    /// Pass over the next file of the series, which is not needed.
+    pub fn skip(&mut self) {
+        if let Some(ahead) = &self.ahead {
+            let _ = ahead.recv();
+        }
+    }
+
+    /// Open the next file of the series, which must be `path`.
+    pub fn open(&mut self, path: &Path) -> Result<DefaultDicomObject, ToolboxError> {
+        let read = self.ahead.as_ref().and_then(|ahead| ahead.recv().ok());
+        match read {
+            Some(bytes) => bytes
+                .map_err(|source| ToolboxError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+                .and_then(|bytes| parse_object(&bytes, path)),
+            None => {
+                throttle::before_read(path);
+                open_object(path)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_series(dir: &Path, count: usize) -> Vec<PathBuf> {
+        (0..count)
+            .map(|i| {
+                let path = dir.join(format!("{i}.dcm"));
+                let mut obj = dicom::object::InMemDicomObject::new_empty();
+                obj.put(dicom::core::DataElement::new(
+                    dicom::dictionary_std::tags::INSTANCE_NUMBER,
+                    dicom::core::VR::IS,
+                    dicom::core::PrimitiveValue::from(i.to_string()),
+                ));
+                let meta = dicom::object::FileMetaTableBuilder::new()
+                    .transfer_syntax("1.2.840.10008.1.2.1")
+                    .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+                    .media_storage_sop_instance_uid(format!("1.2.{i}"))
+                    .build()
+                    .unwrap();
+                obj.with_exact_meta(meta).write_to_file(&path).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn instance(obj: &DefaultDicomObject) -> String {
+        obj.element(dicom::dictionary_std::tags::INSTANCE_NUMBER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    #[test]
+    fn prefetched_files_open_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_series(dir.path(), 5);
+        let mut ahead = ReadAhead::with_depth(&files, 2);
+        assert!(ahead.ahead.is_some());
+        for (i, path) in files.iter().enumerate() {
+            assert_eq!(instance(&ahead.open(path).unwrap()), i.to_string());
+        }
+    }
+
+    #[test]
+    fn unreadable_files_fail_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = write_series(dir.path(), 2);
+        files.insert(1, dir.path().join("missing.dcm"));
+        let mut ahead = ReadAhead::with_depth(&files, 4);
+        assert!(ahead.open(&files[0]).is_ok());
+        let err = ahead.open(&files[1]).unwrap_err();
+        assert!(format!("{err}").contains("missing.dcm"));
+        assert_eq!(instance(&ahead.open(&files[2]).unwrap()), "1");
+    }
+
+    #[test]
+    fn no_depth_reads_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_series(dir.path(), 3);
+        let mut ahead = ReadAhead::with_depth(&files, 0);
+        assert!(ahead.ahead.is_none());
+        assert_eq!(instance(&ahead.open(&files[2]).unwrap()), "2");
+    }
+}
@@ -0,0 +1,468 @@
+//! Private tag policy (`private-tags`).
+//!
+//! Vendors keep useful acquisition details in private blocks (GE's
+//! `GEMS_ACQU_01` scan parameters, Siemens' `SIEMENS CSA HEADER`), next to
+//! blocks that may repeat patient data nobody has vetted. Instead of keeping
+//! or dropping all of them, a TOML policy lists the blocks known to be safe
+//! and copies are written with only those; every other private element is
+//! dropped, in nested sequence items too.
+//!
+//! A block is named by its private creator, the value of `(gggg,00xx)` that
+//! reserves elements `(gggg,xx00)` to `(gggg,xxFF)`, since vendors place the
+//! same block at different `xx` from file to file:
+//!
+//! ```toml
+//! [[keep]]
+//! creator = "GEMS_ACQU_01"
+//!
+//! [[keep]]
+//! creator = "SIEMENS CSA HEADER"
+//! group = 0x0029           # only in this group
+//! elements = [0x08, 0x10]  # only these elements of the block (low byte)
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use dicom::core::{Tag, VR, header::Header};
+use dicom::object::InMemDicomObject;
+use serde::Deserialize;
+
+use crate::cancel;
+use crate::i18n::t;
+use crate::inventory;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, display_name};
+
+/// CLI arguments for the `private-tags` subcommand.
+#[derive(Args, Debug)]
+pub struct PrivateTagsArgs {
+    /// DICOM file, or folder of them (subfolders included)
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Folder to write the copies to, in the same layout as `--in`
+    #[arg(long = "out")]
+    pub output: PathBuf,
+
+    /// TOML file listing the private blocks to keep; without it, every
+    /// private element is dropped
+    #[arg(long, value_name = "FILE")]
+    pub policy: Option<PathBuf>,
+
+    /// Include symlinked .dcm files and folders (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Private blocks to keep; everything else private is dropped.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    keep: Vec<Rule>,
+}
+
+/// One block to keep, by private creator.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    /// Private creator, compared without padding.
+    creator: String,
+    /// Only in this group; any private group otherwise.
+    group: Option<u16>,
+    /// Only these elements of the block (the low byte of the element
+    /// number); the whole block otherwise.
+    elements: Option<Vec<u8>>,
+}
+
+/// Private elements kept and dropped. Private creators are not counted,
+/// except unreadable ones, which are dropped like any other element.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Tally {
+    kept: usize,
+    dropped: usize,
+}
+
+impl std::ops::AddAssign for Tally {
+    fn add_assign(&mut self, other: Self) {
+        self.kept += other.kept;
+        self.dropped += other.dropped;
+    }
+}
+
+/// Whether `group` holds private elements: odd, and not one of the
+/// reserved groups 0001 to 0007.
+fn is_private(group: u16) -> bool {
+    group % 2 == 1 && group > 0x0008
+}
+
+impl Policy {
+    /// Read the policy at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| BadInput(format!("Failed to read policy: {}: {e}", path.display())))?;
+        Self::parse(&text)
+            .map_err(|e| BadInput(format!("Invalid policy {}: {e}", path.display())).into())
+    }
+
+    fn parse(text: &str) -> std::result::Result<Self, String> {
+        let policy: Self = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        for rule in &policy.keep {
+            if rule.creator.trim().is_empty() {
+                return Err("`creator` must not be empty".to_string());
+            }
+            if let Some(group) = rule.group.filter(|&group| !is_private(group)) {
+                return Err(format!(
+                    "group {group:04X} of `{}` is not a private group",
+                    rule.creator
+                ));
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Whether element `offset` of the block reserved by `creator` in
+    /// `group` is kept.
+    fn keeps(&self, group: u16, creator: &str, offset: u8) -> bool {
+        self.keep.iter().any(|rule| {
+            rule.creator.trim() == creator
+                && rule.group.is_none_or(|g| g == group)
+                && rule
+                    .elements
+                    .as_ref()
+                    .is_none_or(|elements| elements.contains(&offset))
+        })
+    }
+
+    /// Drop the private elements of `obj` and its sequence items that the
+    /// policy does not keep, along with the creators of emptied blocks.
+    fn apply(&self, obj: &mut InMemDicomObject) -> Tally {
+        let creators: HashMap<(u16, u8), String> = obj
+            .iter()
+            .filter(|e| is_private(e.tag().group()) && (0x10..=0xFF).contains(&e.tag().element()))
+            .filter_map(|e| {
+                let creator = e.to_str().ok()?;
+                let block = u8::try_from(e.tag().element()).ok()?;
+                Some((
+                    (e.tag().group(), block),
+                    creator.trim_matches([' ', '\0']).to_string(),
+                ))
+            })
+            .collect();
+
+        let mut tally = Tally::default();
+        let mut used = HashSet::new();
+        let mut dropped = Vec::new();
+        for tag in obj.tags().filter(|tag| is_private(tag.group())) {
+            let Tag(group, element) = tag;
+            if (0x10..=0xFF).contains(&element) {
+                // A creator that cannot be read reserves nothing and goes;
+                // the others are kept or dropped below, once their blocks
+                // are decided
+                let block = u8::try_from(element).unwrap_or(u8::MAX);
+                if !creators.contains_key(&(group, block)) {
+                    tally.dropped += 1;
+                    dropped.push(tag);
+                }
+                continue;
+            }
+            let [block, offset] = element.to_be_bytes();
+            let kept = element >= 0x1000
+                && creators
+                    .get(&(group, block))
+                    .is_some_and(|creator| self.keeps(group, creator, offset));
+            if kept {
+                tally.kept += 1;
+                used.insert((group, block));
+            } else {
+                // Elements 0000 to 000F (group lengths and reserved) go too
+                tally.dropped += 1;
+                dropped.push(tag);
+            }
+        }
+        dropped.extend(
+            creators
+                .keys()
+                .filter(|block| !used.contains(*block))
+                .map(|&(group, block)| Tag(group, u16::from(block))),
+        );
+        for tag in dropped {
+            obj.remove_element(tag);
+        }
+
+        let sequences: Vec<Tag> = obj
+            .iter()
+            .filter(|e| e.vr() == VR::SQ)
+            .map(|e| e.tag())
+            .collect();
+        for tag in sequences {
+            obj.update_value(tag, |value| {
+                for item in value.items_mut().into_iter().flatten() {
+                    tally += self.apply(item);
+                }
+            });
+        }
+        tally
+    }
+}
+
+/// Input files paired with the paths of their copies below `--out`.
+fn targets(args: &PrivateTagsArgs) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let (root, files) = if args.input.is_file() {
+        let root = args.input.parent().unwrap_or(Path::new("."));
+        (root.to_path_buf(), vec![args.input.clone()])
+    } else if args.input.is_dir() {
+        let files = inventory::walk(&args.input, args.follow_symlinks)?;
+        (args.input.clone(), files)
+    } else {
+        anyhow::bail!(BadInput(format!(
+            "--in must be a DICOM file or folder: {}",
+            args.input.display()
+        )));
+    };
+    let same_folder = fs::canonicalize(&root)
+        .ok()
+        .zip(fs::canonicalize(&args.output).ok())
+        .is_some_and(|(root, output)| root == output);
+    if same_folder {
+        anyhow::bail!(BadInput(format!(
+            "--out must not be the folder of the originals: {}",
+            args.output.display()
+        )));
+    }
+    Ok(files
+        .into_iter()
+        .map(|file| {
+            let relative = file
+                .strip_prefix(&root)
+                .map_or_else(|_| PathBuf::from(display_name(&file)), Path::to_path_buf);
+            let copy = args.output.join(relative);
+            (file, copy)
+        })
+        .collect())
+}
+
+/// Copy `input` to `output` with only the private elements `policy` keeps.
+fn copy_file(policy: &Policy, input: &Path, output: &Path) -> Result<Tally> {
+    let mut obj = pipeline::open_object(input)?;
+    let tally = policy.apply(&mut obj);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output folder: {}", parent.display()))?;
+    }
+    obj.write_to_file(output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(tally)
+}
+
+/// Write a copy of every input file with the private blocks the policy
+/// keeps, print a line each and a summary, and fail if any file could not
+/// be copied.
+pub fn run(args: &PrivateTagsArgs) -> Result<()> {
+    let policy = match &args.policy {
+        Some(path) => Policy::load(path)?,
+        None => Policy::default(),
+    };
+    let targets = targets(args)?;
+    if targets.is_empty() {
+        println!(
+            "{}",
+            t!("no-dcm-files", path = args.input.display().to_string())
+        );
+        return Ok(());
+    }
+    println!("{}\n", t!("private-tags-writing", count = targets.len()));
+
+    let (mut total, mut failed) = (Tally::default(), 0);
+    for (input, output) in &targets {
+        if let Some(stopped) = cancel::ended() {
+            anyhow::bail!(stopped);
+        }
+        let file = display_name(input);
+        match copy_file(&policy, input, output) {
+            Ok(tally) => {
+                total += tally;
+                println!(
+                    "{}",
+                    t!(
+                        "private-tags-file",
+                        file = file,
+                        kept = tally.kept,
+                        dropped = tally.dropped
+                    )
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                println!(
+                    "{}",
+                    t!("private-tags-failed", file = file, error = format!("{e:#}"))
+                );
+            }
+        }
+    }
+
+    println!(
+        "\n{}",
+        t!(
+            "private-tags-summary",
+            files = targets.len() - failed,
+            path = args.output.display().to_string(),
+            kept = total.kept,
+            dropped = total.dropped
+        )
+    );
+    if failed > 0 {
+        anyhow::bail!("Failed to copy {failed} file(s)");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::value::DataSetSequence;
+    use dicom::core::{DataElement, PrimitiveValue};
+    use dicom::dictionary_std::tags;
+
+    use super::*;
+
+    const POLICY: &str = r#"
+        [[keep]]
+        creator = "GEMS_ACQU_01"
+
+        [[keep]]
+        creator = "SIEMENS CSA HEADER"
+        group = 0x0029
+        elements = [0x08]
+    "#;
+
+    fn put(obj: &mut InMemDicomObject, tag: Tag, vr: VR, value: &str) {
+        obj.put(DataElement::new(tag, vr, PrimitiveValue::from(value)));
+    }
+
+    /// A header with a GE block at (0019,10xx), a Siemens block at
+    /// (0029,11xx), and an unknown one at (0009,10xx).
+    fn header() -> InMemDicomObject {
+        let mut obj = InMemDicomObject::new_empty();
+        put(&mut obj, tags::PATIENT_NAME, VR::PN, "Doe^Jane");
+        put(&mut obj, Tag(0x0009, 0x0010), VR::LO, "ACME PRIVATE ");
+        put(&mut obj, Tag(0x0009, 0x1001), VR::LO, "Jane Doe");
+        put(&mut obj, Tag(0x0019, 0x0010), VR::LO, "GEMS_ACQU_01");
+        put(&mut obj, Tag(0x0019, 0x1002), VR::DS, "1.5");
+        put(&mut obj, Tag(0x0019, 0x1018), VR::DS, "0.8");
+        put(&mut obj, Tag(0x0029, 0x0011), VR::LO, "SIEMENS CSA HEADER");
+        put(&mut obj, Tag(0x0029, 0x1108), VR::CS, "IMAGE NUM 4");
+        put(&mut obj, Tag(0x0029, 0x1110), VR::OB, "CSA");
+        obj
+    }
+
+    #[test]
+    fn keeps_listed_blocks_and_drops_the_rest() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let mut obj = header();
+        let tally = policy.apply(&mut obj);
+
+        assert_eq!(
+            tally,
+            Tally {
+                kept: 3,
+                dropped: 2
+            }
+        );
+        let tags: Vec<Tag> = obj.tags().collect();
+        assert_eq!(
+            tags,
+            [
+                tags::PATIENT_NAME,
+                Tag(0x0019, 0x0010),
+                Tag(0x0019, 0x1002),
+                Tag(0x0019, 0x1018),
+                Tag(0x0029, 0x0011),
+                Tag(0x0029, 0x1108),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_a_policy_every_private_element_goes() {
+        let mut obj = header();
+        let tally = Policy::default().apply(&mut obj);
+        assert_eq!(tally.kept, 0);
+        assert_eq!(tally.dropped, 5);
+        assert_eq!(obj.tags().collect::<Vec<_>>(), [tags::PATIENT_NAME]);
+    }
+
+    #[test]
+    fn blocks_are_found_by_creator_wherever_they_are_reserved() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let mut obj = InMemDicomObject::new_empty();
+        put(&mut obj, Tag(0x0019, 0x0042), VR::LO, "GEMS_ACQU_01");
+        put(&mut obj, Tag(0x0019, 0x4202), VR::DS, "1.5");
+        // Same creator in a group the rule does not allow
+        put(&mut obj, Tag(0x0031, 0x0010), VR::LO, "SIEMENS CSA HEADER");
+        put(&mut obj, Tag(0x0031, 0x1008), VR::CS, "IMAGE NUM 4");
+        // An element without a creator
+        put(&mut obj, Tag(0x0019, 0x5001), VR::LO, "orphan");
+        policy.apply(&mut obj);
+
+        assert_eq!(
+            obj.tags().collect::<Vec<_>>(),
+            [Tag(0x0019, 0x0042), Tag(0x0019, 0x4202)]
+        );
+    }
+
+    #[test]
+    fn unreadable_creators_are_dropped() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let mut obj = header();
+        obj.put(DataElement::new(
+            Tag(0x0011, 0x0010),
+            VR::SQ,
+            DataSetSequence::from(vec![InMemDicomObject::new_empty()]),
+        ));
+        put(&mut obj, Tag(0x0011, 0x1001), VR::LO, "Jane Doe");
+        let tally = policy.apply(&mut obj);
+
+        assert_eq!(
+            tally,
+            Tally {
+                kept: 3,
+                dropped: 4
+            }
+        );
+        assert!(obj.tags().all(|tag| tag.group() != 0x0011));
+    }
+
+    #[test]
+    fn sequence_items_are_filtered_too() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let mut obj = InMemDicomObject::new_empty();
+        obj.put(DataElement::new(
+            tags::REFERENCED_IMAGE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![header()]),
+        ));
+        let tally = policy.apply(&mut obj);
+
+        assert_eq!(tally.dropped, 2);
+        let item = &obj
+            .element(tags::REFERENCED_IMAGE_SEQUENCE)
+            .unwrap()
+            .items()
+            .unwrap()[0];
+        assert!(item.element_opt(Tag(0x0009, 0x1001)).unwrap().is_none());
+        assert!(item.element_opt(Tag(0x0019, 0x1002)).unwrap().is_some());
+    }
+
+    #[test]
+    fn policies_are_checked() {
+        assert!(Policy::parse("").unwrap().keep.is_empty());
+        assert!(Policy::parse("[[keep]]\ncreator = \" \"").is_err());
+        assert!(Policy::parse("[[keep]]\ncreator = \"X\"\ngroup = 0x0010").is_err());
+        assert!(Policy::parse("[[keep]]\ncreator = \"X\"\nblock = 1").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Quick convert: `dcm-toolbox <path>` with nothing else.
+//!
+//! File managers open a file or folder "with" a program by passing its path
+//! alone, so that is all an association with dcm-toolbox hands over. A lone
+//! path that exists and is not a command name stands for a `convert jpeg`
+//! with defaults:
+//!
+//! - a file becomes one JPEG beside it (`scan.dcm` → `scan.jpg`, first frame)
+//! - a folder becomes a series folder each in `<folder>_export` beside it
+//!
+//! Any other command line is parsed as usual.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::CommandFactory;
+
+use crate::CliArgs;
+
+/// Suffix of the folder a quick-converted folder is written to.
+const EXPORT_SUFFIX: &str = "_export";
+
+/// `args` (program name first) as the `convert` command a lone path stands
+/// for, or unchanged.
+pub fn expand(args: Vec<OsString>) -> Vec<OsString> {
+    let [program, path] = args.as_slice() else {
+        return args;
+    };
+    let name = path.to_string_lossy();
+    let path = Path::new(path);
+    if name.starts_with('-') || is_command(&name) || !path.exists() {
+        return args;
+    }
+    let output = if path.is_dir() {
+        beside(path, EXPORT_SUFFIX)
+    } else {
+        path.with_extension("jpg")
+    };
+    vec![
+        program.clone(),
+        "convert".into(),
+        "--in".into(),
+        path.into(),
+        "--out".into(),
+        output.into(),
+        "jpeg".into(),
+    ]
+}
+
+/// Whether `name` is a subcommand (or `help`), which wins over a file of
+/// the same name.
+fn is_command(name: &str) -> bool {
+    let mut command = CliArgs::command();
+    command.build();
+    command
+        .get_subcommands()
+        .any(|sub| sub.get_name() == name || sub.get_all_aliases().any(|alias| alias == name))
+}
+
+/// The sibling of `dir` named after it with `suffix`; `.` and `..` are
+/// resolved first so they get a name.
+fn beside(dir: &Path, suffix: &str) -> PathBuf {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut name = dir.file_name().unwrap_or(dir.as_os_str()).to_os_string();
+    name.push(suffix);
+    dir.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn args(list: &[&OsString]) -> Vec<OsString> {
+        list.iter().map(|&arg| arg.clone()).collect()
+    }
+
+    #[test]
+    fn files_become_a_jpeg_beside_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("scan.dcm");
+        fs::write(&file, b"").unwrap();
+        let expanded = expand(args(&[&"dcm-toolbox".into(), &file.clone().into()]));
+        assert_eq!(
+            expanded,
+            [
+                OsString::from("dcm-toolbox"),
+                "convert".into(),
+                "--in".into(),
+                file.into(),
+                "--out".into(),
+                dir.path().join("scan.jpg").into(),
+                "jpeg".into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn folders_export_beside_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let scans = dir.path().join("scans");
+        fs::create_dir(&scans).unwrap();
+        let expanded = expand(args(&[&"dcm-toolbox".into(), &scans.into()]));
+        let output = dir.path().canonicalize().unwrap().join("scans_export");
+        assert_eq!(expanded[5], output);
+    }
+
+    #[test]
+    fn other_command_lines_are_left_alone() {
+        for line in [
+            vec!["dcm-toolbox"],
+            vec!["dcm-toolbox", "list"],
+            vec!["dcm-toolbox", "help"],
+            vec!["dcm-toolbox", "--help"],
+            vec!["dcm-toolbox", "/nonexistent/scan.dcm"],
+            vec!["dcm-toolbox", "analyze", "--in", "."],
+        ] {
+            let line: Vec<OsString> = line.into_iter().map(OsString::from).collect();
+            assert_eq!(expand(line.clone()), line);
+        }
+    }
+}
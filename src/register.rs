@@ -0,0 +1,308 @@
+//! Rigid registration between two series volumes.
+//!
+//! Aligns a moving series (e.g. PET) to a fixed series (e.g. CT) with a
+//! translation + rotation transform that maximizes normalized mutual
+//! information, writes the transform as JSON, and optionally writes the
+//! moving volume resampled onto the fixed grid as NRRD.
+//!
+//! The transform maps fixed patient coordinates (mm) to moving patient
+//! coordinates, so resampling reads `moving(T(p))` for each fixed voxel `p`.
+//! Series in different frames of reference start from aligned volume
+//! centers, since their patient coordinates are unrelated.
+
+mod optimize;
+mod rigid;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::align::{self, Alignment, Interpolation};
+use crate::i18n::t;
+use crate::volume;
+
+use optimize::{Outcome, Settings};
+pub use rigid::Rigid;
+
+/// Minimum number of slices for a usable volume.
+const MIN_SLICES: usize = 3;
+
+/// Resolution levels used by the optimizer (coarse to fine).
+const LEVELS: usize = 3;
+
+/// Maximum pattern-search sweeps per level.
+const MAX_ITERATIONS: usize = 200;
+
+/// CLI arguments for the `register` subcommand.
+#[derive(Args, Debug)]
+pub struct RegisterArgs {
+    /// Folder with the reference (fixed) series
+    #[arg(long)]
+    pub fixed: PathBuf,
+
+    /// Folder with the series to align (moving)
+    #[arg(long)]
+    pub moving: PathBuf,
+
+    /// Output JSON file for the transform
+    #[arg(long = "out", short = 'o')]
+    pub output: PathBuf,
+
+    /// Also write the moving volume resampled onto the fixed grid (.nrrd)
+    #[arg(long)]
+    pub resampled: Option<PathBuf>,
+
+    /// How `--resampled` reads values between voxels
+    #[arg(long, value_enum, default_value_t = Interpolation::Linear)]
+    pub interpolation: Interpolation,
+
+    /// Histogram bins for the mutual information metric
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(4..=256))]
+    pub bins: u16,
+
+    /// Maximum sampled voxels per resolution level
+    #[arg(long, default_value_t = 50_000)]
+    pub samples: usize,
+
+    /// Start from aligned volume centers instead of shared patient coordinates
+    /// (the default when the series are in different frames of reference)
+    #[arg(long)]
+    pub align_centers: bool,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Transform report written as JSON.
+#[derive(Debug, Serialize)]
+struct TransformReport {
+    transform: &'static str,
+    metric: &'static str,
+    /// Direction of `matrix`: fixed patient coordinates to moving ones
+    direction: &'static str,
+    fixed: String,
+    moving: String,
+    center_mm: [f64; 3],
+    translation_mm: [f64; 3],
+    rotation_deg: [f64; 3],
+    matrix: [[f64; 4]; 4],
+    initial_metric: f64,
+    final_metric: f64,
+}
+
+impl TransformReport {
+    fn new(args: &RegisterArgs, outcome: &Outcome) -> Self {
+        let transform = &outcome.transform;
+        Self {
+            transform: "rigid",
+            metric: "normalized_mutual_information",
+            direction: "fixed_to_moving",
+            fixed: args.fixed.display().to_string(),
+            moving: args.moving.display().to_string(),
+            center_mm: transform.center,
+            translation_mm: transform.translation(),
+            rotation_deg: transform.rotation_degrees(),
+            matrix: transform.matrix(),
+            initial_metric: outcome.initial_metric,
+            final_metric: outcome.final_metric,
+        }
+    }
+}
+
+/// Fields of a transform report needed to rebuild the transform.
+#[derive(Debug, Deserialize)]
+struct TransformFile {
+    center_mm: [f64; 3],
+    translation_mm: [f64; 3],
+    rotation_deg: [f64; 3],
+}
+
+/// Read a transform written by `register`.
+pub fn read_transform(path: &Path) -> Result<Rigid> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read transform: {}", path.display()))?;
+    let file: TransformFile = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid transform file: {}", path.display()))?;
+    let [tx, ty, tz] = file.translation_mm;
+    let [rx, ry, rz] = file.rotation_deg.map(f64::to_radians);
+    Ok(Rigid {
+        center: file.center_mm,
+        params: [tx, ty, tz, rx, ry, rz],
+    })
+}
+
+/// Register the moving series onto the fixed series.
+pub fn run(args: &RegisterArgs) -> Result<()> {
+    println!(
+        "{}",
+        t!(
+            "register-loading-fixed",
+            path = args.fixed.display().to_string()
+        )
+    );
+    let (fixed_files, fixed) = volume::load_series(&args.fixed, MIN_SLICES, args.follow_symlinks)?;
+    println!(
+        "{}",
+        t!(
+            "register-loading-moving",
+            path = args.moving.display().to_string()
+        )
+    );
+    let (moving_files, moving) =
+        volume::load_series(&args.moving, MIN_SLICES, args.follow_symlinks)?;
+
+    let mut start = Rigid::identity(fixed.center());
+    let other_frame = !align::same_frame(&fixed_files, &moving_files);
+    if other_frame && !args.align_centers {
+        println!("  {}", t!("register-other-frame"));
+    }
+    if args.align_centers || other_frame {
+        let (f, m) = (fixed.center(), moving.center());
+        start.params[..3].copy_from_slice(&[m[0] - f[0], m[1] - f[1], m[2] - f[2]]);
+    }
+
+    let settings = Settings {
+        bins: usize::from(args.bins),
+        max_samples: args.samples,
+        levels: LEVELS,
+        max_iterations: MAX_ITERATIONS,
+    };
+    let outcome = optimize::register(
+        &fixed,
+        &moving,
+        start,
+        &settings,
+        &mut |level, samples, metric| {
+            println!(
+                "  {}",
+                t!(
+                    "register-level",
+                    level = level,
+                    levels = LEVELS,
+                    samples = samples,
+                    metric = format!("{metric:.4}")
+                )
+            );
+        },
+    );
+
+    let report = TransformReport::new(args, &outcome);
+    println!(
+        "  {}",
+        t!(
+            "register-result",
+            translation = format_triplet(report.translation_mm),
+            rotation = format_triplet(report.rotation_deg)
+        )
+    );
+
+    let json = serde_json::to_string_pretty(&report).context("Failed to serialize transform")?;
+    fs::write(&args.output, json + "\n")
+        .with_context(|| format!("Failed to write transform: {}", args.output.display()))?;
+    println!(
+        "{}",
+        t!(
+            "register-saved-transform",
+            path = args.output.display().to_string()
+        )
+    );
+
+    if let Some(path) = &args.resampled {
+        println!("  {}", t!("register-resampling"));
+        let alignment = Alignment {
+            transform: outcome.transform,
+            interpolation: args.interpolation,
+        };
+        volume::write_nrrd(&alignment.resample(&fixed, &moving), path)?;
+        println!(
+            "{}",
+            t!("register-saved-volume", path = path.display().to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Format three numbers as `x, y, z` with two decimals.
+fn format_triplet(values: [f64; 3]) -> String {
+    format!("{:.2}, {:.2}, {:.2}", values[0], values[1], values[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_serializes_expected_fields() {
+        let args = RegisterArgs {
+            fixed: PathBuf::from("ct"),
+            moving: PathBuf::from("pet"),
+            output: PathBuf::from("t.json"),
+            resampled: None,
+            interpolation: Interpolation::Linear,
+            bins: 32,
+            samples: 1000,
+            align_centers: false,
+            follow_symlinks: false,
+        };
+        let outcome = Outcome {
+            transform: Rigid {
+                center: [0.0; 3],
+                params: [1.0, 2.0, 3.0, 0.0, 0.0, 0.0],
+            },
+            initial_metric: 1.1,
+            final_metric: 1.3,
+        };
+        let json = serde_json::to_value(TransformReport::new(&args, &outcome)).unwrap();
+        assert_eq!(json["transform"], "rigid");
+        assert_eq!(json["direction"], "fixed_to_moving");
+        assert_eq!(json["translation_mm"][1], 2.0);
+        assert_eq!(json["matrix"][2][3], 3.0);
+        assert_eq!(json["matrix"][3][3], 1.0);
+    }
+
+    #[test]
+    fn transform_round_trips_through_json() {
+        let args = RegisterArgs {
+            fixed: PathBuf::from("ct"),
+            moving: PathBuf::from("pet"),
+            output: PathBuf::from("t.json"),
+            resampled: None,
+            interpolation: Interpolation::Linear,
+            bins: 32,
+            samples: 1000,
+            align_centers: false,
+            follow_symlinks: false,
+        };
+        let transform = Rigid {
+            center: [1.0, 2.0, 3.0],
+            params: [4.0, -5.0, 6.0, 0.1, -0.05, 0.2],
+        };
+        let outcome = Outcome {
+            transform,
+            initial_metric: 1.0,
+            final_metric: 1.2,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.json");
+        let json = serde_json::to_string(&TransformReport::new(&args, &outcome)).unwrap();
+        fs::write(&path, json).unwrap();
+
+        let read = read_transform(&path).unwrap();
+        for i in 0..6 {
+            assert!((read.params[i] - transform.params[i]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn invalid_transform_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.json");
+        fs::write(&path, "{}").unwrap();
+        assert!(read_transform(&path).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Rigid (rotation + translation) transform about a fixed center.
+
+/// Rigid transform mapping fixed-space points (mm) into moving space.
+///
+/// `q = R (p - center) + center + t`, with `R = Rz * Ry * Rx`. Parameters are
+/// `[tx, ty, tz, rx, ry, rz]` in mm and radians.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rigid {
+    /// Center of rotation (mm), usually the fixed volume center.
+    pub center: [f64; 3],
+    /// Translation (mm) followed by rotation angles (radians).
+    pub params: [f64; 6],
+}
+
+impl Rigid {
+    /// Identity transform rotating about `center`.
+    pub const fn identity(center: [f64; 3]) -> Self {
+        Self {
+            center,
+            params: [0.0; 6],
+        }
+    }
+
+    /// Translation in mm.
+    pub const fn translation(&self) -> [f64; 3] {
+        [self.params[0], self.params[1], self.params[2]]
+    }
+
+    /// Rotation angles about X, Y, Z in degrees.
+    pub const fn rotation_degrees(&self) -> [f64; 3] {
+        [
+            self.params[3].to_degrees(),
+            self.params[4].to_degrees(),
+            self.params[5].to_degrees(),
+        ]
+    }
+
+    /// 3x3 rotation matrix `Rz * Ry * Rx`.
+    pub fn rotation(&self) -> [[f64; 3]; 3] {
+        let (sx, cx) = self.params[3].sin_cos();
+        let (sy, cy) = self.params[4].sin_cos();
+        let (sz, cz) = self.params[5].sin_cos();
+        [
+            [
+                cz * cy,
+                (cz * sy).mul_add(sx, -sz * cx),
+                (cz * sy).mul_add(cx, sz * sx),
+            ],
+            [
+                sz * cy,
+                (sz * sy).mul_add(sx, cz * cx),
+                (sz * sy).mul_add(cx, -cz * sx),
+            ],
+            [-sy, cy * sx, cy * cx],
+        ]
+    }
+
+    /// Map a fixed-space point into moving space.
+    pub fn apply(&self, point: [f64; 3]) -> [f64; 3] {
+        let r = self.rotation();
+        let d = [
+            point[0] - self.center[0],
+            point[1] - self.center[1],
+            point[2] - self.center[2],
+        ];
+        std::array::from_fn(|i| {
+            r[i][0].mul_add(d[0], r[i][1].mul_add(d[1], r[i][2] * d[2]))
+                + self.center[i]
+                + self.params[i]
+        })
+    }
+
+    /// Homogeneous 4x4 matrix equivalent to [`Rigid::apply`].
+    pub fn matrix(&self) -> [[f64; 4]; 4] {
+        let r = self.rotation();
+        let origin = self.apply([0.0; 3]);
+        [
+            [r[0][0], r[0][1], r[0][2], origin[0]],
+            [r[1][0], r[1][1], r[1][2], origin[1]],
+            [r[2][0], r[2][1], r[2][2], origin[2]],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f64; 3], b: [f64; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-9, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn identity_keeps_points() {
+        let t = Rigid::identity([5.0, 5.0, 5.0]);
+        assert_close(t.apply([1.0, -2.0, 3.0]), [1.0, -2.0, 3.0]);
+    }
+
+    #[test]
+    fn translation_is_added() {
+        let t = Rigid {
+            center: [0.0; 3],
+            params: [1.0, 2.0, -3.0, 0.0, 0.0, 0.0],
+        };
+        assert_close(t.apply([1.0, 1.0, 1.0]), [2.0, 3.0, -2.0]);
+    }
+
+    #[test]
+    fn rotation_is_about_the_center() {
+        let t = Rigid {
+            center: [10.0, 0.0, 0.0],
+            params: [0.0, 0.0, 0.0, 0.0, 0.0, 90_f64.to_radians()],
+        };
+        assert_close(t.apply([10.0, 0.0, 0.0]), [10.0, 0.0, 0.0]);
+        assert_close(t.apply([11.0, 0.0, 0.0]), [10.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn matrix_matches_apply() {
+        let t = Rigid {
+            center: [3.0, -1.0, 7.0],
+            params: [1.5, -2.0, 0.5, 0.1, -0.2, 0.3],
+        };
+        let m = t.matrix();
+        let p = [4.0, 5.0, -6.0];
+        let via_matrix: [f64; 3] = std::array::from_fn(|i| {
+            m[i][0].mul_add(p[0], m[i][1].mul_add(p[1], m[i][2].mul_add(p[2], m[i][3])))
+        });
+        assert_close(via_matrix, t.apply(p));
+    }
+}
@@ -0,0 +1,206 @@
+//! Cohort selection by ID list (`--patients`, `--studies`).
+//!
+//! A cohort is usually a spreadsheet column of patient IDs or study UIDs.
+//! Saved as text or CSV, its first column restricts a run over a big
+//! archive to the listed patients or studies: each file's header is read
+//! once and the file is dropped unless its `PatientID` (and
+//! `StudyInstanceUID`) is listed. Listed IDs without any file are reported,
+//! so a cohort that did not fully match is noticed.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Args;
+use dicom::core::Tag;
+use dicom::dictionary_std::tags;
+use dicom::object::OpenFileOptions;
+
+use crate::i18n::t;
+use crate::outcome::BadInput;
+
+/// Most missing IDs listed by name.
+const MAX_LISTED_MISSING: usize = 10;
+
+/// Options restricting a run to listed patients or studies.
+#[derive(Args, Debug, Default)]
+pub struct SelectionArgs {
+    /// Only process files of the patients in this file: one `PatientID` per
+    /// line (or in the first column of a CSV)
+    #[arg(long, value_name = "FILE")]
+    pub patients: Option<PathBuf>,
+
+    /// Only process files of the studies in this file: one
+    /// `StudyInstanceUID` per line (or in the first column of a CSV)
+    #[arg(long, value_name = "FILE")]
+    pub studies: Option<PathBuf>,
+}
+
+impl SelectionArgs {
+    /// Whether any list was given.
+    pub const fn is_set(&self) -> bool {
+        self.patients.is_some() || self.studies.is_some()
+    }
+
+    /// Read the lists; `None` if none was given.
+    pub fn load(&self) -> Result<Option<Selection>> {
+        if !self.is_set() {
+            return Ok(None);
+        }
+        let list = |path: Option<&Path>, tag| path.map(|path| IdList::load(path, tag)).transpose();
+        Ok(Some(Selection {
+            patients: list(self.patients.as_deref(), tags::PATIENT_ID)?,
+            studies: list(self.studies.as_deref(), tags::STUDY_INSTANCE_UID)?,
+        }))
+    }
+}
+
+/// Loaded ID lists; a file is kept when every list holds its ID.
+#[derive(Debug)]
+pub struct Selection {
+    patients: Option<IdList>,
+    studies: Option<IdList>,
+}
+
+/// The IDs of one list file, and the attribute they are matched against.
+#[derive(Debug)]
+struct IdList {
+    ids: HashSet<String>,
+    tag: Tag,
+    path: PathBuf,
+}
+
+impl Selection {
+    /// The listed files among `files`, reporting how many were kept and
+    /// which listed IDs matched none.
+    pub fn filter(&self, files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let total = files.len();
+        let mut found: [HashSet<String>; 2] = Default::default();
+        let kept: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|path| self.keeps(path, &mut found))
+            .collect();
+        println!("{}", t!("select-kept", count = kept.len(), total = total));
+        for (list, found) in [&self.patients, &self.studies].into_iter().zip(&found) {
+            if let Some(list) = list {
+                list.report_missing(found);
+            }
+        }
+        kept
+    }
+
+    /// Whether `path` belongs to the selection, noting the IDs it has.
+    fn keeps(&self, path: &Path, found: &mut [HashSet<String>; 2]) -> bool {
+        let Ok(obj) = OpenFileOptions::new()
+            .read_until(tags::PIXEL_DATA)
+            .open_file(path)
+        else {
+            return false;
+        };
+        let mut keep = true;
+        for (list, found) in [&self.patients, &self.studies].into_iter().zip(found) {
+            let Some(list) = list else {
+                continue;
+            };
+            let id = obj
+                .element(list.tag)
+                .ok()
+                .and_then(|elem| elem.to_str().ok())
+                .map(|value| value.trim_end_matches(['\0', ' ']).trim().to_string());
+            match id {
+                Some(id) if list.ids.contains(&id) => {
+                    found.insert(id);
+                }
+                _ => keep = false,
+            }
+        }
+        keep
+    }
+}
+
+impl IdList {
+    fn load(path: &Path, tag: Tag) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| BadInput(format!("Failed to read ID list: {}: {e}", path.display())))?;
+        let ids = parse_ids(&text);
+        if ids.is_empty() {
+            anyhow::bail!(BadInput(format!("No IDs found in {}", path.display())));
+        }
+        Ok(Self {
+            ids,
+            tag,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Warn about listed IDs that no file had.
+    fn report_missing(&self, found: &HashSet<String>) {
+        let missing: BTreeSet<&String> = self.ids.difference(found).collect();
+        if missing.is_empty() {
+            return;
+        }
+        let mut names: Vec<&str> = missing
+            .iter()
+            .take(MAX_LISTED_MISSING)
+            .map(|id| id.as_str())
+            .collect();
+        if missing.len() > MAX_LISTED_MISSING {
+            names.push("...");
+        }
+        eprintln!(
+            "{}",
+            t!(
+                "select-missing",
+                count = missing.len(),
+                path = self.path.display().to_string(),
+                ids = names.join(", ")
+            )
+        );
+    }
+}
+
+/// IDs from the first column of each line. Blank lines and `#` comments are
+/// skipped, and surrounding quotes and spaces removed.
+fn parse_ids(text: &str) -> HashSet<String> {
+    text.lines()
+        .map(|line| line.split([',', ';', '\t']).next().unwrap_or_default())
+        .map(|field| field.trim().trim_matches('"').trim())
+        .filter(|id| !id.is_empty() && !id.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_come_from_the_first_column() {
+        let text = "# cohort A\nP001\n\n  P002  \n\"P003\",Doe^Jane,1970\nP004;x\nP005\tx\r\n";
+        let ids = parse_ids(text);
+        let mut ids: Vec<_> = ids.into_iter().collect();
+        ids.sort();
+        assert_eq!(ids, ["P001", "P002", "P003", "P004", "P005"]);
+    }
+
+    #[test]
+    fn empty_list_is_bad_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ids.txt");
+        fs::write(&path, "# nobody yet\n\n").unwrap();
+        let err = IdList::load(&path, tags::PATIENT_ID).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some(), "{err:#}");
+    }
+
+    #[test]
+    fn missing_list_file_fails() {
+        let args = SelectionArgs {
+            patients: Some(PathBuf::from("/nonexistent/ids.txt")),
+            studies: None,
+        };
+        let err = args.load().unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some(), "{err:#}");
+        assert!(SelectionArgs::default().load().unwrap().is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! Standalone STL export (`stl`).
+//!
+//! Builds one model from a single series folder, without the grouping and
+//! per-series output folders of `convert … stl`, and adds the mesh-only
+//! options: cropping to a voxel box, decimation, hollowing, multi-part
+//! output with one named, colored object per `--part` value band or per
+//! label of a `--labels` segmentation, and turntable videos.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::convert::{
+    Crop, MIN_SLICES_FOR_3D, MeshFormat, MeshOptions, MeshOutput, Part, Presets, Target,
+    VideoCodec, check_ffmpeg, parse_positive, write_model, write_parts,
+};
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::utils::extended_length_path;
+use crate::volume::{self, LabelMap, labels_present};
+
+/// CLI arguments for the `stl` subcommand.
+#[derive(Args, Debug)]
+pub struct StlArgs {
+    /// Series folder (all .dcm files form one volume)
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Output model file; the extension picks the format (.stl, .ply, .obj, .glb, .3mf)
+    #[arg(long = "out")]
+    pub output: PathBuf,
+
+    /// Isosurface threshold level (auto-detected via Otsu if omitted)
+    #[arg(long, allow_negative_numbers = true)]
+    pub iso_level: Option<f32>,
+
+    /// Mesh at a preset level for this target (CT only; picked from the
+    /// body part and the series' histogram)
+    #[arg(long, value_enum, conflicts_with_all = ["iso_level", "parts"])]
+    pub target: Option<Target>,
+
+    /// Gaussian smoothing sigma (0 disables smoothing)
+    #[arg(long, default_value_t = 1.0)]
+    pub smooth: f32,
+
+    /// Remove air, table, and noise around the patient before meshing
+    #[arg(long)]
+    pub strip_background: bool,
+
+    /// Only mesh voxels X0:X1,Y0:Y1,Z0:Z1 (0-based, end exclusive, empty bound = open)
+    #[arg(long, value_name = "RANGES")]
+    pub crop: Option<Crop>,
+
+    /// Merge vertices on a grid of this size in mm to shrink the mesh
+    #[arg(long, value_name = "MM", value_parser = parse_positive)]
+    pub decimate: Option<f32>,
+
+    /// Hollow the model to walls this thick, to save print material
+    #[arg(long, value_name = "MM", value_parser = parse_positive, conflicts_with = "parts")]
+    pub shell_thickness: Option<f32>,
+
+    /// Mesh voxels in LOW..HIGH as a named part of the model (repeatable;
+    /// empty bound = open, color defaults to a palette; not for .stl)
+    #[arg(long = "part", value_name = "NAME:LOW:HIGH[:#RRGGBB]")]
+    pub parts: Vec<Part>,
+
+    /// Segmentation aligned to the series (.nii, .nii.gz, .nrrd); each
+    /// label becomes a named part of the model (not for .stl)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["parts", "iso_level", "target", "shell_thickness", "strip_background"]
+    )]
+    pub labels: Option<PathBuf>,
+
+    /// Also save an MP4 of the model turning once in this many seconds
+    /// (requires ffmpeg)
+    #[arg(long, value_name = "SECONDS", value_parser = parse_positive)]
+    pub turntable: Option<f32>,
+
+    #[command(flatten)]
+    pub output_options: MeshOutput,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// Mesh a series folder into one model file.
+pub fn run(args: &StlArgs) -> Result<()> {
+    let flag = if args.labels.is_some() {
+        Some("--labels")
+    } else {
+        (!args.parts.is_empty()).then_some("--part")
+    };
+    if let Some(flag) = flag
+        && MeshFormat::from_path(&args.output) == MeshFormat::Stl
+    {
+        anyhow::bail!(BadInput(format!(
+            "{flag} needs a .3mf, .glb, .obj, or .ply output to keep parts apart, got {}",
+            args.output.display()
+        )));
+    }
+
+    if args.turntable.is_some() {
+        check_ffmpeg(VideoCodec::H264, None)?;
+    }
+
+    let (files, volume) =
+        volume::load_series(&args.input, MIN_SLICES_FOR_3D, args.follow_symlinks)?;
+    println!("  {}", t!("stl-building-volume", count = files.len()));
+
+    let output = extended_length_path(&args.output);
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output folder: {}", parent.display()))?;
+    }
+
+    let options = MeshOptions {
+        iso_level: args.iso_level,
+        smooth_sigma: args.smooth,
+        strip_background: args.strip_background,
+        crop: args.crop,
+        decimate: args.decimate,
+        shell_thickness: args.shell_thickness,
+        output: args.output_options,
+        target: args.target,
+        presets: Presets::detect(&files[0], &volume.values),
+        turntable: args.turntable,
+    };
+    if let Some(path) = &args.labels {
+        let map = LabelMap::load(path)?;
+        let volume = map.resample(&volume)?;
+        let parts = label_parts(&map, &volume.values)?;
+        println!(
+            "  {}",
+            t!(
+                "stl-labels",
+                count = parts.len(),
+                path = path.display().to_string()
+            )
+        );
+        write_parts(volume, options, &parts, &output)?;
+    } else if args.parts.is_empty() {
+        write_model(volume, options, &output)?;
+    } else {
+        write_parts(volume, options, &args.parts, &output)?;
+    }
+    Ok(())
+}
+
+/// One part per label found inside the series, named and colored from the
+/// file's segment metadata when it has any.
+#[allow(clippy::cast_precision_loss)]
+fn label_parts(map: &LabelMap, values: &[f32]) -> Result<Vec<Part>> {
+    let labels = labels_present(values);
+    if labels.is_empty() {
+        anyhow::bail!(BadInput(
+            "The label map has no labels inside the series; check that it belongs to it"
+                .to_string()
+        ));
+    }
+    Ok(labels
+        .into_iter()
+        .map(|label| {
+            let segment = map.segment(label);
+            let value = label as f32;
+            Part {
+                name: segment.map_or_else(|| format!("label_{label}"), |s| s.name.clone()),
+                low: Some(value - 0.5),
+                high: Some(value + 0.5),
+                color: segment.and_then(|s| s.color),
+            }
+        })
+        .collect())
+}
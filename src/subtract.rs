@@ -0,0 +1,393 @@
+//! Subtraction imaging between two series (e.g. post minus pre contrast).
+//!
+//! The pre series is resampled onto the post grid — through a `register`
+//! transform when one is given, otherwise through shared patient coordinates,
+//! which needs both series in one frame of reference —
+//! and subtracted voxel-wise. The difference is exported as an image stack,
+//! a maximum intensity projection (MIP), or an MP4 video.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use image::DynamicImage;
+
+use crate::align::{self, Alignment, Interpolation};
+use crate::convert::{
+    ImageFormat, JpegSink, RawStagingSink, VideoCodec, check_ffmpeg, encode_mp4, staging_estimate,
+};
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, FrameSink};
+use crate::pixel::{Frame, Window};
+use crate::register::{self, Rigid};
+use crate::utils::{create_temp_dir, ensure_free_space, extended_length_path, validate_temp_dir};
+use crate::volume::{self, Volume};
+
+/// Minimum number of slices per series.
+const MIN_SLICES: usize = 1;
+
+/// What to export from the difference volume.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SubtractOutput {
+    /// One image per slice
+    #[default]
+    Stack,
+    /// Single maximum intensity projection along the slice axis
+    Mip,
+    /// MP4 video through the slices (requires ffmpeg)
+    Video,
+}
+
+/// How difference values map to gray levels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Normalize {
+    /// Stretch the range of the whole volume (consistent brightness across slices)
+    #[default]
+    Volume,
+    /// Stretch each slice on its own
+    Slice,
+    /// Zero difference is mid-gray; gains are brighter, losses darker
+    Symmetric,
+}
+
+/// CLI arguments for the `subtract` subcommand.
+#[derive(Args, Debug)]
+pub struct SubtractArgs {
+    /// Folder with the post-contrast series (the result grid)
+    #[arg(long)]
+    pub post: PathBuf,
+
+    /// Folder with the pre-contrast series subtracted from `--post`
+    #[arg(long)]
+    pub pre: PathBuf,
+
+    /// Output folder
+    #[arg(long = "out")]
+    pub output: PathBuf,
+
+    /// Transform JSON from `register` (run with `--fixed <post> --moving <pre>`)
+    #[arg(long)]
+    pub transform: Option<PathBuf>,
+
+    /// How the pre series is read between its voxels
+    #[arg(long, value_enum, default_value_t = Interpolation::Linear)]
+    pub interpolation: Interpolation,
+
+    /// What to export
+    #[arg(long, value_enum, default_value_t = SubtractOutput::Stack)]
+    pub mode: SubtractOutput,
+
+    /// Image encoding for stack and MIP output
+    #[arg(long, value_enum, default_value_t = ImageFormat::Jpeg)]
+    pub image_format: ImageFormat,
+
+    /// Frames per second for video output
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+
+    /// Clamp differences below this value (modality units)
+    #[arg(long, allow_negative_numbers = true)]
+    pub clip_min: Option<f32>,
+
+    /// Clamp differences above this value (modality units)
+    #[arg(long, allow_negative_numbers = true)]
+    pub clip_max: Option<f32>,
+
+    /// How differences map to gray levels
+    #[arg(long, value_enum, default_value_t = Normalize::Volume)]
+    pub normalize: Normalize,
+
+    /// Include symlinked .dcm files (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Folder for intermediate video frames [default: system temp folder]
+    #[arg(long, value_name = "DIR")]
+    pub temp_dir: Option<PathBuf>,
+}
+
+/// Subtract the pre series from the post series and export the difference.
+pub fn run(args: &SubtractArgs) -> Result<()> {
+    if let (Some(lo), Some(hi)) = (args.clip_min, args.clip_max)
+        && lo >= hi
+    {
+        anyhow::bail!(BadInput(format!(
+            "--clip-min ({lo}) must be below --clip-max ({hi})"
+        )));
+    }
+    validate_temp_dir(args.temp_dir.as_deref())?;
+    if args.mode == SubtractOutput::Video {
+        check_ffmpeg(VideoCodec::H264, None)?;
+    }
+
+    println!(
+        "{}",
+        t!(
+            "subtract-loading-post",
+            path = args.post.display().to_string()
+        )
+    );
+    let (post_files, post) = volume::load_series(&args.post, MIN_SLICES, args.follow_symlinks)?;
+    println!(
+        "{}",
+        t!(
+            "subtract-loading-pre",
+            path = args.pre.display().to_string()
+        )
+    );
+    let (pre_files, pre) = volume::load_series(&args.pre, MIN_SLICES, args.follow_symlinks)?;
+
+    let transform = match &args.transform {
+        Some(path) => register::read_transform(path)?,
+        None => {
+            align::check_same_frame(&post_files, &pre_files)?;
+            Rigid::identity(post.center())
+        }
+    };
+    println!("  {}", t!("subtract-resampling"));
+    let alignment = Alignment {
+        transform,
+        interpolation: args.interpolation,
+    };
+    let pre_on_post = alignment.resample(&post, &pre);
+
+    let diff = subtract(&post, &pre_on_post, args.clip_min, args.clip_max);
+    let (lo, hi) = diff.value_range();
+    println!(
+        "  {}",
+        t!(
+            "subtract-range",
+            min = format!("{lo:.1}"),
+            max = format!("{hi:.1}")
+        )
+    );
+
+    let output = extended_length_path(&args.output);
+    fs::create_dir_all(&output)
+        .with_context(|| format!("Failed to create output folder: {}", args.output.display()))?;
+    let window = window_for(args.normalize, lo, hi);
+
+    match args.mode {
+        SubtractOutput::Stack => {
+            let mut sink = JpegSink::new(&output, diff.slices, args.image_format);
+            write_slices(&diff, &post_files, window, &mut sink)?;
+        }
+        SubtractOutput::Mip => {
+            let mip = max_intensity_projection(&diff);
+            let path = output.join(format!("mip.{}", args.image_format.extension()));
+            render(mip, window)
+                .save_with_format(&path, args.image_format.encoding())
+                .with_context(|| format!("Failed to save image: {}", path.display()))?;
+            println!(
+                "{}",
+                t!("subtract-saved", path = path.display().to_string())
+            );
+        }
+        SubtractOutput::Video => {
+            let temp_dir = create_temp_dir(args.temp_dir.as_deref())?;
+            let frame_bytes = (diff.cols * diff.rows) as u64;
+            ensure_free_space(temp_dir.path(), staging_estimate(frame_bytes, diff.slices))?;
+            let mut sink = RawStagingSink::new(temp_dir.path(), diff.slices);
+            write_slices(&diff, &post_files, window, &mut sink)?;
+            let input = sink
+                .finish()?
+                .context("No frames were successfully processed for video creation")?;
+            let path = output.join("subtraction.mp4");
+            println!("\n{}", t!("video-encoding"));
+            encode_mp4(&input, args.fps, &path)?;
+            println!(
+                "{}",
+                t!("subtract-saved", path = path.display().to_string())
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Voxel-wise `post - pre`, clamped to the optional clip range.
+fn subtract(post: &Volume, pre: &Volume, clip_min: Option<f32>, clip_max: Option<f32>) -> Volume {
+    let values = post
+        .values
+        .iter()
+        .zip(&pre.values)
+        .map(|(&a, &b)| {
+            let d = a - b;
+            let d = clip_min.map_or(d, |lo| d.max(lo));
+            clip_max.map_or(d, |hi| d.min(hi))
+        })
+        .collect();
+    Volume {
+        values,
+        ..post.clone()
+    }
+}
+
+/// Display window for a normalization mode; `None` stretches each image.
+fn window_for(normalize: Normalize, lo: f32, hi: f32) -> Option<Window> {
+    match normalize {
+        Normalize::Volume => Some(Window::spanning(lo, hi)),
+        Normalize::Slice => None,
+        Normalize::Symmetric => {
+            let extent = lo.abs().max(hi.abs());
+            Some(Window::spanning(-extent, extent))
+        }
+    }
+}
+
+/// Render every slice of the difference volume into a sink.
+fn write_slices(
+    diff: &Volume,
+    sources: &[PathBuf],
+    window: Option<Window>,
+    sink: &mut dyn FrameSink,
+) -> Result<()> {
+    let slice_size = diff.cols * diff.rows;
+    // A multi-frame source holds one slice per frame
+    let sources = sources
+        .iter()
+        .flat_map(|path| std::iter::repeat_n(path, pipeline::frames_in(path)));
+    for (z, source) in sources.enumerate().take(diff.slices) {
+        let values = diff.values[z * slice_size..(z + 1) * slice_size].to_vec();
+        let frame = slice_frame(diff, values);
+        sink.write_frame(z, source, render(frame, window))?;
+    }
+    Ok(())
+}
+
+/// Maximum along the slice axis, as a single frame.
+fn max_intensity_projection(diff: &Volume) -> Frame {
+    let slice_size = diff.cols * diff.rows;
+    let mut values = vec![f32::MIN; slice_size];
+    for slice in diff.values.chunks_exact(slice_size) {
+        for (max, &v) in values.iter_mut().zip(slice) {
+            *max = max.max(v);
+        }
+    }
+    slice_frame(diff, values)
+}
+
+/// Wrap one slice worth of values as a monochrome frame.
+fn slice_frame(diff: &Volume, values: Vec<f32>) -> Frame {
+    Frame {
+        width: u32::try_from(diff.cols).unwrap_or(u32::MAX),
+        height: u32::try_from(diff.rows).unwrap_or(u32::MAX),
+        values,
+        window: None,
+        invert: false,
+    }
+}
+
+/// Render a frame with an explicit window (or its own range when `None`).
+fn render(frame: Frame, window: Option<Window>) -> DynamicImage {
+    DynamicImage::ImageLuma8(Frame { window, ..frame }.to_luma8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2x1x3 volume with the given values.
+    fn volume(values: &[f32]) -> Volume {
+        Volume {
+            values: values.to_vec(),
+            cols: 2,
+            rows: 1,
+            slices: 3,
+            spacing_x: 1.0,
+            spacing_y: 1.0,
+            spacing_z: 1.0,
+            origin: [0.0; 3],
+        }
+    }
+
+    // =========================================================================
+    // Difference Tests
+    // =========================================================================
+
+    mod difference {
+        use super::*;
+
+        #[test]
+        fn subtracts_voxel_wise() {
+            let post = volume(&[10.0, 20.0, 30.0, 40.0, 50.0, 60.0]);
+            let pre = volume(&[1.0, 2.0, 3.0, 4.0, 5.0, 70.0]);
+            let diff = subtract(&post, &pre, None, None);
+            assert_eq!(diff.values, vec![9.0, 18.0, 27.0, 36.0, 45.0, -10.0]);
+        }
+
+        #[test]
+        fn clips_both_ends() {
+            let post = volume(&[10.0, 20.0, 30.0, 40.0, 50.0, 60.0]);
+            let pre = volume(&[0.0; 6]);
+            let diff = subtract(&post, &pre, Some(15.0), Some(45.0));
+            assert_eq!(diff.values, vec![15.0, 20.0, 30.0, 40.0, 45.0, 45.0]);
+        }
+
+        #[test]
+        fn mip_takes_maximum_over_slices() {
+            let diff = volume(&[1.0, 9.0, 5.0, 2.0, 3.0, 4.0]);
+            assert_eq!(max_intensity_projection(&diff).values, vec![5.0, 9.0]);
+        }
+    }
+
+    // =========================================================================
+    // Normalization Tests
+    // =========================================================================
+
+    mod normalization {
+        use super::*;
+
+        #[test]
+        fn symmetric_puts_zero_at_mid_gray() {
+            let window = window_for(Normalize::Symmetric, -50.0, 200.0).unwrap();
+            let gray = window.apply(0.0);
+            assert!((126..=129).contains(&gray), "got {gray}");
+            assert_eq!(window.apply(-200.0), 0);
+            assert_eq!(window.apply(200.0), 255);
+        }
+
+        #[test]
+        fn volume_window_spans_the_range() {
+            let window = window_for(Normalize::Volume, -100.0, 100.0).unwrap();
+            assert_eq!(window.apply(-100.0), 0);
+            assert_eq!(window.apply(100.0), 255);
+        }
+
+        #[test]
+        fn slice_mode_has_no_shared_window() {
+            assert!(window_for(Normalize::Slice, 0.0, 1.0).is_none());
+        }
+    }
+
+    // =========================================================================
+    // Output Tests
+    // =========================================================================
+
+    mod output {
+        use super::*;
+
+        fn stack_png(dir: &std::path::Path, index: usize) -> PathBuf {
+            dir.join(format!("{:04}.png", index + 1))
+        }
+
+        #[test]
+        fn stack_writes_one_image_per_slice() {
+            let dir = tempfile::tempdir().unwrap();
+            let diff = volume(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+            let sources: Vec<PathBuf> = (0..3).map(|i| PathBuf::from(format!("{i}.dcm"))).collect();
+            let window = window_for(Normalize::Volume, 0.0, 5.0);
+            let mut sink = JpegSink::new(dir.path(), 3, ImageFormat::Png);
+
+            write_slices(&diff, &sources, window, &mut sink).unwrap();
+
+            for index in 0..3 {
+                assert!(stack_png(dir.path(), index).exists());
+            }
+            let last = image::open(stack_png(dir.path(), 2)).unwrap();
+            assert_eq!(last.to_luma8().get_pixel(1, 0).0[0], 255);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Sharing a workstation during long conversions (`--throttle-read`,
+//! `--nice`, `--ionice`).
+//!
+//! The read limit paces every DICOM file opened for its pixels: before a
+//! file is read, the run sleeps until its average rate, counting that file,
+//! is back under the limit. Header-only reads are too small to count. CPU
+//! and I/O priorities are lowered for the whole process at startup, and
+//! ffmpeg inherits them.
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+
+use crate::convert::parse_size;
+use crate::i18n::t;
+
+/// Global options for running in the background of a busy machine.
+#[derive(Args, Debug)]
+pub struct ThrottleArgs {
+    /// Read DICOM files at no more than this rate per second (e.g. 20MB)
+    #[arg(long, global = true, value_name = "RATE", value_parser = parse_size)]
+    pub throttle_read: Option<u64>,
+
+    /// Lower the CPU priority by this niceness, 1 (a little) to 19 (only
+    /// idle time); Unix only
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(i32).range(1..=19))]
+    pub nice: Option<i32>,
+
+    /// Lower the disk priority; Linux only
+    #[arg(long, global = true, value_enum, value_name = "CLASS")]
+    pub ionice: Option<IoPriority>,
+}
+
+/// Disk priority class (`--ionice`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum IoPriority {
+    /// Lowest best-effort level: other programs' reads go first
+    Low,
+    /// Only when no other program uses the disk
+    Idle,
+}
+
+/// Pace of reads under `--throttle-read`, once set.
+static PACE: OnceLock<Mutex<Pace>> = OnceLock::new();
+
+/// Apply `args` to this process. Priorities the system refuses only warn.
+pub fn apply(args: &ThrottleArgs) {
+    if let Some(rate) = args.throttle_read {
+        let _ = PACE.set(Mutex::new(Pace::new(rate, Instant::now())));
+    }
+    if let Some(nice) = args.nice
+        && let Err(e) = set_nice(nice)
+    {
+        eprintln!("{}", t!("throttle-nice-failed", error = e.to_string()));
+    }
+    if let Some(class) = args.ionice
+        && let Err(e) = set_io_priority(class)
+    {
+        eprintln!("{}", t!("throttle-ionice-failed", error = e.to_string()));
+    }
+}
+
+/// Wait, if reads are limited, until `path` can be read within the limit.
+pub fn before_read(path: &Path) {
+    let Some(pace) = PACE.get() else {
+        return;
+    };
+    let bytes = fs::metadata(path).map_or(0, |meta| meta.len());
+    let delay = pace
+        .lock()
+        .map_or(Duration::ZERO, |mut pace| pace.take(bytes, Instant::now()));
+    if !delay.is_zero() {
+        thread::sleep(delay);
+    }
+}
+
+/// Bytes read since a start time, against a rate.
+#[derive(Debug)]
+struct Pace {
+    /// Bytes per second.
+    rate: u64,
+    start: Instant,
+    bytes: u64,
+}
+
+impl Pace {
+    const fn new(rate: u64, start: Instant) -> Self {
+        Self {
+            rate,
+            start,
+            bytes: 0,
+        }
+    }
+
+    /// Count `bytes` more and return how long to wait at `now` so the
+    /// average stays at the rate.
+    fn take(&mut self, bytes: u64, now: Instant) -> Duration {
+        self.bytes = self.bytes.saturating_add(bytes);
+        #[allow(clippy::cast_precision_loss)]
+        let due = Duration::from_secs_f64(self.bytes as f64 / self.rate.max(1) as f64);
+        due.saturating_sub(now.saturating_duration_since(self.start))
+    }
+}
+
+/// Lower the CPU priority of this process by `nice`.
+#[cfg(unix)]
+fn set_nice(nice: i32) -> std::io::Result<()> {
+    // SAFETY: setpriority only changes the scheduling priority of this
+    // process (`who` 0) and takes no pointers.
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_nice(_: i32) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Set the I/O scheduling class of this process (`ioprio_set`).
+#[cfg(target_os = "linux")]
+fn set_io_priority(class: IoPriority) -> std::io::Result<()> {
+    const WHO_PROCESS: libc::c_long = 1;
+    const CLASS_SHIFT: libc::c_long = 13;
+    let value = match class {
+        IoPriority::Low => (2 << CLASS_SHIFT) | 7, // Best effort, lowest level
+        IoPriority::Idle => 3 << CLASS_SHIFT,
+    };
+    // SAFETY: ioprio_set only changes the I/O priority of this process
+    // (`who` 0) and takes no pointers.
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, WHO_PROCESS, 0, value) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_io_priority(_: IoPriority) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_ahead_of_the_rate_wait() {
+        let start = Instant::now();
+        let mut pace = Pace::new(1_000_000, start);
+        // 2 MB at 1 MB/s after half a second: 1.5 s to go
+        assert_eq!(
+            pace.take(2_000_000, start + Duration::from_millis(500)),
+            Duration::from_millis(1500)
+        );
+    }
+
+    #[test]
+    fn reads_behind_the_rate_go_at_once() {
+        let start = Instant::now();
+        let mut pace = Pace::new(1_000_000, start);
+        assert_eq!(
+            pace.take(500_000, start + Duration::from_secs(3)),
+            Duration::ZERO
+        );
+    }
+}
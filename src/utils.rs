@@ -1,10 +1,16 @@
 //! Utility functions for path validation, file operations, and sanitization.
 
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
+use tempfile::TempDir;
+
+use crate::i18n::t;
+use crate::outcome::BadInput;
 
 /// User's choice when prompted about overwriting existing folders.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -33,50 +39,392 @@ impl CleanupChoice {
 
 /// Prompt the user for overwrite confirmation.
 pub fn prompt_to_cleanup(folder_path: &Path) -> Result<CleanupChoice> {
-    println!("Folder already exists: {}", folder_path.display());
-    print!("Cleanup? [Y]es / Yes to [A]ll / [N]o / No to A[l]l: ");
+    println!(
+        "{}",
+        t!(
+            "cleanup-folder-exists",
+            path = folder_path.display().to_string()
+        )
+    );
+    print!("{}", t!("cleanup-prompt"));
     io::stdout().flush()?;
 
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
 
-    let choice = match input.trim().to_lowercase().as_str() {
-        "y" | "yes" => CleanupChoice::Yes,
-        "a" | "yes to all" | "all" => CleanupChoice::YesToAll,
-        "n" | "no" => CleanupChoice::No,
-        "l" | "no to all" => CleanupChoice::NoToAll,
-        _ => {
-            println!("Invalid choice, defaulting to 'No'");
-            CleanupChoice::No
-        }
-    };
+    let choice = parse_cleanup_choice(&input).unwrap_or_else(|| {
+        println!("{}", t!("cleanup-invalid-choice"));
+        CleanupChoice::No
+    });
 
     Ok(choice)
 }
 
+/// Parse an answer to the cleanup prompt.
+///
+/// English and Spanish answers are both accepted regardless of the message
+/// language, so shared workstations behave the same for everyone.
+pub fn parse_cleanup_choice(input: &str) -> Option<CleanupChoice> {
+    match input.trim().to_lowercase().as_str() {
+        "y" | "yes" | "s" | "si" | "sí" => Some(CleanupChoice::Yes),
+        "a" | "yes to all" | "all" | "t" | "todo" | "sí a todo" | "si a todo" => {
+            Some(CleanupChoice::YesToAll)
+        }
+        "n" | "no" => Some(CleanupChoice::No),
+        "l" | "no to all" | "o" | "no a todo" => Some(CleanupChoice::NoToAll),
+        _ => None,
+    }
+}
+
 /// Validate that the input folder exists and is a directory.
 pub fn validate_input_folder(input: &Path) -> Result<()> {
     if !input.exists() {
-        anyhow::bail!("Input folder does not exist: {}", input.display());
+        anyhow::bail!(BadInput(format!(
+            "Input folder does not exist: {}",
+            input.display()
+        )));
     }
     if !input.is_dir() {
-        anyhow::bail!("Input path is not a directory: {}", input.display());
+        anyhow::bail!(BadInput(format!(
+            "Input path is not a directory: {}",
+            input.display()
+        )));
     }
     Ok(())
 }
 
+/// List the `.dcm` files (case-insensitive extension) directly inside a folder.
+///
+/// Symlinked entries (including Windows junctions) are skipped unless
+/// `follow_symlinks` is set. When following, every entry is resolved to its
+/// target and entries pointing at an already listed file are dropped, so a
+/// symlink farm never yields the same slice twice. Skipped entries are
+/// reported on stderr.
+pub fn list_dcm_files(input: &Path, follow_symlinks: bool) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(extended_length_path(input))
+        .with_context(|| format!("Failed to read input folder: {}", input.display()))?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(std::result::Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("dcm"))
+        })
+        .collect();
+    // `read_dir` order is filesystem-dependent; sort so runs are reproducible
+    // and the first of several links to one target is the one kept.
+    paths.sort();
+
+    let mut files = Vec::with_capacity(paths.len());
+    let mut targets = HashSet::new();
+    let (mut links, mut broken, mut duplicates) = (0, 0, 0);
+    for path in paths {
+        let is_link = fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_symlink());
+        if is_link && !follow_symlinks {
+            links += 1;
+            continue;
+        }
+        if !path.is_file() {
+            if is_link && !path.exists() {
+                broken += 1;
+            }
+            continue;
+        }
+        if follow_symlinks {
+            let Ok(target) = fs::canonicalize(&path) else {
+                broken += 1;
+                continue;
+            };
+            if !targets.insert(target) {
+                duplicates += 1;
+                continue;
+            }
+        }
+        files.push(path);
+    }
+
+    let folder = input.display().to_string();
+    if links > 0 {
+        eprintln!(
+            "{}",
+            t!(
+                "input-symlinks-skipped",
+                count = links,
+                path = folder.clone()
+            )
+        );
+    }
+    if broken > 0 {
+        eprintln!(
+            "{}",
+            t!(
+                "input-symlinks-broken",
+                count = broken,
+                path = folder.clone()
+            )
+        );
+    }
+    if duplicates > 0 {
+        eprintln!(
+            "{}",
+            t!(
+                "input-duplicates-skipped",
+                count = duplicates,
+                path = folder
+            )
+        );
+    }
+    Ok(files)
+}
+
+/// Longest sanitized name in bytes. Well under the usual 255-byte limit, so
+/// a folder name still fits with an extension (`T2_AX/T2_AX.mp4`) or a
+/// collision suffix appended.
+const MAX_FILENAME_BYTES: usize = 128;
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 /// Sanitize a string for use as a filename/folder name.
-/// Replaces invalid characters with underscores.
+/// Replaces invalid characters with underscores, strips trailing dots and
+/// spaces (which Windows drops), renames reserved device names (`CON` becomes
+/// `CON_`), and shortens names longer than [`MAX_FILENAME_BYTES`], ending
+/// them with a hash of the full name so shortened names stay distinct.
 pub fn sanitize_filename(name: &str) -> String {
-    name.chars()
+    let replaced: String = name
+        .chars()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
             c if c.is_ascii_control() => '_',
             c => c,
         })
-        .collect::<String>()
-        .trim()
-        .to_string()
+        .collect();
+    let trimmed = replaced.trim();
+    let mut sanitized = trimmed.trim_end_matches(['.', ' ']).to_string();
+    if sanitized.is_empty() && !trimmed.is_empty() {
+        // Only dots, such as `..`, which must never name a folder.
+        sanitized.push('_');
+    }
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(stem.len(), '_');
+    }
+    if sanitized.len() > MAX_FILENAME_BYTES {
+        let hash = format!("_{:08x}", fnv1a(name));
+        let mut cut = MAX_FILENAME_BYTES - hash.len();
+        while !sanitized.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        sanitized.truncate(cut);
+        let kept = sanitized.trim_end_matches(['.', ' ']).len();
+        sanitized.truncate(kept);
+        sanitized.push_str(&hash);
+    }
+    sanitized
+}
+
+/// ASCII letters for U+00C0..=U+00FF; `?` marks the entries of
+/// [`transliterate_char`] that need more than one letter.
+const LATIN_1: &str = "AAAAAA?CEEEEIIIIDNOOOOOxOUUUUY??aaaaaa?ceeeeiiiidnooooo?ouuuuy?y";
+
+/// ASCII letters for U+0100..=U+017F (Latin Extended-A), same convention.
+const LATIN_EXTENDED_A: &str = "AaAaAaCcCcCcCcDdDdEeEeEeEeEeGgGgGgGgHhHhIiIiIiIiIi??JjKkkLlLlLlLlLlNnNnNnnNnOoOoOo??RrRrRrSsSsSsSsTtTtTtUuUuUuUuUuUuWwYyYZzZzZzs";
+
+/// Spell a name in ASCII for filesystems and tools that mangle other
+/// characters: Latin letters lose their accents (`é` becomes `e`, `ß` becomes
+/// `ss`), combining accents are dropped, and any other character is written
+/// as its code point (`中` becomes `u4E2D`).
+pub fn transliterate(name: &str) -> String {
+    let mut ascii = String::with_capacity(name.len());
+    for c in name.chars() {
+        match transliterate_char(c) {
+            Some(text) => ascii.push_str(text),
+            None if c.is_ascii() => ascii.push(c),
+            // Combining diacritical marks, as in decomposed (NFD) names.
+            None if ('\u{300}'..='\u{36f}').contains(&c) => {}
+            None => ascii.push_str(&format!("u{:04X}", u32::from(c))),
+        }
+    }
+    ascii
+}
+
+/// The ASCII spelling of a non-ASCII Latin character, if it has one.
+fn transliterate_char(c: char) -> Option<&'static str> {
+    let special = match c {
+        '\u{a0}' => " ",
+        'µ' => "u",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Þ' => "TH",
+        'þ' => "th",
+        'ß' => "ss",
+        '÷' => "_",
+        'Ĳ' => "IJ",
+        'ĳ' => "ij",
+        'Œ' => "OE",
+        'œ' => "oe",
+        _ => "",
+    };
+    if !special.is_empty() {
+        return Some(special);
+    }
+    let (table, start) = match u32::from(c) {
+        0xc0..=0xff => (LATIN_1, 0xc0),
+        0x100..=0x17f => (LATIN_EXTENDED_A, 0x100),
+        _ => return None,
+    };
+    let at = (u32::from(c) - start) as usize;
+    table.get(at..=at).filter(|letter| *letter != "?")
+}
+
+/// 32-bit FNV-1a hash: short, and the same on every platform and release.
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// `<dir>/<dir name>.<extension>`, e.g. `out/T2_AX/T2_AX.mp4`.
+///
+/// The name is built from the raw `OsStr`, so Unicode and non-UTF-8 folder
+/// names are kept as-is. A path without a final component falls back to
+/// `output`.
+pub fn named_after_folder(dir: &Path, extension: &str) -> PathBuf {
+    let mut name = dir
+        .file_name()
+        .unwrap_or_else(|| OsStr::new("output"))
+        .to_os_string();
+    name.push(".");
+    name.push(extension);
+    dir.join(name)
+}
+
+/// Rewrite a path so Windows accepts it beyond `MAX_PATH` (260 characters).
+///
+/// The path is made absolute and given the `\\?\` prefix; UNC shares become
+/// `\\?\UNC\server\share\...`. Paths already in verbatim form are returned
+/// unchanged.
+#[cfg(windows)]
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let mut components = absolute.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return absolute;
+    };
+    let mut extended = match prefix.kind() {
+        Prefix::Disk(_) => {
+            let mut head = OsString::from(r"\\?\");
+            head.push(prefix.as_os_str());
+            head
+        }
+        Prefix::UNC(server, share) => {
+            let mut head = OsString::from(r"\\?\UNC\");
+            head.push(server);
+            head.push(r"\");
+            head.push(share);
+            head
+        }
+        _ => return absolute,
+    };
+    extended.push(components.as_path());
+    PathBuf::from(extended)
+}
+
+/// Rewrite a path so Windows accepts it beyond `MAX_PATH` (260 characters).
+///
+/// Other platforms have no such limit; the path is returned unchanged.
+#[cfg(not(windows))]
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Validate a `--temp-dir` folder, so a typo fails before any work starts.
+pub fn validate_temp_dir(parent: Option<&Path>) -> Result<()> {
+    if let Some(parent) = parent
+        && !parent.is_dir()
+    {
+        anyhow::bail!(BadInput(format!(
+            "Temp folder does not exist or is not a directory: {}",
+            parent.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Make sure an `--audio` track exists before any frame is rendered.
+pub fn validate_audio(audio: Option<&Path>) -> Result<()> {
+    if let Some(audio) = audio
+        && !audio.is_file()
+    {
+        anyhow::bail!(BadInput(format!(
+            "Audio file does not exist or is not a file: {}",
+            audio.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Create a temporary folder for intermediates inside `parent`, or in the
+/// system temp folder when no parent is given (`--temp-dir`).
+pub fn create_temp_dir(parent: Option<&Path>) -> Result<TempDir> {
+    validate_temp_dir(parent)?;
+    let Some(parent) = parent else {
+        return TempDir::new().context("Failed to create temporary directory");
+    };
+    TempDir::new_in(extended_length_path(parent)).with_context(|| {
+        format!(
+            "Failed to create temporary directory in {}",
+            parent.display()
+        )
+    })
+}
+
+/// Fail early when the filesystem holding `dir` has less than `required`
+/// bytes available, instead of running out of space halfway through.
+///
+/// Filesystems that cannot report their free space are not checked.
+pub fn ensure_free_space(dir: &Path, required: u64) -> Result<()> {
+    let Ok(available) = fs4::available_space(dir) else {
+        return Ok(());
+    };
+    if available < required {
+        anyhow::bail!(
+            "Not enough free space in {}: about {} needed, {} available (use --temp-dir to pick another disk)",
+            dir.display(),
+            format_bytes(required),
+            format_bytes(available)
+        );
+    }
+    Ok(())
+}
+
+/// Human-readable byte count with binary units, e.g. `1.5 GiB`.
+#[allow(clippy::cast_precision_loss)]
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
 }
 
 /// Clean existing output folder if requested.
@@ -91,13 +439,19 @@ pub fn clean_output(path: &PathBuf, should_clean: bool) -> Result<()> {
         if should_clean {
             fs::remove_file(path)
                 .with_context(|| format!("Failed to remove existing file: {}", path.display()))?;
-            println!("Removed existing file: {}", path.display());
+            println!(
+                "{}",
+                t!("cleanup-removed-file", path = path.display().to_string())
+            );
         }
         // If not cleaning, the file will be overwritten naturally
     } else if path.is_dir() && !is_folder_empty(path)? && should_clean {
         fs::remove_dir_all(path)
             .with_context(|| format!("Failed to clean output folder: {}", path.display()))?;
-        println!("Cleaned output folder: {}", path.display());
+        println!(
+            "{}",
+            t!("cleanup-cleaned-folder", path = path.display().to_string())
+        );
     }
 
     Ok(())
@@ -105,8 +459,8 @@ pub fn clean_output(path: &PathBuf, should_clean: bool) -> Result<()> {
 
 /// Check if a folder is empty.
 pub fn is_folder_empty(path: &PathBuf) -> Result<bool> {
-    let mut entries =
-        fs::read_dir(path).with_context(|| format!("Failed to read directory: {}", path.display()))?;
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory: {}", path.display()))?;
     Ok(entries.next().is_none())
 }
 
@@ -190,6 +544,36 @@ mod tests {
         }
     }
 
+    // =========================================================================
+    // parse_cleanup_choice Tests
+    // =========================================================================
+
+    mod parse_cleanup_choice_tests {
+        use super::*;
+
+        #[test]
+        fn parses_english_answers() {
+            assert_eq!(parse_cleanup_choice("y\n"), Some(CleanupChoice::Yes));
+            assert_eq!(parse_cleanup_choice("A"), Some(CleanupChoice::YesToAll));
+            assert_eq!(parse_cleanup_choice(" no "), Some(CleanupChoice::No));
+            assert_eq!(parse_cleanup_choice("l"), Some(CleanupChoice::NoToAll));
+        }
+
+        #[test]
+        fn parses_spanish_answers() {
+            assert_eq!(parse_cleanup_choice("s"), Some(CleanupChoice::Yes));
+            assert_eq!(parse_cleanup_choice("Sí"), Some(CleanupChoice::Yes));
+            assert_eq!(parse_cleanup_choice("t"), Some(CleanupChoice::YesToAll));
+            assert_eq!(parse_cleanup_choice("o"), Some(CleanupChoice::NoToAll));
+        }
+
+        #[test]
+        fn rejects_unknown_answers() {
+            assert_eq!(parse_cleanup_choice(""), None);
+            assert_eq!(parse_cleanup_choice("maybe"), None);
+        }
+    }
+
     // =========================================================================
     // validate_input_folder Tests
     // =========================================================================
@@ -430,6 +814,51 @@ mod tests {
             assert_eq!(sanitize_filename("café"), "café");
         }
 
+        #[test]
+        fn strips_trailing_dots_and_spaces() {
+            assert_eq!(sanitize_filename("T2 AX. ."), "T2 AX");
+            assert_eq!(sanitize_filename("v1.0."), "v1.0");
+            assert_eq!(sanitize_filename(".."), "_");
+        }
+
+        #[test]
+        fn renames_reserved_names() {
+            assert_eq!(sanitize_filename("CON"), "CON_");
+            assert_eq!(sanitize_filename("nul.txt"), "nul_.txt");
+            assert_eq!(sanitize_filename("com1"), "com1_");
+            assert_eq!(sanitize_filename("CONSOLE"), "CONSOLE");
+        }
+
+        #[test]
+        fn shortens_long_names_with_a_hash() {
+            let long = "é".repeat(100);
+            let short = sanitize_filename(&long);
+            assert!(short.len() <= MAX_FILENAME_BYTES, "{}", short.len());
+            assert!(short.starts_with("éé"));
+            let other = sanitize_filename(&format!("{long}x"));
+            assert_eq!(short.len(), other.len());
+            assert_ne!(short, other);
+            assert_eq!(sanitize_filename(&long), short);
+        }
+
+        #[test]
+        fn transliterates_to_ascii() {
+            assert_eq!(transliterate("Tórax Ü ß Łódź"), "Torax U ss Lodz");
+            assert_eq!(transliterate("Cœur Œ Æ"), "Coeur OE AE");
+            assert_eq!(transliterate("cafe\u{301}"), "cafe");
+            assert_eq!(transliterate("頭部 CT"), "u982Du90E8 CT");
+            assert_eq!(transliterate("T1_AX-3.5"), "T1_AX-3.5");
+        }
+
+        #[test]
+        fn transliteration_tables_cover_their_ranges() {
+            assert_eq!(LATIN_1.len(), 0x40);
+            assert_eq!(LATIN_EXTENDED_A.len(), 0x80);
+            for c in '\u{c0}'..='\u{17f}' {
+                assert!(transliterate_char(c).is_some(), "{c}");
+            }
+        }
+
         #[test]
         fn handles_consecutive_invalid_chars() {
             assert_eq!(sanitize_filename("a//\\\\b"), "a____b");
@@ -671,4 +1100,217 @@ mod tests {
             assert!(!result.unwrap());
         }
     }
+
+    // =========================================================================
+    // Path Encoding Tests
+    // =========================================================================
+
+    mod path_encoding {
+        use super::*;
+
+        #[test]
+        fn output_file_is_named_after_unicode_folder() {
+            let path = named_after_folder(Path::new("/out/Tórax 胸部"), "mp4");
+            assert_eq!(path, Path::new("/out/Tórax 胸部/Tórax 胸部.mp4"));
+        }
+
+        #[test]
+        fn dotted_folder_name_keeps_its_dots() {
+            let path = named_after_folder(Path::new("/out/1.5"), "stl");
+            assert!(path.ends_with("1.5.stl"));
+        }
+
+        #[test]
+        fn folder_without_name_falls_back_to_output() {
+            assert_eq!(
+                named_after_folder(Path::new("/"), "stl"),
+                Path::new("/output.stl")
+            );
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn non_utf8_folder_name_is_preserved() {
+            use std::os::unix::ffi::OsStrExt;
+
+            let folder = Path::new("/out").join(OsStr::from_bytes(b"series_\xff"));
+            let path = named_after_folder(&folder, "mp4");
+            assert_eq!(path.file_name().unwrap().as_bytes(), b"series_\xff.mp4");
+        }
+
+        #[test]
+        fn unicode_folders_can_be_created_and_listed() {
+            let temp_dir = TempDir::new().unwrap();
+            let series = temp_dir.path().join("Cráneo – 頭部 (ñ)");
+            fs::create_dir_all(&series).unwrap();
+            fs::write(series.join("Corte 1.dcm"), "x").unwrap();
+
+            let files = list_dcm_files(&series, false).unwrap();
+            assert_eq!(files, vec![series.join("Corte 1.dcm")]);
+        }
+
+        #[test]
+        #[cfg(not(windows))]
+        fn paths_are_unchanged_off_windows() {
+            let path = Path::new("relative/series");
+            assert_eq!(extended_length_path(path), path);
+        }
+
+        #[test]
+        #[cfg(windows)]
+        fn drive_paths_get_verbatim_prefix() {
+            assert_eq!(
+                extended_length_path(Path::new(r"C:\data\..\scans\ct")),
+                Path::new(r"\\?\C:\scans\ct")
+            );
+        }
+
+        #[test]
+        #[cfg(windows)]
+        fn unc_paths_get_verbatim_unc_prefix() {
+            assert_eq!(
+                extended_length_path(Path::new(r"\\pacs\share\ct")),
+                Path::new(r"\\?\UNC\pacs\share\ct")
+            );
+        }
+
+        #[test]
+        #[cfg(windows)]
+        fn verbatim_paths_are_unchanged() {
+            let path = Path::new(r"\\?\C:\scans");
+            assert_eq!(extended_length_path(path), path);
+        }
+    }
+
+    // =========================================================================
+    // Temp Folder and Disk Space Tests
+    // =========================================================================
+
+    mod temp_space {
+        use super::*;
+
+        #[test]
+        fn temp_dir_is_created_inside_given_parent() {
+            let parent = TempDir::new().unwrap();
+            let temp = create_temp_dir(Some(parent.path())).unwrap();
+            assert!(temp.path().starts_with(parent.path()));
+        }
+
+        #[test]
+        fn missing_temp_parent_is_bad_input() {
+            let parent = TempDir::new().unwrap();
+            let missing = parent.path().join("scratch");
+            let err = create_temp_dir(Some(&missing)).unwrap_err();
+            assert!(err.downcast_ref::<BadInput>().is_some());
+        }
+
+        #[test]
+        fn missing_audio_is_bad_input() {
+            let dir = TempDir::new().unwrap();
+            assert!(validate_audio(None).is_ok());
+            // A folder is not an audio track
+            let err = validate_audio(Some(dir.path())).unwrap_err();
+            assert!(err.downcast_ref::<BadInput>().is_some());
+            let err = validate_audio(Some(&dir.path().join("talk.mp3"))).unwrap_err();
+            assert!(err.to_string().contains("talk.mp3"), "{err}");
+        }
+
+        #[test]
+        fn small_requirement_fits() {
+            let dir = TempDir::new().unwrap();
+            assert!(ensure_free_space(dir.path(), 1).is_ok());
+        }
+
+        #[test]
+        fn impossible_requirement_names_the_flag() {
+            let dir = TempDir::new().unwrap();
+            let err = ensure_free_space(dir.path(), u64::MAX).unwrap_err();
+            assert!(err.to_string().contains("--temp-dir"), "{err}");
+        }
+
+        #[test]
+        fn bytes_use_binary_units() {
+            assert_eq!(format_bytes(512), "512 B");
+            assert_eq!(format_bytes(1536), "1.5 KiB");
+            assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+        }
+    }
+
+    // =========================================================================
+    // list_dcm_files Tests
+    // =========================================================================
+
+    mod list_dcm_files_tests {
+        use super::*;
+
+        #[test]
+        fn lists_only_dcm_files_in_sorted_order() {
+            let temp_dir = TempDir::new().unwrap();
+            for name in ["c.dcm", "a.DCM", "notes.txt", "b.dcm"] {
+                fs::write(temp_dir.path().join(name), "x").unwrap();
+            }
+            fs::create_dir(temp_dir.path().join("sub.dcm")).unwrap();
+
+            let names: Vec<String> = list_dcm_files(temp_dir.path(), false)
+                .unwrap()
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect();
+            assert_eq!(names, vec!["a.DCM", "b.dcm", "c.dcm"]);
+        }
+
+        #[cfg(unix)]
+        fn symlink_farm() -> TempDir {
+            use std::os::unix::fs::symlink;
+
+            let temp_dir = TempDir::new().unwrap();
+            let store = temp_dir.path().join("store");
+            let farm = temp_dir.path().join("farm");
+            fs::create_dir_all(&store).unwrap();
+            fs::create_dir_all(&farm).unwrap();
+            fs::write(store.join("1.dcm"), "x").unwrap();
+            fs::write(store.join("2.dcm"), "x").unwrap();
+            fs::write(farm.join("a.dcm"), "x").unwrap();
+            symlink(store.join("1.dcm"), farm.join("b.dcm")).unwrap();
+            symlink(store.join("1.dcm"), farm.join("c.dcm")).unwrap();
+            symlink(store.join("2.dcm"), farm.join("d.dcm")).unwrap();
+            symlink(farm.join("a.dcm"), farm.join("e.dcm")).unwrap();
+            symlink(store.join("missing.dcm"), farm.join("f.dcm")).unwrap();
+            temp_dir
+        }
+
+        fn names(files: &[PathBuf]) -> Vec<String> {
+            files
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn symlinks_are_skipped_by_default() {
+            let temp_dir = symlink_farm();
+            let files = list_dcm_files(&temp_dir.path().join("farm"), false).unwrap();
+            assert_eq!(names(&files), vec!["a.dcm"]);
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn followed_symlinks_are_deduplicated_by_target() {
+            let temp_dir = symlink_farm();
+            let files = list_dcm_files(&temp_dir.path().join("farm"), true).unwrap();
+            // c.dcm and e.dcm repeat earlier targets; f.dcm is dangling
+            assert_eq!(names(&files), vec!["a.dcm", "b.dcm", "d.dcm"]);
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn symlinked_input_folder_is_listed() {
+            let temp_dir = symlink_farm();
+            let link = temp_dir.path().join("link");
+            std::os::unix::fs::symlink(temp_dir.path().join("store"), &link).unwrap();
+            let files = list_dcm_files(&link, false).unwrap();
+            assert_eq!(names(&files), vec!["1.dcm", "2.dcm"]);
+        }
+    }
 }
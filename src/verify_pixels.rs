@@ -0,0 +1,491 @@
+//! Pixel comparison of round-tripped files (`verify-pixels`).
+//!
+//! After a transcode or an anonymization pass, the header of a copy differs
+//! from its source by design, so checksums cannot tell whether the images
+//! survived. Here both files are decoded frame by frame and their calibrated
+//! values compared. A copy whose transfer syntax is lossless (and that is not
+//! marked `LossyImageCompression` `01`) must match its source exactly; a
+//! lossy copy is reported with its peak signal-to-noise ratio instead.
+//!
+//! Copies are found by their path relative to `--copy`, else by
+//! `SOPInstanceUID`, so both renamed and re-identified copies are matched.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Args;
+use dicom::dictionary_std::{tags, uids};
+use dicom::object::DefaultDicomObject;
+use image::{DynamicImage, GenericImageView};
+
+use crate::cancel;
+use crate::i18n::t;
+use crate::inventory;
+use crate::meta::InstanceMeta;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, display_name};
+use crate::pixel::{self, DecodedFrame};
+
+/// Transfer syntaxes that keep every pixel value.
+const LOSSLESS: [&str; 13] = [
+    uids::IMPLICIT_VR_LITTLE_ENDIAN,
+    uids::EXPLICIT_VR_LITTLE_ENDIAN,
+    uids::DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN,
+    uids::ENCAPSULATED_UNCOMPRESSED_EXPLICIT_VR_LITTLE_ENDIAN,
+    uids::RLE_LOSSLESS,
+    uids::JPEG_LOSSLESS,
+    uids::JPEG_LOSSLESS_SV1,
+    uids::JPEGLS_LOSSLESS,
+    uids::JPEG2000_LOSSLESS,
+    uids::JPEG2000MC_LOSSLESS,
+    uids::HTJ2K_LOSSLESS,
+    uids::HTJ2K_LOSSLESS_RPCL,
+    uids::JPEGXL_LOSSLESS,
+];
+
+/// CLI arguments for the `verify-pixels` subcommand.
+#[derive(Args, Debug)]
+pub struct VerifyPixelsArgs {
+    /// Original DICOM file, or folder of them (subfolders included)
+    #[arg(long)]
+    pub source: PathBuf,
+
+    /// Transcoded or anonymized copy of `--source`: a file, or a folder
+    #[arg(long)]
+    pub copy: PathBuf,
+
+    /// Include symlinked .dcm files and folders (resolved, duplicates dropped)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+}
+
+/// How far a frame of the copy is from the source's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Difference {
+    /// Largest difference of one sample.
+    max: f64,
+    /// Peak signal-to-noise ratio in dB; infinite for identical frames.
+    psnr: f64,
+}
+
+impl Difference {
+    fn is_identical(self) -> bool {
+        self.max == 0.0
+    }
+}
+
+/// How a copy compares with its source.
+#[derive(Debug, Clone, PartialEq)]
+enum Verdict {
+    /// Every frame decodes to the same values.
+    Identical { frames: u32 },
+    /// A lossy copy; its worst frame.
+    Lossy { worst: Difference },
+    /// A lossless copy whose frame `frame` (from 0) differs.
+    Differs { frame: u32, difference: Difference },
+    /// The files cannot be compared (missing, unreadable, other sizes).
+    Failed(String),
+}
+
+/// Decode `source` and `copy` and compare them frame by frame.
+fn compare_files(source: &Path, copy: &Path) -> Verdict {
+    let open = |path: &Path| pipeline::open_object(path).map_err(|e| e.to_string());
+    let (source, copy) = match (open(source), open(copy)) {
+        (Ok(source), Ok(copy)) => (source, copy),
+        (Err(e), _) | (_, Err(e)) => return Verdict::Failed(e),
+    };
+    let frames = pipeline::number_of_frames(&source);
+    let copy_frames = pipeline::number_of_frames(&copy);
+    if frames != copy_frames {
+        return Verdict::Failed(t!(
+            "verify-pixels-frame-count",
+            source = frames,
+            copy = copy_frames
+        ));
+    }
+    let lossless = is_lossless(&copy);
+    let mut worst: Option<Difference> = None;
+    for frame in 0..frames {
+        let decode = |obj: &DefaultDicomObject| {
+            pixel::decode_frame(obj, frame).map_err(|e| {
+                t!(
+                    "verify-pixels-frame-error",
+                    frame = frame + 1,
+                    error = e.to_string()
+                )
+            })
+        };
+        let difference = match (decode(&source), decode(&copy)) {
+            (Ok(a), Ok(b)) => difference(&a, &b),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        };
+        let difference = match difference {
+            Ok(difference) => difference,
+            Err(e) => return Verdict::Failed(e),
+        };
+        if lossless && !difference.is_identical() {
+            return Verdict::Differs { frame, difference };
+        }
+        if worst.is_none_or(|worst| difference.psnr < worst.psnr) {
+            worst = Some(difference);
+        }
+    }
+    match worst {
+        Some(worst) if !worst.is_identical() => Verdict::Lossy { worst },
+        _ => Verdict::Identical { frames },
+    }
+}
+
+/// Whether `obj` claims to hold every original pixel value: a lossless
+/// transfer syntax, and no `LossyImageCompression` `01` from an earlier
+/// lossy step.
+fn is_lossless(obj: &DefaultDicomObject) -> bool {
+    let syntax = obj.meta().transfer_syntax().trim_end_matches(['\0', ' ']);
+    let compressed_before = obj
+        .element(tags::LOSSY_IMAGE_COMPRESSION)
+        .ok()
+        .and_then(|e| e.to_str().ok())
+        .is_some_and(|value| value.trim() == "01");
+    LOSSLESS.contains(&syntax) && !compressed_before
+}
+
+/// How far `copy` is from `source`: monochrome frames in modality units,
+/// color frames in 8-bit RGB.
+#[allow(clippy::cast_precision_loss)]
+fn difference(source: &DecodedFrame, copy: &DecodedFrame) -> Result<Difference, String> {
+    let (a, b, peak): (Vec<f64>, Vec<f64>, f64) = match (source, copy) {
+        (DecodedFrame::Mono(a), DecodedFrame::Mono(b)) => {
+            if (a.width, a.height) != (b.width, b.height) {
+                return Err(t!(
+                    "verify-pixels-size",
+                    source = format!("{}x{}", a.width, a.height),
+                    copy = format!("{}x{}", b.width, b.height)
+                ));
+            }
+            let (low, high) = a.value_range();
+            let values = |values: &[f32]| values.iter().copied().map(f64::from).collect();
+            (
+                values(&a.values),
+                values(&b.values),
+                f64::from(high - low).max(1.0),
+            )
+        }
+        (DecodedFrame::Color(a), DecodedFrame::Color(b)) => {
+            if a.dimensions() != b.dimensions() {
+                let ((aw, ah), (bw, bh)) = (a.dimensions(), b.dimensions());
+                return Err(t!(
+                    "verify-pixels-size",
+                    source = format!("{aw}x{ah}"),
+                    copy = format!("{bw}x{bh}")
+                ));
+            }
+            let values = |image: &DynamicImage| {
+                image
+                    .to_rgb8()
+                    .into_raw()
+                    .into_iter()
+                    .map(f64::from)
+                    .collect()
+            };
+            (values(a), values(b), 255.0)
+        }
+        _ => return Err(t!("verify-pixels-color-mismatch")),
+    };
+    let (mut max, mut squares) = (0.0_f64, 0.0_f64);
+    for (a, b) in a.iter().zip(&b) {
+        let delta = (a - b).abs();
+        max = max.max(delta);
+        squares += delta * delta;
+    }
+    let mse = squares / a.len().max(1) as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (peak * peak / mse).log10()
+    };
+    Ok(Difference { max, psnr })
+}
+
+/// Source files paired with their copies; `None` when no copy was found.
+fn pair(args: &VerifyPixelsArgs) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    if args.source.is_file() {
+        return if args.copy.is_file() {
+            Ok(vec![(args.source.clone(), Some(args.copy.clone()))])
+        } else {
+            Err(BadInput(format!(
+                "--source is a file, so --copy must be one too: {}",
+                args.copy.display()
+            ))
+            .into())
+        };
+    }
+    for (flag, path) in [("--source", &args.source), ("--copy", &args.copy)] {
+        if !path.is_dir() {
+            anyhow::bail!(BadInput(format!(
+                "{flag} must be a DICOM file or folder: {}",
+                path.display()
+            )));
+        }
+    }
+    let sources = inventory::walk(&args.source, args.follow_symlinks)?;
+    let copies = inventory::walk(&args.copy, args.follow_symlinks)?;
+    let relative = |path: &Path, root: &Path| path.strip_prefix(root).map(Path::to_path_buf).ok();
+    let by_name: HashMap<PathBuf, &PathBuf> = copies
+        .iter()
+        .filter_map(|copy| Some((relative(copy, &args.copy)?, copy)))
+        .collect();
+    // Headers are only read for sources whose copy was renamed
+    let mut by_uid: Option<HashMap<String, &PathBuf>> = None;
+    let mut pairs = Vec::with_capacity(sources.len());
+    for source in &sources {
+        let named = relative(source, &args.source).and_then(|name| by_name.get(&name).copied());
+        let copy = named.or_else(|| {
+            let uid = InstanceMeta::read(source).sop_instance_uid?;
+            by_uid
+                .get_or_insert_with(|| {
+                    copies
+                        .iter()
+                        .filter_map(|copy| Some((InstanceMeta::read(copy).sop_instance_uid?, copy)))
+                        .collect()
+                })
+                .get(&uid)
+                .copied()
+        });
+        pairs.push((source.clone(), copy.cloned()));
+    }
+    Ok(pairs)
+}
+
+/// Compare every source file with its copy, print a line each and a
+/// summary, and fail if a lossless copy differs or a copy is missing.
+pub fn run(args: &VerifyPixelsArgs) -> Result<()> {
+    let pairs = pair(args)?;
+    if pairs.is_empty() {
+        println!(
+            "{}",
+            t!("no-dcm-files", path = args.source.display().to_string())
+        );
+        return Ok(());
+    }
+    println!("{}\n", t!("verify-pixels-comparing", count = pairs.len()));
+
+    let (mut identical, mut lossy, mut failed) = (0, 0, 0);
+    let mut lowest: Option<f64> = None;
+    for (source, copy) in &pairs {
+        if let Some(stopped) = cancel::ended() {
+            anyhow::bail!(stopped);
+        }
+        let file = display_name(source);
+        let verdict = match copy {
+            Some(copy) => compare_files(source, copy),
+            None => Verdict::Failed(t!("verify-pixels-no-copy")),
+        };
+        match verdict {
+            Verdict::Identical { frames } => {
+                identical += 1;
+                println!(
+                    "{}",
+                    t!("verify-pixels-identical", file = file, frames = frames)
+                );
+            }
+            Verdict::Lossy { worst } => {
+                lossy += 1;
+                lowest = Some(lowest.map_or(worst.psnr, |lowest| lowest.min(worst.psnr)));
+                println!(
+                    "{}",
+                    t!(
+                        "verify-pixels-lossy",
+                        file = file,
+                        psnr = format!("{:.1}", worst.psnr),
+                        max = worst.max
+                    )
+                );
+            }
+            Verdict::Differs { frame, difference } => {
+                failed += 1;
+                println!(
+                    "{}",
+                    t!(
+                        "verify-pixels-differs",
+                        file = file,
+                        frame = frame + 1,
+                        max = difference.max,
+                        psnr = format!("{:.1}", difference.psnr)
+                    )
+                );
+            }
+            Verdict::Failed(error) => {
+                failed += 1;
+                println!("{}", t!("verify-pixels-failed", file = file, error = error));
+            }
+        }
+    }
+
+    println!(
+        "\n{}",
+        t!(
+            "verify-pixels-summary",
+            identical = identical,
+            total = pairs.len(),
+            lossy = lossy
+        )
+    );
+    if let Some(lowest) = lowest {
+        println!(
+            "{}",
+            t!("verify-pixels-lowest-psnr", psnr = format!("{lowest:.1}"))
+        );
+    }
+    if failed > 0 {
+        anyhow::bail!(t!("verify-pixels-mismatched", count = failed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use dicom::core::{DataElement, PrimitiveValue, VR};
+    use dicom::object::InMemDicomObject;
+    use image::RgbImage;
+
+    use super::*;
+    use crate::pixel::Frame;
+
+    /// A 2x2 8-bit monochrome object `uid` at `path`; `lossy` marks it
+    /// `LossyImageCompression` `01`.
+    fn write_image(path: &Path, uid: &str, pixels: [u8; 4], lossy: bool) {
+        let mut obj = InMemDicomObject::new_empty();
+        let mut put = |tag, vr, value: PrimitiveValue| obj.put(DataElement::new(tag, vr, value));
+        put(tags::SOP_INSTANCE_UID, VR::UI, PrimitiveValue::from(uid));
+        put(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(1_u16));
+        put(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            PrimitiveValue::from("MONOCHROME2"),
+        );
+        put(tags::ROWS, VR::US, PrimitiveValue::from(2_u16));
+        put(tags::COLUMNS, VR::US, PrimitiveValue::from(2_u16));
+        put(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(8_u16));
+        put(tags::BITS_STORED, VR::US, PrimitiveValue::from(8_u16));
+        put(tags::HIGH_BIT, VR::US, PrimitiveValue::from(7_u16));
+        put(
+            tags::PIXEL_REPRESENTATION,
+            VR::US,
+            PrimitiveValue::from(0_u16),
+        );
+        if lossy {
+            put(
+                tags::LOSSY_IMAGE_COMPRESSION,
+                VR::CS,
+                PrimitiveValue::from("01"),
+            );
+        }
+        put(
+            tags::PIXEL_DATA,
+            VR::OB,
+            PrimitiveValue::from(pixels.to_vec()),
+        );
+        let meta = dicom::object::FileMetaTableBuilder::new()
+            .transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN)
+            .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.7")
+            .media_storage_sop_instance_uid(uid)
+            .build()
+            .unwrap();
+        obj.with_exact_meta(meta).write_to_file(path).unwrap();
+    }
+
+    fn mono(values: &[f32]) -> DecodedFrame {
+        DecodedFrame::Mono(Frame {
+            width: u32::try_from(values.len()).unwrap(),
+            height: 1,
+            values: values.to_vec(),
+            window: None,
+            invert: false,
+        })
+    }
+
+    #[test]
+    fn identical_frames_have_infinite_psnr() {
+        let frame = mono(&[-1000.0, 0.0, 40.0, 3000.0]);
+        let difference = difference(&frame, &frame).unwrap();
+        assert!(difference.is_identical());
+        assert!(difference.psnr.is_infinite());
+    }
+
+    #[test]
+    fn psnr_is_relative_to_the_source_range() {
+        let source = mono(&[0.0, 100.0, 0.0, 100.0]);
+        let copy = mono(&[0.0, 100.0, 10.0, 100.0]);
+        let difference = difference(&source, &copy).unwrap();
+        assert_eq!(difference.max, 10.0);
+        // MSE 25 over a range of 100: 10 log10(10000 / 25)
+        assert!((difference.psnr - 26.02).abs() < 0.01, "{difference:?}");
+    }
+
+    #[test]
+    fn other_sizes_and_kinds_cannot_be_compared() {
+        assert!(difference(&mono(&[1.0, 2.0]), &mono(&[1.0, 2.0, 3.0])).is_err());
+        let color = DecodedFrame::Color(DynamicImage::ImageRgb8(RgbImage::new(2, 1)));
+        assert!(difference(&mono(&[1.0, 2.0]), &color).is_err());
+        assert!(difference(&color, &color).unwrap().is_identical());
+    }
+
+    #[test]
+    fn lossless_copies_must_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, copy) = (dir.path().join("a.dcm"), dir.path().join("b.dcm"));
+        write_image(&source, "1.2.3", [0, 80, 160, 240], false);
+        write_image(&copy, "1.2.3", [0, 80, 160, 240], false);
+        assert_eq!(
+            compare_files(&source, &copy),
+            Verdict::Identical { frames: 1 }
+        );
+
+        write_image(&copy, "1.2.3", [0, 80, 161, 240], false);
+        assert!(matches!(
+            compare_files(&source, &copy),
+            Verdict::Differs {
+                frame: 0,
+                difference: Difference { max: 1.0, .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn lossy_copies_report_their_psnr() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, copy) = (dir.path().join("a.dcm"), dir.path().join("b.dcm"));
+        write_image(&source, "1.2.3", [0, 80, 160, 240], false);
+        write_image(&copy, "1.2.3", [0, 80, 161, 240], true);
+        let Verdict::Lossy { worst } = compare_files(&source, &copy) else {
+            panic!("a copy marked lossy is not held to equality");
+        };
+        assert!(worst.psnr.is_finite() && worst.psnr > 40.0, "{worst:?}");
+    }
+
+    #[test]
+    fn renamed_copies_are_found_by_uid() {
+        let (source, copy) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        write_image(&source.path().join("1.dcm"), "1.2.3", [0; 4], false);
+        write_image(&source.path().join("2.dcm"), "1.2.4", [0; 4], false);
+        write_image(&copy.path().join("1.dcm"), "9.9.9", [0; 4], false);
+        write_image(&copy.path().join("renamed.dcm"), "1.2.4", [0; 4], false);
+        let args = VerifyPixelsArgs {
+            source: source.path().to_path_buf(),
+            copy: copy.path().to_path_buf(),
+            follow_symlinks: false,
+        };
+        let pairs = pair(&args).unwrap();
+        assert_eq!(
+            pairs,
+            [
+                (source.path().join("1.dcm"), Some(copy.path().join("1.dcm"))),
+                (
+                    source.path().join("2.dcm"),
+                    Some(copy.path().join("renamed.dcm"))
+                ),
+            ]
+        );
+    }
+}
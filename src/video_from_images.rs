@@ -0,0 +1,288 @@
+//! MP4 re-encoding of exported image series (`video-from-images`).
+//!
+//! Works on the numbered `0001.jpg`, `0002.jpg`, … files written by
+//! `convert jpeg` (or `convert video --with-images`), so a series can be
+//! encoded again at another frame rate or codec without decoding the DICOM
+//! files a second time.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::cancel::Cancel;
+use crate::convert::{
+    Background, Encoding, FrameInput, Hwaccel, VideoCodec, check_ffmpeg, encode_sequence,
+    parse_size, video_bitrate,
+};
+use crate::i18n::t;
+use crate::outcome::BadInput;
+use crate::utils::{named_after_folder, validate_audio, validate_input_folder};
+
+/// Image extensions written by `convert jpeg`.
+const EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// CLI arguments for the `video-from-images` subcommand.
+#[derive(Args, Debug)]
+pub struct VideoFromImagesArgs {
+    /// Series folder with numbered images (0001.jpg, 0002.jpg, ...)
+    #[arg(long = "in")]
+    pub input: PathBuf,
+
+    /// Output MP4 file [default: <in>/<folder name>.mp4]
+    #[arg(long = "out")]
+    pub output: Option<PathBuf>,
+
+    /// Frames per second for video output
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+
+    /// Video codec
+    #[arg(long, value_enum, default_value_t = VideoCodec::H264)]
+    pub codec: VideoCodec,
+
+    /// Narration (e.g. MP3) to add to the MP4, looped or cut to the video's
+    /// length
+    #[arg(long, value_name = "FILE")]
+    pub audio: Option<PathBuf>,
+
+    /// Color around images smaller or shaped differently than the first
+    /// (black, white, or #rrggbb)
+    #[arg(long, value_name = "COLOR", default_value = "black")]
+    pub background: Background,
+
+    /// Fit the MP4 in this file size (e.g. 25MB) with a two-pass encode
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub target_size: Option<u64>,
+
+    /// Encode on the GPU with this encoder family
+    #[arg(long, value_enum, value_name = "GPU")]
+    pub hwaccel: Option<Hwaccel>,
+
+    /// If ffmpeg fails, encode once more with software H.264 at a fast preset
+    #[arg(long)]
+    pub encode_fallback: bool,
+}
+
+/// An unbroken run of numbered images sharing one extension and padding.
+#[derive(Debug, PartialEq, Eq)]
+struct Sequence {
+    /// Extension as written on disk (case preserved).
+    extension: String,
+    /// Digits in every file stem.
+    padding: usize,
+    /// Number of the first image.
+    start: u32,
+    /// Number of images.
+    count: usize,
+}
+
+impl Sequence {
+    /// ffmpeg `image2` input pattern, e.g. `<dir>/%04d.jpg`.
+    fn pattern(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("%0{}d.{}", self.padding, self.extension))
+    }
+
+    /// Path of the first image.
+    fn first(&self, dir: &Path) -> PathBuf {
+        let padding = self.padding;
+        dir.join(format!("{:0padding$}.{}", self.start, self.extension))
+    }
+}
+
+/// Encode a folder of exported images into an MP4.
+pub fn run(args: &VideoFromImagesArgs) -> Result<()> {
+    validate_input_folder(&args.input)?;
+    validate_audio(args.audio.as_deref())?;
+    let sequence = find_sequence(&args.input)?;
+    check_ffmpeg(args.codec, args.hwaccel)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| named_after_folder(&args.input, "mp4"));
+
+    println!(
+        "{}",
+        t!(
+            "reencode-found-images",
+            count = sequence.count,
+            path = args.input.display().to_string()
+        )
+    );
+
+    // Exported images may differ in size; fit all of them to the first one
+    let first = sequence.first(&args.input);
+    let size = image::image_dimensions(&first)
+        .with_context(|| format!("Failed to read image: {}", first.display()))?;
+    let frame_count = u32::try_from(sequence.count).context("Too many frames for video")?;
+    let bitrate = args
+        .target_size
+        .map(|size| video_bitrate(size, frame_count, args.fps, args.audio.is_some()))
+        .transpose()?;
+
+    println!("\n{}", t!("video-encoding"));
+    encode_sequence(
+        &FrameInput::images(sequence.pattern(&args.input), sequence.start),
+        Encoding {
+            fps: args.fps,
+            codec: args.codec,
+            scale: Some(size),
+            background: args.background,
+            audio: args.audio.as_deref(),
+            chapters: None,
+            bitrate,
+            hwaccel: args.hwaccel,
+            retry: args.encode_fallback,
+            simple: false,
+            cancel: Cancel::default(),
+        },
+        &output,
+    )?;
+
+    println!(
+        "\n{}",
+        t!("video-saved", path = output.display().to_string())
+    );
+    println!("  {}", t!("video-total-frames", count = frame_count));
+    println!(
+        "  {}",
+        t!(
+            "video-duration",
+            seconds = format!("{:.2}", f64::from(frame_count) / f64::from(args.fps))
+        )
+    );
+    Ok(())
+}
+
+/// Find the numbered image sequence in a folder.
+///
+/// Other files (the video itself, `patches/`, notes) are ignored. Mixed
+/// extensions or paddings, and gaps in the numbering, are bad input: ffmpeg
+/// would silently stop at the first missing number.
+fn find_sequence(dir: &Path) -> Result<Sequence> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read input folder: {}", dir.display()))?;
+
+    let mut images: Vec<(u32, usize, String)> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| numbered_image(&entry.path()))
+        .collect();
+    images.sort();
+
+    let Some((start, padding, extension)) = images.first().cloned() else {
+        anyhow::bail!(BadInput(format!(
+            "No numbered images (0001.jpg, 0002.jpg, ...) found in {}",
+            dir.display()
+        )));
+    };
+    if let Some((_, other_padding, other_extension)) = images
+        .iter()
+        .find(|(_, p, e)| *p != padding || *e != extension)
+    {
+        anyhow::bail!(BadInput(format!(
+            "Mixed image names in {}: both {padding}-digit .{extension} and {other_padding}-digit .{other_extension} files",
+            dir.display()
+        )));
+    }
+    if let Some(missing) = (start..)
+        .zip(&images)
+        .find_map(|(expected, &(number, ..))| (number != expected).then_some(expected))
+    {
+        anyhow::bail!(BadInput(format!(
+            "Image {missing} is missing from the sequence in {}",
+            dir.display()
+        )));
+    }
+
+    Ok(Sequence {
+        extension,
+        padding,
+        start,
+        count: images.len(),
+    })
+}
+
+/// Number, digit count, and extension of a file named like `0001.jpg`.
+fn numbered_image(path: &Path) -> Option<(u32, usize, String)> {
+    if !path.is_file() {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+    if stem.is_empty()
+        || !stem.bytes().all(|b| b.is_ascii_digit())
+        || !EXTENSIONS
+            .iter()
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    {
+        return None;
+    }
+    Some((stem.parse().ok()?, stem.len(), extension.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(names: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in names {
+            fs::write(dir.path().join(name), "x").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn finds_exported_series() {
+        let dir = folder(&[
+            "0001.jpg",
+            "0002.jpg",
+            "0003.jpg",
+            "series.mp4",
+            "notes.txt",
+        ]);
+        let sequence = find_sequence(dir.path()).unwrap();
+        assert_eq!(
+            sequence,
+            Sequence {
+                extension: "jpg".to_string(),
+                padding: 4,
+                start: 1,
+                count: 3,
+            }
+        );
+        assert_eq!(sequence.pattern(dir.path()), dir.path().join("%04d.jpg"));
+        assert_eq!(sequence.first(dir.path()), dir.path().join("0001.jpg"));
+    }
+
+    #[test]
+    fn gap_in_numbering_is_bad_input() {
+        let dir = folder(&["0001.png", "0002.png", "0004.png"]);
+        let err = find_sequence(dir.path()).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        assert!(err.to_string().contains("Image 3 is missing"), "{err}");
+    }
+
+    #[test]
+    fn mixed_extensions_are_bad_input() {
+        let dir = folder(&["0001.jpg", "0002.png"]);
+        let err = find_sequence(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("Mixed image names"), "{err}");
+    }
+
+    #[test]
+    fn folder_without_images_is_bad_input() {
+        let dir = folder(&["series.mp4", "a1.jpg"]);
+        let err = find_sequence(dir.path()).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+    }
+
+    #[test]
+    fn sequence_may_start_after_one() {
+        let dir = folder(&["00010.JPG", "00011.JPG"]);
+        let sequence = find_sequence(dir.path()).unwrap();
+        assert_eq!((sequence.start, sequence.count), (10, 2));
+        assert_eq!(sequence.first(dir.path()), dir.path().join("00010.JPG"));
+    }
+}
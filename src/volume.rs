@@ -0,0 +1,461 @@
+//! 3D volumes assembled from sorted DICOM slices.
+//!
+//! Shared by the STL mesher, point cloud export, and the `register`
+//! subcommand. Voxel values are calibrated modality units (e.g. HU) packed
+//! X-fastest, and physical positions use the patient coordinate system of
+//! the first slice. Segmentation label maps (NIfTI-1, NRRD) are resampled onto
+//! these volumes by patient position.
+//!
+//! Slices are assumed to be axis-aligned (identity `ImageOrientationPatient`),
+//! which holds for the axial CT/MR/PET stacks this tool targets. Every frame
+//! is a slice, so one tall multi-frame object (breast tomosynthesis,
+//! enhanced CT/MR) loads like a folder of single-frame files; its spacing
+//! and positions come from the functional groups.
+
+mod labels;
+mod nifti;
+mod nrrd;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use dicom::dictionary_std::tags;
+use dicom::object::{InMemDicomObject, open_file};
+
+use crate::convert::sort_files_by_position;
+use crate::i18n::t;
+use crate::meta::InstanceMeta;
+use crate::outcome::BadInput;
+use crate::pipeline::{self, display_name};
+use crate::pixel::groups;
+use crate::prefetch::ReadAhead;
+use crate::utils::{list_dcm_files, validate_input_folder};
+
+pub use labels::{LabelMap, labels_present};
+pub use nifti::write_nifti;
+pub use nrrd::write_nrrd;
+
+/// Default slice thickness when metadata is unavailable (mm).
+const DEFAULT_SLICE_THICKNESS: f32 = 1.0;
+
+/// Default pixel spacing when metadata is unavailable (mm).
+const DEFAULT_PIXEL_SPACING: f32 = 1.0;
+
+/// Holds the 3D volumetric data built from stacked DICOM slices.
+#[derive(Debug, Clone)]
+pub struct Volume {
+    /// Flat array of voxel values in modality units (e.g. HU), packed X-fastest.
+    pub values: Vec<f32>,
+    /// Number of columns (X dimension).
+    pub cols: usize,
+    /// Number of rows (Y dimension).
+    pub rows: usize,
+    /// Number of slices (Z dimension).
+    pub slices: usize,
+    /// Physical pixel spacing along X in mm.
+    pub spacing_x: f32,
+    /// Physical pixel spacing along Y in mm.
+    pub spacing_y: f32,
+    /// Physical slice spacing along Z in mm.
+    pub spacing_z: f32,
+    /// Patient position of the first voxel in mm (`ImagePositionPatient`).
+    pub origin: [f64; 3],
+}
+
+impl Volume {
+    /// Build a 3D volume from sorted DICOM slices.
+    ///
+    /// Each slice is calibrated to modality units (rescale slope/intercept or
+    /// Modality LUT). Pixel spacing and slice thickness are extracted from DICOM
+    /// metadata when available.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn load(dcm_files: &[PathBuf]) -> Result<Self> {
+        let Some(first_path) = dcm_files.first() else {
+            anyhow::bail!("No slices to build a volume from");
+        };
+
+        // Read metadata from the first file to establish dimensions
+        let first_obj = open_file(first_path).with_context(|| {
+            format!("Failed to open first DICOM file: {}", first_path.display())
+        })?;
+
+        let rows = first_obj
+            .element(tags::ROWS)
+            .ok()
+            .and_then(|e| e.to_int::<u32>().ok())
+            .unwrap_or(0) as usize;
+        let cols = first_obj
+            .element(tags::COLUMNS)
+            .ok()
+            .and_then(|e| e.to_int::<u32>().ok())
+            .unwrap_or(0) as usize;
+
+        if rows == 0 || cols == 0 {
+            anyhow::bail!("Invalid image dimensions: {cols}x{rows}");
+        }
+
+        // Rows and columns may be spaced differently (e.g. tomosynthesis)
+        let [spacing_y, spacing_x] = groups::pixel_spacing(&first_obj, 0)
+            .map_or([DEFAULT_PIXEL_SPACING; 2], |spacing| {
+                spacing.map(|v| v as f32)
+            });
+
+        // Compute Z spacing from first two slice positions, or fall back to SliceThickness
+        let spacing_z = compute_slice_spacing(dcm_files, &first_obj).unwrap_or_else(|| {
+            first_obj
+                .element(tags::SLICE_THICKNESS)
+                .ok()
+                .and_then(|e| e.to_str().ok())
+                .and_then(|s| s.trim().parse::<f32>().ok())
+                .unwrap_or(DEFAULT_SLICE_THICKNESS)
+        });
+
+        let origin = groups::image_position(&first_obj, 0).unwrap_or_default();
+
+        let num_slices = pipeline::count_frames(dcm_files);
+        let slice_size = cols * rows;
+        let mut values = Vec::with_capacity(slice_size * num_slices);
+
+        let mut ahead = ReadAhead::start(dcm_files);
+        let mut z = 0;
+        for dcm_path in dcm_files {
+            let obj = ahead.open(dcm_path)?;
+            for number in 0..pipeline::number_of_frames(&obj) {
+                let frame = pipeline::decode_opened(&obj, dcm_path, number)?.into_mono();
+
+                // Ensure consistent dimensions
+                if frame.width as usize != cols || frame.height as usize != rows {
+                    anyhow::bail!(
+                        "Inconsistent slice dimensions: expected {cols}x{rows}, got {}x{} in {}",
+                        frame.width,
+                        frame.height,
+                        dcm_path.display()
+                    );
+                }
+
+                // Pack into the flat volume array
+                // mcubes indexes as: values[x + y * cols + z * cols * rows]
+                // (X varies fastest, Z varies slowest); frames are already row-major
+                values.extend_from_slice(&frame.values);
+                z += 1;
+
+                println!(
+                    "  {}",
+                    t!(
+                        "volume-loaded-slice",
+                        index = z,
+                        total = num_slices,
+                        file = display_name(dcm_path)
+                    )
+                );
+            }
+        }
+
+        Ok(Self {
+            values,
+            cols,
+            rows,
+            slices: z,
+            spacing_x,
+            spacing_y,
+            spacing_z,
+            origin,
+        })
+    }
+
+    /// Voxel spacing along X, Y, Z in mm.
+    pub fn spacing(&self) -> [f64; 3] {
+        [
+            f64::from(self.spacing_x),
+            f64::from(self.spacing_y),
+            f64::from(self.spacing_z),
+        ]
+    }
+
+    /// Patient position (mm) of the voxel at `(x, y, z)`.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn position(&self, x: usize, y: usize, z: usize) -> [f64; 3] {
+        let spacing = self.spacing();
+        [
+            (x as f64).mul_add(spacing[0], self.origin[0]),
+            (y as f64).mul_add(spacing[1], self.origin[1]),
+            (z as f64).mul_add(spacing[2], self.origin[2]),
+        ]
+    }
+
+    /// Patient position (mm) of the volume center.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn center(&self) -> [f64; 3] {
+        let spacing = self.spacing();
+        let extent = [self.cols, self.rows, self.slices];
+        std::array::from_fn(|i| {
+            (extent[i].saturating_sub(1) as f64 / 2.0).mul_add(spacing[i], self.origin[i])
+        })
+    }
+
+    /// Value range `(min, max)` of the voxels.
+    pub fn value_range(&self) -> (f32, f32) {
+        self.values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+    }
+
+    /// Sub-volume of the voxels in `min..max` along X, Y, Z.
+    ///
+    /// `max` is clamped to the volume; the origin moves to the first kept
+    /// voxel. Returns `None` when nothing is left.
+    pub fn crop(&self, min: [usize; 3], max: [usize; 3]) -> Option<Self> {
+        let extent = [self.cols, self.rows, self.slices];
+        let max: [usize; 3] = std::array::from_fn(|i| max[i].min(extent[i]));
+        if (0..3).any(|i| min[i] >= max[i]) {
+            return None;
+        }
+
+        let [cols, rows, slices] = std::array::from_fn(|i| max[i] - min[i]);
+        let mut values = Vec::with_capacity(cols * rows * slices);
+        for z in min[2]..max[2] {
+            for y in min[1]..max[1] {
+                let start = min[0] + y * self.cols + z * self.cols * self.rows;
+                values.extend_from_slice(&self.values[start..start + cols]);
+            }
+        }
+        Some(Self {
+            values,
+            cols,
+            rows,
+            slices,
+            spacing_x: self.spacing_x,
+            spacing_y: self.spacing_y,
+            spacing_z: self.spacing_z,
+            origin: self.position(min[0], min[1], min[2]),
+        })
+    }
+
+    /// Trilinear interpolation at a patient position (mm).
+    ///
+    /// Returns `None` outside the volume.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn sample(&self, point: [f64; 3]) -> Option<f32> {
+        let spacing = self.spacing();
+        let extent = [self.cols, self.rows, self.slices];
+        let mut base = [0_usize; 3];
+        let mut frac = [0.0_f64; 3];
+        for i in 0..3 {
+            let f = (point[i] - self.origin[i]) / spacing[i];
+            let last = extent[i].saturating_sub(1) as f64;
+            if !(0.0..=last).contains(&f) {
+                return None;
+            }
+            // Keep one neighbour to the right so the 2x2x2 cell stays inside
+            let cell = f.floor().min((last - 1.0).max(0.0));
+            base[i] = cell as usize;
+            frac[i] = f - cell;
+        }
+
+        let at = |dx: usize, dy: usize, dz: usize| -> f64 {
+            let x = (base[0] + dx).min(self.cols - 1);
+            let y = (base[1] + dy).min(self.rows - 1);
+            let z = (base[2] + dz).min(self.slices - 1);
+            f64::from(self.values[x + y * self.cols + z * self.cols * self.rows])
+        };
+        let lerp = |a: f64, b: f64, t: f64| (b - a).mul_add(t, a);
+
+        let c00 = lerp(at(0, 0, 0), at(1, 0, 0), frac[0]);
+        let c10 = lerp(at(0, 1, 0), at(1, 1, 0), frac[0]);
+        let c01 = lerp(at(0, 0, 1), at(1, 0, 1), frac[0]);
+        let c11 = lerp(at(0, 1, 1), at(1, 1, 1), frac[0]);
+        let c0 = lerp(c00, c10, frac[1]);
+        let c1 = lerp(c01, c11, frac[1]);
+        Some(lerp(c0, c1, frac[2]) as f32)
+    }
+
+    /// Value of the voxel nearest to a patient position (mm).
+    ///
+    /// Returns `None` outside the volume, which reaches half a voxel past
+    /// the outer voxel centers.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn nearest(&self, point: [f64; 3]) -> Option<f32> {
+        let spacing = self.spacing();
+        let extent = [self.cols, self.rows, self.slices];
+        let mut index = [0_usize; 3];
+        for i in 0..3 {
+            let f = ((point[i] - self.origin[i]) / spacing[i]).round();
+            if !(0.0..extent[i] as f64).contains(&f) {
+                return None;
+            }
+            index[i] = f as usize;
+        }
+        let [x, y, z] = index;
+        Some(self.values[x + y * self.cols + z * self.cols * self.rows])
+    }
+}
+
+/// Load a folder of slices as a position-sorted volume.
+///
+/// Returns the sorted files alongside the volume so callers can name outputs
+/// after their source slices. Fewer than `min_slices` files is bad input.
+pub fn load_series(
+    folder: &Path,
+    min_slices: usize,
+    follow_symlinks: bool,
+) -> Result<(Vec<PathBuf>, Volume)> {
+    validate_input_folder(folder)?;
+    let files = list_dcm_files(folder, follow_symlinks)?;
+    // A multi-frame object holds a slice per frame
+    let slices = pipeline::count_frames(&files);
+    if slices < min_slices {
+        anyhow::bail!(BadInput(format!(
+            "Need at least {min_slices} slices, found {slices} in {}",
+            folder.display()
+        )));
+    }
+    let files = sort_files_by_position(&files);
+    let volume = Volume::load(&files)
+        .with_context(|| format!("Failed to load series: {}", folder.display()))?;
+    Ok((files, volume))
+}
+
+/// Compute the Z spacing between slices from `ImagePositionPatient` tags:
+/// of the first two frames of a multi-frame `first`, which may also record
+/// it in its Pixel Measures, else of the first two files.
+#[allow(clippy::cast_possible_truncation)]
+fn compute_slice_spacing(dcm_files: &[PathBuf], first: &InMemDicomObject) -> Option<f32> {
+    let spacing = if pipeline::number_of_frames(first) > 1 {
+        let distance = groups::image_position(first, 0)
+            .zip(groups::image_position(first, 1))
+            .map(|(a, b)| (0..3).map(|i| (b[i] - a[i]).powi(2)).sum::<f64>().sqrt());
+        distance
+            .filter(|d| *d > 0.0)
+            .or_else(|| groups::slice_spacing(first, 0))?
+    } else {
+        if dcm_files.len() < 2 {
+            return None;
+        }
+        let z0 = InstanceMeta::read(&dcm_files[0]).position?[2];
+        let z1 = InstanceMeta::read(&dcm_files[1]).position?[2];
+        (z1 - z0).abs()
+    };
+
+    if spacing > 0.0 {
+        Some(spacing as f32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x3x3 volume whose value is `x + 10 y + 100 z`, 2 mm spacing.
+    fn ramp() -> Volume {
+        let mut values = Vec::new();
+        for z in 0..3_u16 {
+            for y in 0..3_u16 {
+                for x in 0..3_u16 {
+                    values.push(f32::from(x + 10 * y + 100 * z));
+                }
+            }
+        }
+        Volume {
+            values,
+            cols: 3,
+            rows: 3,
+            slices: 3,
+            spacing_x: 2.0,
+            spacing_y: 2.0,
+            spacing_z: 2.0,
+            origin: [-2.0, 0.0, 10.0],
+        }
+    }
+
+    #[test]
+    fn sample_hits_voxel_centers() {
+        let volume = ramp();
+        let point = volume.position(1, 2, 1);
+        assert_eq!(volume.sample(point), Some(121.0));
+    }
+
+    #[test]
+    fn sample_interpolates_between_voxels() {
+        let volume = ramp();
+        // Halfway between x=0 and x=1, y=1, z=2
+        let value = volume.sample([-1.0, 2.0, 14.0]).unwrap();
+        assert!((value - 210.5).abs() < 1e-4, "got {value}");
+    }
+
+    #[test]
+    fn sample_accepts_the_far_edge() {
+        let volume = ramp();
+        assert_eq!(volume.sample(volume.position(2, 2, 2)), Some(222.0));
+    }
+
+    #[test]
+    fn sample_outside_is_none() {
+        let volume = ramp();
+        assert_eq!(volume.sample([-2.5, 0.0, 10.0]), None);
+        assert_eq!(volume.sample([0.0, 0.0, 15.0]), None);
+    }
+
+    #[test]
+    fn nearest_rounds_to_a_voxel() {
+        let volume = ramp();
+        assert_eq!(volume.nearest([-1.1, 0.9, 12.9]), Some(100.0));
+        assert_eq!(volume.nearest([2.9, 4.9, 10.0]), Some(22.0));
+        assert_eq!(volume.nearest([-3.1, 0.0, 10.0]), None);
+    }
+
+    #[test]
+    fn center_is_middle_voxel() {
+        let volume = ramp();
+        assert_eq!(volume.sample(volume.center()), Some(111.0));
+    }
+
+    #[test]
+    fn crop_keeps_the_box_and_moves_origin() {
+        let volume = ramp();
+        let cropped = volume.crop([1, 0, 1], [3, 2, 9]).unwrap();
+        assert_eq!((cropped.cols, cropped.rows, cropped.slices), (2, 2, 2));
+        assert_eq!(
+            cropped.values,
+            [101.0, 102.0, 111.0, 112.0, 201.0, 202.0, 211.0, 212.0]
+        );
+        assert_eq!(cropped.sample(volume.position(1, 0, 1)), Some(101.0));
+        assert_eq!(cropped.sample(volume.position(2, 1, 2)), Some(212.0));
+    }
+
+    #[test]
+    fn empty_crop_is_none() {
+        let volume = ramp();
+        assert!(volume.crop([0, 0, 3], [3, 3, 5]).is_none());
+        assert!(volume.crop([2, 0, 0], [2, 3, 3]).is_none());
+    }
+
+    #[test]
+    fn load_rejects_empty_series() {
+        assert!(Volume::load(&[]).is_err());
+    }
+
+    #[test]
+    fn missing_folder_is_bad_input() {
+        let err = load_series(Path::new("/nonexistent/series"), 1, false).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+    }
+
+    #[test]
+    fn too_few_slices_is_bad_input() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.dcm"), b"").unwrap();
+        let err = load_series(dir.path(), 3, false).unwrap_err();
+        assert!(err.downcast_ref::<BadInput>().is_some());
+        assert!(err.to_string().contains("at least 3"), "{err}");
+    }
+}
@@ -0,0 +1,333 @@
+//! Segmentation label maps (`stl --labels`).
+//!
+//! Reads NIfTI-1 and NRRD label volumes and resamples them onto a DICOM
+//! series by patient position (nearest neighbour), so a segmentation made in
+//! 3D Slicer, MONAI, or ITK-SNAP lines up with the series even when its voxel
+//! order, orientation, or grid differs.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+
+use super::{Volume, nifti, nrrd};
+use crate::outcome::BadInput;
+
+/// A label volume with its placement in patient space.
+#[derive(Debug, Clone)]
+pub struct LabelMap {
+    /// Label of each voxel, packed X-fastest.
+    pub(super) values: Vec<f32>,
+    /// Voxels along each axis.
+    pub(super) dims: [usize; 3],
+    /// Voxel index to LPS patient position (mm), as the rows of `[M | t]`.
+    pub(super) affine: [[f64; 4]; 3],
+    /// Names and colors stored in the file (3D Slicer `.seg.nrrd`).
+    pub segments: Vec<Segment>,
+}
+
+/// Metadata of one label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Voxel value of the segment.
+    pub label: i32,
+    /// Segment name.
+    pub name: String,
+    /// Display color, when the file has one.
+    pub color: Option<[u8; 3]>,
+}
+
+impl LabelMap {
+    /// Load a `.nii`, `.nii.gz`, or `.nrrd` label map.
+    pub fn load(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let has_suffix = |suffixes: &[&str]| suffixes.iter().any(|s| name.ends_with(s));
+        let read = if has_suffix(&[".nrrd"]) {
+            nrrd::read
+        } else if has_suffix(&[".nii", ".nii.gz"]) {
+            nifti::read
+        } else {
+            anyhow::bail!(BadInput(format!(
+                "Unsupported label map {}; expected .nii, .nii.gz, or .nrrd",
+                path.display()
+            )));
+        };
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read label map: {}", path.display()))?;
+        read(&data).with_context(|| format!("Failed to read label map: {}", path.display()))
+    }
+
+    /// Segment metadata of a label, if the file has any.
+    pub fn segment(&self, label: i32) -> Option<&Segment> {
+        self.segments.iter().find(|s| s.label == label)
+    }
+
+    /// Labels at the voxel centers of `volume`, as a volume with the series'
+    /// geometry. Voxels outside the label map get label 0.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn resample(&self, volume: &Volume) -> Result<Volume> {
+        let inverse = invert(&self.affine)
+            .context("Label map has a degenerate orientation (zero-size voxels)")?;
+        let [cols, rows, _] = self.dims;
+
+        let mut values = Vec::with_capacity(volume.values.len());
+        for z in 0..volume.slices {
+            for y in 0..volume.rows {
+                for x in 0..volume.cols {
+                    let point = volume.position(x, y, z);
+                    let index: [f64; 3] = std::array::from_fn(|r| {
+                        (0..3)
+                            .map(|c| inverse[r][c] * (point[c] - self.affine[c][3]))
+                            .sum::<f64>()
+                            .round()
+                    });
+                    let inside = index
+                        .iter()
+                        .zip(self.dims)
+                        .all(|(&i, len)| (0.0..len as f64).contains(&i));
+                    values.push(if inside {
+                        let [i, j, k] = index.map(|i| i as usize);
+                        self.values[i + j * cols + k * cols * rows].round()
+                    } else {
+                        0.0
+                    });
+                }
+            }
+        }
+
+        Ok(Volume {
+            values,
+            cols: volume.cols,
+            rows: volume.rows,
+            slices: volume.slices,
+            spacing_x: volume.spacing_x,
+            spacing_y: volume.spacing_y,
+            spacing_z: volume.spacing_z,
+            origin: volume.origin,
+        })
+    }
+}
+
+/// Distinct non-zero labels of a resampled volume, in increasing order.
+#[allow(clippy::cast_possible_truncation)]
+pub fn labels_present(values: &[f32]) -> BTreeSet<i32> {
+    values
+        .iter()
+        .filter(|v| v.abs() >= 0.5)
+        .map(|&v| v.round() as i32)
+        .collect()
+}
+
+/// Sample type of a label file's voxel data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Sample {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+}
+
+impl Sample {
+    /// Bytes per sample.
+    const fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Decode the first `count` samples of `data`.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub(super) fn decode(self, data: &[u8], count: usize, big_endian: bool) -> Result<Vec<f32>> {
+        let size = self.size();
+        let needed = count.saturating_mul(size);
+        if data.len() < needed {
+            anyhow::bail!(
+                "Voxel data is truncated: expected {needed} bytes, got {}",
+                data.len()
+            );
+        }
+        let decoded = data[..needed].chunks_exact(size).map(|chunk| {
+            let mut raw = [0u8; 8];
+            raw[..size].copy_from_slice(chunk);
+            if big_endian {
+                raw[..size].reverse();
+            }
+            let [b0, b1, b2, b3, ..] = raw;
+            match self {
+                Self::U8 => f32::from(b0),
+                Self::I8 => f32::from(i8::from_le_bytes([b0])),
+                Self::U16 => f32::from(u16::from_le_bytes([b0, b1])),
+                Self::I16 => f32::from(i16::from_le_bytes([b0, b1])),
+                Self::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f32,
+                Self::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f32,
+                Self::F32 => f32::from_le_bytes([b0, b1, b2, b3]),
+                Self::F64 => f64::from_le_bytes(raw) as f32,
+            }
+        });
+        Ok(decoded.collect())
+    }
+}
+
+/// Decompress gzip data (one or more members).
+pub(super) fn gunzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    MultiGzDecoder::new(data)
+        .read_to_end(&mut out)
+        .context("Failed to decompress gzip data")?;
+    Ok(out)
+}
+
+/// Flip an affine from RAS (NIfTI-1, some NRRD) to LPS patient coordinates.
+pub(super) fn ras_to_lps(mut affine: [[f64; 4]; 3]) -> [[f64; 4]; 3] {
+    for row in &mut affine[..2] {
+        for value in row {
+            *value = -*value;
+        }
+    }
+    affine
+}
+
+/// Inverse of the 3x3 part of an affine; `None` when it is singular.
+fn invert(affine: &[[f64; 4]; 3]) -> Option<[[f64; 3]; 3]> {
+    let m = |r: usize, c: usize| affine[r][c];
+    // Cofactor of (r, c), with the sign folded in by cyclic indices
+    let cofactor = |r: usize, c: usize| {
+        let (r1, r2, c1, c2) = ((r + 1) % 3, (r + 2) % 3, (c + 1) % 3, (c + 2) % 3);
+        m(r1, c1).mul_add(m(r2, c2), -m(r1, c2) * m(r2, c1))
+    };
+    let det: f64 = (0..3).map(|c| m(0, c) * cofactor(0, c)).sum();
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    Some(std::array::from_fn(|r| {
+        std::array::from_fn(|c| cofactor(c, r) / det)
+    }))
+}
+
+/// Assert that two affines match to rounding error.
+#[cfg(test)]
+pub(super) fn assert_affine(actual: [[f64; 4]; 3], expected: [[f64; 4]; 3]) {
+    let close = actual
+        .iter()
+        .flatten()
+        .zip(expected.iter().flatten())
+        .all(|(a, b)| (a - b).abs() < 1e-9);
+    assert!(close, "{actual:?} != {expected:?}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2x2x2 map: label = x + 2y + 4z, 1 mm voxels at the origin.
+    fn counting(affine: [[f64; 4]; 3]) -> LabelMap {
+        LabelMap {
+            values: (0..8u8).map(f32::from).collect(),
+            dims: [2; 3],
+            affine,
+            segments: Vec::new(),
+        }
+    }
+
+    fn series(origin: [f64; 3]) -> Volume {
+        Volume {
+            values: vec![0.0; 8],
+            cols: 2,
+            rows: 2,
+            slices: 2,
+            spacing_x: 1.0,
+            spacing_y: 1.0,
+            spacing_z: 1.0,
+            origin,
+        }
+    }
+
+    const IDENTITY: [[f64; 4]; 3] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ];
+
+    #[test]
+    fn aligned_map_copies_labels() {
+        let resampled = counting(IDENTITY).resample(&series([0.0; 3])).unwrap();
+        assert_eq!(
+            resampled.values,
+            (0..8u8).map(f32::from).collect::<Vec<_>>()
+        );
+        assert_eq!(labels_present(&resampled.values), (1..8).collect());
+    }
+
+    #[test]
+    fn flipped_axes_are_followed_by_position() {
+        // Map stored with X reversed: voxel 0 sits at x = 1 mm
+        let affine = [
+            [-1.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+        ];
+        let resampled = counting(affine).resample(&series([0.0; 3])).unwrap();
+        assert_eq!(resampled.values[..2], [1.0, 0.0]);
+        assert_eq!(resampled.values[6..], [7.0, 6.0]);
+    }
+
+    #[test]
+    fn voxels_outside_the_map_are_background() {
+        let resampled = counting(IDENTITY)
+            .resample(&series([1.0, 0.0, 0.0]))
+            .unwrap();
+        // Only x = 0 of the series (x = 1 mm) falls inside the map
+        assert_eq!(resampled.values[..2], [1.0, 0.0]);
+        assert_eq!(labels_present(&resampled.values), [1, 3, 5, 7].into());
+    }
+
+    #[test]
+    fn decodes_sample_types_and_byte_orders() {
+        let data = [0x01, 0x02, 0xFF, 0xFE];
+        assert_eq!(
+            Sample::U16.decode(&data, 2, false).unwrap(),
+            [513.0, 65_279.0]
+        );
+        assert_eq!(Sample::I16.decode(&data, 2, true).unwrap(), [258.0, -2.0]);
+        assert_eq!(
+            Sample::I8.decode(&data, 4, false).unwrap(),
+            [1.0, 2.0, -1.0, -2.0]
+        );
+        assert!(Sample::F32.decode(&data, 2, false).is_err());
+    }
+
+    #[test]
+    fn singular_affine_has_no_inverse() {
+        let mut affine = IDENTITY;
+        affine[2][2] = 0.0;
+        assert!(invert(&affine).is_none());
+
+        let scaled = [
+            [0.0, 2.0, 0.0, 5.0],
+            [-0.5, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0, 0.0],
+        ];
+        let inverse = invert(&scaled).unwrap();
+        assert_eq!(
+            inverse,
+            [[0.0, -2.0, 0.0], [0.5, 0.0, 0.0], [0.0, 0.0, 0.25]]
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Minimal NIfTI-1 reader for label maps, and writer for float volumes.
+//!
+//! Handles single-file images (`.nii`, optionally gzipped) with integer or
+//! float samples. The voxel-to-world transform comes from the sform, then
+//! the qform, then plain voxel sizes, as the NIfTI-1 standard orders them;
+//! its world coordinates are RAS and are flipped to DICOM's LPS. Written
+//! files carry an sform only, and `float` samples.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use super::Volume;
+use super::labels::{LabelMap, Sample, gunzip, ras_to_lps};
+
+/// `sizeof_hdr` of NIfTI-1; the first field of every header.
+const HEADER_SIZE: i32 = 348;
+
+/// Header plus the 4-byte extension flag that precedes the voxel data.
+const MIN_OFFSET: usize = 352;
+
+/// Write a volume as a single-file NIfTI-1 image, gzipped when `path` ends
+/// in `.gz`.
+pub fn write_nifti(volume: &Volume, path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create NIfTI file: {}", path.display()))?;
+    let gzip = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gz"));
+    let mut out: Box<dyn Write> = if gzip {
+        Box::new(GzEncoder::new(BufWriter::new(file), Compression::default()))
+    } else {
+        Box::new(BufWriter::new(file))
+    };
+    out.write_all(&header(volume))
+        .and_then(|()| {
+            volume
+                .values
+                .iter()
+                .try_for_each(|v| out.write_all(&v.to_le_bytes()))
+        })
+        .and_then(|()| out.flush())
+        .with_context(|| format!("Failed to write NIfTI data: {}", path.display()))
+}
+
+/// Little-endian header of a `float` volume, with the empty extension flag.
+#[allow(clippy::cast_possible_truncation)]
+fn header(volume: &Volume) -> Vec<u8> {
+    let mut data = vec![0u8; MIN_OFFSET];
+    let mut put = |at: usize, bytes: &[u8]| data[at..at + bytes.len()].copy_from_slice(bytes);
+    put(0, &HEADER_SIZE.to_le_bytes());
+    let dims = [3, volume.cols, volume.rows, volume.slices, 1, 1, 1, 1];
+    for (i, dim) in dims.into_iter().enumerate() {
+        let dim = i16::try_from(dim).unwrap_or(i16::MAX);
+        put(40 + 2 * i, &dim.to_le_bytes());
+    }
+    // datatype FLOAT32, bitpix 32
+    put(70, &16i16.to_le_bytes());
+    put(72, &32i16.to_le_bytes());
+    let spacing = volume.spacing();
+    for (i, pixdim) in [1.0, spacing[0], spacing[1], spacing[2]]
+        .into_iter()
+        .enumerate()
+    {
+        put(76 + 4 * i, &(pixdim as f32).to_le_bytes());
+    }
+    #[allow(clippy::cast_precision_loss)]
+    put(108, &(MIN_OFFSET as f32).to_le_bytes());
+    // xyzt_units: mm
+    data[123] = 2;
+    // sform_code: scanner coordinates, axis-aligned rows in RAS
+    data[254..256].copy_from_slice(&1i16.to_le_bytes());
+    let lps: [[f64; 4]; 3] = std::array::from_fn(|r| {
+        std::array::from_fn(|c| match c {
+            3 => volume.origin[r],
+            c if c == r => spacing[r],
+            _ => 0.0,
+        })
+    });
+    for (r, row) in ras_to_lps(lps).iter().enumerate() {
+        for (c, value) in row.iter().enumerate() {
+            let at = 280 + 16 * r + 4 * c;
+            data[at..at + 4].copy_from_slice(&(*value as f32).to_le_bytes());
+        }
+    }
+    data[344..348].copy_from_slice(b"n+1\0");
+    data
+}
+
+/// Read a NIfTI-1 file, gzipped or not.
+pub(super) fn read(data: &[u8]) -> Result<LabelMap> {
+    if data.starts_with(&[0x1F, 0x8B]) {
+        return read(&gunzip(data)?);
+    }
+    if data.len() < MIN_OFFSET {
+        anyhow::bail!("File is too short for a NIfTI-1 header");
+    }
+    let first = [data[0], data[1], data[2], data[3]];
+    let big_endian = if i32::from_le_bytes(first) == HEADER_SIZE {
+        false
+    } else if i32::from_be_bytes(first) == HEADER_SIZE {
+        true
+    } else {
+        anyhow::bail!("Not a NIfTI-1 file (NIfTI-2 is not supported)");
+    };
+    let header = Header { data, big_endian };
+    if &data[344..347] != b"n+1" {
+        anyhow::bail!("Only single-file NIfTI-1 images are supported, not .hdr/.img pairs");
+    }
+
+    let ndim = usize::try_from(header.i16(40)).unwrap_or(0);
+    let dim: Vec<usize> = (1..=ndim.min(7))
+        .map(|i| usize::try_from(header.i16(40 + 2 * i)).unwrap_or(0))
+        .collect();
+    let (dims, rest) = dim.split_at(dim.len().min(3));
+    let Ok(dims) = <[usize; 3]>::try_from(dims) else {
+        anyhow::bail!("Label map must be 3D, got {ndim} dimension(s)");
+    };
+    if dims.contains(&0) || rest.iter().any(|&d| d > 1) {
+        anyhow::bail!("Label map must be one 3D volume, got dimensions {dim:?}");
+    }
+
+    let sample = match header.i16(70) {
+        2 => Sample::U8,
+        4 => Sample::I16,
+        8 => Sample::I32,
+        16 => Sample::F32,
+        64 => Sample::F64,
+        256 => Sample::I8,
+        512 => Sample::U16,
+        768 => Sample::U32,
+        other => anyhow::bail!("Unsupported NIfTI datatype {other}"),
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let offset = (header.f32(108).max(0.0) as usize).max(MIN_OFFSET);
+    let voxels = data
+        .get(offset..)
+        .context("Voxel data starts past the end of the file")?;
+    let mut values = sample.decode(voxels, dims.iter().product(), big_endian)?;
+
+    let (slope, intercept) = (header.f32(112), header.f32(116));
+    if slope.is_normal() {
+        for value in &mut values {
+            *value = value.mul_add(slope, intercept);
+        }
+    }
+
+    Ok(LabelMap {
+        values,
+        dims,
+        affine: ras_to_lps(header.affine()),
+        segments: Vec::new(),
+    })
+}
+
+/// Field access on the 348-byte header.
+struct Header<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Header<'_> {
+    fn bytes<const N: usize>(&self, at: usize) -> [u8; N] {
+        let mut bytes: [u8; N] = std::array::from_fn(|i| self.data[at + i]);
+        if self.big_endian {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    fn i16(&self, at: usize) -> i16 {
+        i16::from_le_bytes(self.bytes(at))
+    }
+
+    fn f32(&self, at: usize) -> f32 {
+        f32::from_le_bytes(self.bytes(at))
+    }
+
+    fn f64(&self, at: usize) -> f64 {
+        f64::from(self.f32(at))
+    }
+
+    /// Voxel index to RAS world position (mm), as the rows of `[M | t]`.
+    fn affine(&self) -> [[f64; 4]; 3] {
+        let pixdim: [f64; 4] = std::array::from_fn(|i| self.f64(76 + 4 * i));
+
+        // sform_code: rows of the matrix are stored directly
+        if self.i16(254) > 0 {
+            return std::array::from_fn(|r| {
+                std::array::from_fn(|c| self.f64(280 + 16 * r + 4 * c))
+            });
+        }
+
+        // qform_code: rotation quaternion (b, c, d), voxel sizes, offset
+        if self.i16(252) > 0 {
+            let [b, c, d] = [256, 260, 264].map(|at| self.f64(at));
+            let a = (1.0 - b.mul_add(b, c.mul_add(c, d * d))).max(0.0).sqrt();
+            let [aa, bb, cc, dd] = [a * a, b * b, c * c, d * d];
+            let rotation = [
+                [
+                    aa + bb - cc - dd,
+                    2.0 * b.mul_add(c, -a * d),
+                    2.0 * b.mul_add(d, a * c),
+                ],
+                [
+                    2.0 * b.mul_add(c, a * d),
+                    aa + cc - bb - dd,
+                    2.0 * c.mul_add(d, -a * b),
+                ],
+                [
+                    2.0 * b.mul_add(d, -a * c),
+                    2.0 * c.mul_add(d, a * b),
+                    aa + dd - bb - cc,
+                ],
+            ];
+            // pixdim[0] is qfac: -1 flips the slice axis
+            let qfac = if pixdim[0] < 0.0 { -1.0 } else { 1.0 };
+            let scale = [pixdim[1], pixdim[2], pixdim[3] * qfac];
+            let offset = [268, 272, 276].map(|at| self.f64(at));
+            return std::array::from_fn(|r| {
+                std::array::from_fn(|c| {
+                    if c < 3 {
+                        rotation[r][c] * scale[c]
+                    } else {
+                        offset[r]
+                    }
+                })
+            });
+        }
+
+        // Neither: voxel sizes along the axes, first voxel at the origin
+        std::array::from_fn(|r| std::array::from_fn(|c| if c == r { pixdim[r + 1] } else { 0.0 }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume::labels::assert_affine;
+
+    /// Header for a `dims` uint8 image with the given geometry fields set.
+    fn header(dims: [i16; 3], fields: &[(usize, f32)], codes: (i16, i16)) -> Vec<u8> {
+        let mut data = vec![0u8; MIN_OFFSET];
+        data[..4].copy_from_slice(&HEADER_SIZE.to_le_bytes());
+        for (i, value) in [3, dims[0], dims[1], dims[2], 1].into_iter().enumerate() {
+            data[40 + 2 * i..42 + 2 * i].copy_from_slice(&value.to_le_bytes());
+        }
+        data[70..72].copy_from_slice(&2i16.to_le_bytes());
+        data[108..112].copy_from_slice(&352f32.to_le_bytes());
+        for &(at, value) in fields {
+            data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        data[252..254].copy_from_slice(&codes.0.to_le_bytes());
+        data[254..256].copy_from_slice(&codes.1.to_le_bytes());
+        data[344..348].copy_from_slice(b"n+1\0");
+        data
+    }
+
+    #[test]
+    fn reads_sform_image_as_lps() {
+        // 2 mm voxels, first voxel at RAS (10, 20, 30)
+        let sform = [
+            (280, 2.0),
+            (292, 10.0),
+            (300, 2.0),
+            (308, 20.0),
+            (320, 2.0),
+            (324, 30.0),
+        ];
+        let mut data = header([2, 1, 1], &sform, (0, 1));
+        data.extend([0, 5]);
+
+        let map = read(&data).unwrap();
+        assert_eq!(map.dims, [2, 1, 1]);
+        assert_eq!(map.values, [0.0, 5.0]);
+        assert_affine(
+            map.affine,
+            [
+                [-2.0, 0.0, 0.0, -10.0],
+                [0.0, -2.0, 0.0, -20.0],
+                [0.0, 0.0, 2.0, 30.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn qform_rotates_and_flips_slices() {
+        // 180° about Z (quaternion d = 1), qfac -1, 1.5 mm voxels
+        let qform = [
+            (76, -1.0),
+            (80, 1.5),
+            (84, 1.5),
+            (88, 1.5),
+            (264, 1.0),
+            (268, 4.0),
+        ];
+        let mut data = header([1, 1, 1], &qform, (1, 0));
+        data.push(3);
+
+        let map = read(&data).unwrap();
+        // RAS rotation diag(-1.5, -1.5, -1.5), flipped to LPS
+        assert_affine(
+            map.affine,
+            [
+                [1.5, 0.0, 0.0, -4.0],
+                [0.0, 1.5, 0.0, 0.0],
+                [0.0, 0.0, -1.5, 0.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn gzipped_and_big_endian_files_read() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        let mut data = header([1, 1, 2], &[(80, 1.0), (84, 1.0), (88, 1.0)], (0, 0));
+        data.extend([1, 2]);
+        // Swap every header field to big-endian
+        for range in [0..4, 108..112] {
+            data[range].reverse();
+        }
+        for at in (40..52).step_by(2).chain([70, 252, 254]) {
+            data.swap(at, at + 1);
+        }
+        for at in [80, 84, 88] {
+            data[at..at + 4].reverse();
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&data).unwrap();
+        let map = read(&encoder.finish().unwrap()).unwrap();
+        assert_eq!(map.values, [1.0, 2.0]);
+        assert_affine(
+            map.affine,
+            [
+                [-1.0, 0.0, 0.0, 0.0],
+                [0.0, -1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn written_volumes_read_back_in_place() {
+        let volume = Volume {
+            values: vec![0.5, 1.5, 2.5, 3.5],
+            cols: 2,
+            rows: 1,
+            slices: 2,
+            spacing_x: 2.0,
+            spacing_y: 3.0,
+            spacing_z: 2.5,
+            origin: [-10.0, 20.0, 30.0],
+        };
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["dose.nii", "dose.nii.gz"] {
+            let path = dir.path().join(name);
+            write_nifti(&volume, &path).unwrap();
+            let map = read(&std::fs::read(&path).unwrap()).unwrap();
+            assert_eq!(map.dims, [2, 1, 2]);
+            assert_eq!(map.values, volume.values);
+            assert_affine(
+                map.affine,
+                [
+                    [2.0, 0.0, 0.0, -10.0],
+                    [0.0, 3.0, 0.0, 20.0],
+                    [0.0, 0.0, 2.5, 30.0],
+                ],
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_4d_and_paired_files() {
+        let mut data = header([2, 2, 2], &[], (0, 0));
+        data[48..50].copy_from_slice(&3i16.to_le_bytes());
+        data[40..42].copy_from_slice(&4i16.to_le_bytes());
+        assert!(
+            read(&data)
+                .unwrap_err()
+                .to_string()
+                .contains("one 3D volume")
+        );
+
+        let mut data = header([1, 1, 1], &[], (0, 0));
+        data[344..348].copy_from_slice(b"ni1\0");
+        assert!(read(&data).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Minimal NRRD writer for float volumes, and reader for label maps.
+//!
+//! Writes an attached header plus raw little-endian `float` data, which 3D
+//! Slicer, ITK-SNAP and `pynrrd` all read. Reads attached-header 3D files
+//! with raw or gzip encoding, including the segment names and colors that
+//! 3D Slicer stores in `.seg.nrrd` files.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::Volume;
+use super::labels::{LabelMap, Sample, Segment, gunzip, ras_to_lps};
+
+/// Write a volume as an attached-header NRRD (`.nrrd`) file.
+pub fn write_nrrd(volume: &Volume, path: &Path) -> Result<()> {
+    let mut file = BufWriter::new(
+        File::create(path)
+            .with_context(|| format!("Failed to create NRRD file: {}", path.display()))?,
+    );
+    file.write_all(header(volume).as_bytes())
+        .and_then(|()| {
+            volume
+                .values
+                .iter()
+                .try_for_each(|v| file.write_all(&v.to_le_bytes()))
+        })
+        .and_then(|()| file.flush())
+        .with_context(|| format!("Failed to write NRRD data: {}", path.display()))
+}
+
+/// Build the NRRD header, including the blank line that ends it.
+fn header(volume: &Volume) -> String {
+    let [sx, sy, sz] = volume.spacing();
+    let [ox, oy, oz] = volume.origin;
+    format!(
+        "NRRD0004\n\
+         type: float\n\
+         dimension: 3\n\
+         space: left-posterior-superior\n\
+         sizes: {} {} {}\n\
+         space directions: ({sx},0,0) (0,{sy},0) (0,0,{sz})\n\
+         kinds: domain domain domain\n\
+         endian: little\n\
+         encoding: raw\n\
+         space origin: ({ox},{oy},{oz})\n\n",
+        volume.cols, volume.rows, volume.slices
+    )
+}
+
+/// Read an attached-header NRRD label map.
+pub(super) fn read(data: &[u8]) -> Result<LabelMap> {
+    let end = data
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .context("NRRD header has no end (blank line)")?;
+    let header = std::str::from_utf8(&data[..end]).context("NRRD header is not text")?;
+    let mut lines = header.lines();
+    if !lines.next().is_some_and(|magic| magic.starts_with("NRRD")) {
+        anyhow::bail!("Not a NRRD file");
+    }
+
+    // Fields are `name: value`; key/value pairs are `key:=value`
+    let (mut fields, mut pairs) = (HashMap::new(), HashMap::new());
+    for line in lines.filter(|l| !l.starts_with('#')) {
+        if let Some((key, value)) = line.split_once(":=") {
+            pairs.insert(key, value.trim());
+        } else if let Some((name, value)) = line.split_once(": ") {
+            fields.insert(name.trim().to_ascii_lowercase(), value.trim());
+        }
+    }
+    let field = |name: &str| fields.get(name).copied();
+
+    if field("data file").or_else(|| field("datafile")).is_some() {
+        anyhow::bail!("Detached NRRD data (.nhdr) is not supported; save as .nrrd");
+    }
+    if field("dimension") != Some("3") {
+        anyhow::bail!(
+            "Label map must be 3D, got dimension {}",
+            field("dimension").unwrap_or("?")
+        );
+    }
+    let sample = sample_type(field("type").unwrap_or_default())?;
+    let sizes: Vec<usize> = field("sizes")
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|s| s.parse().ok())
+        .collect();
+    let Ok(dims) = <[usize; 3]>::try_from(sizes.as_slice()) else {
+        anyhow::bail!("NRRD sizes must list 3 numbers");
+    };
+
+    let body = &data[end + 2..];
+    let body = match field("encoding").unwrap_or("raw") {
+        "raw" => body.to_vec(),
+        "gzip" | "gz" => gunzip(body)?,
+        other => anyhow::bail!("Unsupported NRRD encoding {other}; use raw or gzip"),
+    };
+    let big_endian = field("endian") == Some("big");
+    let values = sample.decode(&body, dims.iter().product(), big_endian)?;
+
+    Ok(LabelMap {
+        values,
+        dims,
+        affine: affine(
+            field("space"),
+            field("space directions"),
+            field("space origin"),
+            field("spacings"),
+        )?,
+        segments: segments(&pairs),
+    })
+}
+
+/// Sample type from a NRRD `type` field.
+fn sample_type(name: &str) -> Result<Sample> {
+    Ok(match name.trim() {
+        "uchar" | "unsigned char" | "uint8" | "uint8_t" => Sample::U8,
+        "signed char" | "int8" | "int8_t" => Sample::I8,
+        "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => Sample::U16,
+        "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+            Sample::I16
+        }
+        "uint" | "unsigned int" | "uint32" | "uint32_t" => Sample::U32,
+        "int" | "signed int" | "int32" | "int32_t" => Sample::I32,
+        "float" => Sample::F32,
+        "double" => Sample::F64,
+        other => anyhow::bail!("Unsupported NRRD type {other:?}"),
+    })
+}
+
+/// Voxel index to LPS position from the space fields, falling back to
+/// `spacings` (or 1 mm) along the axes when there are no directions.
+fn affine(
+    space: Option<&str>,
+    directions: Option<&str>,
+    origin: Option<&str>,
+    spacings: Option<&str>,
+) -> Result<[[f64; 4]; 3]> {
+    let origin = match origin {
+        Some(text) => vector(text).context("Malformed NRRD space origin")?,
+        None => [0.0; 3],
+    };
+    let axes: [[f64; 3]; 3] = if let Some(text) = directions {
+        let axes: Option<Vec<[f64; 3]>> = text
+            .split(')')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.strip_prefix('(').and_then(components))
+            .collect();
+        axes.and_then(|axes| axes.try_into().ok())
+            .context("Malformed NRRD space directions")?
+    } else {
+        let steps: Vec<f64> = spacings
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|s| s.parse().ok())
+            .collect();
+        std::array::from_fn(|axis| {
+            let step = steps.get(axis).copied().filter(|s: &f64| s.is_finite());
+            std::array::from_fn(|c| if c == axis { step.unwrap_or(1.0) } else { 0.0 })
+        })
+    };
+    // Each direction is the world step of one voxel axis: a matrix column
+    let affine = std::array::from_fn(|r| {
+        std::array::from_fn(|c| if c < 3 { axes[c][r] } else { origin[r] })
+    });
+
+    Ok(match space.map(str::to_ascii_lowercase).as_deref() {
+        Some("right-anterior-superior" | "ras") => ras_to_lps(affine),
+        Some("left-anterior-superior" | "las") => {
+            let mut affine = affine;
+            affine[1].iter_mut().for_each(|v| *v = -*v);
+            affine
+        }
+        _ => affine,
+    })
+}
+
+/// `(x,y,z)` vector of a space field.
+fn vector(text: &str) -> Option<[f64; 3]> {
+    components(text.trim().strip_prefix('(')?.strip_suffix(')')?)
+}
+
+/// `x,y,z` inside a vector's parentheses.
+fn components(inner: &str) -> Option<[f64; 3]> {
+    let values: Vec<f64> = inner
+        .split(',')
+        .map(|v| v.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+/// Segments of a 3D Slicer `.seg.nrrd` (`SegmentN_LabelValue`, `_Name`,
+/// `_Color` with 0–1 RGB).
+fn segments(pairs: &HashMap<&str, &str>) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = (0..)
+        .map_while(|n| {
+            let get = |key: &str| pairs.get(format!("Segment{n}_{key}").as_str()).copied();
+            get("Name").map(|name| (name, get("LabelValue"), get("Color")))
+        })
+        .filter_map(|(name, label, color)| {
+            Some(Segment {
+                label: label?.parse().ok()?,
+                name: name.to_string(),
+                color: color.and_then(parse_color),
+            })
+        })
+        .collect();
+    segments.sort_by_key(|s| s.label);
+    segments
+}
+
+/// `r g b` in 0–1 as 8-bit color.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let channels: Vec<f32> = text
+        .split_whitespace()
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    let channels: [f32; 3] = channels.try_into().ok()?;
+    Some(channels.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume::labels::assert_affine;
+
+    fn tiny() -> Volume {
+        Volume {
+            values: vec![0.0, 1.0, 2.0, 3.0],
+            cols: 2,
+            rows: 2,
+            slices: 1,
+            spacing_x: 0.5,
+            spacing_y: 0.5,
+            spacing_z: 3.0,
+            origin: [-10.0, 4.5, 0.0],
+        }
+    }
+
+    #[test]
+    fn header_describes_geometry() {
+        let header = header(&tiny());
+        assert!(header.starts_with("NRRD0004\n"));
+        assert!(header.contains("sizes: 2 2 1\n"));
+        assert!(header.contains("space directions: (0.5,0,0) (0,0.5,0) (0,0,3)\n"));
+        assert!(header.contains("space origin: (-10,4.5,0)\n"));
+        assert!(header.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn data_follows_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiny.nrrd");
+        let volume = tiny();
+        write_nrrd(&volume, &path).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let header_len = header(&volume).len();
+        assert_eq!(bytes.len(), header_len + 4 * 4);
+        assert_eq!(
+            &bytes[header_len + 4..header_len + 8],
+            &1.0_f32.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn written_volume_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiny.nrrd");
+        write_nrrd(&tiny(), &path).unwrap();
+
+        let map = read(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(map.dims, [2, 2, 1]);
+        assert_eq!(map.values, [0.0, 1.0, 2.0, 3.0]);
+        assert_affine(
+            map.affine,
+            [
+                [0.5, 0.0, 0.0, -10.0],
+                [0.0, 0.5, 0.0, 4.5],
+                [0.0, 0.0, 3.0, 0.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn reads_gzipped_slicer_segmentation() {
+        use flate2::{Compression, write::GzEncoder};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&[0, 1, 2, 2]).unwrap();
+        let mut data = b"NRRD0004\n\
+            # Complete NRRD file format specification at: ...\n\
+            type: unsigned char\n\
+            dimension: 3\n\
+            space: right-anterior-superior\n\
+            sizes: 2 1 2\n\
+            space directions: (0, -1, 0) (2,0,0) (0,0,1)\n\
+            encoding: gzip\n\
+            space origin: (5,6,7)\n\
+            Segment0_Color:=0.5 0.25 1\n\
+            Segment0_LabelValue:=2\n\
+            Segment0_Name:=Skull\n\
+            Segment1_LabelValue:=1\n\
+            Segment1_Name:=Brain\n\n"
+            .to_vec();
+        data.extend(encoder.finish().unwrap());
+
+        let map = read(&data).unwrap();
+        assert_eq!(map.values, [0.0, 1.0, 2.0, 2.0]);
+        // Direction vectors are columns; RAS X and Y flip to LPS
+        assert_affine(
+            map.affine,
+            [
+                [0.0, -2.0, 0.0, -5.0],
+                [1.0, 0.0, 0.0, -6.0],
+                [0.0, 0.0, 1.0, 7.0],
+            ],
+        );
+
+        let names: Vec<&str> = map.segments.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Brain", "Skull"]);
+        assert_eq!(map.segments[1].color, Some([128, 64, 255]));
+        assert_eq!(map.segments[0].color, None);
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        let header = |extra: &str| {
+            format!("NRRD0004\ntype: uchar\nsizes: 1 1 1\n{extra}\n\n\0").into_bytes()
+        };
+        assert!(read(&header("dimension: 3")).is_ok());
+        assert!(read(&header("dimension: 4")).is_err());
+        assert!(read(&header("dimension: 3\ndata file: seg.raw")).is_err());
+        assert!(read(&header("dimension: 3\nencoding: bzip2")).is_err());
+        assert!(read(b"P5\n1 1\n\n").is_err());
+    }
+}
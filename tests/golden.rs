@@ -0,0 +1,267 @@
+//! Golden-image tests for dcm-toolbox.
+//!
+//! Synthetic phantoms from `gen-test-data` are converted end to end and the
+//! results are held against references instead of log lines:
+//!
+//! - PNG slices must match `tests/golden/<name>.png` pixel for pixel
+//! - JPEG slices of the same render must stay within an SSIM bound of them
+//! - meshes must enclose the phantom's volume within a tolerance
+//! - output folders must have exactly the expected files
+//!
+//! After a deliberate change to the pixel pipeline, rewrite the references
+//! with `DCM_TOOLBOX_UPDATE_GOLDEN=1 cargo test --test golden` and review the
+//! new images before committing them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use image::GrayImage;
+use tempfile::TempDir;
+
+/// Grid of the phantoms: 32 x 32 pixels, 32 slices of 1 mm.
+const SIZE: &str = "32";
+const SLICES: usize = 32;
+
+/// Slice compared against the references, through the sphere's center.
+const MIDDLE: &str = "0016";
+
+/// Lowest SSIM accepted for a JPEG against its PNG reference.
+const MIN_JPEG_SSIM: f64 = 0.95;
+
+/// Helper to get the path to the test binary
+fn binary_path() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // Remove test binary name
+    path.pop(); // Remove deps
+    path.push("dcm-toolbox");
+    path
+}
+
+/// Run the CLI with English messages and expect success.
+fn run_ok(args: &[&str]) {
+    let output = Command::new(binary_path())
+        .args(args)
+        .env("DCM_TOOLBOX_LANG", "en")
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "{args:?} failed: {output:?}");
+}
+
+/// Write `phantoms` (comma-separated) into `dir/phantoms`.
+fn phantoms(dir: &Path, phantoms: &str) -> PathBuf {
+    let folder = dir.join("phantoms");
+    run_ok(&[
+        "gen-test-data",
+        "--out",
+        folder.to_str().unwrap(),
+        "--phantoms",
+        phantoms,
+        "--size",
+        SIZE,
+        "--slices",
+        &SLICES.to_string(),
+    ]);
+    folder
+}
+
+/// Convert `input` into `dir/<name>` with `format` and its options.
+fn convert(input: &Path, dir: &Path, name: &str, format: &[&str]) -> PathBuf {
+    let output = dir.join(name);
+    let mut args = vec![
+        "convert",
+        "--in",
+        input.to_str().unwrap(),
+        "--out",
+        output.to_str().unwrap(),
+    ];
+    args.extend(format);
+    run_ok(&args);
+    output
+}
+
+/// Files below `dir`, as sorted `/`-separated relative paths.
+fn layout(dir: &Path) -> Vec<String> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                walk(root, &path, files);
+            } else {
+                let relative = path.strip_prefix(root).unwrap();
+                let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+                files.push(parts.join("/"));
+            }
+        }
+    }
+    let mut files = Vec::new();
+    walk(dir, dir, &mut files);
+    files.sort();
+    files
+}
+
+/// Compare `actual` with the reference `tests/golden/<name>.png`, or
+/// replace the reference when `DCM_TOOLBOX_UPDATE_GOLDEN` is set.
+fn assert_golden(name: &str, actual: &GrayImage) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    if std::env::var_os("DCM_TOOLBOX_UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+    let expected = image::open(&path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "No reference {} ({e}); write it with DCM_TOOLBOX_UPDATE_GOLDEN=1",
+                path.display()
+            )
+        })
+        .into_luma8();
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{name}: size");
+    let differing = expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(a, b)| a != b)
+        .count();
+    assert_eq!(differing, 0, "{name}: {differing} pixels differ");
+}
+
+/// The reference `tests/golden/<name>.png`.
+fn golden(name: &str) -> GrayImage {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    image::open(&path).unwrap().into_luma8()
+}
+
+/// Mean structural similarity of two gray images over 8x8 windows.
+#[allow(clippy::cast_precision_loss)]
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    assert_eq!(a.dimensions(), b.dimensions());
+    let (width, height) = a.dimensions();
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in (0..=height.saturating_sub(8)).step_by(8) {
+        for left in (0..=width.saturating_sub(8)).step_by(8) {
+            let values = |image: &GrayImage| -> Vec<f64> {
+                (top..top + 8)
+                    .flat_map(|y| (left..left + 8).map(move |x| (x, y)))
+                    .map(|(x, y)| f64::from(image.get_pixel(x, y).0[0]))
+                    .collect()
+            };
+            let (x, y) = (values(a), values(b));
+            let n = x.len() as f64;
+            let (mean_x, mean_y) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+            let (mut var_x, mut var_y, mut cov) = (0.0, 0.0, 0.0);
+            for (x, y) in x.iter().zip(&y) {
+                var_x += (x - mean_x).powi(2) / n;
+                var_y += (y - mean_y).powi(2) / n;
+                cov += (x - mean_x) * (y - mean_y) / n;
+            }
+            total += ((2.0 * mean_x * mean_y + C1) * (2.0 * cov + C2))
+                / ((mean_x.powi(2) + mean_y.powi(2) + C1) * (var_x + var_y + C2));
+            windows += 1;
+        }
+    }
+    total / f64::from(windows)
+}
+
+/// Volume enclosed by a closed triangle mesh (mm³), by the divergence
+/// theorem.
+fn enclosed_volume(mesh: &stl_io::IndexedMesh) -> f64 {
+    mesh.faces
+        .iter()
+        .map(|face| {
+            let [a, b, c] = face.vertices.map(|i| mesh.vertices[i].0.map(f64::from));
+            let cross = [
+                b[1] * c[2] - b[2] * c[1],
+                b[2] * c[0] - b[0] * c[2],
+                b[0] * c[1] - b[1] * c[0],
+            ];
+            (a[0] * cross[0] + a[1] * cross[1] + a[2] * cross[2]) / 6.0
+        })
+        .sum::<f64>()
+        .abs()
+}
+
+#[test]
+fn png_slices_match_the_references() {
+    let dir = TempDir::new().unwrap();
+    let input = phantoms(dir.path(), "gradient,sphere");
+    let output = convert(
+        &input,
+        dir.path(),
+        "png",
+        &["jpeg", "--image-format", "png"],
+    );
+
+    for (series, name) in [("1", "gradient"), ("2", "sphere")] {
+        let slice = output.join(series).join(format!("{MIDDLE}.png"));
+        let image = image::open(&slice).unwrap().into_luma8();
+        assert_golden(name, &image);
+    }
+}
+
+#[test]
+fn jpeg_slices_stay_close_to_the_references() {
+    if std::env::var_os("DCM_TOOLBOX_UPDATE_GOLDEN").is_some() {
+        return;
+    }
+    let dir = TempDir::new().unwrap();
+    let input = phantoms(dir.path(), "gradient,sphere");
+    let output = convert(&input, dir.path(), "jpeg", &["jpeg"]);
+
+    for (series, name) in [("1", "gradient"), ("2", "sphere")] {
+        let slice = output.join(series).join(format!("{MIDDLE}.jpg"));
+        let image = image::open(&slice).unwrap().into_luma8();
+        let similarity = ssim(&golden(name), &image);
+        assert!(similarity >= MIN_JPEG_SSIM, "{name}: SSIM {similarity:.4}");
+    }
+}
+
+#[test]
+fn folder_layout_is_deterministic() {
+    let dir = TempDir::new().unwrap();
+    let input = phantoms(dir.path(), "gradient,sphere");
+    let first = convert(&input, dir.path(), "first", &["jpeg"]);
+    let second = convert(&input, dir.path(), "second", &["jpeg"]);
+
+    let expected: Vec<String> = ["1", "2"]
+        .iter()
+        .flat_map(|series| (1..=SLICES).map(move |i| format!("{series}/{i:04}.jpg")))
+        .collect();
+    assert_eq!(layout(&first), expected);
+    for file in &expected {
+        assert_eq!(
+            fs::read(first.join(file)).unwrap(),
+            fs::read(second.join(file)).unwrap(),
+            "{file} differs between runs"
+        );
+    }
+}
+
+#[test]
+fn sphere_mesh_encloses_the_phantom_volume() {
+    let dir = TempDir::new().unwrap();
+    let input = phantoms(dir.path(), "sphere");
+    let output = convert(&input, dir.path(), "stl", &["stl"]);
+
+    // The model and its preview
+    assert_eq!(layout(&output), ["1/1.png", "1/1.stl"]);
+    let mut file = fs::File::open(output.join("1/1.stl")).unwrap();
+    let mesh = stl_io::read_stl(&mut file).unwrap();
+
+    // The phantom's radius is a third of the grid (32 / 3 voxels of 1 mm)
+    let radius = 32.0 / 3.0;
+    let expected = 4.0 / 3.0 * std::f64::consts::PI * f64::powi(radius, 3);
+    let volume = enclosed_volume(&mesh);
+    let error = (volume - expected).abs() / expected;
+    assert!(
+        error < 0.05,
+        "mesh volume {volume:.0} mm³, sphere {expected:.0} mm³"
+    );
+}
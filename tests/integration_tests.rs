@@ -13,12 +13,14 @@
 //!
 //! The converter creates subfolders per series/group:
 //! - JPEG mode: `{output}/{series}/{0001.jpg, 0002.jpg, ...}`
+//! - Piping mode (`--out -`): encoded image bytes on stdout
 //! - Video mode: `{output}/{series}/{series}.mp4`
 //! - STL mode: `{output}/{series}/{series}.stl`
 
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
+use std::sync::OnceLock;
 
 use tempfile::TempDir;
 
@@ -31,9 +33,25 @@ fn binary_path() -> PathBuf {
     path
 }
 
-/// Helper to get the example folder path
+/// Helper to get the example folder path.
+///
+/// The optional `example/` folder of real scans when present; otherwise
+/// synthetic phantoms written once per test run by `gen-test-data`.
 fn example_folder() -> PathBuf {
-    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("example")
+    static GENERATED: OnceLock<PathBuf> = OnceLock::new();
+
+    let example = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("example");
+    if example.exists() {
+        return example;
+    }
+    GENERATED
+        .get_or_init(|| {
+            let folder = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("example");
+            let output = run_raw(&["gen-test-data", "--out", folder.to_str().unwrap()]);
+            assert!(output.status.success(), "gen-test-data failed: {output:?}");
+            folder
+        })
+        .clone()
 }
 
 /// Run a convert subcommand with shared options before the format subcommand.
@@ -41,6 +59,8 @@ fn example_folder() -> PathBuf {
 /// `format`: The format subcommand (`jpeg`, `video`, `stl`)
 /// `shared_args`: Shared opts like `--in`, `--out`, `--force`, `--split-by`
 /// `format_args`: Format-specific opts like `--fps`, `--iso-level`, `--smooth`
+///
+/// Messages are pinned to English so assertions do not depend on the locale.
 fn run_convert(format: &str, shared_args: &[&str], format_args: &[&str]) -> std::process::Output {
     let mut full_args = vec!["convert"];
     full_args.extend(shared_args);
@@ -48,14 +68,19 @@ fn run_convert(format: &str, shared_args: &[&str], format_args: &[&str]) -> std:
     full_args.extend(format_args);
     Command::new(binary_path())
         .args(&full_args)
+        .env("DCM_TOOLBOX_LANG", "en")
         .output()
         .expect("Failed to execute command")
 }
 
 /// Run an arbitrary CLI command (no prepended subcommand).
+///
+/// Like [`run_convert`], messages are pinned to English so assertions do not
+/// depend on the developer's locale.
 fn run_raw(args: &[&str]) -> std::process::Output {
     Command::new(binary_path())
         .args(args)
+        .env("DCM_TOOLBOX_LANG", "en")
         .output()
         .expect("Failed to execute command")
 }
@@ -113,12 +138,50 @@ mod cli_args {
     }
 
     #[test]
-    fn missing_output_arg_shows_error() {
-        let output = run_raw(&["convert", "--in", "./example", "jpeg"]);
+    fn missing_output_defaults_to_an_export_folder() {
+        let input = example_folder();
+        if !input.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let output = Command::new(binary_path())
+            .args(["convert", "--in", input.to_str().unwrap(), "jpeg"])
+            .current_dir(temp_dir.path())
+            .env("DCM_TOOLBOX_LANG", "en")
+            .output()
+            .expect("Failed to execute command");
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("No --out given"), "{stdout}");
+        let folders = get_subdirs(&temp_dir.path().join("export"));
+        assert_eq!(folders.len(), 1, "one folder named after the study");
+    }
+
+    #[test]
+    fn missing_output_with_stdin_is_rejected() {
+        let output = run_raw(&["convert", "--in", "-", "jpeg"]);
 
-        assert!(!output.status.success());
+        assert_eq!(output.status.code(), Some(4));
         let stderr = String::from_utf8_lossy(&output.stderr);
-        assert!(stderr.contains("--out"));
+        assert!(stderr.contains("--out is required"), "{stderr}");
+    }
+
+    #[test]
+    fn pick_is_rejected_where_there_are_no_series_to_choose() {
+        let temp_dir = TempDir::new().unwrap();
+        let out = temp_dir.path().join("out");
+        for (input, output, format) in [
+            ("-", "-", "jpeg"),
+            ("./example", out.to_str().unwrap(), "dicomweb"),
+        ] {
+            let output = run_raw(&["convert", "--in", input, "--out", output, "--pick", format]);
+
+            assert_eq!(output.status.code(), Some(4), "{format}: {output:?}");
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("--pick"), "{stderr}");
+        }
     }
 
     #[test]
@@ -137,379 +200,595 @@ mod cli_args {
     }
 
     #[test]
-    fn nonexistent_input_folder_fails() {
-        let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
-
-        let output = run_convert(
+    fn unknown_denoise_filter_is_rejected() {
+        let output = run_raw(&[
+            "convert",
+            "--in",
+            ".",
+            "--out",
+            ".",
+            "--denoise",
+            "gaussian",
             "jpeg",
-            &[
-                "--in",
-                "/nonexistent/folder/that/does/not/exist",
-                "--out",
-                output_path.to_str().unwrap(),
-            ],
-            &[],
+        ]);
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("nlm"),
+            "Should list valid filters: {stderr}"
         );
+    }
+
+    #[test]
+    fn export_patches_requires_annotations() {
+        let output = run_raw(&[
+            "convert",
+            "--in",
+            ".",
+            "--out",
+            ".",
+            "--export-patches",
+            "jpeg",
+        ]);
 
         assert!(!output.status.success());
         let stderr = String::from_utf8_lossy(&output.stderr);
         assert!(
-            stderr.contains("does not exist") || stderr.contains("Error"),
-            "Expected error about nonexistent folder: {stderr}"
+            stderr.contains("--annotations"),
+            "Should name the missing option: {stderr}"
         );
     }
 
     #[test]
-    fn help_flag_shows_convert_subcommands() {
-        let output = run_raw(&["convert", "--help"]);
+    fn out_of_range_sharpen_amount_is_rejected() {
+        let output = run_raw(&[
+            "convert",
+            "--in",
+            ".",
+            "--out",
+            ".",
+            "--sharpen",
+            "12",
+            "jpeg",
+        ]);
 
-        assert!(output.status.success());
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        assert!(stdout.contains("jpeg"), "Should show jpeg subcommand");
-        assert!(stdout.contains("video"), "Should show video subcommand");
-        assert!(stdout.contains("stl"), "Should show stl subcommand");
-        assert!(stdout.contains("--in"), "Should show --in option");
-        assert!(stdout.contains("--out"), "Should show --out option");
-        assert!(stdout.contains("--force"), "Should show --force option");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
         assert!(
-            stdout.contains("--split-by"),
-            "Should show --split-by option"
+            stderr.contains("between 0 and 5"),
+            "Should explain the range: {stderr}"
         );
     }
 
     #[test]
-    fn video_help_shows_fps_option() {
-        let output = run_raw(&["convert", "--in", ".", "--out", ".", "video", "--help"]);
+    fn window_needs_a_center_and_width() {
+        let output = run_raw(&[
+            "convert", "--in", ".", "--out", ".", "--window", "40", "jpeg",
+        ]);
 
-        assert!(output.status.success());
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        assert!(stdout.contains("--fps"), "Should show --fps option");
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("CENTER,WIDTH"),
+            "Should explain the format: {stderr}"
+        );
     }
 
     #[test]
-    fn stl_help_shows_specific_options() {
-        let output = run_raw(&["convert", "--in", ".", "--out", ".", "stl", "--help"]);
+    fn window_center_needs_a_width() {
+        let output = run_raw(&[
+            "convert",
+            "--in",
+            ".",
+            "--out",
+            ".",
+            "--window-center",
+            "40",
+            "jpeg",
+        ]);
 
-        assert!(output.status.success());
-        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
         assert!(
-            stdout.contains("--iso-level"),
-            "Should show --iso-level option"
+            stderr.contains("--window-width"),
+            "Should name the missing flag: {stderr}"
         );
-        assert!(stdout.contains("--smooth"), "Should show --smooth option");
     }
-}
 
-// =============================================================================
-// JPG Conversion Tests
-// =============================================================================
+    #[test]
+    fn window_and_its_split_form_conflict() {
+        let output = run_raw(&[
+            "convert",
+            "--in",
+            ".",
+            "--out",
+            ".",
+            "--window",
+            "40,400",
+            "--window-center",
+            "40",
+            "--window-width",
+            "400",
+            "jpeg",
+        ]);
 
-mod jpg_conversion {
-    use super::*;
+        assert_eq!(output.status.code(), Some(4));
+    }
 
     #[test]
-    fn converts_dcm_files_to_jpg_in_series_subfolders() {
-        let example = example_folder();
-        if !example.exists() {
-            eprintln!("Skipping test: example folder not found");
-            return;
-        }
+    fn unknown_background_color_is_rejected() {
+        let output = run_raw(&[
+            "convert",
+            "--in",
+            ".",
+            "--out",
+            ".",
+            "video",
+            "--background",
+            "mauve",
+        ]);
 
-        let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("black, white, or #rrggbb"),
+            "Should list the accepted colors: {stderr}"
+        );
+    }
 
+    #[test]
+    fn cardiac_loops_cannot_be_combined() {
         let output = run_convert(
-            "jpeg",
-            &[
-                "--in",
-                example.to_str().unwrap(),
-                "--out",
-                output_path.to_str().unwrap(),
-                "--force",
-            ],
-            &[],
+            "video",
+            &["--in", ".", "--out", "out"],
+            &["--cardiac-loops", "--combine-series"],
         );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
 
-        assert!(output.status.success(), "CLI failed: {output:?}");
-        assert!(output_path.exists(), "Output folder should exist");
+        let output = run_convert(
+            "video",
+            &["--in", ".", "--out", "out"],
+            &["--cardiac-slice", "0"],
+        );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+    }
 
-        // Should have series subfolders (not JPGs directly in output)
-        let subdirs = get_subdirs(&output_path);
-        assert!(
-            !subdirs.is_empty(),
-            "Should have at least one series subfolder"
+    #[test]
+    fn slide_tile_size_needs_deepzoom() {
+        let output = run_convert(
+            "slide",
+            &["--in", ".", "--out", "out"],
+            &["--tile-size", "512"],
         );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
 
-        // JPG files should exist inside series subfolders
-        let jpg_count = count_files_with_extension(&output_path, "jpg");
-        assert!(jpg_count > 0, "Should have created JPG files in subfolders");
+        let output = run_convert(
+            "slide",
+            &["--in", ".", "--out", "out"],
+            &["--deepzoom", "--max-size", "4096"],
+        );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
     }
 
     #[test]
-    fn jpg_files_are_sequentially_named_within_series() {
-        let example = example_folder();
-        if !example.exists() {
-            eprintln!("Skipping test: example folder not found");
-            return;
-        }
+    fn unknown_target_size_unit_is_rejected() {
+        let output = run_raw(&["video-from-images", "--in", ".", "--target-size", "25XB"]);
 
-        let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("expected KB, MB, or GB"),
+            "Should list the accepted units: {stderr}"
+        );
+    }
 
+    #[test]
+    fn zero_timeout_is_rejected() {
         let output = run_convert(
             "jpeg",
-            &[
-                "--in",
-                example.to_str().unwrap(),
-                "--out",
-                output_path.to_str().unwrap(),
-                "--force",
-            ],
+            &["--in", ".", "--out", "out", "--timeout", "0"],
             &[],
         );
 
-        assert!(output.status.success());
-
-        // Check each series subfolder
-        for subdir in get_subdirs(&output_path) {
-            let mut filenames: Vec<String> = fs::read_dir(&subdir)
-                .unwrap()
-                .filter_map(std::result::Result::ok)
-                .filter_map(|e| e.file_name().into_string().ok())
-                .filter(|name| {
-                    std::path::Path::new(name)
-                        .extension()
-                        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg"))
-                })
-                .collect();
-
-            if filenames.is_empty() {
-                continue;
-            }
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--timeout"), "{stderr}");
+    }
 
-            filenames.sort();
+    #[test]
+    fn encrypt_zip_needs_series_folders() {
+        let output = run_convert(
+            "jpeg",
+            &["--in", ".", "--out", "-", "--encrypt-zip", "s3cret"],
+            &[],
+        );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--encrypt-zip"), "{stderr}");
 
-            // Files should be sequential starting from 0001
-            for (i, name) in filenames.iter().enumerate() {
-                let expected_num = i + 1;
-                let file_num: usize = name.trim_end_matches(".jpg").parse().unwrap_or(0);
-                assert_eq!(
-                    file_num, expected_num,
-                    "File {name} should be numbered {expected_num}"
-                );
-            }
-        }
+        let output = run_convert(
+            "jpeg",
+            &["--in", ".", "--out", "out", "--encrypt-zip", ""],
+            &[],
+        );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
     }
 
     #[test]
-    fn force_flag_cleans_existing_series_folders() {
-        let example = example_folder();
-        if !example.exists() {
-            return;
-        }
+    fn encrypted_dicomweb_needs_a_zip_output() {
+        let output = run_convert(
+            "dicomweb",
+            &["--in", ".", "--out", "site", "--encrypt-zip", "s3cret"],
+            &[],
+        );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(".zip"), "{stderr}");
+    }
 
+    #[test]
+    fn existing_zip_output_needs_force() {
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+        let archive = temp_dir.path().join("export.zip");
+        fs::write(&archive, "keep me").unwrap();
 
-        // First run to create series folders
         let output = run_convert(
             "jpeg",
             &[
                 "--in",
-                example.to_str().unwrap(),
+                temp_dir.path().to_str().unwrap(),
                 "--out",
-                output_path.to_str().unwrap(),
-                "--force",
+                archive.to_str().unwrap(),
             ],
             &[],
         );
-        assert!(output.status.success());
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--force"), "{stderr}");
+        assert_eq!(fs::read_to_string(&archive).unwrap(), "keep me");
+    }
 
-        // Add a dummy file to one of the series folders
-        let subdirs = get_subdirs(&output_path);
-        if let Some(first_subdir) = subdirs.first() {
-            fs::write(first_subdir.join("old_file.txt"), "old content").unwrap();
-        }
+    #[test]
+    fn files_without_the_split_tag_can_stop_the_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("in");
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("broken.dcm"), "not dicom").unwrap();
+        let out = temp_dir.path().join("out");
 
-        // Run again with force
         let output = run_convert(
             "jpeg",
             &[
                 "--in",
-                example.to_str().unwrap(),
+                input.to_str().unwrap(),
                 "--out",
-                output_path.to_str().unwrap(),
-                "--force",
+                out.to_str().unwrap(),
+                "--unknown-group",
+                "error",
             ],
             &[],
         );
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--unknown-group"), "{stderr}");
+        assert!(!out.join("unknown").exists());
+    }
 
-        assert!(output.status.success());
+    #[test]
+    fn zero_read_rate_is_rejected() {
+        let output = run_raw(&["--throttle-read", "0", "analyze", "--in", "."]);
 
-        // Old file should be gone from all series folders
-        for subdir in get_subdirs(&output_path) {
-            assert!(
-                !subdir.join("old_file.txt").exists(),
-                "Old files should be cleaned from {subdir:?}"
-            );
-        }
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--throttle-read"), "{stderr}");
     }
 
     #[test]
-    fn empty_series_folder_works_without_force() {
-        let example = example_folder();
-        if !example.exists() {
-            return;
-        }
-
+    fn metrics_file_records_a_failed_run() {
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
-
-        // Create empty output folder
-        fs::create_dir_all(&output_path).unwrap();
+        let metrics = temp_dir.path().join("dcm.prom");
 
         let output = run_convert(
             "jpeg",
             &[
                 "--in",
-                example.to_str().unwrap(),
+                "/nonexistent/folder",
                 "--out",
-                output_path.to_str().unwrap(),
+                temp_dir.path().join("out").to_str().unwrap(),
+                "--metrics-file",
+                metrics.to_str().unwrap(),
             ],
             &[],
         );
 
-        assert!(output.status.success());
-
-        // Should have series subfolders with JPGs
-        let subdirs = get_subdirs(&output_path);
-        assert!(!subdirs.is_empty(), "Should have series subfolders");
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let text = fs::read_to_string(&metrics).unwrap();
+        assert!(text.contains("dcm_toolbox_exit_code 4\n"), "{text}");
     }
 
     #[test]
-    fn short_force_flag_works() {
-        let example = example_folder();
-        if !example.exists() {
-            return;
-        }
-
-        let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+    fn webhook_receives_the_run_summary() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            String::from_utf8(body).unwrap()
+        });
 
-        // First run to create folders
-        run_convert(
+        let output = run_convert(
             "jpeg",
             &[
                 "--in",
-                example.to_str().unwrap(),
+                "/nonexistent/folder",
                 "--out",
-                output_path.to_str().unwrap(),
-                "--force",
+                "out",
+                "--notify-webhook",
+                &url,
             ],
             &[],
         );
 
-        // Add dummy file to a series folder
-        let subdirs = get_subdirs(&output_path);
-        if let Some(first_subdir) = subdirs.first() {
-            fs::write(first_subdir.join("old.txt"), "content").unwrap();
-        }
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let body = server.join().unwrap();
+        assert!(body.contains(r#""status":"bad_input""#), "{body}");
+    }
+
+    #[test]
+    fn nonexistent_input_folder_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
 
         let output = run_convert(
             "jpeg",
             &[
                 "--in",
-                example.to_str().unwrap(),
+                "/nonexistent/folder/that/does/not/exist",
                 "--out",
                 output_path.to_str().unwrap(),
-                "-f", // Short flag
             ],
             &[],
         );
 
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("does not exist") || stderr.contains("Error"),
+            "Expected error about nonexistent folder: {stderr}"
+        );
+    }
+
+    #[test]
+    fn help_flag_shows_convert_subcommands() {
+        let output = run_raw(&["convert", "--help"]);
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("jpeg"), "Should show jpeg subcommand");
+        assert!(stdout.contains("video"), "Should show video subcommand");
+        assert!(stdout.contains("stl"), "Should show stl subcommand");
+        assert!(
+            stdout.contains("pointcloud"),
+            "Should show pointcloud subcommand"
+        );
+        assert!(stdout.contains("--in"), "Should show --in option");
+        assert!(stdout.contains("--out"), "Should show --out option");
+        assert!(stdout.contains("--force"), "Should show --force option");
+        assert!(
+            stdout.contains("--split-by"),
+            "Should show --split-by option"
+        );
+        assert!(
+            stdout.contains("--strip-background"),
+            "Should show --strip-background option"
+        );
+        assert!(stdout.contains("--denoise"), "Should show --denoise option");
+        assert!(
+            stdout.contains("--follow-symlinks"),
+            "Should show --follow-symlinks option"
+        );
+        assert!(
+            stdout.contains("--annotations"),
+            "Should show --annotations option"
+        );
+    }
+
+    #[test]
+    fn video_help_shows_fps_option() {
+        let output = run_raw(&["convert", "--in", ".", "--out", ".", "video", "--help"]);
+
         assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("--fps"), "Should show --fps option");
+        assert!(
+            stdout.contains("--with-images"),
+            "Should show --with-images option"
+        );
+        assert!(
+            stdout.contains("--position-bar"),
+            "Should show --position-bar option"
+        );
+        assert!(stdout.contains("--audio"), "Should show --audio option");
+        assert!(
+            stdout.contains("--combine-series"),
+            "Should show --combine-series option"
+        );
+        assert!(stdout.contains("--title"), "Should show --title option");
+        assert!(
+            stdout.contains("--watermark"),
+            "Should show --watermark option"
+        );
+        assert!(
+            stdout.contains("--background"),
+            "Should show --background option"
+        );
+        assert!(
+            stdout.contains("--target-size"),
+            "Should show --target-size option"
+        );
+        assert!(stdout.contains("--hwaccel"), "Should show --hwaccel option");
+    }
 
-        // Verify old files are cleaned
-        for subdir in get_subdirs(&output_path) {
-            assert!(
-                !subdir.join("old.txt").exists(),
-                "Old file should be cleaned"
-            );
+    #[test]
+    fn stl_help_shows_specific_options() {
+        let output = run_raw(&["convert", "--in", ".", "--out", ".", "stl", "--help"]);
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("--iso-level"),
+            "Should show --iso-level option"
+        );
+        assert!(stdout.contains("--smooth"), "Should show --smooth option");
+        for option in [
+            "--target",
+            "--mesh-format",
+            "--mesh-units",
+            "--mesh-scale",
+            "--center",
+            "--mesh-coords",
+            "--flip-x",
+        ] {
+            assert!(stdout.contains(option), "Should show {option} option");
         }
     }
 
     #[test]
-    fn creates_multiple_series_folders_when_data_has_multiple_series() {
-        let example = example_folder();
-        if !example.exists() {
-            return;
+    fn pointcloud_help_shows_specific_options() {
+        let output = run_raw(&["convert", "--in", ".", "--out", ".", "pointcloud", "--help"]);
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for option in ["--threshold", "--point-format", "--coords"] {
+            assert!(stdout.contains(option), "Should show {option} option");
         }
+    }
 
-        let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+    #[test]
+    fn standalone_stl_help_shows_mesh_options() {
+        let output = run_raw(&["stl", "--help"]);
 
-        let output = run_convert(
-            "jpeg",
-            &[
-                "--in",
-                example.to_str().unwrap(),
-                "--out",
-                output_path.to_str().unwrap(),
-                "--force",
-            ],
-            &[],
-        );
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for option in [
+            "--iso-level",
+            "--target",
+            "--smooth",
+            "--crop",
+            "--decimate",
+            "--shell-thickness",
+            "--part",
+            "--labels",
+            "--turntable",
+            "--mesh-units",
+        ] {
+            assert!(stdout.contains(option), "Should show {option} option");
+        }
+    }
+
+    #[test]
+    fn centerline_help_shows_options() {
+        let output = run_raw(&["centerline", "--help"]);
 
         assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for option in [
+            "--threshold",
+            "--below",
+            "--seed",
+            "--min-length",
+            "--coords",
+        ] {
+            assert!(stdout.contains(option), "Should show {option} option");
+        }
+    }
 
-        let subdirs = get_subdirs(&output_path);
-        // This test verifies the structure; actual count depends on test data
-        assert!(
-            !subdirs.is_empty(),
-            "Should create at least one series subfolder"
-        );
+    #[test]
+    fn panoramic_help_shows_options() {
+        let output = run_raw(&["panoramic", "--help"]);
 
-        // Verify each subfolder has JPGs
-        for subdir in &subdirs {
-            let jpg_count: usize = fs::read_dir(subdir)
-                .unwrap()
-                .filter_map(std::result::Result::ok)
-                .filter(|e| e.path().extension().is_some_and(|ext| ext == "jpg"))
-                .count();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for option in [
+            "--thickness",
+            "--mip",
+            "--arch-slices",
+            "--threshold",
+            "--arch-preview",
+        ] {
+            assert!(stdout.contains(option), "Should show {option} option");
+        }
+    }
 
-            assert!(
-                jpg_count > 0,
-                "Series folder {subdir:?} should have JPG files"
-            );
+    #[test]
+    fn measure_help_shows_options() {
+        let output = run_raw(&["measure", "--help"]);
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for option in ["--roi", "--out", "circle:SLICE:X,Y,R"] {
+            assert!(stdout.contains(option), "Should show {option} option");
+        }
+    }
+
+    #[test]
+    fn dose_help_shows_options() {
+        let output = run_raw(&["dose", "--help"]);
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for option in ["--out", "--ct", "--overlays", "--levels", "--prescription"] {
+            assert!(stdout.contains(option), "Should show {option} option");
         }
     }
 }
 
 // =============================================================================
-// Video Conversion Tests
+// JPG Conversion Tests
 // =============================================================================
 
-mod video_conversion {
+mod jpg_conversion {
     use super::*;
 
     #[test]
-    fn video_subcommand_creates_video_in_series_subfolders() {
+    fn unicode_and_long_output_paths_work() {
         let example = example_folder();
         if !example.exists() {
-            return;
-        }
-
-        if !ffmpeg_available() {
-            eprintln!("Skipping test: ffmpeg not available");
+            eprintln!("Skipping test: example folder not found");
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("video_output");
+        // Well past the 260-character Windows MAX_PATH once series folders
+        // and file names are appended
+        let mut output_path = temp_dir.path().join("Radiología – 放射線科");
+        for level in 0..6 {
+            output_path.push(format!("{level}_{}", "estudio_ñ".repeat(4)));
+        }
 
         let output = run_convert(
-            "video",
+            "jpeg",
             &[
                 "--in",
                 example.to_str().unwrap(),
@@ -521,175 +800,267 @@ mod video_conversion {
         );
 
         assert!(output.status.success(), "CLI failed: {output:?}");
-        assert!(output_path.exists(), "Output folder should exist");
-
-        // Videos should be in series subfolders, named after the folder
-        let subdirs = get_subdirs(&output_path);
-        assert!(
-            !subdirs.is_empty(),
-            "Should have at least one series subfolder"
-        );
-
-        for subdir in &subdirs {
-            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
-            let video_file = subdir.join(format!("{folder_name}.mp4"));
-
-            assert!(
-                video_file.exists(),
-                "Video file should exist at {video_file:?}"
-            );
-
-            // Check file size is reasonable (> 1KB)
-            let metadata = fs::metadata(&video_file).unwrap();
-            assert!(
-                metadata.len() > 1024,
-                "Video file should have content: {} bytes",
-                metadata.len()
-            );
-        }
+        assert!(output_path.as_os_str().len() > 260);
+        assert!(count_files_with_extension(&output_path, "jpg") > 0);
     }
 
     #[test]
-    fn fps_parameter_is_accepted() {
+    fn strip_background_writes_same_number_of_images() {
         let example = example_folder();
         if !example.exists() {
-            return;
-        }
-
-        if !ffmpeg_available() {
+            eprintln!("Skipping test: example folder not found");
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("video_output");
+        let plain = temp_dir.path().join("plain");
+        let stripped = temp_dir.path().join("stripped");
 
-        let output = run_convert(
-            "video",
-            &[
+        for (output_path, extra) in [(&plain, None), (&stripped, Some("--strip-background"))] {
+            let mut args = vec![
                 "--in",
                 example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
                 "--force",
-            ],
-            &["--fps", "30"],
-        );
-
-        assert!(output.status.success());
+            ];
+            args.extend(extra);
+            let output = run_convert("jpeg", &args, &[]);
+            assert!(output.status.success(), "CLI failed: {output:?}");
+        }
 
-        // Verify video files were created in series subfolders
-        let mp4_count = count_files_with_extension(&output_path, "mp4");
-        assert!(mp4_count > 0, "Should have created at least one MP4 file");
+        assert_eq!(
+            count_files_with_extension(&stripped, "jpg"),
+            count_files_with_extension(&plain, "jpg")
+        );
     }
 
     #[test]
-    fn video_files_named_after_series_folder() {
+    fn throttled_run_writes_the_same_images() {
         let example = example_folder();
         if !example.exists() {
-            return;
-        }
-
-        if !ffmpeg_available() {
+            eprintln!("Skipping test: example folder not found");
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("video_output");
-
-        let output = run_convert(
-            "video",
-            &[
+        let output_path = temp_dir.path().join("out");
+        let output = run_raw(&[
+            "--throttle-read",
+            "1GB",
+            "--nice",
+            "5",
+            "--ionice",
+            "low",
+            "convert",
+            "--in",
+            example.to_str().unwrap(),
+            "--out",
+            output_path.to_str().unwrap(),
+            "jpeg",
+        ]);
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(count_files_with_extension(&output_path, "jpg") > 0);
+    }
+
+    #[test]
+    fn prefetched_run_writes_the_same_images() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let plain = temp_dir.path().join("plain");
+        let prefetched = temp_dir.path().join("prefetched");
+        for (out, prefetch) in [(&plain, "0"), (&prefetched, "4")] {
+            let output = run_raw(&[
+                "--prefetch",
+                prefetch,
+                "convert",
                 "--in",
                 example.to_str().unwrap(),
                 "--out",
-                output_path.to_str().unwrap(),
-                "--force",
-            ],
-            &[],
-        );
-
-        assert!(output.status.success());
-
-        // Each series folder should have a video named {folder}.mp4
-        for subdir in get_subdirs(&output_path) {
-            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
-            let expected_video = subdir.join(format!("{folder_name}.mp4"));
+                out.to_str().unwrap(),
+                "jpeg",
+            ]);
+            assert!(output.status.success(), "CLI failed: {output:?}");
+        }
 
-            assert!(
-                expected_video.exists(),
-                "Video should be named {folder_name}.mp4 in {subdir:?}"
-            );
+        for dir in get_subdirs(&plain) {
+            let twin = prefetched.join(dir.file_name().unwrap());
+            for entry in fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                let copy = twin.join(path.file_name().unwrap());
+                assert_eq!(fs::read(&path).unwrap(), fs::read(&copy).unwrap());
+            }
         }
     }
 
     #[test]
-    fn video_mode_cleans_existing_series_with_force() {
+    fn split_window_writes_the_same_images() {
         let example = example_folder();
         if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
             return;
         }
 
-        if !ffmpeg_available() {
+        let temp_dir = TempDir::new().unwrap();
+        let joined = temp_dir.path().join("joined");
+        let split = temp_dir.path().join("split");
+        let runs: [(&PathBuf, &[&str]); 2] = [
+            (&joined, &["--window", "-600,1500"]),
+            (
+                &split,
+                &["--window-center", "-600", "--window-width", "1500"],
+            ),
+        ];
+        for (out, window) in runs {
+            let mut args = vec![
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                out.to_str().unwrap(),
+            ];
+            args.extend(window);
+            let output = run_convert("jpeg", &args, &[]);
+            assert!(output.status.success(), "CLI failed: {output:?}");
+        }
+
+        assert!(count_files_with_extension(&joined, "jpg") > 0);
+        for dir in get_subdirs(&joined) {
+            let twin = split.join(dir.file_name().unwrap());
+            for entry in fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                let copy = twin.join(path.file_name().unwrap());
+                assert_eq!(fs::read(&path).unwrap(), fs::read(&copy).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn encrypt_zip_replaces_series_folders_with_archives() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("video_output");
-
-        // First run
+        let output_path = temp_dir.path().join("out");
         let output = run_convert(
-            "video",
+            "jpeg",
             &[
                 "--in",
                 example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
-                "--force",
+                "--encrypt-zip",
+                "s3cret",
             ],
             &[],
         );
-        assert!(output.status.success());
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(get_subdirs(&output_path).is_empty());
+        assert!(count_files_with_extension(&output_path, "zip") > 0);
+    }
 
-        // Add dummy file to a series folder
-        let subdirs = get_subdirs(&output_path);
-        if let Some(first_subdir) = subdirs.first() {
-            fs::write(first_subdir.join("old_video.txt"), "old").unwrap();
+    #[test]
+    fn zip_output_holds_the_series_folders() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
         }
 
-        // Run again with force
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path().join("out");
+        let archive = temp_dir.path().join("export.zip");
+        for output_path in [&folder, &archive] {
+            let output = run_convert(
+                "jpeg",
+                &[
+                    "--in",
+                    example.to_str().unwrap(),
+                    "--out",
+                    output_path.to_str().unwrap(),
+                ],
+                &[],
+            );
+            assert!(output.status.success(), "CLI failed: {output:?}");
+        }
+
+        let zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let mut entries: Vec<_> = zip.file_names().map(PathBuf::from).collect();
+        entries.sort();
+        let mut files: Vec<_> = get_subdirs(&folder)
+            .iter()
+            .flat_map(|dir| fs::read_dir(dir).unwrap())
+            .map(|entry| entry.unwrap().path())
+            .map(|path| path.strip_prefix(&folder).unwrap().to_path_buf())
+            .collect();
+        files.sort();
+        assert_eq!(entries, files);
+    }
+
+    #[test]
+    fn dicomweb_output_links_studies_series_and_frames() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
         let output = run_convert(
-            "video",
+            "dicomweb",
             &[
                 "--in",
                 example.to_str().unwrap(),
                 "--out",
-                output_path.to_str().unwrap(),
-                "--force",
+                temp_dir.path().to_str().unwrap(),
             ],
             &[],
         );
+        assert!(output.status.success(), "CLI failed: {output:?}");
 
-        assert!(output.status.success());
-
-        // Old files should be cleaned
-        for subdir in get_subdirs(&output_path) {
-            assert!(
-                !subdir.join("old_video.txt").exists(),
-                "Old files should be cleaned"
+        let read_json = |path: PathBuf| -> serde_json::Value {
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap()
+        };
+        let uid = |entry: &serde_json::Value, tag: &str| {
+            entry[tag]["Value"][0].as_str().unwrap().to_string()
+        };
+        let studies_dir = temp_dir.path().join("studies");
+        let studies = read_json(studies_dir.join("index.json"));
+        assert!(!studies.as_array().unwrap().is_empty());
+        for study in studies.as_array().unwrap() {
+            let study_dir = studies_dir.join(uid(study, "0020000D"));
+            let series = read_json(study_dir.join("series").join("index.json"));
+            assert_eq!(
+                series.as_array().unwrap().len(),
+                study["00201206"]["Value"][0]
             );
+            for entry in series.as_array().unwrap() {
+                let series_dir = study_dir.join("series").join(uid(entry, "0020000E"));
+                let metadata = read_json(series_dir.join("metadata").join("index.json"));
+                let instances = metadata.as_array().unwrap();
+                assert_eq!(instances.len(), entry["00201209"]["Value"][0]);
+                for instance in instances {
+                    assert!(instance.get("7FE00010").is_none());
+                    let frame = series_dir
+                        .join("instances")
+                        .join(uid(instance, "00080018"))
+                        .join("frames")
+                        .join("1");
+                    assert!(frame.is_file(), "missing {}", frame.display());
+                }
+            }
         }
     }
-}
-
-// =============================================================================
-// STL Conversion Tests
-// =============================================================================
-
-mod stl_conversion {
-    use super::*;
 
     #[test]
-    fn stl_subcommand_creates_stl_in_series_subfolders() {
+    fn converts_dcm_files_to_jpg_in_series_subfolders() {
         let example = example_folder();
         if !example.exists() {
             eprintln!("Skipping test: example folder not found");
@@ -697,10 +1068,10 @@ mod stl_conversion {
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("stl_output");
+        let output_path = temp_dir.path().join("output");
 
         let output = run_convert(
-            "stl",
+            "jpeg",
             &[
                 "--in",
                 example.to_str().unwrap(),
@@ -714,42 +1085,87 @@ mod stl_conversion {
         assert!(output.status.success(), "CLI failed: {output:?}");
         assert!(output_path.exists(), "Output folder should exist");
 
-        // STL files should be in series subfolders, named after the folder
+        // Should have series subfolders (not JPGs directly in output)
         let subdirs = get_subdirs(&output_path);
         assert!(
             !subdirs.is_empty(),
             "Should have at least one series subfolder"
         );
 
-        for subdir in &subdirs {
-            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
-            let stl_file = subdir.join(format!("{folder_name}.stl"));
+        // JPG files should exist inside series subfolders
+        let jpg_count = count_files_with_extension(&output_path, "jpg");
+        assert!(jpg_count > 0, "Should have created JPG files in subfolders");
+    }
 
-            assert!(stl_file.exists(), "STL file should exist at {stl_file:?}");
+    #[test]
+    fn jpg_files_are_sequentially_named_within_series() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
 
-            // Check file size is reasonable (> 84 bytes = STL header)
-            let metadata = fs::metadata(&stl_file).unwrap();
-            assert!(
-                metadata.len() > 84,
-                "STL file should have content beyond header: {} bytes",
-                metadata.len()
-            );
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        // Check each series subfolder
+        for subdir in get_subdirs(&output_path) {
+            let mut filenames: Vec<String> = fs::read_dir(&subdir)
+                .unwrap()
+                .filter_map(std::result::Result::ok)
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|name| {
+                    std::path::Path::new(name)
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg"))
+                })
+                .collect();
+
+            if filenames.is_empty() {
+                continue;
+            }
+
+            filenames.sort();
+
+            // Files should be sequential starting from 0001
+            for (i, name) in filenames.iter().enumerate() {
+                let expected_num = i + 1;
+                let file_num: usize = name.trim_end_matches(".jpg").parse().unwrap_or(0);
+                assert_eq!(
+                    file_num, expected_num,
+                    "File {name} should be numbered {expected_num}"
+                );
+            }
         }
     }
 
     #[test]
-    fn stl_mode_cleans_existing_series_with_force() {
+    fn force_flag_cleans_existing_series_folders() {
         let example = example_folder();
         if !example.exists() {
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("stl_output");
+        let output_path = temp_dir.path().join("output");
 
-        // First run
+        // First run to create series folders
         let output = run_convert(
-            "stl",
+            "jpeg",
             &[
                 "--in",
                 example.to_str().unwrap(),
@@ -761,15 +1177,15 @@ mod stl_conversion {
         );
         assert!(output.status.success());
 
-        // Add dummy file to a series folder
+        // Add a dummy file to one of the series folders
         let subdirs = get_subdirs(&output_path);
         if let Some(first_subdir) = subdirs.first() {
-            fs::write(first_subdir.join("old_stl.txt"), "old").unwrap();
+            fs::write(first_subdir.join("old_file.txt"), "old content").unwrap();
         }
 
         // Run again with force
         let output = run_convert(
-            "stl",
+            "jpeg",
             &[
                 "--in",
                 example.to_str().unwrap(),
@@ -782,32 +1198,27 @@ mod stl_conversion {
 
         assert!(output.status.success());
 
-        // Old files should be cleaned
+        // Old file should be gone from all series folders
         for subdir in get_subdirs(&output_path) {
             assert!(
-                !subdir.join("old_stl.txt").exists(),
-                "Old files should be cleaned"
+                !subdir.join("old_file.txt").exists(),
+                "Old files should be cleaned from {subdir:?}"
             );
         }
     }
-}
-
-// =============================================================================
-// Output Mode Tests
-// =============================================================================
-
-mod output_mode {
-    use super::*;
 
     #[test]
-    fn jpeg_subcommand_creates_jpgs_in_series_folders() {
+    fn empty_series_folder_works_without_force() {
         let example = example_folder();
         if !example.exists() {
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("my_output_folder");
+        let output_path = temp_dir.path().join("output");
+
+        // Create empty output folder
+        fs::create_dir_all(&output_path).unwrap();
 
         let output = run_convert(
             "jpeg",
@@ -816,47 +1227,30 @@ mod output_mode {
                 example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
-                "--force",
             ],
             &[],
         );
 
         assert!(output.status.success());
-        assert!(output_path.is_dir(), "Output should be a directory");
 
-        // Should have series subfolders with JPGs inside
+        // Should have series subfolders with JPGs
         let subdirs = get_subdirs(&output_path);
         assert!(!subdirs.is_empty(), "Should have series subfolders");
-
-        // No JPGs directly in output root
-        let root_jpgs: usize = fs::read_dir(&output_path)
-            .unwrap()
-            .filter_map(std::result::Result::ok)
-            .filter(|e| e.path().extension().is_some_and(|ext| ext == "jpg"))
-            .count();
-        assert_eq!(root_jpgs, 0, "JPGs should not be in root output folder");
-
-        // JPGs should be inside series subfolders
-        let total_jpgs = count_files_with_extension(&output_path, "jpg");
-        assert!(total_jpgs > 0, "Should have JPG files in series folders");
     }
 
     #[test]
-    fn video_subcommand_creates_videos_in_series_folders() {
+    fn short_force_flag_works() {
         let example = example_folder();
         if !example.exists() {
             return;
         }
 
-        if !ffmpeg_available() {
-            return;
-        }
-
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("video_folder");
+        let output_path = temp_dir.path().join("output");
 
-        let output = run_convert(
-            "video",
+        // First run to create folders
+        run_convert(
+            "jpeg",
             &[
                 "--in",
                 example.to_str().unwrap(),
@@ -867,26 +1261,37 @@ mod output_mode {
             &[],
         );
 
-        assert!(output.status.success());
-        assert!(output_path.is_dir(), "Output should be a directory");
-
-        // Should have series subfolders
+        // Add dummy file to a series folder
         let subdirs = get_subdirs(&output_path);
-        assert!(!subdirs.is_empty(), "Should have series subfolders");
+        if let Some(first_subdir) = subdirs.first() {
+            fs::write(first_subdir.join("old.txt"), "content").unwrap();
+        }
 
-        // Each series folder should have its own video
-        for subdir in &subdirs {
-            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
-            let video_file = subdir.join(format!("{folder_name}.mp4"));
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "-f", // Short flag
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        // Verify old files are cleaned
+        for subdir in get_subdirs(&output_path) {
             assert!(
-                video_file.is_file(),
-                "Should have {folder_name}.mp4 in {subdir:?}"
+                !subdir.join("old.txt").exists(),
+                "Old file should be cleaned"
             );
         }
     }
 
     #[test]
-    fn no_mp4_files_in_jpeg_mode() {
+    fn creates_multiple_series_folders_when_data_has_multiple_series() {
         let example = example_folder();
         if !example.exists() {
             return;
@@ -909,23 +1314,50 @@ mod output_mode {
 
         assert!(output.status.success());
 
-        let mp4_count = count_files_with_extension(&output_path, "mp4");
-        assert_eq!(mp4_count, 0, "JPEG mode should not create MP4 files");
+        let subdirs = get_subdirs(&output_path);
+        // This test verifies the structure; actual count depends on test data
+        assert!(
+            !subdirs.is_empty(),
+            "Should create at least one series subfolder"
+        );
+
+        // Verify each subfolder has JPGs
+        for subdir in &subdirs {
+            let jpg_count: usize = fs::read_dir(subdir)
+                .unwrap()
+                .filter_map(std::result::Result::ok)
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "jpg"))
+                .count();
+
+            assert!(
+                jpg_count > 0,
+                "Series folder {subdir:?} should have JPG files"
+            );
+        }
     }
+}
+
+// =============================================================================
+// Video Conversion Tests
+// =============================================================================
+
+mod video_conversion {
+    use super::*;
 
     #[test]
-    fn no_jpg_files_in_video_mode() {
+    fn video_subcommand_creates_video_in_series_subfolders() {
         let example = example_folder();
         if !example.exists() {
             return;
         }
 
         if !ffmpeg_available() {
+            eprintln!("Skipping test: ffmpeg not available");
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+        let output_path = temp_dir.path().join("video_output");
 
         let output = run_convert(
             "video",
@@ -939,142 +1371,150 @@ mod output_mode {
             &[],
         );
 
-        assert!(output.status.success());
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(output_path.exists(), "Output folder should exist");
 
-        // Video mode uses temp dir for JPGs, then converts
-        let jpg_count = count_files_with_extension(&output_path, "jpg");
-        assert_eq!(jpg_count, 0, "Video mode should not leave JPG files");
-    }
-}
+        // Videos should be in series subfolders, named after the folder
+        let subdirs = get_subdirs(&output_path);
+        assert!(
+            !subdirs.is_empty(),
+            "Should have at least one series subfolder"
+        );
 
-// =============================================================================
-// Empty Input Tests
-// =============================================================================
+        for subdir in &subdirs {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let video_file = subdir.join(format!("{folder_name}.mp4"));
 
-mod empty_input {
-    use super::*;
+            assert!(
+                video_file.exists(),
+                "Video file should exist at {video_file:?}"
+            );
+
+            // Check file size is reasonable (> 1KB)
+            let metadata = fs::metadata(&video_file).unwrap();
+            assert!(
+                metadata.len() > 1024,
+                "Video file should have content: {} bytes",
+                metadata.len()
+            );
+        }
+    }
 
     #[test]
-    fn empty_folder_shows_message() {
-        let temp_input = TempDir::new().unwrap();
-        let temp_output = TempDir::new().unwrap();
-        let output_path = temp_output.path().join("output");
+    fn fps_parameter_is_accepted() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        if !ffmpeg_available() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("video_output");
 
         let output = run_convert(
-            "jpeg",
+            "video",
             &[
                 "--in",
-                temp_input.path().to_str().unwrap(),
+                example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
+                "--force",
             ],
-            &[],
+            &["--fps", "30"],
         );
 
         assert!(output.status.success());
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        assert!(
-            stdout.contains("No .dcm files"),
-            "Should indicate no DCM files found: {stdout}"
-        );
+
+        // Verify video files were created in series subfolders
+        let mp4_count = count_files_with_extension(&output_path, "mp4");
+        assert!(mp4_count > 0, "Should have created at least one MP4 file");
     }
 
     #[test]
-    fn empty_folder_does_not_create_output() {
-        let temp_input = TempDir::new().unwrap();
-        let temp_output = TempDir::new().unwrap();
-        let output_path = temp_output.path().join("output");
+    fn video_files_named_after_series_folder() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        if !ffmpeg_available() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("video_output");
 
         let output = run_convert(
-            "jpeg",
+            "video",
             &[
                 "--in",
-                temp_input.path().to_str().unwrap(),
+                example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
+                "--force",
             ],
             &[],
         );
 
         assert!(output.status.success());
 
-        // Output folder may exist but should have no series subfolders
-        if output_path.exists() {
-            let subdirs = get_subdirs(&output_path);
+        // Each series folder should have a video named {folder}.mp4
+        for subdir in get_subdirs(&output_path) {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let expected_video = subdir.join(format!("{folder_name}.mp4"));
+
             assert!(
-                subdirs.is_empty(),
-                "Empty input should not create series folders"
+                expected_video.exists(),
+                "Video should be named {folder_name}.mp4 in {subdir:?}"
             );
         }
     }
-}
-
-// =============================================================================
-// Split-By Tests
-// =============================================================================
-
-mod split_by {
-    use super::*;
 
     #[test]
-    fn split_by_series_number_creates_numbered_folders() {
+    fn video_mode_cleans_existing_series_with_force() {
         let example = example_folder();
         if !example.exists() {
             return;
         }
 
+        if !ffmpeg_available() {
+            return;
+        }
+
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+        let output_path = temp_dir.path().join("video_output");
 
+        // First run
         let output = run_convert(
-            "jpeg",
+            "video",
             &[
                 "--in",
                 example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
-                "--split-by",
-                "series-number",
                 "--force",
             ],
             &[],
         );
-
         assert!(output.status.success());
 
+        // Add dummy file to a series folder
         let subdirs = get_subdirs(&output_path);
-        assert!(!subdirs.is_empty(), "Should have series subfolders");
-
-        // Folder names should be numeric (series numbers)
-        for subdir in &subdirs {
-            let name = subdir.file_name().unwrap().to_str().unwrap();
-            // Either numeric or "unknown"
-            assert!(
-                name.parse::<i32>().is_ok() || name == "unknown",
-                "Folder name should be numeric or 'unknown': {name}"
-            );
-        }
-    }
-
-    #[test]
-    fn split_by_flag_shown_in_output() {
-        let example = example_folder();
-        if !example.exists() {
-            return;
+        if let Some(first_subdir) = subdirs.first() {
+            fs::write(first_subdir.join("old_video.txt"), "old").unwrap();
         }
 
-        let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
-
+        // Run again with force
         let output = run_convert(
-            "jpeg",
+            "video",
             &[
                 "--in",
                 example.to_str().unwrap(),
                 "--out",
                 output_path.to_str().unwrap(),
-                "--split-by",
-                "description",
                 "--force",
             ],
             &[],
@@ -1082,25 +1522,36 @@ mod split_by {
 
         assert!(output.status.success());
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        assert!(
-            stdout.contains("Splitting by") || stdout.contains("Description"),
-            "Should indicate split-by mode: {stdout}"
-        );
+        // Old files should be cleaned
+        for subdir in get_subdirs(&output_path) {
+            assert!(
+                !subdir.join("old_video.txt").exists(),
+                "Old files should be cleaned"
+            );
+        }
     }
+}
+
+// =============================================================================
+// STL Conversion Tests
+// =============================================================================
+
+mod stl_conversion {
+    use super::*;
 
     #[test]
-    fn default_split_by_is_series_number() {
+    fn stl_subcommand_creates_stl_in_series_subfolders() {
         let example = example_folder();
         if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
             return;
         }
 
         let temp_dir = TempDir::new().unwrap();
-        let output_path = temp_dir.path().join("output");
+        let output_path = temp_dir.path().join("stl_output");
 
         let output = run_convert(
-            "jpeg",
+            "stl",
             &[
                 "--in",
                 example.to_str().unwrap(),
@@ -1111,12 +1562,1933 @@ mod split_by {
             &[],
         );
 
-        assert!(output.status.success());
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(output_path.exists(), "Output folder should exist");
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
         assert!(
-            stdout.contains("SeriesNumber") || stdout.contains("series-number"),
-            "Default should be series-number: {stdout}"
+            stderr
+                .lines()
+                .any(|l| l.starts_with("mesh ") && l.contains("volume_mm3=")),
+            "Each mesh should be measured in the summary: {stderr}"
         );
+
+        // STL files should be in series subfolders, named after the folder
+        let subdirs = get_subdirs(&output_path);
+        assert!(
+            !subdirs.is_empty(),
+            "Should have at least one series subfolder"
+        );
+
+        for subdir in &subdirs {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let stl_file = subdir.join(format!("{folder_name}.stl"));
+
+            assert!(stl_file.exists(), "STL file should exist at {stl_file:?}");
+            assert!(
+                subdir.join(format!("{folder_name}.png")).exists(),
+                "Mesh preview should be written next to the STL"
+            );
+
+            // Check file size is reasonable (> 84 bytes = STL header)
+            let metadata = fs::metadata(&stl_file).unwrap();
+            assert!(
+                metadata.len() > 84,
+                "STL file should have content beyond header: {} bytes",
+                metadata.len()
+            );
+        }
+    }
+
+    #[test]
+    fn stl_mode_cleans_existing_series_with_force() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("stl_output");
+
+        // First run
+        let output = run_convert(
+            "stl",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+        assert!(output.status.success());
+
+        // Add dummy file to a series folder
+        let subdirs = get_subdirs(&output_path);
+        if let Some(first_subdir) = subdirs.first() {
+            fs::write(first_subdir.join("old_stl.txt"), "old").unwrap();
+        }
+
+        // Run again with force
+        let output = run_convert(
+            "stl",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        // Old files should be cleaned
+        for subdir in get_subdirs(&output_path) {
+            assert!(
+                !subdir.join("old_stl.txt").exists(),
+                "Old files should be cleaned"
+            );
+        }
+    }
+
+    #[test]
+    fn standalone_stl_rejects_malformed_crop() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "stl",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("model.stl").to_str().unwrap(),
+            "--crop",
+            "0:10,0:10",
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("X0:X1,Y0:Y1,Z0:Z1"), "{stderr}");
+    }
+
+    #[test]
+    fn standalone_stl_parts_need_3mf_output() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "stl",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("model.stl").to_str().unwrap(),
+            "--part",
+            "bone:300:",
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(".3mf"), "{stderr}");
+    }
+
+    #[test]
+    fn standalone_stl_labels_need_multi_part_output() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "stl",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("model.stl").to_str().unwrap(),
+            "--labels",
+            temp_dir.path().join("seg.nii.gz").to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--labels"), "{stderr}");
+    }
+
+    #[test]
+    fn suv_max_needs_suv() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("out");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_dir.path().to_str().unwrap(),
+                "--out",
+                output_dir.to_str().unwrap(),
+                "--suv-max",
+                "8",
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--suv"), "{stderr}");
+    }
+
+    #[test]
+    fn measure_rejects_malformed_roi() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "measure",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--roi",
+            "circle:10:20,30",
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("X,Y,R"), "{stderr}");
+    }
+
+    #[test]
+    fn dose_rejects_unknown_volume_format() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "dose",
+            "--in",
+            temp_dir.path().join("RD.dcm").to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("dose.mha").to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(".nrrd, .nii, or .nii.gz"), "{stderr}");
+    }
+
+    #[test]
+    fn dose_overlays_need_a_ct() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "dose",
+            "--in",
+            temp_dir.path().join("RD.dcm").to_str().unwrap(),
+            "--overlays",
+            temp_dir.path().to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--ct"), "{stderr}");
+    }
+
+    #[test]
+    fn panoramic_rejects_unknown_output_format() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "panoramic",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("panoramic.bmp").to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(".png or .jpg"), "{stderr}");
+    }
+
+    #[test]
+    fn centerline_rejects_unknown_output_format() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_raw(&[
+            "centerline",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("lines.stl").to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(".vtk or .json"), "{stderr}");
+    }
+
+    #[test]
+    fn standalone_stl_needs_enough_slices() {
+        let temp_dir = TempDir::new().unwrap();
+        let model = temp_dir.path().join("model.stl");
+
+        let output = run_raw(&[
+            "stl",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            model.to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        assert!(!model.exists());
+    }
+}
+
+// =============================================================================
+// Point Cloud Conversion Tests
+// =============================================================================
+
+mod pointcloud_conversion {
+    use super::*;
+
+    #[test]
+    fn pointcloud_subcommand_writes_ply_per_series() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("cloud_output");
+
+        let output = run_convert(
+            "pointcloud",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        for subdir in &get_subdirs(&output_path) {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let ply = fs::read(subdir.join(format!("{folder_name}.ply"))).unwrap();
+            assert!(ply.starts_with(b"ply\nformat binary_little_endian 1.0\n"));
+        }
+    }
+
+    #[test]
+    fn pointcloud_xyz_is_one_line_per_point() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("cloud_output");
+
+        let output = run_convert(
+            "pointcloud",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &["--point-format", "xyz", "--coords", "lps"],
+        );
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        for subdir in &get_subdirs(&output_path) {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let xyz = fs::read_to_string(subdir.join(format!("{folder_name}.xyz"))).unwrap();
+            assert!(xyz.lines().all(|l| l.split(' ').count() == 4));
+        }
+    }
+}
+
+// =============================================================================
+// Print Layout Tests
+// =============================================================================
+
+mod print_layout {
+    use super::*;
+
+    #[test]
+    fn each_series_becomes_one_pdf() {
+        let example = example_folder();
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("print_output");
+
+        let output = run_convert(
+            "print-layout",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &["--layout", "4x4"],
+        );
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let subdirs = get_subdirs(&output_path);
+        assert!(!subdirs.is_empty());
+        for subdir in &subdirs {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let pdf = fs::read(subdir.join(format!("{folder_name}.pdf"))).unwrap();
+            assert!(pdf.starts_with(b"%PDF-1.4\n"));
+            assert!(pdf.ends_with(b"%%EOF\n"));
+            let text = String::from_utf8_lossy(&pdf);
+            assert!(text.contains("/MediaBox [0 0 595 842]"), "A4 pages");
+        }
+    }
+
+    #[test]
+    fn png_pages_are_paper_sized() {
+        let example = example_folder();
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("print_output");
+
+        let output = run_convert(
+            "print-layout",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[
+                "--page-format",
+                "png",
+                "--paper",
+                "letter",
+                "--dpi",
+                "72",
+                "--layout",
+                "10x10",
+            ],
+        );
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        for subdir in &get_subdirs(&output_path) {
+            let mut pages: Vec<_> = fs::read_dir(subdir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            pages.sort();
+            assert_eq!(pages, ["page_001.png"]);
+            let page = image::open(subdir.join("page_001.png")).unwrap();
+            assert_eq!((page.width(), page.height()), (612, 792));
+        }
+    }
+
+    #[test]
+    fn layouts_need_columns_and_rows() {
+        let output = run_convert("print-layout", &["--in", "."], &["--layout", "4"]);
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("COLUMNSxROWS"), "{stderr}");
+    }
+}
+
+// =============================================================================
+// Output Mode Tests
+// =============================================================================
+
+mod output_mode {
+    use super::*;
+
+    #[test]
+    fn jpeg_subcommand_creates_jpgs_in_series_folders() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("my_output_folder");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+        assert!(output_path.is_dir(), "Output should be a directory");
+
+        // Should have series subfolders with JPGs inside
+        let subdirs = get_subdirs(&output_path);
+        assert!(!subdirs.is_empty(), "Should have series subfolders");
+
+        // No JPGs directly in output root
+        let root_jpgs: usize = fs::read_dir(&output_path)
+            .unwrap()
+            .filter_map(std::result::Result::ok)
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "jpg"))
+            .count();
+        assert_eq!(root_jpgs, 0, "JPGs should not be in root output folder");
+
+        // JPGs should be inside series subfolders
+        let total_jpgs = count_files_with_extension(&output_path, "jpg");
+        assert!(total_jpgs > 0, "Should have JPG files in series folders");
+    }
+
+    #[test]
+    fn video_subcommand_creates_videos_in_series_folders() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        if !ffmpeg_available() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("video_folder");
+
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+        assert!(output_path.is_dir(), "Output should be a directory");
+
+        // Should have series subfolders
+        let subdirs = get_subdirs(&output_path);
+        assert!(!subdirs.is_empty(), "Should have series subfolders");
+
+        // Each series folder should have its own video
+        for subdir in &subdirs {
+            let folder_name = subdir.file_name().unwrap().to_str().unwrap();
+            let video_file = subdir.join(format!("{folder_name}.mp4"));
+            assert!(
+                video_file.is_file(),
+                "Should have {folder_name}.mp4 in {subdir:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn no_mp4_files_in_jpeg_mode() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        let mp4_count = count_files_with_extension(&output_path, "mp4");
+        assert_eq!(mp4_count, 0, "JPEG mode should not create MP4 files");
+    }
+
+    #[test]
+    fn no_jpg_files_in_video_mode() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        if !ffmpeg_available() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        // Video mode uses temp dir for JPGs, then converts
+        let jpg_count = count_files_with_extension(&output_path, "jpg");
+        assert_eq!(jpg_count, 0, "Video mode should not leave JPG files");
+    }
+}
+
+// =============================================================================
+// Empty Input Tests
+// =============================================================================
+
+mod empty_input {
+    use super::*;
+
+    #[test]
+    fn empty_folder_shows_message() {
+        let temp_input = TempDir::new().unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            output.status.code(),
+            Some(3),
+            "Nothing converted: {output:?}"
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("No .dcm files"),
+            "Should indicate no DCM files found: {stdout}"
+        );
+    }
+
+    #[test]
+    fn empty_folder_does_not_create_output() {
+        let temp_input = TempDir::new().unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            output.status.code(),
+            Some(3),
+            "Nothing converted: {output:?}"
+        );
+
+        // Output folder may exist but should have no series subfolders
+        if output_path.exists() {
+            let subdirs = get_subdirs(&output_path);
+            assert!(
+                subdirs.is_empty(),
+                "Empty input should not create series folders"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlinked_files_are_skipped_with_hint() {
+        let temp_input = TempDir::new().unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let target = temp_output.path().join("slice.dcm");
+        fs::write(&target, "not dicom").unwrap();
+        std::os::unix::fs::symlink(&target, temp_input.path().join("slice.dcm")).unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            output.status.code(),
+            Some(3),
+            "Nothing converted: {output:?}"
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("--follow-symlinks"),
+            "Should suggest --follow-symlinks: {stderr}"
+        );
+    }
+}
+
+// =============================================================================
+// Split-By Tests
+// =============================================================================
+
+mod split_by {
+    use super::*;
+
+    #[test]
+    fn split_by_series_number_creates_numbered_folders() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--split-by",
+                "series-number",
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        let subdirs = get_subdirs(&output_path);
+        assert!(!subdirs.is_empty(), "Should have series subfolders");
+
+        // Folder names should be numeric (series numbers)
+        for subdir in &subdirs {
+            let name = subdir.file_name().unwrap().to_str().unwrap();
+            // Either numeric or "unknown"
+            assert!(
+                name.parse::<i32>().is_ok() || name == "unknown",
+                "Folder name should be numeric or 'unknown': {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn split_by_flag_shown_in_output() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--split-by",
+                "description",
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Splitting by") || stdout.contains("Description"),
+            "Should indicate split-by mode: {stdout}"
+        );
+    }
+
+    #[test]
+    fn default_split_by_is_series_number() {
+        let example = example_folder();
+        if !example.exists() {
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("SeriesNumber") || stdout.contains("series-number"),
+            "Default should be series-number: {stdout}"
+        );
+    }
+}
+
+// =============================================================================
+// Piping Tests (`--in -` / `--out -`)
+// =============================================================================
+
+mod piping {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    use super::*;
+
+    /// Find the first `.dcm` file in the example folder, if present.
+    fn example_file() -> Option<PathBuf> {
+        fs::read_dir(example_folder())
+            .ok()?
+            .filter_map(std::result::Result::ok)
+            .map(|e| e.path())
+            .find(|p| p.extension().is_some_and(|e| e == "dcm"))
+    }
+
+    /// Run the CLI with `stdin_bytes` piped into stdin.
+    fn run_with_stdin(args: &[&str], stdin_bytes: &[u8]) -> std::process::Output {
+        let mut child = Command::new(binary_path())
+            .args(args)
+            .env("DCM_TOOLBOX_LANG", "en")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to spawn command");
+        // The process may exit before reading everything; ignore broken pipes.
+        let _ = child.stdin.take().unwrap().write_all(stdin_bytes);
+        child
+            .wait_with_output()
+            .expect("Failed to wait for command")
+    }
+
+    #[test]
+    fn file_to_stdout_writes_png_bytes_only() {
+        let Some(file) = example_file() else {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        };
+
+        let output = run_convert(
+            "jpeg",
+            &["--in", file.to_str().unwrap(), "--out", "-"],
+            &["--image-format", "png"],
+        );
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(
+            output.stdout.starts_with(b"\x89PNG\r\n\x1a\n"),
+            "stdout should contain only PNG data"
+        );
+    }
+
+    #[test]
+    fn stdin_to_stdout_writes_jpeg_bytes() {
+        let Some(file) = example_file() else {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        };
+
+        let output = run_with_stdin(
+            &["convert", "--in", "-", "--out", "-", "jpeg"],
+            &fs::read(file).unwrap(),
+        );
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(
+            output.stdout.starts_with(&[0xFF, 0xD8, 0xFF]),
+            "stdout should contain only JPEG data"
+        );
+    }
+
+    #[test]
+    fn invalid_stdin_fails_without_output() {
+        let output = run_with_stdin(
+            &["convert", "--in", "-", "--out", "-", "jpeg"],
+            b"definitely not DICOM",
+        );
+
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty(), "Nothing should reach stdout");
+    }
+
+    #[test]
+    fn piping_is_rejected_for_video() {
+        let output = run_with_stdin(&["convert", "--in", "-", "--out", "-", "video"], b"");
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("only supported"),
+            "Unexpected error: {stderr}"
+        );
+    }
+
+    #[test]
+    fn piping_is_rejected_for_scout_lines() {
+        let output = run_with_stdin(
+            &[
+                "convert",
+                "--in",
+                "-",
+                "--out",
+                "-",
+                "jpeg",
+                "--scout-lines",
+            ],
+            b"",
+        );
+
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("--scout-lines"),
+            "Unexpected error: {stderr}"
+        );
+    }
+
+    #[test]
+    fn folder_to_stdout_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = run_convert(
+            "jpeg",
+            &["--in", temp_dir.path().to_str().unwrap(), "--out", "-"],
+            &[],
+        );
+
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+}
+
+// =============================================================================
+// Clipboard Tests (only what is checked before the clipboard is opened)
+// =============================================================================
+
+mod clipboard {
+    use super::*;
+
+    #[test]
+    fn slice_needs_to_clipboard() {
+        let output = run_convert("jpeg", &["--in", ".", "--slice", "3"], &[]);
+
+        assert_eq!(output.status.code(), Some(4));
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    #[test]
+    fn builds_without_the_feature_say_so() {
+        let folder = example_folder();
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                folder.to_str().unwrap(),
+                "--to-clipboard",
+                "--slice",
+                "1",
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--features clipboard"), "{stderr}");
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn only_jpeg_renders_go_to_the_clipboard() {
+        let folder = example_folder();
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                folder.to_str().unwrap(),
+                "--to-clipboard",
+                "--slice",
+                "1",
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("jpeg format"), "{stderr}");
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn slices_past_the_end_are_rejected() {
+        let file = fs::read_dir(example_folder())
+            .unwrap()
+            .filter_map(std::result::Result::ok)
+            .map(|e| e.path())
+            .find(|p| p.extension().is_some_and(|e| e == "dcm"))
+            .unwrap();
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                file.to_str().unwrap(),
+                "--to-clipboard",
+                "--slice",
+                "9999",
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("past the last slice"), "{stderr}");
+    }
+}
+
+// =============================================================================
+// Quick Convert Tests (a lone path, as file managers pass it)
+// =============================================================================
+
+mod quick_convert {
+    use super::*;
+
+    /// The first `count` `.dcm` files of the example folder, by name.
+    fn example_files(count: usize) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(example_folder())
+            .unwrap()
+            .filter_map(std::result::Result::ok)
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "dcm"))
+            .collect();
+        files.sort();
+        files.truncate(count);
+        files
+    }
+
+    #[test]
+    fn a_file_becomes_a_jpeg_beside_it() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("scan.dcm");
+        fs::copy(&example_files(1)[0], &file).unwrap();
+
+        let output = run_raw(&[file.to_str().unwrap()]);
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let jpeg = fs::read(dir.path().join("scan.jpg")).unwrap();
+        assert!(jpeg.starts_with(&[0xFF, 0xD8]), "not a JPEG");
+    }
+
+    #[test]
+    fn a_folder_exports_beside_it() {
+        let dir = TempDir::new().unwrap();
+        let scans = dir.path().join("scans");
+        fs::create_dir(&scans).unwrap();
+        for file in example_files(3) {
+            fs::copy(&file, scans.join(file.file_name().unwrap())).unwrap();
+        }
+
+        let output = run_raw(&[scans.to_str().unwrap()]);
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let export = dir.path().join("scans_export");
+        let images = fs::read_dir(&export)
+            .unwrap()
+            .filter_map(std::result::Result::ok)
+            .flat_map(|series| fs::read_dir(series.path()).unwrap())
+            .count();
+        assert_eq!(images, 3);
+    }
+
+    #[test]
+    fn unknown_words_are_still_rejected() {
+        let output = run_raw(&["/nonexistent/scan.dcm"]);
+
+        assert_eq!(output.status.code(), Some(4));
+    }
+}
+
+// =============================================================================
+// Exit Code & Summary Tests
+// =============================================================================
+
+mod exit_codes {
+    use super::*;
+
+    /// Last non-empty stderr line (the machine-readable summary).
+    fn summary_line(output: &std::process::Output) -> String {
+        String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[test]
+    fn unknown_argument_is_bad_input() {
+        let output = run_raw(&["convert", "--no-such-flag"]);
+        assert_eq!(output.status.code(), Some(4));
+    }
+
+    #[test]
+    fn help_exits_zero() {
+        let output = run_raw(&["--help"]);
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[test]
+    fn video_from_images_without_images_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "x").unwrap();
+
+        let output = run_raw(&[
+            "video-from-images",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("No numbered images"), "{stderr}");
+    }
+
+    #[test]
+    fn missing_ffmpeg_fails_before_encoding() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("0001.jpg"), "").unwrap();
+        let missing = temp_dir.path().join("bin").join("ffmpeg");
+
+        let output = run_raw(&[
+            "video-from-images",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--ffmpeg-path",
+            missing.to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--ffmpeg-path"), "{stderr}");
+    }
+
+    #[test]
+    fn missing_temp_dir_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("scratch");
+
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                temp_dir.path().to_str().unwrap(),
+                "--out",
+                temp_dir.path().join("output").to_str().unwrap(),
+                "--temp-dir",
+                missing.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        assert_eq!(summary_line(&output), "summary status=bad_input exit=4");
+    }
+
+    #[test]
+    fn missing_audio_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("narration.mp3");
+
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                temp_dir.path().to_str().unwrap(),
+                "--out",
+                temp_dir.path().join("output").to_str().unwrap(),
+            ],
+            &["--audio", missing.to_str().unwrap()],
+        );
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("narration.mp3"), "{stderr}");
+    }
+
+    #[test]
+    fn missing_watermark_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("logo.png");
+
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                temp_dir.path().to_str().unwrap(),
+                "--out",
+                temp_dir.path().join("output").to_str().unwrap(),
+            ],
+            &["--watermark", missing.to_str().unwrap()],
+        );
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("logo.png"), "{stderr}");
+    }
+
+    #[test]
+    fn combined_video_cannot_export_patches() {
+        let temp_dir = TempDir::new().unwrap();
+        let annotations = temp_dir.path().join("boxes.json");
+        fs::write(&annotations, "{}").unwrap();
+
+        let output = run_convert(
+            "video",
+            &[
+                "--in",
+                temp_dir.path().to_str().unwrap(),
+                "--out",
+                temp_dir.path().join("output").to_str().unwrap(),
+                "--annotations",
+                annotations.to_str().unwrap(),
+                "--export-patches",
+            ],
+            &["--combine-series"],
+        );
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--combine-series"), "{stderr}");
+    }
+
+    #[test]
+    fn nonexistent_input_is_bad_input_with_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                "/nonexistent/folder/that/does/not/exist",
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4));
+        assert_eq!(summary_line(&output), "summary status=bad_input exit=4");
+    }
+
+    #[test]
+    fn malformed_annotation_file_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let annotations = temp_dir.path().join("annotations.json");
+        fs::write(&annotations, r#"{"1.2.3": [{"type": "circle"}]}"#).unwrap();
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_dir.path().to_str().unwrap(),
+                "--out",
+                temp_dir.path().join("output").to_str().unwrap(),
+                "--annotations",
+                annotations.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("Invalid annotation file"),
+            "Should name the problem: {stderr}"
+        );
+    }
+
+    #[test]
+    fn empty_folder_reports_nothing_converted() {
+        let temp_input = TempDir::new().unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(3));
+        assert!(
+            summary_line(&output).starts_with("summary status=nothing_converted exit=3 groups=0"),
+            "Unexpected summary: {}",
+            summary_line(&output)
+        );
+    }
+
+    #[test]
+    fn unreadable_files_report_nothing_converted() {
+        let temp_input = TempDir::new().unwrap();
+        fs::write(temp_input.path().join("broken.dcm"), "not dicom").unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(3));
+        assert!(summary_line(&output).contains("frames=0 frames_failed=1"));
+    }
+
+    #[test]
+    fn successful_conversion_exits_zero() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_path = temp_dir.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--in",
+                example.to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+                "--force",
+            ],
+            &[],
+        );
+
+        assert_eq!(output.status.code(), Some(0));
+        assert!(summary_line(&output).starts_with("summary status=ok exit=0"));
+    }
+}
+
+// =============================================================================
+// Localization Tests
+// =============================================================================
+
+mod localization {
+    use super::*;
+
+    #[test]
+    fn lang_flag_selects_spanish_messages() {
+        let temp_input = TempDir::new().unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--lang",
+                "es",
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("No se encontraron archivos .dcm"),
+            "Expected Spanish message: {stdout}"
+        );
+    }
+
+    #[test]
+    fn env_var_selects_spanish_messages() {
+        let temp_input = TempDir::new().unwrap();
+
+        let output = Command::new(binary_path())
+            .args(["analyze", "--in", temp_input.path().to_str().unwrap()])
+            .env("DCM_TOOLBOX_LANG", "es_ES.UTF-8")
+            .output()
+            .expect("Failed to execute command");
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("No se encontraron archivos .dcm"),
+            "Expected Spanish message: {stdout}"
+        );
+    }
+
+    #[test]
+    fn summary_line_is_not_translated() {
+        let temp_input = TempDir::new().unwrap();
+        let temp_output = TempDir::new().unwrap();
+        let output_path = temp_output.path().join("output");
+
+        let output = run_convert(
+            "jpeg",
+            &[
+                "--lang",
+                "es",
+                "--in",
+                temp_input.path().to_str().unwrap(),
+                "--out",
+                output_path.to_str().unwrap(),
+            ],
+            &[],
+        );
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("summary status=nothing_converted"));
+    }
+}
+
+// =============================================================================
+// Analyze Preview Tests
+// =============================================================================
+
+mod analyze_preview {
+    use super::*;
+
+    #[test]
+    fn preview_dir_requires_preview_flag() {
+        let temp_dir = TempDir::new().unwrap();
+        let output = run_raw(&[
+            "analyze",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--preview-dir",
+            temp_dir.path().to_str().unwrap(),
+        ]);
+
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn preview_writes_gif_per_series() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let preview_dir = temp_dir.path().join("preview");
+
+        let output = run_raw(&[
+            "analyze",
+            "--in",
+            example.to_str().unwrap(),
+            "--preview",
+            "--preview-dir",
+            preview_dir.to_str().unwrap(),
+        ]);
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert!(
+            count_files_with_extension(&preview_dir, "gif") > 0,
+            "Should write at least one preview GIF"
+        );
+    }
+}
+
+// =============================================================================
+// Inventory Tests
+// =============================================================================
+
+mod inventory {
+    use super::*;
+
+    #[test]
+    fn unknown_output_format_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let out = temp_dir.path().join("inventory.xlsx");
+
+        let output = run_raw(&[
+            "inventory",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(".json or .csv"), "{stderr}");
+    }
+
+    #[test]
+    fn counts_nested_folders_as_one_archive() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        // The example twice, one level down: every instance counts twice
+        let temp_dir = TempDir::new().unwrap();
+        let files: Vec<_> = fs::read_dir(&example)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "dcm"))
+            .collect();
+        for copy in ["a", "b"] {
+            let dir = temp_dir.path().join("dump").join(copy);
+            fs::create_dir_all(&dir).unwrap();
+            for file in &files {
+                fs::copy(file, dir.join(file.file_name().unwrap())).unwrap();
+            }
+        }
+        let json = temp_dir.path().join("inventory.json");
+        let csv = temp_dir.path().join("inventory.csv");
+
+        for out in [&json, &csv] {
+            let output = run_raw(&[
+                "inventory",
+                "--in",
+                temp_dir.path().join("dump").to_str().unwrap(),
+                "--out",
+                out.to_str().unwrap(),
+            ]);
+            assert!(output.status.success(), "CLI failed: {output:?}");
+        }
+
+        let inventory: serde_json::Value =
+            serde_json::from_slice(&fs::read(&json).unwrap()).unwrap();
+        let total = &inventory["total"];
+        assert_eq!(total["instances"], files.len() * 2);
+        let studies = inventory["studies"].as_array().unwrap();
+        assert_eq!(total["studies"], studies.len());
+
+        let csv = fs::read_to_string(&csv).unwrap();
+        assert!(csv.starts_with("study_instance_uid,"), "{csv}");
+        assert_eq!(csv.lines().count(), studies.len() + 1);
+    }
+}
+
+// =============================================================================
+// List Tests
+// =============================================================================
+
+mod list {
+    use super::*;
+
+    #[test]
+    fn missing_folder_is_bad_input() {
+        let output = run_raw(&["list", "--in", "/nonexistent/dicom/folder"]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn prints_one_tab_separated_line_per_series() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let output = run_raw(&["list", "--in", example.to_str().unwrap()]);
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut files = 0;
+        for line in stdout.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            assert_eq!(fields.len(), 3, "{line}");
+            files += fields[2].parse::<usize>().unwrap();
+        }
+        assert_eq!(files, count_files_with_extension(&example, "dcm"));
+    }
+}
+
+// =============================================================================
+// Synthetic Test Data Tests
+// =============================================================================
+
+mod gen_test_data {
+    use super::*;
+
+    #[test]
+    fn writes_one_series_per_phantom() {
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path().join("phantoms");
+        let output = run_raw(&[
+            "gen-test-data",
+            "--out",
+            folder.to_str().unwrap(),
+            "--size",
+            "8",
+            "--slices",
+            "3",
+        ]);
+        assert!(output.status.success(), "CLI failed: {output:?}");
+
+        let output = run_raw(&["list", "--in", folder.to_str().unwrap()]);
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "1\tgradient\t3\n2\tsphere\t3\n"
+        );
+    }
+}
+
+// =============================================================================
+// Pixel Verification Tests
+// =============================================================================
+
+mod verify_pixels {
+    use super::*;
+
+    /// Copy the `.dcm` files of `from` into a new folder `to`.
+    fn copy_folder(from: &std::path::Path, to: &std::path::Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "dcm") {
+                fs::copy(&path, to.join(path.file_name().unwrap())).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn identical_copies_pass() {
+        let example = example_folder();
+        let temp_dir = TempDir::new().unwrap();
+        let copy = temp_dir.path().join("copy");
+        copy_folder(&example, &copy);
+
+        let output = run_raw(&[
+            "verify-pixels",
+            "--source",
+            example.to_str().unwrap(),
+            "--copy",
+            copy.to_str().unwrap(),
+        ]);
+
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("have identical pixels; 0 lossy"),
+            "{stdout}"
+        );
+    }
+
+    #[test]
+    fn missing_copies_fail() {
+        let example = example_folder();
+        let temp_dir = TempDir::new().unwrap();
+        let copy = temp_dir.path().join("copy");
+        copy_folder(&example, &copy);
+        let removed = fs::read_dir(&copy).unwrap().next().unwrap().unwrap().path();
+        fs::remove_file(&removed).unwrap();
+
+        let output = run_raw(&[
+            "verify-pixels",
+            "--source",
+            example.to_str().unwrap(),
+            "--copy",
+            copy.to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(1));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let name = removed.file_name().unwrap().to_str().unwrap();
+        assert!(
+            stdout.contains(&format!("Cannot compare {name}")),
+            "{stdout}"
+        );
+    }
+}
+
+// =============================================================================
+// Private Tag Policy Tests
+// =============================================================================
+
+mod private_tags {
+    use super::*;
+
+    #[test]
+    fn copies_keep_their_layout_and_pixels() {
+        let example = example_folder();
+        let temp_dir = TempDir::new().unwrap();
+        let policy = temp_dir.path().join("policy.toml");
+        fs::write(&policy, "[[keep]]\ncreator = \"GEMS_ACQU_01\"\n").unwrap();
+        let copy = temp_dir.path().join("copy");
+
+        let output = run_raw(&[
+            "private-tags",
+            "--in",
+            example.to_str().unwrap(),
+            "--out",
+            copy.to_str().unwrap(),
+            "--policy",
+            policy.to_str().unwrap(),
+        ]);
+        assert!(output.status.success(), "CLI failed: {output:?}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("private element(s) kept"), "{stdout}");
+
+        let output = run_raw(&[
+            "verify-pixels",
+            "--source",
+            example.to_str().unwrap(),
+            "--copy",
+            copy.to_str().unwrap(),
+        ]);
+        assert!(output.status.success(), "CLI failed: {output:?}");
+    }
+
+    #[test]
+    fn invalid_policy_is_bad_input() {
+        let example = example_folder();
+        let temp_dir = TempDir::new().unwrap();
+        let policy = temp_dir.path().join("policy.toml");
+        fs::write(&policy, "[[keep]]\ncreator = \"X\"\ngroup = 0x0010\n").unwrap();
+
+        let output = run_raw(&[
+            "private-tags",
+            "--in",
+            example.to_str().unwrap(),
+            "--out",
+            temp_dir.path().join("copy").to_str().unwrap(),
+            "--policy",
+            policy.to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("not a private group"), "{stderr}");
+    }
+
+    #[test]
+    fn originals_are_not_overwritten() {
+        let temp_dir = TempDir::new().unwrap();
+        let output = run_raw(&[
+            "private-tags",
+            "--in",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            temp_dir.path().to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4));
+    }
+}
+
+// =============================================================================
+// Register Tests
+// =============================================================================
+
+mod register {
+    use super::*;
+
+    #[test]
+    fn missing_fixed_folder_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let out = temp_dir.path().join("transform.json");
+
+        let output = run_raw(&[
+            "register",
+            "--fixed",
+            "/nonexistent/ct",
+            "--moving",
+            temp_dir.path().to_str().unwrap(),
+            "--out",
+            out.to_str().unwrap(),
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        assert!(!out.exists(), "No transform should be written");
+    }
+
+    #[test]
+    fn bins_out_of_range_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+
+        let output = run_raw(&[
+            "register", "--fixed", dir, "--moving", dir, "--out", "t.json", "--bins", "2",
+        ]);
+
+        assert_eq!(output.status.code(), Some(4));
+    }
+}
+
+// =============================================================================
+// Subtract Tests
+// =============================================================================
+
+mod subtract {
+    use super::*;
+
+    #[test]
+    fn missing_post_folder_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+
+        let output = run_raw(&[
+            "subtract",
+            "--post",
+            "/nonexistent/post",
+            "--pre",
+            dir,
+            "--out",
+            dir,
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+    }
+
+    #[test]
+    fn inverted_clip_range_is_bad_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+
+        let output = run_raw(&[
+            "subtract",
+            "--post",
+            dir,
+            "--pre",
+            dir,
+            "--out",
+            dir,
+            "--clip-min",
+            "100",
+            "--clip-max",
+            "-100",
+        ]);
+
+        assert_eq!(output.status.code(), Some(4), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--clip-min"), "{stderr}");
+    }
+}
+
+// =============================================================================
+// Reproducibility Tests
+// =============================================================================
+
+mod reproducibility {
+    use super::*;
+
+    /// Every file under `dir` as (relative path, contents), sorted by path.
+    fn snapshot(dir: &std::path::Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    let relative = path.strip_prefix(dir).unwrap().to_path_buf();
+                    files.push((relative, fs::read(&path).unwrap()));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn repeated_conversions_are_byte_identical() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let runs: Vec<_> = ["first", "second"]
+            .iter()
+            .map(|name| {
+                let output_path = temp_dir.path().join(name);
+                let output = run_convert(
+                    "jpeg",
+                    &[
+                        "--in",
+                        example.to_str().unwrap(),
+                        "--out",
+                        output_path.to_str().unwrap(),
+                        "--force",
+                    ],
+                    &[],
+                );
+                assert!(output.status.success(), "CLI failed: {output:?}");
+                snapshot(&output_path)
+            })
+            .collect();
+
+        assert!(!runs[0].is_empty(), "Should have written files");
+        assert_eq!(runs[0], runs[1], "Output files differ between runs");
+    }
+
+    #[test]
+    fn repeated_analysis_prints_the_same_report() {
+        let example = example_folder();
+        if !example.exists() {
+            eprintln!("Skipping test: example folder not found");
+            return;
+        }
+
+        let reports: Vec<_> = (0..2)
+            .map(|_| run_raw(&["analyze", "--in", example.to_str().unwrap()]).stdout)
+            .collect();
+        assert_eq!(reports[0], reports[1]);
     }
 }